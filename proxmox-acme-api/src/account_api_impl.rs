@@ -2,7 +2,7 @@
 
 use std::ops::ControlFlow;
 
-use anyhow::Error;
+use anyhow::{bail, Error};
 use serde_json::json;
 
 use proxmox_acme::async_client::AcmeClient;
@@ -13,7 +13,7 @@ use proxmox_sys::task_warn;
 
 use crate::account_config::AccountData;
 use crate::config::DEFAULT_ACME_DIRECTORY_ENTRY;
-use crate::types::{AccountEntry, AccountInfo, AcmeAccountName};
+use crate::types::{AccountEntry, AccountInfo, AcmeAccountName, AcmeDirectoryMeta};
 
 fn account_contact_from_string(s: &str) -> Vec<String> {
     s.split(&[' ', ';', ',', '\0'][..])
@@ -51,6 +51,23 @@ pub async fn get_tos(directory: Option<String>) -> Result<Option<String>, Error>
         .map(str::to_owned))
 }
 
+/// Get the metadata of an ACME directory, e.g. to check whether an external account binding is
+/// required for registration.
+pub async fn get_directory_meta(directory: Option<String>) -> Result<AcmeDirectoryMeta, Error> {
+    let directory = directory.unwrap_or_else(|| DEFAULT_ACME_DIRECTORY_ENTRY.url.to_string());
+    let mut client = AcmeClient::new(directory);
+
+    Ok(match client.directory().await?.meta() {
+        Some(meta) => AcmeDirectoryMeta {
+            terms_of_service: meta.terms_of_service.clone(),
+            external_account_required: meta.external_account_required.unwrap_or(false),
+            website: meta.website.clone(),
+            caa_identities: meta.caa_identities.clone(),
+        },
+        None => AcmeDirectoryMeta::default(),
+    })
+}
+
 pub async fn register_account(
     name: &AcmeAccountName,
     contact: String,
@@ -63,6 +80,15 @@ pub async fn register_account(
 
     let mut client = AcmeClient::new(directory_url.clone());
 
+    // the directory is cached by the client and needed for the registration anyway
+    let eab_required = client
+        .directory()
+        .await?
+        .external_account_binding_required();
+    if eab_required && eab_creds.is_none() {
+        bail!("ACME directory '{directory_url}' requires an external account binding");
+    }
+
     let contact = account_contact_from_string(&contact);
     let account = client
         .new_account(tos_url.is_some(), contact, None, eab_creds)
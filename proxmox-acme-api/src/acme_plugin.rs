@@ -10,15 +10,20 @@ use anyhow::{bail, format_err, Error};
 use hyper::{Body, Request, Response};
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
 use tokio::process::Command;
+use tokio::time::Instant;
 
 use proxmox_acme::async_client::AcmeClient;
 use proxmox_acme::{Authorization, Challenge};
 use proxmox_rest_server::WorkerTask;
 
+use crate::dns_challenge::lookup_dns_plugin;
+use crate::dns_query;
+use crate::http_challenge;
 use crate::plugin_config::PluginData;
-use crate::types::{AcmeDomain, DnsPlugin};
+use crate::types::{AcmeDomain, DnsPlugin, StandalonePlugin};
 
 const PROXMOX_ACME_SH_PATH: &str = "/usr/share/proxmox-acme/proxmox-acme";
+const PROPAGATION_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 
 pub(crate) fn get_acme_plugin(
     plugin_data: &PluginData,
@@ -35,8 +40,12 @@ pub(crate) fn get_acme_plugin(
             Box::new(plugin)
         }
         "standalone" => {
-            // this one has no config
-            Box::<StandaloneServer>::default()
+            let plugin: StandalonePlugin = serde::Deserialize::deserialize(data)?;
+            Box::new(StandaloneServer {
+                api_server: plugin.api_server.unwrap_or_default(),
+                abort_handle: None,
+                token: None,
+            })
         }
         other => bail!("missing implementation for plugin type '{}'", other),
     }))
@@ -99,13 +108,32 @@ impl DnsPlugin {
         action: &str,
     ) -> Result<&'a str, Error> {
         let challenge = extract_challenge(authorization, "dns-01")?;
-        let mut stdin_data = client
-            .dns_01_txt_value(
-                challenge
-                    .token()
-                    .ok_or_else(|| format_err!("missing token in challenge"))?,
-            )?
-            .into_bytes();
+        let txt_value = client.dns_01_txt_value(
+            challenge
+                .token()
+                .ok_or_else(|| format_err!("missing token in challenge"))?,
+        )?;
+        let domain = domain.alias.as_deref().unwrap_or(&domain.domain);
+
+        if let Some(plugin) = lookup_dns_plugin(&self.core.api) {
+            let record = format!("_acme-challenge.{domain}");
+            task.log_message(format!(
+                "{action}: using native '{}' DNS plugin for {record}",
+                self.core.api
+            ));
+            if action == "setup" {
+                plugin
+                    .add_txt_record(&record, &txt_value, &self.data)
+                    .await?;
+            } else {
+                plugin
+                    .remove_txt_record(&record, &txt_value, &self.data)
+                    .await?;
+            }
+            return Ok(&challenge.url);
+        }
+
+        let mut stdin_data = txt_value.into_bytes();
         stdin_data.push(b'\n');
         stdin_data.extend(self.data.as_bytes());
         if stdin_data.last() != Some(&b'\n') {
@@ -125,7 +153,7 @@ impl DnsPlugin {
                 PROXMOX_ACME_SH_PATH,
                 action,
                 &self.core.api,
-                domain.alias.as_deref().unwrap_or(&domain.domain),
+                domain,
         ]);
 
         // We could use 1 socketpair, but tokio wraps them all in `File` internally causing `close`
@@ -174,6 +202,59 @@ impl DnsPlugin {
     }
 }
 
+/// Poll the authoritative name servers of `record` until all of them serve the TXT `value`, or
+/// until `deadline` is reached.
+async fn wait_for_propagation(
+    record: &str,
+    value: &str,
+    deadline: Instant,
+    task: &WorkerTask,
+) -> Result<(), Error> {
+    let servers = tokio::time::timeout_at(deadline, dns_query::authoritative_servers(record))
+        .await
+        .map_err(|_| format_err!("timed out looking up the authoritative name servers"))??;
+    task.log_message(format!(
+        "Waiting for TXT record propagation to {} authoritative name servers",
+        servers.len()
+    ));
+
+    loop {
+        let mut missing = 0;
+        for server in &servers {
+            let query = tokio::time::timeout_at(deadline, dns_query::txt_records(*server, record));
+            match query.await.unwrap_or_else(|_| Err(format_err!("timed out"))) {
+                Ok(records) if records.iter().any(|txt| txt == value) => (),
+                Ok(_) => missing += 1,
+                Err(err) => {
+                    task.log_message(format!("querying name server {server} failed - {err}"));
+                    missing += 1;
+                }
+            }
+        }
+
+        if missing == 0 {
+            task.log_message("TXT record found on all authoritative name servers");
+            return Ok(());
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            task.log_warning(format!(
+                "TXT record still missing on {missing} of {} name servers, continuing anyway",
+                servers.len()
+            ));
+            return Ok(());
+        }
+
+        task.log_message(format!(
+            "TXT record not yet found on {missing} of {} name servers, {} seconds left",
+            servers.len(),
+            remaining.as_secs()
+        ));
+        tokio::time::sleep(remaining.min(PROPAGATION_CHECK_INTERVAL)).await;
+    }
+}
+
 impl AcmePlugin for DnsPlugin {
     fn setup<'fut, 'a: 'fut, 'b: 'fut, 'c: 'fut, 'd: 'fut>(
         &'a mut self,
@@ -187,6 +268,30 @@ impl AcmePlugin for DnsPlugin {
                 .action(client, authorization, domain, task.clone(), "setup")
                 .await;
 
+            let propagation_timeout = self.core.propagation_timeout.unwrap_or(0) as u64;
+            if propagation_timeout > 0 && result.is_ok() {
+                let deadline = Instant::now() + Duration::from_secs(propagation_timeout);
+                let record = format!(
+                    "_acme-challenge.{}",
+                    domain.alias.as_deref().unwrap_or(&domain.domain)
+                );
+                let propagation = match extract_challenge(authorization, "dns-01")
+                    .and_then(|challenge| {
+                        challenge
+                            .token()
+                            .ok_or_else(|| format_err!("missing token in challenge"))
+                    })
+                    .and_then(|token| client.dns_01_txt_value(token))
+                {
+                    Ok(value) => wait_for_propagation(&record, &value, deadline, &task).await,
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = propagation {
+                    task.log_warning(format!("unable to check TXT record propagation - {err}"));
+                }
+            }
+
             let validation_delay = self.core.validation_delay.unwrap_or(30) as u64;
             if validation_delay > 0 {
                 task.log_message(format!(
@@ -214,13 +319,15 @@ impl AcmePlugin for DnsPlugin {
     }
 }
 
-#[derive(Default)]
 struct StandaloneServer {
+    /// Answer the challenge via the handler registered on the API server.
+    api_server: bool,
     abort_handle: Option<futures::future::AbortHandle>,
+    token: Option<String>,
 }
 
 // In case the "order_certificates" future gets dropped between setup & teardown, let's also cancel
-// the HTTP listener on Drop:
+// the HTTP listener and forget the challenge on Drop:
 impl Drop for StandaloneServer {
     fn drop(&mut self) {
         self.stop();
@@ -232,25 +339,21 @@ impl StandaloneServer {
         if let Some(abort) = self.abort_handle.take() {
             abort.abort();
         }
+        if let Some(token) = self.token.take() {
+            http_challenge::remove_challenge(&token);
+        }
     }
 }
 
-async fn standalone_respond(
-    req: Request<Body>,
-    path: Arc<String>,
-    key_auth: Arc<String>,
-) -> Result<Response<Body>, hyper::Error> {
-    if req.method() == hyper::Method::GET && req.uri().path() == path.as_str() {
-        Ok(Response::builder()
-            .status(http::StatusCode::OK)
-            .body(key_auth.as_bytes().to_vec().into())
-            .unwrap())
-    } else {
-        Ok(Response::builder()
+async fn standalone_respond(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+    let response = http_challenge::respond(req.method(), req.uri().path());
+
+    Ok(response.unwrap_or_else(|| {
+        Response::builder()
             .status(http::StatusCode::NOT_FOUND)
             .body("Not found.".into())
-            .unwrap())
-    }
+            .unwrap()
+    }))
 }
 
 impl AcmePlugin for StandaloneServer {
@@ -259,7 +362,7 @@ impl AcmePlugin for StandaloneServer {
         client: &'b mut AcmeClient,
         authorization: &'c Authorization,
         _domain: &'d AcmeDomain,
-        _task: Arc<WorkerTask>,
+        task: Arc<WorkerTask>,
     ) -> Pin<Box<dyn Future<Output = Result<&'c str, Error>> + Send + 'fut>> {
         use hyper::server::conn::AddrIncoming;
         use hyper::service::{make_service_fn, service_fn};
@@ -271,17 +374,16 @@ impl AcmePlugin for StandaloneServer {
             let token = challenge
                 .token()
                 .ok_or_else(|| format_err!("missing token in challenge"))?;
-            let key_auth = Arc::new(client.key_authorization(token)?);
-            let path = Arc::new(format!("/.well-known/acme-challenge/{}", token));
-
-            let service = make_service_fn(move |_| {
-                let path = Arc::clone(&path);
-                let key_auth = Arc::clone(&key_auth);
-                async move {
-                    Ok::<_, hyper::Error>(service_fn(move |request| {
-                        standalone_respond(request, Arc::clone(&path), Arc::clone(&key_auth))
-                    }))
-                }
+            http_challenge::add_challenge(token, client.key_authorization(token)?);
+            self.token = Some(token.to_string());
+
+            if self.api_server {
+                task.log_message("Answering HTTP challenge via the API server");
+                return Ok(challenge.url.as_str());
+            }
+
+            let service = make_service_fn(|_| async {
+                Ok::<_, hyper::Error>(service_fn(standalone_respond))
             });
 
             // `[::]:80` first, then `*:80`
@@ -306,9 +408,7 @@ impl AcmePlugin for StandaloneServer {
         _task: Arc<WorkerTask>,
     ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'fut>> {
         Box::pin(async move {
-            if let Some(abort) = self.abort_handle.take() {
-                abort.abort();
-            }
+            self.stop();
             Ok(())
         })
     }
@@ -10,12 +10,20 @@ use openssl::rsa::Rsa;
 use openssl::x509::{X509Builder, X509};
 
 use proxmox_acme::async_client::AcmeClient;
+use proxmox_acme::order::{Identifier, OrderData};
 use proxmox_rest_server::WorkerTask;
 use proxmox_sys::{task_log, task_warn};
 
-use crate::types::{AcmeConfig, AcmeDomain};
+use crate::order_state::{
+    load_order_state, prune_expired_orders, remove_order_state, save_order_state,
+};
+use crate::types::{AcmeAccountName, AcmeConfig, AcmeDomain, AcmeOrderState, AcmeOrderStatus};
 use crate::CertificateInfo;
 
+const PROCESSING_MIN_DELAY: Duration = Duration::from_secs(5);
+const PROCESSING_MAX_DELAY: Duration = Duration::from_secs(60);
+const PROCESSING_MAX_RETRIES: u32 = 15;
+
 pub async fn revoke_certificate(acme_config: &AcmeConfig, certificate: &[u8]) -> Result<(), Error> {
     let mut acme = super::account_config::load_account_config(&acme_config.account)
         .await?
@@ -31,21 +39,15 @@ pub struct OrderedCertificate {
     pub private_key_pem: Vec<u8>,
 }
 
+/// Order a certificate for `domains`.
+///
+/// This places a new order and processes it right away, see [`start_order`] and
+/// [`resume_order`].
 pub async fn order_certificate(
     worker: Arc<WorkerTask>,
     acme_config: &AcmeConfig,
     domains: &[AcmeDomain],
 ) -> Result<Option<OrderedCertificate>, Error> {
-    use proxmox_acme::authorization::Status;
-    use proxmox_acme::order::Identifier;
-
-    let get_domain_config = |domain: &str| {
-        domains
-            .iter()
-            .find(|d| d.domain == domain)
-            .ok_or_else(|| format_err!("no config for domain '{}'", domain))
-    };
-
     if domains.is_empty() {
         task_log!(
             worker,
@@ -54,11 +56,57 @@ pub async fn order_certificate(
         return Ok(None);
     }
 
-    let mut acme = super::account_config::load_account_config(&acme_config.account)
+    let state = start_order(Arc::clone(&worker), acme_config, domains).await?;
+
+    resume_order(worker, &state.id, domains).await.map(Some)
+}
+
+fn order_status(status: proxmox_acme::order::Status) -> Result<AcmeOrderStatus, Error> {
+    use proxmox_acme::order::Status;
+
+    Ok(match status {
+        Status::Pending => AcmeOrderStatus::Pending,
+        Status::Ready => AcmeOrderStatus::Ready,
+        Status::Processing => AcmeOrderStatus::Processing,
+        Status::Valid => AcmeOrderStatus::Valid,
+        Status::Invalid => AcmeOrderStatus::Invalid,
+        Status::New => bail!("ACME server returned an order without status"),
+    })
+}
+
+/// Update the persisted state from the current order data.
+fn update_order_state(state: &mut AcmeOrderState, order: &OrderData) -> Result<(), Error> {
+    state.status = order_status(order.status)?;
+    state.expires = order.expires.clone();
+    state.updated = proxmox_time::epoch_i64();
+
+    if state.status == AcmeOrderStatus::Invalid {
+        remove_order_state(&state.id)?;
+        match &order.error {
+            Some(error) => bail!("order {} is invalid - {}", state.location, error),
+            None => bail!("order {} is invalid", state.location),
+        }
+    }
+
+    save_order_state(state)
+}
+
+/// Place a new order for `domains` and persist its state.
+///
+/// The order can then be processed via [`resume_order`], also after the daemon got restarted.
+pub async fn start_order(
+    worker: Arc<WorkerTask>,
+    acme_config: &AcmeConfig,
+    domains: &[AcmeDomain],
+) -> Result<AcmeOrderState, Error> {
+    let account = AcmeAccountName::from_string(acme_config.account.clone())?;
+    let mut acme = super::account_config::load_account_config(account.as_ref())
         .await?
         .client();
 
-    let (plugins, _) = super::plugin_config::plugin_config()?;
+    if let Err(err) = prune_expired_orders() {
+        task_warn!(worker, "failed to remove expired orders - {err}");
+    }
 
     task_log!(worker, "Placing ACME order");
 
@@ -68,16 +116,115 @@ pub async fn order_certificate(
 
     task_log!(worker, "Order URL: {}", order.location);
 
-    let identifiers: Vec<String> = order
-        .data
-        .identifiers
-        .iter()
-        .map(|identifier| match identifier {
-            Identifier::Dns(domain) => domain.clone(),
-        })
-        .collect();
+    let now = proxmox_time::epoch_i64();
+    let mut state = AcmeOrderState {
+        id: proxmox_uuid::Uuid::generate().to_string(),
+        account,
+        location: order.location.clone(),
+        domains: order
+            .data
+            .identifiers
+            .iter()
+            .map(|identifier| match identifier {
+                Identifier::Dns(domain) => domain.clone(),
+            })
+            .collect(),
+        status: AcmeOrderStatus::Pending,
+        created: now,
+        updated: now,
+        expires: None,
+        private_key: None,
+    };
+    update_order_state(&mut state, &order.data)?;
+
+    Ok(state)
+}
+
+/// Continue a persisted order.
+///
+/// Pending authorizations are validated using the plugins configured in `domains`, then the
+/// order is finalized and the certificate downloaded.
+pub async fn resume_order(
+    worker: Arc<WorkerTask>,
+    order_id: &str,
+    domains: &[AcmeDomain],
+) -> Result<OrderedCertificate, Error> {
+    let mut state = load_order_state(order_id)?;
+    let mut acme = super::account_config::load_account_config(state.account.as_ref())
+        .await?
+        .client();
+
+    task_log!(worker, "Resuming ACME order {}", state.location);
+
+    let result = do_resume_order(&worker, &mut acme, &mut state, domains).await;
+    if result.is_err() {
+        forget_failed_order(&mut acme, &mut state).await;
+    }
+    result
+}
+
+async fn do_resume_order(
+    worker: &Arc<WorkerTask>,
+    acme: &mut AcmeClient,
+    state: &mut AcmeOrderState,
+    domains: &[AcmeDomain],
+) -> Result<OrderedCertificate, Error> {
+    let order = acme.get_order(&state.location).await?;
+    update_order_state(state, &order)?;
+
+    if state.status == AcmeOrderStatus::Pending {
+        validate_authorizations(worker, acme, &order.authorizations, domains).await?;
+        task_log!(worker, "All domains validated");
+    }
+
+    do_finalize_order(worker, acme, state).await
+}
 
-    for auth_url in &order.data.authorizations {
+/// Finalize a persisted order whose authorizations were all validated, and download the
+/// certificate.
+pub async fn finalize_order(
+    worker: Arc<WorkerTask>,
+    order_id: &str,
+) -> Result<OrderedCertificate, Error> {
+    let mut state = load_order_state(order_id)?;
+    let mut acme = super::account_config::load_account_config(state.account.as_ref())
+        .await?
+        .client();
+
+    let result = do_finalize_order(&worker, &mut acme, &mut state).await;
+    if result.is_err() {
+        forget_failed_order(&mut acme, &mut state).await;
+    }
+    result
+}
+
+/// Refresh the state of an order after processing it failed. This removes the state, including
+/// the private key, if the order became invalid, e.g. due to a failed validation.
+async fn forget_failed_order(acme: &mut AcmeClient, state: &mut AcmeOrderState) {
+    if let Ok(order) = acme.get_order(&state.location).await {
+        // an invalid order is reported as error after removing its state
+        let _ = update_order_state(state, &order);
+    }
+}
+
+async fn validate_authorizations(
+    worker: &Arc<WorkerTask>,
+    acme: &mut AcmeClient,
+    authorizations: &[String],
+    domains: &[AcmeDomain],
+) -> Result<(), Error> {
+    use proxmox_acme::authorization::Status;
+
+    let get_domain_config = |domain: &str| {
+        domains
+            .iter()
+            .find(|d| d.domain.eq_ignore_ascii_case(domain))
+            .ok_or_else(|| format_err!("no config for domain '{}'", domain))
+    };
+
+    let (plugins, _) = super::plugin_config::plugin_config()?;
+
+    for auth_url in authorizations {
         task_log!(worker, "Getting authorization details from '{}'", auth_url);
         let mut auth = acme.get_authorization(auth_url).await?;
 
@@ -100,13 +247,13 @@ pub async fn order_certificate(
 
         task_log!(worker, "Setting up validation plugin");
         let validation_url = plugin_cfg
-            .setup(&mut acme, &auth, domain_config, Arc::clone(&worker))
+            .setup(acme, &auth, domain_config, Arc::clone(worker))
             .await?;
 
-        let result = request_validation(&worker, &mut acme, auth_url, validation_url).await;
+        let result = request_validation(worker, acme, auth_url, validation_url).await;
 
         if let Err(err) = plugin_cfg
-            .teardown(&mut acme, &auth, domain_config, Arc::clone(&worker))
+            .teardown(acme, &auth, domain_config, Arc::clone(worker))
             .await
         {
             task_warn!(
@@ -121,27 +268,54 @@ pub async fn order_certificate(
         result?;
     }
 
-    task_log!(worker, "All domains validated");
-    task_log!(worker, "Creating CSR");
+    Ok(())
+}
+
+/// Generate a new CSR for the order and persist its private key before it gets used.
+fn generate_csr(state: &mut AcmeOrderState) -> Result<proxmox_acme::util::Csr, Error> {
+    let csr = proxmox_acme::util::Csr::generate(&state.domains, &Default::default())?;
+    state.private_key = Some(
+        String::from_utf8(csr.private_key_pem.clone())
+            .map_err(|_| format_err!("generated private key is not valid UTF-8"))?,
+    );
+    save_order_state(state)?;
+    Ok(csr)
+}
 
-    let csr = proxmox_acme::util::Csr::generate(&identifiers, &Default::default())?;
+async fn do_finalize_order(
+    worker: &WorkerTask,
+    acme: &mut AcmeClient,
+    state: &mut AcmeOrderState,
+) -> Result<OrderedCertificate, Error> {
+    let mut csr = None;
     let mut finalize_error_cnt = 0u8;
-    let order_url = &order.location;
+    let mut processing_cnt = 0u32;
+    let mut processing_delay = PROCESSING_MIN_DELAY;
     let mut order;
     loop {
-        use proxmox_acme::order::Status;
+        order = acme.get_order(&state.location).await?;
+        update_order_state(state, &order)?;
+
+        match state.status {
+            AcmeOrderStatus::Pending | AcmeOrderStatus::Ready => {
+                if state.status == AcmeOrderStatus::Pending {
+                    task_log!(worker, "still pending, trying to finalize anyway");
+                } else {
+                    task_log!(worker, "order is ready, finalizing");
+                }
 
-        order = acme.get_order(order_url).await?;
+                if csr.is_none() {
+                    task_log!(worker, "Creating CSR");
+                    csr = Some(generate_csr(state)?);
+                }
+                let csr = csr.as_ref().unwrap();
 
-        match order.status {
-            Status::Pending => {
-                task_log!(worker, "still pending, trying to finalize anyway");
                 let finalize = order
                     .finalize
                     .as_deref()
                     .ok_or_else(|| format_err!("missing 'finalize' URL in order"))?;
                 if let Err(err) = acme.finalize(finalize, &csr.data).await {
-                    if finalize_error_cnt >= 5 {
+                    if state.status == AcmeOrderStatus::Ready || finalize_error_cnt >= 5 {
                         return Err(err);
                     }
 
@@ -149,27 +323,36 @@ pub async fn order_certificate(
                 }
                 tokio::time::sleep(Duration::from_secs(5)).await;
             }
-            Status::Ready => {
-                task_log!(worker, "order is ready, finalizing");
-                let finalize = order
-                    .finalize
-                    .as_deref()
-                    .ok_or_else(|| format_err!("missing 'finalize' URL in order"))?;
-                acme.finalize(finalize, &csr.data).await?;
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
-            Status::Processing => {
-                task_log!(worker, "still processing, trying again in 30 seconds");
-                tokio::time::sleep(Duration::from_secs(30)).await;
+            AcmeOrderStatus::Processing => {
+                if processing_cnt >= PROCESSING_MAX_RETRIES {
+                    bail!(
+                        "order is still processing, giving up for now - resume order '{}' later",
+                        state.id
+                    );
+                }
+                processing_cnt += 1;
+
+                task_log!(
+                    worker,
+                    "still processing, trying again in {} seconds",
+                    processing_delay.as_secs()
+                );
+                tokio::time::sleep(processing_delay).await;
+                processing_delay = (processing_delay * 2).min(PROCESSING_MAX_DELAY);
             }
-            Status::Valid => {
+            AcmeOrderStatus::Valid => {
                 task_log!(worker, "valid");
                 break;
             }
-            other => bail!("order status: {:?}", other),
+            AcmeOrderStatus::Invalid => bail!("order {} is invalid", state.location),
         }
     }
 
+    let private_key_pem = match &state.private_key {
+        Some(key) => key.as_bytes().to_vec(),
+        None => bail!("the private key of order '{}' got lost", state.id),
+    };
+
     task_log!(worker, "Downloading certificate");
     let certificate = acme
         .get_certificate(
@@ -180,10 +363,12 @@ pub async fn order_certificate(
         )
         .await?;
 
-    Ok(Some(OrderedCertificate {
+    remove_order_state(&state.id)?;
+
+    Ok(OrderedCertificate {
         certificate: certificate.to_vec(),
-        private_key_pem: csr.private_key_pem,
-    }))
+        private_key_pem,
+    })
 }
 
 async fn request_validation(
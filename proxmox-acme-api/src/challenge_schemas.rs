@@ -35,7 +35,7 @@ fn load_dns_challenge_schema() -> Result<Vec<AcmeChallengeSchema>, Error> {
     let raw = file_read_string(ACME_DNS_SCHEMA_FN)?;
     let schemas: serde_json::Map<String, Value> = serde_json::from_str(&raw)?;
 
-    Ok(schemas
+    let mut schemas: Vec<_> = schemas
         .iter()
         .map(|(id, schema)| AcmeChallengeSchema {
             id: id.to_owned(),
@@ -47,7 +47,11 @@ fn load_dns_challenge_schema() -> Result<Vec<AcmeChallengeSchema>, Error> {
             ty: "dns".into(),
             schema: schema.to_owned(),
         })
-        .collect())
+        .collect();
+
+    schemas.extend(crate::dns_plugins::builtin_schemas());
+
+    Ok(schemas)
 }
 
 pub fn get_cached_challenge_schemas() -> Result<ChallengeSchemaWrapper, Error> {
@@ -0,0 +1,57 @@
+//! Native DNS challenge plugins.
+//!
+//! By default, DNS plugins are executed via the `acme.sh` based shell hooks. Some common DNS APIs
+//! have built-in native implementations, and products can register their own for further APIs.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Error;
+
+/// A native implementation of a DNS API used to fulfill `dns-01` challenges.
+pub trait DnsChallengePlugin: Send + Sync {
+    /// Add a TXT record with `value` for the fully qualified `record` name.
+    ///
+    /// `data` contains the plugin's configuration data, usually `KEY=value` lines as used by the
+    /// `acme.sh` DNS hooks.
+    fn add_txt_record<'a>(
+        &'a self,
+        record: &'a str,
+        value: &'a str,
+        data: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+
+    /// Remove the TXT record previously added via [`add_txt_record`](Self::add_txt_record).
+    fn remove_txt_record<'a>(
+        &'a self,
+        record: &'a str,
+        value: &'a str,
+        data: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+}
+
+lazy_static::lazy_static! {
+    static ref NATIVE_DNS_PLUGINS: RwLock<HashMap<String, Arc<dyn DnsChallengePlugin>>> =
+        RwLock::new(
+            crate::dns_plugins::builtin_plugins()
+                .into_iter()
+                .map(|(api, plugin)| (api.to_string(), plugin))
+                .collect()
+        );
+}
+
+/// Register a native implementation for the DNS API `api`.
+///
+/// This replaces the shell hook (or a built-in or previously registered plugin) for this API.
+pub fn register_dns_plugin(api: &str, plugin: Arc<dyn DnsChallengePlugin>) {
+    NATIVE_DNS_PLUGINS
+        .write()
+        .unwrap()
+        .insert(api.to_string(), plugin);
+}
+
+pub(crate) fn lookup_dns_plugin(api: &str) -> Option<Arc<dyn DnsChallengePlugin>> {
+    NATIVE_DNS_PLUGINS.read().unwrap().get(api).cloned()
+}
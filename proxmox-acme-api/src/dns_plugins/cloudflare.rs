@@ -0,0 +1,303 @@
+//! Cloudflare DNS API (`native-cf`, like `dns_cf`).
+//!
+//! Uses either an API token (`CF_Token`, optionally with `CF_Account_ID` or `CF_Zone_ID`) or the
+//! global API key (`CF_Key` and `CF_Email`).
+
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::{bail, format_err, Error};
+use hyper::Method;
+use serde_json::{json, Value};
+
+use super::{http_client, JsonTransport, PluginVars};
+use crate::dns_challenge::DnsChallengePlugin;
+
+const CF_API: &str = "https://api.cloudflare.com/client/v4";
+
+pub(super) struct CloudflarePlugin;
+
+struct CloudflareApi {
+    client: Box<dyn JsonTransport>,
+    auth: Vec<(&'static str, String)>,
+}
+
+impl CloudflareApi {
+    fn new(vars: &PluginVars, client: Box<dyn JsonTransport>) -> Result<Self, Error> {
+        let auth = if let Some(token) = vars.get("CF_Token") {
+            vec![("Authorization", format!("Bearer {token}"))]
+        } else if let (Some(key), Some(email)) = (vars.get("CF_Key"), vars.get("CF_Email")) {
+            vec![
+                ("X-Auth-Key", key.to_string()),
+                ("X-Auth-Email", email.to_string()),
+            ]
+        } else {
+            bail!("either 'CF_Token' or 'CF_Key' and 'CF_Email' are required");
+        };
+
+        Ok(Self { client, auth })
+    }
+
+    /// Perform an API call and return its `result`.
+    async fn call(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, Error> {
+        let headers: Vec<(&str, &str)> = self
+            .auth
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+
+        let uri = format!("{CF_API}{path}");
+        let (status, mut response) = self.client.request(method, &uri, &headers, body).await?;
+
+        if response["success"].as_bool() != Some(true) {
+            let errors: Vec<&str> = response["errors"]
+                .as_array()
+                .map(|errors| {
+                    errors
+                        .iter()
+                        .filter_map(|error| error["message"].as_str())
+                        .collect()
+                })
+                .unwrap_or_default();
+            bail!(
+                "cloudflare API call failed ({}): {}",
+                status,
+                errors.join(", ")
+            );
+        }
+
+        Ok(response["result"].take())
+    }
+
+    async fn zone_id(&self, vars: &PluginVars, record: &str) -> Result<String, Error> {
+        if let Some(zone_id) = vars.get("CF_Zone_ID") {
+            return Ok(zone_id.to_string());
+        }
+
+        let account_filter = match vars.get("CF_Account_ID") {
+            Some(account) => format!("&account.id={account}"),
+            None => String::new(),
+        };
+
+        let mut zone = record;
+        while let Some((_, parent)) = zone.split_once('.') {
+            let zones = self
+                .call(
+                    Method::GET,
+                    &format!("/zones?name={parent}{account_filter}"),
+                    None,
+                )
+                .await?;
+
+            if let Some(id) = zones[0]["id"].as_str() {
+                return Ok(id.to_string());
+            }
+            zone = parent;
+        }
+
+        bail!("unable to find cloudflare zone for '{}'", record);
+    }
+
+    async fn add(&self, vars: &PluginVars, record: &str, value: &str) -> Result<(), Error> {
+        let zone_id = self.zone_id(vars, record).await?;
+
+        let existing = self.find_records(&zone_id, record, value).await?;
+        if !existing.is_empty() {
+            return Ok(());
+        }
+
+        self.call(
+            Method::POST,
+            &format!("/zones/{zone_id}/dns_records"),
+            Some(json!({
+                "type": "TXT",
+                "name": record,
+                "content": value,
+                "ttl": 120,
+            })),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove(&self, vars: &PluginVars, record: &str, value: &str) -> Result<(), Error> {
+        let zone_id = self.zone_id(vars, record).await?;
+
+        for id in self.find_records(&zone_id, record, value).await? {
+            self.call(
+                Method::DELETE,
+                &format!("/zones/{zone_id}/dns_records/{id}"),
+                None,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn find_records(
+        &self,
+        zone_id: &str,
+        record: &str,
+        value: &str,
+    ) -> Result<Vec<String>, Error> {
+        let records = self
+            .call(
+                Method::GET,
+                &format!("/zones/{zone_id}/dns_records?type=TXT&name={record}&content={value}"),
+                None,
+            )
+            .await?;
+
+        records
+            .as_array()
+            .ok_or_else(|| format_err!("unexpected cloudflare API response"))?
+            .iter()
+            .map(|record| {
+                record["id"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| format_err!("cloudflare DNS record without id"))
+            })
+            .collect()
+    }
+}
+
+impl DnsChallengePlugin for CloudflarePlugin {
+    fn add_txt_record<'a>(
+        &'a self,
+        record: &'a str,
+        value: &'a str,
+        data: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let vars = PluginVars::parse(data);
+            CloudflareApi::new(&vars, http_client())?
+                .add(&vars, record, value)
+                .await
+        })
+    }
+
+    fn remove_txt_record<'a>(
+        &'a self,
+        record: &'a str,
+        value: &'a str,
+        data: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let vars = PluginVars::parse(data);
+            CloudflareApi::new(&vars, http_client())?
+                .remove(&vars, record, value)
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dns_plugins::mock::MockTransport;
+
+    const RECORD: &str = "_acme-challenge.www.example.com";
+
+    fn success(result: Value) -> (u16, Value) {
+        (
+            200,
+            json!({ "success": true, "errors": [], "result": result }),
+        )
+    }
+
+    #[test]
+    fn test_add_record() -> Result<(), Error> {
+        let vars = PluginVars::parse("CF_Token=token\nCF_Account_ID=account");
+        let transport = MockTransport::new(vec![
+            // no zone "www.example.com", then "example.com"
+            success(json!([])),
+            success(json!([{ "id": "zone", "name": "example.com" }])),
+            success(json!([])),
+            success(json!({ "id": "record" })),
+        ]);
+        let requests = transport.requests();
+
+        let api = CloudflareApi::new(&vars, Box::new(transport))?;
+        futures::executor::block_on(api.add(&vars, RECORD, "value"))?;
+
+        let requests = requests.lock().unwrap();
+        let uris: Vec<_> = requests.iter().map(|r| r.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            [
+                "https://api.cloudflare.com/client/v4/zones?name=www.example.com&account.id=account",
+                "https://api.cloudflare.com/client/v4/zones?name=example.com&account.id=account",
+                "https://api.cloudflare.com/client/v4/zones/zone/dns_records?type=TXT\
+                    &name=_acme-challenge.www.example.com&content=value",
+                "https://api.cloudflare.com/client/v4/zones/zone/dns_records",
+            ]
+        );
+        assert_eq!(
+            requests[0].headers,
+            [("Authorization".to_string(), "Bearer token".to_string())]
+        );
+        assert_eq!(requests[3].method, Method::POST);
+        assert_eq!(
+            requests[3].body,
+            Some(json!({ "type": "TXT", "name": RECORD, "content": "value", "ttl": 120 }))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_record() -> Result<(), Error> {
+        let vars = PluginVars::parse("CF_Key=key\nCF_Email=root@example.com\nCF_Zone_ID=zone");
+        let transport = MockTransport::new(vec![
+            success(json!([{ "id": "a" }, { "id": "b" }])),
+            success(json!({ "id": "a" })),
+            success(json!({ "id": "b" })),
+        ]);
+        let requests = transport.requests();
+
+        let api = CloudflareApi::new(&vars, Box::new(transport))?;
+        futures::executor::block_on(api.remove(&vars, RECORD, "value"))?;
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0].headers,
+            [
+                ("X-Auth-Key".to_string(), "key".to_string()),
+                ("X-Auth-Email".to_string(), "root@example.com".to_string()),
+            ]
+        );
+        assert_eq!(requests[2].method, Method::DELETE);
+        assert_eq!(
+            requests[2].uri,
+            "https://api.cloudflare.com/client/v4/zones/zone/dns_records/b"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_error() {
+        let vars = PluginVars::parse("CF_Token=token\nCF_Zone_ID=zone");
+        let transport = MockTransport::new(vec![(
+            403,
+            json!({
+                "success": false,
+                "errors": [{ "code": 10000, "message": "Authentication error" }],
+            }),
+        )]);
+
+        let api = CloudflareApi::new(&vars, Box::new(transport)).unwrap();
+        let err = futures::executor::block_on(api.add(&vars, RECORD, "value")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cloudflare API call failed (403 Forbidden): Authentication error"
+        );
+
+        let vars = PluginVars::parse("CF_Key=key");
+        assert!(CloudflareApi::new(&vars, Box::new(MockTransport::default())).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! Built-in native DNS plugins.
+//!
+//! These implement some common DNS APIs natively. They use their own API IDs, prefixed with
+//! `native-`, so existing plugin configurations keep using the `acme.sh` DNS hooks. Apart from
+//! the nsupdate key, which is part of the plugin data instead of a file, they use the same
+//! configuration variables as the corresponding hooks.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{bail, format_err, Error};
+use hyper::{Body, Method, Request, StatusCode};
+use serde_json::{json, Value};
+
+use proxmox_http::client::Client;
+use proxmox_http::{HttpOptions, ProxyConfig};
+
+use crate::dns_challenge::DnsChallengePlugin;
+use crate::types::AcmeChallengeSchema;
+
+mod cloudflare;
+mod nsupdate;
+mod powerdns;
+
+const USER_AGENT_STRING: &str = "proxmox-acme-dns-plugin/1.0";
+
+lazy_static::lazy_static! {
+    static ref PROXY_CONFIG: RwLock<Option<ProxyConfig>> = RwLock::new(None);
+}
+
+/// Set the HTTP proxy used by the built-in DNS plugins, usually the node's proxy configuration.
+pub fn set_dns_plugin_proxy(proxy_config: Option<ProxyConfig>) {
+    *PROXY_CONFIG.write().unwrap() = proxy_config;
+}
+
+/// The built-in plugins with their API IDs.
+pub(crate) fn builtin_plugins() -> Vec<(&'static str, Arc<dyn DnsChallengePlugin>)> {
+    vec![
+        ("native-cf", Arc::new(cloudflare::CloudflarePlugin)),
+        ("native-nsupdate", Arc::new(nsupdate::NsUpdatePlugin)),
+        ("native-pdns", Arc::new(powerdns::PowerDnsPlugin)),
+    ]
+}
+
+/// The schemas of the built-in plugins, in the format of the `acme.sh` DNS hook schemas.
+pub(crate) fn builtin_schemas() -> Vec<AcmeChallengeSchema> {
+    let field = |description: &str| json!({ "type": "string", "description": description });
+
+    let schemas = [
+        (
+            "native-cf",
+            "Cloudflare Managed DNS (native)",
+            json!({
+                "CF_Token": field("API token, alternatively use CF_Key and CF_Email"),
+                "CF_Account_ID": field("Account ID, used to look up the zone"),
+                "CF_Zone_ID": field("Zone ID, looked up if not set"),
+                "CF_Key": field("Global API key"),
+                "CF_Email": field("Email address of the global API key"),
+            }),
+        ),
+        (
+            "native-nsupdate",
+            "nsupdate (RFC 2136, native)",
+            json!({
+                "NSUPDATE_SERVER": field("Name server receiving the updates"),
+                "NSUPDATE_SERVER_PORT": field("Port of the name server, defaults to 53"),
+                "NSUPDATE_KEY": field("TSIG key definition as created by tsig-keygen"),
+                "NSUPDATE_ZONE": field("Zone to update, looked up if not set"),
+            }),
+        ),
+        (
+            "native-pdns",
+            "PowerDNS (native)",
+            json!({
+                "PDNS_Url": field("API URL, e.g. http://ns.example.com:8081"),
+                "PDNS_ServerId": field("Server ID, usually 'localhost'"),
+                "PDNS_Token": field("API key"),
+                "PDNS_Ttl": field("TTL of the TXT record, defaults to 60"),
+            }),
+        ),
+    ];
+
+    schemas
+        .into_iter()
+        .map(|(id, name, fields)| AcmeChallengeSchema {
+            id: id.to_string(),
+            name: name.to_string(),
+            ty: "dns".to_string(),
+            schema: json!({ "name": name, "fields": fields }),
+        })
+        .collect()
+}
+
+/// The `KEY=value` variables of a plugin's data.
+struct PluginVars(HashMap<String, String>);
+
+impl PluginVars {
+    fn parse(data: &str) -> Self {
+        let mut vars = HashMap::new();
+
+        for line in data.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                    .unwrap_or(value);
+                vars.insert(key.trim().to_string(), value.to_string());
+            }
+        }
+
+        Self(vars)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .get(key)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn require(&self, key: &str) -> Result<&str, Error> {
+        self.get(key)
+            .ok_or_else(|| format_err!("missing '{}' in plugin data", key))
+    }
+}
+
+fn http_client() -> Box<dyn JsonTransport> {
+    Box::new(Client::with_options(HttpOptions {
+        user_agent: Some(USER_AGENT_STRING.to_string()),
+        proxy_config: PROXY_CONFIG.read().unwrap().clone(),
+        ..Default::default()
+    }))
+}
+
+/// The status and the decoded body of a JSON API response.
+type JsonResponse<'a> =
+    Pin<Box<dyn Future<Output = Result<(StatusCode, Value), Error>> + Send + 'a>>;
+
+/// Sends the requests of the HTTP API based plugins.
+trait JsonTransport: Send + Sync {
+    /// Perform a JSON API request and return the status and the decoded response body.
+    fn request<'a>(
+        &'a self,
+        method: Method,
+        uri: &'a str,
+        headers: &'a [(&'a str, &'a str)],
+        body: Option<Value>,
+    ) -> JsonResponse<'a>;
+}
+
+impl JsonTransport for Client {
+    fn request<'a>(
+        &'a self,
+        method: Method,
+        uri: &'a str,
+        headers: &'a [(&'a str, &'a str)],
+        body: Option<Value>,
+    ) -> JsonResponse<'a> {
+        Box::pin(json_request(self, method, uri, headers, body))
+    }
+}
+
+async fn json_request(
+    client: &Client,
+    method: Method,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Option<Value>,
+) -> Result<(StatusCode, Value), Error> {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(hyper::header::ACCEPT, "application/json");
+
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+
+    let request = match body {
+        Some(body) => request
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&body)?))?,
+        None => request.body(Body::empty())?,
+    };
+
+    let response = client.request(request).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+
+    let value = if body.is_empty() {
+        Value::Null
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(value) => value,
+            Err(_) if !status.is_success() => {
+                bail!(
+                    "request failed: {} - {}",
+                    status,
+                    String::from_utf8_lossy(&body)
+                )
+            }
+            Err(err) => bail!("unable to parse response - {}", err),
+        }
+    };
+
+    Ok((status, value))
+}
+
+#[cfg(test)]
+pub(super) mod mock {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// A request sent to a [`MockTransport`].
+    #[derive(Debug)]
+    pub(in crate::dns_plugins) struct MockRequest {
+        pub method: Method,
+        pub uri: String,
+        pub headers: Vec<(String, String)>,
+        pub body: Option<Value>,
+    }
+
+    /// Records requests and answers them with queued responses.
+    #[derive(Default)]
+    pub(in crate::dns_plugins) struct MockTransport {
+        responses: Mutex<VecDeque<(StatusCode, Value)>>,
+        requests: Arc<Mutex<Vec<MockRequest>>>,
+    }
+
+    impl MockTransport {
+        pub fn new(responses: Vec<(u16, Value)>) -> Self {
+            Self {
+                responses: Mutex::new(
+                    responses
+                        .into_iter()
+                        .map(|(status, body)| (StatusCode::from_u16(status).unwrap(), body))
+                        .collect(),
+                ),
+                requests: Default::default(),
+            }
+        }
+
+        /// Shared handle to the requests, to inspect them after moving the transport.
+        pub fn requests(&self) -> Arc<Mutex<Vec<MockRequest>>> {
+            Arc::clone(&self.requests)
+        }
+    }
+
+    impl JsonTransport for MockTransport {
+        fn request<'a>(
+            &'a self,
+            method: Method,
+            uri: &'a str,
+            headers: &'a [(&'a str, &'a str)],
+            body: Option<Value>,
+        ) -> JsonResponse<'a> {
+            self.requests.lock().unwrap().push(MockRequest {
+                method,
+                uri: uri.to_string(),
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                body,
+            });
+
+            let response = self
+                .responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| format_err!("unexpected request to {uri}"));
+            Box::pin(async move { response })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PluginVars;
+
+    #[test]
+    fn test_plugin_vars() {
+        let vars = PluginVars::parse(
+            "CF_Token=abc\n\n# comment\nCF_Email=\"root@example.com\"\nCF_Key='a=b'\nCF_Zone_ID=\n",
+        );
+
+        assert_eq!(vars.get("CF_Token"), Some("abc"));
+        assert_eq!(vars.get("CF_Email"), Some("root@example.com"));
+        assert_eq!(vars.get("CF_Key"), Some("a=b"));
+        assert_eq!(vars.get("CF_Zone_ID"), None);
+        assert!(vars.require("CF_Account_ID").is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! RFC 2136 dynamic updates signed with TSIG (`native-nsupdate`, like `dns_nsupdate`).
+//!
+//! Configured via `NSUPDATE_SERVER`, `NSUPDATE_KEY` and optionally `NSUPDATE_SERVER_PORT` and
+//! `NSUPDATE_ZONE`. Unlike with the `acme.sh` hook, `NSUPDATE_KEY` contains the BIND style key
+//! definition as created by `tsig-keygen` itself, not the path to a key file.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use anyhow::{bail, format_err, Error};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+use super::PluginVars;
+use crate::dns_challenge::DnsChallengePlugin;
+use crate::dns_query::{self, TYPE_TXT};
+
+const OPCODE_UPDATE: u16 = 5 << 11;
+const TYPE_SOA: u16 = 6;
+const TYPE_TSIG: u16 = 250;
+const CLASS_IN: u16 = 1;
+const CLASS_NONE: u16 = 254;
+const CLASS_ANY: u16 = 255;
+const RECORD_TTL: u32 = 60;
+const TSIG_FUDGE: u16 = 300;
+
+pub(super) struct NsUpdatePlugin;
+
+/// A TSIG key.
+#[derive(Debug)]
+struct TsigKey {
+    name: String,
+    algorithm: String,
+    secret: Vec<u8>,
+}
+
+impl TsigKey {
+    /// Parse a BIND style key definition:
+    ///
+    /// ```text
+    /// key "name" {
+    ///     algorithm hmac-sha256;
+    ///     secret "base64 secret";
+    /// };
+    /// ```
+    fn parse(content: &str) -> Result<Self, Error> {
+        let content = content.replace(['{', '}', ';'], " ");
+        let mut tokens = content.split_whitespace().map(|t| t.trim_matches('"'));
+
+        let mut name = None;
+        let mut algorithm = None;
+        let mut secret = None;
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "key" => name = tokens.next(),
+                "algorithm" => algorithm = tokens.next(),
+                "secret" => secret = tokens.next(),
+                _ => (),
+            }
+        }
+
+        let name = name.ok_or_else(|| format_err!("missing key name"))?;
+        let algorithm = algorithm.ok_or_else(|| format_err!("missing key algorithm"))?;
+        let secret = secret.ok_or_else(|| format_err!("missing key secret"))?;
+
+        Ok(Self {
+            name: name.to_ascii_lowercase(),
+            algorithm: algorithm.to_ascii_lowercase(),
+            secret: base64::decode(secret).map_err(|err| format_err!("invalid secret - {err}"))?,
+        })
+    }
+
+    /// The digest and the algorithm's domain name used in the TSIG record.
+    fn digest(&self) -> Result<(MessageDigest, &'static str), Error> {
+        Ok(match self.algorithm.trim_end_matches('.') {
+            "hmac-md5" | "hmac-md5.sig-alg.reg.int" => {
+                (MessageDigest::md5(), "hmac-md5.sig-alg.reg.int")
+            }
+            "hmac-sha1" => (MessageDigest::sha1(), "hmac-sha1"),
+            "hmac-sha224" => (MessageDigest::sha224(), "hmac-sha224"),
+            "hmac-sha256" => (MessageDigest::sha256(), "hmac-sha256"),
+            "hmac-sha384" => (MessageDigest::sha384(), "hmac-sha384"),
+            "hmac-sha512" => (MessageDigest::sha512(), "hmac-sha512"),
+            other => bail!("unsupported TSIG algorithm '{}'", other),
+        })
+    }
+
+    /// Append a TSIG record to `message` and update its additional record count.
+    fn sign(&self, message: &mut Vec<u8>, time: u64) -> Result<(), Error> {
+        let (digest, algorithm) = self.digest()?;
+
+        let mut key_name = Vec::new();
+        dns_query::encode_name(&mut key_name, &self.name)?;
+        let mut algorithm_name = Vec::new();
+        dns_query::encode_name(&mut algorithm_name, algorithm)?;
+        let time = &time.to_be_bytes()[2..];
+
+        // TSIG variables, see RFC 8945 section 4.3.3
+        let mut variables = Vec::new();
+        variables.extend(&key_name);
+        variables.extend(CLASS_ANY.to_be_bytes());
+        variables.extend(0u32.to_be_bytes()); // TTL
+        variables.extend(&algorithm_name);
+        variables.extend(time);
+        variables.extend(TSIG_FUDGE.to_be_bytes());
+        variables.extend(0u16.to_be_bytes()); // error
+        variables.extend(0u16.to_be_bytes()); // other length
+
+        let key = PKey::hmac(&self.secret)?;
+        let mut signer = Signer::new(digest, &key)?;
+        signer.update(message)?;
+        signer.update(&variables)?;
+        let mac = signer.sign_to_vec()?;
+
+        let mut rdata = algorithm_name;
+        rdata.extend(time);
+        rdata.extend(TSIG_FUDGE.to_be_bytes());
+        rdata.extend((mac.len() as u16).to_be_bytes());
+        rdata.extend(&mac);
+        rdata.extend(&message[..2]); // original ID
+        rdata.extend(0u16.to_be_bytes()); // error
+        rdata.extend(0u16.to_be_bytes()); // other length
+
+        message.extend(key_name);
+        message.extend(TYPE_TSIG.to_be_bytes());
+        message.extend(CLASS_ANY.to_be_bytes());
+        message.extend(0u32.to_be_bytes());
+        message.extend((rdata.len() as u16).to_be_bytes());
+        message.extend(rdata);
+
+        let additional = dns_query::read_u16(message, 10)? + 1;
+        message[10..12].copy_from_slice(&additional.to_be_bytes());
+
+        Ok(())
+    }
+}
+
+/// Build an update message adding (`add == true`) or deleting a single TXT record.
+fn update_message(
+    id: u16,
+    zone: &str,
+    record: &str,
+    value: &str,
+    add: bool,
+) -> Result<Vec<u8>, Error> {
+    if value.len() > 255 {
+        bail!("TXT record value too long");
+    }
+
+    let mut message = Vec::new();
+    message.extend(id.to_be_bytes());
+    message.extend(OPCODE_UPDATE.to_be_bytes());
+    message.extend(1u16.to_be_bytes()); // zone
+    message.extend(0u16.to_be_bytes()); // prerequisites
+    message.extend(1u16.to_be_bytes()); // updates
+    message.extend(0u16.to_be_bytes()); // additionals
+
+    dns_query::encode_name(&mut message, zone)?;
+    message.extend(TYPE_SOA.to_be_bytes());
+    message.extend(CLASS_IN.to_be_bytes());
+
+    // deleting a specific record uses class NONE and a TTL of zero (RFC 2136 section 2.5.4)
+    let (class, ttl) = if add {
+        (CLASS_IN, RECORD_TTL)
+    } else {
+        (CLASS_NONE, 0)
+    };
+
+    dns_query::encode_name(&mut message, record)?;
+    message.extend(TYPE_TXT.to_be_bytes());
+    message.extend(class.to_be_bytes());
+    message.extend(ttl.to_be_bytes());
+    message.extend((value.len() as u16 + 1).to_be_bytes());
+    message.push(value.len() as u8);
+    message.extend(value.as_bytes());
+
+    Ok(message)
+}
+
+fn rcode_name(rcode: u16) -> &'static str {
+    match rcode {
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        _ => "unknown error",
+    }
+}
+
+async fn update(record: &str, value: &str, data: &str, add: bool) -> Result<(), Error> {
+    let vars = PluginVars::parse(data);
+
+    let key = TsigKey::parse(vars.require("NSUPDATE_KEY")?)
+        .map_err(|err| format_err!("invalid 'NSUPDATE_KEY' - {}", err))?;
+
+    let port: u16 = match vars.get("NSUPDATE_SERVER_PORT") {
+        Some(port) => port
+            .parse()
+            .map_err(|_| format_err!("invalid 'NSUPDATE_SERVER_PORT' value '{}'", port))?,
+        None => 53,
+    };
+    let host = vars.require("NSUPDATE_SERVER")?;
+    let server: SocketAddr = tokio::net::lookup_host((host, port))
+        .await?
+        .next()
+        .ok_or_else(|| format_err!("unable to resolve '{}'", host))?;
+
+    let zone = match vars.get("NSUPDATE_ZONE") {
+        Some(zone) => zone.to_string(),
+        None => dns_query::find_zone(record).await?.0,
+    };
+
+    let id = dns_query::random_id()?;
+    let mut message = update_message(id, &zone, record, value, add)?;
+    key.sign(&mut message, proxmox_time::epoch_i64() as u64)?;
+
+    let response = dns_query::exchange(server, &message).await?;
+    if dns_query::read_u16(&response, 0)? != id {
+        bail!("DNS update response ID mismatch");
+    }
+
+    match dns_query::read_u16(&response, 2)? & 0x000f {
+        0 => Ok(()),
+        rcode => bail!(
+            "DNS update of '{}' in zone '{}' failed: {}",
+            record,
+            zone,
+            rcode_name(rcode)
+        ),
+    }
+}
+
+impl DnsChallengePlugin for NsUpdatePlugin {
+    fn add_txt_record<'a>(
+        &'a self,
+        record: &'a str,
+        value: &'a str,
+        data: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(update(record, value, data, true))
+    }
+
+    fn remove_txt_record<'a>(
+        &'a self,
+        record: &'a str,
+        value: &'a str,
+        data: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(update(record, value, data, false))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tsig_signing() -> Result<(), Error> {
+        let key = TsigKey::parse(
+            "key \"acme-key\" {\n\talgorithm hmac-sha256;\n\tsecret \"c2VjcmV0\";\n};\n",
+        )?;
+        assert_eq!(key.name, "acme-key");
+        assert_eq!(key.algorithm, "hmac-sha256");
+        assert_eq!(key.secret, b"secret");
+
+        let mut message = update_message(
+            0x1234,
+            "example.com",
+            "_acme-challenge.example.com",
+            "value",
+            true,
+        )?;
+        let unsigned_len = message.len();
+        key.sign(&mut message, 1_700_000_000)?;
+
+        // the additional record count now includes the TSIG record
+        assert_eq!(&message[10..12], &[0, 1]);
+
+        let tsig = &message[unsigned_len..];
+        assert!(tsig.starts_with(b"\x08acme-key\x00\x00\xfa\x00\xff"));
+
+        // TSIG rdata: algorithm name, time, fudge, 32 byte MAC and the original ID
+        let rdata = &tsig[20..];
+        assert!(rdata.starts_with(b"\x0bhmac-sha256\x00"));
+        assert_eq!(&rdata[13..19], &1_700_000_000u64.to_be_bytes()[2..]);
+        assert_eq!(&rdata[21..23], &[0, 32]);
+        assert_eq!(&rdata[55..57], &[0x12, 0x34]);
+
+        assert!(TsigKey::parse("key \"x\" { algorithm hmac-sha256; };").is_err());
+
+        // as a single line of plugin data
+        let vars = PluginVars::parse(
+            "NSUPDATE_KEY=key \"acme-key\" { algorithm hmac-sha256; secret \"c2VjcmV0\"; };",
+        );
+        let key = TsigKey::parse(vars.require("NSUPDATE_KEY")?)?;
+        assert_eq!(key.name, "acme-key");
+        assert_eq!(key.secret, b"secret");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+//! PowerDNS HTTP API (`native-pdns`, like `dns_pdns`).
+//!
+//! Configured via `PDNS_Url`, `PDNS_ServerId`, `PDNS_Token` and optionally `PDNS_Ttl`.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::{bail, format_err, Error};
+use hyper::Method;
+use serde_json::{json, Value};
+
+use super::{http_client, JsonTransport, PluginVars};
+use crate::dns_challenge::DnsChallengePlugin;
+
+const DEFAULT_TTL: u64 = 60;
+
+pub(super) struct PowerDnsPlugin;
+
+struct PowerDnsApi {
+    client: Box<dyn JsonTransport>,
+    server_url: String,
+    token: String,
+    ttl: u64,
+}
+
+impl PowerDnsApi {
+    fn new(vars: &PluginVars, client: Box<dyn JsonTransport>) -> Result<Self, Error> {
+        let ttl = match vars.get("PDNS_Ttl") {
+            Some(ttl) => ttl
+                .parse()
+                .map_err(|_| format_err!("invalid 'PDNS_Ttl' value '{}'", ttl))?,
+            None => DEFAULT_TTL,
+        };
+
+        Ok(Self {
+            client,
+            server_url: format!(
+                "{}/api/v1/servers/{}",
+                vars.require("PDNS_Url")?.trim_end_matches('/'),
+                vars.require("PDNS_ServerId")?,
+            ),
+            token: vars.require("PDNS_Token")?.to_string(),
+            ttl,
+        })
+    }
+
+    async fn call(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, Error> {
+        let uri = format!("{}{path}", self.server_url);
+        let headers = [("X-API-Key", self.token.as_str())];
+        let (status, response) = self.client.request(method, &uri, &headers, body).await?;
+
+        if !status.is_success() {
+            match response["error"].as_str() {
+                Some(error) => bail!("PowerDNS API call failed ({}): {}", status, error),
+                None => bail!("PowerDNS API call failed ({})", status),
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Find the zone containing `record`, zone names are fully qualified (ending with a dot).
+    async fn zone(&self, record: &str) -> Result<String, Error> {
+        let zones = self.call(Method::GET, "/zones", None).await?;
+        let record = format!("{record}.");
+
+        zones
+            .as_array()
+            .ok_or_else(|| format_err!("unexpected PowerDNS API response"))?
+            .iter()
+            .filter_map(|zone| zone["name"].as_str())
+            .filter(|zone| record.ends_with(&format!(".{zone}")))
+            .max_by_key(|zone| zone.len())
+            .map(str::to_string)
+            .ok_or_else(|| format_err!("unable to find PowerDNS zone for '{}'", record))
+    }
+
+    /// Get the current (quoted) contents of the TXT records for `record`.
+    async fn txt_records(&self, zone: &str, record: &str) -> Result<Vec<String>, Error> {
+        let zone_data = self
+            .call(Method::GET, &format!("/zones/{zone}"), None)
+            .await?;
+        let record = format!("{record}.");
+
+        Ok(zone_data["rrsets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|rrset| rrset["type"] == "TXT" && rrset["name"] == record.as_str())
+            .flat_map(|rrset| rrset["records"].as_array().into_iter().flatten())
+            .filter_map(|rr| rr["content"].as_str().map(str::to_string))
+            .collect())
+    }
+
+    /// Replace the TXT records of `record`, or delete them if `contents` is empty.
+    async fn update(&self, zone: &str, record: &str, contents: Vec<String>) -> Result<(), Error> {
+        let rrset = if contents.is_empty() {
+            json!({
+                "name": format!("{record}."),
+                "type": "TXT",
+                "changetype": "DELETE",
+            })
+        } else {
+            let records: Vec<Value> = contents
+                .into_iter()
+                .map(|content| json!({ "content": content, "disabled": false }))
+                .collect();
+
+            json!({
+                "name": format!("{record}."),
+                "type": "TXT",
+                "ttl": self.ttl,
+                "changetype": "REPLACE",
+                "records": records,
+            })
+        };
+
+        self.call(
+            Method::PATCH,
+            &format!("/zones/{zone}"),
+            Some(json!({ "rrsets": [rrset] })),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add(&self, record: &str, value: &str) -> Result<(), Error> {
+        let zone = self.zone(record).await?;
+        let content = format!("\"{value}\"");
+
+        let mut contents = self.txt_records(&zone, record).await?;
+        if contents.contains(&content) {
+            return Ok(());
+        }
+        contents.push(content);
+
+        self.update(&zone, record, contents).await
+    }
+
+    async fn remove(&self, record: &str, value: &str) -> Result<(), Error> {
+        let zone = self.zone(record).await?;
+        let content = format!("\"{value}\"");
+
+        let mut contents = self.txt_records(&zone, record).await?;
+        let count = contents.len();
+        contents.retain(|existing| *existing != content);
+        if contents.len() == count {
+            return Ok(());
+        }
+
+        self.update(&zone, record, contents).await
+    }
+}
+
+impl DnsChallengePlugin for PowerDnsPlugin {
+    fn add_txt_record<'a>(
+        &'a self,
+        record: &'a str,
+        value: &'a str,
+        data: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            PowerDnsApi::new(&PluginVars::parse(data), http_client())?
+                .add(record, value)
+                .await
+        })
+    }
+
+    fn remove_txt_record<'a>(
+        &'a self,
+        record: &'a str,
+        value: &'a str,
+        data: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            PowerDnsApi::new(&PluginVars::parse(data), http_client())?
+                .remove(record, value)
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dns_plugins::mock::MockTransport;
+
+    const VARS: &str = "PDNS_Url=http://ns.example.com:8081/\nPDNS_ServerId=localhost\n\
+        PDNS_Token=token\nPDNS_Ttl=120";
+    const RECORD: &str = "_acme-challenge.www.example.com";
+
+    fn zones() -> (u16, Value) {
+        (
+            200,
+            json!([{ "name": "com." }, { "name": "example.com." }, { "name": "other.com." }]),
+        )
+    }
+
+    fn zone(contents: &[&str]) -> (u16, Value) {
+        let records: Vec<Value> = contents
+            .iter()
+            .map(|content| json!({ "content": content, "disabled": false }))
+            .collect();
+        (
+            200,
+            json!({
+                "name": "example.com.",
+                "rrsets": [
+                    { "name": "example.com.", "type": "TXT", "records": [{ "content": "\"x\"" }] },
+                    { "name": "_acme-challenge.www.example.com.", "type": "TXT", "records": records },
+                ],
+            }),
+        )
+    }
+
+    #[test]
+    fn test_add_record() -> Result<(), Error> {
+        let transport = MockTransport::new(vec![zones(), zone(&["\"old\""]), (204, Value::Null)]);
+        let requests = transport.requests();
+
+        let api = PowerDnsApi::new(&PluginVars::parse(VARS), Box::new(transport))?;
+        futures::executor::block_on(api.add(RECORD, "value"))?;
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[0].uri,
+            "http://ns.example.com:8081/api/v1/servers/localhost/zones"
+        );
+        assert_eq!(
+            requests[0].headers,
+            [("X-API-Key".to_string(), "token".to_string())]
+        );
+        assert_eq!(
+            requests[1].uri,
+            "http://ns.example.com:8081/api/v1/servers/localhost/zones/example.com."
+        );
+        assert_eq!(requests[2].method, Method::PATCH);
+        assert_eq!(
+            requests[2].body,
+            Some(json!({
+                "rrsets": [{
+                    "name": "_acme-challenge.www.example.com.",
+                    "type": "TXT",
+                    "ttl": 120,
+                    "changetype": "REPLACE",
+                    "records": [
+                        { "content": "\"old\"", "disabled": false },
+                        { "content": "\"value\"", "disabled": false },
+                    ],
+                }],
+            }))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_record() -> Result<(), Error> {
+        let transport = MockTransport::new(vec![zones(), zone(&["\"value\""]), (204, Value::Null)]);
+        let requests = transport.requests();
+
+        let api = PowerDnsApi::new(&PluginVars::parse(VARS), Box::new(transport))?;
+        futures::executor::block_on(api.remove(RECORD, "value"))?;
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[2].body,
+            Some(json!({
+                "rrsets": [{
+                    "name": "_acme-challenge.www.example.com.",
+                    "type": "TXT",
+                    "changetype": "DELETE",
+                }],
+            }))
+        );
+
+        // nothing to do if the record is gone already
+        let transport = MockTransport::new(vec![zones(), zone(&[])]);
+        let requests = transport.requests();
+        let api = PowerDnsApi::new(&PluginVars::parse(VARS), Box::new(transport))?;
+        futures::executor::block_on(api.remove(RECORD, "value"))?;
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_error() {
+        let transport = MockTransport::new(vec![(401, json!({ "error": "Unauthorized" }))]);
+
+        let api = PowerDnsApi::new(&PluginVars::parse(VARS), Box::new(transport)).unwrap();
+        let err = futures::executor::block_on(api.add(RECORD, "value")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "PowerDNS API call failed (401 Unauthorized): Unauthorized"
+        );
+    }
+}
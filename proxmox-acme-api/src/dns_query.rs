@@ -0,0 +1,407 @@
+//! Minimal DNS client used to check the propagation of DNS challenge records.
+//!
+//! This only supports what is needed to find the authoritative name servers of a record and to
+//! query them for TXT records directly, bypassing any caching resolvers.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use tokio::net::UdpSocket;
+
+pub(crate) const TYPE_NS: u16 = 2;
+pub(crate) const TYPE_TXT: u16 = 16;
+
+const CLASS_IN: u16 = 1;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// The data of a resource record, only names and TXT records are decoded.
+#[derive(Debug, PartialEq)]
+pub(crate) enum RecordData {
+    Name(String),
+    Txt(String),
+    Other,
+}
+
+#[derive(Debug)]
+pub(crate) struct Record {
+    pub name: String,
+    pub ty: u16,
+    pub data: RecordData,
+}
+
+#[derive(Debug)]
+pub(crate) struct Response {
+    pub answers: Vec<Record>,
+}
+
+/// Append `name` in uncompressed wire format.
+pub(crate) fn encode_name(packet: &mut Vec<u8>, name: &str) -> Result<(), Error> {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("invalid DNS name '{}'", name);
+        }
+        packet.push(label.len() as u8);
+        packet.extend(label.as_bytes());
+    }
+    packet.push(0);
+    Ok(())
+}
+
+fn encode_query(id: u16, name: &str, ty: u16, recursion: bool) -> Result<Vec<u8>, Error> {
+    let flags: u16 = if recursion { 0x0100 } else { 0 };
+
+    let mut packet = Vec::with_capacity(12 + name.len() + 6);
+    packet.extend(id.to_be_bytes());
+    packet.extend(flags.to_be_bytes());
+    packet.extend(1u16.to_be_bytes()); // questions
+    packet.extend([0u8; 6]); // answers, authorities, additionals
+
+    encode_name(&mut packet, name)?;
+
+    packet.extend(ty.to_be_bytes());
+    packet.extend(CLASS_IN.to_be_bytes());
+
+    Ok(packet)
+}
+
+pub(crate) fn read_u16(packet: &[u8], pos: usize) -> Result<u16, Error> {
+    match packet.get(pos..(pos + 2)) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => bail!("truncated DNS packet"),
+    }
+}
+
+/// Read a (possibly compressed) name starting at `pos`, which is moved behind it.
+fn read_name(packet: &[u8], pos: &mut usize) -> Result<String, Error> {
+    let mut labels = Vec::new();
+    let mut cursor = *pos;
+    let mut jumped = false;
+
+    // limit the number of pointers to avoid loops
+    for _ in 0..64 {
+        let len = *packet
+            .get(cursor)
+            .ok_or_else(|| format_err!("truncated DNS packet"))? as usize;
+
+        if len & 0xc0 == 0xc0 {
+            let pointer = (read_u16(packet, cursor)? & 0x3fff) as usize;
+            if !jumped {
+                *pos = cursor + 2;
+                jumped = true;
+            }
+            cursor = pointer;
+        } else if len == 0 {
+            if !jumped {
+                *pos = cursor + 1;
+            }
+            return Ok(labels.join("."));
+        } else {
+            let label = packet
+                .get((cursor + 1)..(cursor + 1 + len))
+                .ok_or_else(|| format_err!("truncated DNS packet"))?;
+            labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+            cursor += 1 + len;
+        }
+    }
+
+    bail!("too many compression pointers in DNS packet");
+}
+
+fn parse_response(id: u16, packet: &[u8]) -> Result<Response, Error> {
+    if read_u16(packet, 0)? != id {
+        bail!("DNS response ID mismatch");
+    }
+
+    let flags = read_u16(packet, 2)?;
+    match flags & 0x000f {
+        0 | 3 => (), // NOERROR and NXDOMAIN
+        rcode => bail!("DNS query failed with response code {}", rcode),
+    }
+
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        read_name(packet, &mut pos)?;
+        pos += 4;
+    }
+
+    let mut records = Vec::with_capacity(answers as usize);
+    for _ in 0..answers {
+        let name = read_name(packet, &mut pos)?;
+        let ty = read_u16(packet, pos)?;
+        let len = read_u16(packet, pos + 8)? as usize;
+        pos += 10;
+
+        let rdata = packet
+            .get(pos..(pos + len))
+            .ok_or_else(|| format_err!("truncated DNS packet"))?;
+
+        let data = match ty {
+            TYPE_NS => {
+                let mut name_pos = pos;
+                RecordData::Name(read_name(packet, &mut name_pos)?)
+            }
+            TYPE_TXT => {
+                let mut txt = String::new();
+                let mut i = 0;
+                while i < rdata.len() {
+                    let part_len = rdata[i] as usize;
+                    let part = rdata
+                        .get((i + 1)..(i + 1 + part_len))
+                        .ok_or_else(|| format_err!("truncated TXT record"))?;
+                    txt.push_str(&String::from_utf8_lossy(part));
+                    i += 1 + part_len;
+                }
+                RecordData::Txt(txt)
+            }
+            _ => RecordData::Other,
+        };
+
+        records.push(Record { name, ty, data });
+        pos += len;
+    }
+
+    Ok(Response { answers: records })
+}
+
+/// Generate a random message ID.
+pub(crate) fn random_id() -> Result<u16, Error> {
+    let mut id = [0u8; 2];
+    openssl::rand::rand_bytes(&mut id)?;
+    Ok(u16::from_ne_bytes(id))
+}
+
+/// Send a message via UDP and wait for the response.
+pub(crate) async fn exchange(server: SocketAddr, packet: &[u8]) -> Result<Vec<u8>, Error> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => ([0u8; 4], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(packet).await?;
+
+    let mut buffer = vec![0u8; 4096];
+    let len = tokio::time::timeout(QUERY_TIMEOUT, socket.recv(&mut buffer))
+        .await
+        .map_err(|_| format_err!("DNS query to {} timed out", server))??;
+    buffer.truncate(len);
+
+    Ok(buffer)
+}
+
+/// Send a single query via UDP.
+pub(crate) async fn query(
+    server: SocketAddr,
+    name: &str,
+    ty: u16,
+    recursion: bool,
+) -> Result<Response, Error> {
+    let id = random_id()?;
+    let packet = encode_query(id, name, ty, recursion)?;
+    let response = exchange(server, &packet).await?;
+
+    parse_response(id, &response)
+}
+
+/// The name servers from `/etc/resolv.conf`.
+fn system_resolvers() -> Result<Vec<SocketAddr>, Error> {
+    let content = std::fs::read_to_string(RESOLV_CONF)
+        .map_err(|err| format_err!("unable to read {} - {}", RESOLV_CONF, err))?;
+
+    let resolvers: Vec<SocketAddr> = content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .map(|addr| SocketAddr::new(addr, 53))
+        .collect();
+
+    if resolvers.is_empty() {
+        bail!("no name servers configured in {}", RESOLV_CONF);
+    }
+
+    Ok(resolvers)
+}
+
+/// Find the zone containing `name` and the host names of its name servers.
+pub(crate) async fn find_zone(name: &str) -> Result<(String, Vec<String>), Error> {
+    let resolver = system_resolvers()?[0];
+
+    let mut zone = name.trim_end_matches('.');
+    loop {
+        let response = query(resolver, zone, TYPE_NS, true).await?;
+        let hosts: Vec<String> = response
+            .answers
+            .into_iter()
+            .filter(|record| record.ty == TYPE_NS && record.name.eq_ignore_ascii_case(zone))
+            .filter_map(|record| match record.data {
+                RecordData::Name(host) => Some(host),
+                _ => None,
+            })
+            .collect();
+
+        if !hosts.is_empty() {
+            return Ok((zone.to_string(), hosts));
+        }
+
+        zone = match zone.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => parent,
+            _ => bail!("unable to find authoritative name servers for '{}'", name),
+        };
+    }
+}
+
+/// Find the addresses of the authoritative name servers of the zone containing `name`.
+pub(crate) async fn authoritative_servers(name: &str) -> Result<Vec<SocketAddr>, Error> {
+    let (zone, hosts) = find_zone(name).await?;
+
+    let mut servers = Vec::new();
+    for host in hosts {
+        match tokio::net::lookup_host((host.as_str(), 53)).await {
+            Ok(addrs) => servers.extend(addrs),
+            Err(err) => log::warn!("unable to resolve name server '{host}' - {err}"),
+        }
+    }
+    if servers.is_empty() {
+        bail!("unable to resolve any name server of zone '{}'", zone);
+    }
+
+    Ok(servers)
+}
+
+/// Get the TXT records of `name` from a specific (authoritative) server.
+pub(crate) async fn txt_records(server: SocketAddr, name: &str) -> Result<Vec<String>, Error> {
+    let name = name.trim_end_matches('.');
+    let response = query(server, name, TYPE_TXT, false).await?;
+
+    Ok(response
+        .answers
+        .into_iter()
+        .filter(|record| record.ty == TYPE_TXT && record.name.eq_ignore_ascii_case(name))
+        .filter_map(|record| match record.data {
+            RecordData::Txt(txt) => Some(txt),
+            _ => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() -> Result<(), Error> {
+        let query = encode_query(0x1234, "_acme-challenge.example.com.", TYPE_TXT, false)?;
+        assert_eq!(&query[..4], &[0x12, 0x34, 0, 0]);
+
+        let mut packet = query.clone();
+        packet[2] = 0x84; // response, authoritative
+        packet[7] = 2; // two answers
+
+        // TXT record split into two strings, name compressed to the question
+        packet.extend([0xc0, 12]);
+        packet.extend(TYPE_TXT.to_be_bytes());
+        packet.extend(CLASS_IN.to_be_bytes());
+        packet.extend(60u32.to_be_bytes());
+        packet.extend(9u16.to_be_bytes());
+        packet.extend(b"\x03abc\x04defg");
+
+        // NS record pointing into the question name ("example.com")
+        packet.extend([0xc0, 12 + 16]);
+        packet.extend(TYPE_NS.to_be_bytes());
+        packet.extend(CLASS_IN.to_be_bytes());
+        packet.extend(60u32.to_be_bytes());
+        packet.extend(5u16.to_be_bytes());
+        packet.extend(b"\x02ns\xc0");
+        packet.push(12 + 16);
+
+        let response = parse_response(0x1234, &packet)?;
+        assert_eq!(response.answers.len(), 2);
+        assert_eq!(response.answers[0].name, "_acme-challenge.example.com");
+        assert_eq!(response.answers[0].data, RecordData::Txt("abcdefg".into()));
+        assert_eq!(response.answers[1].name, "example.com");
+        assert_eq!(
+            response.answers[1].data,
+            RecordData::Name("ns.example.com".into())
+        );
+
+        assert!(parse_response(0x4321, &packet).is_err());
+        assert!(parse_response(0x1234, &packet[..packet.len() - 3]).is_err());
+
+        Ok(())
+    }
+    #[test]
+    fn test_encode_name() -> Result<(), Error> {
+        let mut packet = Vec::new();
+        encode_name(&mut packet, "_acme-challenge.example.com.")?;
+        assert_eq!(packet, b"\x0f_acme-challenge\x07example\x03com\x00");
+
+        assert!(encode_name(&mut Vec::new(), "example..com").is_err());
+        assert!(encode_name(&mut Vec::new(), &format!("{}.com", "a".repeat(64))).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_name_pointer_loop() {
+        let packet = [0xc0, 0];
+        assert!(read_name(&packet, &mut 0).is_err());
+    }
+
+    /// Answer a single TXT query with `records`, each given as owner name and text.
+    async fn answer_txt_query(socket: UdpSocket, records: &[(&str, &str)]) -> Result<(), Error> {
+        let mut buffer = vec![0u8; 512];
+        let (len, peer) = socket.recv_from(&mut buffer).await?;
+        buffer.truncate(len);
+
+        assert_eq!(
+            read_u16(&buffer, 2)? & 0x0100,
+            0,
+            "must not ask for recursion"
+        );
+
+        buffer[2] |= 0x84; // response, authoritative
+        buffer[6..8].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        for (name, txt) in records {
+            encode_name(&mut buffer, name)?;
+            buffer.extend(TYPE_TXT.to_be_bytes());
+            buffer.extend(CLASS_IN.to_be_bytes());
+            buffer.extend(60u32.to_be_bytes());
+            buffer.extend((txt.len() as u16 + 1).to_be_bytes());
+            buffer.push(txt.len() as u8);
+            buffer.extend(txt.as_bytes());
+        }
+
+        socket.send_to(&buffer, peer).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_txt_records() -> Result<(), Error> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let server = socket.local_addr()?;
+
+        let name = "_acme-challenge.example.com";
+        let responder = tokio::spawn(async move {
+            answer_txt_query(
+                socket,
+                &[
+                    ("_ACME-challenge.example.com", "first"),
+                    ("other.example.com", "unrelated"),
+                    ("_acme-challenge.example.com", "second"),
+                ],
+            )
+            .await
+        });
+
+        let records = txt_records(server, &format!("{name}.")).await?;
+        assert_eq!(records, ["first", "second"]);
+        responder.await??;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! HTTP-01 challenge responder.
+//!
+//! Pending challenges are kept in a process wide table. By default, they are answered by a
+//! temporary listener on port 80 spawned for the duration of the validation. Standalone plugins
+//! with `api-server` set instead rely on a handler registered on an existing [`ApiConfig`], see
+//! [`register_acme_challenge_handler`].
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use hyper::{Body, Method, Response, StatusCode};
+
+use proxmox_rest_server::{ApiConfig, Middleware};
+
+pub(crate) const ACME_CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+lazy_static::lazy_static! {
+    static ref PENDING_CHALLENGES: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+}
+
+/// Register a handler answering HTTP-01 challenges on `config`.
+///
+/// The handler is only relied upon by standalone plugins with `api-server` set, which requires
+/// the server using this configuration to be reachable via plain HTTP on port 80 for all domains
+/// validated with such a plugin.
+pub fn register_acme_challenge_handler(config: ApiConfig) -> ApiConfig {
+    config.register_middleware(Middleware::new().before_fn(|parts, _peer| {
+        let response = respond(&parts.method, parts.uri.path());
+        Box::pin(async move { Ok(response) })
+    }))
+}
+
+/// The response to a request for `path`, if it is for a pending challenge.
+pub(crate) fn respond(method: &Method, path: &str) -> Option<Response<Body>> {
+    match *method {
+        Method::GET => path
+            .strip_prefix(ACME_CHALLENGE_PATH)
+            .and_then(challenge_response),
+        _ => None,
+    }
+}
+
+pub(crate) fn add_challenge(token: &str, key_authorization: String) {
+    PENDING_CHALLENGES
+        .write()
+        .unwrap()
+        .insert(token.to_string(), key_authorization);
+}
+
+pub(crate) fn remove_challenge(token: &str) {
+    PENDING_CHALLENGES.write().unwrap().remove(token);
+}
+
+/// The response for a pending challenge `token`, if there is one.
+pub(crate) fn challenge_response(token: &str) -> Option<Response<Body>> {
+    let key_authorization = PENDING_CHALLENGES.read().unwrap().get(token).cloned()?;
+
+    Some(
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "application/octet-stream")
+            .body(key_authorization.into())
+            .unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn body(response: Response<Body>) -> String {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_challenge_response() {
+        assert!(challenge_response("test-token").is_none());
+
+        add_challenge("test-token", "test-token.thumbprint".to_string());
+        let response = challenge_response("test-token").unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[hyper::header::CONTENT_TYPE],
+            "application/octet-stream"
+        );
+        let body = futures::executor::block_on(body(response));
+        assert_eq!(body, "test-token.thumbprint");
+
+        remove_challenge("test-token");
+        assert!(challenge_response("test-token").is_none());
+    }
+
+    #[test]
+    fn test_respond() {
+        add_challenge("respond-token", "respond-token.thumbprint".to_string());
+
+        let path = format!("{ACME_CHALLENGE_PATH}respond-token");
+        let response = respond(&Method::GET, &path).unwrap();
+        let body = futures::executor::block_on(body(response));
+        assert_eq!(body, "respond-token.thumbprint");
+
+        // other requests are left to the API server
+        assert!(respond(&Method::POST, &path).is_none());
+        assert!(respond(&Method::GET, "/respond-token").is_none());
+        assert!(respond(&Method::GET, "/api2/json/version").is_none());
+        assert!(respond(&Method::GET, &format!("{ACME_CHALLENGE_PATH}other-token")).is_none());
+
+        remove_challenge("respond-token");
+        assert!(respond(&Method::GET, &path).is_none());
+    }
+}
@@ -25,6 +25,7 @@ pub fn init<P: AsRef<Path>>(acme_config_dir: P, create_subdirs: bool) -> Result<
     if create_subdirs {
         create_secret_dir(self::acme_config_dir())?;
         create_secret_dir(acme_account_dir())?;
+        create_secret_dir(acme_order_dir())?;
     }
 
     Ok(())
@@ -46,6 +47,10 @@ pub(crate) fn acme_account_dir() -> &'static Path {
     acme_api_config().acme_account_dir.as_path()
 }
 
+pub(crate) fn acme_order_dir() -> PathBuf {
+    acme_config_dir().join("orders")
+}
+
 pub(crate) fn plugin_cfg_filename() -> PathBuf {
     acme_config_dir().join("plugins.cfg")
 }
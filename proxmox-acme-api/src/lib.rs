@@ -29,7 +29,8 @@ mod plugin_config;
 mod account_api_impl;
 #[cfg(feature = "impl")]
 pub use account_api_impl::{
-    deactivate_account, get_account, get_tos, list_accounts, register_account, update_account,
+    deactivate_account, get_account, get_directory_meta, get_tos, list_accounts, register_account,
+    update_account,
 };
 
 #[cfg(feature = "impl")]
@@ -40,7 +41,33 @@ pub use plugin_api_impl::{add_plugin, delete_plugin, get_plugin, list_plugins, u
 #[cfg(feature = "impl")]
 pub(crate) mod acme_plugin;
 
+#[cfg(feature = "impl")]
+mod dns_challenge;
+#[cfg(feature = "impl")]
+pub use dns_challenge::{register_dns_plugin, DnsChallengePlugin};
+
+#[cfg(feature = "impl")]
+mod dns_plugins;
+#[cfg(feature = "impl")]
+pub use dns_plugins::set_dns_plugin_proxy;
+
+#[cfg(feature = "impl")]
+mod http_challenge;
+#[cfg(feature = "impl")]
+pub use http_challenge::register_acme_challenge_handler;
+
+#[cfg(feature = "impl")]
+mod dns_query;
+
 #[cfg(feature = "impl")]
 mod certificate_helpers;
 #[cfg(feature = "impl")]
-pub use certificate_helpers::{create_self_signed_cert, order_certificate, revoke_certificate};
+pub use certificate_helpers::{
+    create_self_signed_cert, finalize_order, order_certificate, resume_order, revoke_certificate,
+    start_order,
+};
+
+#[cfg(feature = "impl")]
+mod order_state;
+#[cfg(feature = "impl")]
+pub use order_state::{delete_order, get_order, list_orders};
@@ -96,6 +96,9 @@ pub fn update_plugin(
                         DeletablePluginProperty::ValidationDelay => {
                             plugin.core.validation_delay = None;
                         }
+                        DeletablePluginProperty::PropagationTimeout => {
+                            plugin.core.propagation_timeout = None;
+                        }
                         DeletablePluginProperty::Disable => {
                             plugin.core.disable = None;
                         }
@@ -111,6 +114,9 @@ pub fn update_plugin(
             if update.validation_delay.is_some() {
                 plugin.core.validation_delay = update.validation_delay;
             }
+            if update.propagation_timeout.is_some() {
+                plugin.core.propagation_timeout = update.propagation_timeout;
+            }
             if update.disable.is_some() {
                 plugin.core.disable = update.disable;
             }
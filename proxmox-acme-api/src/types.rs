@@ -85,6 +85,43 @@ pub struct KnownAcmeDirectory {
     pub url: Cow<'static, str>,
 }
 
+#[api(
+    properties: {
+        "external-account-required": {
+            optional: true,
+            default: false,
+        },
+        "caa-identities": {
+            type: Array,
+            items: {
+                type: String,
+                description: "A CAA identity.",
+            },
+            optional: true,
+        },
+    },
+)]
+/// Metadata of an ACME directory.
+#[derive(Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AcmeDirectoryMeta {
+    /// The terms of service URL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terms_of_service: Option<String>,
+
+    /// Whether an external account binding is required to register an account.
+    #[serde(default)]
+    pub external_account_required: bool,
+
+    /// Website with information about the ACME server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub website: Option<String>,
+
+    /// Hostnames used by the CA, intended to be used with CAA DNS records.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub caa_identities: Vec<String>,
+}
+
 #[api(
     properties: {
         schema: {
@@ -205,6 +242,72 @@ pub struct AcmeConfig {
     pub account: String,
 }
 
+#[api()]
+/// Status of an ACME order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AcmeOrderStatus {
+    /// Some authorizations still need to be validated.
+    Pending,
+    /// All authorizations are valid, the order can be finalized.
+    Ready,
+    /// The order was finalized and the certificate is being issued.
+    Processing,
+    /// The certificate has been issued.
+    Valid,
+    /// The order failed or expired.
+    Invalid,
+}
+
+#[api(
+    properties: {
+        account: { type: AcmeAccountName },
+        domains: {
+            type: Array,
+            items: {
+                type: String,
+                description: "A domain name.",
+            },
+        },
+        status: { type: AcmeOrderStatus },
+    },
+)]
+/// The persisted state of an ACME order.
+///
+/// This allows to resume an interrupted order instead of placing a new one.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AcmeOrderState {
+    /// The order ID.
+    pub id: String,
+
+    /// The account the order was placed with.
+    pub account: AcmeAccountName,
+
+    /// The order's URL.
+    pub location: String,
+
+    /// The ordered domains.
+    pub domains: Vec<String>,
+
+    /// The last known status of the order.
+    pub status: AcmeOrderStatus,
+
+    /// Time the order was placed (UNIX epoch).
+    pub created: i64,
+
+    /// Time of the last status update (UNIX epoch).
+    pub updated: i64,
+
+    /// The order's expiration date as RFC3339 formatted time string.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+
+    /// The private key of the certificate signing request, in PEM format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+}
+
 /// Parse [AcmeConfig] from property string.
 pub fn parse_acme_config_string(value_str: &str) -> Result<AcmeConfig, Error> {
     let value = AcmeConfig::API_SCHEMA.parse_property_string(value_str)?;
@@ -250,6 +353,11 @@ pub struct PluginConfig {
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub validation_delay: Option<u32>,
 
+    /// Maximum time in seconds to wait for the TXT record to appear on all authoritative name
+    /// servers before the validation delay starts.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub propagation_timeout: Option<u32>,
+
     /// Flag to disable the config.
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub disable: Option<bool>,
@@ -258,19 +366,31 @@ pub struct PluginConfig {
 #[api(
     properties: {
         id: { schema: PLUGIN_ID_SCHEMA },
+        "api-server": {
+            optional: true,
+            default: false,
+        },
     },
 )]
 #[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 /// Standalone ACME Plugin for the http-1 challenge.
 pub struct StandalonePlugin {
     /// Plugin ID.
     id: String,
+
+    /// Answer challenges via the API server instead of a temporary listener on port 80. The API
+    /// server must have the ACME challenge handler registered and be reachable via plain HTTP on
+    /// port 80.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub api_server: Option<bool>,
 }
 
 impl Default for StandalonePlugin {
     fn default() -> Self {
         Self {
             id: "standalone".to_string(),
+            api_server: None,
         }
     }
 }
@@ -288,6 +408,12 @@ impl Default for StandalonePlugin {
             minimum: 0,
             maximum: 2 * 24 * 60 * 60,
         },
+        "propagation-timeout": {
+            default: 0,
+            optional: true,
+            minimum: 0,
+            maximum: 2 * 24 * 60 * 60,
+        },
     },
 )]
 /// DNS ACME Challenge Plugin core data.
@@ -307,6 +433,14 @@ pub struct DnsPluginCore {
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub validation_delay: Option<u32>,
 
+    /// Maximum time in seconds to wait for the TXT record to appear on all authoritative name
+    /// servers before the validation delay starts.
+    ///
+    /// The name servers are queried directly, so the validation delay can usually be reduced
+    /// when this is enabled. A value of 0 disables the check.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub propagation_timeout: Option<u32>,
+
     /// Flag to disable the config.
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub disable: Option<bool>,
@@ -339,6 +473,8 @@ pub enum DeletablePluginProperty {
     Disable,
     /// Delete the validation-delay property
     ValidationDelay,
+    /// Delete the propagation-timeout property
+    PropagationTimeout,
 }
 
 #[api(
@@ -353,3 +489,27 @@ pub enum DeletablePluginProperty {
 pub struct AccountEntry {
     pub name: AcmeAccountName,
 }
+
+#[cfg(test)]
+mod tests {
+    use proxmox_schema::ApiType;
+
+    use super::*;
+
+    #[test]
+    fn test_directory_meta_schema() {
+        let verify = |meta: &AcmeDirectoryMeta| {
+            let value = serde_json::to_value(meta).unwrap();
+            AcmeDirectoryMeta::API_SCHEMA.verify_json(&value)
+        };
+
+        verify(&AcmeDirectoryMeta::default()).expect("empty metadata must verify");
+        verify(&AcmeDirectoryMeta {
+            terms_of_service: Some("https://example.com/tos".into()),
+            external_account_required: true,
+            website: Some("https://example.com".into()),
+            caa_identities: vec!["example.com".into()],
+        })
+        .expect("full metadata must verify");
+    }
+}
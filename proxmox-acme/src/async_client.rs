@@ -31,6 +31,7 @@ impl AcmeClient {
             proxy_config: None, // fixme???
             user_agent: Some(USER_AGENT_STRING.to_string()),
             tcp_keepalive: Some(TCP_KEEPALIVE_TIME),
+            ..Default::default()
         };
 
         let http_client = Client::with_options(options);
@@ -11,10 +11,19 @@ pub struct UpdaterFieldAttributes {
     /// Change the type for the updater.
     ty: Option<syn::TypePath>,
 
+    /// Add `add_<field>` and `remove_<field>` updater fields for a `Vec` field.
+    list: Option<syn::LitBool>,
+
     /// Replace any `#[serde]` attributes on the field with these (accumulates).
     serde: Vec<syn::Attribute>,
 }
 
+/// Check whether a nested meta item has a value or arguments, the input also contains the
+/// following items.
+fn has_data(meta: &ParseNestedMeta<'_>) -> bool {
+    meta.input.peek(syn::Token![=]) || meta.input.peek(syn::token::Paren)
+}
+
 impl UpdaterFieldAttributes {
     pub fn from_attributes(input: &mut Vec<syn::Attribute>) -> Self {
         let mut this = Self::default();
@@ -37,10 +46,15 @@ impl UpdaterFieldAttributes {
         let path = &meta.path;
 
         if path.is_ident("skip") {
-            if !meta.input.is_empty() {
+            if has_data(&meta) {
                 return Err(meta.error("'skip' attribute does not take any data"));
             }
             util::set_bool(&mut self.skip, path, true);
+        } else if path.is_ident("list") {
+            if has_data(&meta) {
+                return Err(meta.error("'list' attribute does not take any data"));
+            }
+            util::set_bool(&mut self.list, path, true);
         } else if path.is_ident("type") {
             util::parse_str_value_to_option(&mut self.ty, path, meta.value()?);
         } else if path.is_ident("serde") {
@@ -57,6 +71,10 @@ impl UpdaterFieldAttributes {
         util::default_false(self.skip.as_ref())
     }
 
+    pub fn list(&self) -> bool {
+        util::default_false(self.list.as_ref())
+    }
+
     pub fn ty(&self) -> Option<&syn::TypePath> {
         self.ty.as_ref()
     }
@@ -68,3 +86,56 @@ impl UpdaterFieldAttributes {
         }
     }
 }
+
+#[derive(Default)]
+pub struct CliFieldAttributes {
+    /// Pass this field as positional argument instead of an option.
+    positional: Option<syn::LitBool>,
+
+    /// Environment variable used when the option is not passed on the command line.
+    env: Option<syn::LitStr>,
+}
+
+impl CliFieldAttributes {
+    pub fn from_attributes(input: &[syn::Attribute]) -> Self {
+        let mut this = Self::default();
+
+        for attr in input {
+            if attr.style != syn::AttrStyle::Outer || !attr.path().is_ident("cli") {
+                continue;
+            }
+            match attr.parse_nested_meta(|meta| this.parse(meta)) {
+                Ok(()) => (),
+                Err(err) => crate::add_error(err),
+            }
+        }
+
+        this
+    }
+
+    fn parse(&mut self, meta: ParseNestedMeta<'_>) -> Result<(), syn::Error> {
+        let path = &meta.path;
+
+        if path.is_ident("positional") {
+            if has_data(&meta) {
+                return Err(meta.error("'positional' attribute does not take any data"));
+            }
+            util::set_bool(&mut self.positional, path, true);
+        } else if path.is_ident("env") {
+            util::duplicate(&self.env, path);
+            self.env = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error(format!("invalid cli attribute: {path:?}")));
+        }
+
+        Ok(())
+    }
+
+    pub fn positional(&self) -> bool {
+        util::default_false(self.positional.as_ref())
+    }
+
+    pub fn env(&self) -> Option<&syn::LitStr> {
+        self.env.as_ref()
+    }
+}
@@ -3,17 +3,31 @@ use std::convert::{TryFrom, TryInto};
 use anyhow::Error;
 
 use proc_macro2::{Ident, Span, TokenStream};
-use quote::quote_spanned;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
 
-use super::Schema;
+use super::structs::handle_regular_field;
+use super::{ObjectEntry, Schema};
 use crate::serde;
 use crate::util::{self, FieldName, JSONObject, JSONValue, Maybe};
 
 /// Enums, provided they're simple enums, simply get an enum string schema attached to them.
+///
+/// Enums with data are handled by `handle_data_enum`.
 pub fn handle_enum(
     mut attribs: JSONObject,
     mut enum_ty: syn::ItemEnum,
 ) -> Result<TokenStream, Error> {
+    let container_attrs = serde::ContainerAttrib::try_from(&enum_ty.attrs[..])?;
+
+    let has_fields = enum_ty
+        .variants
+        .iter()
+        .any(|variant| !matches!(variant.fields, syn::Fields::Unit));
+    if has_fields || container_attrs.tag.is_some() {
+        return handle_data_enum(attribs, enum_ty, container_attrs);
+    }
+
     if !attribs.contains_key("type") {
         attribs.insert(
             FieldName::new("type".to_string(), Span::call_site()),
@@ -40,7 +54,6 @@ pub fn handle_enum(
         ts
     };
 
-    let container_attrs = serde::ContainerAttrib::try_from(&enum_ty.attrs[..])?;
     let derives_default = util::derives_trait(&enum_ty.attrs, "Default");
     let mut default_value = None;
 
@@ -57,16 +70,7 @@ pub fn handle_enum(
             comment = "<missing description>".to_string();
         }
 
-        let attrs = serde::VariantAttrib::try_from(&variant.attrs[..])?;
-        let variant_string = if let Some(renamed) = attrs.rename {
-            renamed
-        } else if let Some(rename_all) = container_attrs.rename_all {
-            let name = rename_all.apply_to_variant(&variant.ident.to_string());
-            syn::LitStr::new(&name, variant.ident.span())
-        } else {
-            let name = &variant.ident;
-            syn::LitStr::new(&name.to_string(), name.span())
-        };
+        let variant_string = variant_name(variant, &container_attrs)?;
 
         if derives_default {
             if let Some(attr) = variant.attrs.iter().find(|a| a.path().is_ident("default")) {
@@ -114,3 +118,191 @@ pub fn handle_enum(
         }
     })
 }
+
+/// Get the serialized name of an enum variant.
+fn variant_name(
+    variant: &syn::Variant,
+    container_attrs: &serde::ContainerAttrib,
+) -> Result<syn::LitStr, Error> {
+    let attrs = serde::VariantAttrib::try_from(&variant.attrs[..])?;
+    Ok(if let Some(renamed) = attrs.rename {
+        renamed
+    } else if let Some(rename_all) = container_attrs.rename_all {
+        let name = rename_all.apply_to_variant(&variant.ident.to_string());
+        syn::LitStr::new(&name, variant.ident.span())
+    } else {
+        let name = &variant.ident;
+        syn::LitStr::new(&name.to_string(), name.span())
+    })
+}
+
+/// Enums with data get a `OneOfSchema` with an object schema per variant.
+///
+/// This requires serde's internally tagged representation (`#[serde(tag = "type")]`), where the
+/// variant's fields are stored in an object alongside the tag property containing the variant
+/// name. Variants can be:
+///
+/// - unit variants, which get an empty object schema,
+/// - struct variants, whose fields are handled like the fields of regular structs,
+/// - newtype variants, which need to contain an api type with an object schema.
+fn handle_data_enum(
+    mut attribs: JSONObject,
+    enum_ty: syn::ItemEnum,
+    container_attrs: serde::ContainerAttrib,
+) -> Result<TokenStream, Error> {
+    let name = &enum_ty.ident;
+
+    let tag = match (
+        container_attrs.tag.as_ref(),
+        container_attrs.content.as_ref(),
+    ) {
+        (Some(tag), None) => tag,
+        (Some(_), Some(content)) => bail!(
+            content => "api macro does not support adjacently tagged enums, remove 'content'"
+        ),
+        (None, _) => bail!(
+            name => "api macro only supports enums with fields which are internally tagged via \
+                     #[serde(tag = \"...\")]"
+        ),
+    };
+
+    let description = match attribs.remove("description") {
+        Some(description) => description.try_into()?,
+        None => {
+            let (comment, span) = util::get_doc_comments(&enum_ty.attrs)?;
+            syn::LitStr::new(comment.trim(), span)
+        }
+    };
+
+    for (key, _) in attribs {
+        error!(
+            key.span(),
+            "unsupported key '{}' on enums with fields",
+            key.as_str()
+        );
+    }
+
+    let mut variants = Vec::new();
+    let mut schema_checks = TokenStream::new();
+    for variant in &enum_ty.variants {
+        let (mut comment, doc_span) = util::get_doc_comments(&variant.attrs)?;
+        if comment.is_empty() {
+            error!(&variant => "enum variant needs a description");
+            comment = "<missing description>".to_string();
+        }
+        let comment = syn::LitStr::new(comment.trim(), doc_span);
+
+        let variant_string = variant_name(variant, &container_attrs)?;
+
+        let schema = match &variant.fields {
+            syn::Fields::Unit => quote_spanned! { variant.ident.span() =>
+                &::proxmox_schema::ObjectSchema::new(#comment, &[]).schema()
+            },
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                // the type is only known by name here, so let the compiler check its schema
+                schema_checks.extend(quote_spanned! { ty.span() =>
+                    const _: () = assert!(
+                        matches!(
+                            <#ty as ::proxmox_schema::ApiType>::API_SCHEMA,
+                            ::proxmox_schema::Schema::Object(_)
+                                | ::proxmox_schema::Schema::AllOf(_)
+                                | ::proxmox_schema::Schema::OneOf(_)
+                        ),
+                        "newtype variants of api enums need to contain a type with an object schema",
+                    );
+                });
+                quote_spanned! { ty.span() => &<#ty as ::proxmox_schema::ApiType>::API_SCHEMA }
+            }
+            syn::Fields::Unnamed(fields) => bail!(
+                fields.paren_token.span.open(),
+                "api macro does not support tuple variants with multiple fields"
+            ),
+            syn::Fields::Named(fields) => {
+                let mut schema = Schema::empty_object(variant.ident.span());
+                schema.description = Maybe::Derived(comment.clone());
+
+                let mut properties = Vec::new();
+                for field in &fields.named {
+                    let attrs = serde::FieldAttrib::try_from(&field.attrs[..])?;
+                    if attrs.flatten {
+                        error!(field => "flattened fields are not supported in enum variants");
+                    }
+
+                    let ident = field
+                        .ident
+                        .as_ref()
+                        .ok_or_else(|| format_err!(field => "field without name?"))?;
+                    let name = match &attrs.rename {
+                        Some(renamed) => renamed.value(),
+                        None => ident.to_string(),
+                    };
+
+                    let mut field_def = ObjectEntry::new(
+                        FieldName::new(name, ident.span()),
+                        false,
+                        Schema::blank(ident.span()),
+                    );
+                    handle_regular_field(&mut field_def, field, true, &attrs)?;
+                    properties.push(field_def);
+                }
+                schema
+                    .item
+                    .check_object_mut()?
+                    .extend_properties(properties);
+
+                let mut ts = quote! { & };
+                schema.to_schema(&mut ts)?;
+                ts
+            }
+        };
+
+        variants.push((variant_string, comment, schema));
+    }
+
+    // `OneOfSchema::lookup_variant` expects the list to be sorted by name
+    variants.sort_by_key(|(variant_string, _, _)| variant_string.value());
+    for pair in variants.windows(2) {
+        if pair[0].0.value() == pair[1].0.value() {
+            error!(&pair[1].0 => "duplicate variant name '{}'", pair[1].0.value());
+        }
+    }
+
+    let mut type_entries = TokenStream::new();
+    let mut list = TokenStream::new();
+    for (variant_string, comment, schema) in variants {
+        type_entries.extend(quote_spanned! { variant_string.span() =>
+            ::proxmox_schema::EnumEntry {
+                value: #variant_string,
+                description: #comment,
+            },
+        });
+        list.extend(quote_spanned! { variant_string.span() => (#variant_string, #schema), });
+    }
+
+    Ok(quote_spanned! { name.span() =>
+        #enum_ty
+
+        #schema_checks
+
+        impl ::proxmox_schema::ApiType for #name {
+            const API_SCHEMA: ::proxmox_schema::Schema =
+                ::proxmox_schema::OneOfSchema::new(
+                    #description,
+                    &(
+                        #tag,
+                        false,
+                        &::proxmox_schema::StringSchema::new(#description)
+                            .format(&::proxmox_schema::ApiStringFormat::Enum(&[#type_entries]))
+                            .schema(),
+                    ),
+                    &[#list],
+                )
+                .schema();
+        }
+
+        impl ::proxmox_schema::UpdaterType for #name {
+            type Updater = Option<Self>;
+        }
+    })
+}
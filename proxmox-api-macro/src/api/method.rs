@@ -175,6 +175,14 @@ pub fn handle_method(mut attribs: JSONObject, mut func: syn::ItemFn) -> Result<T
         .transpose()?
         .unwrap_or(false);
 
+    let deprecated_setter = match attribs.remove("deprecated") {
+        Some(value) => match util::parse_deprecation(value)? {
+            Some(deprecation) => quote! { .deprecated(#deprecation) },
+            None => TokenStream::new(),
+        },
+        None => TokenStream::new(),
+    };
+
     if !attribs.is_empty() {
         error!(
             attribs.span(),
@@ -242,7 +250,8 @@ pub fn handle_method(mut attribs: JSONObject, mut func: syn::ItemFn) -> Result<T
             #returns_schema_setter
             #access_setter
             .reload_timezone(#reload_timezone)
-            .protected(#protected);
+            .protected(#protected)
+            #deprecated_setter;
 
         #default_consts
 
@@ -113,14 +113,33 @@ impl TryFrom<JSONObject> for Schema {
                 .transpose()?,
         );
 
+        let deprecated = match obj.remove("deprecated") {
+            Some(value) => {
+                let span = value.span();
+                crate::util::parse_deprecation(value)?.map(|deprecation| (span, deprecation))
+            }
+            None => None,
+        };
+
+        let span = obj.span();
+        let item = SchemaItem::try_extract_from(&mut obj)?;
+        let mut properties: Vec<(Ident, syn::Expr)> = obj
+            .into_iter()
+            .map(|(key, value)| Ok((key.into_ident(), value.try_into()?)))
+            .collect::<Result<_, syn::Error>>()?;
+
+        if let Some((span, deprecation)) = deprecated {
+            properties.push((
+                Ident::new("deprecated", span),
+                syn::Expr::Verbatim(deprecation),
+            ));
+        }
+
         Ok(Self {
-            span: obj.span(),
+            span,
             description,
-            item: SchemaItem::try_extract_from(&mut obj)?,
-            properties: obj
-                .into_iter()
-                .map(|(key, value)| Ok((key.into_ident(), value.try_into()?)))
-                .collect::<Result<_, syn::Error>>()?,
+            item,
+            properties,
         })
     }
 }
@@ -398,7 +417,12 @@ impl SchemaItem {
         for prop in properties {
             let key = &prop.0;
             let value = &prop.1;
-            ts.extend(quote! { .#key(#value) });
+            if key == "format" && matches!(self, SchemaItem::String(_)) {
+                let format = string_format(value);
+                ts.extend(quote! { .#key(#format) });
+            } else {
+                ts.extend(quote! { .#key(#value) });
+            }
         }
 
         Ok(false)
@@ -434,6 +458,43 @@ impl SchemaItem {
     }
 }
 
+/// Create the argument for a string schema's `format` builder.
+///
+/// Apart from arbitrary expressions, this allows referencing an `ApiStringFormat` constant by its
+/// path, and regular expressions given as string literals. The latter are verified here, so
+/// invalid patterns cause a compile error instead of a panic when they are first used.
+fn string_format(value: &syn::Expr) -> TokenStream {
+    match value {
+        Expr::Path(path) => quote_spanned! { path.span() => &#path },
+        Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(regex),
+            ..
+        }) => {
+            if let Err(err) = regex::Regex::new(&regex.value()) {
+                error!(regex => "invalid regular expression - {}", err);
+            }
+
+            quote_spanned! { regex.span() => {
+                const PATTERN: ::proxmox_schema::ConstRegexPattern =
+                    ::proxmox_schema::ConstRegexPattern {
+                        regex_string: #regex,
+                        regex_obj: || -> &'static ::proxmox_schema::semver_exempt::Regex {
+                            ::proxmox_schema::semver_exempt::lazy_static! {
+                                static ref REGEX: ::proxmox_schema::semver_exempt::Regex =
+                                    ::proxmox_schema::semver_exempt::Regex::new(#regex).unwrap();
+                            }
+                            &REGEX
+                        },
+                    };
+                const FORMAT: ::proxmox_schema::ApiStringFormat =
+                    ::proxmox_schema::ApiStringFormat::Pattern(&PATTERN);
+                &FORMAT
+            }}
+        }
+        _ => value.to_token_stream(),
+    }
+}
+
 #[derive(Clone)]
 pub enum OptionType {
     /// All regular api types just have simple boolean expressions for whether the fields in an
@@ -18,7 +18,7 @@ use anyhow::Error;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote_spanned;
 
-use super::attributes::UpdaterFieldAttributes;
+use super::attributes::{CliFieldAttributes, UpdaterFieldAttributes};
 use super::Schema;
 use crate::api::{self, ObjectEntry, SchemaItem};
 use crate::serde;
@@ -158,6 +158,10 @@ fn handle_regular_struct(
     let mut all_of_schemas = TokenStream::new();
     let mut to_remove = Vec::new();
 
+    let derive_cli = util::derives_trait(&stru.attrs, "CliArguments");
+    let mut cli_arg_param = Vec::new();
+    let mut cli_env_param = Vec::new();
+
     if let syn::Fields::Named(ref fields) = &stru.fields {
         for field in &fields.named {
             let attrs = serde::FieldAttrib::try_from(&field.attrs[..])?;
@@ -178,6 +182,19 @@ fn handle_regular_struct(
                 }
             };
 
+            if derive_cli {
+                let cli_attrs = CliFieldAttributes::from_attributes(&field.attrs);
+                if attrs.flatten && (cli_attrs.positional() || cli_attrs.env().is_some()) {
+                    error!(field => "cli attributes are not supported on flattened fields");
+                }
+                if cli_attrs.positional() {
+                    cli_arg_param.push(syn::LitStr::new(&name, span));
+                }
+                if let Some(env) = cli_attrs.env() {
+                    cli_env_param.push((syn::LitStr::new(&name, span), env.clone()));
+                }
+            }
+
             match schema_fields.remove(&name) {
                 Some(field_def) => {
                     if attrs.flatten {
@@ -222,9 +239,10 @@ fn handle_regular_struct(
                         field_def.schema.to_schema(&mut all_of_schemas)?;
                         all_of_schemas.extend(quote::quote! {,});
                         to_remove.push(name.clone());
-                    } else {
-                        new_fields.push(field_def);
                     }
+
+                    // flattened fields are kept (but marked) for the updater
+                    new_fields.push(field_def);
                 }
             }
         }
@@ -243,6 +261,10 @@ fn handle_regular_struct(
 
     {
         let obj = schema.item.check_object_mut()?;
+
+        // add derived fields
+        obj.extend_properties(new_fields);
+
         // remove flattened fields
         for field in to_remove {
             //if !obj.remove_property_by_ident(&field)
@@ -255,9 +277,6 @@ fn handle_regular_struct(
                 );
             }
         }
-
-        // add derived fields
-        obj.extend_properties(new_fields);
     }
 
     let updater = {
@@ -294,9 +313,35 @@ fn handle_regular_struct(
 
     output.extend(updater);
 
+    if derive_cli {
+        output.extend(derive_cli_arguments(
+            &stru.ident,
+            cli_arg_param,
+            cli_env_param,
+        ));
+    }
+
     Ok(output)
 }
 
+/// Implement `CliArguments` with the `#[cli(positional)]` fields as positional arguments and the
+/// `#[cli(env = "VAR")]` fields' environment variables.
+fn derive_cli_arguments(
+    name: &Ident,
+    arg_param: Vec<syn::LitStr>,
+    env_param: Vec<(syn::LitStr, syn::LitStr)>,
+) -> TokenStream {
+    let (env_names, env_vars): (Vec<_>, Vec<_>) = env_param.into_iter().unzip();
+
+    quote_spanned! { name.span() =>
+        impl ::proxmox_router::cli::CliArguments for #name {
+            const ARG_PARAM: &'static [&'static str] = &[#(#arg_param,)*];
+            const ENV_PARAM: &'static [(&'static str, &'static str)] =
+                &[#((#env_names, #env_vars),)*];
+        }
+    }
+}
+
 /// If we have flattened fields the struct schema is not the "final" schema, but part of an AllOf
 /// schema containing it and all the flattened field schemas.
 fn finish_all_of_struct(
@@ -369,7 +414,7 @@ fn finish_all_of_struct(
 /// Field handling:
 ///
 /// For each field we derive the description from doc-attributes if available.
-fn handle_regular_field(
+pub fn handle_regular_field(
     field_def: &mut ObjectEntry,
     field: &syn::Field,
     derived: bool, // whether this field was missing in the schema
@@ -415,6 +460,7 @@ fn derive_updater(
     }
 
     let updater_name = &stru.ident;
+    let container_attrs = serde::ContainerAttrib::try_from(&stru.attrs[..])?;
     let mut all_of_schemas = TokenStream::new();
     let mut is_empty_impl = TokenStream::new();
 
@@ -423,10 +469,15 @@ fn derive_updater(
             match handle_updater_field(
                 &mut field,
                 &mut schema,
+                &container_attrs,
                 &mut all_of_schemas,
                 &mut is_empty_impl,
             ) {
                 Ok(FieldAction::Keep) => fields.named.push(field),
+                Ok(FieldAction::KeepWithListFields(list_fields)) => {
+                    fields.named.push(field);
+                    fields.named.extend(list_fields);
+                }
                 Ok(FieldAction::Skip) => (),
                 Err(err) => {
                     crate::add_error(err);
@@ -463,12 +514,15 @@ fn derive_updater(
 
 enum FieldAction {
     Keep,
+    /// Keep the field and add the `add_<field>` and `remove_<field>` fields after it.
+    KeepWithListFields(Vec<syn::Field>),
     Skip,
 }
 
 fn handle_updater_field(
     field: &mut syn::Field,
     schema: &mut Schema,
+    container_attrs: &serde::ContainerAttrib,
     all_of_schemas: &mut TokenStream,
     is_empty_impl: &mut TokenStream,
 ) -> Result<FieldAction, syn::Error> {
@@ -499,6 +553,24 @@ fn handle_updater_field(
         }
     };
 
+    let list_fields = if updater_attrs.list() {
+        let list_entry = field_schema.clone();
+        Some(make_list_updater_fields(
+            field,
+            list_entry,
+            schema,
+            container_attrs,
+            is_empty_impl,
+        )?)
+    } else {
+        None
+    };
+
+    // the schema may have been extended by the list fields
+    let field_schema = schema
+        .find_obj_property_by_ident_mut(&field_name_string)
+        .expect("schema entry vanished");
+
     let span = Span::call_site();
     field_schema.optional = field.ty.clone().into();
     let updater = match updater_attrs.ty() {
@@ -543,5 +615,85 @@ fn handle_updater_field(
         self.#field_name.is_empty()
     });
 
-    Ok(FieldAction::Keep)
+    Ok(match list_fields {
+        Some(list_fields) => FieldAction::KeepWithListFields(list_fields),
+        None => FieldAction::Keep,
+    })
+}
+
+/// For `#[updater(list)]` fields of type `Vec<T>` we add `add_<field>` and `remove_<field>`
+/// fields of the same type to the updater, see `proxmox_schema::update_list`.
+fn make_list_updater_fields(
+    field: &syn::Field,
+    list_entry: ObjectEntry,
+    schema: &mut Schema,
+    container_attrs: &serde::ContainerAttrib,
+    is_empty_impl: &mut TokenStream,
+) -> Result<Vec<syn::Field>, syn::Error> {
+    use syn::parse::Parser;
+
+    let field_name = field.ident.as_ref().expect("unnamed field in FieldsNamed");
+
+    let is_vec = match &field.ty {
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "Vec")
+            .unwrap_or(false),
+        _ => false,
+    };
+    if !is_vec {
+        bail!(&field.ty => "'list' updater attribute is only supported on `Vec` fields");
+    }
+
+    let mut new_entries = Vec::new();
+    let mut new_fields = Vec::new();
+    for (prefix, what) in [
+        ("add", "Entries to add to"),
+        ("remove", "Entries to remove from"),
+    ] {
+        let ident = Ident::new(&format!("{prefix}_{field_name}"), field_name.span());
+        let name = match container_attrs.rename_all {
+            Some(rename_all) => rename_all.apply_to_field(&ident.to_string()),
+            None => ident.to_string(),
+        };
+
+        let mut entry = list_entry.clone();
+        entry.name = FieldName::new(name, field_name.span());
+        entry.optional = true.into();
+        if !matches!(
+            entry.schema.item,
+            SchemaItem::ExternType(_) | SchemaItem::ExternSchema(_)
+        ) {
+            entry.schema.description = Maybe::Derived(syn::LitStr::new(
+                &format!("{what} '{}'.", list_entry.name.as_str()),
+                field_name.span(),
+            ));
+        }
+        new_entries.push(entry);
+
+        let vis = &field.vis;
+        let ty = &field.ty;
+        new_fields.push(syn::Field::parse_named.parse2(
+            quote::quote_spanned! { field_name.span() =>
+                #[serde(default, skip_serializing_if = "Vec::is_empty")]
+                #vis #ident: #ty
+            },
+        )?);
+
+        if !is_empty_impl.is_empty() {
+            is_empty_impl.extend(quote::quote! { && });
+        }
+        is_empty_impl.extend(quote::quote! {
+            self.#ident.is_empty()
+        });
+    }
+
+    schema
+        .item
+        .check_object_mut()?
+        .extend_properties(new_entries);
+
+    Ok(new_fields)
 }
@@ -229,6 +229,102 @@ fn router_do(item: TokenStream) -> Result<TokenStream, Error> {
     declarations. If it contains a `schema` key, this is expected to be the path to an existing
     schema. (Hence `type: Foo` is the same as `schema: Foo::API_SCHEMA`.)
 
+    Similarly, the `format` of a string schema can be the path to an `ApiStringFormat` constant
+    (`format: SOME_FORMAT` is the same as `format: &SOME_FORMAT`), or a string literal containing
+    a regular expression. Such a regular expression is checked when the macro is expanded, so that
+    errors in it are reported at compile time.
+
+    ```
+    # use proxmox_api_macro::api;
+    # use serde::{Deserialize, Serialize};
+    #[api(
+        properties: {
+            id: { format: r"^[a-z][a-z0-9-]*$" },
+        },
+    )]
+    #[derive(Deserialize, Serialize)]
+    /// Some Description.
+    pub struct SomeId {
+        /// The id.
+        id: String,
+    }
+    ```
+
+    # Deprecation:
+
+    Methods, parameters and struct fields can be marked as deprecated via `deprecated: true`, or
+    with an object containing an optional `since` version and a `note` for clients. This is
+    exposed via the `deprecation()` methods of the generated `ApiMethod` and schemas, so that the
+    documentation generators can mention it. External types cannot be marked as deprecated.
+
+    ```
+    # use proxmox_api_macro::api;
+    # use anyhow::Error;
+    #[api(
+        input: {
+            properties: {
+                verbose: {
+                    type: bool,
+                    description: "Verbose output.",
+                    optional: true,
+                    deprecated: { since: "2.1", note: "the output is always verbose" },
+                },
+            },
+        },
+        deprecated: true,
+    )]
+    /// Get the status.
+    fn get_status(verbose: Option<bool>) -> Result<(), Error> {
+        # let _ = verbose;
+        Ok(())
+    }
+    ```
+
+    # Enums with data:
+
+    Enums with struct or newtype variants need to use serde's internally tagged representation via
+    `#[serde(tag = "...")]` and produce a `OneOfSchema` with the tag as type property. Struct
+    variants get an object schema derived from their fields like regular structs, newtype variants
+    need to contain an api type with an object schema, and unit variants get an empty object schema.
+
+    ```
+    # use proxmox_api_macro::api;
+    # use serde::{Deserialize, Serialize};
+    #[api]
+    /// A notification target.
+    #[derive(Deserialize, Serialize)]
+    #[serde(tag = "type", rename_all = "kebab-case")]
+    pub enum Target {
+        /// Send to a mail address.
+        Mail {
+            /// The recipient.
+            recipient: String,
+        },
+        /// Discard the notification.
+        Discard,
+    }
+    ```
+
+    Newtype variants containing a type without an object schema are rejected at compile time:
+
+    ```compile_fail
+    # use proxmox_api_macro::api;
+    # use serde::{Deserialize, Serialize};
+    #[api]
+    /// A mail address.
+    #[derive(Deserialize, Serialize)]
+    pub struct Address(String);
+
+    #[api]
+    /// A notification target.
+    #[derive(Deserialize, Serialize)]
+    #[serde(tag = "type", rename_all = "kebab-case")]
+    pub enum Target {
+        /// Send to a mail address.
+        Mail(Address),
+    }
+    ```
+
     # Deriving an `Updater`:
 
     An "Updater" struct can be generated automatically for a type. This affects the `UpdaterType`
@@ -246,6 +342,12 @@ fn router_do(item: TokenStream) -> Result<TokenStream, Error> {
       for the updater field.
     - `#[updater(serde(<content>))]`: *replace* the `#[serde]` attributes in the generated updater
       with `<content`>. This can be used to have different `skip_serializing_if` serde attributes.
+    - `#[updater(list)]`: for `Vec` fields, additionally generate `add_<field>` and
+      `remove_<field>` fields of the same type in the updater, which can be applied via
+      `proxmox_schema::update_list`.
+
+    Fields with `#[serde(flatten)]` use the flattened type's `Updater`, so the flattened type
+    needs to be an api type with an `Updater` as well.
 
     ```ignore
     #[api]
@@ -286,6 +388,41 @@ fn router_do(item: TokenStream) -> Result<TokenStream, Error> {
     }
 
     ```
+
+    # Deriving `CliArguments`:
+
+    Adding `#[derive(CliArguments)]` to an `#[api]` struct implements
+    `proxmox_router::cli::CliArguments` for it, so it can be parsed from command line arguments.
+    Fields are passed as `--<property-name> <value>` options and are verified against the schema.
+    The following field attributes can be used:
+
+    - `#[cli(positional)]`: pass the field as positional argument instead, in field order. Only
+      the last positional argument may be optional, and if it is an array, it takes all remaining
+      arguments.
+    - `#[cli(env = "VARIABLE")]`: use the environment variable if the option was not passed.
+
+    ```
+    # use proxmox_api_macro::{api, CliArguments};
+    # use serde::Deserialize;
+    use proxmox_router::cli::CliArguments;
+
+    #[api]
+    /// Login arguments.
+    #[derive(CliArguments, Deserialize)]
+    pub struct LoginArgs {
+        /// The user name.
+        #[cli(positional)]
+        user: String,
+
+        /// The password.
+        #[cli(env = "MY_TOOL_PASSWORD")]
+        password: Option<String>,
+    }
+
+    let (args, _remaining) = LoginArgs::parse_cli_arguments(&["root@pam", "--password", "123"])?;
+    assert_eq!(args.user, "root@pam");
+    # Ok::<(), anyhow::Error>(())
+    ```
 */
 #[proc_macro_attribute]
 pub fn api(attr: TokenStream_1, item: TokenStream_1) -> TokenStream_1 {
@@ -301,6 +438,13 @@ pub fn derive_updater(_item: TokenStream_1) -> TokenStream_1 {
     TokenStream_1::new()
 }
 
+/// This is a dummy derive macro actually handled by `#[api]`!
+#[doc(hidden)]
+#[proc_macro_derive(CliArguments, attributes(cli))]
+pub fn derive_cli_arguments(_item: TokenStream_1) -> TokenStream_1 {
+    TokenStream_1::new()
+}
+
 /// Create the default `UpdaterType` implementation as an `Option<Self>`.
 #[proc_macro_derive(UpdaterType, attributes(updater_type, serde))]
 pub fn derive_updater_type(item: TokenStream_1) -> TokenStream_1 {
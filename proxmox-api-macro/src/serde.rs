@@ -128,6 +128,20 @@ impl RenameAll {
 #[derive(Default)]
 pub struct ContainerAttrib {
     pub rename_all: Option<RenameAll>,
+    /// The property containing the variant name of an internally or adjacently tagged enum.
+    pub tag: Option<syn::LitStr>,
+    /// The property containing the variant data of an adjacently tagged enum.
+    pub content: Option<syn::LitStr>,
+}
+
+fn parse_lit_str(name: &str, value: &syn::Expr) -> Result<syn::LitStr, syn::Error> {
+    match value {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(s),
+            ..
+        }) => Ok(s.clone()),
+        _ => bail!(value => "'{}' value must be a string literal", name),
+    }
 }
 
 impl TryFrom<&[syn::Attribute]> for ContainerAttrib {
@@ -147,18 +161,22 @@ impl TryFrom<&[syn::Attribute]> for ContainerAttrib {
 
             for arg in args {
                 if let syn::Meta::NameValue(var) = arg {
-                    if !var.path.is_ident("rename_all") {
-                        continue;
-                    }
-                    match &var.value {
-                        syn::Expr::Lit(lit) => {
-                            let rename_all = RenameAll::try_from(&lit.lit)?;
-                            if this.rename_all.is_some() && this.rename_all != Some(rename_all) {
-                                error!(var.value => "multiple conflicting 'rename_all' attributes");
+                    if var.path.is_ident("rename_all") {
+                        match &var.value {
+                            syn::Expr::Lit(lit) => {
+                                let rename_all = RenameAll::try_from(&lit.lit)?;
+                                if this.rename_all.is_some() && this.rename_all != Some(rename_all)
+                                {
+                                    error!(var.value => "multiple conflicting 'rename_all' attributes");
+                                }
+                                this.rename_all = Some(rename_all);
                             }
-                            this.rename_all = Some(rename_all);
+                            _ => error!(var.value => "invalid 'rename_all' value type"),
                         }
-                        _ => error!(var.value => "invalid 'rename_all' value type"),
+                    } else if var.path.is_ident("tag") {
+                        this.tag = Some(parse_lit_str("tag", &var.value)?);
+                    } else if var.path.is_ident("content") {
+                        this.content = Some(parse_lit_str("content", &var.value)?);
                     }
                 }
             }
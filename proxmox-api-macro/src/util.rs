@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use proc_macro2::{Ident, Span, TokenStream, TokenTree};
-use quote::ToTokens;
+use quote::{quote_spanned, ToTokens};
 use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
@@ -373,6 +373,43 @@ impl IntoIterator for JSONObject {
     }
 }
 
+/// Parse a `deprecated` schema or method element into a `&Deprecation` expression.
+///
+/// Accepts either a boolean or an object with optional `since` and `note` strings. Returns `None`
+/// for `deprecated: false`.
+pub fn parse_deprecation(value: JSONValue) -> Result<Option<TokenStream>, syn::Error> {
+    let span = value.span();
+
+    let mut deprecation = quote_spanned! { span => ::proxmox_schema::Deprecation::new() };
+
+    match value {
+        JSONValue::Object(mut obj) => {
+            for key in ["since", "note"] {
+                if let Some(value) = obj.remove(key) {
+                    let value = syn::LitStr::try_from(value)?;
+                    let method = Ident::new(key, value.span());
+                    deprecation.extend(quote_spanned! { value.span() => .#method(#value) });
+                }
+            }
+
+            if !obj.is_empty() {
+                bail!(
+                    obj.span(),
+                    "unexpected deprecation elements: {}",
+                    join_debug(", ", obj.elements.keys()),
+                );
+            }
+        }
+        value => {
+            if !bool::try_from(value)? {
+                return Ok(None);
+            }
+        }
+    }
+
+    Ok(Some(quote_spanned! { span => &#deprecation }))
+}
+
 /// An element in a json style map.
 struct JSONMapEntry {
     pub key: FieldName,
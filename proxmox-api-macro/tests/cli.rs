@@ -0,0 +1,80 @@
+//! Test the `CliArguments` derive.
+
+use serde::Deserialize;
+
+use proxmox_api_macro::{api, CliArguments};
+use proxmox_router::cli::CliArguments;
+
+#[api(
+    properties: {
+        files: {
+            optional: true,
+            items: {
+                type: String,
+                description: "A file.",
+            },
+        },
+    },
+)]
+/// Backup arguments.
+#[derive(CliArguments, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackupArgs {
+    /// The repository.
+    #[cli(positional)]
+    repository: String,
+
+    /// The files.
+    #[cli(positional)]
+    files: Option<Vec<String>>,
+
+    /// The password.
+    #[cli(env = "PROXMOX_API_MACRO_TEST_PASSWORD")]
+    password: Option<String>,
+
+    /// Verbose output.
+    verbose: Option<bool>,
+
+    /// Chunk size in KiB.
+    chunk_size: Option<u64>,
+}
+
+#[test]
+fn test_cli_arguments() {
+    assert_eq!(BackupArgs::ARG_PARAM, ["repository", "files"]);
+    assert_eq!(
+        BackupArgs::ENV_PARAM,
+        [("password", "PROXMOX_API_MACRO_TEST_PASSWORD")]
+    );
+
+    let (args, remaining) = BackupArgs::parse_cli_arguments(&[
+        "--verbose",
+        "--chunk-size",
+        "4096",
+        "--password=secret",
+        "store",
+        "a.pxar",
+        "b.pxar",
+    ])
+    .expect("failed to parse arguments");
+
+    assert!(remaining.is_empty());
+    assert_eq!(args.repository, "store");
+    assert_eq!(args.files.unwrap(), ["a.pxar", "b.pxar"]);
+    assert_eq!(args.password.as_deref(), Some("secret"));
+    assert_eq!(args.verbose, Some(true));
+    assert_eq!(args.chunk_size, Some(4096));
+
+    std::env::set_var("PROXMOX_API_MACRO_TEST_PASSWORD", "from-env");
+    let (args, _) = BackupArgs::parse_cli_arguments(&["store"]).unwrap();
+    assert_eq!(args.password.as_deref(), Some("from-env"));
+    assert!(args.files.is_none());
+
+    let (args, _) = BackupArgs::parse_cli_arguments(&["--password", "given", "store"]).unwrap();
+    assert_eq!(args.password.as_deref(), Some("given"));
+
+    // schema validation
+    assert!(BackupArgs::parse_cli_arguments(&["--chunk-size", "-1", "store"]).is_err());
+    assert!(BackupArgs::parse_cli_arguments(&["--unknown", "1", "store"]).is_err());
+    assert!(BackupArgs::parse_cli_arguments::<&str>(&[]).is_err());
+}
@@ -0,0 +1,107 @@
+//! Test deprecation of methods, parameters and struct fields.
+
+#![allow(dead_code)]
+
+use proxmox_api_macro::api;
+use proxmox_schema::{ApiType, Deprecation, ObjectSchemaType};
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+#[api(
+    properties: {
+        "old-name": {
+            optional: true,
+            deprecated: { since: "2.0", note: "use 'name' instead" },
+        },
+        count: {
+            optional: true,
+            deprecated: true,
+        },
+    },
+)]
+/// A struct with deprecated fields.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WithDeprecated {
+    /// The name.
+    name: String,
+
+    /// The old name.
+    old_name: Option<String>,
+
+    /// A count.
+    count: Option<u64>,
+}
+
+#[test]
+fn test_deprecated_fields() {
+    const TEST_SCHEMA: ::proxmox_schema::Schema = ::proxmox_schema::ObjectSchema::new(
+        "A struct with deprecated fields.",
+        &[
+            (
+                "count",
+                true,
+                &::proxmox_schema::IntegerSchema::new("A count.")
+                    .minimum(0)
+                    .deprecated(&Deprecation::new())
+                    .schema(),
+            ),
+            (
+                "name",
+                false,
+                &::proxmox_schema::StringSchema::new("The name.").schema(),
+            ),
+            (
+                "old-name",
+                true,
+                &::proxmox_schema::StringSchema::new("The old name.")
+                    .deprecated(&Deprecation::new().since("2.0").note("use 'name' instead"))
+                    .schema(),
+            ),
+        ],
+    )
+    .schema();
+
+    assert_eq!(TEST_SCHEMA, WithDeprecated::API_SCHEMA);
+}
+
+#[api(
+    input: {
+        properties: {
+            value: {
+                type: Integer,
+                description: "A value.",
+                optional: true,
+                deprecated: { since: "3.1" },
+            },
+        },
+    },
+    deprecated: { note: "use the new endpoint" },
+)]
+/// A deprecated method.
+pub fn deprecated_method(value: Option<i64>) -> Result<(), Error> {
+    let _ = value;
+    Ok(())
+}
+
+#[api]
+/// A method which is not deprecated.
+pub fn current_method() -> Result<(), Error> {
+    Ok(())
+}
+
+#[test]
+fn test_deprecated_method() {
+    assert_eq!(
+        API_METHOD_DEPRECATED_METHOD.deprecation(),
+        Some(&Deprecation::new().note("use the new endpoint"))
+    );
+    assert_eq!(API_METHOD_CURRENT_METHOD.deprecation(), None);
+
+    let (_, schema) = API_METHOD_DEPRECATED_METHOD
+        .parameters
+        .lookup("value")
+        .expect("missing parameter");
+    assert_eq!(schema.deprecation(), Some(&Deprecation::new().since("3.1")));
+}
@@ -0,0 +1,112 @@
+//! Testing the `OneOf` schema on enums with data.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use proxmox_api_macro::api;
+use proxmox_schema as schema;
+use proxmox_schema::ApiType;
+
+#[api]
+/// A network address.
+#[derive(Deserialize, Serialize)]
+pub struct Address {
+    /// The host name.
+    host: String,
+    /// The port.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+}
+
+#[api]
+/// A notification target.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Target {
+    /// Send to a mail address.
+    Mail {
+        /// The recipient.
+        #[serde(rename = "mail-to")]
+        mail_to: String,
+        /// An optional subject prefix.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        prefix: Option<String>,
+    },
+    /// Send to a gotify server.
+    GotifyServer(Address),
+    /// Discard the notification.
+    Discard,
+}
+
+#[test]
+fn test_target() {
+    const TEST_TYPE_SCHEMA: schema::Schema = schema::StringSchema::new("A notification target.")
+        .format(&schema::ApiStringFormat::Enum(&[
+            schema::EnumEntry::new("discard", "Discard the notification."),
+            schema::EnumEntry::new("gotify-server", "Send to a gotify server."),
+            schema::EnumEntry::new("mail", "Send to a mail address."),
+        ]))
+        .schema();
+
+    const TEST_SCHEMA: schema::Schema = schema::OneOfSchema::new(
+        "A notification target.",
+        &("type", false, &TEST_TYPE_SCHEMA),
+        &[
+            (
+                "discard",
+                &schema::ObjectSchema::new("Discard the notification.", &[]).schema(),
+            ),
+            ("gotify-server", &Address::API_SCHEMA),
+            (
+                "mail",
+                &schema::ObjectSchema::new(
+                    "Send to a mail address.",
+                    &[
+                        (
+                            "mail-to",
+                            false,
+                            &schema::StringSchema::new("The recipient.").schema(),
+                        ),
+                        (
+                            "prefix",
+                            true,
+                            &schema::StringSchema::new("An optional subject prefix.").schema(),
+                        ),
+                    ],
+                )
+                .schema(),
+            ),
+        ],
+    )
+    .schema();
+
+    assert_eq!(TEST_SCHEMA, Target::API_SCHEMA);
+}
+
+#[test]
+fn test_target_verify() {
+    let schema = &Target::API_SCHEMA;
+
+    let mail = serde_json::to_value(Target::Mail {
+        mail_to: "root@localhost".to_string(),
+        prefix: None,
+    })
+    .unwrap();
+    schema.verify_json(&mail).expect("valid mail target");
+
+    let gotify = serde_json::to_value(Target::GotifyServer(Address {
+        host: "localhost".to_string(),
+        port: Some(80),
+    }))
+    .unwrap();
+    schema.verify_json(&gotify).expect("valid gotify target");
+
+    schema
+        .verify_json(&json!({ "type": "discard" }))
+        .expect("valid discard target");
+
+    assert!(schema
+        .verify_json(&json!({ "type": "mail", "host": "localhost" }))
+        .is_err());
+    assert!(schema.verify_json(&json!({ "type": "other" })).is_err());
+}
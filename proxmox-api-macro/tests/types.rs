@@ -186,3 +186,45 @@ fn string_check_schema_test() {
 pub struct RenamedAndDescribed {
     a_field: String,
 }
+
+const NAME_FORMAT: schema::ApiStringFormat =
+    schema::ApiStringFormat::Enum(&[EnumEntry::new("foo", "Foo"), EnumEntry::new("bar", "Bar")]);
+
+#[api(
+    properties: {
+        name: {
+            format: NAME_FORMAT,
+        },
+        id: {
+            format: r"^[a-z][a-z0-9-]*$",
+        },
+    },
+)]
+#[derive(Deserialize)]
+/// Formats via constants and regular expressions.
+pub struct WithFormats {
+    /// The name.
+    name: String,
+
+    /// The id.
+    id: String,
+}
+
+#[test]
+fn string_formats() {
+    let schema = WithFormats::API_SCHEMA.unwrap_object_schema();
+
+    let (_, name) = schema.lookup("name").unwrap();
+    assert_eq!(name.unwrap_string_schema().format, Some(&NAME_FORMAT));
+
+    let (_, id) = schema.lookup("id").unwrap();
+    match id.unwrap_string_schema().format {
+        Some(schema::ApiStringFormat::Pattern(pattern)) => {
+            assert_eq!(pattern.regex_string, r"^[a-z][a-z0-9-]*$");
+        }
+        _ => panic!("expected a pattern format"),
+    }
+
+    assert!(id.parse_simple_value("vm-100").is_ok());
+    assert!(id.parse_simple_value("100").is_err());
+}
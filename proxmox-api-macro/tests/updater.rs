@@ -155,3 +155,130 @@ pub struct WithSerde {
     #[updater(serde(skip_serializing_if = "Option::is_none"))]
     more: MyType,
 }
+
+#[api]
+/// Innermost flattened struct.
+#[derive(Deserialize, Serialize, Updater)]
+pub struct Inner {
+    /// Inner value.
+    inner_value: String,
+}
+
+#[api]
+/// Flattened struct containing another flattened struct.
+#[derive(Deserialize, Serialize, Updater)]
+pub struct Middle {
+    /// Middle value.
+    middle_value: String,
+
+    #[serde(flatten)]
+    inner: Inner,
+}
+
+#[api]
+/// Struct with nested flattened structs.
+#[derive(Deserialize, Serialize, Updater)]
+pub struct Outer {
+    /// Outer value.
+    outer_value: String,
+
+    #[serde(flatten)]
+    middle: Middle,
+}
+
+#[test]
+fn test_nested_flatten() {
+    let updater: OuterUpdater = serde_json::from_value(serde_json::json!({
+        "inner_value": "a",
+        "outer_value": "c",
+    }))
+    .unwrap();
+    assert_eq!(updater.middle.inner.inner_value.as_deref(), Some("a"));
+    assert_eq!(updater.outer_value.as_deref(), Some("c"));
+    assert_eq!(updater.middle.middle_value, None);
+    assert!(!updater.is_empty());
+    assert!(OuterUpdater::default().is_empty());
+
+    OuterUpdater::API_SCHEMA
+        .verify_json(&serde_json::json!({ "inner_value": "a" }))
+        .unwrap();
+    assert!(OuterUpdater::API_SCHEMA
+        .verify_json(&serde_json::json!({ "unknown": "a" }))
+        .is_err());
+}
+
+#[api(
+    properties: {
+        targets: {
+            type: Array,
+            items: { type: String, description: "A target." },
+        },
+    },
+)]
+/// A struct with a list field.
+#[derive(Deserialize, Serialize, Updater)]
+#[serde(rename_all = "kebab-case")]
+pub struct WithList {
+    /// The targets.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[updater(list, serde(skip_serializing_if = "Option::is_none"))]
+    targets: Vec<String>,
+}
+
+#[test]
+fn test_list() {
+    const TARGET_SCHEMA: ::proxmox_schema::Schema =
+        ::proxmox_schema::StringSchema::new("A target.").schema();
+
+    pub const TEST_SCHEMA: ::proxmox_schema::Schema = ::proxmox_schema::ObjectSchema::new(
+        "A struct with a list field.",
+        &[
+            (
+                "add-targets",
+                true,
+                &::proxmox_schema::ArraySchema::new("Entries to add to 'targets'.", &TARGET_SCHEMA)
+                    .schema(),
+            ),
+            (
+                "remove-targets",
+                true,
+                &::proxmox_schema::ArraySchema::new(
+                    "Entries to remove from 'targets'.",
+                    &TARGET_SCHEMA,
+                )
+                .schema(),
+            ),
+            (
+                "targets",
+                true,
+                &::proxmox_schema::ArraySchema::new("The targets.", &TARGET_SCHEMA).schema(),
+            ),
+        ],
+    )
+    .schema();
+
+    assert_eq!(TEST_SCHEMA, WithListUpdater::API_SCHEMA);
+
+    let updater: WithListUpdater = serde_json::from_value(serde_json::json!({
+        "add-targets": ["c", "a"],
+        "remove-targets": ["b"],
+    }))
+    .unwrap();
+    assert!(!updater.is_empty());
+    assert!(WithListUpdater::default().is_empty());
+    assert_eq!(
+        serde_json::to_value(&updater).unwrap(),
+        serde_json::json!({ "add-targets": ["c", "a"], "remove-targets": ["b"] }),
+    );
+
+    let mut config = WithList {
+        targets: vec!["a".to_string(), "b".to_string()],
+    };
+    proxmox_schema::update_list(
+        &mut config.targets,
+        updater.targets,
+        updater.add_targets,
+        &updater.remove_targets,
+    );
+    assert_eq!(config.targets, ["a", "c"]);
+}
@@ -0,0 +1,414 @@
+//! Retrieval of package changelogs from Debian and Proxmox repositories.
+//!
+//! The changelog URL is derived from the package index entry and the `(In)Release` file of the
+//! repository providing the version. Credentials for authenticated repositories, like the
+//! Proxmox enterprise repositories, are taken from APT's `auth.conf` files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_http::HttpClient;
+
+use crate::packages::{APTPackageCache, APTPackageEntry, APTPackageIndex};
+use crate::preferences::preferences;
+use crate::repositories::repositories;
+
+/// Directory where downloaded changelogs are cached.
+pub const CHANGELOG_CACHE_DIR: &str = "/var/cache/proxmox-apt/changelogs";
+
+/// APT's main authentication configuration file.
+const APT_AUTH_CONF_FILENAME: &str = "/etc/apt/auth.conf";
+/// APT's authentication configuration directory.
+const APT_AUTH_CONF_DIRECTORY: &str = "/etc/apt/auth.conf.d/";
+
+/// Returns the changelog URL for the package index `entry` originating from `index`.
+///
+/// If the `(In)Release` file of the repository provides a `Changelogs` field, like Debian's
+/// does, it is used. Otherwise, for repositories with origin `Proxmox`, the changelog is
+/// expected next to the package file.
+pub fn changelog_url(entry: &APTPackageEntry, index: &APTPackageIndex) -> Result<String, Error> {
+    let filename = entry
+        .filename
+        .as_deref()
+        .ok_or_else(|| format_err!("package entry for '{}' has no filename", entry.package))?;
+
+    let base = match filename.rsplit_once('/') {
+        Some((base, _)) => base,
+        None => bail!("unexpected filename '{filename}' for '{}'", entry.package),
+    };
+
+    let release = index.release.as_ref();
+
+    if let Some(changelogs) = release.and_then(|release| release.changelogs.as_deref()) {
+        if changelogs == "no" {
+            bail!("repository '{}' does not provide changelogs", index.uri);
+        }
+
+        let (source, source_version) = source_package(entry);
+        let path = base.strip_prefix("pool/").unwrap_or(base);
+        let change_path = format!("{path}/{source}_{}", strip_epoch(source_version));
+
+        return Ok(changelogs.replace("@CHANGEPATH@", &change_path));
+    }
+
+    match release.and_then(|release| release.origin.as_deref()) {
+        Some("Proxmox") => Ok(format!(
+            "{}/{base}/{}_{}.changelog",
+            index.uri.trim_end_matches('/'),
+            entry.package,
+            strip_epoch(&entry.version),
+        )),
+        Some(origin) => bail!("unable to determine changelog URL for origin '{origin}'"),
+        None => bail!("unable to determine changelog URL - unknown origin"),
+    }
+}
+
+/// Returns the source package name and version, which default to the binary package's.
+fn source_package(entry: &APTPackageEntry) -> (&str, &str) {
+    let source = match entry.source.as_deref() {
+        Some(source) => source.trim(),
+        None => return (&entry.package, &entry.version),
+    };
+
+    match source.split_once(' ') {
+        Some((name, version)) => (name, version.trim().trim_matches(|c| c == '(' || c == ')')),
+        None => (source, &entry.version),
+    }
+}
+
+fn strip_epoch(version: &str) -> &str {
+    match version.split_once(':') {
+        Some((_, version)) => version,
+        None => version,
+    }
+}
+
+/// Returns the `Authorization` header value for `uri`, if APT's `auth.conf` files contain
+/// credentials for it.
+pub fn auth_header(uri: &str) -> Result<Option<String>, Error> {
+    let mut paths = vec![PathBuf::from(APT_AUTH_CONF_FILENAME)];
+
+    match std::fs::read_dir(APT_AUTH_CONF_DIRECTORY) {
+        Ok(read_dir) => {
+            let mut conf_paths = vec![];
+            for entry in read_dir {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) == Some("conf") {
+                    conf_paths.push(path);
+                }
+            }
+            conf_paths.sort();
+            paths.append(&mut conf_paths);
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => bail!("unable to read {APT_AUTH_CONF_DIRECTORY:?} - {err}"),
+    }
+
+    for path in paths {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => bail!("unable to read {path:?} - {err}"),
+        };
+
+        if let Some((login, password)) = find_credentials(&content, uri) {
+            let auth = openssl::base64::encode_block(format!("{login}:{password}").as_bytes());
+            return Ok(Some(format!("Basic {auth}")));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Checks whether `machine` (without scheme) is a prefix of `rest` ending at a host, port or
+/// path component boundary.
+fn machine_matches(machine: &str, rest: &str) -> bool {
+    let remaining = match rest.strip_prefix(machine) {
+        Some(remaining) => remaining,
+        None => return false,
+    };
+
+    machine.ends_with('/')
+        || remaining.is_empty()
+        || remaining.starts_with('/')
+        || (remaining.starts_with(':') && !machine.contains('/'))
+}
+
+/// Finds the login and password for `uri` in the netrc-like `content` of an `auth.conf` file.
+///
+/// Like APT, a `machine` entry matches if it is a prefix of the URI without scheme, ending at a
+/// host, port or path component boundary. If the `machine` includes a scheme, it has to match
+/// too. Entries without scheme are only used for `https` URIs, so that credentials are never
+/// sent in cleartext by accident.
+fn find_credentials(content: &str, uri: &str) -> Option<(String, String)> {
+    let (scheme, rest) = uri.split_once("://").unwrap_or(("", uri));
+
+    let matches = |machine: &str| match machine.split_once("://") {
+        Some((machine_scheme, machine)) => {
+            machine_scheme == scheme && machine_matches(machine, rest)
+        }
+        None => scheme == "https" && machine_matches(machine, rest),
+    };
+
+    let tokens: Vec<&str> = content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split_ascii_whitespace())
+        .collect();
+
+    let mut current: Option<(bool, Option<&str>, Option<&str>)> = None;
+
+    let finish = |entry: Option<(bool, Option<&str>, Option<&str>)>| match entry {
+        Some((true, Some(login), Some(password))) => {
+            Some((login.to_string(), password.to_string()))
+        }
+        _ => None,
+    };
+
+    for pair in tokens.chunks(2) {
+        let (key, value) = match pair {
+            [key, value] => (*key, *value),
+            _ => break,
+        };
+
+        match key {
+            "machine" => {
+                if let Some(credentials) = finish(current.take()) {
+                    return Some(credentials);
+                }
+                current = Some((matches(value), None, None));
+            }
+            "login" => {
+                if let Some(entry) = current.as_mut() {
+                    entry.1 = Some(value);
+                }
+            }
+            "password" => {
+                if let Some(entry) = current.as_mut() {
+                    entry.2 = Some(value);
+                }
+            }
+            _ => (), // e.g. 'port'
+        }
+    }
+
+    finish(current)
+}
+
+/// Fetches the changelog of `package` for `version`, or the candidate version if `None`.
+///
+/// Uses the configured repositories, pins and the downloaded package indices to find the
+/// repository providing the version. Changelogs are cached in [`CHANGELOG_CACHE_DIR`].
+pub fn fetch<C: HttpClient<String, String>>(
+    client: &C,
+    package: &str,
+    version: Option<&str>,
+) -> Result<String, Error> {
+    let (files, _, _) = repositories()?;
+    let (preference_files, _) = preferences()?;
+    let pins = preference_files
+        .into_iter()
+        .flat_map(|file| file.pins.into_iter())
+        .collect();
+
+    let cache = APTPackageCache::load(&files, pins)?;
+
+    fetch_with_cache(
+        client,
+        &cache,
+        package,
+        version,
+        Some(Path::new(CHANGELOG_CACHE_DIR)),
+    )
+}
+
+/// Checks whether `package` is a valid Debian package name.
+fn valid_package_name(package: &str) -> bool {
+    package.len() >= 2
+        && package.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && package
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c))
+}
+
+/// Checks whether `version` is a valid Debian package version, `[epoch:]upstream[-revision]`.
+fn valid_package_version(version: &str) -> bool {
+    let upstream = match version.split_once(':') {
+        Some((epoch, upstream)) => {
+            if epoch.is_empty() || !epoch.chars().all(|c| c.is_ascii_digit()) {
+                return false;
+            }
+            upstream
+        }
+        None => version,
+    };
+
+    upstream.starts_with(|c: char| c.is_ascii_digit())
+        && upstream
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.~".contains(c))
+}
+
+/// Like [`fetch`], but uses the package `cache` and stores changelogs in `cache_dir`, if set.
+pub fn fetch_with_cache<C: HttpClient<String, String>>(
+    client: &C,
+    cache: &APTPackageCache,
+    package: &str,
+    version: Option<&str>,
+    cache_dir: Option<&Path>,
+) -> Result<String, Error> {
+    let version = match version {
+        Some(version) => version,
+        None => cache
+            .candidate_version(package)
+            .ok_or_else(|| format_err!("no candidate version for package '{package}'"))?,
+    };
+
+    // both end up in the cache path
+    if !valid_package_name(package) {
+        bail!("invalid package name '{package}'");
+    }
+    if !valid_package_version(version) {
+        bail!("invalid version '{version}' for package '{package}'");
+    }
+
+    let cache_path = cache_dir.map(|dir| {
+        dir.join(format!(
+            "{package}_{}.changelog",
+            version.replace(':', "%3a")
+        ))
+    });
+
+    if let Some(path) = &cache_path {
+        match std::fs::read_to_string(path) {
+            Ok(changelog) => return Ok(changelog),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => bail!("unable to read cached changelog {path:?} - {err}"),
+        }
+    }
+
+    let (entry, index) = cache
+        .available_entries(package)
+        .into_iter()
+        .find(|(entry, _)| entry.version == version)
+        .ok_or_else(|| format_err!("no repository provides '{package}' version '{version}'"))?;
+
+    let url = changelog_url(entry, index)?;
+
+    let mut headers = HashMap::new();
+    if let Some(auth) = auth_header(&url)? {
+        headers.insert("Authorization".to_string(), auth);
+    }
+
+    let response = client.get(&url, Some(&headers))?;
+    if !response.status().is_success() {
+        bail!(
+            "unable to fetch changelog from '{url}' - {}",
+            response.status()
+        );
+    }
+    let changelog = response.into_body();
+
+    if let Some(path) = &cache_path {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| format_err!("unable to create dir {dir:?} - {err}"))?;
+        }
+
+        let mut tmp_path = path.clone();
+        tmp_path.set_extension(format!("{}", std::process::id()));
+
+        if let Err(err) = std::fs::write(&tmp_path, &changelog) {
+            let _ = std::fs::remove_file(&tmp_path);
+            bail!("write failed for {tmp_path:?} - {err}");
+        }
+
+        if let Err(err) = std::fs::rename(&tmp_path, path) {
+            let _ = std::fs::remove_file(&tmp_path);
+            bail!("rename failed for {path:?} - {err}");
+        }
+    }
+
+    Ok(changelog)
+}
+
+#[test]
+fn test_find_credentials() {
+    let content = "\
+# comment
+machine enterprise.proxmox.com/debian/pve
+ login pve4s-1234567890
+ password 0123456789ABCDEF0123456789ABCDEF
+
+machine https://example.com login user password secret port 443
+";
+
+    assert_eq!(
+        find_credentials(
+            content,
+            "https://enterprise.proxmox.com/debian/pve/dists/bookworm/pve-enterprise/foo"
+        ),
+        Some((
+            "pve4s-1234567890".to_string(),
+            "0123456789ABCDEF0123456789ABCDEF".to_string()
+        )),
+    );
+    assert_eq!(
+        find_credentials(content, "https://enterprise.proxmox.com/debian/pbs/dists"),
+        None,
+    );
+    assert_eq!(
+        find_credentials(content, "https://example.com/debian"),
+        Some(("user".to_string(), "secret".to_string())),
+    );
+    assert_eq!(find_credentials(content, "http://example.com/debian"), None);
+
+    // only match at host, port or path component boundaries
+    for uri in [
+        "https://enterprise.proxmox.com/debian/pve-evil/dists",
+        "https://enterprise.proxmox.com/debian/pvex",
+        "https://example.com.evil.tld/debian",
+        "https://example.comx/debian",
+    ] {
+        assert_eq!(find_credentials(content, uri), None, "{uri}");
+    }
+
+    let content = "machine enterprise.proxmox.com login user password secret";
+    let credentials = Some(("user".to_string(), "secret".to_string()));
+    assert_eq!(
+        find_credentials(content, "https://enterprise.proxmox.com/debian"),
+        credentials,
+    );
+    assert_eq!(
+        find_credentials(content, "https://enterprise.proxmox.com:443/debian"),
+        credentials,
+    );
+    assert_eq!(
+        find_credentials(content, "https://enterprise.proxmox.com.evil.tld/debian"),
+        None,
+    );
+    // entries without scheme are not used for cleartext http
+    assert_eq!(
+        find_credentials(content, "http://enterprise.proxmox.com/debian"),
+        None,
+    );
+}
+
+#[test]
+fn test_valid_package_name_version() {
+    assert!(valid_package_name("proxmox-ve"));
+    assert!(valid_package_name("libstdc++6"));
+    assert!(!valid_package_name("../../etc/passwd"));
+    assert!(!valid_package_name("Foo"));
+    assert!(!valid_package_name("a"));
+
+    assert!(valid_package_version("8.1.4"));
+    assert!(valid_package_version("1:2.38.1-5+deb12u1"));
+    assert!(valid_package_version("2.0~rc1"));
+    assert!(!valid_package_version("1.0/../../x"));
+    assert!(!valid_package_version("../1.0"));
+    assert!(!valid_package_version("a:1.0"));
+    assert!(!valid_package_version(""));
+}
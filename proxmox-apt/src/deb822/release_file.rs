@@ -276,7 +276,7 @@ impl FileReference {
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 /// A parsed representation of a Release file
 pub struct ReleaseFile {
     /// List of architectures, e.g., `amd64` or `all`.
@@ -1,3 +1,9 @@
+#[cfg(feature = "changelogs")]
+pub mod changelogs;
 pub mod config;
 pub mod deb822;
+pub mod packages;
+pub mod preferences;
 pub mod repositories;
+#[cfg(feature = "update")]
+pub mod update;
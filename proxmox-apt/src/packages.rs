@@ -0,0 +1,606 @@
+//! Queries for installed and available package versions, similar to `apt-cache policy`.
+//!
+//! Available versions are read from the package indices in APT's lists directory that belong to
+//! the configured repositories, installed versions from the dpkg status file.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::api;
+
+use crate::deb822::ReleaseFile;
+use crate::preferences::{glob_match, APTPin, APTPinType};
+use crate::repositories::{
+    host_from_uri, lists_filename, APTRepositoryFile, APTRepositoryPackageType,
+};
+
+/// Path to the dpkg status file.
+pub const DPKG_STATUS_FILENAME: &str = "/var/lib/dpkg/status";
+
+/// Priority of versions without matching pin.
+const DEFAULT_PRIORITY: i64 = 500;
+/// Priority of the installed version.
+const INSTALLED_PRIORITY: i64 = 100;
+/// Minimum priority to allow downgrading the installed version.
+const DOWNGRADE_PRIORITY: i64 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A package stanza from a package index or the dpkg status file.
+pub struct APTPackageEntry {
+    /// The name of the package.
+    pub package: String,
+    /// The version of the package.
+    pub version: String,
+    /// The architecture of the package, e.g. `amd64` or `all`.
+    pub architecture: String,
+    /// The source package, if it differs from the package name.
+    pub source: Option<String>,
+    /// The `Status` field, only present in the dpkg status file.
+    pub status: Option<String>,
+    /// Path of the package file relative to the repository URI, only present in package indices.
+    pub filename: Option<String>,
+}
+
+impl APTPackageEntry {
+    /// Checks if the `Status` field marks the package as installed.
+    pub fn is_installed(&self) -> bool {
+        matches!(
+            self.status
+                .as_deref()
+                .and_then(|status| status.split_ascii_whitespace().nth(2)),
+            Some("installed")
+        )
+    }
+}
+
+/// Parses the package stanzas of a package index or the dpkg status file.
+///
+/// Only the fields needed for version queries are kept, stanzas without package name or
+/// version are skipped.
+pub fn parse_package_entries<R: BufRead>(input: R) -> Result<Vec<APTPackageEntry>, Error> {
+    let mut entries = vec![];
+    let mut fields: HashMap<String, String> = HashMap::new();
+
+    let mut finish_stanza = |fields: &mut HashMap<String, String>| {
+        let package = fields.remove("package");
+        let version = fields.remove("version");
+        if let (Some(package), Some(version)) = (package, version) {
+            entries.push(APTPackageEntry {
+                package,
+                version,
+                architecture: fields.remove("architecture").unwrap_or_default(),
+                source: fields.remove("source"),
+                status: fields.remove("status"),
+                filename: fields.remove("filename"),
+            });
+        }
+        fields.clear();
+    };
+
+    for line in input.lines() {
+        let line = line.map_err(|err| format_err!("input error - {err}"))?;
+
+        if line.trim().is_empty() {
+            finish_stanza(&mut fields);
+            continue;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            continue; // folded value, none of the fields we're interested in
+        }
+
+        if let Some((key, value)) = line.split_once(':') {
+            let key = key.to_lowercase();
+            if matches!(
+                &key[..],
+                "package" | "version" | "architecture" | "source" | "status" | "filename"
+            ) {
+                fields.insert(key, value.trim().to_string());
+            }
+        }
+    }
+
+    finish_stanza(&mut fields);
+
+    Ok(entries)
+}
+
+/// Returns the installed packages from the dpkg status file at `path`.
+pub fn installed_packages<P: AsRef<Path>>(path: P) -> Result<Vec<APTPackageEntry>, Error> {
+    let path = path.as_ref();
+
+    let file =
+        std::fs::File::open(path).map_err(|err| format_err!("unable to open {path:?} - {err}"))?;
+
+    let mut entries = parse_package_entries(BufReader::new(file))
+        .map_err(|err| format_err!("unable to parse {path:?} - {err}"))?;
+    entries.retain(|entry| entry.is_installed());
+
+    Ok(entries)
+}
+
+#[derive(Debug, Clone)]
+/// A downloaded package index of a configured repository.
+pub struct APTPackageIndex {
+    /// The URI of the repository.
+    pub uri: String,
+    /// The suite of the repository.
+    pub suite: String,
+    /// The component of the repository, empty for absolute suites.
+    pub component: String,
+    /// Path to the index file.
+    pub path: PathBuf,
+    /// The `(In)Release` file of the suite, if present. Does not include file references.
+    pub release: Option<ReleaseFile>,
+}
+
+/// Returns the downloaded package indices of all enabled repositories in `files`.
+///
+/// Repositories for which `apt update` did not download an index yet are skipped.
+pub fn package_indices(files: &[APTRepositoryFile]) -> Result<Vec<APTPackageIndex>, Error> {
+    let mut indices = vec![];
+
+    for repo in files.iter().flat_map(|file| file.repositories.iter()) {
+        if !repo.enabled || !repo.types.contains(&APTRepositoryPackageType::Deb) {
+            continue;
+        }
+
+        for uri in repo.uris.iter() {
+            for suite in repo.suites.iter() {
+                let release = read_release(&lists_filename(uri, suite, "InRelease"))
+                    .or_else(|| read_release(&lists_filename(uri, suite, "Release")));
+
+                let components = match repo.components.is_empty() {
+                    true => vec![String::new()],
+                    false => repo.components.clone(),
+                };
+
+                for component in components {
+                    let prefix = match component.is_empty() {
+                        true => lists_filename(uri, suite, ""),
+                        false => lists_filename(uri, suite, &format!("{component}_binary-")),
+                    };
+
+                    for path in find_indices(&prefix)? {
+                        indices.push(APTPackageIndex {
+                            uri: uri.clone(),
+                            suite: suite.clone(),
+                            component: component.clone(),
+                            path,
+                            release: release.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(indices)
+}
+
+/// Finds the `Packages` files in the lists directory starting with the file name of `prefix`.
+fn find_indices(prefix: &Path) -> Result<Vec<PathBuf>, Error> {
+    let (dir, prefix) = match (prefix.parent(), prefix.file_name().and_then(|n| n.to_str())) {
+        (Some(dir), Some(prefix)) => (dir, prefix),
+        _ => return Ok(vec![]),
+    };
+
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(format_err!("unable to read {dir:?} - {err}")),
+    };
+
+    let mut indices = vec![];
+
+    for entry in read_dir {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => continue,
+        };
+
+        if let Some(rest) = name.strip_prefix(prefix) {
+            // for absolute suites, the prefix is directly followed by 'Packages'
+            if rest == "Packages"
+                || (rest.ends_with("_Packages") && !rest[..rest.len() - 9].contains('_'))
+            {
+                indices.push(entry.path());
+            }
+        }
+    }
+
+    indices.sort();
+
+    Ok(indices)
+}
+
+/// Reads the header fields of a cached `(In)Release` file, ignoring file references.
+fn read_release(path: &Path) -> Option<ReleaseFile> {
+    let raw = std::fs::read_to_string(path).ok()?;
+
+    let mut fields = HashMap::new();
+    let mut lines = raw.lines().peekable();
+
+    if lines
+        .peek()?
+        .starts_with("-----BEGIN PGP SIGNED MESSAGE-----")
+    {
+        // skip the armor headers
+        for line in lines.by_ref() {
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+    }
+
+    for line in lines {
+        if line.trim().is_empty() || line.starts_with("-----BEGIN PGP SIGNATURE-----") {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            if !line.starts_with([' ', '\t']) {
+                fields.insert(key.to_string(), value.trim().to_string());
+            }
+        }
+    }
+
+    let split = |value: Option<&String>| -> Vec<String> {
+        value
+            .map(|value| value.split_ascii_whitespace().map(String::from).collect())
+            .unwrap_or_default()
+    };
+
+    Some(ReleaseFile {
+        architectures: split(fields.get("Architectures")),
+        changelogs: fields.get("Changelogs").cloned(),
+        codename: fields.get("Codename").cloned(),
+        components: split(fields.get("Components")),
+        date: None,
+        valid_until: None,
+        description: fields.get("Description").cloned(),
+        label: fields.get("Label").cloned(),
+        origin: fields.get("Origin").cloned(),
+        suite: fields.get("Suite").cloned(),
+        version: fields.get("Version").cloned(),
+        aquire_by_hash: fields.get("Acquire-By-Hash").map(String::as_str) == Some("yes"),
+        files: HashMap::new(),
+    })
+}
+
+#[api]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Installed and candidate version of a package, similar to `apt-cache policy`.
+pub struct APTPackagePolicy {
+    /// The name of the package.
+    pub package: String,
+
+    /// The installed version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed: Option<String>,
+
+    /// The version that would be installed on upgrade.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate: Option<String>,
+
+    /// The priority of the candidate version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i64>,
+
+    /// Origin of the candidate version, as stated in the `(In)Release` file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+
+    /// URI of the repository providing the candidate version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+
+    /// Suite of the repository providing the candidate version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suite: Option<String>,
+
+    /// Component of the repository providing the candidate version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component: Option<String>,
+}
+
+/// Installed and available package versions, including the pins to determine candidates.
+///
+/// Packages are identified by name only, i.e. foreign architectures are not distinguished.
+pub struct APTPackageCache {
+    indices: Vec<APTPackageIndex>,
+    installed: HashMap<String, String>,
+    /// Package name to entries and index into `indices`.
+    available: HashMap<String, Vec<(APTPackageEntry, usize)>>,
+    pins: Vec<APTPin>,
+}
+
+impl APTPackageCache {
+    /// Loads the package indices of the configured repositories in `files` and the installed
+    /// versions from the default dpkg status file.
+    ///
+    /// `pins` are considered in order, i.e. the first matching pin determines the priority.
+    pub fn load(files: &[APTRepositoryFile], pins: Vec<APTPin>) -> Result<Self, Error> {
+        Self::load_with_status(files, pins, DPKG_STATUS_FILENAME)
+    }
+
+    /// Like [`load`](Self::load), but reads the installed versions from `status_path`.
+    pub fn load_with_status<P: AsRef<Path>>(
+        files: &[APTRepositoryFile],
+        pins: Vec<APTPin>,
+        status_path: P,
+    ) -> Result<Self, Error> {
+        let installed = installed_packages(status_path)?
+            .into_iter()
+            .map(|entry| (entry.package, entry.version))
+            .collect();
+
+        let indices = package_indices(files)?;
+        let mut available: HashMap<String, Vec<(APTPackageEntry, usize)>> = HashMap::new();
+
+        for (n, index) in indices.iter().enumerate() {
+            let file = std::fs::File::open(&index.path)
+                .map_err(|err| format_err!("unable to open {:?} - {err}", index.path))?;
+            let entries = parse_package_entries(BufReader::new(file))
+                .map_err(|err| format_err!("unable to parse {:?} - {err}", index.path))?;
+
+            for entry in entries {
+                available
+                    .entry(entry.package.clone())
+                    .or_default()
+                    .push((entry, n));
+            }
+        }
+
+        Ok(Self {
+            indices,
+            installed,
+            available,
+            pins,
+        })
+    }
+
+    /// Returns the installed version of the package.
+    pub fn installed_version(&self, package: &str) -> Option<&str> {
+        self.installed.get(package).map(String::as_str)
+    }
+
+    /// Returns all available versions of the package and the index they originate from.
+    pub fn available_versions(&self, package: &str) -> Vec<(&str, &APTPackageIndex)> {
+        self.available_entries(package)
+            .into_iter()
+            .map(|(entry, index)| (entry.version.as_str(), index))
+            .collect()
+    }
+
+    /// Returns the package index entries of all available versions of the package and the index
+    /// they originate from.
+    pub fn available_entries(&self, package: &str) -> Vec<(&APTPackageEntry, &APTPackageIndex)> {
+        self.available
+            .get(package)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|(entry, n)| (entry, &self.indices[*n]))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the version that would be installed on upgrade.
+    pub fn candidate_version(&self, package: &str) -> Option<&str> {
+        self.candidate(package).map(|(version, _, _)| version)
+    }
+
+    /// Returns the installed and candidate version of the package, as well as the origin of the
+    /// candidate, or `None` if the package is neither installed nor available.
+    pub fn policy(&self, package: &str) -> Option<APTPackagePolicy> {
+        let installed = self.installed_version(package).map(String::from);
+
+        if installed.is_none() && !self.available.contains_key(package) {
+            return None;
+        }
+
+        let mut policy = APTPackagePolicy {
+            package: package.to_string(),
+            installed,
+            candidate: None,
+            priority: None,
+            origin: None,
+            uri: None,
+            suite: None,
+            component: None,
+        };
+
+        if let Some((version, priority, index)) = self.candidate(package) {
+            policy.candidate = Some(version.to_string());
+            policy.priority = Some(priority);
+            if let Some(index) = index {
+                policy.origin = index
+                    .release
+                    .as_ref()
+                    .and_then(|release| release.origin.clone());
+                policy.uri = Some(index.uri.clone());
+                policy.suite = Some(index.suite.clone());
+                policy.component = Some(index.component.clone());
+            }
+        }
+
+        Some(policy)
+    }
+
+    /// Determines the candidate version, its priority and the index it originates from.
+    ///
+    /// The version with the highest priority wins, the higher version on equal priority. Versions
+    /// with a priority lower than 0 are never selected, and the installed version is only
+    /// downgraded with a priority of at least 1000.
+    fn candidate(&self, package: &str) -> Option<(&str, i64, Option<&APTPackageIndex>)> {
+        let installed = self.installed_version(package);
+
+        let mut versions: Vec<(&str, i64, Option<&APTPackageIndex>)> = self
+            .available_versions(package)
+            .into_iter()
+            .map(|(version, index)| {
+                let mut priority = self.priority(package, version, index);
+                if installed == Some(version) {
+                    priority = priority.max(INSTALLED_PRIORITY);
+                }
+                (version, priority, Some(index))
+            })
+            .collect();
+
+        if let Some(installed) = installed {
+            if !versions.iter().any(|(version, _, _)| *version == installed) {
+                versions.push((installed, INSTALLED_PRIORITY, None));
+            }
+        }
+
+        versions
+            .into_iter()
+            .filter(|(_, priority, _)| *priority >= 0)
+            .filter(|(version, priority, _)| match installed {
+                Some(installed) => {
+                    *priority >= DOWNGRADE_PRIORITY
+                        || compare_versions(version, installed) != Ordering::Less
+                }
+                None => true,
+            })
+            .max_by(|(a_version, a_priority, _), (b_version, b_priority, _)| {
+                a_priority
+                    .cmp(b_priority)
+                    .then_with(|| compare_versions(a_version, b_version))
+            })
+    }
+
+    /// Returns the priority of the first pin matching the version, or the default priority.
+    fn priority(&self, package: &str, version: &str, index: &APTPackageIndex) -> i64 {
+        self.pins
+            .iter()
+            .filter(|pin| pin.matches_package(package))
+            .find(|pin| match pin.pin_type {
+                APTPinType::Release => match &index.release {
+                    Some(release) => pin.matches_release(release),
+                    None => false,
+                },
+                APTPinType::Origin => host_from_uri(&index.uri) == Some(pin.pin.trim_matches('"')),
+                APTPinType::Version => glob_match(pin.pin.trim(), version),
+            })
+            .map(|pin| pin.priority)
+            .unwrap_or(DEFAULT_PRIORITY)
+    }
+}
+
+/// Compares two Debian package versions, see `man deb-version`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_upstream, a_revision) = split_version(a);
+    let (b_epoch, b_upstream, b_revision) = split_version(b);
+
+    a_epoch
+        .cmp(&b_epoch)
+        .then_with(|| compare_version_part(a_upstream, b_upstream))
+        .then_with(|| compare_version_part(a_revision, b_revision))
+}
+
+/// Splits a version into epoch, upstream version and Debian revision.
+fn split_version(version: &str) -> (u64, &str, &str) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) => match epoch.parse::<u64>() {
+            Ok(epoch) => (epoch, rest),
+            Err(_) => (0, version),
+        },
+        None => (0, version),
+    };
+
+    match rest.rsplit_once('-') {
+        Some((upstream, revision)) => (epoch, upstream, revision),
+        None => (epoch, rest, ""),
+    }
+}
+
+/// Sort weight of a non-digit character, `~` sorts before everything, even the end of a part.
+fn char_order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => c as i32,
+        Some(c) => c as i32 + 256,
+    }
+}
+
+/// Compares an upstream version or revision, based on dpkg's `verrevcmp`.
+fn compare_version_part(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let a_order = char_order(a.get(i).copied());
+            let b_order = char_order(b.get(j).copied());
+            if a_order != b_order {
+                return a_order.cmp(&b_order);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        while i < a.len() && a[i] == b'0' {
+            i += 1;
+        }
+        while j < b.len() && b[j] == b'0' {
+            j += 1;
+        }
+
+        let mut first_diff = Ordering::Equal;
+        while i < a.len() && a[i].is_ascii_digit() && j < b.len() && b[j].is_ascii_digit() {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        if i < a.len() && a[i].is_ascii_digit() {
+            return Ordering::Greater;
+        }
+        if j < b.len() && b[j].is_ascii_digit() {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+
+    Ordering::Equal
+}
+
+#[test]
+fn test_compare_versions() {
+    let cases = [
+        ("1.0", "1.0", Ordering::Equal),
+        ("1.0", "1.1", Ordering::Less),
+        ("1.10", "1.9", Ordering::Greater),
+        ("1.0~rc1", "1.0", Ordering::Less),
+        ("1.0", "1.0+b1", Ordering::Less),
+        ("1:1.0", "2.0", Ordering::Greater),
+        ("8.0.4", "8.0-1", Ordering::Greater),
+        ("7.0-10", "7.0-2", Ordering::Greater),
+        ("2.0.3-pve1", "2.0.3-9", Ordering::Greater),
+        ("1.0-1", "1.0-1~bpo11+1", Ordering::Greater),
+        ("0001.0", "1.0", Ordering::Equal),
+        ("1.0a", "1.0", Ordering::Greater),
+        ("1.0a", "1.0.", Ordering::Less),
+    ];
+
+    for (a, b, expected) in cases {
+        assert_eq!(compare_versions(a, b), expected, "{a} vs {b}");
+        assert_eq!(compare_versions(b, a), expected.reverse(), "{b} vs {a}");
+    }
+}
@@ -0,0 +1,667 @@
+//! APT preferences (pinning) as configured in `/etc/apt/preferences` and
+//! `/etc/apt/preferences.d/`.
+//!
+//! See `man apt_preferences` for the format specification.
+
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::api;
+
+use crate::deb822::ReleaseFile;
+use crate::repositories::APTRepositoryFileError;
+
+const APT_PREFERENCES_FILENAME: &str = "/etc/apt/preferences";
+const APT_PREFERENCES_DIRECTORY: &str = "/etc/apt/preferences.d/";
+
+#[api]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// How a pin selects the package versions it applies to.
+pub enum APTPinType {
+    /// By properties of the `(In)Release` file, e.g. `release n=bookworm, o=Proxmox`.
+    Release,
+    /// By version, e.g. `version 6.8*`.
+    Version,
+    /// By the host name of the repository, e.g. `origin "download.proxmox.com"`.
+    Origin,
+}
+
+impl TryFrom<&str> for APTPinType {
+    type Error = Error;
+
+    fn try_from(pin_type: &str) -> Result<Self, Error> {
+        match pin_type {
+            "release" => Ok(APTPinType::Release),
+            "version" => Ok(APTPinType::Version),
+            "origin" => Ok(APTPinType::Origin),
+            _ => bail!("invalid pin type '{pin_type}'"),
+        }
+    }
+}
+
+impl Display for APTPinType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            APTPinType::Release => write!(f, "release"),
+            APTPinType::Version => write!(f, "version"),
+            APTPinType::Origin => write!(f, "origin"),
+        }
+    }
+}
+
+#[api(
+    properties: {
+        packages: {
+            description: "List of package names, globs or regular expressions.",
+            type: Array,
+            items: {
+                description: "Package name, glob or regular expression.",
+                type: String,
+            },
+        },
+        "pin-type": {
+            type: APTPinType,
+        },
+        explanation: {
+            description: "Content of the 'Explanation' fields.",
+            type: Array,
+            optional: true,
+            items: {
+                description: "Explanation line.",
+                type: String,
+            },
+        },
+        comment: {
+            description: "Associated comment.",
+            type: String,
+            optional: true,
+        },
+    },
+)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// A single pin, i.e. one stanza of an APT preferences file.
+pub struct APTPin {
+    /// List of package names, globs or regular expressions, `*` matches all packages.
+    pub packages: Vec<String>,
+
+    /// How the pin selects package versions.
+    pub pin_type: APTPinType,
+
+    /// The value of the pin without the type, e.g. `n=bookworm, o=Proxmox`.
+    pub pin: String,
+
+    /// The priority assigned to the selected package versions.
+    pub priority: i64,
+
+    /// Content of the `Explanation` fields.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub explanation: Vec<String>,
+
+    /// Associated comment.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub comment: String,
+}
+
+impl APTPin {
+    /// Creates a new pin without explanation and comment.
+    pub fn new(packages: Vec<String>, pin_type: APTPinType, pin: String, priority: i64) -> Self {
+        Self {
+            packages,
+            pin_type,
+            pin,
+            priority,
+            explanation: vec![],
+            comment: String::new(),
+        }
+    }
+
+    /// Creates a new release pin from a list of properties, e.g. `[("n", "bookworm")]`.
+    pub fn with_release_properties(
+        packages: Vec<String>,
+        properties: &[(&str, &str)],
+        priority: i64,
+    ) -> Self {
+        let pin = properties
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        Self::new(packages, APTPinType::Release, pin, priority)
+    }
+
+    /// Returns the properties of a release pin as key-value pairs.
+    ///
+    /// A value without key (e.g. `release 12.5`) is returned with the `v` (version) key.
+    pub fn release_properties(&self) -> Result<Vec<(String, String)>, Error> {
+        if self.pin_type != APTPinType::Release {
+            bail!("not a release pin");
+        }
+
+        let mut properties = vec![];
+
+        for property in self.pin.split(',') {
+            let property = property.trim_matches(|c| char::is_ascii_whitespace(&c));
+            if property.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match property.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => ("v", property),
+            };
+
+            if !matches!(key, "a" | "n" | "v" | "o" | "l" | "c" | "b") {
+                bail!("invalid release property '{key}'");
+            }
+
+            properties.push((key.to_string(), value.trim_matches('"').to_string()));
+        }
+
+        Ok(properties)
+    }
+
+    /// Checks if the release pin selects the repository described by the `(In)Release` file.
+    ///
+    /// Values may contain `*` and `?` wildcards. Regular expressions are not supported and never
+    /// match. Always returns `false` for non-release pins.
+    pub fn matches_release(&self, release: &ReleaseFile) -> bool {
+        let properties = match self.release_properties() {
+            Ok(properties) => properties,
+            Err(_) => return false,
+        };
+
+        let matches_option = |value: &str, field: &Option<String>| match field {
+            Some(field) => glob_match(value, field),
+            None => false,
+        };
+        let matches_list =
+            |value: &str, list: &[String]| list.iter().any(|item| glob_match(value, item));
+
+        properties.iter().all(|(key, value)| match key.as_str() {
+            "a" => matches_option(value, &release.suite),
+            "n" => matches_option(value, &release.codename),
+            "v" => matches_option(value, &release.version),
+            "o" => matches_option(value, &release.origin),
+            "l" => matches_option(value, &release.label),
+            "c" => matches_list(value, &release.components),
+            "b" => matches_list(value, &release.architectures),
+            _ => false,
+        })
+    }
+
+    /// Checks if the pin applies to the `package`.
+    pub fn matches_package(&self, package: &str) -> bool {
+        self.packages
+            .iter()
+            .any(|pattern| glob_match(pattern, package))
+    }
+
+    /// Makes sure that all properties of a pin are present and not obviously invalid.
+    pub fn basic_check(&self) -> Result<(), Error> {
+        if self.packages.is_empty() {
+            bail!("missing package(s)");
+        }
+        if self.pin.trim().is_empty() {
+            bail!("missing pin");
+        }
+        if self.pin_type == APTPinType::Release {
+            self.release_properties()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the pin as a stanza followed by a blank line.
+    ///
+    /// Expects that `basic_check()` for the pin was successful.
+    pub fn write(&self, w: &mut dyn Write) -> Result<(), Error> {
+        for line in self.comment.lines() {
+            writeln!(w, "#{line}")?;
+        }
+
+        for line in self.explanation.iter() {
+            writeln!(w, "Explanation: {line}")?;
+        }
+
+        writeln!(w, "Package: {}", self.packages.join(" "))?;
+        writeln!(w, "Pin: {} {}", self.pin_type, self.pin)?;
+        writeln!(w, "Pin-Priority: {}", self.priority)?;
+
+        writeln!(w)?;
+
+        Ok(())
+    }
+}
+
+/// Simple glob matching supporting `*` and `?`. Values enclosed in `/` are regular expressions
+/// for APT, which are not supported.
+pub(crate) fn glob_match(pattern: &str, value: &str) -> bool {
+    if pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/') {
+        return false;
+    }
+
+    let pattern = pattern.as_bytes();
+    let value = value.as_bytes();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+#[api(
+    properties: {
+        pins: {
+            description: "List of APT pins.",
+            type: Array,
+            items: {
+                type: APTPin,
+            },
+        },
+        "trailing-comment": {
+            description: "Comment after the last pin.",
+            type: String,
+            optional: true,
+        },
+        digest: {
+            description: "Digest for the content of the file.",
+            optional: true,
+            type: Array,
+            items: {
+                description: "Digest byte.",
+                type: u8,
+            },
+        },
+    },
+)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Represents an APT preferences file.
+pub struct APTPreferencesFile {
+    /// The path to the file. If None, `contents` must be set directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    /// List of pins in the file.
+    pub pins: Vec<APTPin>,
+
+    /// Comment after the last pin, e.g. fully commented out stanzas at the end of the file.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub trailing_comment: String,
+
+    /// The file content, if already parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+
+    /// Digest of the original contents.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<[u8; 32]>,
+}
+
+impl APTPreferencesFile {
+    /// Creates a new `APTPreferencesFile` without parsing.
+    ///
+    /// If the file is hidden, the path points to a directory, or the file name is ignored by APT
+    /// (i.e. it has an extension other than `.pref`), `Ok(None)` is returned, while invalid file
+    /// names yield an error.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Option<Self>, APTRepositoryFileError> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+
+        let path_string =
+            path.to_str()
+                .map(String::from)
+                .ok_or_else(|| APTRepositoryFileError {
+                    path: path.to_string_lossy().to_string(),
+                    error: "path is not valid unicode".to_string(),
+                })?;
+
+        let new_err = |err: &str| APTRepositoryFileError {
+            path: path_string.clone(),
+            error: err.to_string(),
+        };
+
+        if path.is_dir() {
+            return Ok(None);
+        }
+
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name,
+            None => return Err(new_err("invalid path")),
+        };
+
+        if file_name.starts_with('.') || file_name.ends_with('~') {
+            return Ok(None);
+        }
+
+        // See APT's apt-pkg/policy.cc, files without extension are accepted too
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("pref") | None => (),
+            Some(_) => return Ok(None),
+        }
+
+        if !file_name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-' || x == '.')
+        {
+            return Err(new_err("invalid characters in file name"));
+        }
+
+        Ok(Some(Self {
+            path: Some(path_string),
+            pins: vec![],
+            trailing_comment: String::new(),
+            content: None,
+            digest: None,
+        }))
+    }
+
+    /// Creates a new `APTPreferencesFile` from `content` instead of a file on disk.
+    ///
+    /// The content can be parsed like a file, but the result cannot be written without setting
+    /// a path first.
+    pub fn with_content(content: String) -> Self {
+        Self {
+            path: None,
+            pins: vec![],
+            trailing_comment: String::new(),
+            content: Some(content),
+            digest: None,
+        }
+    }
+
+    /// Check if the file exists.
+    pub fn exists(&self) -> bool {
+        if let Some(path) = &self.path {
+            PathBuf::from(path).exists()
+        } else {
+            false
+        }
+    }
+
+    /// Reads the raw content and computes its digest.
+    ///
+    /// Reads the file if a path is set, and uses the content set with [Self::with_content]
+    /// otherwise.
+    pub fn read_with_digest(&self) -> Result<(Vec<u8>, [u8; 32]), APTRepositoryFileError> {
+        if let Some(path) = &self.path {
+            let content = std::fs::read(path).map_err(|err| self.err(format_err!("{}", err)))?;
+            let digest = openssl::sha::sha256(&content);
+
+            Ok((content, digest))
+        } else if let Some(ref content) = self.content {
+            let content = content.as_bytes();
+            let digest = openssl::sha::sha256(content);
+            Ok((content.to_vec(), digest))
+        } else {
+            Err(self.err(format_err!(
+                "Neither 'path' nor 'content' set, cannot read APT preferences."
+            )))
+        }
+    }
+
+    /// Create an `APTRepositoryFileError`.
+    pub fn err(&self, error: Error) -> APTRepositoryFileError {
+        APTRepositoryFileError {
+            path: self.path.clone().unwrap_or_default(),
+            error: error.to_string(),
+        }
+    }
+
+    /// Parses the pins configured in the file.
+    ///
+    /// Resets the current pins, trailing comment and digest, even on failure.
+    pub fn parse(&mut self) -> Result<(), APTRepositoryFileError> {
+        self.pins.clear();
+        self.trailing_comment.clear();
+        self.digest = None;
+
+        let (content, digest) = self.read_with_digest()?;
+
+        let (pins, trailing_comment) = parse_pins(&content[..]).map_err(|err| self.err(err))?;
+
+        for (n, pin) in pins.iter().enumerate() {
+            pin.basic_check()
+                .map_err(|err| self.err(format_err!("check for pin {} - {}", n + 1, err)))?;
+        }
+
+        self.pins = pins;
+        self.trailing_comment = trailing_comment;
+        self.digest = Some(digest);
+
+        Ok(())
+    }
+
+    /// Writes the pins and the trailing comment to the file on disk.
+    ///
+    /// If a digest is provided, checks that the current content of the file still
+    /// produces the same one. The file is removed if there are no pins and no trailing comment.
+    pub fn write(&self) -> Result<(), APTRepositoryFileError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => {
+                return Err(self.err(format_err!(
+                    "Cannot write to APT preferences file without path."
+                )));
+            }
+        };
+
+        if let Some(digest) = self.digest {
+            if !self.exists() {
+                return Err(self.err(format_err!("digest specified, but file does not exist")));
+            }
+
+            let (_, current_digest) = self.read_with_digest()?;
+            if digest != current_digest {
+                return Err(self.err(format_err!("digest mismatch")));
+            }
+        }
+
+        if self.pins.is_empty() && self.trailing_comment.is_empty() {
+            return std::fs::remove_file(path)
+                .map_err(|err| self.err(format_err!("unable to remove file - {}", err)));
+        }
+
+        let mut content = vec![];
+
+        for (n, pin) in self.pins.iter().enumerate() {
+            pin.basic_check()
+                .map_err(|err| self.err(format_err!("check for pin {} - {}", n + 1, err)))?;
+
+            pin.write(&mut content)
+                .map_err(|err| self.err(format_err!("writing pin {} - {}", n + 1, err)))?;
+        }
+
+        for line in self.trailing_comment.lines() {
+            writeln!(content, "#{line}")
+                .map_err(|err| self.err(format_err!("writing trailing comment - {}", err)))?;
+        }
+
+        let path = PathBuf::from(&path);
+        let dir = match path.parent() {
+            Some(dir) => dir,
+            None => return Err(self.err(format_err!("invalid path"))),
+        };
+
+        std::fs::create_dir_all(dir)
+            .map_err(|err| self.err(format_err!("unable to create parent dir - {}", err)))?;
+
+        let mut tmp_path = path.clone();
+        tmp_path.set_extension(format!("{}", std::process::id()));
+
+        if let Err(err) = std::fs::write(&tmp_path, content) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(self.err(format_err!("writing {:?} failed - {}", path, err)));
+        }
+
+        if let Err(err) = std::fs::rename(&tmp_path, &path) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(self.err(format_err!("rename failed for {:?} - {}", path, err)));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the pins applying to the `package` in the order APT considers them, i.e. the
+    /// first matching pin of the file wins.
+    pub fn pins_for_package<'a>(&'a self, package: &'a str) -> impl Iterator<Item = &'a APTPin> {
+        self.pins
+            .iter()
+            .filter(move |pin| pin.matches_package(package))
+    }
+}
+
+/// Parses all stanzas of a preferences file.
+///
+/// Comments before a stanza are added to the pin's `comment` property, comments after the last
+/// stanza are returned separately. Fully commented out stanzas are treated as comments.
+fn parse_pins<R: BufRead>(input: R) -> Result<(Vec<APTPin>, String), Error> {
+    let mut pins = vec![];
+    let mut stanza = vec![];
+    let mut comment = String::new();
+
+    for line in input.lines() {
+        let line = line.map_err(|err| format_err!("input error - {err}"))?;
+
+        if let Some(value) = line.strip_prefix([' ', '\t']) {
+            // folded value, continues the previous field
+            match stanza.last_mut() {
+                Some((_, previous)) => *previous = format!("{previous} {}", value.trim()),
+                None => bail!("continuation line without field - '{line}'"),
+            }
+            continue;
+        }
+
+        let line = line.trim_matches(|c| char::is_ascii_whitespace(&c));
+
+        if line.is_empty() {
+            if !stanza.is_empty() {
+                let pin =
+                    parse_stanza(std::mem::take(&mut stanza), &mut comment).map_err(|err| {
+                        format_err!("malformed entry in stanza {} - {err}", pins.len() + 1)
+                    })?;
+                pins.push(pin);
+            }
+            continue;
+        }
+
+        if let Some(commented_out) = line.strip_prefix('#') {
+            comment = format!("{comment}{commented_out}\n");
+            continue;
+        }
+
+        match line.split_once(':') {
+            Some((key, value)) => stanza.push((key.trim().to_string(), value.trim().to_string())),
+            None => bail!("got invalid line - '{line}'"),
+        }
+    }
+
+    if !stanza.is_empty() {
+        let pin = parse_stanza(stanza, &mut comment)
+            .map_err(|err| format_err!("malformed entry in stanza {} - {err}", pins.len() + 1))?;
+        pins.push(pin);
+    }
+
+    Ok((pins, comment))
+}
+
+fn parse_stanza(fields: Vec<(String, String)>, comment: &mut String) -> Result<APTPin, Error> {
+    let mut packages = None;
+    let mut pin = None;
+    let mut priority = None;
+    let mut explanation = vec![];
+
+    for (key, value) in fields {
+        match &key.to_lowercase()[..] {
+            "package" => {
+                packages = Some(value.split_ascii_whitespace().map(String::from).collect());
+            }
+            "pin" => {
+                let (pin_type, pin_value) = value
+                    .split_once(|c| char::is_ascii_whitespace(&c))
+                    .ok_or_else(|| format_err!("invalid pin '{value}'"))?;
+                pin = Some((
+                    APTPinType::try_from(pin_type)?,
+                    pin_value.trim().to_string(),
+                ));
+            }
+            "pin-priority" => {
+                priority = Some(
+                    value
+                        .parse::<i64>()
+                        .map_err(|err| format_err!("invalid priority '{value}' - {err}"))?,
+                );
+            }
+            "explanation" => explanation.push(value),
+            _ => bail!("unknown field '{key}'"),
+        }
+    }
+
+    let (pin_type, pin) = pin.ok_or_else(|| format_err!("missing 'Pin' field"))?;
+
+    Ok(APTPin {
+        packages: packages.ok_or_else(|| format_err!("missing 'Package' field"))?,
+        pin_type,
+        pin,
+        priority: priority.ok_or_else(|| format_err!("missing 'Pin-Priority' field"))?,
+        explanation,
+        comment: std::mem::take(comment),
+    })
+}
+
+/// Returns all APT preferences files configured as `/etc/apt/preferences` and in
+/// `/etc/apt/preferences.d`, as well as a list of errors for files that could not be read.
+pub fn preferences() -> Result<(Vec<APTPreferencesFile>, Vec<APTRepositoryFileError>), Error> {
+    let mut files = vec![];
+    let mut errors = vec![];
+
+    let mut add_file = |path: PathBuf| match APTPreferencesFile::new(path) {
+        Ok(Some(mut file)) => match file.parse() {
+            Ok(()) => files.push(file),
+            Err(err) => errors.push(err),
+        },
+        Ok(None) => (),
+        Err(err) => errors.push(err),
+    };
+
+    let preferences_path = PathBuf::from(APT_PREFERENCES_FILENAME);
+    if preferences_path.is_file() {
+        add_file(preferences_path);
+    }
+
+    let preferences_d_path = PathBuf::from(APT_PREFERENCES_DIRECTORY);
+    if preferences_d_path.is_dir() {
+        for entry in std::fs::read_dir(preferences_d_path)? {
+            add_file(entry?.path());
+        }
+    }
+
+    Ok((files, errors))
+}
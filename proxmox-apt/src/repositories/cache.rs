@@ -0,0 +1,190 @@
+//! In-memory cache for the configured repositories, invalidated via inotify.
+//!
+//! Frontends poll the repository configuration regularly, so re-reading and re-hashing all
+//! files every time is wasteful. The watches are set up before the files are read, so changes
+//! happening while parsing are not missed.
+
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, format_err, Error};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+
+use super::{repositories, Repositories};
+
+/// Directory containing `sources.list` and `sources.list.d`.
+const APT_CONFIG_DIRECTORY: &str = "/etc/apt/";
+
+const SOURCES_LIST: &str = "sources.list";
+const SOURCES_LIST_D: &str = "sources.list.d";
+
+struct Cache {
+    watcher: Watcher,
+    repositories: Arc<Repositories>,
+}
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+/// Inotify instance watching an APT configuration directory and its `sources.list.d`.
+struct Watcher {
+    inotify: Inotify,
+    /// Watch for the APT configuration directory, only events for `sources.list` and
+    /// `sources.list.d` themselves are relevant.
+    config_dir: WatchDescriptor,
+}
+
+impl Watcher {
+    fn new(config_dir: &Path) -> Result<Self, Error> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)
+            .map_err(|err| format_err!("unable to initialize inotify - {err}"))?;
+
+        let mask = AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_MODIFY
+            | AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_ATTRIB
+            | AddWatchFlags::IN_MOVE;
+
+        let config_dir_wd = match inotify.add_watch(config_dir, mask) {
+            Ok(config_dir_wd) => config_dir_wd,
+            Err(err) => {
+                let _ = nix::unistd::close(inotify.as_raw_fd());
+                bail!("unable to watch {config_dir:?} - {err}");
+            }
+        };
+
+        // the file descriptor is closed on drop from now on
+        let watcher = Self {
+            inotify,
+            config_dir: config_dir_wd,
+        };
+
+        let sources_list_d = config_dir.join(SOURCES_LIST_D);
+        match watcher.inotify.add_watch(
+            &sources_list_d,
+            mask | AddWatchFlags::IN_DELETE_SELF | AddWatchFlags::IN_MOVE_SELF,
+        ) {
+            // created later on, which is noticed via the watch for the configuration directory
+            Ok(_) | Err(Errno::ENOENT) => (),
+            Err(err) => bail!("unable to watch {sources_list_d:?} - {err}"),
+        }
+
+        Ok(watcher)
+    }
+
+    /// Drains the pending events and checks if any of them concerns the repository files.
+    fn changed(&self) -> bool {
+        let mut changed = false;
+
+        loop {
+            let events = match self.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => return changed,
+                Err(_) => return true,
+            };
+
+            for event in events {
+                if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) || event.wd != self.config_dir
+                {
+                    changed = true;
+                    continue;
+                }
+
+                if let Some(name) = event.name.as_deref().and_then(|name| name.to_str()) {
+                    if name == SOURCES_LIST || name == SOURCES_LIST_D {
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.inotify.as_raw_fd());
+    }
+}
+
+/// Like [`repositories`], but keeps the result in memory until one of the files in
+/// `/etc/apt/sources.list.d/` or `/etc/apt/sources.list` changes.
+///
+/// Falls back to reading the files every time if inotify is not available.
+pub fn repositories_cached() -> Result<Arc<Repositories>, Error> {
+    let mut cache = CACHE.lock().unwrap();
+
+    if let Some(cached) = cache.as_ref() {
+        if !cached.watcher.changed() {
+            return Ok(Arc::clone(&cached.repositories));
+        }
+    }
+
+    *cache = None;
+
+    let watcher = match Watcher::new(Path::new(APT_CONFIG_DIRECTORY)) {
+        Ok(watcher) => watcher,
+        Err(_) => return Ok(Arc::new(repositories()?)),
+    };
+
+    let repositories = Arc::new(repositories()?);
+
+    *cache = Some(Cache {
+        watcher,
+        repositories: Arc::clone(&repositories),
+    });
+
+    Ok(repositories)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use anyhow::Error;
+
+    use super::Watcher;
+
+    struct TestDir(PathBuf);
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_watcher() -> Result<(), Error> {
+        let dir = TestDir(
+            std::env::temp_dir().join(format!("proxmox-apt-cache-test-{}", std::process::id())),
+        );
+        fs::create_dir_all(&dir.0)?;
+
+        // sources.list.d does not exist yet
+        let watcher = Watcher::new(&dir.0)?;
+        assert!(!watcher.changed());
+
+        fs::write(dir.0.join("apt.conf"), "")?;
+        assert!(!watcher.changed(), "unrelated files must be ignored");
+
+        fs::write(dir.0.join("sources.list"), "")?;
+        assert!(watcher.changed());
+        assert!(!watcher.changed(), "events must be drained");
+
+        fs::create_dir(dir.0.join("sources.list.d"))?;
+        assert!(watcher.changed());
+
+        // a fresh watcher also watches the now existing sources.list.d
+        let watcher = Watcher::new(&dir.0)?;
+        fs::write(dir.0.join("sources.list.d").join("test.sources"), "")?;
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_file(dir.0.join("sources.list"))?;
+        assert!(watcher.changed());
+
+        Ok(())
+    }
+}
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::repositories::release::DebianCodename;
 use crate::repositories::repository::{
-    APTRepository, APTRepositoryFileType, APTRepositoryPackageType,
+    host_from_uri, APTRepository, APTRepositoryFileType, APTRepositoryPackageType,
 };
 
 use proxmox_schema::api;
@@ -299,15 +299,7 @@ impl APTRepositoryFile {
                 .map_err(|err| self.err(format_err!("unable to remove file - {}", err)));
         }
 
-        let mut content = vec![];
-
-        for (n, repo) in self.repositories.iter().enumerate() {
-            repo.basic_check()
-                .map_err(|err| self.err(format_err!("check for repository {} - {}", n + 1, err)))?;
-
-            repo.write(&mut content)
-                .map_err(|err| self.err(format_err!("writing repository {} - {}", n + 1, err)))?;
-        }
+        let content = self.render()?;
 
         let path = PathBuf::from(&path);
         let dir = match path.parent() {
@@ -336,6 +328,95 @@ impl APTRepositoryFile {
         Ok(())
     }
 
+    /// Returns the content that [`write`](Self::write) would write to the file on disk.
+    ///
+    /// For DEB822-style files, stanzas of repositories that are unchanged compared to the current
+    /// content of the file are kept as they are, including the order and formatting of fields.
+    pub fn render(&self) -> Result<Vec<u8>, APTRepositoryFileError> {
+        let mut content = vec![];
+        let mut original = self.original_stanzas();
+
+        for (n, repo) in self.repositories.iter().enumerate() {
+            repo.basic_check()
+                .map_err(|err| self.err(format_err!("check for repository {} - {}", n + 1, err)))?;
+
+            if let Some(pos) = original.iter().position(|(parsed, _)| parsed == repo) {
+                let (_, stanza) = original.remove(pos);
+                content.extend(
+                    stanza
+                        .trim_end_matches(|c| char::is_ascii_whitespace(&c))
+                        .bytes(),
+                );
+                content.extend(b"\n\n");
+                continue;
+            }
+
+            repo.write(&mut content)
+                .map_err(|err| self.err(format_err!("writing repository {} - {}", n + 1, err)))?;
+        }
+
+        Ok(content)
+    }
+
+    /// The repositories in the current content of a DEB822-style file, together with the text
+    /// they were parsed from. Empty if the file cannot be read or parsed.
+    fn original_stanzas(&self) -> Vec<(APTRepository, String)> {
+        if self.file_type != APTRepositoryFileType::Sources {
+            return vec![];
+        }
+
+        let content = match self.read_with_digest() {
+            Ok((content, _)) => content,
+            Err(_) => return vec![],
+        };
+
+        let mut parser = APTSourcesFileParser::new(&content[..]);
+        match parser.parse_repositories() {
+            Ok(repos) => repos.into_iter().zip(parser.into_stanzas()).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Converts the file to the given format, preserving comments and options.
+    ///
+    /// Returns a new file with the matching extension, the original file is left untouched. To
+    /// migrate, write the new file and remove the original one afterwards, e.g. by writing it
+    /// with an empty list of repositories.
+    pub fn convert_to(
+        &self,
+        file_type: APTRepositoryFileType,
+    ) -> Result<APTRepositoryFile, APTRepositoryFileError> {
+        let path = match &self.path {
+            Some(path) => {
+                let mut path = PathBuf::from(path);
+                path.set_extension(file_type.to_string());
+                let path = path
+                    .into_os_string()
+                    .into_string()
+                    .map_err(|_| self.err(format_err!("path is not valid unicode")))?;
+                Some(path)
+            }
+            None => None,
+        };
+
+        let mut repositories = vec![];
+
+        for (n, repo) in self.repositories.iter().enumerate() {
+            let mut converted = repo
+                .convert_to(file_type)
+                .map_err(|err| self.err(format_err!("converting repository {} - {err}", n + 1)))?;
+            repositories.append(&mut converted);
+        }
+
+        Ok(APTRepositoryFile {
+            path,
+            file_type,
+            repositories,
+            content: None,
+            digest: None,
+        })
+    }
+
     /// Checks if old or unstable suites are configured and that the Debian security repository
     /// has the correct suite. Also checks that the `stable` keyword is not used.
     pub fn check_suites(&self, current_codename: DebianCodename) -> Vec<APTRepositoryInfo> {
@@ -459,6 +540,99 @@ impl APTRepositoryFile {
 
         infos
     }
+
+    /// Checks that the components of Proxmox repositories exist for the product, e.g. to catch
+    /// typos or a `pbs-enterprise` component used with the Proxmox VE URI.
+    pub fn check_components(&self) -> Vec<APTRepositoryInfo> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return vec![],
+        };
+
+        let mut infos = vec![];
+
+        for (n, repo) in self.repositories.iter().enumerate() {
+            for uri in repo.uris.iter() {
+                let available = match proxmox_components(uri) {
+                    Some(available) => available,
+                    None => continue,
+                };
+
+                for component in repo.components.iter() {
+                    if !available.contains(component) {
+                        infos.push(APTRepositoryInfo {
+                            path: path.clone(),
+                            index: n,
+                            property: Some("Components".to_string()),
+                            kind: "invalid-component".to_string(),
+                            message: format!("component '{component}' does not exist for '{uri}'"),
+                        });
+                    }
+                }
+            }
+        }
+
+        infos
+    }
+
+    /// Checks that the keyrings referenced via `Signed-By` exist.
+    pub fn check_signed_by(&self) -> Vec<APTRepositoryInfo> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return vec![],
+        };
+
+        self.repositories
+            .iter()
+            .enumerate()
+            .filter_map(|(n, repo)| {
+                let err = repo.check_signed_by().err()?;
+                Some(APTRepositoryInfo {
+                    path: path.clone(),
+                    index: n,
+                    property: Some("Signed-By".to_string()),
+                    kind: "warning".to_string(),
+                    message: err.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns the components available in the Proxmox repository with the given `uri`, or `None`
+/// if the URI does not belong to a known Proxmox product repository.
+fn proxmox_components(uri: &str) -> Option<Vec<String>> {
+    let host = host_from_uri(uri)?;
+    if host != "proxmox.com" && !host.ends_with(".proxmox.com") {
+        return None;
+    }
+
+    let path = uri.split_once("://")?.1;
+    let path = path.split_once('/').map(|(_, path)| path).unwrap_or("");
+    let path = path.trim_end_matches('/');
+
+    let product = match path {
+        "debian" => "pve", // legacy URI of the Proxmox VE repositories
+        _ => path.strip_prefix("debian/")?,
+    };
+
+    if product.starts_with("ceph-") {
+        return Some(
+            ["enterprise", "no-subscription", "test", "main"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+    }
+
+    match product {
+        "pve" | "pbs" | "pmg" => Some(vec![
+            format!("{product}-enterprise"),
+            format!("{product}-no-subscription"),
+            format!("{product}test"),
+        ]),
+        _ => None,
+    }
 }
 
 /// Splits the suite into its base part and variant.
@@ -3,6 +3,7 @@ use std::iter::Iterator;
 
 use anyhow::{bail, Error};
 
+use crate::repositories::repository::{is_embedded_key, is_known_sources_option};
 use crate::repositories::{
     APTRepository, APTRepositoryFileType, APTRepositoryOption, APTRepositoryPackageType,
 };
@@ -13,6 +14,9 @@ pub struct APTSourcesFileParser<R: BufRead> {
     input: R,
     stanza_nr: usize,
     comment: String,
+    /// The text of each parsed stanza, including preceding comment-only stanzas.
+    stanzas: Vec<String>,
+    skipped: String,
 }
 
 /// See `man sources.list` and `man deb822` for the format specification.
@@ -22,9 +26,16 @@ impl<R: BufRead> APTSourcesFileParser<R> {
             input: reader,
             stanza_nr: 1,
             comment: String::new(),
+            stanzas: vec![],
+            skipped: String::new(),
         }
     }
 
+    /// The text each of the parsed repositories was parsed from.
+    pub fn into_stanzas(self) -> Vec<String> {
+        self.stanzas
+    }
+
     /// Based on APT's `StringToBool` in `strutl.cc`
     fn string_to_bool(string: &str, default: bool) -> bool {
         let string = string.trim_matches(|c| char::is_ascii_whitespace(&c));
@@ -58,18 +69,32 @@ impl<R: BufRead> APTSourcesFileParser<R> {
 
         // Values may be folded into multiple lines.
         // Those lines have to start with a space or a tab.
-        let lines = lines.replace("\n ", " ");
-        let lines = lines.replace("\n\t", " ");
+        let mut unfolded: Vec<String> = vec![];
+        for line in lines.lines() {
+            match unfolded.last_mut() {
+                Some(previous) if line.starts_with([' ', '\t']) => {
+                    previous.push('\n');
+                    if previous.starts_with('#') {
+                        previous.push_str(line.trim_matches(|c| char::is_ascii_whitespace(&c)));
+                    } else {
+                        // keep the indentation for unknown fields
+                        previous.push_str(line.trim_end_matches(|c| char::is_ascii_whitespace(&c)));
+                    }
+                }
+                _ => unfolded.push(line.to_string()),
+            }
+        }
 
         let mut got_something = false;
 
-        for line in lines.lines() {
+        for line in unfolded.iter() {
             let line = line.trim_matches(|c| char::is_ascii_whitespace(&c));
             if line.is_empty() {
                 continue;
             }
 
             if let Some(commented_out) = line.strip_prefix('#') {
+                let commented_out = commented_out.replace('\n', " ");
                 self.comment = format!("{}{}\n", self.comment, commented_out);
                 continue;
             }
@@ -83,7 +108,12 @@ impl<R: BufRead> APTSourcesFileParser<R> {
                     bail!("option has no key: '{}'", line);
                 }
 
-                if value_str.is_empty() {
+                let known = matches!(
+                    &key.to_lowercase()[..],
+                    "types" | "uris" | "suites" | "components"
+                ) || is_known_sources_option(key);
+
+                if value_str.trim().is_empty() && known {
                     // ignored by APT
                     eprintln!("option has no value: '{}'", line);
                     continue;
@@ -95,10 +125,25 @@ impl<R: BufRead> APTSourcesFileParser<R> {
                     continue;
                 }
 
-                let values: Vec<String> = value_str
-                    .split_ascii_whitespace()
-                    .map(|value| value.to_string())
-                    .collect();
+                let values: Vec<String> = if !known {
+                    // keep unknown fields verbatim, their value format is not known
+                    vec![value_str.trim_start_matches([' ', '\t']).to_string()]
+                } else if is_embedded_key(value_str) {
+                    // keep the line structure, a single '.' represents an empty line
+                    let key = value_str
+                        .trim_matches(|c| char::is_ascii_whitespace(&c))
+                        .lines()
+                        .map(|line| line.trim_matches(|c| char::is_ascii_whitespace(&c)))
+                        .map(|line| if line == "." { "" } else { line })
+                        .collect::<Vec<&str>>()
+                        .join("\n");
+                    vec![key]
+                } else {
+                    value_str
+                        .split_ascii_whitespace()
+                        .map(|value| value.to_string())
+                        .collect()
+                };
 
                 match &key.to_lowercase()[..] {
                     "types" => {
@@ -162,9 +207,10 @@ impl<R: BufRead> APTSourcesFileParser<R> {
         match self.parse_stanza(lines) {
             Ok(Some(repo)) => {
                 repos.push(repo);
+                self.stanzas.push(std::mem::take(&mut self.skipped) + lines);
                 self.stanza_nr += 1;
             }
-            Ok(None) => (),
+            Ok(None) => self.skipped.push_str(lines),
             Err(err) => bail!("malformed entry in stanza {} - {}", self.stanza_nr, err),
         }
 
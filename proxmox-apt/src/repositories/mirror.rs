@@ -0,0 +1,268 @@
+//! Rewriting repository URIs to a local mirror or CDN.
+//!
+//! Air-gapped setups often cannot reach `download.proxmox.com` or the Debian mirrors directly,
+//! but provide an internal mirror with the same layout. The functions here swap the base of
+//! all matching URIs, either in place or as a dry run producing a diff of the affected files.
+
+use std::fmt::Write;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::api;
+
+use crate::repositories::file::APTRepositoryFile;
+
+/// Number of unchanged lines shown around changes in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+#[api]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// A single URI rewritten by [`rewrite_uris`].
+pub struct APTRepositoryUriChange {
+    /// Path to the defining file.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+
+    /// Index of the associated repository within the file (starting from 0).
+    pub index: usize,
+
+    /// The URI before rewriting.
+    pub old_uri: String,
+
+    /// The URI after rewriting.
+    pub new_uri: String,
+}
+
+/// Returns the rewritten URI if `uri` is below `from`.
+///
+/// If `from` does not contain a scheme, URIs are matched regardless of their scheme, and the
+/// original scheme is kept unless `to` specifies one.
+fn rewrite_uri(uri: &str, from: &str, to: &str) -> Option<String> {
+    let (scheme, rest) = match uri.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, uri),
+    };
+
+    let remainder = if from.contains("://") {
+        uri.strip_prefix(from)?
+    } else {
+        rest.strip_prefix(from)?
+    };
+
+    if !remainder.is_empty() && !remainder.starts_with('/') && !from.ends_with('/') {
+        return None; // only a partial match of the last path component or the host
+    }
+
+    let remainder = remainder.trim_start_matches('/');
+    let to = to.trim_end_matches('/');
+
+    let base = match (to.contains("://"), scheme) {
+        (false, Some(scheme)) => format!("{scheme}://{to}"),
+        _ => to.to_string(),
+    };
+
+    match remainder.is_empty() {
+        true => Some(base),
+        false => Some(format!("{base}/{remainder}")),
+    }
+}
+
+fn check_rewrite_params(from: &str, to: &str) -> Result<(), Error> {
+    if from.trim_end_matches('/').is_empty() || to.trim_end_matches('/').is_empty() {
+        bail!("source and target of the URI rewrite must not be empty");
+    }
+
+    if from.contains(char::is_whitespace) || to.contains(char::is_whitespace) {
+        bail!("source and target of the URI rewrite must not contain whitespace");
+    }
+
+    Ok(())
+}
+
+/// Rewrites all URIs in `files` starting with `from` to start with `to` instead.
+///
+/// `from` is either a full URI base, e.g. `http://download.proxmox.com`, or a base without
+/// scheme, e.g. `download.proxmox.com/debian`, which matches all schemes. Only whole path
+/// components are matched. The files are modified in memory only and need to be written by
+/// the caller. Their digests are kept, so concurrent modifications are detected on write.
+pub fn rewrite_uris(
+    files: &mut [APTRepositoryFile],
+    from: &str,
+    to: &str,
+) -> Result<Vec<APTRepositoryUriChange>, Error> {
+    check_rewrite_params(from, to)?;
+
+    let mut changes = vec![];
+
+    for file in files.iter_mut() {
+        let path = file.path.clone().unwrap_or_default();
+
+        for (index, repo) in file.repositories.iter_mut().enumerate() {
+            for uri in repo.uris.iter_mut() {
+                if let Some(new_uri) = rewrite_uri(uri, from, to) {
+                    if new_uri == *uri {
+                        continue;
+                    }
+                    changes.push(APTRepositoryUriChange {
+                        path: path.clone(),
+                        index,
+                        old_uri: std::mem::replace(uri, new_uri.clone()),
+                        new_uri,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Returns a unified diff of the changes [`rewrite_uris`] would apply to `files`, without
+/// modifying them. Files without matching URIs are not part of the diff.
+pub fn rewrite_uris_diff(
+    files: &[APTRepositoryFile],
+    from: &str,
+    to: &str,
+) -> Result<String, Error> {
+    let mut rewritten = files.to_vec();
+    let changes = rewrite_uris(&mut rewritten, from, to)?;
+
+    let mut diff = String::new();
+
+    for (old, new) in files.iter().zip(rewritten.iter()) {
+        let path = old.path.clone().unwrap_or_default();
+        if !changes.iter().any(|change| change.path == path) {
+            continue;
+        }
+
+        let old_content = String::from_utf8(old.render()?)
+            .map_err(|err| format_err!("{path} - content is not valid UTF-8 - {err}"))?;
+        let new_content = String::from_utf8(new.render()?)
+            .map_err(|err| format_err!("{path} - content is not valid UTF-8 - {err}"))?;
+
+        diff.push_str(&unified_diff(&path, &old_content, &new_content));
+    }
+
+    Ok(diff)
+}
+
+/// Produces a unified diff between `old` and `new`, based on their longest common subsequence.
+fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    // operations with the old and new line index they start at
+    let mut ops: Vec<(char, &str, usize, usize)> = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push((' ', old[i], i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', old[i], i, j));
+            i += 1;
+        } else {
+            ops.push(('+', new[j], i, j));
+            j += 1;
+        }
+    }
+
+    // ranges of operations to show, changes with their context merged if they overlap
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for (n, _) in ops.iter().enumerate().filter(|(_, op)| op.0 != ' ') {
+        let start = n.saturating_sub(DIFF_CONTEXT);
+        let end = (n + DIFF_CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut diff = String::new();
+    if hunks.is_empty() {
+        return diff;
+    }
+
+    let _ = writeln!(diff, "--- {path}");
+    let _ = writeln!(diff, "+++ {path}");
+
+    for (start, end) in hunks {
+        let ops = &ops[start..end];
+        let old_len = ops.iter().filter(|op| op.0 != '+').count();
+        let new_len = ops.iter().filter(|op| op.0 != '-').count();
+        // like diff(1), empty ranges start at the line before them
+        let old_start = ops[0].2 + usize::from(old_len > 0);
+        let new_start = ops[0].3 + usize::from(new_len > 0);
+
+        let _ = writeln!(diff, "@@ -{old_start},{old_len} +{new_start},{new_len} @@");
+        for (kind, line, _, _) in ops {
+            let _ = writeln!(diff, "{kind}{line}");
+        }
+    }
+
+    diff
+}
+
+#[test]
+fn test_rewrite_uri() {
+    let cases = [
+        (
+            "http://download.proxmox.com/debian/pve",
+            "download.proxmox.com",
+            "mirror.local/proxmox",
+            Some("http://mirror.local/proxmox/debian/pve"),
+        ),
+        (
+            "https://enterprise.proxmox.com/debian/pve",
+            "https://enterprise.proxmox.com/debian",
+            "http://mirror.local/enterprise/",
+            Some("http://mirror.local/enterprise/pve"),
+        ),
+        (
+            "http://download.proxmox.com/debian/pve",
+            "https://download.proxmox.com",
+            "mirror.local",
+            None,
+        ),
+        (
+            "http://download.proxmox.com.evil/debian",
+            "download.proxmox.com",
+            "mirror.local",
+            None,
+        ),
+        (
+            "http://deb.debian.org/debian",
+            "deb.debian.org/debian",
+            "file:///srv/mirror/debian",
+            Some("file:///srv/mirror/debian"),
+        ),
+        (
+            "http://deb.debian.org/debian-security",
+            "deb.debian.org/debian",
+            "mirror.local",
+            None,
+        ),
+    ];
+
+    for (uri, from, to, expected) in cases {
+        assert_eq!(
+            rewrite_uri(uri, from, to).as_deref(),
+            expected,
+            "{uri}: {from} -> {to}"
+        );
+    }
+}
@@ -1,11 +1,13 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
 
 use anyhow::{bail, Error};
 
 mod repository;
+pub(crate) use repository::{host_from_uri, lists_filename};
 pub use repository::{
     APTRepository, APTRepositoryFileType, APTRepositoryOption, APTRepositoryPackageType,
+    APTRepositorySignedBy,
 };
 
 mod file;
@@ -15,7 +17,22 @@ mod release;
 pub use release::{get_current_release_codename, DebianCodename};
 
 mod standard;
-pub use standard::{APTRepositoryHandle, APTStandardRepository};
+pub use standard::{
+    register_standard_repository, standard_repository_definitions, APTRepositoryHandle,
+    APTStandardRepository, APTStandardRepositoryDefinition,
+};
+
+mod snapshot;
+pub use snapshot::{APTSnapshotDirectory, APTSnapshotInfo};
+
+mod mirror;
+pub use mirror::{rewrite_uris, rewrite_uris_diff, APTRepositoryUriChange};
+
+mod transaction;
+pub use transaction::RepositoryTransaction;
+
+mod cache;
+pub use cache::repositories_cached;
 
 const APT_SOURCES_LIST_FILENAME: &str = "/etc/apt/sources.list";
 const APT_SOURCES_LIST_DIRECTORY: &str = "/etc/apt/sources.list.d/";
@@ -49,6 +66,10 @@ fn common_digest(files: &[APTRepositoryFile]) -> [u8; 32] {
 /// `warnings` for bad suites.
 /// `ignore-pre-upgrade-warning` when the next stable suite is configured.
 /// `badge` for official URIs.
+/// `warning` for missing keyrings referenced via `Signed-By`.
+/// `duplicate` for enabled repositories also defined elsewhere.
+/// `conflict` for disabled repositories that are enabled elsewhere.
+/// `invalid-component` for components not available for a Proxmox product.
 pub fn check_repositories(
     files: &[APTRepositoryFile],
     current_suite: DebianCodename,
@@ -58,11 +79,100 @@ pub fn check_repositories(
     for file in files.iter() {
         infos.append(&mut file.check_suites(current_suite));
         infos.append(&mut file.check_uris());
+        infos.append(&mut file.check_signed_by());
+        infos.append(&mut file.check_components());
     }
 
+    infos.append(&mut check_duplicates(files));
+
     infos
 }
 
+/// Checks for repositories providing the same package type, URI, suite and component, also
+/// across files.
+///
+/// APT warns about enabled duplicates. A disabled duplicate of an enabled repository is
+/// reported as conflict, as enabling or disabling only one of them does not have the expected
+/// effect.
+fn check_duplicates(files: &[APTRepositoryFile]) -> Vec<APTRepositoryInfo> {
+    // (path, index, enabled) of all repositories providing the entry
+    let mut entries: BTreeMap<_, Vec<(&str, usize, bool)>> = BTreeMap::new();
+
+    for file in files.iter() {
+        let path = match &file.path {
+            Some(path) => path.as_str(),
+            None => continue,
+        };
+
+        for (n, repo) in file.repositories.iter().enumerate() {
+            let components = match repo.components.is_empty() {
+                true => vec![""],
+                false => repo.components.iter().map(String::as_str).collect(),
+            };
+
+            for package_type in repo.types.iter() {
+                for uri in repo.uris.iter() {
+                    for suite in repo.suites.iter() {
+                        for component in components.iter() {
+                            let key = (
+                                package_type.to_string(),
+                                uri.trim_end_matches('/'),
+                                suite.trim_end_matches('/'),
+                                *component,
+                            );
+                            let defined = entries.entry(key).or_default();
+                            if !defined.contains(&(path, n, repo.enabled)) {
+                                defined.push((path, n, repo.enabled));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut infos = BTreeSet::new();
+
+    for defined in entries.values() {
+        let mut enabled = defined.iter().filter(|(_, _, enabled)| *enabled);
+
+        let first = match enabled.next() {
+            Some(first) => first,
+            None => continue,
+        };
+
+        let mut add_info = |(path, index, _): &(&str, usize, bool), kind: &str, message| {
+            infos.insert(APTRepositoryInfo {
+                path: path.to_string(),
+                index: *index,
+                property: None,
+                kind: kind.to_string(),
+                message,
+            });
+        };
+
+        let defined_at = format!("'{}' (repository {})", first.0, first.1 + 1);
+
+        for duplicate in enabled {
+            add_info(
+                duplicate,
+                "duplicate",
+                format!("repository already defined in {defined_at}"),
+            );
+        }
+
+        for disabled in defined.iter().filter(|(_, _, enabled)| !*enabled) {
+            add_info(
+                disabled,
+                "conflict",
+                format!("repository is disabled, but enabled in {defined_at}"),
+            );
+        }
+    }
+
+    infos.into_iter().collect()
+}
+
 /// Get the repository associated to the handle and the path where it is usually configured.
 pub fn get_standard_repository(
     handle: APTRepositoryHandle,
@@ -77,40 +187,29 @@ pub fn get_standard_repository(
 
 /// Return handles for standard Proxmox repositories and their status, where
 /// `None` means not configured, and `Some(bool)` indicates enabled or disabled.
+///
+/// The repositories are described by the definitions registered with
+/// [`register_standard_repository`], or the built-in ones.
 pub fn standard_repositories(
     files: &[APTRepositoryFile],
     product: &str,
     suite: DebianCodename,
 ) -> Vec<APTStandardRepository> {
-    let mut result = vec![
-        APTStandardRepository::from(APTRepositoryHandle::Enterprise),
-        APTStandardRepository::from(APTRepositoryHandle::NoSubscription),
-        APTStandardRepository::from(APTRepositoryHandle::Test),
-    ];
-
-    if product == "pve" {
-        result.append(&mut vec![
-            APTStandardRepository::from(APTRepositoryHandle::CephQuincyEnterprise),
-            APTStandardRepository::from(APTRepositoryHandle::CephQuincyNoSubscription),
-            APTStandardRepository::from(APTRepositoryHandle::CephQuincyTest),
-        ]);
-        if suite == DebianCodename::Bookworm {
-            result.append(&mut vec![
-                APTStandardRepository::from(APTRepositoryHandle::CephReefEnterprise),
-                APTStandardRepository::from(APTRepositoryHandle::CephReefNoSubscription),
-                APTStandardRepository::from(APTRepositoryHandle::CephReefTest),
-            ]);
-        }
-    }
+    let definitions = standard_repository_definitions(product, suite);
+
+    let mut result: Vec<APTStandardRepository> = definitions
+        .iter()
+        .map(APTStandardRepository::from)
+        .collect();
 
     for file in files.iter() {
         for repo in file.repositories.iter() {
-            for entry in result.iter_mut() {
+            for (entry, definition) in result.iter_mut().zip(definitions.iter()) {
                 if entry.status == Some(true) {
                     continue;
                 }
 
-                if repo.is_referenced_repository(entry.handle, product, &suite.to_string()) {
+                if repo.is_standard_repository(definition, product, &suite.to_string()) {
                     entry.status = Some(repo.enabled);
                 }
             }
@@ -4,7 +4,7 @@ use std::io::{BufRead, BufReader};
 use anyhow::{bail, format_err, Error};
 
 /// The code names of Debian releases. Does not include `sid`.
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DebianCodename {
     Lenny = 5,
     Squeeze,
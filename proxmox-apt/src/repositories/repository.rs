@@ -1,13 +1,13 @@
 use std::fmt::Display;
 use std::io::{BufRead, BufReader, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, format_err, Error};
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::api;
 
-use crate::repositories::standard::APTRepositoryHandle;
+use crate::repositories::standard::{APTRepositoryHandle, APTStandardRepositoryDefinition};
 
 #[api]
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -87,7 +87,7 @@ impl Display for APTRepositoryPackageType {
         },
     },
 )]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "PascalCase")] // for consistency
 /// Additional options for an APT repository.
 /// Used for both single- and mutli-value options.
@@ -152,7 +152,7 @@ pub struct APTRepositoryOption {
         },
     },
 )]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "PascalCase")]
 /// Describes an APT repository.
 pub struct APTRepository {
@@ -188,6 +188,76 @@ pub struct APTRepository {
     pub enabled: bool,
 }
 
+/// Keys used to verify the `(In)Release` file of a repository, see the `Signed-By` option in
+/// `man sources.list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum APTRepositorySignedBy {
+    /// Paths to keyring files and/or key fingerprints.
+    Keyrings(Vec<String>),
+    /// An embedded ASCII-armored public key block. Only supported in DEB822-style format.
+    Embedded(String),
+}
+
+const PGP_PUBLIC_KEY_BEGIN: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----";
+const PGP_PUBLIC_KEY_END: &str = "-----END PGP PUBLIC KEY BLOCK-----";
+
+/// Option keys in one-line-style format and their DEB822-style counterparts, see
+/// `man sources.list`.
+const OPTION_KEYS: &[(&str, &str)] = &[
+    ("arch", "Architectures"),
+    ("arch+", "Architectures-Add"),
+    ("arch-", "Architectures-Remove"),
+    ("lang", "Languages"),
+    ("lang+", "Languages-Add"),
+    ("lang-", "Languages-Remove"),
+    ("target", "Targets"),
+    ("target+", "Targets-Add"),
+    ("target-", "Targets-Remove"),
+    ("pdiffs", "PDiffs"),
+    ("by-hash", "By-Hash"),
+    ("allow-insecure", "Allow-Insecure"),
+    ("allow-weak", "Allow-Weak"),
+    ("allow-downgrade-to-insecure", "Allow-Downgrade-To-Insecure"),
+    ("trusted", "Trusted"),
+    ("signed-by", "Signed-By"),
+    ("check-valid-until", "Check-Valid-Until"),
+    ("valid-until-min", "Valid-Until-Min"),
+    ("valid-until-max", "Valid-Until-Max"),
+    ("check-date", "Check-Date"),
+    ("date-max-future", "Date-Max-Future"),
+    ("inrelease-path", "InRelease-Path"),
+    ("snapshot", "Snapshot"),
+];
+
+/// Get the option key used in the given format. Unknown keys are returned unchanged.
+fn option_key_for(key: &str, file_type: APTRepositoryFileType) -> String {
+    for (list_key, sources_key) in OPTION_KEYS {
+        if key.eq_ignore_ascii_case(list_key) || key.eq_ignore_ascii_case(sources_key) {
+            return match file_type {
+                APTRepositoryFileType::List => list_key.to_string(),
+                APTRepositoryFileType::Sources => sources_key.to_string(),
+            };
+        }
+    }
+
+    key.to_string()
+}
+
+/// Checks if `key` is a DEB822-style option known to APT, see `man sources.list`.
+pub(crate) fn is_known_sources_option(key: &str) -> bool {
+    key.eq_ignore_ascii_case("enabled")
+        || OPTION_KEYS
+            .iter()
+            .any(|(_, sources_key)| key.eq_ignore_ascii_case(sources_key))
+}
+
+/// Checks if the option value is an embedded ASCII-armored public key block.
+pub(crate) fn is_embedded_key(value: &str) -> bool {
+    value
+        .trim_start_matches(|c| char::is_ascii_whitespace(&c))
+        .starts_with(PGP_PUBLIC_KEY_BEGIN)
+}
+
 impl APTRepository {
     /// Crates an empty repository.
     pub fn new(file_type: APTRepositoryFileType) -> Self {
@@ -262,11 +332,168 @@ impl APTRepository {
             if self.suites.len() > 1 {
                 bail!("more than one suite");
             }
+            if let Some(APTRepositorySignedBy::Embedded(_)) = self.signed_by() {
+                bail!("embedded keys are not supported in one-line-style format");
+            }
         }
 
         Ok(())
     }
 
+    /// Get the keys configured via the `Signed-By` option, if any.
+    pub fn signed_by(&self) -> Option<APTRepositorySignedBy> {
+        let option = self
+            .options
+            .iter()
+            .find(|option| option.key.eq_ignore_ascii_case("signed-by"))?;
+
+        match option.values.first() {
+            Some(value) if is_embedded_key(value) => {
+                Some(APTRepositorySignedBy::Embedded(value.clone()))
+            }
+            _ => Some(APTRepositorySignedBy::Keyrings(option.values.clone())),
+        }
+    }
+
+    /// Sets or removes the `Signed-By` option.
+    ///
+    /// Embedded keys can only be used for repositories in DEB822-style format.
+    pub fn set_signed_by(&mut self, signed_by: Option<APTRepositorySignedBy>) -> Result<(), Error> {
+        let values = match signed_by {
+            None => None,
+            Some(APTRepositorySignedBy::Keyrings(keyrings)) => {
+                if keyrings.is_empty() {
+                    bail!("no keyring specified");
+                }
+                for keyring in keyrings.iter() {
+                    if keyring.is_empty()
+                        || keyring.contains(|c: char| c.is_ascii_whitespace() || c == ',')
+                    {
+                        bail!("invalid keyring '{keyring}'");
+                    }
+                }
+                Some(keyrings)
+            }
+            Some(APTRepositorySignedBy::Embedded(key)) => {
+                if self.file_type != APTRepositoryFileType::Sources {
+                    bail!("embedded keys are not supported in one-line-style format");
+                }
+                let key = key.trim_matches(|c| char::is_ascii_whitespace(&c));
+                if !key.starts_with(PGP_PUBLIC_KEY_BEGIN) || !key.ends_with(PGP_PUBLIC_KEY_END) {
+                    bail!("embedded key is not an ASCII-armored public key block");
+                }
+                Some(vec![key.to_string()])
+            }
+        };
+
+        self.options
+            .retain(|option| !option.key.eq_ignore_ascii_case("signed-by"));
+
+        if let Some(values) = values {
+            let key = match self.file_type {
+                APTRepositoryFileType::List => "signed-by",
+                APTRepositoryFileType::Sources => "Signed-By",
+            };
+            self.options.push(APTRepositoryOption {
+                key: key.to_string(),
+                values,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the keyring files referenced via the `Signed-By` option exist.
+    ///
+    /// Key fingerprints and embedded keys are not checked.
+    pub fn check_signed_by(&self) -> Result<(), Error> {
+        if let Some(APTRepositorySignedBy::Keyrings(keyrings)) = self.signed_by() {
+            for keyring in keyrings.iter().filter(|keyring| keyring.starts_with('/')) {
+                if !Path::new(keyring).is_file() {
+                    bail!("keyring '{keyring}' does not exist");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Converts the repository to the given format.
+    ///
+    /// Converting to one-line-style format results in one repository for each combination of
+    /// package type, URI and suite, the comment is kept for the first one. Embedded keys cannot
+    /// be converted to one-line-style format.
+    pub fn convert_to(
+        &self,
+        file_type: APTRepositoryFileType,
+    ) -> Result<Vec<APTRepository>, Error> {
+        if self.file_type == file_type {
+            return Ok(vec![self.clone()]);
+        }
+
+        let options: Vec<APTRepositoryOption> = self
+            .options
+            .iter()
+            .filter(|option| !option.key.eq_ignore_ascii_case("enabled"))
+            .map(|option| APTRepositoryOption {
+                key: option_key_for(&option.key, file_type),
+                // unknown options keep their values verbatim in DEB822-style format
+                values: match file_type {
+                    APTRepositoryFileType::List => option
+                        .values
+                        .iter()
+                        .flat_map(|value| value.split_ascii_whitespace())
+                        .map(String::from)
+                        .collect(),
+                    APTRepositoryFileType::Sources => option.values.clone(),
+                },
+            })
+            .collect();
+
+        match file_type {
+            APTRepositoryFileType::Sources => {
+                let mut repo = APTRepository {
+                    options,
+                    file_type,
+                    ..self.clone()
+                };
+                if !self.enabled {
+                    repo.set_enabled(false);
+                }
+                Ok(vec![repo])
+            }
+            APTRepositoryFileType::List => {
+                if let Some(APTRepositorySignedBy::Embedded(_)) = self.signed_by() {
+                    bail!("embedded keys are not supported in one-line-style format");
+                }
+
+                let mut repos = vec![];
+
+                for package_type in self.types.iter() {
+                    for uri in self.uris.iter() {
+                        for suite in self.suites.iter() {
+                            repos.push(APTRepository {
+                                types: vec![*package_type],
+                                uris: vec![uri.clone()],
+                                suites: vec![suite.clone()],
+                                components: self.components.clone(),
+                                options: options.clone(),
+                                comment: match repos.is_empty() {
+                                    true => self.comment.clone(),
+                                    false => String::new(),
+                                },
+                                file_type,
+                                enabled: self.enabled,
+                            });
+                        }
+                    }
+                }
+
+                Ok(repos)
+            }
+        }
+    }
+
     /// Checks if the repository is the one referenced by the handle.
     pub fn is_referenced_repository(
         &self,
@@ -274,7 +501,18 @@ impl APTRepository {
         product: &str,
         suite: &str,
     ) -> bool {
-        let (package_type, handle_uris, component) = handle.info(product);
+        self.is_standard_repository(&handle.definition(product), product, suite)
+    }
+
+    /// Checks if the repository is the standard repository described by `definition`.
+    pub fn is_standard_repository(
+        &self,
+        definition: &APTStandardRepositoryDefinition,
+        product: &str,
+        suite: &str,
+    ) -> bool {
+        let handle_uris = definition.uris(product);
+        let component = definition.component(product);
 
         let mut found_uri = false;
 
@@ -284,15 +522,15 @@ impl APTRepository {
             found_uri = found_uri || handle_uris.iter().any(|handle_uri| handle_uri == uri);
         }
 
-        // In the past it was main instead of enterprise/no-subscription, and main now maps to
-        // no-subscription. Note this only applies for Quincy.
-        let found_component = if handle == APTRepositoryHandle::CephQuincyNoSubscription {
-            self.components.contains(&component) || self.components.contains(&"main".to_string())
-        } else {
-            self.components.contains(&component)
-        };
+        let found_component = self.components.iter().any(|self_component| {
+            *self_component == component
+                || definition
+                    .legacy_components
+                    .iter()
+                    .any(|legacy| legacy == self_component)
+        });
 
-        self.types.contains(&package_type)
+        self.types.contains(&definition.package_type)
             && found_uri
             // using contains would require a &String
             && self.suites.iter().any(|self_suite| self_suite == suite)
@@ -366,11 +604,18 @@ impl APTRepository {
 
 /// Get the path to the cached (In)Release file.
 fn release_filename(uri: &str, suite: &str, detached: bool) -> PathBuf {
+    let filename = if detached { "Release" } else { "InRelease" };
+
+    lists_filename(uri, suite, filename)
+}
+
+/// Get the path to a file for the repository's `suite` in APT's lists directory, e.g. an index
+/// file, where `filename` is the part after the suite, like `main_binary-amd64_Packages`.
+pub(crate) fn lists_filename(uri: &str, suite: &str, filename: &str) -> PathBuf {
     let mut path = PathBuf::from(&crate::config::get().dir_state);
     path.push(&crate::config::get().dir_state_lists);
 
     let encoded_uri = uri_to_filename(uri);
-    let filename = if detached { "Release" } else { "InRelease" };
 
     if suite == "/" {
         path.push(format!("{encoded_uri}_{filename}"));
@@ -421,7 +666,7 @@ fn uri_to_filename(uri: &str) -> String {
 }
 
 /// Get the host part from a given URI.
-fn host_from_uri(uri: &str) -> Option<&str> {
+pub(crate) fn host_from_uri(uri: &str) -> Option<&str> {
     let host = uri.strip_prefix("http")?;
     let host = host.strip_prefix('s').unwrap_or(host);
     let mut host = host.strip_prefix("://")?;
@@ -553,7 +798,7 @@ fn write_stanza(repo: &APTRepository, w: &mut dyn Write) -> Result<(), Error> {
     }
 
     for option in repo.options.iter() {
-        writeln!(w, "{}: {}", option.key, option.values.join(" "))?;
+        write_stanza_option(option, w)?;
     }
 
     writeln!(w)?;
@@ -561,6 +806,39 @@ fn write_stanza(repo: &APTRepository, w: &mut dyn Write) -> Result<(), Error> {
     Ok(())
 }
 
+fn write_stanza_option(option: &APTRepositoryOption, w: &mut dyn Write) -> Result<(), Error> {
+    let value = option.values.join(" ");
+
+    if value.contains('\n') {
+        // fold multi-line values, a single '.' represents an empty line, embedded keys start on
+        // their own line
+        let mut lines = value.lines();
+        match is_embedded_key(&value) {
+            true => writeln!(w, "{}:", option.key)?,
+            false => match lines.next() {
+                Some(first) if !first.is_empty() => writeln!(w, "{}: {first}", option.key)?,
+                _ => writeln!(w, "{}:", option.key)?,
+            },
+        }
+        for line in lines {
+            if line.is_empty() {
+                writeln!(w, " .")?;
+            } else if line.starts_with([' ', '\t']) {
+                // unknown fields keep the indentation of their continuation lines
+                writeln!(w, "{line}")?;
+            } else {
+                writeln!(w, " {line}")?;
+            }
+        }
+    } else if value.is_empty() {
+        writeln!(w, "{}:", option.key)?;
+    } else {
+        writeln!(w, "{}: {}", option.key, value)?;
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_uri_to_filename() {
     let filename = uri_to_filename("https://some_host/some/path");
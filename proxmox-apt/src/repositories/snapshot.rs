@@ -0,0 +1,308 @@
+//! Offline mirror snapshots for air-gapped environments.
+//!
+//! A snapshot directory contains one sub-directory per staged snapshot, each of them being a
+//! complete copy of an APT repository (`dists/` and `pool/`), and a `current` symlink pointing
+//! to the active snapshot. Repositories use a `file://` URI pointing to that symlink, so
+//! switching between snapshots only requires to atomically replace the link.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::api;
+
+use crate::deb822::{FileReferenceType, PackagesFile, ReleaseFile};
+use crate::repositories::repository::APTRepository;
+
+/// Name of the symlink pointing to the active snapshot.
+const CURRENT_SNAPSHOT_LINK: &str = "current";
+
+#[api(
+    properties: {
+        suites: {
+            description: "List of suites contained in the snapshot.",
+            type: Array,
+            items: {
+                description: "Suite.",
+                type: String,
+            },
+        },
+    },
+)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Information about a staged offline mirror snapshot.
+pub struct APTSnapshotInfo {
+    /// The name of the snapshot.
+    pub name: String,
+
+    /// Whether the snapshot is the currently active one.
+    pub active: bool,
+
+    /// List of suites contained in the snapshot.
+    pub suites: Vec<String>,
+}
+
+/// A directory containing staged offline mirror snapshots.
+pub struct APTSnapshotDirectory {
+    base: PathBuf,
+}
+
+impl APTSnapshotDirectory {
+    /// Creates a new instance for the snapshots stored in `base`.
+    pub fn new<P: AsRef<Path>>(base: P) -> Self {
+        Self {
+            base: base.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The `file://` URI repositories need to use to follow the active snapshot.
+    pub fn uri(&self) -> String {
+        format!("file://{}", self.base.join(CURRENT_SNAPSHOT_LINK).display())
+    }
+
+    /// Get the path to the snapshot with the given `name`.
+    pub fn snapshot_path(&self, name: &str) -> Result<PathBuf, Error> {
+        if name.is_empty() || name.starts_with('.') || name == CURRENT_SNAPSHOT_LINK {
+            bail!("invalid snapshot name '{name}'");
+        }
+
+        if !name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-' || x == '.')
+        {
+            bail!("invalid characters in snapshot name '{name}'");
+        }
+
+        Ok(self.base.join(name))
+    }
+
+    /// Returns the name of the currently active snapshot, if any.
+    pub fn current(&self) -> Result<Option<String>, Error> {
+        let link = self.base.join(CURRENT_SNAPSHOT_LINK);
+
+        match std::fs::read_link(&link) {
+            Ok(target) => {
+                let name = target
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or_else(|| format_err!("invalid snapshot link target {target:?}"))?;
+                Ok(Some(name.to_string()))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => bail!("unable to read snapshot link {link:?} - {err}"),
+        }
+    }
+
+    /// Lists the suites of a snapshot, i.e. all directories in `dists/` with a `Release` file.
+    pub fn suites(&self, name: &str) -> Result<Vec<String>, Error> {
+        let dists = self.snapshot_path(name)?.join("dists");
+
+        let mut suites = vec![];
+
+        for entry in std::fs::read_dir(&dists)
+            .map_err(|err| format_err!("unable to read {dists:?} - {err}"))?
+        {
+            let entry = entry?;
+            if !entry.path().join("Release").is_file() {
+                continue;
+            }
+            if let Some(suite) = entry.file_name().to_str() {
+                suites.push(suite.to_string());
+            }
+        }
+
+        suites.sort();
+
+        Ok(suites)
+    }
+
+    /// Lists all staged snapshots.
+    pub fn list(&self) -> Result<Vec<APTSnapshotInfo>, Error> {
+        let current = self.current()?;
+
+        let mut list = vec![];
+
+        for entry in std::fs::read_dir(&self.base)
+            .map_err(|err| format_err!("unable to read {:?} - {err}", self.base))?
+        {
+            let entry = entry?;
+
+            if !entry.file_type()?.is_dir() {
+                continue; // also skips the 'current' symlink
+            }
+
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+
+            if self.snapshot_path(&name).is_err() {
+                continue;
+            }
+
+            let suites = match self.suites(&name) {
+                Ok(suites) => suites,
+                Err(_) => continue,
+            };
+
+            list.push(APTSnapshotInfo {
+                active: current.as_deref() == Some(name.as_str()),
+                name,
+                suites,
+            });
+        }
+
+        list.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(list)
+    }
+
+    /// Verifies that the snapshot contains everything referenced by the `Release` file of
+    /// `suite`.
+    ///
+    /// Package indices need to be present in at least one (compressed) variant, other
+    /// referenced files are optional. Every present file needs to match the size and
+    /// checksums of the `Release` file. For uncompressed `Packages` indices, the referenced
+    /// package files also need to exist with the expected size.
+    pub fn verify(&self, name: &str, suite: &str) -> Result<(), Error> {
+        let snapshot = self.snapshot_path(name)?;
+        let dist_dir = snapshot.join("dists").join(suite);
+
+        let release_path = dist_dir.join("Release");
+        let raw = std::fs::read(&release_path)
+            .map_err(|err| format_err!("unable to read {release_path:?} - {err}"))?;
+        let release = ReleaseFile::try_from(&raw[..])
+            .map_err(|err| format_err!("unable to parse {release_path:?} - {err}"))?;
+
+        let mut problems = vec![];
+
+        for references in release.files.values() {
+            let mut found = false;
+
+            for reference in references {
+                let path = dist_dir.join(&reference.path);
+
+                let content = match std::fs::read(&path) {
+                    Ok(content) => content,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(err) => {
+                        problems.push(format!("unable to read '{}' - {err}", reference.path));
+                        found = true;
+                        continue;
+                    }
+                };
+                found = true;
+
+                if content.len() != reference.size {
+                    problems.push(format!(
+                        "size mismatch for '{}': {} != {}",
+                        reference.path,
+                        content.len(),
+                        reference.size
+                    ));
+                    continue;
+                }
+
+                if let Err(err) = reference.checksums.verify(&content) {
+                    problems.push(format!("'{}' - {err}", reference.path));
+                    continue;
+                }
+
+                if let FileReferenceType::Packages(_, None) = reference.file_type {
+                    if let Err(err) = verify_pool_files(&snapshot, &content) {
+                        problems.push(format!("'{}' - {err}", reference.path));
+                    }
+                }
+            }
+
+            if !found {
+                if let Some(reference) = references
+                    .iter()
+                    .find(|reference| reference.file_type.is_package_index())
+                {
+                    problems.push(format!("missing package index '{}'", reference.path));
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            problems.sort();
+            bail!(
+                "snapshot '{name}' suite '{suite}' is incomplete:\n{}",
+                problems.join("\n")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Verifies all suites of the snapshot and makes it the active one.
+    ///
+    /// The `current` link is replaced atomically, so APT either sees the old or the new
+    /// snapshot, but never a mixture of both.
+    pub fn activate(&self, name: &str) -> Result<(), Error> {
+        let suites = self.suites(name)?;
+        if suites.is_empty() {
+            bail!("snapshot '{name}' does not contain any suites");
+        }
+
+        for suite in suites.iter() {
+            self.verify(name, suite)?;
+        }
+
+        let link = self.base.join(CURRENT_SNAPSHOT_LINK);
+        let tmp_link = self
+            .base
+            .join(format!(".{CURRENT_SNAPSHOT_LINK}.{}", std::process::id()));
+
+        let _ = std::fs::remove_file(&tmp_link);
+        std::os::unix::fs::symlink(name, &tmp_link)
+            .map_err(|err| format_err!("unable to create link {tmp_link:?} - {err}"))?;
+
+        if let Err(err) = std::fs::rename(&tmp_link, &link) {
+            let _ = std::fs::remove_file(&tmp_link);
+            bail!("rename failed for {link:?} - {err}");
+        }
+
+        Ok(())
+    }
+
+    /// Points the repository to the active snapshot, by replacing its URIs.
+    pub fn point_repository(&self, repo: &mut APTRepository) {
+        repo.uris = vec![self.uri()];
+    }
+
+    /// Checks if the repository points to this snapshot directory.
+    pub fn is_snapshot_repository(&self, repo: &APTRepository) -> bool {
+        let uri = self.uri();
+        repo.uris
+            .iter()
+            .any(|repo_uri| repo_uri.trim_end_matches('/') == uri)
+    }
+}
+
+/// Checks that all package files referenced by a `Packages` index exist in the snapshot.
+fn verify_pool_files(snapshot: &Path, packages: &[u8]) -> Result<(), Error> {
+    let packages = PackagesFile::try_from(packages)?;
+
+    for entry in packages.files.iter() {
+        let path = snapshot.join(&entry.file);
+        let size = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            Err(err) => bail!("package file '{}' - {err}", entry.file),
+        };
+
+        if size != entry.size as u64 {
+            bail!(
+                "size mismatch for package file '{}': {} != {}",
+                entry.file,
+                size,
+                entry.size
+            );
+        }
+    }
+
+    Ok(())
+}
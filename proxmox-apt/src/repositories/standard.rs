@@ -1,13 +1,16 @@
 use std::fmt::Display;
+use std::sync::RwLock;
 
 use anyhow::{bail, Error};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
+use crate::repositories::release::DebianCodename;
 use crate::repositories::repository::{
-    APTRepository, APTRepositoryFileType, APTRepositoryPackageType,
+    APTRepository, APTRepositoryFileType, APTRepositoryOption, APTRepositoryPackageType,
 };
 
-use proxmox_schema::api;
+use proxmox_schema::{api, ApiType, Schema, StringSchema};
 
 #[api(
     properties: {
@@ -36,9 +39,7 @@ pub struct APTStandardRepository {
     pub description: String,
 }
 
-#[api]
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 /// Handles for Proxmox repositories.
 pub enum APTRepositoryHandle {
     /// The enterprise repository for production use.
@@ -61,35 +62,489 @@ pub enum APTRepositoryHandle {
     CephReefNoSubscription,
     /// Ceph Reef test repository.
     CephReefTest,
+    /// Ceph Squid enterprise repository.
+    CephSquidEnterprise,
+    /// Ceph Squid no-subscription repository.
+    CephSquidNoSubscription,
+    /// Ceph Squid test repository.
+    CephSquidTest,
+    /// A repository registered with [register_standard_repository], referenced by its name.
+    Custom(String),
+}
+
+impl ApiType for APTRepositoryHandle {
+    const API_SCHEMA: Schema =
+        StringSchema::new("Handle referencing a standard repository.").schema();
+}
+
+impl Serialize for APTRepositoryHandle {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for APTRepositoryHandle {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let string = String::deserialize(deserializer)?;
+        APTRepositoryHandle::try_from(string.as_str()).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Definition of a standard repository.
+///
+/// The URIs, the component, the path and the keyring may contain a `{product}` placeholder, which
+/// is replaced by the product the repository is requested for. The keyring may additionally
+/// contain a `{suite}` placeholder for the Debian release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct APTStandardRepositoryDefinition {
+    /// Handle referencing the repository.
+    pub handle: APTRepositoryHandle,
+    /// Display name of the repository.
+    pub name: String,
+    /// Description of the repository.
+    pub description: String,
+    /// Products the definition applies to, all products if empty.
+    pub products: Vec<String>,
+    /// Debian releases the repository is offered for, all releases if empty.
+    pub suites: Vec<DebianCodename>,
+    /// Package type of the repository.
+    pub package_type: APTRepositoryPackageType,
+    /// Possible URIs of the repository, the first one is the preferred one.
+    pub uris: Vec<String>,
+    /// Component of the repository.
+    pub component: String,
+    /// Deprecated components that are still recognized as this repository.
+    pub legacy_components: Vec<String>,
+    /// Standard file path for the repository.
+    pub path: String,
+    /// Path to the keyring used to sign the repository.
+    pub keyring: String,
+}
+
+impl APTStandardRepositoryDefinition {
+    fn new(
+        handle: APTRepositoryHandle,
+        name: &str,
+        description: &str,
+        uris: &[&str],
+        component: &str,
+        path: &str,
+    ) -> Self {
+        Self {
+            handle,
+            name: name.to_string(),
+            description: description.to_string(),
+            products: vec![],
+            suites: vec![],
+            package_type: APTRepositoryPackageType::Deb,
+            uris: uris.iter().map(|uri| uri.to_string()).collect(),
+            component: component.to_string(),
+            legacy_components: vec![],
+            path: path.to_string(),
+            keyring: PROXMOX_RELEASE_KEYRING.to_string(),
+        }
+    }
+
+    fn products(mut self, products: &[&str]) -> Self {
+        self.products = products.iter().map(|product| product.to_string()).collect();
+        self
+    }
+
+    fn suites(mut self, suites: &[DebianCodename]) -> Self {
+        self.suites = suites.to_vec();
+        self
+    }
+
+    fn applies_to(&self, product: &str) -> bool {
+        self.products.is_empty() || self.products.iter().any(|p| p == product)
+    }
+
+    /// Checks if the repository is offered for the product and Debian release.
+    pub fn is_offered(&self, product: &str, suite: DebianCodename) -> bool {
+        self.applies_to(product) && (self.suites.is_empty() || self.suites.contains(&suite))
+    }
+
+    /// Get the possible URIs for the product, the first one is the preferred one.
+    pub fn uris(&self, product: &str) -> Vec<String> {
+        self.uris
+            .iter()
+            .map(|uri| uri.replace("{product}", product))
+            .collect()
+    }
+
+    /// Get the component for the product.
+    pub fn component(&self, product: &str) -> String {
+        self.component.replace("{product}", product)
+    }
+
+    /// Get the standard file path for the product.
+    pub fn path(&self, product: &str) -> String {
+        self.path.replace("{product}", product)
+    }
+
+    /// Get the path to the keyring used to sign the repository for the product and Debian
+    /// release.
+    pub fn keyring(&self, product: &str, suite: &str) -> String {
+        self.keyring
+            .replace("{product}", product)
+            .replace("{suite}", suite)
+    }
+
+    /// Get the standard repository for the product, with the `Signed-By` option pointing to
+    /// its keyring.
+    pub fn to_signed_repository(&self, product: &str, suite: &str) -> APTRepository {
+        let mut repo = self.to_repository(product, suite);
+        repo.options.push(APTRepositoryOption {
+            key: "signed-by".to_string(),
+            values: vec![self.keyring(product, suite)],
+        });
+        repo
+    }
+
+    /// Get the standard repository for the product.
+    ///
+    /// An URI in the result is not '/'-terminated (under the assumption that no valid
+    /// product name is).
+    pub fn to_repository(&self, product: &str, suite: &str) -> APTRepository {
+        APTRepository {
+            types: vec![self.package_type],
+            uris: self.uris(product).into_iter().take(1).collect(),
+            suites: vec![suite.to_string()],
+            components: vec![self.component(product)],
+            options: vec![],
+            comment: String::new(),
+            file_type: APTRepositoryFileType::List,
+            enabled: true,
+        }
+    }
+}
+
+impl From<&APTStandardRepositoryDefinition> for APTStandardRepository {
+    fn from(definition: &APTStandardRepositoryDefinition) -> Self {
+        APTStandardRepository {
+            handle: definition.handle.clone(),
+            status: None,
+            name: definition.name.clone(),
+            description: definition.description.clone(),
+        }
+    }
+}
+
+/// All handles, in the order the repositories are listed.
+const HANDLES: [APTRepositoryHandle; 12] = [
+    APTRepositoryHandle::Enterprise,
+    APTRepositoryHandle::NoSubscription,
+    APTRepositoryHandle::Test,
+    APTRepositoryHandle::CephQuincyEnterprise,
+    APTRepositoryHandle::CephQuincyNoSubscription,
+    APTRepositoryHandle::CephQuincyTest,
+    APTRepositoryHandle::CephReefEnterprise,
+    APTRepositoryHandle::CephReefNoSubscription,
+    APTRepositoryHandle::CephReefTest,
+    APTRepositoryHandle::CephSquidEnterprise,
+    APTRepositoryHandle::CephSquidNoSubscription,
+    APTRepositoryHandle::CephSquidTest,
+];
+
+const ENTERPRISE_DESCRIPTION: &str = "This is the default, stable, and recommended repository, \
+    available for all Proxmox subscription users.";
+const NO_SUBSCRIPTION_DESCRIPTION: &str = "This is the recommended repository for testing and \
+    non-production use. Its packages are not as heavily tested and validated as the production \
+    ready enterprise repository. You don't need a subscription key to access this repository.";
+const TEST_DESCRIPTION: &str = "This repository contains the latest packages and is primarily \
+    used for test labs and by developers to test new features.";
+
+const ENTERPRISE_PATH: &str = "/etc/apt/sources.list.d/{product}-enterprise.list";
+const SOURCES_LIST_PATH: &str = "/etc/apt/sources.list";
+const CEPH_PATH: &str = "/etc/apt/sources.list.d/ceph.list";
+const PROXMOX_RELEASE_KEYRING: &str = "/etc/apt/trusted.gpg.d/proxmox-release-{suite}.gpg";
+
+fn ceph_definition(
+    handle: APTRepositoryHandle,
+    release: &str,
+    component: &str,
+    suites: &[DebianCodename],
+) -> APTStandardRepositoryDefinition {
+    let name = format!("Ceph {}{}", release[..1].to_uppercase(), &release[1..]);
+    let uri = match component {
+        "enterprise" => format!("https://enterprise.proxmox.com/debian/ceph-{release}"),
+        _ => format!("http://download.proxmox.com/debian/ceph-{release}"),
+    };
+    let (name, description) = match component {
+        "enterprise" => (
+            format!("{name} Enterprise"),
+            format!("This repository holds the production-ready Proxmox {name} packages."),
+        ),
+        "no-subscription" => (
+            format!("{name} No-Subscription"),
+            format!(
+                "This repository holds the Proxmox {name} packages intended for non-production \
+                use."
+            ),
+        ),
+        _ => (
+            format!("{name} Test"),
+            format!(
+                "This repository contains the {name} packages before they are moved to the main \
+                repository."
+            ),
+        ),
+    };
+
+    APTStandardRepositoryDefinition::new(handle, &name, &description, &[&uri], component, CEPH_PATH)
+        .products(&["pve"])
+        .suites(suites)
+}
+
+/// The built-in definition of `handle` that applies to all products it is offered for.
+///
+/// Custom handles have no built-in definition, so an empty one is used, which does not match any
+/// repository.
+fn builtin_definition(handle: &APTRepositoryHandle) -> APTStandardRepositoryDefinition {
+    use APTRepositoryHandle::*;
+
+    let handle = handle.clone();
+
+    let bookworm = [DebianCodename::Bookworm];
+    let bookworm_trixie = [DebianCodename::Bookworm, DebianCodename::Trixie];
+
+    match handle {
+        Enterprise => APTStandardRepositoryDefinition::new(
+            handle,
+            "Enterprise",
+            ENTERPRISE_DESCRIPTION,
+            &["https://enterprise.proxmox.com/debian/{product}"],
+            "{product}-enterprise",
+            ENTERPRISE_PATH,
+        ),
+        NoSubscription => APTStandardRepositoryDefinition::new(
+            handle,
+            "No-Subscription",
+            NO_SUBSCRIPTION_DESCRIPTION,
+            &["http://download.proxmox.com/debian/{product}"],
+            "{product}-no-subscription",
+            SOURCES_LIST_PATH,
+        ),
+        Test => APTStandardRepositoryDefinition::new(
+            handle,
+            "Test",
+            TEST_DESCRIPTION,
+            &["http://download.proxmox.com/debian/{product}"],
+            "{product}test",
+            SOURCES_LIST_PATH,
+        ),
+        CephQuincyEnterprise => ceph_definition(handle, "quincy", "enterprise", &[]),
+        CephQuincyNoSubscription => {
+            let mut definition = ceph_definition(handle, "quincy", "no-subscription", &[]);
+            definition.description.push_str(
+                " The deprecated 'main' repository is an alias for this in Proxmox VE 8.",
+            );
+            // In the past it was main instead of enterprise/no-subscription, and main now maps to
+            // no-subscription.
+            definition.legacy_components = vec!["main".to_string()];
+            definition
+        }
+        CephQuincyTest => ceph_definition(handle, "quincy", "test", &[]),
+        CephReefEnterprise => ceph_definition(handle, "reef", "enterprise", &bookworm),
+        CephReefNoSubscription => ceph_definition(handle, "reef", "no-subscription", &bookworm),
+        CephReefTest => ceph_definition(handle, "reef", "test", &bookworm),
+        CephSquidEnterprise => ceph_definition(handle, "squid", "enterprise", &bookworm_trixie),
+        CephSquidNoSubscription => {
+            ceph_definition(handle, "squid", "no-subscription", &bookworm_trixie)
+        }
+        CephSquidTest => ceph_definition(handle, "squid", "test", &bookworm_trixie),
+        Custom(ref name) => {
+            APTStandardRepositoryDefinition::new(handle.clone(), name, "", &[], "", "")
+        }
+    }
+}
+
+/// Proxmox VE still recognizes the URIs from before the per-product split.
+fn builtin_pve_definition(handle: &APTRepositoryHandle) -> Option<APTStandardRepositoryDefinition> {
+    let (uris, component): (&[&str], &str) = match handle {
+        APTRepositoryHandle::Enterprise => (
+            &[
+                "https://enterprise.proxmox.com/debian/pve",
+                "https://enterprise.proxmox.com/debian",
+            ],
+            "pve-enterprise",
+        ),
+        APTRepositoryHandle::NoSubscription => (
+            &[
+                "http://download.proxmox.com/debian/pve",
+                "http://download.proxmox.com/debian",
+            ],
+            "pve-no-subscription",
+        ),
+        APTRepositoryHandle::Test => (
+            &[
+                "http://download.proxmox.com/debian/pve",
+                "http://download.proxmox.com/debian",
+            ],
+            "pvetest",
+        ),
+        _ => return None,
+    };
+
+    let mut definition = builtin_definition(handle).products(&["pve"]);
+    definition.uris = uris.iter().map(|uri| uri.to_string()).collect();
+    definition.component = component.to_string();
+    Some(definition)
+}
+
+fn builtin_definitions() -> Vec<APTStandardRepositoryDefinition> {
+    let mut definitions = Vec::new();
+    for handle in HANDLES.iter() {
+        definitions.extend(builtin_pve_definition(handle));
+        definitions.push(builtin_definition(handle));
+    }
+    definitions
+}
+
+static STANDARD_REPOSITORIES: Lazy<RwLock<Vec<APTStandardRepositoryDefinition>>> =
+    Lazy::new(|| RwLock::new(builtin_definitions()));
+
+/// Registers a definition for a standard repository, e.g. to use a different URI for a product.
+///
+/// A definition with the same handle and products replaces the existing one, otherwise the
+/// definition is added. A definition for specific products takes precedence over one for all
+/// products with the same handle.
+///
+/// Products can add their own repositories with an [APTRepositoryHandle::Custom] handle. Those
+/// are offered after the built-in repositories, in the order they were first registered.
+pub fn register_standard_repository(
+    definition: APTStandardRepositoryDefinition,
+) -> Result<(), Error> {
+    if let APTRepositoryHandle::Custom(name) = &definition.handle {
+        check_custom_handle(name)?;
+    }
+
+    let mut definitions = STANDARD_REPOSITORIES.write().unwrap();
+    insert_definition(&mut definitions, definition);
+    Ok(())
+}
+
+fn check_custom_handle(name: &str) -> Result<(), Error> {
+    if builtin_handle(name).is_some() {
+        bail!("repository handle '{name}' is already used by a built-in repository");
+    }
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        bail!("invalid repository handle '{name}'");
+    }
+    Ok(())
+}
+
+fn insert_definition(
+    definitions: &mut Vec<APTStandardRepositoryDefinition>,
+    definition: APTStandardRepositoryDefinition,
+) {
+    match definitions
+        .iter_mut()
+        .find(|d| d.handle == definition.handle && d.products == definition.products)
+    {
+        Some(existing) => *existing = definition,
+        None => definitions.push(definition),
+    }
+}
+
+fn find_definition<'a>(
+    definitions: &'a [APTStandardRepositoryDefinition],
+    handle: &APTRepositoryHandle,
+    product: &str,
+) -> Option<&'a APTStandardRepositoryDefinition> {
+    let mut candidates = definitions
+        .iter()
+        .filter(|definition| definition.handle == *handle && definition.applies_to(product));
+
+    let first = candidates.next()?;
+    if !first.products.is_empty() {
+        return Some(first);
+    }
+
+    Some(
+        candidates
+            .find(|definition| !definition.products.is_empty())
+            .unwrap_or(first),
+    )
+}
+
+fn offered_definitions(
+    definitions: &[APTStandardRepositoryDefinition],
+    product: &str,
+    suite: DebianCodename,
+) -> Vec<APTStandardRepositoryDefinition> {
+    // built-in definitions come first, so this lists custom handles after the built-in ones
+    let mut handles: Vec<&APTRepositoryHandle> = Vec::new();
+    for definition in definitions {
+        if !handles.contains(&&definition.handle) {
+            handles.push(&definition.handle);
+        }
+    }
+
+    handles
+        .into_iter()
+        .filter_map(|handle| find_definition(definitions, handle, product))
+        .filter(|definition| definition.is_offered(product, suite))
+        .cloned()
+        .collect()
+}
+
+/// Get the definitions of all standard repositories offered for the product and Debian release.
+pub fn standard_repository_definitions(
+    product: &str,
+    suite: DebianCodename,
+) -> Vec<APTStandardRepositoryDefinition> {
+    let definitions = STANDARD_REPOSITORIES.read().unwrap();
+    offered_definitions(&definitions, product, suite)
 }
 
 impl From<APTRepositoryHandle> for APTStandardRepository {
     fn from(handle: APTRepositoryHandle) -> Self {
         APTStandardRepository {
-            handle,
             status: None,
             name: handle.name(),
             description: handle.description(),
+            handle,
         }
     }
 }
 
+fn builtin_handle(string: &str) -> Option<APTRepositoryHandle> {
+    HANDLES
+        .iter()
+        .find(|handle| handle.to_string() == string)
+        .cloned()
+}
+
 impl TryFrom<&str> for APTRepositoryHandle {
     type Error = Error;
 
+    /// Parses a built-in handle, or the handle of a registered custom repository.
     fn try_from(string: &str) -> Result<Self, Error> {
-        match string {
-            "enterprise" => Ok(APTRepositoryHandle::Enterprise),
-            "no-subscription" => Ok(APTRepositoryHandle::NoSubscription),
-            "test" => Ok(APTRepositoryHandle::Test),
-            "ceph-quincy-enterprise" => Ok(APTRepositoryHandle::CephQuincyEnterprise),
-            "ceph-quincy-no-subscription" => Ok(APTRepositoryHandle::CephQuincyNoSubscription),
-            "ceph-quincy-test" => Ok(APTRepositoryHandle::CephQuincyTest),
-            "ceph-reef-enterprise" => Ok(APTRepositoryHandle::CephReefEnterprise),
-            "ceph-reef-no-subscription" => Ok(APTRepositoryHandle::CephReefNoSubscription),
-            "ceph-reef-test" => Ok(APTRepositoryHandle::CephReefTest),
-            _ => bail!("unknown repository handle '{}'", string),
+        if let Some(handle) = builtin_handle(string) {
+            return Ok(handle);
         }
+
+        let handle = APTRepositoryHandle::Custom(string.to_string());
+        let definitions = STANDARD_REPOSITORIES.read().unwrap();
+        if definitions
+            .iter()
+            .any(|definition| definition.handle == handle)
+        {
+            return Ok(handle);
+        }
+
+        bail!("unknown repository handle '{}'", string);
     }
 }
 
@@ -107,174 +562,213 @@ impl Display for APTRepositoryHandle {
             APTRepositoryHandle::CephReefEnterprise => write!(f, "ceph-reef-enterprise"),
             APTRepositoryHandle::CephReefNoSubscription => write!(f, "ceph-reef-no-subscription"),
             APTRepositoryHandle::CephReefTest => write!(f, "ceph-reef-test"),
+            APTRepositoryHandle::CephSquidEnterprise => write!(f, "ceph-squid-enterprise"),
+            APTRepositoryHandle::CephSquidNoSubscription => {
+                write!(f, "ceph-squid-no-subscription")
+            }
+            APTRepositoryHandle::CephSquidTest => write!(f, "ceph-squid-test"),
+            APTRepositoryHandle::Custom(name) => write!(f, "{name}"),
         }
     }
 }
 
 impl APTRepositoryHandle {
+    /// Get the definition of the repository for the product.
+    ///
+    /// Falls back to the first registered definition of the handle if the repository is not
+    /// offered for the product.
+    pub fn definition(&self, product: &str) -> APTStandardRepositoryDefinition {
+        let definitions = STANDARD_REPOSITORIES.read().unwrap();
+
+        find_definition(&definitions, self, product)
+            .or_else(|| definitions.iter().find(|d| d.handle == *self))
+            .cloned()
+            .unwrap_or_else(|| builtin_definition(self))
+    }
+
     /// Get the description for the repository.
-    pub fn description(self) -> String {
-        match self {
-            APTRepositoryHandle::Enterprise => {
-                "This is the default, stable, and recommended repository, available for all \
-                Proxmox subscription users."
-            }
-            APTRepositoryHandle::NoSubscription => {
-                "This is the recommended repository for testing and non-production use. \
-                Its packages are not as heavily tested and validated as the production ready \
-                enterprise repository. You don't need a subscription key to access this repository."
-            }
-            APTRepositoryHandle::Test => {
-                "This repository contains the latest packages and is primarily used for test labs \
-                and by developers to test new features."
-            }
-            APTRepositoryHandle::CephQuincyEnterprise => {
-                "This repository holds the production-ready Proxmox Ceph Quincy packages."
-            }
-            APTRepositoryHandle::CephQuincyNoSubscription => {
-                "This repository holds the Proxmox Ceph Quincy packages intended for \
-                non-production use. The deprecated 'main' repository is an alias for this in \
-                Proxmox VE 8."
-            }
-            APTRepositoryHandle::CephQuincyTest => {
-                "This repository contains the Ceph Quincy packages before they are moved to the \
-                main repository."
-            }
-            APTRepositoryHandle::CephReefEnterprise => {
-                "This repository holds the production-ready Proxmox Ceph Reef packages."
-            }
-            APTRepositoryHandle::CephReefNoSubscription => {
-                "This repository holds the Proxmox Ceph Reef packages intended for \
-                non-production use."
-            }
-            APTRepositoryHandle::CephReefTest => {
-                "This repository contains the Ceph Reef packages before they are moved to the \
-                main repository."
-            }
-        }
-        .to_string()
+    pub fn description(&self) -> String {
+        self.definition("").description
     }
 
     /// Get the display name of the repository.
-    pub fn name(self) -> String {
-        match self {
-            APTRepositoryHandle::Enterprise => "Enterprise",
-            APTRepositoryHandle::NoSubscription => "No-Subscription",
-            APTRepositoryHandle::Test => "Test",
-            APTRepositoryHandle::CephQuincyEnterprise => "Ceph Quincy Enterprise",
-            APTRepositoryHandle::CephQuincyNoSubscription => "Ceph Quincy No-Subscription",
-            APTRepositoryHandle::CephQuincyTest => "Ceph Quincy Test",
-            APTRepositoryHandle::CephReefEnterprise => "Ceph Reef Enterprise",
-            APTRepositoryHandle::CephReefNoSubscription => "Ceph Reef No-Subscription",
-            APTRepositoryHandle::CephReefTest => "Ceph Reef Test",
-        }
-        .to_string()
+    pub fn name(&self) -> String {
+        self.definition("").name
     }
 
     /// Get the standard file path for the repository referenced by the handle.
-    pub fn path(self, product: &str) -> String {
-        match self {
-            APTRepositoryHandle::Enterprise => {
-                format!("/etc/apt/sources.list.d/{}-enterprise.list", product)
-            }
-            APTRepositoryHandle::NoSubscription => "/etc/apt/sources.list".to_string(),
-            APTRepositoryHandle::Test => "/etc/apt/sources.list".to_string(),
-            APTRepositoryHandle::CephQuincyEnterprise
-            | APTRepositoryHandle::CephQuincyNoSubscription
-            | APTRepositoryHandle::CephQuincyTest
-            | APTRepositoryHandle::CephReefEnterprise
-            | APTRepositoryHandle::CephReefNoSubscription
-            | APTRepositoryHandle::CephReefTest => "/etc/apt/sources.list.d/ceph.list".to_string(),
-        }
+    pub fn path(&self, product: &str) -> String {
+        self.definition(product).path(product)
     }
 
     /// Get package type, possible URIs and the component associated with the handle.
     ///
     /// The first URI is the preferred one.
-    pub fn info(self, product: &str) -> (APTRepositoryPackageType, Vec<String>, String) {
-        match self {
-            APTRepositoryHandle::Enterprise => (
-                APTRepositoryPackageType::Deb,
-                match product {
-                    "pve" => vec![
-                        "https://enterprise.proxmox.com/debian/pve".to_string(),
-                        "https://enterprise.proxmox.com/debian".to_string(),
-                    ],
-                    _ => vec![format!("https://enterprise.proxmox.com/debian/{}", product)],
-                },
-                format!("{}-enterprise", product),
-            ),
-            APTRepositoryHandle::NoSubscription => (
-                APTRepositoryPackageType::Deb,
-                match product {
-                    "pve" => vec![
-                        "http://download.proxmox.com/debian/pve".to_string(),
-                        "http://download.proxmox.com/debian".to_string(),
-                    ],
-                    _ => vec![format!("http://download.proxmox.com/debian/{}", product)],
-                },
-                format!("{}-no-subscription", product),
-            ),
-            APTRepositoryHandle::Test => (
-                APTRepositoryPackageType::Deb,
-                match product {
-                    "pve" => vec![
-                        "http://download.proxmox.com/debian/pve".to_string(),
-                        "http://download.proxmox.com/debian".to_string(),
-                    ],
-                    _ => vec![format!("http://download.proxmox.com/debian/{}", product)],
-                },
-                format!("{}test", product),
-            ),
-            APTRepositoryHandle::CephQuincyEnterprise => (
-                APTRepositoryPackageType::Deb,
-                vec!["https://enterprise.proxmox.com/debian/ceph-quincy".to_string()],
-                "enterprise".to_string(),
-            ),
-            APTRepositoryHandle::CephQuincyNoSubscription => (
-                APTRepositoryPackageType::Deb,
-                vec!["http://download.proxmox.com/debian/ceph-quincy".to_string()],
-                "no-subscription".to_string(),
-            ),
-            APTRepositoryHandle::CephQuincyTest => (
-                APTRepositoryPackageType::Deb,
-                vec!["http://download.proxmox.com/debian/ceph-quincy".to_string()],
-                "test".to_string(),
-            ),
-            APTRepositoryHandle::CephReefEnterprise => (
-                APTRepositoryPackageType::Deb,
-                vec!["https://enterprise.proxmox.com/debian/ceph-reef".to_string()],
-                "enterprise".to_string(),
-            ),
-            APTRepositoryHandle::CephReefNoSubscription => (
-                APTRepositoryPackageType::Deb,
-                vec!["http://download.proxmox.com/debian/ceph-reef".to_string()],
-                "no-subscription".to_string(),
-            ),
-            APTRepositoryHandle::CephReefTest => (
-                APTRepositoryPackageType::Deb,
-                vec!["http://download.proxmox.com/debian/ceph-reef".to_string()],
-                "test".to_string(),
-            ),
-        }
+    pub fn info(&self, product: &str) -> (APTRepositoryPackageType, Vec<String>, String) {
+        let definition = self.definition(product);
+
+        (
+            definition.package_type,
+            definition.uris(product),
+            definition.component(product),
+        )
+    }
+
+    /// Get the path to the keyring used to sign the repository for the product and Debian
+    /// release.
+    pub fn keyring(&self, product: &str, suite: &str) -> String {
+        self.definition(product).keyring(product, suite)
+    }
+
+    /// Get the standard repository referenced by the handle, with the `Signed-By` option
+    /// pointing to its keyring.
+    pub fn to_signed_repository(&self, product: &str, suite: &str) -> APTRepository {
+        self.definition(product)
+            .to_signed_repository(product, suite)
     }
 
     /// Get the standard repository referenced by the handle.
     ///
     /// An URI in the result is not '/'-terminated (under the assumption that no valid
     /// product name is).
-    pub fn to_repository(self, product: &str, suite: &str) -> APTRepository {
-        let (package_type, uris, component) = self.info(product);
+    pub fn to_repository(&self, product: &str, suite: &str) -> APTRepository {
+        self.definition(product).to_repository(product, suite)
+    }
+}
 
-        APTRepository {
-            types: vec![package_type],
-            uris: vec![uris.into_iter().next().unwrap()],
-            suites: vec![suite.to_string()],
-            components: vec![component],
-            options: vec![],
-            comment: String::new(),
-            file_type: APTRepositoryFileType::List,
-            enabled: true,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_definition() {
+        let mut definitions = builtin_definitions();
+
+        // every handle has a definition that applies to all products it is offered for
+        for handle in HANDLES.iter() {
+            assert!(definitions
+                .iter()
+                .any(|d| d.handle == *handle && d == &builtin_definition(handle)));
         }
+
+        let pbs_offered = offered_definitions(&definitions, "pbs", DebianCodename::Bookworm);
+        assert_eq!(pbs_offered.len(), 3);
+
+        let mut definition = builtin_definition(&APTRepositoryHandle::NoSubscription);
+        definition.products = vec!["pbs".to_string()];
+        definition.uris = vec!["http://mirror.example.com/{product}".to_string()];
+        insert_definition(&mut definitions, definition.clone());
+
+        let found = find_definition(&definitions, &APTRepositoryHandle::NoSubscription, "pbs");
+        assert_eq!(found, Some(&definition));
+        let repo = definition.to_repository("pbs", "bookworm");
+        assert_eq!(repo.uris, ["http://mirror.example.com/pbs"]);
+        assert_eq!(repo.components, ["pbs-no-subscription"]);
+
+        // other products keep the built-in definition, and the order stays the same
+        let found = find_definition(&definitions, &APTRepositoryHandle::NoSubscription, "pmg");
+        assert_eq!(
+            found.unwrap().uris("pmg"),
+            ["http://download.proxmox.com/debian/pmg"]
+        );
+        let offered = offered_definitions(&definitions, "pbs", DebianCodename::Bookworm);
+        assert_eq!(offered[1], definition);
+        assert_eq!(offered.len(), pbs_offered.len());
+
+        // registering again replaces the definition
+        definition.uris = vec!["http://other.example.com/{product}".to_string()];
+        insert_definition(&mut definitions, definition.clone());
+        let found = find_definition(&definitions, &APTRepositoryHandle::NoSubscription, "pbs");
+        assert_eq!(found, Some(&definition));
+
+        // products-specific definitions take precedence, even if registered later
+        let found = find_definition(&definitions, &APTRepositoryHandle::Test, "pve");
+        assert_eq!(found.unwrap().component("pve"), "pvetest");
+
+        let mut definition = builtin_definition(&APTRepositoryHandle::CephSquidTest);
+        definition.products = vec!["pbs".to_string()];
+        insert_definition(&mut definitions, definition);
+        let offered = offered_definitions(&definitions, "pbs", DebianCodename::Trixie);
+        assert_eq!(offered.len(), 4);
+        assert_eq!(offered[3].handle, APTRepositoryHandle::CephSquidTest);
+        assert!(
+            offered_definitions(&definitions, "pbs", DebianCodename::Bullseye)
+                .iter()
+                .all(|d| d.handle != APTRepositoryHandle::CephSquidTest)
+        );
+    }
+
+    #[test]
+    fn test_register_custom_handle() -> Result<(), Error> {
+        let handle = APTRepositoryHandle::Custom("custom-extras".to_string());
+        assert!(APTRepositoryHandle::try_from("custom-extras").is_err());
+
+        let definition = APTStandardRepositoryDefinition::new(
+            handle.clone(),
+            "Extras",
+            "Additional packages.",
+            &["http://download.example.com/{product}-extras"],
+            "main",
+            "/etc/apt/sources.list.d/{product}-extras.list",
+        )
+        .products(&["custom"]);
+        register_standard_repository(definition.clone())?;
+
+        let offered = standard_repository_definitions("custom", DebianCodename::Bookworm);
+        assert_eq!(offered.last(), Some(&definition));
+        assert!(
+            standard_repository_definitions("pve", DebianCodename::Bookworm)
+                .iter()
+                .all(|d| d.handle != handle)
+        );
+
+        assert_eq!(APTRepositoryHandle::try_from("custom-extras")?, handle);
+        assert_eq!(serde_json::to_string(&handle)?, r#""custom-extras""#);
+        let parsed: APTRepositoryHandle = serde_json::from_str(r#""custom-extras""#)?;
+        assert_eq!(parsed, handle);
+        assert_eq!(handle.name(), "Extras");
+
+        let repo = handle.to_repository("custom", "bookworm");
+        assert_eq!(repo.uris, ["http://download.example.com/custom-extras"]);
+        assert!(repo.is_referenced_repository(handle.clone(), "custom", "bookworm"));
+        assert_eq!(
+            handle.path("custom"),
+            "/etc/apt/sources.list.d/custom-extras.list"
+        );
+
+        let mut builtin = definition.clone();
+        builtin.handle = APTRepositoryHandle::Custom("enterprise".to_string());
+        assert!(register_standard_repository(builtin).is_err());
+        let mut invalid = definition;
+        invalid.handle = APTRepositoryHandle::Custom("Invalid Name".to_string());
+        assert!(register_standard_repository(invalid).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_definition_keyring() {
+        use crate::repositories::repository::APTRepositorySignedBy;
+
+        let mut definition = builtin_definition(&APTRepositoryHandle::Enterprise);
+        assert_eq!(
+            definition.keyring("pbs", "trixie"),
+            "/etc/apt/trusted.gpg.d/proxmox-release-trixie.gpg"
+        );
+
+        definition.keyring = "/usr/share/keyrings/{product}-{suite}.gpg".to_string();
+        assert_eq!(
+            definition.keyring("pbs", "trixie"),
+            "/usr/share/keyrings/pbs-trixie.gpg"
+        );
+
+        let repo = definition.to_signed_repository("pbs", "trixie");
+        assert_eq!(repo.uris, ["https://enterprise.proxmox.com/debian/pbs"]);
+        assert_eq!(
+            repo.signed_by(),
+            Some(APTRepositorySignedBy::Keyrings(vec![
+                "/usr/share/keyrings/pbs-trixie.gpg".to_string()
+            ]))
+        );
     }
 }
@@ -0,0 +1,223 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::format_err;
+
+use crate::repositories::file::{APTRepositoryFile, APTRepositoryFileError};
+
+/// A staged file, ready to be written.
+struct PreparedFile {
+    path: PathBuf,
+    /// New content, or `None` if the file is to be removed.
+    content: Option<Vec<u8>>,
+    /// Content on disk at the time of preparation, or `None` if the file did not exist.
+    backup: Option<Vec<u8>>,
+    tmp_path: Option<PathBuf>,
+}
+
+/// Stages modifications to several repository files and writes them all at once.
+///
+/// All files are checked before anything is written, i.e. their repositories must pass the
+/// basic checks and their digests, if set, must match the current content on disk. The new
+/// contents are first written to temporary files, which are then renamed over the originals.
+/// If a rename fails, already replaced files are restored to their original content.
+///
+/// Like with [`APTRepositoryFile::write`], files without repositories are removed.
+#[derive(Default)]
+pub struct RepositoryTransaction {
+    files: Vec<APTRepositoryFile>,
+}
+
+impl RepositoryTransaction {
+    /// Creates a new, empty transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stages `file` for writing, replacing a previously staged file with the same path.
+    pub fn stage(&mut self, file: APTRepositoryFile) -> Result<(), APTRepositoryFileError> {
+        let path = match &file.path {
+            Some(path) => path,
+            None => {
+                return Err(file.err(format_err!(
+                    "Cannot write to APT repository file without path."
+                )));
+            }
+        };
+
+        match self
+            .files
+            .iter_mut()
+            .find(|staged| staged.path.as_ref() == Some(path))
+        {
+            Some(staged) => *staged = file,
+            None => self.files.push(file),
+        }
+
+        Ok(())
+    }
+
+    /// Returns the currently staged files.
+    pub fn staged(&self) -> &[APTRepositoryFile] {
+        &self.files
+    }
+
+    /// Checks and writes all staged files.
+    ///
+    /// On error, the files on disk are either all unchanged or, if rolling back failed, the
+    /// error mentions the files that could not be restored.
+    pub fn commit(self) -> Result<(), APTRepositoryFileError> {
+        let mut prepared = self
+            .files
+            .iter()
+            .map(prepare)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pid = std::process::id();
+
+        let written = prepared
+            .iter_mut()
+            .zip(self.files.iter())
+            .try_for_each(|(prepared, file)| write_tmp_file(prepared, file, pid));
+
+        if let Err(err) = written {
+            cleanup_tmp_files(&prepared);
+            return Err(err);
+        }
+
+        for (n, (prepared_file, file)) in prepared.iter().zip(self.files.iter()).enumerate() {
+            if let Err(err) = replace_file(prepared_file, file) {
+                cleanup_tmp_files(&prepared[n..]);
+
+                let failed: Vec<String> = prepared[..n]
+                    .iter()
+                    .filter(|file| restore_file(file, pid).is_err())
+                    .map(|file| file.path.display().to_string())
+                    .collect();
+
+                if failed.is_empty() {
+                    return Err(err);
+                }
+
+                return Err(APTRepositoryFileError {
+                    path: err.path,
+                    error: format!("{} - rollback failed for {}", err.error, failed.join(", ")),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the digest and repositories of `file` and renders its new content.
+fn prepare(file: &APTRepositoryFile) -> Result<PreparedFile, APTRepositoryFileError> {
+    let path = PathBuf::from(file.path.as_deref().unwrap_or_default());
+
+    let backup = if path.exists() {
+        if !path.is_file() {
+            return Err(file.err(format_err!("not a regular file")));
+        }
+
+        let (content, digest) = file.read_with_digest()?;
+        if let Some(expected) = file.digest {
+            if expected != digest {
+                return Err(file.err(format_err!("digest mismatch")));
+            }
+        }
+        Some(content)
+    } else {
+        if file.digest.is_some() {
+            return Err(file.err(format_err!("digest specified, but file does not exist")));
+        }
+        None
+    };
+
+    let content = match file.repositories.is_empty() {
+        true => None,
+        false => Some(file.render()?),
+    };
+
+    if path.parent().is_none() {
+        return Err(file.err(format_err!("invalid path")));
+    }
+
+    Ok(PreparedFile {
+        path,
+        content,
+        backup,
+        tmp_path: None,
+    })
+}
+
+/// Temporary path next to `path`, keeping the full file name to avoid clashes between staged
+/// `.list` and `.sources` files with the same stem.
+fn tmp_path_for(path: &Path, pid: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{pid}"));
+    path.with_file_name(name)
+}
+
+fn write_tmp_file(
+    prepared: &mut PreparedFile,
+    file: &APTRepositoryFile,
+    pid: u32,
+) -> Result<(), APTRepositoryFileError> {
+    let content = match &prepared.content {
+        Some(content) => content,
+        None => return Ok(()),
+    };
+
+    if let Some(dir) = prepared.path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| file.err(format_err!("unable to create parent dir - {err}")))?;
+    }
+
+    let tmp_path = tmp_path_for(&prepared.path, pid);
+
+    if let Err(err) = std::fs::write(&tmp_path, content) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(file.err(format_err!("writing {tmp_path:?} failed - {err}")));
+    }
+
+    prepared.tmp_path = Some(tmp_path);
+
+    Ok(())
+}
+
+fn replace_file(
+    prepared: &PreparedFile,
+    file: &APTRepositoryFile,
+) -> Result<(), APTRepositoryFileError> {
+    match &prepared.tmp_path {
+        Some(tmp_path) => std::fs::rename(tmp_path, &prepared.path)
+            .map_err(|err| file.err(format_err!("rename failed for {:?} - {err}", prepared.path))),
+        None if prepared.backup.is_none() => Ok(()), // nothing to remove
+        None => std::fs::remove_file(&prepared.path)
+            .map_err(|err| file.err(format_err!("unable to remove file - {err}"))),
+    }
+}
+
+/// Restores the content a file had before the transaction.
+fn restore_file(prepared: &PreparedFile, pid: u32) -> Result<(), std::io::Error> {
+    match &prepared.backup {
+        Some(backup) => {
+            let tmp_path = tmp_path_for(&prepared.path, pid);
+            let result = std::fs::write(&tmp_path, backup)
+                .and_then(|()| std::fs::rename(&tmp_path, &prepared.path));
+            if result.is_err() {
+                let _ = std::fs::remove_file(&tmp_path);
+            }
+            result
+        }
+        None => match std::fs::remove_file(&prepared.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        },
+    }
+}
+
+fn cleanup_tmp_files(prepared: &[PreparedFile]) {
+    for tmp_path in prepared.iter().filter_map(|file| file.tmp_path.as_ref()) {
+        let _ = std::fs::remove_file(tmp_path);
+    }
+}
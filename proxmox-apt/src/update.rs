@@ -0,0 +1,382 @@
+//! Updating the package indices via `apt-get update`.
+//!
+//! The output of `apt-get` is parsed into [`APTUpdateEvent`]s, which are passed to a callback as
+//! they arrive, e.g. to log them in a worker task. The progress is taken from the status lines
+//! APT writes when `APT::Status-Fd` is set.
+
+use std::process::Stdio;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+/// An event reported while updating the package indices.
+pub enum APTUpdateEvent {
+    /// Overall download progress.
+    Progress {
+        /// Progress in percent.
+        percent: f64,
+        /// Status message, e.g. `Retrieving file 2 of 5`.
+        message: String,
+    },
+    /// The index file is up to date.
+    Hit {
+        /// Number of the item as reported by APT.
+        id: u64,
+        /// URI, suite and file of the item.
+        description: String,
+    },
+    /// The index file is downloaded.
+    Get {
+        /// Number of the item as reported by APT.
+        id: u64,
+        /// URI, suite and file of the item.
+        description: String,
+        /// Download size, e.g. `55.4 kB`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<String>,
+    },
+    /// The index file is not available, but this is not an error, e.g. for optional files.
+    Ignored {
+        /// Number of the item as reported by APT.
+        id: u64,
+        /// URI, suite and file of the item.
+        description: String,
+    },
+    /// Downloading the index file failed.
+    Failed {
+        /// Number of the item as reported by APT.
+        id: u64,
+        /// URI, suite and file of the item.
+        description: String,
+        /// Reason for the failure, e.g. `404  Not Found`.
+        reason: String,
+    },
+    /// A notice (`N:`) from APT.
+    Notice {
+        /// The message.
+        message: String,
+    },
+    /// A warning (`W:`) from APT.
+    Warning {
+        /// The message.
+        message: String,
+    },
+    /// An error (`E:`) from APT.
+    Error {
+        /// The message.
+        message: String,
+    },
+    /// Any other output line.
+    Output {
+        /// The line.
+        line: String,
+    },
+}
+
+/// Parser for the output of `apt-get update`, one line at a time.
+///
+/// Failures are followed by indented lines with the reason, so their events are only emitted
+/// once the next unindented line or the end of the output is seen.
+#[derive(Default)]
+pub struct APTUpdateOutputParser {
+    failed: Option<(u64, String, Vec<String>)>,
+}
+
+impl APTUpdateOutputParser {
+    /// Creates a new parser.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses `line` and returns the events it completes.
+    pub fn feed(&mut self, line: &str) -> Vec<APTUpdateEvent> {
+        if line.starts_with(' ') {
+            if let Some((_, _, reason)) = self.failed.as_mut() {
+                reason.push(line.trim().to_string());
+                return vec![];
+            }
+        }
+
+        let mut events: Vec<APTUpdateEvent> = self.finish().into_iter().collect();
+
+        if let Some(event) = self.parse_line(line.trim_end()) {
+            events.push(event);
+        }
+
+        events
+    }
+
+    /// Returns the pending event, if any, at the end of the output.
+    pub fn finish(&mut self) -> Option<APTUpdateEvent> {
+        self.failed
+            .take()
+            .map(|(id, description, reason)| APTUpdateEvent::Failed {
+                id,
+                description,
+                reason: reason.join(" "),
+            })
+    }
+
+    fn parse_line(&mut self, line: &str) -> Option<APTUpdateEvent> {
+        if line.is_empty() {
+            return None;
+        }
+
+        if let Some(status) = line.strip_prefix("dlstatus:") {
+            // dlstatus:<item>:<percent>:<message>
+            let mut parts = status.splitn(3, ':');
+            let _item = parts.next();
+            if let (Some(Ok(percent)), Some(message)) =
+                (parts.next().map(str::parse::<f64>), parts.next())
+            {
+                return Some(APTUpdateEvent::Progress {
+                    percent,
+                    message: message.to_string(),
+                });
+            }
+        }
+
+        for (prefix, constructor) in [
+            ("N: ", notice as fn(String) -> APTUpdateEvent),
+            ("W: ", warning),
+            ("E: ", error),
+        ] {
+            if let Some(message) = line.strip_prefix(prefix) {
+                return Some(constructor(message.to_string()));
+            }
+        }
+
+        if let Some((kind, rest)) = line.split_once(':') {
+            if let Some((id, description)) = rest.split_once(' ') {
+                if let Ok(id) = id.parse::<u64>() {
+                    let description = description.to_string();
+                    match kind {
+                        "Hit" => return Some(APTUpdateEvent::Hit { id, description }),
+                        "Ign" => return Some(APTUpdateEvent::Ignored { id, description }),
+                        "Get" => return Some(get_event(id, description)),
+                        "Err" => {
+                            self.failed = Some((id, description, vec![]));
+                            return None;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        Some(APTUpdateEvent::Output {
+            line: line.to_string(),
+        })
+    }
+}
+
+fn notice(message: String) -> APTUpdateEvent {
+    APTUpdateEvent::Notice { message }
+}
+
+fn warning(message: String) -> APTUpdateEvent {
+    APTUpdateEvent::Warning { message }
+}
+
+fn error(message: String) -> APTUpdateEvent {
+    APTUpdateEvent::Error { message }
+}
+
+/// Splits off the size APT appends in brackets, e.g. `[55.4 kB]`.
+fn get_event(id: u64, description: String) -> APTUpdateEvent {
+    if let Some(stripped) = description.strip_suffix(']') {
+        if let Some((description, size)) = stripped.rsplit_once(" [") {
+            return APTUpdateEvent::Get {
+                id,
+                description: description.to_string(),
+                size: Some(size.to_string()),
+            };
+        }
+    }
+
+    APTUpdateEvent::Get {
+        id,
+        description,
+        size: None,
+    }
+}
+
+/// Runs `apt-get update` and reports the parsed output to `callback`.
+///
+/// Fails if `apt-get` exits with an error, the error includes the messages APT reported with
+/// `E:`. Failed downloads of single index files are only reported as events, like APT itself
+/// does not consider them fatal.
+pub async fn update_index<F>(callback: F) -> Result<(), Error>
+where
+    F: FnMut(APTUpdateEvent),
+{
+    let mut command = Command::new("apt-get");
+    command.args(["update", "-o", "APT::Status-Fd=1"]);
+    command.env("DEBIAN_FRONTEND", "noninteractive");
+    command.env("LC_ALL", "C");
+
+    run_update(command, callback).await
+}
+
+async fn run_update<F>(mut command: Command, mut callback: F) -> Result<(), Error>
+where
+    F: FnMut(APTUpdateEvent),
+{
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = command
+        .spawn()
+        .map_err(|err| format_err!("unable to run apt-get update - {err}"))?;
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+
+    let mut stdout_parser = APTUpdateOutputParser::new();
+    let mut stderr_parser = APTUpdateOutputParser::new();
+
+    let mut errors = vec![];
+    let mut handle_event = |event: APTUpdateEvent| {
+        if let APTUpdateEvent::Error { message } = &event {
+            errors.push(message.clone());
+        }
+        callback(event);
+    };
+
+    let (mut stdout_done, mut stderr_done) = (false, false);
+
+    while !(stdout_done && stderr_done) {
+        let (parser, line, done) = tokio::select! {
+            line = stdout.next_line(), if !stdout_done => {
+                (&mut stdout_parser, line, &mut stdout_done)
+            }
+            line = stderr.next_line(), if !stderr_done => {
+                (&mut stderr_parser, line, &mut stderr_done)
+            }
+        };
+
+        match line.map_err(|err| format_err!("unable to read apt-get output - {err}"))? {
+            Some(line) => parser.feed(&line).into_iter().for_each(&mut handle_event),
+            None => {
+                *done = true;
+                parser.finish().into_iter().for_each(&mut handle_event);
+            }
+        }
+    }
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|err| format_err!("unable to wait for apt-get update - {err}"))?;
+
+    if !status.success() {
+        match errors.is_empty() {
+            true => bail!("apt-get update failed - {status}"),
+            false => bail!("apt-get update failed - {}", errors.join(", ")),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_update_output() {
+    let output = "\
+dlstatus:1:0.0000:Retrieving file 1 of 3
+Hit:1 http://deb.debian.org/debian bookworm InRelease
+Get:2 http://security.debian.org bookworm-security InRelease [48.0 kB]
+Ign:3 http://mirror.local/debian bookworm InRelease
+Err:4 https://enterprise.proxmox.com/debian/pve bookworm InRelease
+  401  Unauthorized [IP: 198.51.100.1 443]
+dlstatus:4:100.0000:Retrieving file 3 of 3
+Reading package lists...
+W: Target Packages is configured multiple times
+E: Failed to fetch https://enterprise.proxmox.com/debian/pve/dists/bookworm/InRelease  401  Unauthorized [IP: 198.51.100.1 443]
+Err:5 http://mirror.local/debian bookworm Release
+  404  Not Found";
+
+    let mut parser = APTUpdateOutputParser::new();
+    let mut events: Vec<APTUpdateEvent> = output.lines().flat_map(|l| parser.feed(l)).collect();
+    events.extend(parser.finish());
+
+    assert_eq!(
+        events,
+        vec![
+            APTUpdateEvent::Progress {
+                percent: 0.0,
+                message: "Retrieving file 1 of 3".to_string(),
+            },
+            APTUpdateEvent::Hit {
+                id: 1,
+                description: "http://deb.debian.org/debian bookworm InRelease".to_string(),
+            },
+            APTUpdateEvent::Get {
+                id: 2,
+                description: "http://security.debian.org bookworm-security InRelease".to_string(),
+                size: Some("48.0 kB".to_string()),
+            },
+            APTUpdateEvent::Ignored {
+                id: 3,
+                description: "http://mirror.local/debian bookworm InRelease".to_string(),
+            },
+            APTUpdateEvent::Failed {
+                id: 4,
+                description: "https://enterprise.proxmox.com/debian/pve bookworm InRelease"
+                    .to_string(),
+                reason: "401  Unauthorized [IP: 198.51.100.1 443]".to_string(),
+            },
+            APTUpdateEvent::Progress {
+                percent: 100.0,
+                message: "Retrieving file 3 of 3".to_string(),
+            },
+            APTUpdateEvent::Output {
+                line: "Reading package lists...".to_string(),
+            },
+            APTUpdateEvent::Warning {
+                message: "Target Packages is configured multiple times".to_string(),
+            },
+            APTUpdateEvent::Error {
+                message: "Failed to fetch https://enterprise.proxmox.com/debian/pve/dists/bookworm/InRelease  401  Unauthorized [IP: 198.51.100.1 443]".to_string(),
+            },
+            APTUpdateEvent::Failed {
+                id: 5,
+                description: "http://mirror.local/debian bookworm Release".to_string(),
+                reason: "404  Not Found".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_run_update() -> Result<(), Error> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()?;
+
+    let script = "echo 'Hit:1 http://deb.debian.org/debian bookworm InRelease'; \
+        echo 'E: The repository is not signed.' >&2; \
+        exit 100";
+
+    let mut command = Command::new("sh");
+    command.args(["-c", script]);
+
+    let mut events = vec![];
+    let result = rt.block_on(run_update(command, |event| events.push(event)));
+
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "apt-get update failed - The repository is not signed.",
+    );
+    assert_eq!(events.len(), 2);
+    assert!(events.contains(&APTUpdateEvent::Error {
+        message: "The repository is not signed.".to_string(),
+    }));
+
+    Ok(())
+}
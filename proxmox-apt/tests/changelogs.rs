@@ -0,0 +1,101 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{bail, Error};
+use http::{Request, Response};
+
+use proxmox_apt::changelogs::{changelog_url, fetch_with_cache};
+use proxmox_apt::config::APTConfig;
+use proxmox_apt::packages::APTPackageCache;
+use proxmox_apt::repositories::APTRepositoryFile;
+use proxmox_http::HttpClient;
+
+/// Client answering every GET request with a fake changelog, recording the requested URIs.
+#[derive(Default)]
+struct TestClient {
+    requests: RefCell<Vec<String>>,
+}
+
+impl HttpClient<String, String> for TestClient {
+    fn get(
+        &self,
+        uri: &str,
+        _extra_headers: Option<&HashMap<String, String>>,
+    ) -> Result<Response<String>, Error> {
+        self.requests.borrow_mut().push(uri.to_string());
+        Ok(Response::new(format!("changelog from {uri}")))
+    }
+
+    fn post(
+        &self,
+        _uri: &str,
+        _body: Option<String>,
+        _content_type: Option<&str>,
+        _extra_headers: Option<&HashMap<String, String>>,
+    ) -> Result<Response<String>, Error> {
+        bail!("not supported");
+    }
+
+    fn request(&self, _request: Request<String>) -> Result<Response<String>, Error> {
+        bail!("not supported");
+    }
+}
+
+#[test]
+fn test_changelogs() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR").to_string());
+    let read_dir = test_dir.join("sources.list.d");
+    let status_path = test_dir.join("dpkg").join("status");
+    let cache_dir = tmp_dir.join("changelogs");
+
+    proxmox_apt::config::init(APTConfig::new(
+        Some(&test_dir.clone().into_os_string().into_string().unwrap()),
+        None,
+    ));
+
+    let mut file = APTRepositoryFile::new(read_dir.join("pve.list"))?.unwrap();
+    file.parse()?;
+    let cache = APTPackageCache::load_with_status(&[file], vec![], &status_path)?;
+
+    let urls: Vec<String> = cache
+        .available_entries("zfsutils-linux")
+        .into_iter()
+        .map(|(entry, index)| changelog_url(entry, index))
+        .collect::<Result<_, Error>>()?;
+    assert_eq!(
+        urls,
+        [
+            "https://metadata.ftp-master.debian.org/changelogs/contrib/z/zfs-linux/zfs-linux_2.0.3-9_changelog",
+            "http://download.proxmox.com/debian/pve/dists/bullseye/pve-no-subscription/binary-amd64/zfsutils-linux_2.0.5-pve1.changelog",
+        ],
+    );
+
+    match std::fs::remove_dir_all(&cache_dir) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+        _ => (),
+    }
+
+    let client = TestClient::default();
+
+    let changelog = fetch_with_cache(&client, &cache, "pve-manager", None, Some(&cache_dir))?;
+    assert_eq!(
+        changelog,
+        "changelog from http://download.proxmox.com/debian/pve/dists/bullseye/pve-no-subscription/binary-amd64/pve-manager_7.0-10.changelog",
+    );
+    assert!(cache_dir.join("pve-manager_7.0-10.changelog").exists());
+
+    // served from the cache
+    let cached = fetch_with_cache(&client, &cache, "pve-manager", None, Some(&cache_dir))?;
+    assert_eq!(cached, changelog);
+    assert_eq!(client.requests.borrow().len(), 1);
+
+    fetch_with_cache(&client, &cache, "pve-manager", Some("7.0-9"), None)?;
+    assert_eq!(client.requests.borrow().len(), 2);
+
+    assert!(fetch_with_cache(&client, &cache, "pve-manager", Some("6.4-1"), None).is_err());
+    assert!(fetch_with_cache(&client, &cache, "does-not-exist", None, None).is_err());
+
+    Ok(())
+}
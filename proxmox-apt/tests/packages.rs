@@ -0,0 +1,92 @@
+use anyhow::Error;
+
+use proxmox_apt::config::APTConfig;
+use proxmox_apt::packages::{installed_packages, APTPackageCache};
+use proxmox_apt::preferences::APTPin;
+use proxmox_apt::repositories::APTRepositoryFile;
+
+#[test]
+fn test_package_policy() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let read_dir = test_dir.join("sources.list.d");
+    let status_path = test_dir.join("dpkg").join("status");
+
+    proxmox_apt::config::init(APTConfig::new(
+        Some(&test_dir.clone().into_os_string().into_string().unwrap()),
+        None,
+    ));
+
+    let installed = installed_packages(&status_path)?;
+    let names: Vec<&str> = installed
+        .iter()
+        .map(|entry| entry.package.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        ["bash", "pve-manager", "zfsutils-linux", "local-tool"]
+    );
+
+    let mut file = APTRepositoryFile::new(read_dir.join("pve.list"))?.unwrap();
+    file.parse()?;
+    let files = [file];
+
+    let cache = APTPackageCache::load_with_status(&files, vec![], &status_path)?;
+
+    assert_eq!(cache.installed_version("pve-manager"), Some("7.0-9"));
+    assert_eq!(cache.candidate_version("pve-manager"), Some("7.0-10"));
+    assert_eq!(cache.available_versions("pve-manager").len(), 2);
+
+    let policy = cache.policy("pve-manager").unwrap();
+    assert_eq!(policy.origin.as_deref(), Some("Proxmox"));
+    assert_eq!(
+        policy.uri.as_deref(),
+        Some("http://download.proxmox.com/debian/pve")
+    );
+    assert_eq!(policy.suite.as_deref(), Some("bullseye"));
+    assert_eq!(policy.component.as_deref(), Some("pve-no-subscription"));
+    assert_eq!(policy.priority, Some(500));
+
+    // not installed, but available
+    let policy = cache.policy("proxmox-ve").unwrap();
+    assert_eq!(policy.installed, None);
+    assert_eq!(policy.candidate.as_deref(), Some("7.0-2"));
+
+    // higher version from the Proxmox repository wins
+    let policy = cache.policy("zfsutils-linux").unwrap();
+    assert_eq!(policy.candidate.as_deref(), Some("2.0.5-pve1"));
+    assert_eq!(policy.origin.as_deref(), Some("Proxmox"));
+
+    // only installed locally
+    let policy = cache.policy("local-tool").unwrap();
+    assert_eq!(policy.candidate.as_deref(), Some("1.0"));
+    assert_eq!(policy.priority, Some(100));
+    assert_eq!(policy.uri, None);
+
+    assert!(cache.policy("removed-package").is_none());
+    assert!(cache.policy("does-not-exist").is_none());
+
+    // pinning the Debian version allows downgrading
+    let pins = vec![APTPin::with_release_properties(
+        vec!["zfsutils-linux".to_string()],
+        &[("o", "Debian")],
+        1001,
+    )];
+    let cache = APTPackageCache::load_with_status(&files, pins, &status_path)?;
+
+    let policy = cache.policy("zfsutils-linux").unwrap();
+    assert_eq!(policy.candidate.as_deref(), Some("2.0.3-9"));
+    assert_eq!(policy.origin.as_deref(), Some("Debian"));
+    assert_eq!(policy.priority, Some(1001));
+
+    // a lower priority does not downgrade
+    let pins = vec![APTPin::with_release_properties(
+        vec!["pve-manager".to_string()],
+        &[("o", "Proxmox")],
+        -1,
+    )];
+    let cache = APTPackageCache::load_with_status(&files, pins, &status_path)?;
+
+    assert_eq!(cache.candidate_version("pve-manager"), Some("7.0-9"));
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use anyhow::Error;
+
+use proxmox_apt::deb822::ReleaseFile;
+use proxmox_apt::preferences::{APTPin, APTPinType, APTPreferencesFile};
+
+#[test]
+fn test_parse_write_preferences() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR").to_string());
+    let read_path = test_dir.join("preferences.d").join("proxmox.pref");
+    let write_path = tmp_dir.join("proxmox.pref");
+
+    let mut file = APTPreferencesFile::new(&read_path)?.unwrap();
+    file.parse()?;
+
+    assert_eq!(file.pins.len(), 3);
+
+    let kernel_pin = &file.pins[0];
+    assert_eq!(
+        kernel_pin.packages,
+        vec!["proxmox-kernel-6.8*", "proxmox-headers-6.8*"]
+    );
+    assert_eq!(kernel_pin.pin_type, APTPinType::Release);
+    assert_eq!(kernel_pin.priority, 1001);
+    assert_eq!(
+        kernel_pin.comment,
+        " prefer the kernel from the test repository\n"
+    );
+    assert_eq!(
+        kernel_pin.release_properties()?,
+        vec![
+            ("o".to_string(), "Proxmox".to_string()),
+            ("n".to_string(), "bookworm".to_string()),
+            ("c".to_string(), "pvetest".to_string()),
+        ]
+    );
+
+    // the commented out stanza at the end is kept
+    assert_eq!(
+        file.trailing_comment,
+        " Package: pve-manager\n Pin: version 8.1.*\n Pin-Priority: 1001\n"
+    );
+
+    assert_eq!(file.pins[1].pin_type, APTPinType::Origin);
+    assert_eq!(file.pins[2].pin_type, APTPinType::Version);
+    assert_eq!(file.pins[2].priority, -1);
+    assert!(file.pins[2].release_properties().is_err());
+
+    let matching: Vec<&APTPin> = file
+        .pins_for_package("proxmox-kernel-6.8.4-2-pve")
+        .collect();
+    assert_eq!(matching.len(), 2);
+    assert_eq!(file.pins_for_package("ceph-common").count(), 2);
+
+    let _ = std::fs::remove_file(&write_path);
+    file.path = Some(write_path.clone().into_os_string().into_string().unwrap());
+    file.digest = None;
+    file.write()?;
+
+    assert_eq!(std::fs::read(&read_path)?, std::fs::read(&write_path)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_preferences_content() -> Result<(), Error> {
+    let content = "Package: *\nPin: release o=Proxmox\nPin-Priority: 500\n\n# last comment\n";
+    let mut file = APTPreferencesFile::with_content(content.to_string());
+    file.parse()?;
+
+    assert_eq!(file.pins.len(), 1);
+    assert!(file.pins[0].comment.is_empty());
+    assert_eq!(file.trailing_comment, " last comment\n");
+
+    let (raw, digest) = file.read_with_digest()?;
+    assert_eq!(raw, content.as_bytes());
+    assert_eq!(file.digest, Some(digest));
+
+    // only a path can be written to
+    assert!(file.write().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_pin_matches_release() -> Result<(), Error> {
+    let release = ReleaseFile::try_from(
+        "Origin: Proxmox\nLabel: Proxmox Debian Repository\nSuite: stable\nCodename: bookworm\n\
+         Architectures: amd64\nComponents: pvetest\n"
+            .to_string(),
+    )?;
+
+    let pin = APTPin::with_release_properties(
+        vec!["*".to_string()],
+        &[("o", "Proxmox"), ("n", "bookworm")],
+        1001,
+    );
+    assert_eq!(pin.pin, "o=Proxmox, n=bookworm");
+    assert!(pin.matches_release(&release));
+
+    let pin = APTPin::with_release_properties(vec!["*".to_string()], &[("l", "Proxmox*")], 500);
+    assert!(pin.matches_release(&release));
+
+    let pin = APTPin::with_release_properties(
+        vec!["*".to_string()],
+        &[("o", "Proxmox"), ("n", "trixie")],
+        1001,
+    );
+    assert!(!pin.matches_release(&release));
+
+    let pin = APTPin::new(
+        vec!["*".to_string()],
+        APTPinType::Version,
+        "8.*".to_string(),
+        100,
+    );
+    assert!(!pin.matches_release(&release));
+
+    Ok(())
+}
+
+#[test]
+fn test_preferences_file_names() -> Result<(), Error> {
+    assert!(APTPreferencesFile::new("/etc/apt/preferences")?.is_some());
+    assert!(APTPreferencesFile::new("/etc/apt/preferences.d/pve.pref")?.is_some());
+    assert!(APTPreferencesFile::new("/etc/apt/preferences.d/pve.pref.dpkg-old")?.is_none());
+    assert!(APTPreferencesFile::new("/etc/apt/preferences.d/.hidden")?.is_none());
+    assert!(APTPreferencesFile::new("/etc/apt/preferences.d/in valid").is_err());
+
+    Ok(())
+}
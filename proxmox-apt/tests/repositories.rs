@@ -5,8 +5,10 @@ use anyhow::{bail, format_err, Error};
 use proxmox_apt::config::APTConfig;
 
 use proxmox_apt::repositories::{
-    check_repositories, get_current_release_codename, standard_repositories, APTRepositoryFile,
-    APTRepositoryHandle, APTRepositoryInfo, APTStandardRepository, DebianCodename,
+    check_repositories, get_current_release_codename, rewrite_uris, rewrite_uris_diff,
+    standard_repositories, APTRepositoryFile, APTRepositoryFileType, APTRepositoryHandle,
+    APTRepositoryInfo, APTRepositorySignedBy, APTStandardRepository, DebianCodename,
+    RepositoryTransaction,
 };
 
 fn create_clean_directory(path: &PathBuf) -> Result<(), Error> {
@@ -94,6 +96,35 @@ fn test_parse_write_dir(read_dir: &str) -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_render_unchanged_stanzas() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let content = std::fs::read_to_string(test_dir.join("sources.list.d/unknown-fields.sources"))?;
+
+    let mut file = APTRepositoryFile::with_content(content.clone(), APTRepositoryFileType::Sources);
+    file.parse()?;
+    // stanzas are always terminated by an empty line
+    assert_eq!(
+        String::from_utf8(file.render()?).unwrap(),
+        format!("{content}\n")
+    );
+
+    file.repositories[1].set_enabled(false);
+    let rendered = String::from_utf8(file.render()?).unwrap();
+    let (first, second) = rendered.split_once("\n\n").unwrap();
+    assert!(content.starts_with(first));
+    assert_eq!(
+        second,
+        "Types: deb\n\
+        URIs: http://deb.debian.org/debian\n\
+        Suites: bookworm-updates\n\
+        Components: main\n\
+        Enabled: false\n\n",
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_digest() -> Result<(), Error> {
     let test_dir = std::env::current_dir()?.join("tests");
@@ -367,6 +398,9 @@ fn test_standard_repositories() -> Result<(), Error> {
         APTStandardRepository::from(APTRepositoryHandle::CephReefEnterprise),
         APTStandardRepository::from(APTRepositoryHandle::CephReefNoSubscription),
         APTStandardRepository::from(APTRepositoryHandle::CephReefTest),
+        APTStandardRepository::from(APTRepositoryHandle::CephSquidEnterprise),
+        APTStandardRepository::from(APTRepositoryHandle::CephSquidNoSubscription),
+        APTStandardRepository::from(APTRepositoryHandle::CephSquidTest),
     ];
 
     let absolute_suite_list = read_dir.join("absolute_suite.list");
@@ -473,3 +507,379 @@ fn test_get_current_release_codename() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_signed_by() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let read_dir = test_dir.join("sources.list.d");
+
+    let signed_by_sources = read_dir.join("signed-by.sources");
+    let mut file = APTRepositoryFile::new(&signed_by_sources)?.unwrap();
+    file.parse()?;
+
+    let keyring = "/etc/apt/trusted.gpg.d/proxmox-release-bookworm.gpg".to_string();
+
+    assert_eq!(
+        file.repositories[0].signed_by(),
+        Some(APTRepositorySignedBy::Keyrings(vec![keyring.clone()])),
+    );
+
+    match file.repositories[1].signed_by() {
+        Some(APTRepositorySignedBy::Embedded(key)) => {
+            assert!(key.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nmDMEZQ7R"));
+            assert!(key.ends_with("=AbCd\n-----END PGP PUBLIC KEY BLOCK-----"));
+        }
+        other => bail!("unexpected Signed-By {other:?}"),
+    }
+
+    // an embedded key cannot be referenced from a one-line-style repository
+    let mut repo = APTRepositoryHandle::Enterprise.to_repository("pve", "bookworm");
+    assert_eq!(repo.signed_by(), None);
+    let embedded = file.repositories[1].signed_by();
+    assert!(repo.set_signed_by(embedded).is_err());
+
+    let signed_repo = APTRepositoryHandle::Enterprise.to_signed_repository("pve", "bookworm");
+    assert_eq!(
+        signed_repo.signed_by(),
+        Some(APTRepositorySignedBy::Keyrings(vec![keyring])),
+    );
+
+    let existing = signed_by_sources.into_os_string().into_string().unwrap();
+    repo.set_signed_by(Some(APTRepositorySignedBy::Keyrings(
+        vec![existing.clone()],
+    )))?;
+    assert!(repo.check_signed_by().is_ok());
+
+    let mut content = vec![];
+    repo.write(&mut content)?;
+    assert!(String::from_utf8(content)?.contains(&format!("[ signed-by={existing} ]")));
+
+    repo.set_signed_by(Some(APTRepositorySignedBy::Keyrings(vec![
+        "/does/not/exist.gpg".to_string(),
+        "0123456789ABCDEF".to_string(),
+    ])))?;
+    assert!(repo.check_signed_by().is_err());
+
+    repo.set_signed_by(None)?;
+    assert!(repo.options.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_convert_file_type() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let read_dir = test_dir.join("sources.list.d");
+
+    let to_string = |file: &APTRepositoryFile| -> Result<String, Error> {
+        let mut content = vec![];
+        for repo in file.repositories.iter() {
+            repo.write(&mut content)?;
+        }
+        Ok(String::from_utf8(content)?)
+    };
+
+    let list_path = read_dir.join("options_comment.list");
+    let mut file = APTRepositoryFile::new(&list_path)?.unwrap();
+    file.parse()?;
+
+    let converted = file.convert_to(APTRepositoryFileType::Sources)?;
+    assert_eq!(converted.file_type, APTRepositoryFileType::Sources);
+    assert_eq!(
+        converted.path,
+        Some(
+            read_dir
+                .join("options_comment.sources")
+                .into_os_string()
+                .into_string()
+                .unwrap()
+        ),
+    );
+    assert_eq!(
+        to_string(&converted)?,
+        "# comment\n\
+         Types: deb\n\
+         URIs: http://ftp.at.debian.org/debian\n\
+         Suites: bullseye\n\
+         Components: main contrib\n\
+         Languages: it de\n\
+         Architectures: amd64\n\
+         \n\
+         # non-free :(\n\
+         Types: deb\n\
+         URIs: http://ftp.at.debian.org/debian\n\
+         Suites: bullseye\n\
+         Components: non-free\n\
+         Languages: it de\n\
+         Architectures: amd64\n\
+         Languages-Add: fr\n\
+         Languages-Remove: de\n\
+         \n",
+    );
+
+    // and back again
+    let expected_path = test_dir
+        .join("sources.list.d.expected")
+        .join("options_comment.list");
+    let converted = converted.convert_to(APTRepositoryFileType::List)?;
+    assert_eq!(
+        to_string(&converted)?,
+        String::from_utf8(std::fs::read(expected_path)?)?
+    );
+
+    let sources_path = read_dir.join("standard.sources");
+    let mut file = APTRepositoryFile::new(&sources_path)?.unwrap();
+    file.parse()?;
+
+    let converted = file.convert_to(APTRepositoryFileType::List)?;
+    assert_eq!(
+        to_string(&converted)?,
+        "deb http://ftp.at.debian.org/debian bullseye main contrib\n\
+         \n\
+         deb http://ftp.at.debian.org/debian bullseye-updates main contrib\n\
+         \n\
+         # security updates\n\
+         deb http://security.debian.org bullseye-security main contrib\n\
+         \n",
+    );
+
+    let sources_path = read_dir.join("multiline.sources");
+    let mut file = APTRepositoryFile::new(&sources_path)?.unwrap();
+    file.parse()?;
+
+    let converted = file.convert_to(APTRepositoryFileType::List)?;
+    assert_eq!(converted.repositories.len(), 4);
+    assert!(converted.repositories.iter().all(|repo| !repo.enabled));
+    assert!(converted.repositories[0]
+        .options
+        .iter()
+        .any(|option| option.key == "lang+" && option.values == ["ja"]));
+
+    let signed_by_path = read_dir.join("signed-by.sources");
+    let mut file = APTRepositoryFile::new(&signed_by_path)?.unwrap();
+    file.parse()?;
+
+    assert!(file.convert_to(APTRepositoryFileType::List).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_rewrite_uris() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let read_dir = test_dir.join("sources.list.d");
+
+    let mut files = vec![];
+    for name in ["pve.list", "standard.sources"] {
+        let mut file = APTRepositoryFile::new(read_dir.join(name))?.unwrap();
+        file.parse()?;
+        files.push(file);
+    }
+
+    let pve_path = files[0].path.clone().unwrap();
+
+    let diff = rewrite_uris_diff(&files, "download.proxmox.com", "mirror.example.com/proxmox")?;
+    assert_eq!(
+        diff,
+        format!(
+            "--- {pve_path}\n\
+             +++ {pve_path}\n\
+             @@ -4,7 +4,7 @@\n \n\
+             \x20# PVE pve-no-subscription repository provided by proxmox.com,\n\
+             \x20# NOT recommended for production use\n\
+             -deb http://download.proxmox.com/debian/pve bullseye pve-no-subscription\n\
+             +deb http://mirror.example.com/proxmox/debian/pve bullseye pve-no-subscription\n \n\
+             \x20# deb https://enterprise.proxmox.com/debian/pve bullseye pve-enterprise\n \n"
+        ),
+    );
+    // dry run leaves the files untouched
+    assert_eq!(
+        files[0].repositories[2].uris,
+        ["http://download.proxmox.com/debian/pve"]
+    );
+
+    let changes = rewrite_uris(
+        &mut files,
+        "http://ftp.at.debian.org/debian",
+        "file:///mirror",
+    )?;
+    assert_eq!(changes.len(), 1);
+    assert!(changes
+        .iter()
+        .all(|change| change.new_uri == "file:///mirror"));
+    assert_eq!(files[1].repositories[0].uris, ["file:///mirror"]);
+    assert_eq!(
+        files[1].repositories[1].uris,
+        ["http://security.debian.org"]
+    );
+
+    assert!(rewrite_uris(&mut files, "", "mirror.example.com").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_transaction() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let read_dir = test_dir.join("sources.list.d");
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR").to_string());
+    let write_dir = tmp_dir.join("sources.list.d.transaction");
+
+    create_clean_directory(&write_dir)?;
+
+    let mut files = vec![];
+    for name in ["pve.list", "standard.sources", "absolute_suite.list"] {
+        std::fs::copy(read_dir.join(name), write_dir.join(name))?;
+        let mut file = APTRepositoryFile::new(write_dir.join(name))?.unwrap();
+        file.parse()?;
+        files.push(file);
+    }
+
+    let original: Vec<Vec<u8>> = files
+        .iter()
+        .map(|file| std::fs::read(file.path.as_ref().unwrap()))
+        .collect::<Result<_, _>>()?;
+
+    // a digest mismatch in one file prevents writing any of them
+    let mut transaction = RepositoryTransaction::new();
+    for file in files.iter() {
+        let mut file = file.clone();
+        file.repositories[0].set_enabled(false);
+        transaction.stage(file)?;
+    }
+    let mut outdated = files[2].clone();
+    outdated.digest = Some([0u8; 32]);
+    transaction.stage(outdated)?;
+    assert_eq!(transaction.staged().len(), 3);
+
+    assert!(transaction.commit().is_err());
+    for (file, original) in files.iter().zip(original.iter()) {
+        assert_eq!(std::fs::read(file.path.as_ref().unwrap())?, *original);
+    }
+
+    let mut transaction = RepositoryTransaction::new();
+    for file in files.iter_mut() {
+        file.repositories[0].set_enabled(false);
+        transaction.stage(file.clone())?;
+    }
+    let mut removed = files[2].clone();
+    removed.repositories.clear();
+    transaction.stage(removed)?;
+    let new_file_path = write_dir.join("new.list");
+    let mut new_file = files[0].clone();
+    new_file.path = Some(
+        new_file_path
+            .clone()
+            .into_os_string()
+            .into_string()
+            .unwrap(),
+    );
+    new_file.digest = None;
+    transaction.stage(new_file)?;
+
+    transaction.commit()?;
+
+    for file in files[..2].iter_mut() {
+        file.parse()?;
+        assert!(!file.repositories[0].enabled);
+    }
+    assert!(!files[2].exists());
+    assert!(new_file_path.exists());
+    assert_eq!(std::fs::read_dir(&write_dir)?.count(), 3); // no leftover temporary files
+
+    let mut transaction = RepositoryTransaction::new();
+    assert!(transaction
+        .stage(APTRepositoryFile::with_content(
+            String::new(),
+            APTRepositoryFileType::List
+        ))
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_check_duplicates_and_components() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let read_dir = test_dir.join("sources.list.d");
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR").to_string());
+    let write_dir = tmp_dir.join("sources.list.d.duplicates");
+
+    proxmox_apt::config::init(APTConfig::new(
+        Some(&test_dir.clone().into_os_string().into_string().unwrap()),
+        None,
+    ));
+
+    create_clean_directory(&write_dir)?;
+
+    let proxmox_list = write_dir.join("proxmox.list");
+    std::fs::write(
+        &proxmox_list,
+        "deb http://download.proxmox.com/debian/pve bullseye pve-no-subscription\n\
+         # deb http://download.proxmox.com/debian/pve bullseye pve-no-subscription\n\
+         deb https://enterprise.proxmox.com/debian/pve bullseye pbs-enterprise\n\
+         deb http://download.proxmox.com/debian/ceph-quincy bullseye no-subscription\n\
+         deb http://download.proxmox.com/debian/pbs-client bullseye main\n",
+    )?;
+
+    let mut files = vec![];
+    for path in [
+        read_dir.join("standard.list"),
+        read_dir.join("standard.sources"),
+        proxmox_list.clone(),
+    ] {
+        let mut file = APTRepositoryFile::new(path)?.unwrap();
+        file.parse()?;
+        files.push(file);
+    }
+
+    let standard_list = files[0].path.clone().unwrap();
+    let standard_sources = files[1].path.clone().unwrap();
+    let proxmox_list = proxmox_list.into_os_string().into_string().unwrap();
+
+    let mut infos: Vec<APTRepositoryInfo> = check_repositories(&files, DebianCodename::Bullseye)
+        .into_iter()
+        .filter(|info| info.kind != "origin")
+        .collect();
+    infos.sort();
+
+    let mut expected_infos = vec![
+        APTRepositoryInfo {
+            path: proxmox_list.clone(),
+            index: 1,
+            property: None,
+            kind: "conflict".to_string(),
+            message: format!(
+                "repository is disabled, but enabled in '{proxmox_list}' (repository 1)"
+            ),
+        },
+        APTRepositoryInfo {
+            path: proxmox_list.clone(),
+            index: 2,
+            property: Some("Components".to_string()),
+            kind: "invalid-component".to_string(),
+            message: "component 'pbs-enterprise' does not exist for \
+                'https://enterprise.proxmox.com/debian/pve'"
+                .to_string(),
+        },
+    ];
+
+    // the entries in standard.sources are also defined in standard.list
+    for (index, list_index) in [(0, 0), (0, 1), (1, 2)] {
+        expected_infos.push(APTRepositoryInfo {
+            path: standard_sources.clone(),
+            index,
+            property: None,
+            kind: "duplicate".to_string(),
+            message: format!(
+                "repository already defined in '{standard_list}' (repository {})",
+                list_index + 1
+            ),
+        });
+    }
+    expected_infos.sort();
+
+    assert_eq!(infos, expected_infos);
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_apt::repositories::{APTRepository, APTRepositoryFileType, APTSnapshotDirectory};
+
+fn create_clean_directory(path: &PathBuf) -> Result<(), Error> {
+    match std::fs::remove_dir_all(path) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => bail!("unable to remove dir {path:?} - {err}"),
+        Ok(_) => (),
+    }
+    std::fs::create_dir_all(path)
+        .map_err(|err| format_err!("unable to create dir {path:?} - {err}"))
+}
+
+fn create_snapshot(base: &Path, name: &str, index: &[u8]) -> Result<(), Error> {
+    let dist_dir = base.join(name).join("dists").join("bookworm");
+    std::fs::create_dir_all(dist_dir.join("main/binary-amd64"))?;
+
+    std::fs::write(dist_dir.join("main/binary-amd64/Packages.xz"), index)?;
+
+    let release = format!(
+        "Architectures: amd64\nComponents: main\nSHA256:\n {} {} main/binary-amd64/Packages.xz\n",
+        hex::encode(openssl::sha::sha256(index)),
+        index.len(),
+    );
+    std::fs::write(dist_dir.join("Release"), release)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_switch() -> Result<(), Error> {
+    let base = PathBuf::from(env!("CARGO_TARGET_TMPDIR").to_string()).join("snapshots");
+    create_clean_directory(&base)?;
+
+    let snapshots = APTSnapshotDirectory::new(&base);
+
+    create_snapshot(&base, "2024-01", b"first")?;
+    create_snapshot(&base, "2024-02", b"second")?;
+
+    assert_eq!(snapshots.current()?, None);
+
+    snapshots.activate("2024-01")?;
+    assert_eq!(snapshots.current()?.as_deref(), Some("2024-01"));
+
+    snapshots.activate("2024-02")?;
+    assert_eq!(snapshots.current()?.as_deref(), Some("2024-02"));
+
+    let list = snapshots.list()?;
+    assert_eq!(list.len(), 2);
+    assert!(!list[0].active);
+    assert!(list[1].active);
+    assert_eq!(list[1].suites, vec!["bookworm".to_string()]);
+
+    // corrupt the first snapshot, switching back must fail and keep the current one
+    std::fs::write(
+        base.join("2024-01/dists/bookworm/main/binary-amd64/Packages.xz"),
+        b"corrupt",
+    )?;
+    assert!(snapshots.verify("2024-01", "bookworm").is_err());
+    assert!(snapshots.activate("2024-01").is_err());
+    assert_eq!(snapshots.current()?.as_deref(), Some("2024-02"));
+
+    // missing package index
+    std::fs::remove_file(base.join("2024-01/dists/bookworm/main/binary-amd64/Packages.xz"))?;
+    assert!(snapshots.verify("2024-01", "bookworm").is_err());
+
+    assert!(snapshots.snapshot_path("current").is_err());
+    assert!(snapshots.snapshot_path("../etc").is_err());
+
+    let mut repo = APTRepository::new(APTRepositoryFileType::Sources);
+    repo.uris = vec!["http://deb.debian.org/debian".to_string()];
+    snapshots.point_repository(&mut repo);
+    assert!(snapshots.is_snapshot_repository(&repo));
+    assert_eq!(
+        repo.uris,
+        vec![format!("file://{}/current", base.display())]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! brotli helper
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::Error;
+use brotli::CompressorWriter;
+use bytes::Bytes;
+use futures::ready;
+use futures::stream::Stream;
+
+const BUFFER_SIZE: usize = 8192;
+
+/// Window size (log2) used for compression, 22 is the maximum allowed by RFC 7932
+const WINDOW_SIZE: u32 = 22;
+
+/// An async BrotliEncoder that implements [Stream] for another [Stream]
+///
+/// Useful for on-the-fly brotli compression in streaming api calls
+pub struct BrotliEncoder<T> {
+    inner: T,
+    compressor: Option<CompressorWriter<Vec<u8>>>,
+}
+
+impl<T, O, E> BrotliEncoder<T>
+where
+    T: Stream<Item = Result<O, E>> + Unpin,
+    O: Into<Bytes>,
+    E: Into<Error>,
+{
+    /// Returns a new [BrotliEncoder] with default quality 5
+    pub fn new(inner: T) -> Self {
+        Self::with_quality(inner, 5)
+    }
+
+    /// Returns a new [BrotliEncoder] with the given quality (0-11)
+    pub fn with_quality(inner: T, quality: u32) -> Self {
+        Self {
+            inner,
+            compressor: Some(CompressorWriter::new(
+                Vec::with_capacity(BUFFER_SIZE),
+                BUFFER_SIZE,
+                quality,
+                WINDOW_SIZE,
+            )),
+        }
+    }
+}
+
+impl<T> BrotliEncoder<T> {
+    /// Returns the wrapped [Stream]
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T, O, E> Stream for BrotliEncoder<T>
+where
+    T: Stream<Item = Result<O, E>> + Unpin,
+    O: Into<Bytes>,
+    E: Into<Error>,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            let compressor = match this.compressor.as_mut() {
+                Some(compressor) => compressor,
+                None => return Poll::Ready(None),
+            };
+
+            match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(res) => {
+                    let buf: Bytes = res.map_err(Into::into)?.into();
+                    compressor.write_all(&buf)?;
+                    let output = compressor.get_mut();
+                    if !output.is_empty() {
+                        return Poll::Ready(Some(Ok(std::mem::take(output).into())));
+                    }
+                }
+                None => {
+                    // finishes the brotli stream
+                    let output = this.compressor.take().unwrap().into_inner();
+                    if !output.is_empty() {
+                        return Poll::Ready(Some(Ok(output.into())));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use futures::{stream, TryStreamExt};
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let chunks: Vec<Result<Vec<u8>, Error>> =
+            data.chunks(1000).map(|chunk| Ok(chunk.to_vec())).collect();
+
+        let encoder = BrotliEncoder::new(stream::iter(chunks));
+        let compressed: Vec<Bytes> = futures::executor::block_on(encoder.try_collect()).unwrap();
+        let compressed = compressed.concat();
+        assert!(compressed.len() < data.len());
+
+        let mut decompressed = Vec::new();
+        brotli::Decompressor::new(compressed.as_slice(), BUFFER_SIZE)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
@@ -1,6 +1,7 @@
 mod compression;
 pub use compression::*;
 
+pub mod brotli;
 pub mod tar;
 pub mod zip;
 pub mod zstd;
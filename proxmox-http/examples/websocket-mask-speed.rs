@@ -0,0 +1,44 @@
+//! Measure the throughput of websocket frame masking and frame creation.
+
+use std::time::Instant;
+
+use anyhow::Error;
+
+use proxmox_http::websocket::{create_frame, mask_bytes, OpCode};
+
+const MASK: [u8; 4] = [0x12, 0x34, 0x56, 0x78];
+
+fn measure<F: FnMut()>(name: &str, size: usize, mut func: F) {
+    let loops = (1024 * 1024 * 1024) / size;
+
+    let start = Instant::now();
+    for _ in 0..loops {
+        func();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let mbytes = (loops * size) as f64 / (1024.0 * 1024.0);
+    println!(
+        "{name:>12} {size:>8} bytes: {:>10.2} MB/s",
+        mbytes / elapsed
+    );
+}
+
+fn main() -> Result<(), Error> {
+    for size in [16, 125, 4096, 64 * 1024, 1024 * 1024] {
+        let mut data = vec![0xaau8; size];
+        measure("mask_bytes", size, || {
+            mask_bytes(Some(MASK), std::hint::black_box(&mut data))
+        });
+    }
+
+    for size in [16, 125, 4096, 64 * 1024, 1024 * 1024] {
+        let data = vec![0xaau8; size];
+        measure("create_frame", size, || {
+            let frame = create_frame(Some(MASK), std::hint::black_box(&data), OpCode::Binary);
+            std::hint::black_box(frame.unwrap());
+        });
+    }
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use anyhow::{bail, format_err, Error};
+use std::net::IpAddr;
 use std::os::unix::io::AsRawFd;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -9,13 +10,13 @@ use http::Uri;
 use hyper::client::HttpConnector;
 use openssl::ssl::SslConnector;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::net::{TcpStream, UnixStream};
 use tokio_openssl::SslStream;
 
 use proxmox_sys::linux::socket::set_tcp_keepalive;
 
-use crate::proxy_config::ProxyConfig;
-use crate::uri::build_authority;
+use crate::proxy_config::{NoProxy, ProxyConfig, ProxyProtocol};
+use crate::uri::{build_authority, unix_socket_path};
 
 use super::tls::MaybeTlsStream;
 use crate::{RateLimitedStream, ShareableRateLimit};
@@ -27,6 +28,8 @@ pub struct HttpsConnector {
     connector: HttpConnector,
     ssl_connector: Arc<SslConnector>,
     proxy: Option<ProxyConfig>,
+    https_proxy: Option<ProxyConfig>,
+    no_proxy: Option<NoProxy>,
     tcp_keepalive: u32,
     read_limiter: Option<SharedRateLimit>,
     write_limiter: Option<SharedRateLimit>,
@@ -43,6 +46,8 @@ impl HttpsConnector {
             connector,
             ssl_connector: Arc::new(ssl_connector),
             proxy: None,
+            https_proxy: None,
+            no_proxy: None,
             tcp_keepalive,
             read_limiter: None,
             write_limiter: None,
@@ -53,6 +58,30 @@ impl HttpsConnector {
         self.proxy = Some(proxy);
     }
 
+    /// Use a different proxy for HTTPS connections, instead of the one set with `set_proxy`.
+    pub fn set_https_proxy(&mut self, proxy: ProxyConfig) {
+        self.https_proxy = Some(proxy);
+    }
+
+    /// Set the hosts which are connected to directly, without using a proxy.
+    pub fn set_no_proxy(&mut self, no_proxy: NoProxy) {
+        self.no_proxy = Some(no_proxy);
+    }
+
+    fn proxy_for(&self, host: &str, is_https: bool) -> Option<&ProxyConfig> {
+        if let Some(ref no_proxy) = self.no_proxy {
+            if no_proxy.matches(host) {
+                return None;
+            }
+        }
+
+        if is_https {
+            self.https_proxy.as_ref().or(self.proxy.as_ref())
+        } else {
+            self.proxy.as_ref()
+        }
+    }
+
     pub fn set_read_limiter(&mut self, limiter: Option<SharedRateLimit>) {
         self.read_limiter = limiter;
     }
@@ -120,6 +149,124 @@ impl HttpsConnector {
         }
         Ok(())
     }
+
+    /// Establish a connection to `host` and `port` through a SOCKS5 proxy (RFC 1928), using
+    /// username/password authentication (RFC 1929) if `authorization` is set.
+    async fn socks5_connect<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        host: &str,
+        port: u16,
+        authorization: Option<&str>,
+    ) -> Result<(), Error> {
+        const VERSION: u8 = 5;
+        const AUTH_NONE: u8 = 0;
+        const AUTH_PASSWORD: u8 = 2;
+        const AUTH_UNACCEPTABLE: u8 = 0xff;
+
+        let greeting: &[u8] = match authorization {
+            Some(_) => &[VERSION, 2, AUTH_NONE, AUTH_PASSWORD],
+            None => &[VERSION, 1, AUTH_NONE],
+        };
+        stream.write_all(greeting).await?;
+        stream.flush().await?;
+
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != VERSION {
+            bail!("socks5 proxy connect failed - invalid version {}", reply[0]);
+        }
+
+        match (reply[1], authorization) {
+            (AUTH_NONE, _) => (),
+            (AUTH_PASSWORD, Some(authorization)) => {
+                let (user, password) = authorization.split_once(':').unwrap_or((authorization, ""));
+                if user.len() > 255 || password.len() > 255 {
+                    bail!("socks5 proxy connect failed - credentials too long");
+                }
+
+                let mut request = vec![1, user.len() as u8];
+                request.extend(user.as_bytes());
+                request.push(password.len() as u8);
+                request.extend(password.as_bytes());
+                stream.write_all(&request).await?;
+                stream.flush().await?;
+
+                stream.read_exact(&mut reply).await?;
+                if reply[1] != 0 {
+                    bail!("socks5 proxy connect failed - authentication failed");
+                }
+            }
+            (AUTH_UNACCEPTABLE, _) => {
+                bail!("socks5 proxy connect failed - no acceptable authentication method")
+            }
+            (method, _) => {
+                bail!("socks5 proxy connect failed - unexpected authentication method {method}")
+            }
+        }
+
+        // CONNECT command, host names are resolved by the proxy
+        let mut request = vec![VERSION, 1, 0];
+        let addr = host.trim_start_matches('[').trim_end_matches(']');
+        match addr.parse::<IpAddr>() {
+            Ok(IpAddr::V4(addr)) => {
+                request.push(1);
+                request.extend(addr.octets());
+            }
+            Ok(IpAddr::V6(addr)) => {
+                request.push(4);
+                request.extend(addr.octets());
+            }
+            Err(_) => {
+                if host.len() > 255 {
+                    bail!("socks5 proxy connect failed - host name too long");
+                }
+                request.push(3);
+                request.push(host.len() as u8);
+                request.extend(host.as_bytes());
+            }
+        }
+        request.extend(port.to_be_bytes());
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != VERSION {
+            bail!("socks5 proxy connect failed - invalid version {}", reply[0]);
+        }
+        if reply[1] != 0 {
+            bail!(
+                "socks5 proxy connect failed - {}",
+                Self::socks5_error(reply[1])
+            );
+        }
+
+        // skip the bound address and port
+        let addr_len = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => stream.read_u8().await? as usize,
+            other => bail!("socks5 proxy connect failed - invalid address type {other}"),
+        };
+        let mut bound = vec![0u8; addr_len + 2];
+        stream.read_exact(&mut bound).await?;
+
+        Ok(())
+    }
+
+    fn socks5_error(reply: u8) -> &'static str {
+        match reply {
+            1 => "general SOCKS server failure",
+            2 => "connection not allowed by ruleset",
+            3 => "network unreachable",
+            4 => "host unreachable",
+            5 => "connection refused",
+            6 => "TTL expired",
+            7 => "command not supported",
+            8 => "address type not supported",
+            _ => "unknown error",
+        }
+    }
 }
 
 impl hyper::service::Service<Uri> for HttpsConnector {
@@ -134,6 +281,20 @@ impl hyper::service::Service<Uri> for HttpsConnector {
     }
 
     fn call(&mut self, dst: Uri) -> Self::Future {
+        match unix_socket_path(&dst) {
+            Ok(Some(path)) => {
+                return async move {
+                    let stream = UnixStream::connect(&path)
+                        .await
+                        .map_err(|err| format_err!("error connecting to {:?} - {}", path, err))?;
+                    Ok(MaybeTlsStream::Unix(stream))
+                }
+                .boxed();
+            }
+            Ok(None) => (),
+            Err(err) => return futures::future::err(err).boxed(),
+        }
+
         let mut connector = self.connector.clone();
         let ssl_connector = Arc::clone(&self.ssl_connector);
         let is_https = dst.scheme() == Some(&http::uri::Scheme::HTTPS);
@@ -148,7 +309,7 @@ impl hyper::service::Service<Uri> for HttpsConnector {
         let read_limiter = self.read_limiter.clone();
         let write_limiter = self.write_limiter.clone();
 
-        if let Some(ref proxy) = self.proxy {
+        if let Some(proxy) = self.proxy_for(&host, is_https) {
             let use_connect = is_https || proxy.force_connect;
 
             let proxy_authority = match build_authority(&proxy.host, proxy.port) {
@@ -168,7 +329,28 @@ impl hyper::service::Service<Uri> for HttpsConnector {
 
             let authorization = proxy.authorization.clone();
 
-            if use_connect {
+            if proxy.protocol == ProxyProtocol::Socks5 {
+                async move {
+                    let tcp_stream = connector.call(proxy_uri).await.map_err(|err| {
+                        format_err!("error connecting to {} - {}", proxy_authority, err)
+                    })?;
+
+                    let _ = set_tcp_keepalive(tcp_stream.as_raw_fd(), keepalive);
+
+                    let mut tcp_stream =
+                        RateLimitedStream::with_limiter(tcp_stream, read_limiter, write_limiter);
+
+                    Self::socks5_connect(&mut tcp_stream, &host, port, authorization.as_deref())
+                        .await?;
+
+                    if is_https {
+                        Self::secure_stream(tcp_stream, &ssl_connector, &host).await
+                    } else {
+                        Ok(MaybeTlsStream::Normal(tcp_stream))
+                    }
+                }
+                .boxed()
+            } else if use_connect {
                 async move {
                     use std::fmt::Write as _;
                     let tcp_stream = connector.call(proxy_uri).await.map_err(|err| {
@@ -240,3 +422,44 @@ impl hyper::service::Service<Uri> for HttpsConnector {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::HttpsConnector;
+
+    #[test]
+    fn test_socks5_connect() {
+        let (mut client, mut proxy) = tokio::io::duplex(1024);
+
+        futures::executor::block_on(async move {
+            // method selection, authentication and connect replies
+            proxy
+                .write_all(&[5, 2, 1, 0, 5, 0, 0, 1, 10, 0, 0, 1, 0x1f, 0x90])
+                .await
+                .unwrap();
+
+            HttpsConnector::socks5_connect(&mut client, "example.com", 443, Some("user:pass"))
+                .await
+                .unwrap();
+            drop(client);
+
+            let mut sent = Vec::new();
+            proxy.read_to_end(&mut sent).await.unwrap();
+            assert_eq!(
+                sent,
+                b"\x05\x02\x00\x02\x01\x04user\x04pass\x05\x01\x00\x03\x0bexample.com\x01\xbb"
+            );
+        });
+
+        let (mut client, mut proxy) = tokio::io::duplex(1024);
+        futures::executor::block_on(async move {
+            proxy.write_all(&[5, 0, 5, 5, 0, 1]).await.unwrap();
+            let err = HttpsConnector::socks5_connect(&mut client, "[::1]", 80, None)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("connection refused"));
+        });
+    }
+}
@@ -7,12 +7,22 @@
 //! [`sync::Client`](crate::client::sync::Client).
 //!
 //! Both clients implement [`HttpClient`](crate::HttpClient) if the feature `client-trait` is enabled.
+//! For large bodies, the sync client implements [`HttpStreamClient`](crate::HttpStreamClient) and
+//! the async client provides `get_stream` and `post_stream`.
+//!
+//! The async client can also send requests over unix domain sockets, using URIs built with
+//! [`unix_socket_uri`](crate::uri::unix_socket_uri).
 
 #[cfg(feature = "client")]
 mod connector;
 #[cfg(feature = "client")]
 pub use connector::HttpsConnector;
 
+#[cfg(feature = "client")]
+mod policy;
+#[cfg(feature = "client")]
+pub use policy::RequestPolicy;
+
 #[cfg(feature = "client")]
 mod simple;
 #[cfg(feature = "client")]
@@ -24,3 +34,6 @@ pub mod tls;
 #[cfg(feature = "client-sync")]
 /// Blocking HTTP client
 pub mod sync;
+
+mod observer;
+mod verify;
@@ -0,0 +1,201 @@
+//! Reporting of requests to the [`HttpInstrumentation`] and `tracing`.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Error;
+use http::{Method, StatusCode, Uri};
+
+use crate::instrumentation::{HttpInstrumentation, RequestInfo};
+
+/// Reports the progress of a single request to the instrumentation and `tracing`.
+pub(crate) struct RequestObserver {
+    instrumentation: Option<Arc<dyn HttpInstrumentation>>,
+    info: RequestInfo,
+    start: Instant,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl RequestObserver {
+    /// Start observing a request, `None` if there is nothing to report to.
+    pub(crate) fn start(
+        instrumentation: Option<&Arc<dyn HttpInstrumentation>>,
+        method: &Method,
+        uri: &Uri,
+    ) -> Option<Arc<Self>> {
+        if instrumentation.is_none() && !cfg!(feature = "tracing") {
+            return None;
+        }
+
+        let info = RequestInfo {
+            method: method.clone(),
+            uri: uri.clone(),
+        };
+
+        #[cfg(feature = "tracing")]
+        let span = {
+            let span = tracing::info_span!(
+                "http_request",
+                method = %info.method,
+                uri = %info.uri,
+                status = tracing::field::Empty,
+            );
+            span.in_scope(|| tracing::debug!("sending request"));
+            span
+        };
+
+        if let Some(instrumentation) = instrumentation {
+            instrumentation.request_start(&info);
+        }
+
+        Some(Arc::new(Self {
+            instrumentation: instrumentation.cloned(),
+            info,
+            start: Instant::now(),
+            #[cfg(feature = "tracing")]
+            span,
+        }))
+    }
+
+    pub(crate) fn end(&self, result: Result<StatusCode, &Error>) {
+        let duration = self.start.elapsed();
+
+        #[cfg(feature = "tracing")]
+        self.span.in_scope(|| match result {
+            Ok(status) => {
+                self.span.record("status", status.as_u16());
+                tracing::debug!(?duration, "received response");
+            }
+            Err(err) => tracing::debug!(?duration, "request failed - {err}"),
+        });
+
+        if let Some(ref instrumentation) = self.instrumentation {
+            instrumentation.request_end(&self.info, result, duration);
+        }
+    }
+
+    pub(crate) fn sent(&self, count: u64) {
+        if let Some(ref instrumentation) = self.instrumentation {
+            instrumentation.bytes_sent(&self.info, count);
+        }
+    }
+
+    pub(crate) fn received(&self, count: u64) {
+        if let Some(ref instrumentation) = self.instrumentation {
+            instrumentation.bytes_received(&self.info, count);
+        }
+    }
+}
+
+/// Report a retry of the request to `uri`.
+#[cfg(feature = "client")]
+pub(crate) fn report_retry(
+    instrumentation: Option<&Arc<dyn HttpInstrumentation>>,
+    method: &Method,
+    uri: &Uri,
+    retry: u32,
+    delay: std::time::Duration,
+) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(%method, %uri, ?delay, "retrying request ({retry})");
+
+    if let Some(instrumentation) = instrumentation {
+        let info = RequestInfo {
+            method: method.clone(),
+            uri: uri.clone(),
+        };
+        instrumentation.request_retry(&info, retry, delay);
+    }
+}
+
+/// A request body which reports the bytes sent, keeping the size hint of the inner body.
+#[cfg(feature = "client")]
+pub(crate) struct CountingBody {
+    inner: hyper::Body,
+    observer: Option<Arc<RequestObserver>>,
+}
+
+#[cfg(feature = "client")]
+impl CountingBody {
+    pub(crate) fn new(inner: hyper::Body, observer: Option<Arc<RequestObserver>>) -> Self {
+        Self { inner, observer }
+    }
+}
+
+#[cfg(feature = "client")]
+impl hyper::body::HttpBody for CountingBody {
+    type Data = hyper::body::Bytes;
+    type Error = hyper::Error;
+
+    fn poll_data(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context,
+    ) -> std::task::Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = &mut *self;
+        let result = futures::ready!(std::pin::Pin::new(&mut this.inner).poll_data(cx));
+        if let (Some(Ok(chunk)), Some(observer)) = (&result, &this.observer) {
+            observer.sent(chunk.len() as u64);
+        }
+        std::task::Poll::Ready(result)
+    }
+
+    fn poll_trailers(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context,
+    ) -> std::task::Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        std::pin::Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(feature = "client-sync")]
+/// A reader which reports the bytes read through it as sent or received bytes of a request.
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    observer: Arc<RequestObserver>,
+    sending: bool,
+}
+
+#[cfg(feature = "client-sync")]
+impl<R> CountingReader<R> {
+    /// Count the bytes read from a request body.
+    pub(crate) fn request_body(inner: R, observer: Arc<RequestObserver>) -> Self {
+        Self {
+            inner,
+            observer,
+            sending: true,
+        }
+    }
+
+    /// Count the bytes read from a response body.
+    pub(crate) fn response_body(inner: R, observer: Arc<RequestObserver>) -> Self {
+        Self {
+            inner,
+            observer,
+            sending: false,
+        }
+    }
+}
+
+#[cfg(feature = "client-sync")]
+impl<R: std::io::Read> std::io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        if count > 0 {
+            if self.sending {
+                self.observer.sent(count as u64);
+            } else {
+                self.observer.received(count as u64);
+            }
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,364 @@
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use http::header::{self, HeaderName, LOCATION, RETRY_AFTER};
+use http::request::Parts;
+use http::{Method, Response, StatusCode, Uri};
+
+use crate::uri::UNIX_SOCKET_SCHEME;
+
+/// Retry, redirect and timeout policy for requests made with a [`Client`](super::Client).
+///
+/// Failed requests are retried with exponential backoff if they are idempotent (as defined by
+/// their method) and failed with a connection error (other than a TLS failure), a timeout or one
+/// of the status codes 408, 429, 500, 502, 503 and 504. A `Retry-After` header sent along with the response is honored,
+/// as long as it does not exceed `max_backoff`.
+///
+/// When following a redirect to another origin, only standard headers which do not carry
+/// credentials are kept, custom headers like `X-API-Key` are dropped.
+///
+/// Since requests may need to be sent multiple times, their body is buffered in memory.
+#[derive(Clone, Debug)]
+pub struct RequestPolicy {
+    /// Number of retries after the first attempt, defaults to 3
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further retry, defaults to 500ms
+    pub initial_backoff: Duration,
+    /// Upper limit for the delay between retries, defaults to 30s
+    pub max_backoff: Duration,
+    /// Randomize the delay between retries to avoid synchronized clients, defaults to true
+    pub jitter: bool,
+    /// Number of redirects to follow, `0` disables following redirects, defaults to 10
+    pub max_redirects: u32,
+    /// Timeout for every single attempt until the response headers are received
+    pub attempt_timeout: Option<Duration>,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+            max_redirects: 10,
+            attempt_timeout: None,
+        }
+    }
+}
+
+impl RequestPolicy {
+    /// A policy which neither retries requests nor follows redirects.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            max_redirects: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Builder-style method to set the number of retries.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Builder-style method to set the initial and the maximum delay between retries.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Builder-style method to enable or disable randomizing the delay between retries.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Builder-style method to set the number of redirects to follow.
+    pub fn max_redirects(mut self, max_redirects: u32) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Builder-style method to set the timeout of a single attempt.
+    pub fn attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = Some(timeout);
+        self
+    }
+
+    /// The delay before retry number `retry` (starting at 0), without jitter.
+    pub(crate) fn backoff_delay(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+
+    /// The delay before retry number `retry`, taking `Retry-After` and jitter into account.
+    pub(crate) fn retry_delay<B>(&self, retry: u32, response: Option<&Response<B>>) -> Duration {
+        if let Some(delay) = response.and_then(retry_after) {
+            if delay <= self.max_backoff {
+                return delay;
+            }
+        }
+
+        let delay = self.backoff_delay(retry);
+        if !self.jitter {
+            return delay;
+        }
+
+        // pick a delay between 50% and 100% of the computed backoff
+        let mut random = [0u8; 4];
+        if openssl::rand::rand_bytes(&mut random).is_err() {
+            return delay;
+        }
+        let factor = 0.5 + (u32::from_ne_bytes(random) as f64 / u32::MAX as f64) / 2.0;
+        delay.mul_f64(factor)
+    }
+}
+
+/// The delay requested by a response's `Retry-After` header, only delays in seconds are
+/// supported.
+fn retry_after<B>(response: &Response<B>) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+pub(crate) fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// An attempt exceeding [`RequestPolicy::attempt_timeout`].
+#[derive(Debug)]
+pub(crate) struct AttemptTimeout(pub(crate) Duration);
+
+impl fmt::Display for AttemptTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request timed out after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for AttemptTimeout {}
+
+/// Whether a failed attempt may succeed when retried, i.e. it timed out or failed to connect.
+pub(crate) fn is_retryable_error(err: &Error) -> bool {
+    if err.is::<AttemptTimeout>() {
+        return true;
+    }
+
+    let err = match err.downcast_ref::<hyper::Error>() {
+        Some(err) => err,
+        None => return false,
+    };
+    if err.is_timeout() {
+        return true;
+    }
+    if !err.is_connect() {
+        return false;
+    }
+
+    // TLS failures, e.g. an untrusted certificate, will just fail again
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if err.is::<openssl::ssl::Error>() || err.is::<openssl::error::ErrorStack>() {
+            return false;
+        }
+        source = err.source();
+    }
+
+    true
+}
+
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+pub(crate) fn is_redirect(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308)
+}
+
+/// Resolve a `Location` header value against the URI of the request it was returned for.
+pub(crate) fn resolve_location(base: &Uri, location: &str) -> Result<Uri, Error> {
+    let invalid = |err| format_err!("invalid redirect location '{}' - {}", location, err);
+
+    if location.contains("://") {
+        return location.parse().map_err(invalid);
+    }
+
+    if location.starts_with("//") {
+        let scheme = base.scheme_str().unwrap_or("https");
+        return format!("{scheme}:{location}").parse().map_err(invalid);
+    }
+
+    let path_and_query = if location.starts_with('/') {
+        location.to_string()
+    } else {
+        let base_path = base.path();
+        let dir = &base_path[..base_path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        format!("{dir}{location}")
+    };
+
+    let mut parts = base.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().map_err(invalid)?);
+
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// Headers kept when following a redirect to another origin.
+const CROSS_ORIGIN_HEADERS: &[HeaderName] = &[
+    header::ACCEPT,
+    header::ACCEPT_ENCODING,
+    header::ACCEPT_LANGUAGE,
+    header::CACHE_CONTROL,
+    header::CONTENT_ENCODING,
+    header::CONTENT_LANGUAGE,
+    header::CONTENT_LENGTH,
+    header::CONTENT_TYPE,
+    header::IF_MATCH,
+    header::IF_MODIFIED_SINCE,
+    header::IF_NONE_MATCH,
+    header::IF_UNMODIFIED_SINCE,
+    header::RANGE,
+    header::USER_AGENT,
+];
+
+/// Update the request `parts` to follow a redirect response.
+///
+/// Returns whether the body needs to be dropped, because the request was changed to a `GET`.
+pub(crate) fn follow_redirect<B>(parts: &mut Parts, response: &Response<B>) -> Result<bool, Error> {
+    let location = response
+        .headers()
+        .get(LOCATION)
+        .ok_or_else(|| format_err!("redirect ({}) without location", response.status()))?
+        .to_str()
+        .map_err(|_| format_err!("redirect location is not valid UTF-8"))?;
+
+    let uri = resolve_location(&parts.uri, location)?;
+    let supported = match uri.scheme_str() {
+        Some("http") | Some("https") => true,
+        // only stay on the same socket
+        Some(UNIX_SOCKET_SCHEME) => uri.authority() == parts.uri.authority(),
+        _ => false,
+    };
+    if !supported {
+        bail!("unsupported redirect location '{}'", uri);
+    }
+
+    // don't leak credentials to other hosts, the proxy authorization is added per request
+    if uri.authority() != parts.uri.authority() || uri.scheme() != parts.uri.scheme() {
+        let headers = std::mem::take(&mut parts.headers);
+        let mut name = None;
+        for (next, value) in headers {
+            name = next.or(name);
+            if let Some(name) = name.as_ref().filter(|n| CROSS_ORIGIN_HEADERS.contains(n)) {
+                parts.headers.append(name.clone(), value);
+            }
+        }
+    }
+    parts.uri = uri;
+
+    // like browsers, switch to GET for 303 and for POST requests redirected via 301 or 302
+    let status = response.status().as_u16();
+    let to_get = (status == 303 && parts.method != Method::HEAD)
+        || (matches!(status, 301 | 302) && parts.method == Method::POST);
+    if to_get {
+        parts.method = Method::GET;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+    }
+
+    Ok(to_get)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_location() {
+        let base: Uri = "https://example.com/api/v1/items?page=2".parse().unwrap();
+
+        let resolve = |location| resolve_location(&base, location).unwrap().to_string();
+        assert_eq!(resolve("https://other.com/x"), "https://other.com/x");
+        assert_eq!(resolve("/new"), "https://example.com/new");
+        assert_eq!(resolve("/new?a=b"), "https://example.com/new?a=b");
+        assert_eq!(resolve("other"), "https://example.com/api/v1/other");
+        assert_eq!(resolve("//cdn.example.com/x"), "https://cdn.example.com/x");
+    }
+
+    #[test]
+    fn test_follow_redirect() {
+        let (mut parts, ()) = http::Request::post("https://example.com/login")
+            .header(header::AUTHORIZATION, "secret")
+            .header(header::PROXY_AUTHORIZATION, "secret")
+            .header(header::COOKIE, "session=secret")
+            .header("X-API-Key", "secret")
+            .header(header::ACCEPT, "text/html")
+            .header(header::ACCEPT, "application/json")
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        let response = Response::builder()
+            .status(302)
+            .header(LOCATION, "/home")
+            .body(())
+            .unwrap();
+        assert!(follow_redirect(&mut parts, &response).unwrap());
+        assert_eq!(parts.method, Method::GET);
+        assert_eq!(parts.uri, "https://example.com/home");
+        assert_eq!(parts.headers.len(), 6);
+
+        let response = Response::builder()
+            .status(307)
+            .header(LOCATION, "https://cdn.example.org/home")
+            .body(())
+            .unwrap();
+        assert!(!follow_redirect(&mut parts, &response).unwrap());
+        assert_eq!(parts.headers.len(), 2);
+        let accept: Vec<_> = parts.headers.get_all(header::ACCEPT).iter().collect();
+        assert_eq!(accept, ["text/html", "application/json"]);
+    }
+
+    #[test]
+    fn test_retryable_error() {
+        let timeout = Error::from(AttemptTimeout(Duration::from_secs(1)));
+        assert!(is_retryable_error(&timeout));
+        assert_eq!(timeout.to_string(), "request timed out after 1s");
+
+        assert!(!is_retryable_error(&format_err!("invalid header value")));
+        assert!(!is_retryable_error(&Error::from(
+            openssl::error::ErrorStack::get()
+        )));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RequestPolicy::default()
+            .backoff(Duration::from_secs(1), Duration::from_secs(5))
+            .jitter(false);
+
+        assert_eq!(policy.backoff_delay(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff_delay(2), Duration::from_secs(4));
+        assert_eq!(policy.backoff_delay(3), Duration::from_secs(5));
+        assert_eq!(policy.backoff_delay(40), Duration::from_secs(5));
+
+        let response = Response::builder()
+            .status(503)
+            .header(RETRY_AFTER, "3")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            policy.retry_delay(0, Some(&response)),
+            Duration::from_secs(3)
+        );
+
+        let jittered = RequestPolicy::default().retry_delay::<()>(1, None);
+        assert!(jittered >= Duration::from_millis(500) && jittered <= Duration::from_secs(1));
+    }
+}
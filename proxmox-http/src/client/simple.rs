@@ -1,5 +1,7 @@
 use anyhow::{bail, format_err, Error};
 use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
 #[cfg(all(feature = "client-trait", feature = "proxmox-async"))]
 use std::str::FromStr;
@@ -8,18 +10,30 @@ use futures::*;
 #[cfg(all(feature = "client-trait", feature = "proxmox-async"))]
 use http::header::HeaderName;
 use http::{HeaderValue, Request, Response};
+use hyper::body::{Bytes, HttpBody};
 use hyper::client::Client as HyperClient;
 use hyper::client::HttpConnector;
 use hyper::Body;
-use openssl::ssl::{SslConnector, SslMethod};
-
+use openssl::hash::MessageDigest;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::X509StoreContextRef;
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+use crate::client::observer::{report_retry, CountingBody, RequestObserver};
+use crate::client::policy::{self, RequestPolicy};
+use crate::client::verify::{self, format_fingerprint, CertificatePinning};
 use crate::client::HttpsConnector;
-use crate::HttpOptions;
+use crate::uri::UNIX_SOCKET_SCHEME;
+use crate::{HttpOptions, ProgressCallback, ProgressReader, ProxyProtocol};
+
+/// Buffer size used when streaming a request body.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
 /// Asynchronous HTTP client implementation
 pub struct Client {
-    client: HyperClient<HttpsConnector, Body>,
+    client: HyperClient<HttpsConnector, CountingBody>,
     options: HttpOptions,
+    policy: Option<RequestPolicy>,
 }
 
 impl Client {
@@ -29,11 +43,48 @@ impl Client {
         Self::with_options(HttpOptions::default())
     }
 
+    /// Create a client with `options`.
+    ///
+    /// Additional CA certificates which cannot be read are logged and ignored, use
+    /// [`try_with_options`](Self::try_with_options) to fail instead.
     pub fn with_options(options: HttpOptions) -> Self {
-        let ssl_connector = SslConnector::builder(SslMethod::tls()).unwrap().build();
+        let ssl_connector = Self::ssl_connector(&options, true).unwrap();
         Self::with_ssl_connector(ssl_connector, options)
     }
 
+    /// Create a client with `options`, failing if the TLS options are invalid.
+    pub fn try_with_options(options: HttpOptions) -> Result<Self, Error> {
+        let ssl_connector = Self::ssl_connector(&options, false)?;
+        Ok(Self::with_ssl_connector(ssl_connector, options))
+    }
+
+    fn ssl_connector(options: &HttpOptions, ignore_ca_errors: bool) -> Result<SslConnector, Error> {
+        let mut builder = SslConnector::builder(SslMethod::tls())?;
+
+        for cert in verify::ca_certificates(options, ignore_ca_errors)? {
+            builder.cert_store_mut().add_cert(cert)?;
+        }
+
+        if let Some(pinning) = CertificatePinning::from_options(options) {
+            builder.set_verify_callback(SslVerifyMode::PEER, move |valid, ctx| {
+                valid || Self::leaf_fingerprint(ctx).is_some_and(|fp| pinning.accept(&fp))
+            });
+        }
+
+        Ok(builder.build())
+    }
+
+    /// The SHA-256 fingerprint of the server certificate being verified.
+    fn leaf_fingerprint(ctx: &X509StoreContextRef) -> Option<String> {
+        let cert = match ctx.chain().and_then(|chain| chain.get(0)) {
+            Some(cert) => cert,
+            None if ctx.error_depth() == 0 => ctx.current_cert()?,
+            None => return None,
+        };
+        let digest = cert.digest(MessageDigest::sha256()).ok()?;
+        Some(format_fingerprint(&digest))
+    }
+
     pub fn with_ssl_connector(ssl_connector: SslConnector, options: HttpOptions) -> Self {
         let connector = HttpConnector::new();
         let mut https = HttpsConnector::with_connector(
@@ -44,8 +95,29 @@ impl Client {
         if let Some(ref proxy_config) = options.proxy_config {
             https.set_proxy(proxy_config.clone());
         }
+        if let Some(ref proxy_config) = options.https_proxy_config {
+            https.set_https_proxy(proxy_config.clone());
+        }
+        if let Some(ref no_proxy) = options.no_proxy {
+            https.set_no_proxy(no_proxy.clone());
+        }
         let client = HyperClient::builder().build(https);
-        Self { client, options }
+        Self {
+            client,
+            options,
+            policy: None,
+        }
+    }
+
+    /// Builder-style method to apply a retry, redirect and timeout policy to all requests.
+    pub fn with_request_policy(mut self, policy: RequestPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Apply a retry, redirect and timeout policy to all requests.
+    pub fn set_request_policy(&mut self, policy: Option<RequestPolicy>) {
+        self.policy = policy;
     }
 
     pub fn set_user_agent(&mut self, user_agent: &str) -> Result<(), Error> {
@@ -54,18 +126,97 @@ impl Client {
     }
 
     fn add_proxy_headers(&self, request: &mut Request<Body>) -> Result<(), Error> {
-        if request.uri().scheme() != Some(&http::uri::Scheme::HTTPS) {
-            if let Some(ref authorization) = self.options.get_proxy_authorization() {
-                request.headers_mut().insert(
-                    http::header::PROXY_AUTHORIZATION,
-                    HeaderValue::from_str(authorization)?,
-                );
+        let scheme = request.uri().scheme_str();
+        if scheme != Some("https") && scheme != Some(UNIX_SOCKET_SCHEME) {
+            let host = request.uri().host().unwrap_or_default();
+            if let Some(proxy_config) = self.options.proxy_for(host, false) {
+                let is_http = proxy_config.protocol == ProxyProtocol::Http;
+                if let (true, false, Some(authorization)) = (
+                    is_http,
+                    proxy_config.force_connect,
+                    &proxy_config.authorization,
+                ) {
+                    request.headers_mut().insert(
+                        http::header::PROXY_AUTHORIZATION,
+                        HeaderValue::from_str(authorization)?,
+                    );
+                }
             }
         }
         Ok(())
     }
 
-    pub async fn request(&self, mut request: Request<Body>) -> Result<Response<Body>, Error> {
+    /// Send a request, applying the request policy if one is set.
+    pub async fn request(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
+        match &self.policy {
+            Some(policy) => self.request_with_policy(request, policy).await,
+            None => self.send(request).await,
+        }
+    }
+
+    /// Send a request, applying a specific request `policy`.
+    pub async fn request_with_policy(
+        &self,
+        request: Request<Body>,
+        policy: &RequestPolicy,
+    ) -> Result<Response<Body>, Error> {
+        let (mut parts, body) = request.into_parts();
+        let mut body = hyper::body::to_bytes(body).await?;
+
+        let mut retries = 0;
+        let mut redirects = 0;
+        loop {
+            let mut request = Request::new(Body::from(body.clone()));
+            *request.method_mut() = parts.method.clone();
+            *request.uri_mut() = parts.uri.clone();
+            *request.version_mut() = parts.version;
+            *request.headers_mut() = parts.headers.clone();
+
+            let result = match policy.attempt_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, self.send(request))
+                    .await
+                    .unwrap_or_else(|_| Err(policy::AttemptTimeout(timeout).into())),
+                None => self.send(request).await,
+            };
+
+            let retry = policy::is_idempotent(&parts.method) && retries < policy.max_retries;
+
+            let delay = match result {
+                Ok(response)
+                    if policy.max_redirects > 0 && policy::is_redirect(response.status()) =>
+                {
+                    if redirects >= policy.max_redirects {
+                        bail!("too many redirects (more than {})", policy.max_redirects);
+                    }
+                    redirects += 1;
+
+                    if policy::follow_redirect(&mut parts, &response)? {
+                        body.clear();
+                    }
+                    continue;
+                }
+                Ok(response) if retry && policy::is_retryable_status(response.status()) => {
+                    policy.retry_delay(retries, Some(&response))
+                }
+                Err(err) if retry && policy::is_retryable_error(&err) => {
+                    policy.retry_delay::<Body>(retries, None)
+                }
+                result => return result,
+            };
+
+            retries += 1;
+            report_retry(
+                self.options.instrumentation.as_ref(),
+                &parts.method,
+                &parts.uri,
+                retries,
+                delay,
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn send(&self, mut request: Request<Body>) -> Result<Response<Body>, Error> {
         let user_agent = if let Some(user_agent) = &self.options.user_agent {
             HeaderValue::from_str(user_agent)?
         } else {
@@ -78,7 +229,48 @@ impl Client {
 
         self.add_proxy_headers(&mut request)?;
 
-        self.client.request(request).map_err(Error::from).await
+        // the host is the encoded socket path, which is meaningless for the server
+        if request.uri().scheme_str() == Some(UNIX_SOCKET_SCHEME) {
+            request
+                .headers_mut()
+                .entry(hyper::header::HOST)
+                .or_insert(HeaderValue::from_static("localhost"));
+        }
+
+        let observer = RequestObserver::start(
+            self.options.instrumentation.as_ref(),
+            request.method(),
+            request.uri(),
+        );
+
+        let (parts, body) = request.into_parts();
+        let body = CountingBody::new(body, observer.clone());
+
+        let result = self
+            .client
+            .request(Request::from_parts(parts, body))
+            .map_err(Error::from)
+            .await;
+
+        let observer = match observer {
+            Some(observer) => observer,
+            None => return result,
+        };
+        observer.end(result.as_ref().map(|response| response.status()));
+        let response = result?;
+
+        // keep bodies of known size as they are and count them at once, a wrapped body would
+        // lose its size, e.g. when it gets forwarded
+        if let Some(size) = HttpBody::size_hint(response.body()).exact() {
+            if size > 0 {
+                observer.received(size);
+            }
+            return Ok(response);
+        }
+
+        Ok(response.map(|body| {
+            Body::wrap_stream(body.inspect_ok(move |chunk| observer.received(chunk.len() as u64)))
+        }))
     }
 
     pub async fn post(
@@ -131,6 +323,89 @@ impl Client {
         Self::response_body_string(res).await
     }
 
+    /// Send a `GET` request and return the response with a reader for its body, so that large
+    /// downloads need not be buffered in memory.
+    ///
+    /// `progress` is called while the body is read, with the total size taken from the
+    /// `Content-Length` header.
+    pub async fn get_stream(
+        &self,
+        uri: &str,
+        extra_headers: Option<&HashMap<String, String>>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Response<Box<dyn AsyncRead + Send + Unpin>>, Error> {
+        let mut request = Request::builder().method("GET").uri(uri);
+
+        if let Some(extra_headers) = extra_headers {
+            for (header, value) in extra_headers {
+                request = request.header(header, value);
+            }
+        }
+
+        let response = self.request(request.body(Body::empty())?).await?;
+
+        let total = response
+            .headers()
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok()?.parse().ok());
+
+        Ok(response.map(|body| {
+            let reader = BodyReader {
+                body,
+                chunk: Bytes::new(),
+            };
+            let boxed: Box<dyn AsyncRead + Send + Unpin> = match progress {
+                Some(progress) => Box::new(ProgressReader::new(reader, total, progress)),
+                None => Box::new(reader),
+            };
+            boxed
+        }))
+    }
+
+    /// Send a `POST` request with a body streamed from `body`.
+    ///
+    /// `progress` is called while the body is sent. Since the body cannot be sent again, the
+    /// request policy is not applied.
+    pub async fn post_stream<R>(
+        &self,
+        uri: &str,
+        body: R,
+        content_type: Option<&str>,
+        extra_headers: Option<&HashMap<String, String>>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Response<Body>, Error>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let reader: Box<dyn AsyncRead + Send + Unpin> = match progress {
+            Some(progress) => Box::new(ProgressReader::new(body, None, progress)),
+            None => Box::new(body),
+        };
+
+        let stream = stream::try_unfold(reader, |mut reader| async move {
+            let mut buf = vec![0u8; STREAM_CHUNK_SIZE];
+            let count = reader.read(&mut buf).await?;
+            if count == 0 {
+                return Ok::<_, std::io::Error>(None);
+            }
+            buf.truncate(count);
+            Ok(Some((Bytes::from(buf), reader)))
+        });
+
+        let mut request = Request::builder().method("POST").uri(uri).header(
+            hyper::header::CONTENT_TYPE,
+            content_type.unwrap_or("application/octet-stream"),
+        );
+
+        if let Some(extra_headers) = extra_headers {
+            for (header, value) in extra_headers {
+                request = request.header(header, value);
+            }
+        }
+
+        self.send(request.body(Body::wrap_stream(stream))?).await
+    }
+
     pub async fn response_body_string(res: Response<Body>) -> Result<String, Error> {
         Self::convert_body_to_string(Ok(res))
             .await
@@ -155,6 +430,34 @@ impl Client {
     }
 }
 
+/// Adapter to read a response body via [`AsyncRead`].
+struct BodyReader {
+    body: Body,
+    chunk: Bytes,
+}
+
+impl AsyncRead for BodyReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        while self.chunk.is_empty() {
+            match ready!(Pin::new(&mut self.body).poll_data(cx)) {
+                Some(Ok(chunk)) => self.chunk = chunk,
+                Some(Err(err)) => {
+                    return Poll::Ready(Err(std::io::Error::new(std::io::ErrorKind::Other, err)))
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let count = buf.remaining().min(self.chunk.len());
+        buf.put_slice(&self.chunk.split_to(count));
+        Poll::Ready(Ok(()))
+    }
+}
+
 impl Default for Client {
     fn default() -> Self {
         Self::new()
@@ -245,3 +548,119 @@ impl crate::HttpClient<String, String> for Client {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use anyhow::Error;
+    use http::{Request, StatusCode};
+    use hyper::body::HttpBody;
+    use hyper::Body;
+
+    use super::Client;
+    use crate::instrumentation::{HttpInstrumentation, RequestInfo};
+    use crate::HttpOptions;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl HttpInstrumentation for Recorder {
+        fn request_start(&self, request: &RequestInfo) {
+            let event = format!("start {} {}", request.method, request.uri.path());
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn request_end(&self, _: &RequestInfo, result: Result<StatusCode, &Error>, _: Duration) {
+            let event = format!("end {}", result.unwrap().as_u16());
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn bytes_sent(&self, _: &RequestInfo, count: u64) {
+            self.0.lock().unwrap().push(format!("sent {count}"));
+        }
+
+        fn bytes_received(&self, _: &RequestInfo, count: u64) {
+            self.0.lock().unwrap().push(format!("received {count}"));
+        }
+    }
+
+    /// Answer one request per connection, reading the request until it ends with `end`.
+    fn serve(responses: Vec<(&'static [u8], &'static [u8])>) -> (u16, std::thread::JoinHandle<()>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for (end, response) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(end) {
+                    let count = stream.read(&mut buf).unwrap();
+                    assert!(count > 0, "connection closed before request body");
+                    request.extend(&buf[..count]);
+                }
+                stream.write_all(response).unwrap();
+            }
+        });
+        (port, server)
+    }
+
+    #[tokio::test]
+    async fn test_instrumentation() {
+        let (port, server) = serve(vec![
+            (
+                b"hello",
+                b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+            ),
+            (
+                b"0\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+                  2\r\nok\r\n0\r\n\r\n",
+            ),
+        ]);
+
+        let recorder = Arc::new(Recorder::default());
+        let client = Client::with_options(HttpOptions {
+            instrumentation: Some(recorder.clone()),
+            ..Default::default()
+        });
+
+        // bodies of known size keep it
+        let request = Request::post(format!("http://127.0.0.1:{port}/upload"))
+            .body(Body::from("hello"))
+            .unwrap();
+        let response = client.request(request).await.unwrap();
+        assert_eq!(response.body().size_hint().exact(), Some(2));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"ok");
+
+        // streamed bodies are counted while they are transferred
+        let chunks: Vec<Result<_, std::io::Error>> = vec![Ok("hel"), Ok("lo")];
+        let request = Request::post(format!("http://127.0.0.1:{port}/stream"))
+            .body(Body::wrap_stream(futures::stream::iter(chunks)))
+            .unwrap();
+        let response = client.request(request).await.unwrap();
+        assert_eq!(response.body().size_hint().exact(), None);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"ok");
+
+        server.join().unwrap();
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "start POST /upload",
+                "sent 5",
+                "end 201",
+                "received 2",
+                "start POST /stream",
+                "sent 3",
+                "sent 2",
+                "end 200",
+                "received 2",
+            ]
+        );
+    }
+}
@@ -1,24 +1,149 @@
 use std::collections::HashMap;
 use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use anyhow::Error;
+use anyhow::{format_err, Error};
 use http::Response;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
 
+use crate::client::observer::{CountingReader, RequestObserver};
+use crate::client::verify::{self, format_fingerprint, CertificatePinning};
 use crate::HttpClient;
 use crate::HttpOptions;
+use crate::{HttpStreamClient, ProgressCallback, ProgressReader};
 
 #[derive(Default)]
 /// Blocking HTTP client for usage with [`HttpClient`].
+///
+/// Agents, and with them their pooled connections, are reused for all requests made with the
+/// same client instance.
 pub struct Client {
     options: HttpOptions,
+    agents: Mutex<AgentCache>,
+}
+
+#[derive(Default)]
+struct AgentCache {
+    /// Agents by the proxy they use, along with the time they were last used.
+    agents: HashMap<Option<String>, (ureq::Agent, Instant)>,
+    /// Shared by all agents, so TLS sessions can be resumed across them.
+    tls_config: Option<Arc<rustls::ClientConfig>>,
+}
+
+/// Verifies server certificates like rustls does by default, but accepts certificates which
+/// failed verification if they are accepted by the [`CertificatePinning`].
+struct PinningVerifier {
+    inner: Arc<rustls::client::WebPkiServerVerifier>,
+    pinning: CertificatePinning,
+}
+
+impl std::fmt::Debug for PinningVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PinningVerifier")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        ) {
+            Ok(verified) => Ok(verified),
+            Err(err) => {
+                let digest = openssl::sha::sha256(end_entity.as_ref());
+                if self.pinning.accept(&format_fingerprint(&digest)) {
+                    Ok(ServerCertVerified::assertion())
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
 }
 
 impl Client {
     pub fn new(options: HttpOptions) -> Self {
-        Self { options }
+        Self {
+            options,
+            agents: Mutex::new(AgentCache::default()),
+        }
+    }
+
+    fn agent(&self, uri: &str) -> Result<ureq::Agent, Error> {
+        let uri: http::Uri = uri.parse()?;
+        let is_https = uri.scheme() == Some(&http::uri::Scheme::HTTPS);
+        let host = uri.host().unwrap_or_default();
+
+        let proxy = match self.options.proxy_for(host, is_https) {
+            Some(proxy_config) => Some(proxy_config.to_proxy_string()?),
+            None => None,
+        };
+
+        let mut cache = self.agents.lock().unwrap();
+        let now = Instant::now();
+
+        if let Some((agent, last_used)) = cache.agents.get_mut(&proxy) {
+            let expired = self
+                .options
+                .agent_idle_timeout
+                .map(|timeout| now.duration_since(*last_used) > timeout)
+                .unwrap_or(false);
+
+            if !expired {
+                *last_used = now;
+                return Ok(agent.clone());
+            }
+        }
+
+        let agent = self.build_agent(proxy.as_deref(), &mut cache.tls_config)?;
+        cache.agents.insert(proxy, (agent.clone(), now));
+
+        Ok(agent)
     }
 
-    fn agent(&self) -> Result<ureq::Agent, Error> {
+    fn build_agent(
+        &self,
+        proxy: Option<&str>,
+        tls_config: &mut Option<Arc<rustls::ClientConfig>>,
+    ) -> Result<ureq::Agent, Error> {
         let mut builder = ureq::AgentBuilder::new();
 
         builder = builder.user_agent(self.options.user_agent.as_deref().unwrap_or(concat!(
@@ -26,22 +151,131 @@ impl Client {
             env!("CARGO_PKG_VERSION")
         )));
 
-        if let Some(proxy_config) = &self.options.proxy_config {
-            builder = builder.proxy(ureq::Proxy::new(proxy_config.to_proxy_string()?)?);
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(ureq::Proxy::new(proxy)?);
+        }
+
+        if let Some(max_idle) = self.options.pool_max_idle {
+            builder = builder.max_idle_connections(max_idle);
+        }
+
+        if let Some(max_idle_per_host) = self.options.pool_max_idle_per_host {
+            builder = builder.max_idle_connections_per_host(max_idle_per_host);
+        }
+
+        if Self::custom_tls_config(&self.options) {
+            let config = match tls_config {
+                Some(config) => Arc::clone(config),
+                None => tls_config.insert(Self::tls_config(&self.options)?).clone(),
+            };
+            builder = builder.tls_config(config);
         }
 
         Ok(builder.build())
     }
 
-    fn call(req: ureq::Request) -> Result<ureq::Response, Error> {
-        req.call().map_err(Into::into)
+    /// Whether `options` require a TLS configuration different from ureq's default one.
+    fn custom_tls_config(options: &HttpOptions) -> bool {
+        options.tls_session_cache_size.is_some()
+            || options.ca_file.is_some()
+            || options.ca_dir.is_some()
+            || options.fingerprint.is_some()
+            || options.verify_callback.is_some()
     }
 
-    fn send<R>(req: ureq::Request, body: R) -> Result<ureq::Response, Error>
+    /// A TLS configuration like ureq's default one, with the additional CA certificates,
+    /// certificate pinning and session cache size from `options`.
+    fn tls_config(options: &HttpOptions) -> Result<Arc<rustls::ClientConfig>, Error> {
+        let mut roots = rustls::RootCertStore::empty();
+        let certs = rustls_native_certs::load_native_certs()
+            .map_err(|err| format_err!("failed to load native root certificates - {}", err))?;
+        roots.add_parsable_certificates(certs);
+
+        for cert in verify::ca_certificates(options, false)? {
+            let cert = CertificateDer::from(cert.to_der()?);
+            roots
+                .add(cert)
+                .map_err(|err| format_err!("invalid additional CA certificate - {}", err))?;
+        }
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_protocol_versions(&[&rustls::version::TLS12, &rustls::version::TLS13])?;
+
+        let mut config = match CertificatePinning::from_options(options) {
+            Some(pinning) => {
+                let inner = rustls::client::WebPkiServerVerifier::builder_with_provider(
+                    Arc::new(roots),
+                    provider,
+                )
+                .build()?;
+                builder
+                    .dangerous()
+                    .with_custom_certificate_verifier(Arc::new(PinningVerifier { inner, pinning }))
+                    .with_no_client_auth()
+            }
+            None => builder.with_root_certificates(roots).with_no_client_auth(),
+        };
+
+        config.resumption = match options.tls_session_cache_size {
+            Some(0) => rustls::client::Resumption::disabled(),
+            Some(cache_size) => rustls::client::Resumption::in_memory_sessions(cache_size),
+            None => rustls::client::Resumption::default(),
+        };
+
+        Ok(Arc::new(config))
+    }
+
+    /// Start observing a request, if there is an instrumentation or tracing is enabled.
+    fn observe(&self, req: &ureq::Request) -> Option<Arc<RequestObserver>> {
+        let method = req.method().parse().ok()?;
+        let uri = req.url().parse().ok()?;
+        RequestObserver::start(self.options.instrumentation.as_ref(), &method, &uri)
+    }
+
+    fn call(&self, req: ureq::Request) -> Result<ObservedResponse, Error> {
+        let observer = self.observe(&req);
+        Self::finish(req.call(), observer)
+    }
+
+    fn send<R>(&self, req: ureq::Request, body: R) -> Result<ObservedResponse, Error>
     where
         R: Read,
     {
-        req.send(body).map_err(Into::into)
+        let observer = self.observe(&req);
+        let result = match observer {
+            Some(ref observer) => {
+                req.send(CountingReader::request_body(body, Arc::clone(observer)))
+            }
+            None => req.send(body),
+        };
+        Self::finish(result, observer)
+    }
+
+    fn finish(
+        result: Result<ureq::Response, ureq::Error>,
+        observer: Option<Arc<RequestObserver>>,
+    ) -> Result<ObservedResponse, Error> {
+        let status = match result {
+            Ok(ref res) | Err(ureq::Error::Status(_, ref res)) => {
+                http::StatusCode::from_u16(res.status()).ok()
+            }
+            Err(_) => None,
+        };
+        let result = result.map_err(Error::from);
+
+        if let Some(ref observer) = observer {
+            match (status, &result) {
+                (Some(status), _) => observer.end(Ok(status)),
+                (None, Err(err)) => observer.end(Err(err)),
+                (None, Ok(_)) => observer.end(Err(&format_err!("invalid response status"))),
+            }
+        }
+
+        Ok(ObservedResponse {
+            response: result?,
+            observer,
+        })
     }
 
     fn convert_response(res: &ureq::Response) -> Result<http::response::Builder, Error> {
@@ -75,25 +309,63 @@ impl Client {
         req
     }
 
-    fn convert_response_to_string(res: ureq::Response) -> Result<Response<String>, Error> {
-        let builder = Self::convert_response(&res)?;
-        let body = res.into_string()?;
+    fn convert_response_to_string(res: ObservedResponse) -> Result<Response<String>, Error> {
+        let builder = Self::convert_response(&res.response)?;
+        let body = res.response.into_string()?;
+        if let Some(observer) = res.observer {
+            observer.received(body.len() as u64);
+        }
         builder.body(body).map_err(Into::into)
     }
 
-    fn convert_response_to_vec(res: ureq::Response) -> Result<Response<Vec<u8>>, Error> {
-        let builder = Self::convert_response(&res)?;
+    fn convert_response_to_vec(res: ObservedResponse) -> Result<Response<Vec<u8>>, Error> {
+        let builder = Self::convert_response(&res.response)?;
         let mut body = Vec::new();
-        res.into_reader().read_to_end(&mut body)?;
+        res.response.into_reader().read_to_end(&mut body)?;
+        if let Some(observer) = res.observer {
+            observer.received(body.len() as u64);
+        }
         builder.body(body).map_err(Into::into)
     }
 
-    fn convert_response_to_reader(res: ureq::Response) -> Result<Response<Box<dyn Read>>, Error> {
-        let builder = Self::convert_response(&res)?;
+    fn convert_response_to_stream(
+        res: ObservedResponse,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Response<Box<dyn Read + Send>>, Error> {
+        let builder = Self::convert_response(&res.response)?;
+        let total = res
+            .response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok());
         let reader = res.into_reader();
-        let boxed: Box<dyn Read> = Box::new(reader);
+        let boxed: Box<dyn Read + Send> = match progress {
+            Some(progress) => Box::new(ProgressReader::new(reader, total, progress)),
+            None => reader,
+        };
         builder.body(boxed).map_err(Into::into)
     }
+
+    fn convert_response_to_reader(res: ObservedResponse) -> Result<Response<Box<dyn Read>>, Error> {
+        let builder = Self::convert_response(&res.response)?;
+        let boxed: Box<dyn Read> = res.into_reader();
+        builder.body(boxed).map_err(Into::into)
+    }
+}
+
+/// A response along with the observer of its request.
+struct ObservedResponse {
+    response: ureq::Response,
+    observer: Option<Arc<RequestObserver>>,
+}
+
+impl ObservedResponse {
+    fn into_reader(self) -> Box<dyn Read + Send> {
+        let reader = self.response.into_reader();
+        match self.observer {
+            Some(observer) => Box::new(CountingReader::response_body(reader, observer)),
+            None => reader,
+        }
+    }
 }
 
 impl HttpClient<String, String> for Client {
@@ -102,10 +374,10 @@ impl HttpClient<String, String> for Client {
         uri: &str,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<Response<String>, Error> {
-        let req = self.agent()?.get(uri);
+        let req = self.agent(uri)?.get(uri);
         let req = Self::add_headers(req, None, extra_headers);
 
-        Self::call(req).and_then(Self::convert_response_to_string)
+        self.call(req).and_then(Self::convert_response_to_string)
     }
 
     fn post(
@@ -115,20 +387,19 @@ impl HttpClient<String, String> for Client {
         content_type: Option<&str>,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<Response<String>, Error> {
-        let req = self.agent()?.post(uri);
+        let req = self.agent(uri)?.post(uri);
         let req = Self::add_headers(req, content_type, extra_headers);
 
         match body {
-            Some(body) => Self::send(req, body.as_bytes()),
-            None => Self::call(req),
+            Some(body) => self.send(req, body.as_bytes()),
+            None => self.call(req),
         }
         .and_then(Self::convert_response_to_string)
     }
 
     fn request(&self, request: http::Request<String>) -> Result<Response<String>, Error> {
-        let mut req = self
-            .agent()?
-            .request(request.method().as_str(), &request.uri().to_string());
+        let uri = request.uri().to_string();
+        let mut req = self.agent(&uri)?.request(request.method().as_str(), &uri);
 
         let orig_headers = request.headers();
 
@@ -138,7 +409,8 @@ impl HttpClient<String, String> for Client {
             }
         }
 
-        Self::send(req, request.body().as_bytes()).and_then(Self::convert_response_to_string)
+        self.send(req, request.body().as_bytes())
+            .and_then(Self::convert_response_to_string)
     }
 }
 
@@ -148,10 +420,10 @@ impl HttpClient<&[u8], Vec<u8>> for Client {
         uri: &str,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<Response<Vec<u8>>, Error> {
-        let req = self.agent()?.get(uri);
+        let req = self.agent(uri)?.get(uri);
         let req = Self::add_headers(req, None, extra_headers);
 
-        Self::call(req).and_then(Self::convert_response_to_vec)
+        self.call(req).and_then(Self::convert_response_to_vec)
     }
 
     fn post(
@@ -161,20 +433,19 @@ impl HttpClient<&[u8], Vec<u8>> for Client {
         content_type: Option<&str>,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<Response<Vec<u8>>, Error> {
-        let req = self.agent()?.post(uri);
+        let req = self.agent(uri)?.post(uri);
         let req = Self::add_headers(req, content_type, extra_headers);
 
         match body {
-            Some(body) => Self::send(req, body),
-            None => Self::call(req),
+            Some(body) => self.send(req, body),
+            None => self.call(req),
         }
         .and_then(Self::convert_response_to_vec)
     }
 
     fn request(&self, request: http::Request<&[u8]>) -> Result<Response<Vec<u8>>, Error> {
-        let mut req = self
-            .agent()?
-            .request(request.method().as_str(), &request.uri().to_string());
+        let uri = request.uri().to_string();
+        let mut req = self.agent(&uri)?.request(request.method().as_str(), &uri);
 
         let orig_headers = request.headers();
 
@@ -184,7 +455,8 @@ impl HttpClient<&[u8], Vec<u8>> for Client {
             }
         }
 
-        Self::send(req, *request.body()).and_then(Self::convert_response_to_vec)
+        self.send(req, *request.body())
+            .and_then(Self::convert_response_to_vec)
     }
 }
 
@@ -194,10 +466,10 @@ impl HttpClient<Box<dyn Read>, Box<dyn Read>> for Client {
         uri: &str,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<Response<Box<dyn Read>>, Error> {
-        let req = self.agent()?.get(uri);
+        let req = self.agent(uri)?.get(uri);
         let req = Self::add_headers(req, None, extra_headers);
 
-        Self::call(req).and_then(Self::convert_response_to_reader)
+        self.call(req).and_then(Self::convert_response_to_reader)
     }
 
     fn post(
@@ -207,12 +479,12 @@ impl HttpClient<Box<dyn Read>, Box<dyn Read>> for Client {
         content_type: Option<&str>,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<Response<Box<dyn Read>>, Error> {
-        let req = self.agent()?.post(uri);
+        let req = self.agent(uri)?.post(uri);
         let req = Self::add_headers(req, content_type, extra_headers);
 
         match body {
-            Some(body) => Self::send(req, body),
-            None => Self::call(req),
+            Some(body) => self.send(req, body),
+            None => self.call(req),
         }
         .and_then(Self::convert_response_to_reader)
     }
@@ -221,9 +493,8 @@ impl HttpClient<Box<dyn Read>, Box<dyn Read>> for Client {
         &self,
         mut request: http::Request<Box<dyn Read>>,
     ) -> Result<Response<Box<dyn Read>>, Error> {
-        let mut req = self
-            .agent()?
-            .request(request.method().as_str(), &request.uri().to_string());
+        let uri = request.uri().to_string();
+        let mut req = self.agent(&uri)?.request(request.method().as_str(), &uri);
         let orig_headers = request.headers();
 
         for header in orig_headers.keys() {
@@ -232,6 +503,211 @@ impl HttpClient<Box<dyn Read>, Box<dyn Read>> for Client {
             }
         }
 
-        Self::send(req, Box::new(request.body_mut())).and_then(Self::convert_response_to_reader)
+        self.send(req, Box::new(request.body_mut()))
+            .and_then(Self::convert_response_to_reader)
+    }
+}
+
+impl HttpStreamClient for Client {
+    fn get_stream(
+        &self,
+        uri: &str,
+        extra_headers: Option<&HashMap<String, String>>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Response<Box<dyn Read + Send>>, Error> {
+        let req = self.agent(uri)?.get(uri);
+        let req = Self::add_headers(req, None, extra_headers);
+
+        self.call(req)
+            .and_then(|res| Self::convert_response_to_stream(res, progress))
+    }
+
+    fn post_stream(
+        &self,
+        uri: &str,
+        body: Box<dyn Read + Send>,
+        content_type: Option<&str>,
+        extra_headers: Option<&HashMap<String, String>>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Response<Box<dyn Read + Send>>, Error> {
+        let req = self.agent(uri)?.post(uri);
+        let req = Self::add_headers(req, content_type, extra_headers);
+
+        match progress {
+            Some(progress) => self.send(req, ProgressReader::new(body, None, progress)),
+            None => self.send(req, body),
+        }
+        .and_then(|res| Self::convert_response_to_stream(res, None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use anyhow::Error;
+    use http::StatusCode;
+
+    use super::Client;
+    use crate::instrumentation::{HttpInstrumentation, RequestInfo};
+    use crate::{HttpClient, HttpOptions};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl HttpInstrumentation for Recorder {
+        fn request_start(&self, request: &RequestInfo) {
+            let event = format!("start {} {}", request.method, request.uri.path());
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn request_end(&self, _: &RequestInfo, result: Result<StatusCode, &Error>, _: Duration) {
+            let event = format!("end {}", result.unwrap().as_u16());
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn bytes_sent(&self, _: &RequestInfo, count: u64) {
+            self.0.lock().unwrap().push(format!("sent {count}"));
+        }
+
+        fn bytes_received(&self, _: &RequestInfo, count: u64) {
+            self.0.lock().unwrap().push(format!("received {count}"));
+        }
+    }
+
+    #[test]
+    fn test_instrumentation() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"hello") {
+                let count = stream.read(&mut buf).unwrap();
+                assert!(count > 0, "connection closed before request body");
+                request.extend(&buf[..count]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap();
+        });
+
+        let recorder = Arc::new(Recorder::default());
+        let client = Client::new(HttpOptions {
+            instrumentation: Some(recorder.clone()),
+            ..Default::default()
+        });
+
+        let mut headers = std::collections::HashMap::new();
+        headers.insert("Content-Length".to_string(), "5".to_string());
+        let response: http::Response<String> = client
+            .post(
+                &format!("http://127.0.0.1:{port}/upload"),
+                Some("hello".to_string()),
+                None,
+                Some(&headers),
+            )
+            .unwrap();
+        assert_eq!(response.body(), "ok");
+        server.join().unwrap();
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            ["start POST /upload", "sent 5", "end 201", "received 2"]
+        );
+    }
+
+    fn self_signed_certificate(name: &str) -> openssl::x509::X509 {
+        use openssl::ec::{EcGroup, EcKey};
+        use openssl::nid::Nid;
+        use openssl::pkey::PKey;
+        use openssl::x509::{X509Builder, X509NameBuilder};
+
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_issuer_name(&subject).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        let not_before = openssl::asn1::Asn1Time::days_from_now(0).unwrap();
+        let not_after = openssl::asn1::Asn1Time::days_from_now(1).unwrap();
+        builder.set_not_before(&not_before).unwrap();
+        builder.set_not_after(&not_after).unwrap();
+        builder
+            .sign(&key, openssl::hash::MessageDigest::sha256())
+            .unwrap();
+        builder.build()
+    }
+
+    #[test]
+    fn test_custom_tls_config() {
+        let mut options = HttpOptions::default();
+        assert!(!Client::custom_tls_config(&options));
+
+        for cache_size in [0, 16] {
+            options.tls_session_cache_size = Some(cache_size);
+            assert!(Client::custom_tls_config(&options));
+            Client::tls_config(&options).unwrap();
+        }
+
+        // the config is built once and shared by the agents
+        let client = Client::new(options);
+        client.agent("https://example.com/").unwrap();
+        assert!(client.agents.lock().unwrap().tls_config.is_some());
+
+        let options = HttpOptions {
+            ca_file: Some("/nonexistent/ca.pem".into()),
+            ..Default::default()
+        };
+        assert!(Client::custom_tls_config(&options));
+        assert!(Client::tls_config(&options).is_err());
+    }
+
+    #[test]
+    fn test_pinning_verifier() {
+        use rustls::client::danger::ServerCertVerifier;
+        use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+
+        use super::{format_fingerprint, CertificatePinning, PinningVerifier};
+
+        let ca = self_signed_certificate("Test CA");
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(CertificateDer::from(ca.to_der().unwrap()))
+            .unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let inner =
+            rustls::client::WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .unwrap();
+
+        let cert = CertificateDer::from(self_signed_certificate("localhost").to_der().unwrap());
+        let fingerprint = format_fingerprint(&openssl::sha::sha256(cert.as_ref()));
+        let server_name = ServerName::try_from("localhost").unwrap();
+
+        let verify = |fingerprint: &str| {
+            let options = HttpOptions {
+                fingerprint: Some(fingerprint.to_string()),
+                ..Default::default()
+            };
+            let verifier = PinningVerifier {
+                inner: Arc::clone(&inner),
+                pinning: CertificatePinning::from_options(&options).unwrap(),
+            };
+            verifier.verify_server_cert(&cert, &[], &server_name, &[], UnixTime::now())
+        };
+
+        assert!(verify(&fingerprint).is_ok());
+        assert!(verify(&fingerprint.to_uppercase()).is_ok());
+        assert!(verify(&format_fingerprint(&[0u8; 32])).is_err());
     }
 }
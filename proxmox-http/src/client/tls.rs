@@ -6,6 +6,7 @@ use std::task::{Context, Poll};
 
 use hyper::client::connect::{Connected, Connection};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::UnixStream;
 use tokio_openssl::SslStream;
 
 /// Asynchronous stream, possibly encrypted and proxied
@@ -15,6 +16,8 @@ pub enum MaybeTlsStream<S> {
     Normal(S),
     Proxied(S),
     Secured(SslStream<S>),
+    /// Connection to a local unix domain socket
+    Unix(UnixStream),
 }
 
 impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for MaybeTlsStream<S> {
@@ -27,6 +30,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for MaybeTlsStream<S> {
             MaybeTlsStream::Normal(ref mut s) => Pin::new(s).poll_read(cx, buf),
             MaybeTlsStream::Proxied(ref mut s) => Pin::new(s).poll_read(cx, buf),
             MaybeTlsStream::Secured(ref mut s) => Pin::new(s).poll_read(cx, buf),
+            MaybeTlsStream::Unix(ref mut s) => Pin::new(s).poll_read(cx, buf),
         }
     }
 }
@@ -41,6 +45,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for MaybeTlsStream<S> {
             MaybeTlsStream::Normal(ref mut s) => Pin::new(s).poll_write(cx, buf),
             MaybeTlsStream::Proxied(ref mut s) => Pin::new(s).poll_write(cx, buf),
             MaybeTlsStream::Secured(ref mut s) => Pin::new(s).poll_write(cx, buf),
+            MaybeTlsStream::Unix(ref mut s) => Pin::new(s).poll_write(cx, buf),
         }
     }
 
@@ -53,6 +58,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for MaybeTlsStream<S> {
             MaybeTlsStream::Normal(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
             MaybeTlsStream::Proxied(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
             MaybeTlsStream::Secured(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            MaybeTlsStream::Unix(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
         }
     }
 
@@ -61,6 +67,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for MaybeTlsStream<S> {
             MaybeTlsStream::Normal(s) => s.is_write_vectored(),
             MaybeTlsStream::Proxied(s) => s.is_write_vectored(),
             MaybeTlsStream::Secured(s) => s.is_write_vectored(),
+            MaybeTlsStream::Unix(s) => s.is_write_vectored(),
         }
     }
 
@@ -69,6 +76,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for MaybeTlsStream<S> {
             MaybeTlsStream::Normal(ref mut s) => Pin::new(s).poll_flush(cx),
             MaybeTlsStream::Proxied(ref mut s) => Pin::new(s).poll_flush(cx),
             MaybeTlsStream::Secured(ref mut s) => Pin::new(s).poll_flush(cx),
+            MaybeTlsStream::Unix(ref mut s) => Pin::new(s).poll_flush(cx),
         }
     }
 
@@ -77,6 +85,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for MaybeTlsStream<S> {
             MaybeTlsStream::Normal(ref mut s) => Pin::new(s).poll_shutdown(cx),
             MaybeTlsStream::Proxied(ref mut s) => Pin::new(s).poll_shutdown(cx),
             MaybeTlsStream::Secured(ref mut s) => Pin::new(s).poll_shutdown(cx),
+            MaybeTlsStream::Unix(ref mut s) => Pin::new(s).poll_shutdown(cx),
         }
     }
 }
@@ -95,6 +104,7 @@ impl<S: Connection + AsyncRead + AsyncWrite + Unpin> Connection for MaybeTlsStre
                     connected
                 }
             }
+            MaybeTlsStream::Unix(_) => Connected::new(),
         }
     }
 }
@@ -0,0 +1,149 @@
+//! Additional CA certificates and fingerprint pinning for server certificates.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Error};
+use openssl::x509::X509;
+
+use crate::http_options::CertificateVerifyCallback;
+use crate::HttpOptions;
+
+/// The `*.pem` and `*.crt` files in a CA directory.
+fn ca_dir_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format_err!("unable to read CA directory {:?} - {}", dir, err))?;
+    for entry in entries {
+        let path = entry?.path();
+        let is_cert = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("pem") | Some("crt")
+        );
+        if is_cert && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Read the certificates of a PEM encoded CA file.
+fn read_ca_file(path: &Path) -> Result<Vec<X509>, Error> {
+    let data = std::fs::read(path)
+        .map_err(|err| format_err!("unable to read CA file {:?} - {}", path, err))?;
+    X509::stack_from_pem(&data)
+        .map_err(|err| format_err!("unable to parse CA file {:?} - {}", path, err))
+}
+
+/// The additional trusted CA certificates from `ca_file` and `ca_dir`.
+///
+/// With `ignore_errors`, files and directories which cannot be read are logged and skipped.
+pub(crate) fn ca_certificates(
+    options: &HttpOptions,
+    ignore_errors: bool,
+) -> Result<Vec<X509>, Error> {
+    let mut files: Vec<PathBuf> = options.ca_file.iter().cloned().collect();
+
+    if let Some(ref dir) = options.ca_dir {
+        match ca_dir_files(dir) {
+            Ok(mut dir_files) => files.append(&mut dir_files),
+            Err(err) if ignore_errors => log::warn!("ignoring additional CA certificates: {err}"),
+            Err(err) => return Err(err),
+        }
+    }
+
+    let mut certs = Vec::new();
+    for file in files {
+        match read_ca_file(&file) {
+            Ok(mut file_certs) => certs.append(&mut file_certs),
+            Err(err) if ignore_errors => log::warn!("ignoring additional CA certificates: {err}"),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(certs)
+}
+
+/// Format a certificate digest as lower case hex bytes separated by colons.
+pub(crate) fn format_fingerprint(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint.replace(':', "").to_ascii_lowercase()
+}
+
+/// Checks for server certificates which failed verification, see [`HttpOptions::fingerprint`] and
+/// [`HttpOptions::verify_callback`].
+#[derive(Clone)]
+pub(crate) struct CertificatePinning {
+    fingerprint: Option<String>,
+    callback: Option<CertificateVerifyCallback>,
+}
+
+impl CertificatePinning {
+    /// The checks configured in `options`, if any.
+    pub(crate) fn from_options(options: &HttpOptions) -> Option<Self> {
+        if options.fingerprint.is_none() && options.verify_callback.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            fingerprint: options.fingerprint.as_deref().map(normalize_fingerprint),
+            callback: options.verify_callback.clone(),
+        })
+    }
+
+    /// Check whether a certificate with the SHA-256 `fingerprint` is accepted anyway.
+    pub(crate) fn accept(&self, fingerprint: &str) -> bool {
+        if self.fingerprint.as_deref() == Some(&normalize_fingerprint(fingerprint)) {
+            return true;
+        }
+
+        match self.callback {
+            Some(ref callback) => callback(fingerprint),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_certificate_pinning() {
+        let fingerprint = format_fingerprint(&[0xab, 0x01, 0xff]);
+        assert_eq!(fingerprint, "ab:01:ff");
+
+        let mut options = HttpOptions::default();
+        assert!(CertificatePinning::from_options(&options).is_none());
+
+        options.fingerprint = Some("AB:01:FF".to_string());
+        let pinning = CertificatePinning::from_options(&options).unwrap();
+        assert!(pinning.accept(&fingerprint));
+        assert!(!pinning.accept("ab:01:fe"));
+
+        options.verify_callback = Some(Arc::new(|fp| fp == "ab:01:fe"));
+        let pinning = CertificatePinning::from_options(&options).unwrap();
+        assert!(pinning.accept("ab:01:fe"));
+        assert!(!pinning.accept("00:00:00"));
+    }
+
+    #[test]
+    fn test_ca_certificates() {
+        let mut options = HttpOptions::default();
+        assert!(ca_certificates(&options, false).unwrap().is_empty());
+
+        options.ca_file = Some("/nonexistent/ca.pem".into());
+        options.ca_dir = Some("/nonexistent/certs".into());
+        assert!(ca_certificates(&options, false).is_err());
+        assert!(ca_certificates(&options, true).unwrap().is_empty());
+    }
+}
@@ -1,8 +1,11 @@
 use std::collections::HashMap;
+use std::io::Read;
 
 use anyhow::Error;
 use http::{Request, Response};
 
+use crate::ProgressCallback;
+
 pub trait HttpClient<RequestBody, ResponseBody> {
     fn get(
         &self,
@@ -20,3 +23,31 @@ pub trait HttpClient<RequestBody, ResponseBody> {
 
     fn request(&self, request: Request<RequestBody>) -> Result<Response<ResponseBody>, Error>;
 }
+
+/// Streaming counterpart of [`HttpClient`], for bodies which should not be buffered in memory,
+/// like ISO images or appliance templates.
+pub trait HttpStreamClient {
+    /// Send a `GET` request and return the response with a reader for its body.
+    ///
+    /// `progress` is called while the body is read, with the total size taken from the
+    /// `Content-Length` header.
+    fn get_stream(
+        &self,
+        uri: &str,
+        extra_headers: Option<&HashMap<String, String>>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Response<Box<dyn Read + Send>>, Error>;
+
+    /// Send a `POST` request with a body read from `body` and return the response with a reader
+    /// for its body.
+    ///
+    /// `progress` is called while the request body is sent.
+    fn post_stream(
+        &self,
+        uri: &str,
+        body: Box<dyn Read + Send>,
+        content_type: Option<&str>,
+        extra_headers: Option<&HashMap<String, String>>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Response<Box<dyn Read + Send>>, Error>;
+}
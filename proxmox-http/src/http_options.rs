@@ -1,24 +1,105 @@
-use crate::ProxyConfig;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Error;
+
+use crate::instrumentation::HttpInstrumentation;
+use crate::proxy_config::NoProxy;
+use crate::{ProxyConfig, ProxyProtocol};
+
+/// Callback deciding whether a server certificate which failed verification is accepted anyway.
+///
+/// It is called with the SHA-256 fingerprint of the certificate, formatted as lower case hex bytes
+/// separated by colons.
+pub type CertificateVerifyCallback = Arc<dyn Fn(&str) -> bool + Send + Sync>;
 
 /// Options for an HTTP client.
 #[derive(Default)]
 pub struct HttpOptions {
     /// Proxy configuration
     pub proxy_config: Option<ProxyConfig>,
+    /// Proxy configuration for HTTPS requests, `proxy_config` is used if this is not set
+    pub https_proxy_config: Option<ProxyConfig>,
+    /// Hosts which are accessed directly, even if a proxy is configured
+    pub no_proxy: Option<NoProxy>,
     /// `User-Agent` header value
     pub user_agent: Option<String>,
     /// TCP keepalive time, defaults to 7200
     pub tcp_keepalive: Option<u32>,
+    /// Maximum number of idle connections kept for reuse, only used by the sync client
+    pub pool_max_idle: Option<usize>,
+    /// Maximum number of idle connections kept for reuse per host, only used by the sync client
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Lifetime of unused HTTP agents, only used by the sync client
+    ///
+    /// Agents are cached per proxy and reused for all requests. An agent which was not used for
+    /// this long is dropped along with all its pooled connections, and a new one is created for
+    /// the next request. Pooled connections cannot expire on their own, as ureq does not support
+    /// an idle timeout for them.
+    pub agent_idle_timeout: Option<Duration>,
+    /// Number of TLS sessions cached for resumption, `0` disables resumption, only used by the
+    /// sync client
+    pub tls_session_cache_size: Option<usize>,
+    /// PEM file with CA certificates trusted in addition to the system's ones
+    pub ca_file: Option<PathBuf>,
+    /// Directory with `*.pem` and `*.crt` CA certificates trusted in addition to the system's ones
+    pub ca_dir: Option<PathBuf>,
+    /// SHA-256 fingerprint of a server certificate which is accepted even if it cannot be
+    /// verified, e.g. because it is self-signed
+    pub fingerprint: Option<String>,
+    /// Called for server certificates which cannot be verified and do not match `fingerprint`
+    pub verify_callback: Option<CertificateVerifyCallback>,
+    /// Hooks called for every request, e.g. to collect metrics
+    pub instrumentation: Option<Arc<dyn HttpInstrumentation>>,
 }
 
 impl HttpOptions {
+    /// Create options with the proxy configuration from the environment.
+    ///
+    /// `http_proxy`/`HTTP_PROXY` and `https_proxy`/`HTTPS_PROXY` are used for the respective
+    /// request schemes, with `all_proxy`/`ALL_PROXY` as fallback for both. Hosts listed in
+    /// `no_proxy`/`NO_PROXY` are accessed directly, see [NoProxy] for the supported syntax.
+    /// The lower case variants take precedence. Like with manually set options, the HTTP proxy
+    /// is also used for HTTPS requests if no HTTPS proxy is set. SOCKS5 proxies can be used with
+    /// a `socks5://` URL.
+    pub fn from_env() -> Result<Self, Error> {
+        let all_proxy = ProxyConfig::from_env_vars(&["all_proxy", "ALL_PROXY"])?;
+        let http_proxy = ProxyConfig::from_env_vars(&["http_proxy", "HTTP_PROXY"])?;
+        let https_proxy = ProxyConfig::from_env_vars(&["https_proxy", "HTTPS_PROXY"])?;
+
+        Ok(Self {
+            proxy_config: http_proxy.or_else(|| all_proxy.clone()),
+            https_proxy_config: https_proxy.or(all_proxy),
+            no_proxy: NoProxy::from_env()?,
+            ..Default::default()
+        })
+    }
+
     pub fn get_proxy_authorization(&self) -> Option<String> {
         if let Some(ref proxy_config) = self.proxy_config {
-            if !proxy_config.force_connect {
+            if proxy_config.protocol == ProxyProtocol::Http && !proxy_config.force_connect {
                 return proxy_config.authorization.clone();
             }
         }
 
         None
     }
+
+    /// Returns the proxy to use for a request to `host`, if any.
+    pub fn proxy_for(&self, host: &str, is_https: bool) -> Option<&ProxyConfig> {
+        if let Some(ref no_proxy) = self.no_proxy {
+            if no_proxy.matches(host) {
+                return None;
+            }
+        }
+
+        if is_https {
+            self.https_proxy_config
+                .as_ref()
+                .or(self.proxy_config.as_ref())
+        } else {
+            self.proxy_config.as_ref()
+        }
+    }
 }
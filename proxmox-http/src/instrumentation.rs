@@ -0,0 +1,49 @@
+//! Observability hooks for the HTTP clients.
+//!
+//! An [`HttpInstrumentation`] set in [`HttpOptions`](crate::HttpOptions) is called by the sync
+//! and the async client for every request, so that products can export metrics without wrapping
+//! every call site. With the `tracing` feature, the clients additionally emit a span for every
+//! request.
+
+use std::time::Duration;
+
+use anyhow::Error;
+use http::{Method, StatusCode, Uri};
+
+/// A request observed by an [`HttpInstrumentation`].
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    pub method: Method,
+    pub uri: Uri,
+}
+
+/// Callbacks invoked by the HTTP clients, all of them do nothing by default.
+pub trait HttpInstrumentation: Send + Sync {
+    /// Called before a request is sent.
+    ///
+    /// Retries and the redirects followed by the request policy of the async client are separate
+    /// requests. The sync client follows redirects internally, they are reported as one request
+    /// with the status of the final response.
+    fn request_start(&self, _request: &RequestInfo) {}
+
+    /// Called once the response headers were received or the request failed.
+    fn request_end(
+        &self,
+        _request: &RequestInfo,
+        _result: Result<StatusCode, &Error>,
+        _duration: Duration,
+    ) {
+    }
+
+    /// Called before a request is retried, with the number of the retry (starting at 1) and the
+    /// delay before it is sent.
+    fn request_retry(&self, _request: &RequestInfo, _retry: u32, _delay: Duration) {}
+
+    /// Called with the number of request body bytes sent, possibly multiple times per request.
+    fn bytes_sent(&self, _request: &RequestInfo, _count: u64) {}
+
+    /// Called with the number of response body bytes received, possibly multiple times per
+    /// request. The async client reports response bodies of known size at once when the response
+    /// headers were received.
+    fn bytes_received(&self, _request: &RequestInfo, _count: u64) {}
+}
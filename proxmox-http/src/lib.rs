@@ -9,12 +9,17 @@ pub mod uri;
 #[cfg(feature = "http-helpers")]
 pub mod proxy_config;
 #[cfg(feature = "http-helpers")]
-pub use proxy_config::ProxyConfig;
+pub use proxy_config::{ProxyConfig, ProxyProtocol};
+
+#[cfg(feature = "http-helpers")]
+pub mod instrumentation;
+#[cfg(feature = "http-helpers")]
+pub use instrumentation::HttpInstrumentation;
 
 #[cfg(feature = "http-helpers")]
 mod http_options;
 #[cfg(feature = "http-helpers")]
-pub use http_options::HttpOptions;
+pub use http_options::{CertificateVerifyCallback, HttpOptions};
 
 #[cfg(any(feature = "client", feature = "client-sync"))]
 pub mod client;
@@ -22,7 +27,12 @@ pub mod client;
 #[cfg(feature = "client-trait")]
 mod client_trait;
 #[cfg(feature = "client-trait")]
-pub use client_trait::HttpClient;
+pub use client_trait::{HttpClient, HttpStreamClient};
+
+#[cfg(any(feature = "client", feature = "client-trait"))]
+mod progress;
+#[cfg(any(feature = "client", feature = "client-trait"))]
+pub use progress::{ProgressCallback, ProgressReader};
 
 #[cfg(feature = "rate-limiter")]
 mod rate_limiter;
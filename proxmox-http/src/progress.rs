@@ -0,0 +1,97 @@
+//! Progress reporting for streamed request and response bodies.
+
+use std::io::{self, Read};
+
+/// Called with the number of bytes transferred so far and the total size, if known.
+pub type ProgressCallback = Box<dyn FnMut(u64, Option<u64>) + Send>;
+
+/// A reader which reports the number of bytes read through it to a [`ProgressCallback`].
+///
+/// Implements [`Read`], and [`AsyncRead`](tokio::io::AsyncRead) with the `client` feature, if the
+/// inner reader does.
+pub struct ProgressReader<R> {
+    inner: R,
+    transferred: u64,
+    total: Option<u64>,
+    callback: ProgressCallback,
+}
+
+impl<R> ProgressReader<R> {
+    /// Wrap `inner`, whose size is `total`, if known.
+    pub fn new(inner: R, total: Option<u64>, callback: ProgressCallback) -> Self {
+        Self {
+            inner,
+            transferred: 0,
+            total,
+            callback,
+        }
+    }
+
+    /// The number of bytes read so far.
+    pub fn transferred(&self) -> u64 {
+        self.transferred
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn advance(&mut self, count: usize) {
+        if count > 0 {
+            self.transferred += count as u64;
+            (self.callback)(self.transferred, self.total);
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.advance(count);
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "client")]
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for ProgressReader<R> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        futures::ready!(std::pin::Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let count = buf.filled().len() - before;
+        self.advance(count);
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    use super::ProgressReader;
+
+    #[test]
+    fn test_progress_reader() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let callback = {
+            let reports = Arc::clone(&reports);
+            Box::new(move |done, total| reports.lock().unwrap().push((done, total)))
+        };
+
+        let data = vec![0u8; 10];
+        let mut reader = ProgressReader::new(&data[..], Some(10), callback);
+
+        let mut buf = [0u8; 4];
+        while reader.read(&mut buf).unwrap() > 0 {}
+
+        assert_eq!(reader.transferred(), 10);
+        assert_eq!(
+            *reports.lock().unwrap(),
+            [(4, Some(10)), (8, Some(10)), (10, Some(10))]
+        );
+    }
+}
@@ -1,11 +1,19 @@
 //! URI Related helpers, such as `build_authority`
 
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
 use anyhow::bail;
 use anyhow::format_err;
 use anyhow::Error;
 use http::uri::{Authority, InvalidUri};
+use http::Uri;
 use serde_json::Value;
 
+/// URI scheme for HTTP requests over a unix domain socket, see [`unix_socket_uri`].
+pub const UNIX_SOCKET_SCHEME: &str = "http+unix";
+
 // Build an [`Authority`](http::uri::Authority) from a combination of `host` and `port`, ensuring that
 // IPv6 addresses are enclosed in brackets.
 pub fn build_authority(host: &str, port: u16) -> Result<Authority, InvalidUri> {
@@ -62,3 +70,72 @@ pub fn json_object_to_query(data: Value) -> Result<String, Error> {
 
     Ok(query.finish())
 }
+
+/// Build a URI for an HTTP request to `path_and_query` over the unix domain socket `socket`.
+///
+/// The socket path is hex encoded and used as host, since URI authorities cannot contain slashes,
+/// for example `http+unix://2f72756e2f706f646d616e2e736f636b/v4.0.0/libpod/info` for
+/// `/run/podman.sock`.
+pub fn unix_socket_uri(socket: &Path, path_and_query: &str) -> Result<Uri, Error> {
+    let authority: String = socket
+        .as_os_str()
+        .as_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    Ok(Uri::builder()
+        .scheme(UNIX_SOCKET_SCHEME)
+        .authority(authority)
+        .path_and_query(path_and_query)
+        .build()?)
+}
+
+/// The socket path of a URI built with [`unix_socket_uri`], `None` for other schemes.
+pub fn unix_socket_path(uri: &Uri) -> Result<Option<PathBuf>, Error> {
+    if uri.scheme_str() != Some(UNIX_SOCKET_SCHEME) {
+        return Ok(None);
+    }
+
+    let encoded = uri.host().unwrap_or_default().as_bytes();
+    let chunks = encoded.chunks_exact(2);
+    if encoded.is_empty() || !chunks.remainder().is_empty() {
+        bail!("invalid socket path in URI '{}'", uri);
+    }
+
+    let path = chunks
+        .map(|hex| {
+            std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| format_err!("invalid socket path in URI '{}'", uri))?;
+
+    Ok(Some(PathBuf::from(OsStr::from_bytes(&path))))
+}
+
+#[test]
+fn test_unix_socket_uri() {
+    let uri = unix_socket_uri(Path::new("/run/podman.sock"), "/v4.0.0/libpod/info").unwrap();
+    assert_eq!(
+        uri.to_string(),
+        "http+unix://2f72756e2f706f646d616e2e736f636b/v4.0.0/libpod/info"
+    );
+    assert_eq!(
+        unix_socket_path(&uri).unwrap().unwrap(),
+        Path::new("/run/podman.sock")
+    );
+
+    let uri: Uri = "http+unix://2F746D702F6120622E736F636B/".parse().unwrap();
+    assert_eq!(
+        unix_socket_path(&uri).unwrap().unwrap(),
+        Path::new("/tmp/a b.sock")
+    );
+
+    let uri: Uri = "http://localhost/".parse().unwrap();
+    assert!(unix_socket_path(&uri).unwrap().is_none());
+
+    let uri: Uri = "http+unix://2f7/".parse().unwrap();
+    assert!(unix_socket_path(&uri).is_err());
+}
@@ -2,22 +2,28 @@
 //!
 //! Provides methods to read and write from websockets The reader and writer take a reader/writer
 //! with AsyncRead/AsyncWrite respectively and provides the same
+//!
+//! [WebSocket] implements the server side, [WebSocketClient] the client side of a connection.
 
 use std::cmp::min;
+use std::collections::VecDeque;
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use anyhow::{bail, format_err, Error};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
 use futures::select;
 use hyper::header::{
-    HeaderMap, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
-    SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE,
+    HeaderMap, HeaderName, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_EXTENSIONS,
+    SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE,
 };
 use hyper::{Body, Response, StatusCode};
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 
 use futures::future::FutureExt;
 use futures::ready;
@@ -33,6 +39,7 @@ pub enum WebSocketErrorKind {
     ProtocolError = 1002,
     InvalidData = 1003,
     Other = 1008,
+    MessageTooBig = 1009,
     Unexpected = 1011,
 }
 
@@ -81,6 +88,98 @@ impl std::fmt::Display for WebSocketError {
 
 impl std::error::Error for WebSocketError {}
 
+/// Status code and reason of a Close frame, see RFC6455 section 5.5.1 and 7.4.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseReason {
+    /// The status code, e.g. 1000 for a normal closure.
+    pub code: u16,
+    /// The reason, only meant for debugging.
+    pub reason: String,
+}
+
+impl CloseReason {
+    /// Create a new close reason. The reason is truncated to fit into a control frame.
+    pub fn new(code: u16, reason: &str) -> Self {
+        // control frames are limited to 125 bytes, including the status code
+        let mut len = reason.len().min(123);
+        while !reason.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        Self {
+            code,
+            reason: reason[..len].to_string(),
+        }
+    }
+
+    /// Parse the payload of a Close frame. An empty payload contains no status code.
+    ///
+    /// Example:
+    /// ```
+    /// # use proxmox_http::websocket::*;
+    /// # fn main() -> Result<(), WebSocketError> {
+    /// let reason = CloseReason::from_payload(b"\x03\xe9going away")?;
+    /// assert_eq!(reason, Some(CloseReason::new(1001, "going away")));
+    /// assert_eq!(CloseReason::from_payload(b"")?, None);
+    /// assert!(CloseReason::from_payload(b"\x03\xed").is_err()); // 1005 must not be sent
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_payload(payload: &[u8]) -> Result<Option<Self>, WebSocketError> {
+        let (code, reason) = match payload {
+            [] => return Ok(None),
+            [code1, code2, reason @ ..] => (u16::from_be_bytes([*code1, *code2]), reason),
+            _ => {
+                return Err(WebSocketError::new(
+                    WebSocketErrorKind::ProtocolError,
+                    "invalid close frame payload",
+                ))
+            }
+        };
+
+        // 1004 is reserved, 1005, 1006 and 1015 must not be sent in a close frame
+        if !matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999) {
+            return Err(WebSocketError::new(
+                WebSocketErrorKind::ProtocolError,
+                &format!("invalid close status code {}", code),
+            ));
+        }
+
+        let reason = std::str::from_utf8(reason).map_err(|_| {
+            WebSocketError::new(WebSocketErrorKind::InvalidData, "invalid close reason")
+        })?;
+
+        Ok(Some(Self {
+            code,
+            reason: reason.to_string(),
+        }))
+    }
+
+    /// Generate the payload of a Close frame.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.reason.len() + 2);
+        data.extend_from_slice(&self.code.to_be_bytes());
+        data.extend_from_slice(self.reason.as_bytes());
+        data
+    }
+}
+
+impl std::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        if self.reason.is_empty() {
+            write!(f, "code {}", self.code)
+        } else {
+            write!(f, "{} (Code: {})", self.reason, self.code)
+        }
+    }
+}
+
+impl From<WebSocketErrorKind> for CloseReason {
+    fn from(kind: WebSocketErrorKind) -> Self {
+        Self::new(kind as u16, "")
+    }
+}
+
 #[repr(u8)]
 #[derive(Debug, Eq, PartialEq, PartialOrd, Copy, Clone)]
 /// Represents an OpCode of a websocket frame
@@ -106,35 +205,52 @@ impl OpCode {
     }
 }
 
-fn mask_bytes(mask: Option<[u8; 4]>, data: &mut [u8]) {
+/// Generate a random masking key for a client frame.
+fn random_mask() -> Result<[u8; 4], WebSocketError> {
+    let mut mask = [0u8; 4];
+    openssl::rand::rand_bytes(&mut mask).map_err(|err| {
+        WebSocketError::new(
+            WebSocketErrorKind::Unexpected,
+            &format!("unable to generate mask - {}", err),
+        )
+    })?;
+    Ok(mask)
+}
+
+/// Apply (or remove) the WebSocket `mask` to `data` in place.
+///
+/// The data has to start at the beginning of the frame payload, or at an offset which is a
+/// multiple of 4. For other offsets, the mask has to be rotated left by `offset % 4` first.
+///
+/// Example:
+/// ```
+/// # use proxmox_http::websocket::*;
+/// let mut data = vec![0, 1, 2, 3, 4];
+/// mask_bytes(Some([1, 2, 3, 4]), &mut data);
+/// assert_eq!(data, [1, 3, 1, 7, 5]);
+/// mask_bytes(Some([1, 2, 3, 4]), &mut data);
+/// assert_eq!(data, [0, 1, 2, 3, 4]);
+/// ```
+pub fn mask_bytes(mask: Option<[u8; 4]>, data: &mut [u8]) {
     let mask = match mask {
         Some([0, 0, 0, 0]) | None => return,
         Some(mask) => mask,
     };
 
-    if data.len() < 32 {
-        for i in 0..data.len() {
-            data[i] ^= mask[i & 3];
-        }
-        return;
-    }
-
-    let mut newmask: u32 = u32::from_le_bytes(mask);
-
-    let (prefix, middle, suffix) = unsafe { data.align_to_mut::<u32>() };
-
-    for p in prefix {
-        *p ^= newmask as u8;
-        newmask = newmask.rotate_right(8);
-    }
+    // process 8 bytes at once, the compiler vectorizes this loop
+    let mask64 = u64::from_ne_bytes([
+        mask[0], mask[1], mask[2], mask[3], mask[0], mask[1], mask[2], mask[3],
+    ]);
 
-    for m in middle {
-        *m ^= newmask;
+    let mut chunks = data.chunks_exact_mut(8);
+    for chunk in &mut chunks {
+        let value = u64::from_ne_bytes(chunk.try_into().unwrap()) ^ mask64;
+        chunk.copy_from_slice(&value.to_ne_bytes());
     }
 
-    for s in suffix {
-        *s ^= newmask as u8;
-        newmask = newmask.rotate_right(8);
+    // the remainder starts at a multiple of 8, so the mask offset is 0
+    for (i, byte) in chunks.into_remainder().iter_mut().enumerate() {
+        *byte ^= mask[i & 3];
     }
 }
 
@@ -187,7 +303,20 @@ pub fn create_frame(
     data: &[u8],
     frametype: OpCode,
 ) -> Result<Vec<u8>, WebSocketError> {
-    let first_byte = 0b10000000 | (frametype as u8);
+    build_frame(mask, data, frametype, false)
+}
+
+// `compressed` sets the RSV1 bit used by permessage-deflate
+fn build_frame(
+    mask: Option<[u8; 4]>,
+    data: &[u8],
+    frametype: OpCode,
+    compressed: bool,
+) -> Result<Vec<u8>, WebSocketError> {
+    let mut first_byte = 0b10000000 | (frametype as u8);
+    if compressed {
+        first_byte |= 0b01000000;
+    }
     let len = data.len();
     if (frametype as u8) & 0b00001000 > 0 && len > 125 {
         return Err(WebSocketError::new(
@@ -202,7 +331,8 @@ pub fn create_frame(
         0b00000000
     };
 
-    let mut buf = vec![first_byte];
+    let mut buf = Vec::with_capacity(len + 14);
+    buf.push(first_byte);
 
     if len < 126 {
         buf.push(mask_bit | (len as u8));
@@ -217,13 +347,88 @@ pub fn create_frame(
     if let Some(mask) = mask {
         buf.extend_from_slice(&mask);
     }
-    let mut data = data.to_vec().into_boxed_slice();
-    mask_bytes(mask, &mut data);
+    let payload_offset = buf.len();
+    buf.extend_from_slice(data);
+    mask_bytes(mask, &mut buf[payload_offset..]);
 
-    buf.append(&mut data.into_vec());
     Ok(buf)
 }
 
+// every message compressed with permessage-deflate ends with an empty stored block, which is
+// removed before sending, see RFC7692 section 7.2.1
+const DEFLATE_TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+fn deflate_message(compress: &mut Compress, mut input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() + 64);
+    loop {
+        let total_in = compress.total_in();
+        compress
+            .compress_vec(input, &mut output, FlushCompress::Sync)
+            .map_err(io_err_other)?;
+        input = &input[(compress.total_in() - total_in) as usize..];
+
+        if input.is_empty() && output.len() < output.capacity() {
+            break;
+        }
+        output.reserve(4096);
+    }
+
+    if output.ends_with(&DEFLATE_TRAILER) {
+        output.truncate(output.len() - DEFLATE_TRAILER.len());
+    }
+    Ok(output)
+}
+
+/// Inflate `input` into `output`, returning the number of bytes added. Fails if that would be
+/// more than `limit` bytes, without ever inflating more than one byte past it.
+fn inflate_data(
+    decompress: &mut Decompress,
+    mut input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<usize, WebSocketError> {
+    let start = output.len();
+    loop {
+        let size = output.len() - start;
+        let chunk_len = min(16 * 1024, limit - size + 1);
+        output.resize(start + size + chunk_len, 0);
+
+        let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+        let status = decompress
+            .decompress(input, &mut output[start + size..], FlushDecompress::Sync)
+            .map_err(|err| {
+                WebSocketError::new(
+                    WebSocketErrorKind::InvalidData,
+                    &format!("unable to decompress message - {}", err),
+                )
+            });
+        let produced = (decompress.total_out() - total_out) as usize;
+        output.truncate(start + size + produced);
+        let status = status?;
+
+        if size + produced > limit {
+            return Err(WebSocketError::new(
+                WebSocketErrorKind::MessageTooBig,
+                "decompressed message too big",
+            ));
+        }
+
+        input = &input[(decompress.total_in() - total_in) as usize..];
+        let progress = decompress.total_in() != total_in || produced > 0;
+
+        if status == Status::StreamEnd {
+            // a final block ends the stream, the next message starts a new one
+            decompress.reset(false);
+            if input.is_empty() {
+                break;
+            }
+        } else if (input.is_empty() && produced < chunk_len) || !progress {
+            break;
+        }
+    }
+    Ok(output.len() - start)
+}
+
 /// Wrap (encapsulate) an `AsyncWrite`er into a WebSocket transparently
 ///
 /// Send websocket frames to anything accepting AsyncWrite.
@@ -245,6 +450,8 @@ pub fn create_frame(
 pub struct WebSocketWriter<W: AsyncWrite + Unpin> {
     writer: W,
     mask: Option<[u8; 4]>,
+    random_mask: bool,
+    deflate: Option<(Compress, bool)>,
     frame: Option<(Vec<u8>, usize, usize)>,
 }
 
@@ -254,16 +461,68 @@ impl<W: AsyncWrite + Unpin> WebSocketWriter<W> {
         WebSocketWriter {
             writer,
             mask,
+            random_mask: false,
+            deflate: None,
+            frame: None,
+        }
+    }
+
+    /// Create a new WebSocketWriter for the client side of a connection, which masks every
+    /// frame with a fresh random key, as required by RFC6455.
+    pub fn new_client(writer: W) -> WebSocketWriter<W> {
+        WebSocketWriter {
+            writer,
+            mask: None,
+            random_mask: true,
+            deflate: None,
             frame: None,
         }
     }
 
+    /// Compress every written message with permessage-deflate (RFC7692). This must only be
+    /// enabled if the extension was negotiated during the handshake.
+    ///
+    /// With `no_context_takeover` the compression context is reset after every message.
+    pub fn enable_permessage_deflate(&mut self, no_context_takeover: bool) {
+        let compress = Compress::new(Compression::default(), false);
+        self.deflate = Some((compress, no_context_takeover));
+    }
+
+    fn create_data_frame(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mask = self.frame_mask().map_err(io_err_other)?;
+        match &mut self.deflate {
+            Some((compress, no_context_takeover)) => {
+                let data = deflate_message(compress, data)?;
+                if *no_context_takeover {
+                    compress.reset();
+                }
+                build_frame(mask, &data, OpCode::Binary, true).map_err(io_err_other)
+            }
+            None => create_frame(mask, data, OpCode::Binary).map_err(io_err_other),
+        }
+    }
+
+    fn frame_mask(&self) -> Result<Option<[u8; 4]>, WebSocketError> {
+        if self.random_mask {
+            Ok(Some(random_mask()?))
+        } else {
+            Ok(self.mask)
+        }
+    }
+
+    /// Send a control frame. The given mask is ignored by client writers, which always use a
+    /// random mask.
     pub async fn send_control_frame(
         &mut self,
         mask: Option<[u8; 4]>,
         opcode: OpCode,
         data: &[u8],
     ) -> Result<(), Error> {
+        let mask = if self.random_mask {
+            self.frame_mask()?
+        } else {
+            mask
+        };
         let frame = create_frame(mask, data, opcode).map_err(Error::from)?;
         self.writer.write_all(&frame).await.map_err(Error::from)
     }
@@ -275,10 +534,10 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for WebSocketWriter<W> {
 
         if this.frame.is_none() {
             // create frame buf
-            let frame = match create_frame(this.mask, buf, OpCode::Binary) {
+            let frame = match this.create_data_frame(buf) {
                 Ok(f) => f,
                 Err(e) => {
-                    return Poll::Ready(Err(io_err_other(e)));
+                    return Poll::Ready(Err(e));
                 }
             };
             this.frame = Some((frame, 0, buf.len()));
@@ -367,6 +626,13 @@ impl FrameHeader {
     /// # }
     /// ```
     pub fn try_from_bytes(data: &[u8]) -> Result<Option<FrameHeader>, WebSocketError> {
+        Ok(Self::parse(data, false)?.map(|(header, _)| header))
+    }
+
+    /// Like [try_from_bytes](Self::try_from_bytes), but accepts the RSV1 bit if `deflate` is
+    /// set, i.e. if permessage-deflate was negotiated. Also returns whether the bit was set,
+    /// meaning the message is compressed.
+    fn parse(data: &[u8], deflate: bool) -> Result<Option<(FrameHeader, bool)>, WebSocketError> {
         let len = data.len();
         if len < 2 {
             return Ok(None);
@@ -374,13 +640,14 @@ impl FrameHeader {
 
         let data = data;
 
-        // we do not support extensions
-        if data[0] & 0b01110000 > 0 {
+        let reserved_bits = if deflate { 0b00110000 } else { 0b01110000 };
+        if data[0] & reserved_bits > 0 {
             return Err(WebSocketError::new(
                 WebSocketErrorKind::ProtocolError,
                 "Extensions not supported",
             ));
         }
+        let compressed = data[0] & 0b01000000 != 0;
 
         let fin = data[0] & 0b10000000 != 0;
         let frametype = match data[0] & 0b1111 {
@@ -398,6 +665,13 @@ impl FrameHeader {
             }
         };
 
+        if compressed && (frametype.is_control() || frametype == OpCode::Continuation) {
+            return Err(WebSocketError::new(
+                WebSocketErrorKind::ProtocolError,
+                "Only the first frame of a data message can be compressed",
+            ));
+        }
+
         if !fin && frametype.is_control() {
             return Err(WebSocketError::new(
                 WebSocketErrorKind::ProtocolError,
@@ -449,16 +723,22 @@ impl FrameHeader {
             None
         };
 
-        Ok(Some(FrameHeader {
-            fin,
-            mask,
-            frametype,
-            payload_len,
-            header_len: payload_offset,
-        }))
+        Ok(Some((
+            FrameHeader {
+                fin,
+                mask,
+                frametype,
+                payload_len,
+                header_len: payload_offset,
+            },
+            compressed,
+        )))
     }
 }
 
+/// Decompressed messages larger than this are rejected with status code 1009.
+const MAX_INFLATED_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
 type WebSocketReadResult = Result<(OpCode, Box<[u8]>), WebSocketError>;
 
 /// Wraps a `AsyncRead`er for decoding WebSocket frames returning the inner payload.
@@ -475,6 +755,12 @@ pub struct WebSocketReader<R: AsyncRead> {
     read_buffer: Option<ByteBuffer>,
     header: Option<FrameHeader>,
     state: ReaderState<R>,
+    inflate: Option<Decompress>,
+    compressed: bool,
+    inflated: Vec<u8>,
+    inflated_pos: usize,
+    message_size: usize,
+    max_message_size: usize,
 }
 
 impl<R: AsyncRead> WebSocketReader<R> {
@@ -498,7 +784,37 @@ impl<R: AsyncRead> WebSocketReader<R> {
             read_buffer: Some(ByteBuffer::with_capacity(capacity)),
             header: None,
             state: ReaderState::NoData,
+            inflate: None,
+            compressed: false,
+            inflated: Vec::new(),
+            inflated_pos: 0,
+            message_size: 0,
+            max_message_size: MAX_INFLATED_MESSAGE_SIZE,
+        }
+    }
+
+    /// Decompress messages compressed with permessage-deflate (RFC7692). This must only be
+    /// enabled if the extension was negotiated during the handshake.
+    ///
+    /// Messages which decompress to more than 16 MiB fail the connection with status code 1009.
+    pub fn enable_permessage_deflate(&mut self) {
+        self.inflate = Some(Decompress::new(false));
+    }
+
+    fn read_inflated(&mut self, buf: &mut ReadBuf) -> bool {
+        let data = &self.inflated[self.inflated_pos..];
+        if data.is_empty() {
+            return false;
+        }
+
+        let len = min(buf.remaining(), data.len());
+        buf.put_slice(&data[..len]);
+        self.inflated_pos += len;
+        if self.inflated_pos == self.inflated.len() {
+            self.inflated.clear();
+            self.inflated_pos = 0;
         }
+        true
     }
 }
 
@@ -524,6 +840,10 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
     ) -> Poll<io::Result<()>> {
         let this = Pin::get_mut(self);
 
+        if this.read_inflated(buf) {
+            return Poll::Ready(Ok(()));
+        }
+
         loop {
             match &mut this.state {
                 ReaderState::NoData => {
@@ -574,8 +894,17 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
                     let mut header = match this.header.take() {
                         Some(header) => header,
                         None => {
-                            let header = match FrameHeader::try_from_bytes(&read_buffer[..]) {
-                                Ok(Some(header)) => header,
+                            let deflate = this.inflate.is_some();
+                            let header = match FrameHeader::parse(&read_buffer[..], deflate) {
+                                Ok(Some((header, compressed))) => {
+                                    if !header.is_control_frame()
+                                        && header.frametype != OpCode::Continuation
+                                    {
+                                        this.compressed = compressed;
+                                        this.message_size = 0;
+                                    }
+                                    header
+                                }
                                 Ok(None) => {
                                     this.state = ReaderState::NoData;
                                     this.read_buffer = Some(read_buffer);
@@ -616,14 +945,59 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
                         continue;
                     }
 
-                    let len = min(buf.remaining(), min(header.payload_len, read_buffer.len()));
+                    let inflate = match &mut this.inflate {
+                        Some(inflate) if this.compressed => Some(inflate),
+                        _ => None,
+                    };
+
+                    let len = match inflate {
+                        // the decompressed size is unknown, buffer the inflated data
+                        Some(_) => min(header.payload_len, read_buffer.len()),
+                        None => min(buf.remaining(), min(header.payload_len, read_buffer.len())),
+                    };
 
                     let mut data = read_buffer.remove_data(len);
                     mask_bytes(header.mask, &mut data);
-                    buf.put_slice(&data);
+                    // the rest of the payload continues at this offset of the mask
+                    if let Some(mask) = &mut header.mask {
+                        mask.rotate_left(len % 4);
+                    }
 
                     header.payload_len -= len;
 
+                    let copied = match inflate {
+                        Some(inflate) => {
+                            let limit = this.max_message_size - this.message_size;
+                            let mut result =
+                                inflate_data(inflate, &data, &mut this.inflated, limit);
+                            if header.payload_len == 0 && header.fin {
+                                result = result.and_then(|size| {
+                                    let limit = limit - size;
+                                    let trailer_size = inflate_data(
+                                        inflate,
+                                        &DEFLATE_TRAILER,
+                                        &mut this.inflated,
+                                        limit,
+                                    )?;
+                                    Ok(size + trailer_size)
+                                });
+                                this.compressed = false;
+                            }
+                            match result {
+                                Ok(size) => this.message_size += size,
+                                Err(err) => {
+                                    let _ = this.sender.send(Err(err.clone()));
+                                    return Poll::Ready(Err(io_err_other(err)));
+                                }
+                            }
+                            false
+                        }
+                        None => {
+                            buf.put_slice(&data);
+                            len > 0
+                        }
+                    };
+
                     if header.payload_len > 0 {
                         this.header = Some(header);
                     }
@@ -635,7 +1009,7 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
                     };
                     this.read_buffer = Some(read_buffer);
 
-                    if len > 0 {
+                    if copied || this.read_inflated(buf) {
                         return Poll::Ready(Ok(()));
                     }
                 }
@@ -647,15 +1021,111 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
 /// Global Identifier for WebSockets, see RFC6455
 pub const MAGIC_WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 
-/// Provides methods for connecting one WebSocket endpoint with another
-pub struct WebSocket {
-    pub mask: Option<[u8; 4]>,
+/// Negotiated permessage-deflate parameters.
+#[derive(Debug, Clone, Copy)]
+struct DeflateParams {
+    server_no_context_takeover: bool,
 }
 
-impl WebSocket {
+impl DeflateParams {
+    /// Accept the first permessage-deflate offer of the client we can fulfill, see RFC7692
+    /// section 7.1. Returns the parameters and the response header value.
+    fn negotiate(headers: &HeaderMap<HeaderValue>) -> Option<(Self, &'static str)> {
+        let offers = headers
+            .get_all(SEC_WEBSOCKET_EXTENSIONS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+
+        for offer in offers {
+            let mut params = offer.split(';').map(str::trim);
+            if params.next() != Some("permessage-deflate") {
+                continue;
+            }
+            if let Some(params) = Self::parse_offer(params) {
+                let response = if params.server_no_context_takeover {
+                    "permessage-deflate; server_no_context_takeover"
+                } else {
+                    "permessage-deflate"
+                };
+                return Some((params, response));
+            }
+        }
+        None
+    }
+
+    fn parse_offer<'a>(params: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let mut seen = Vec::new();
+        let mut server_no_context_takeover = false;
+
+        for param in params {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+            if seen.contains(&name) {
+                return None; // duplicate parameters make the offer invalid
+            }
+            seen.push(name);
+
+            match (name, value) {
+                ("server_no_context_takeover", None) => server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => (),
+                // we can decompress with any window size
+                ("client_max_window_bits", _) => (),
+                // we always compress with the default (and maximum) window size
+                ("server_max_window_bits", Some("15")) => (),
+                _ => return None,
+            }
+        }
+
+        Some(Self {
+            server_no_context_takeover,
+        })
+    }
+}
+
+/// Keepalive settings, see [WebSocketBuilder::keepalive].
+#[derive(Debug, Clone, Copy)]
+struct Keepalive {
+    interval: Duration,
+    timeout: Duration,
+}
+
+/// Builder for a server side [WebSocket].
+#[derive(Default)]
+pub struct WebSocketBuilder {
+    permessage_deflate: bool,
+    keepalive: Option<Keepalive>,
+}
+
+impl WebSocketBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept the permessage-deflate extension (RFC7692) if the client offers it, compressing
+    /// all messages sent and received by [WebSocket::serve_connection].
+    pub fn permessage_deflate(mut self, enable: bool) -> Self {
+        self.permessage_deflate = enable;
+        self
+    }
+
+    /// Send a Ping frame every `interval` in [WebSocket::serve_connection], and close the
+    /// connection with an error if the peer does not answer with a Pong within `timeout`.
+    ///
+    /// This detects dead peers, e.g. half-open connections through NAT.
+    pub fn keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive = Some(Keepalive { interval, timeout });
+        self
+    }
+
     /// Returns a new WebSocket instance and the correct WebSocket response derived from the
     /// upgrade request's headers
-    pub fn new(headers: HeaderMap<HeaderValue>) -> Result<(Self, Response<Body>), Error> {
+    pub fn build(
+        self,
+        headers: HeaderMap<HeaderValue>,
+    ) -> Result<(WebSocket, Response<Body>), Error> {
         let protocols = headers
             .get(UPGRADE)
             .ok_or_else(|| format_err!("missing Upgrade header"))?
@@ -679,12 +1149,7 @@ impl WebSocket {
             bail!("invalid websocket version");
         }
 
-        // we ignore extensions
-
-        let mut sha1 = openssl::sha::Sha1::new();
-        let data = format!("{}{}", key, MAGIC_WEBSOCKET_GUID);
-        sha1.update(data.as_bytes());
-        let response_key = base64::encode(sha1.finish());
+        let response_key = accept_key(key);
 
         let mut response = Response::builder()
             .status(StatusCode::SWITCHING_PROTOCOLS)
@@ -700,9 +1165,53 @@ impl WebSocket {
             response = response.header(SEC_WEBSOCKET_PROTOCOL, ws_proto)
         }
 
+        // other extensions are ignored
+        let mut deflate = None;
+        if self.permessage_deflate {
+            if let Some((params, extension)) = DeflateParams::negotiate(&headers) {
+                response = response.header(SEC_WEBSOCKET_EXTENSIONS, extension);
+                deflate = Some(params);
+            }
+        }
+
         let response = response.body(Body::empty())?;
 
-        Ok((Self { mask: None }, response))
+        Ok((
+            WebSocket {
+                mask: None,
+                deflate,
+                keepalive: self.keepalive,
+            },
+            response,
+        ))
+    }
+}
+
+/// How copying from the downstream endpoint to the WebSocket finished.
+enum CopyResult {
+    /// The downstream endpoint reached EOF.
+    Eof,
+    /// The connection was closed, with the reason sent by the peer, if any.
+    Closed(Option<CloseReason>),
+}
+
+/// Provides methods for connecting one WebSocket endpoint with another
+pub struct WebSocket {
+    pub mask: Option<[u8; 4]>,
+    deflate: Option<DeflateParams>,
+    keepalive: Option<Keepalive>,
+}
+
+impl WebSocket {
+    /// Returns a new WebSocket instance and the correct WebSocket response derived from the
+    /// upgrade request's headers
+    pub fn new(headers: HeaderMap<HeaderValue>) -> Result<(Self, Response<Body>), Error> {
+        WebSocketBuilder::new().build(headers)
+    }
+
+    /// Create a builder to configure optional features like permessage-deflate.
+    pub fn builder() -> WebSocketBuilder {
+        WebSocketBuilder::new()
     }
 
     pub async fn handle_channel_message<W>(
@@ -739,28 +1248,92 @@ impl WebSocket {
         }
     }
 
-    async fn copy_to_websocket<R, W>(
+    /// Handle a control frame received by the reader, returns how the connection was closed if it
+    /// was a Close frame.
+    async fn handle_control_frame<W>(
+        &self,
+        result: WebSocketReadResult,
+        writer: &mut WebSocketWriter<W>,
+    ) -> Result<Option<CopyResult>, Error>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        let mut peer_reason = None;
+        let result = match result {
+            Ok((OpCode::Close, payload)) => match CloseReason::from_payload(&payload) {
+                Ok(reason) => {
+                    peer_reason = reason;
+                    Ok((OpCode::Close, payload))
+                }
+                Err(err) => Err(err),
+            },
+            result => result,
+        };
+        match self.handle_channel_message(result, writer).await? {
+            OpCode::Close => Ok(Some(CopyResult::Closed(peer_reason))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn copy_to_websocket<R, W, C>(
         &self,
         mut reader: &mut R,
         writer: &mut WebSocketWriter<W>,
         receiver: &mut mpsc::UnboundedReceiver<WebSocketReadResult>,
-    ) -> Result<bool, Error>
+        mut close: C,
+    ) -> Result<CopyResult, Error>
     where
         R: AsyncRead + Unpin + Send,
         W: AsyncWrite + Unpin + Send,
+        C: futures::future::FusedFuture<Output = CloseReason> + Unpin,
     {
         let mut buf = ByteBuffer::with_capacity(16 * 1024);
         let mut eof = false;
+
+        // the time to send the next ping, or the deadline for the pong if one is outstanding
+        let mut ping_deadline = self.keepalive.map(|ka| Instant::now() + ka.interval);
+        let mut pong_pending = false;
+
+        // control frames received while writing a data frame, they cannot be answered before the
+        // data frame is complete
+        let mut deferred = VecDeque::new();
+
         loop {
+            if let Some(result) = deferred.pop_front() {
+                if let Some(result) = self.handle_control_frame(result, writer).await? {
+                    return Ok(result);
+                }
+                continue;
+            }
+
             if !buf.is_full() {
                 let bytes = select! {
                     res = buf.read_from_async(&mut reader).fuse() => res?,
                     res = receiver.recv().fuse() => {
                         let res = res.ok_or_else(|| format_err!("control channel closed"))?;
-                        match self.handle_channel_message(res, writer).await? {
-                            OpCode::Close => return Ok(true),
-                            _ => { continue; },
+                        if let (Ok((OpCode::Pong, _)), Some(keepalive)) = (&res, self.keepalive) {
+                            pong_pending = false;
+                            ping_deadline = Some(Instant::now() + keepalive.interval);
+                        }
+                        match self.handle_control_frame(res, writer).await? {
+                            Some(result) => return Ok(result),
+                            None => continue,
+                        }
+                    }
+                    reason = close => {
+                        writer.send_control_frame(self.mask, OpCode::Close, &reason.to_payload()).await?;
+                        return Ok(CopyResult::Closed(None));
+                    }
+                    _ = keepalive_timer(ping_deadline).fuse() => {
+                        // keepalive_timer only completes if keepalive is set
+                        let keepalive = self.keepalive.unwrap();
+                        if pong_pending {
+                            bail!("websocket peer did not answer ping within {:?}", keepalive.timeout);
                         }
+                        writer.send_control_frame(self.mask, OpCode::Ping, &[]).await?;
+                        pong_pending = true;
+                        ping_deadline = Some(Instant::now() + keepalive.timeout);
+                        continue;
                     }
                 };
 
@@ -769,7 +1342,38 @@ impl WebSocket {
                 }
             }
             if buf.len() > 0 {
-                let bytes = writer.write(&buf).await?;
+                // Keep watching the peer while it does not accept our data, a dead peer would
+                // otherwise block the write forever.
+                let write = writer.write(&buf).fuse();
+                futures::pin_mut!(write);
+                let mut stalled = false;
+                let bytes = loop {
+                    select! {
+                        res = write => break res?,
+                        res = receiver.recv().fuse() => {
+                            let res = res.ok_or_else(|| format_err!("control channel closed"))?;
+                            if let (Ok((OpCode::Pong, _)), Some(keepalive)) = (&res, self.keepalive) {
+                                pong_pending = false;
+                                if !stalled {
+                                    ping_deadline = Some(Instant::now() + keepalive.interval);
+                                }
+                            }
+                            deferred.push_back(res);
+                        }
+                        _ = keepalive_timer(ping_deadline).fuse() => {
+                            let keepalive = self.keepalive.unwrap();
+                            if pong_pending {
+                                bail!("websocket peer did not answer ping within {:?}", keepalive.timeout);
+                            }
+                            if stalled {
+                                bail!("websocket peer did not accept data within {:?}", keepalive.timeout);
+                            }
+                            // a ping cannot be sent in the middle of a data frame
+                            stalled = true;
+                            ping_deadline = Some(Instant::now() + keepalive.timeout);
+                        }
+                    }
+                };
                 if bytes == 0 {
                     eof = true;
                 }
@@ -778,7 +1382,7 @@ impl WebSocket {
 
             if eof && buf.is_empty() {
                 writer.flush().await?;
-                return Ok(false);
+                return Ok(CopyResult::Eof);
             }
         }
     }
@@ -788,37 +1392,611 @@ impl WebSocket {
     ///
     /// This method takes care of copying the data between endpoints, and sending correct responses
     /// for control frames (e.g. a Pont to a Ping).
-    pub async fn serve_connection<S, L>(&self, upstream: S, downstream: L) -> Result<(), Error>
+    ///
+    /// Returns `Some` with the close reason if the peer closed the connection with a status code,
+    /// `None` if it closed it without one, if the downstream endpoint reached EOF or if the
+    /// upstream connection ended without a Close frame. Callers only interested in errors can
+    /// ignore the reason, e.g. with `ws.serve_connection(upstream, downstream).await?;`.
+    pub async fn serve_connection<S, L>(
+        &self,
+        upstream: S,
+        downstream: L,
+    ) -> Result<Option<CloseReason>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        L: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        self.do_serve_connection(upstream, downstream, futures::future::pending(), false)
+            .await
+    }
+
+    /// Like [serve_connection](Self::serve_connection), but closes the connection with the
+    /// reason returned by `close` once it completes, e.g. when the session is terminated.
+    pub async fn serve_connection_with_close<S, L, C>(
+        &self,
+        upstream: S,
+        downstream: L,
+        close: C,
+    ) -> Result<Option<CloseReason>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        L: AsyncRead + AsyncWrite + Unpin + Send,
+        C: Future<Output = CloseReason>,
+    {
+        self.do_serve_connection(upstream, downstream, close, false)
+            .await
+    }
+
+    async fn do_serve_connection<S, L, C>(
+        &self,
+        upstream: S,
+        downstream: L,
+        close: C,
+        client: bool,
+    ) -> Result<Option<CloseReason>, Error>
     where
         S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
         L: AsyncRead + AsyncWrite + Unpin + Send,
+        C: Future<Output = CloseReason>,
     {
         let (usreader, uswriter) = tokio::io::split(upstream);
         let (mut dsreader, mut dswriter) = tokio::io::split(downstream);
 
         let (tx, mut rx) = mpsc::unbounded_channel();
         let mut wsreader = WebSocketReader::new(usreader, tx);
-        let mut wswriter = WebSocketWriter::new(self.mask, uswriter);
+        let mut wswriter = if client {
+            WebSocketWriter::new_client(uswriter)
+        } else {
+            WebSocketWriter::new(self.mask, uswriter)
+        };
+        if let Some(params) = self.deflate {
+            wsreader.enable_permessage_deflate();
+            wswriter.enable_permessage_deflate(params.server_no_context_takeover);
+        }
+
+        let close = close.fuse();
+        futures::pin_mut!(close);
 
         let ws_future = tokio::io::copy(&mut wsreader, &mut dswriter);
-        let term_future = self.copy_to_websocket(&mut dsreader, &mut wswriter, &mut rx);
+        let term_future = self.copy_to_websocket(&mut dsreader, &mut wswriter, &mut rx, close);
 
         select! {
             res = ws_future.fuse() => match res {
-                Ok(_) => Ok(()),
+                Ok(_) => {
+                    // the peer may have sent a Close frame right before closing the stream
+                    while let Ok(result) = rx.try_recv() {
+                        if let Ok((OpCode::Close, payload)) = result {
+                            return Ok(CloseReason::from_payload(&payload)?);
+                        }
+                    }
+                    Ok(None)
+                }
                 Err(err) => Err(Error::from(err)),
             },
             res = term_future.fuse() => match res {
-                Ok(sent_close) if !sent_close => {
+                Ok(CopyResult::Eof) => {
                     // status code 1000 => 0x03E8
                     wswriter
                         .send_control_frame(self.mask, OpCode::Close, &WebSocketErrorKind::Normal.to_be_bytes())
                         .await?;
-                    Ok(())
+                    Ok(None)
                 }
-                Ok(_) => Ok(()),
+                Ok(CopyResult::Closed(reason)) => Ok(reason),
                 Err(err) => Err(err),
             }
         }
     }
 }
+
+/// Completes at `deadline`, never if there is none.
+async fn keepalive_timer(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// Compute the `Sec-WebSocket-Accept` value for the `Sec-WebSocket-Key` `key`.
+fn accept_key(key: &str) -> String {
+    let mut sha1 = openssl::sha::Sha1::new();
+    let data = format!("{}{}", key, MAGIC_WEBSOCKET_GUID);
+    sha1.update(data.as_bytes());
+    base64::encode(sha1.finish())
+}
+
+// limit for the response header of the handshake
+const MAX_HANDSHAKE_RESPONSE_SIZE: usize = 16 * 1024;
+
+/// Client side of a WebSocket connection, e.g. to connect to a vncproxy or termproxy endpoint.
+///
+/// Performs the HTTP upgrade handshake over any stream, the TLS connection (if any) has to be
+/// established before.
+///
+/// Example usage:
+/// ```
+/// # use anyhow::Error;
+/// # use proxmox_http::websocket::*;
+/// # use tokio::io::{AsyncRead, AsyncWrite};
+/// async fn code<S, L>(stream: S, local: L) -> Result<(), Error>
+/// where
+///     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+///     L: AsyncRead + AsyncWrite + Unpin + Send,
+/// {
+///     WebSocketClient::new("localhost:8006", "/vncwebsocket?port=5900")
+///         .header("Cookie", "PVEAuthCookie=...")?
+///         .protocol("binary")
+///         .connect(stream)
+///         .await?
+///         .serve_connection(local)
+///         .await?;
+///     Ok(())
+/// }
+/// ```
+pub struct WebSocketClient {
+    host: String,
+    path: String,
+    protocols: Vec<String>,
+    headers: HeaderMap<HeaderValue>,
+}
+
+impl WebSocketClient {
+    /// Create a client for the resource `path` (including the query) on `host`.
+    pub fn new(host: &str, path: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            path: path.to_string(),
+            protocols: Vec::new(),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Add a header to the upgrade request, e.g. for authentication.
+    pub fn header(mut self, name: &str, value: &str) -> Result<Self, Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())?;
+        self.headers.append(name, HeaderValue::from_str(value)?);
+        Ok(self)
+    }
+
+    /// Request the subprotocol `protocol`, can be called multiple times.
+    pub fn protocol(mut self, protocol: &str) -> Self {
+        self.protocols.push(protocol.to_string());
+        self
+    }
+
+    /// Perform the upgrade handshake on `stream`.
+    pub async fn connect<S>(&self, mut stream: S) -> Result<WebSocketClientConnection<S>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut key = [0u8; 16];
+        openssl::rand::rand_bytes(&mut key)?;
+        let key = base64::encode(key);
+
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\n\
+             Sec-WebSocket-Version: 13\r\n",
+            self.path, self.host, key,
+        );
+        if !self.protocols.is_empty() {
+            request.push_str(&format!(
+                "Sec-WebSocket-Protocol: {}\r\n",
+                self.protocols.join(", ")
+            ));
+        }
+        let mut request = request.into_bytes();
+        for (name, value) in self.headers.iter() {
+            request.extend_from_slice(name.as_str().as_bytes());
+            request.extend_from_slice(b": ");
+            request.extend_from_slice(value.as_bytes());
+            request.extend_from_slice(b"\r\n");
+        }
+        request.extend_from_slice(b"\r\n");
+
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+
+        // read byte by byte, so no frame data following the response gets consumed
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= MAX_HANDSHAKE_RESPONSE_SIZE {
+                bail!("websocket handshake response too large");
+            }
+            response.push(stream.read_u8().await?);
+        }
+
+        let response = std::str::from_utf8(&response)
+            .map_err(|_| format_err!("invalid websocket handshake response"))?;
+        let mut lines = response.split("\r\n");
+
+        let status_line = lines.next().unwrap_or_default();
+        match status_line.split(' ').nth(1) {
+            Some("101") => (),
+            _ => bail!("websocket upgrade failed - {}", status_line),
+        }
+
+        let mut headers = HeaderMap::new();
+        for line in lines.filter(|line| !line.is_empty()) {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format_err!("invalid header line '{}'", line))?;
+            let name = HeaderName::from_bytes(name.trim().as_bytes())?;
+            headers.append(name, HeaderValue::from_str(value.trim())?);
+        }
+
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+        if !matches!(header(UPGRADE), Some(value) if value.eq_ignore_ascii_case("websocket")) {
+            bail!("invalid or missing Upgrade header in websocket response");
+        }
+
+        let connection_upgrade = match header(CONNECTION) {
+            Some(value) => value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("upgrade")),
+            None => false,
+        };
+        if !connection_upgrade {
+            bail!("invalid or missing Connection header in websocket response");
+        }
+
+        if header(SEC_WEBSOCKET_ACCEPT) != Some(accept_key(&key).as_str()) {
+            bail!("invalid Sec-WebSocket-Accept header in websocket response");
+        }
+
+        let protocol = header(SEC_WEBSOCKET_PROTOCOL).map(str::to_string);
+        if let Some(protocol) = &protocol {
+            if !self.protocols.contains(protocol) {
+                bail!(
+                    "server selected unrequested websocket protocol '{}'",
+                    protocol
+                );
+            }
+        }
+
+        Ok(WebSocketClientConnection { stream, protocol })
+    }
+}
+
+/// An established client WebSocket connection, see [WebSocketClient::connect].
+pub struct WebSocketClientConnection<S> {
+    stream: S,
+    protocol: Option<String>,
+}
+
+impl<S> WebSocketClientConnection<S> {
+    /// The subprotocol selected by the server, if any.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Get the underlying stream, e.g. to use it with a [WebSocketReader] and
+    /// [WebSocketWriter::new_client].
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Connect the WebSocket to `downstream`, which sends and receives the raw data.
+    ///
+    /// Answers control frames like [WebSocket::serve_connection], but masks all outgoing frames.
+    /// Returns the close reason sent by the server, like [WebSocket::serve_connection].
+    pub async fn serve_connection<L>(self, downstream: L) -> Result<Option<CloseReason>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        L: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        self.serve_connection_with_close(downstream, futures::future::pending())
+            .await
+    }
+
+    /// Like [serve_connection](Self::serve_connection), but closes the connection with the
+    /// reason returned by `close` once it completes.
+    pub async fn serve_connection_with_close<L, C>(
+        self,
+        downstream: L,
+        close: C,
+    ) -> Result<Option<CloseReason>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        L: AsyncRead + AsyncWrite + Unpin + Send,
+        C: Future<Output = CloseReason>,
+    {
+        WebSocket {
+            mask: None,
+            deflate: None,
+            keepalive: None,
+        }
+        .do_serve_connection(self.stream, downstream, close, true)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+
+    fn write_messages(messages: &[&[u8]], no_context_takeover: bool) -> Vec<u8> {
+        let mut frames = Vec::new();
+        let mut writer = WebSocketWriter::new(None, &mut frames);
+        writer.enable_permessage_deflate(no_context_takeover);
+        for message in messages {
+            block_on(writer.write_all(message)).unwrap();
+        }
+        frames
+    }
+
+    fn read_messages(
+        frames: Vec<u8>,
+        max_message_size: usize,
+    ) -> (
+        io::Result<Vec<u8>>,
+        mpsc::UnboundedReceiver<WebSocketReadResult>,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut reader = WebSocketReader::with_capacity(io::Cursor::new(frames), 1024, tx);
+        reader.enable_permessage_deflate();
+        reader.max_message_size = max_message_size;
+
+        let mut data = Vec::new();
+        let result = block_on(reader.read_to_end(&mut data)).map(|_| data);
+        (result, rx)
+    }
+
+    #[test]
+    fn test_masked_partial_reads() {
+        let message: Vec<u8> = (0..=255).collect();
+        let mut frames = Vec::new();
+        let mut writer = WebSocketWriter::new(Some([1, 2, 3, 4]), &mut frames);
+        block_on(writer.write_all(&message)).unwrap();
+        block_on(writer.write_all(&message[..5])).unwrap();
+
+        // payloads arrive in pieces which do not start at a multiple of 4, the mask has to
+        // continue at the right offset
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut reader = WebSocketReader::with_capacity(io::Cursor::new(frames), 15, tx);
+        let mut data = Vec::new();
+        let mut buf = [0u8; 3];
+        loop {
+            match block_on(reader.read(&mut buf)).unwrap() {
+                0 => break,
+                len => data.extend_from_slice(&buf[..len]),
+            }
+        }
+        assert_eq!(data, [&message[..], &message[..5]].concat());
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        let large: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let messages: [&[u8]; 4] = [b"hello world", b"hello world", &large, b""];
+        let expected = messages.concat();
+
+        for no_context_takeover in [false, true] {
+            let frames = write_messages(&messages, no_context_takeover);
+            assert!(frames.len() < expected.len());
+
+            let (result, _rx) = read_messages(frames, MAX_INFLATED_MESSAGE_SIZE);
+            assert_eq!(result.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_deflate_message_too_big() {
+        let frames = write_messages(&[&[0u8; 1000]], false);
+        let (result, _rx) = read_messages(frames, 1000);
+        assert_eq!(result.unwrap(), [0u8; 1000]);
+
+        // compresses well, but must not be inflated completely
+        let frames = write_messages(&[&[0u8; 1000], &[0u8; 100_000]], false);
+        let (result, mut rx) = read_messages(frames, 1000);
+        assert!(result.is_err());
+        match rx.try_recv() {
+            Ok(Err(err)) => assert!(matches!(err.kind, WebSocketErrorKind::MessageTooBig)),
+            _ => panic!("expected a 'message too big' error"),
+        }
+    }
+
+    async fn read_head<S: AsyncRead + Unpin>(stream: &mut S) -> String {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        String::from_utf8(head).unwrap()
+    }
+
+    /// Answer the upgrade request of a [WebSocketClient] on `stream`, like the api server does.
+    async fn accept_upgrade<S>(stream: &mut S, builder: WebSocketBuilder) -> WebSocket
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let request = read_head(stream).await;
+        let mut headers = HeaderMap::new();
+        for line in request
+            .split("\r\n")
+            .skip(1)
+            .filter(|line| !line.is_empty())
+        {
+            let (name, value) = line.split_once(':').unwrap();
+            headers.append(
+                HeaderName::from_bytes(name.trim().as_bytes()).unwrap(),
+                HeaderValue::from_str(value.trim()).unwrap(),
+            );
+        }
+
+        let (ws, response) = builder.build(headers).unwrap();
+        let mut head = String::from("HTTP/1.1 101 Switching Protocols\r\n");
+        for (name, value) in response.headers() {
+            head.push_str(&format!("{}: {}\r\n", name, value.to_str().unwrap()));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).await.unwrap();
+        ws
+    }
+
+    fn upgrade_headers() -> HeaderMap<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+        headers.insert(
+            SEC_WEBSOCKET_KEY,
+            HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ=="),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_client_close_reason() {
+        let (client_stream, mut server_stream) = tokio::io::duplex(4096);
+        let (client_local, mut client_app) = tokio::io::duplex(4096);
+        let (server_local, mut server_app) = tokio::io::duplex(4096);
+        let (close_tx, close_rx) = tokio::sync::oneshot::channel();
+
+        let server = async {
+            let ws = accept_upgrade(&mut server_stream, WebSocket::builder()).await;
+            ws.serve_connection_with_close(server_stream, server_local, async {
+                close_rx.await.unwrap()
+            })
+            .await
+        };
+
+        let client = async {
+            let connection = WebSocketClient::new("localhost", "/ws")
+                .protocol("binary")
+                .connect(client_stream)
+                .await
+                .unwrap();
+            // the server echoes the requested protocols
+            assert_eq!(connection.protocol(), Some("binary"));
+            connection.serve_connection(client_local).await
+        };
+
+        let data = async {
+            client_app.write_all(b"hello").await.unwrap();
+            let mut buf = [0u8; 5];
+            server_app.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+
+            server_app.write_all(b"world").await.unwrap();
+            client_app.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"world");
+
+            close_tx
+                .send(CloseReason::new(4000, "session ended"))
+                .unwrap();
+        };
+
+        let (server, client, ()) = tokio::join!(server, client, data);
+        assert_eq!(server.unwrap(), None);
+        assert_eq!(
+            client.unwrap(),
+            Some(CloseReason::new(4000, "session ended"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_invalid_accept() {
+        let (client_stream, mut server_stream) = tokio::io::duplex(4096);
+
+        let server = async {
+            read_head(&mut server_stream).await;
+            server_stream
+                .write_all(
+                    b"HTTP/1.1 101 Switching Protocols\r\n\
+                      Upgrade: websocket\r\n\
+                      Connection: Upgrade\r\n\
+                      Sec-WebSocket-Accept: invalid\r\n\r\n",
+                )
+                .await
+                .unwrap();
+        };
+
+        let client = WebSocketClient::new("localhost", "/ws");
+
+        let ((), client) = tokio::join!(server, client.connect(client_stream));
+        let err = client.err().expect("invalid accept key was not detected");
+        assert!(err.to_string().contains("Sec-WebSocket-Accept"), "{}", err);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keepalive() {
+        let (client_stream, mut server_stream) = tokio::io::duplex(4096);
+        let (client_local, client_app) = tokio::io::duplex(4096);
+        let (server_local, _server_app) = tokio::io::duplex(4096);
+
+        // the client answers the pings, so the connection is kept open until it is closed
+        let server = async {
+            let builder =
+                WebSocket::builder().keepalive(Duration::from_secs(10), Duration::from_secs(5));
+            let ws = accept_upgrade(&mut server_stream, builder).await;
+            ws.serve_connection(server_stream, server_local).await
+        };
+
+        let client = async {
+            let connection = WebSocketClient::new("localhost", "/ws")
+                .connect(client_stream)
+                .await
+                .unwrap();
+            connection.serve_connection(client_local).await
+        };
+
+        let close = async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            drop(client_app);
+        };
+
+        let (server, client, ()) = tokio::join!(server, client, close);
+        assert_eq!(server.unwrap(), Some(CloseReason::new(1000, "")));
+        assert_eq!(client.unwrap(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keepalive_dead_peer() {
+        // the peer reads the pings but never answers them
+        let (upstream, mut peer) = tokio::io::duplex(4096);
+        let (downstream, _app) = tokio::io::duplex(4096);
+        let (ws, _response) = WebSocket::builder()
+            .keepalive(Duration::from_secs(10), Duration::from_secs(5))
+            .build(upgrade_headers())
+            .unwrap();
+
+        let start = Instant::now();
+        let drain = async {
+            let mut buf = Vec::new();
+            let _ = peer.read_to_end(&mut buf).await;
+            buf
+        };
+        let (res, frames) = tokio::join!(ws.serve_connection(upstream, downstream), drain);
+
+        let err = res.expect_err("dead peer was not detected");
+        assert!(err.to_string().contains("did not answer ping"), "{}", err);
+        assert_eq!(start.elapsed(), Duration::from_secs(15));
+        // a single, empty ping frame
+        assert_eq!(frames, [0x89, 0x00]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keepalive_back_pressure() {
+        // the peer does not read anything, so writing the data stalls
+        let (upstream, _peer) = tokio::io::duplex(64);
+        let (downstream, mut app) = tokio::io::duplex(64 * 1024);
+        let (ws, _response) = WebSocket::builder()
+            .keepalive(Duration::from_secs(10), Duration::from_secs(5))
+            .build(upgrade_headers())
+            .unwrap();
+
+        app.write_all(&[0u8; 1024]).await.unwrap();
+
+        let start = Instant::now();
+        let err = ws
+            .serve_connection(upstream, downstream)
+            .await
+            .expect_err("stalled peer was not detected");
+        assert!(err.to_string().contains("did not accept data"), "{}", err);
+        assert_eq!(start.elapsed(), Duration::from_secs(15));
+    }
+}
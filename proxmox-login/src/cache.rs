@@ -0,0 +1,302 @@
+//! On-disk ticket cache shared between multiple invocations of CLI tools.
+//!
+//! Tickets are stored per API URL and userid in a JSON file only accessible by its owner (mode
+//! `0600`). All accesses are protected with `flock(2)`, so concurrently running processes never
+//! see partially written data.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::ticket::Authentication;
+
+/// Cached authentication data, indexed by API URL and userid.
+type CacheData = BTreeMap<String, BTreeMap<String, Authentication>>;
+
+/// A ticket cache file.
+#[derive(Clone, Debug)]
+pub struct TicketCache {
+    path: PathBuf,
+}
+
+/// An open and locked cache file. The lock is released when this is dropped.
+struct LockedCache {
+    file: File,
+}
+
+impl LockedCache {
+    fn open(path: &Path, exclusive: bool) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+
+        // make sure an already existing file is not readable by others
+        let metadata = file.metadata()?;
+        if metadata.permissions().mode() & 0o077 != 0 {
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+
+        let operation = if exclusive {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_SH
+        };
+
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                break;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+
+        Ok(Self { file })
+    }
+
+    fn read(&mut self) -> io::Result<CacheData> {
+        let mut data = String::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_string(&mut data)?;
+
+        if data.trim().is_empty() {
+            return Ok(CacheData::new());
+        }
+
+        // a corrupt cache only means that we need to log in again
+        Ok(serde_json::from_str(&data).unwrap_or_default())
+    }
+
+    fn write(&mut self, data: &CacheData) -> io::Result<()> {
+        let data = serde_json::to_vec(data)?;
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&data)?;
+        self.file.sync_data()
+    }
+}
+
+/// Drop all tickets which are not valid anymore.
+fn remove_expired(data: &mut CacheData) {
+    for users in data.values_mut() {
+        users.retain(|_, auth| auth.ticket.validity().is_valid());
+    }
+    data.retain(|_, users| !users.is_empty());
+}
+
+impl TicketCache {
+    /// Use the cache file at `path`. The file and its parent directory are created on demand.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Use the default cache file `proxmox-login/tickets.json` in the user's cache directory.
+    ///
+    /// This is `$XDG_CACHE_HOME` if set, or `$HOME/.cache` otherwise. Returns `None` if neither
+    /// is set.
+    pub fn with_default_path() -> Option<Self> {
+        let base = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+
+        Some(Self::new(base.join("proxmox-login").join("tickets.json")))
+    }
+
+    /// The path of the cache file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get a still valid ticket for a userid on an API URL.
+    ///
+    /// Tickets in their final validity period are returned as well, callers should check
+    /// [`Ticket::validity`](crate::Ticket::validity) and renew them if necessary.
+    pub fn get(&self, api_url: &str, userid: &str) -> io::Result<Option<Authentication>> {
+        let data = LockedCache::open(&self.path, false)?.read()?;
+
+        Ok(data
+            .get(&crate::normalize_url(api_url.to_string()))
+            .and_then(|users| users.get(userid))
+            .filter(|auth| auth.ticket.validity().is_valid())
+            .cloned())
+    }
+
+    /// Store a new or renewed ticket, replacing the previous one of the same userid and API URL.
+    ///
+    /// Expired tickets of other entries are dropped along the way.
+    pub fn store(&self, auth: &Authentication) -> io::Result<()> {
+        self.update(|data| {
+            data.entry(crate::normalize_url(auth.api_url.clone()))
+                .or_default()
+                .insert(auth.userid.clone(), auth.clone());
+        })
+    }
+
+    /// Remove the ticket of a userid on an API URL, for instance after it got rejected.
+    pub fn remove(&self, api_url: &str, userid: &str) -> io::Result<()> {
+        let api_url = crate::normalize_url(api_url.to_string());
+        self.update(|data| {
+            if let Some(users) = data.get_mut(&api_url) {
+                users.remove(userid);
+            }
+        })
+    }
+
+    /// Remove all cached tickets.
+    pub fn clear(&self) -> io::Result<()> {
+        self.update(|data| data.clear())
+    }
+
+    /// Read, modify and write back the cache while holding an exclusive lock.
+    fn update(&self, func: impl FnOnce(&mut CacheData)) -> io::Result<()> {
+        let mut cache = LockedCache::open(&self.path, true)?;
+        let mut data = cache.read()?;
+        func(&mut data);
+        remove_expired(&mut data);
+        cache.write(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ticket::{epoch_i64, TICKET_LIFETIME};
+
+    /// A cache in its own temporary directory, which is removed when this is dropped.
+    struct TestCache {
+        dir: PathBuf,
+        cache: TicketCache,
+    }
+
+    impl TestCache {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "proxmox-login-cache-test-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            let cache = TicketCache::new(dir.join("sub").join("tickets.json"));
+            Self { dir, cache }
+        }
+    }
+
+    impl Drop for TestCache {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn auth(api_url: &str, userid: &str, age: i64) -> Authentication {
+        let ticket = format!("PVE:{}:{:08X}::c2lnbmF0dXJl", userid, epoch_i64() - age);
+        Authentication {
+            api_url: api_url.to_string(),
+            userid: userid.to_string(),
+            ticket: ticket.parse().unwrap(),
+            clustername: None,
+            csrfprevention_token: format!("csrf-{}", userid),
+        }
+    }
+
+    #[test]
+    fn test_store_and_get() {
+        let test = TestCache::new("store");
+        let cache = &test.cache;
+        const URL: &str = "https://pve.example.com:8006";
+
+        assert!(cache.get(URL, "root@pam").unwrap().is_none());
+
+        cache.store(&auth(URL, "root@pam", 0)).unwrap();
+        cache
+            .store(&auth(&format!("{}/", URL), "user@pve", 0))
+            .unwrap();
+
+        // trailing slashes do not matter
+        let root = cache
+            .get(&format!("{}/", URL), "root@pam")
+            .unwrap()
+            .unwrap();
+        assert_eq!(root.ticket.userid(), "root@pam");
+        assert_eq!(root.csrfprevention_token, "csrf-root@pam");
+        let user = cache.get(URL, "user@pve").unwrap().unwrap();
+        assert_eq!(user.csrfprevention_token, "csrf-user@pve");
+
+        assert!(cache.get(URL, "other@pve").unwrap().is_none());
+        assert!(cache
+            .get("https://other.example.com:8006", "root@pam")
+            .unwrap()
+            .is_none());
+
+        // a renewed ticket replaces the old one
+        let mut renewed = auth(URL, "root@pam", 0);
+        renewed.csrfprevention_token = "renewed".to_string();
+        cache.store(&renewed).unwrap();
+        let root = cache.get(URL, "root@pam").unwrap().unwrap();
+        assert_eq!(root.csrfprevention_token, "renewed");
+
+        cache.remove(&format!("{}/", URL), "root@pam").unwrap();
+        assert!(cache.get(URL, "root@pam").unwrap().is_none());
+        assert!(cache.get(URL, "user@pve").unwrap().is_some());
+
+        cache.clear().unwrap();
+        assert!(cache.get(URL, "user@pve").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_expired_tickets() {
+        let test = TestCache::new("expired");
+        let cache = &test.cache;
+        const URL: &str = "https://pbs.example.com:8007";
+
+        cache
+            .store(&auth(URL, "old@pbs", TICKET_LIFETIME + 60))
+            .unwrap();
+        assert!(cache.get(URL, "old@pbs").unwrap().is_none());
+
+        // tickets which need to be renewed soon are still returned
+        cache
+            .store(&auth(URL, "aging@pbs", TICKET_LIFETIME - 60))
+            .unwrap();
+        assert!(cache.get(URL, "aging@pbs").unwrap().is_some());
+
+        // expired entries are dropped from the file
+        let data = std::fs::read_to_string(cache.path()).unwrap();
+        assert!(!data.contains("old@pbs"));
+        assert!(data.contains("aging@pbs"));
+    }
+
+    #[test]
+    fn test_permissions_and_corruption() {
+        let test = TestCache::new("permissions");
+        let cache = &test.cache;
+        const URL: &str = "https://pve.example.com:8006";
+
+        std::fs::create_dir_all(cache.path().parent().unwrap()).unwrap();
+        std::fs::write(cache.path(), b"{ not json").unwrap();
+        std::fs::set_permissions(cache.path(), std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        // a corrupt cache is treated as empty
+        assert!(cache.get(URL, "root@pam").unwrap().is_none());
+        cache.store(&auth(URL, "root@pam", 0)).unwrap();
+        assert!(cache.get(URL, "root@pam").unwrap().is_some());
+
+        let mode = std::fs::metadata(cache.path())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -15,6 +15,18 @@ impl fmt::Display for TicketError {
     }
 }
 
+/// API token validation error.
+#[derive(Clone, Copy, Debug)]
+pub struct TokenError;
+
+impl std::error::Error for TokenError {}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid API token")
+    }
+}
+
 /// Error parsing an API response.
 #[derive(Debug)]
 pub enum ResponseError {
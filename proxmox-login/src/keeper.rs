@@ -0,0 +1,275 @@
+//! Automatic ticket renewal for long-running clients.
+
+use std::fmt;
+
+use crate::error::ResponseError;
+use crate::ticket::{Authentication, Validity, REFRESH_EARLY_BY, TICKET_LIFETIME};
+use crate::{Login, Request, TicketResult};
+
+type RenewalCallback = Box<dyn FnMut(&Authentication) + Send>;
+
+/// Keeps an [`Authentication`] valid by renewing its ticket before it expires.
+///
+/// Like the rest of this crate, this does not perform any I/O itself. Instead, a client should
+/// regularly check [`needs_refresh`](TicketKeeper::needs_refresh) (or sleep for
+/// [`refresh_in`](TicketKeeper::refresh_in) seconds), send the [`Request`] built by
+/// [`refresh_request`](TicketKeeper::refresh_request) and pass the response's body to
+/// [`response`](TicketKeeper::response). Callbacks registered via
+/// [`on_renewal`](TicketKeeper::on_renewal) are then called with the new authentication data,
+/// e.g. to persist it.
+///
+/// Note that expired tickets cannot be renewed, a new [`Login`] with the user's password is
+/// required in that case.
+pub struct TicketKeeper {
+    auth: Authentication,
+    refresh_early_by: i64,
+    callbacks: Vec<RenewalCallback>,
+}
+
+impl fmt::Debug for TicketKeeper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TicketKeeper")
+            .field("auth", &self.auth)
+            .field("refresh_early_by", &self.refresh_early_by)
+            .field("callbacks", &self.callbacks.len())
+            .finish()
+    }
+}
+
+impl TicketKeeper {
+    /// Keep the ticket of `auth` valid, renewing it during its final half hour of validity.
+    pub fn new(auth: Authentication) -> Self {
+        Self {
+            auth,
+            refresh_early_by: REFRESH_EARLY_BY,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// Renew the ticket once it expires within `seconds` instead of the default half hour.
+    ///
+    /// This is clamped to the ticket lifetime of 2 hours.
+    pub fn refresh_early_by(mut self, seconds: i64) -> Self {
+        self.refresh_early_by = seconds.clamp(0, TICKET_LIFETIME);
+        self
+    }
+
+    /// Register a callback which is called with the new authentication data after each renewal.
+    pub fn on_renewal(mut self, callback: impl FnMut(&Authentication) + Send + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// The current authentication data.
+    pub fn authentication(&self) -> &Authentication {
+        &self.auth
+    }
+
+    /// Get the current authentication data, dropping the keeper.
+    pub fn into_authentication(self) -> Authentication {
+        self.auth
+    }
+
+    /// The age of the current ticket in seconds.
+    pub fn ticket_age(&self) -> i64 {
+        self.auth.ticket.age()
+    }
+
+    /// The current ticket's validity, using the configured refresh period.
+    pub fn validity(&self) -> Validity {
+        let age = self.ticket_age();
+        if age > TICKET_LIFETIME {
+            Validity::Expired
+        } else if age >= TICKET_LIFETIME - self.refresh_early_by {
+            Validity::Refresh
+        } else {
+            Validity::Valid
+        }
+    }
+
+    /// Check whether the ticket should be renewed now.
+    ///
+    /// This is `false` for expired tickets, as they cannot be renewed anymore.
+    pub fn needs_refresh(&self) -> bool {
+        self.validity() == Validity::Refresh
+    }
+
+    /// Check whether the ticket is already expired.
+    pub fn is_expired(&self) -> bool {
+        self.validity() == Validity::Expired
+    }
+
+    /// The number of seconds until the ticket should be renewed, `0` if it is already due.
+    pub fn refresh_in(&self) -> i64 {
+        (TICKET_LIFETIME - self.refresh_early_by - self.ticket_age()).max(0)
+    }
+
+    fn login(&self) -> Login {
+        Login::renew_ticket(self.auth.api_url.clone(), self.auth.ticket.clone())
+    }
+
+    /// Create the HTTP [`Request`] renewing the ticket, if it [needs to be
+    /// refreshed](TicketKeeper::needs_refresh).
+    ///
+    /// Use [`renewal_request`](TicketKeeper::renewal_request) to renew the ticket regardless.
+    pub fn refresh_request(&self) -> Option<Request> {
+        self.needs_refresh().then(|| self.renewal_request())
+    }
+
+    /// Create the HTTP [`Request`] renewing the ticket.
+    pub fn renewal_request(&self) -> Request {
+        self.login().request()
+    }
+
+    /// Deal with the API's response to a renewal request.
+    ///
+    /// On success, the new authentication data replaces the current one and all callbacks
+    /// registered via [`on_renewal`](TicketKeeper::on_renewal) are called.
+    pub fn response<T: ?Sized + AsRef<[u8]>>(
+        &mut self,
+        body: &T,
+    ) -> Result<&Authentication, ResponseError> {
+        let auth = match self.login().response(body)? {
+            TicketResult::Full(auth) => auth,
+            TicketResult::TfaRequired(_) => {
+                return Err("unexpected TFA challenge when renewing ticket".into())
+            }
+        };
+
+        self.auth = auth;
+        for callback in &mut self.callbacks {
+            callback(&self.auth);
+        }
+
+        Ok(&self.auth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::ticket::{epoch_i64, Validity};
+
+    fn ticket_data(age: i64) -> String {
+        format!("PVE:root@pam:{:08X}::c2lnbmF0dXJl", epoch_i64() - age)
+    }
+
+    fn auth(age: i64) -> Authentication {
+        Authentication {
+            api_url: "https://pve.example.com:8006".to_string(),
+            userid: "root@pam".to_string(),
+            ticket: ticket_data(age).parse().unwrap(),
+            clustername: None,
+            csrfprevention_token: "csrf".to_string(),
+        }
+    }
+
+    fn response_body(username: &str, ticket: &str) -> String {
+        serde_json::json!({
+            "data": {
+                "username": username,
+                "ticket": ticket,
+                "CSRFPreventionToken": "renewed-csrf",
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_validity() {
+        let due = TICKET_LIFETIME - REFRESH_EARLY_BY;
+
+        let keeper = TicketKeeper::new(auth(0));
+        assert_eq!(keeper.validity(), Validity::Valid);
+        assert!(!keeper.needs_refresh());
+        assert!(keeper.refresh_request().is_none());
+        // allow for the clock ticking between creating and checking the ticket
+        assert!((due - 1..=due).contains(&keeper.refresh_in()));
+
+        let keeper = TicketKeeper::new(auth(due + 10));
+        assert_eq!(keeper.validity(), Validity::Refresh);
+        assert!(keeper.needs_refresh());
+        assert!(!keeper.is_expired());
+        assert_eq!(keeper.refresh_in(), 0);
+        let request = keeper
+            .refresh_request()
+            .expect("ticket needs to be refreshed");
+        assert_eq!(
+            request.url,
+            "https://pve.example.com:8006/api2/json/access/ticket"
+        );
+
+        let keeper = TicketKeeper::new(auth(TICKET_LIFETIME + 10));
+        assert_eq!(keeper.validity(), Validity::Expired);
+        assert!(!keeper.needs_refresh());
+        assert!(keeper.is_expired());
+        assert!(keeper.refresh_request().is_none());
+        assert_eq!(keeper.refresh_in(), 0);
+    }
+
+    #[test]
+    fn test_refresh_early_by() {
+        let age = 3600;
+
+        let keeper = TicketKeeper::new(auth(age));
+        assert_eq!(keeper.validity(), Validity::Valid);
+
+        let keeper = TicketKeeper::new(auth(age)).refresh_early_by(age);
+        assert_eq!(keeper.validity(), Validity::Refresh);
+
+        // clamped to 0, so the ticket is refreshed at the end of its lifetime
+        let keeper = TicketKeeper::new(auth(age)).refresh_early_by(-10);
+        assert_eq!(keeper.validity(), Validity::Valid);
+        assert!((TICKET_LIFETIME - age - 1..=TICKET_LIFETIME - age).contains(&keeper.refresh_in()));
+
+        // clamped to the ticket lifetime, so the ticket is always refreshed
+        let keeper = TicketKeeper::new(auth(0)).refresh_early_by(10 * TICKET_LIFETIME);
+        assert_eq!(keeper.validity(), Validity::Refresh);
+        assert_eq!(keeper.refresh_in(), 0);
+    }
+
+    #[test]
+    fn test_response() {
+        let renewed = Arc::new(Mutex::new(Vec::new()));
+        let calls = Arc::new(Mutex::new(0));
+
+        let mut keeper = TicketKeeper::new(auth(TICKET_LIFETIME - 60))
+            .on_renewal({
+                let renewed = Arc::clone(&renewed);
+                move |auth| renewed.lock().unwrap().push(auth.ticket.to_string())
+            })
+            .on_renewal({
+                let calls = Arc::clone(&calls);
+                move |_| *calls.lock().unwrap() += 1
+            });
+        let old_ticket = keeper.authentication().ticket.to_string();
+
+        // failed renewals keep the current ticket and do not call the callbacks
+        assert!(keeper
+            .response(&response_body("other@pam", &ticket_data(0)))
+            .is_err());
+        assert!(keeper
+            .response(&response_body(
+                "root@pam",
+                "PVE:!tfa!%7B%22totp%22%3Atrue%7D:signature"
+            ))
+            .is_err());
+        assert!(keeper.response("not json").is_err());
+        assert_eq!(keeper.authentication().ticket.to_string(), old_ticket);
+        assert_eq!(*calls.lock().unwrap(), 0);
+
+        let new_ticket = ticket_data(0);
+        let auth = keeper
+            .response(&response_body("root@pam", &new_ticket))
+            .unwrap();
+        assert_eq!(auth.ticket.to_string(), new_ticket);
+        assert_eq!(auth.csrfprevention_token, "renewed-csrf");
+        assert_eq!(keeper.validity(), Validity::Valid);
+        assert_eq!(*renewed.lock().unwrap(), [new_ticket.as_str()]);
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        assert_eq!(keeper.into_authentication().ticket.to_string(), new_ticket);
+    }
+}
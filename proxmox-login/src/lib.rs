@@ -6,14 +6,23 @@ use serde::{Deserialize, Serialize};
 pub mod parse;
 
 pub mod api;
+#[cfg(feature = "cache")]
+pub mod cache;
 pub mod error;
+pub mod keeper;
 pub mod tfa;
 pub mod ticket;
+pub mod token;
+pub mod webauthn;
 
 const CONTENT_TYPE_JSON: &str = "application/json";
 
+#[doc(inline)]
+pub use keeper::TicketKeeper;
 #[doc(inline)]
 pub use ticket::{Authentication, Ticket};
+#[doc(inline)]
+pub use token::{Credentials, Token};
 
 use error::{ResponseError, TfaError, TicketError};
 
@@ -258,7 +267,6 @@ impl SecondFactorChallenge {
         }
     }
 
-    #[cfg(feature = "webauthn")]
     /// Create a HTTP request responding with a FIDO2/webauthn result JSON string.
     ///
     /// Errors with `TfaError::Unavailable` if no webauthn challenge was available.
@@ -270,6 +278,45 @@ impl SecondFactorChallenge {
         }
     }
 
+    /// Get the webauthn challenge the authenticator has to answer.
+    ///
+    /// Errors with `TfaError::Unavailable` if no webauthn challenge was available.
+    pub fn webauthn_challenge(&self) -> Result<webauthn::WebauthnChallenge, TfaError> {
+        let challenge = self
+            .challenge
+            .webauthn
+            .as_ref()
+            .ok_or(TfaError::Unavailable)?;
+
+        #[cfg(feature = "webauthn")]
+        let challenge = serde_json::to_value(challenge)?;
+
+        #[cfg(not(feature = "webauthn"))]
+        let challenge = challenge.clone();
+
+        Ok(serde_json::from_value(challenge)?)
+    }
+
+    /// Assemble the client data for the webauthn challenge, using the API url's origin.
+    ///
+    /// Errors with `TfaError::Unavailable` if no webauthn challenge was available.
+    pub fn webauthn_client_data(&self) -> Result<webauthn::CollectedClientData, TfaError> {
+        Ok(webauthn::CollectedClientData::new(
+            &self.webauthn_challenge()?,
+            webauthn::origin(&self.api_url),
+        ))
+    }
+
+    /// Create a HTTP request responding with the result of a FIDO2/webauthn authenticator.
+    ///
+    /// Errors with `TfaError::Unavailable` if no webauthn challenge was available.
+    pub fn respond_webauthn_assertion(
+        &self,
+        assertion: &webauthn::AuthenticatorAssertion,
+    ) -> Result<Request, TfaError> {
+        self.respond_webauthn(&assertion.to_json())
+    }
+
     /// Create a HTTP request using a raw response.
     ///
     /// A raw response is the response string prefixed with its challenge type and a colon.
@@ -26,6 +26,12 @@ pub struct TfaChallenge {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub webauthn: Option<webauthn_rs::proto::RequestChallengeResponse>,
 
+    #[cfg(not(feature = "webauthn"))]
+    /// If the user has any webauthn credentials registered, this will contain the corresponding
+    /// challenge data, see [`WebauthnChallenge`](crate::webauthn::WebauthnChallenge).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webauthn: Option<serde_json::Value>,
+
     /// True if the user has yubico keys configured.
     #[serde(skip_serializing_if = "bool_is_false", default)]
     pub yubico: bool,
@@ -45,9 +45,9 @@ pub struct Ticket {
 }
 
 /// Tickets are valid for 2 hours.
-const TICKET_LIFETIME: i64 = 2 * 3600;
+pub(crate) const TICKET_LIFETIME: i64 = 2 * 3600;
 /// We refresh during the last half hour.
-const REFRESH_EARLY_BY: i64 = 1800;
+pub(crate) const REFRESH_EARLY_BY: i64 = 1800;
 
 impl Ticket {
     /// The ticket's product prefix.
@@ -241,12 +241,12 @@ impl Authentication {
 }
 
 #[cfg(target_arch = "wasm32")]
-fn epoch_i64() -> i64 {
+pub(crate) fn epoch_i64() -> i64 {
     (js_sys::Date::now() / 1000.0) as i64
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn epoch_i64() -> i64 {
+pub(crate) fn epoch_i64() -> i64 {
     use std::time::{SystemTime, UNIX_EPOCH};
 
     let now = SystemTime::now();
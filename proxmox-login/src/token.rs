@@ -0,0 +1,275 @@
+//! API token authentication.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TokenError;
+use crate::ticket::Authentication;
+
+/// The header name used for API token authentication.
+pub const AUTHORIZATION_HEADER_NAME: &str = "Authorization";
+
+/// An API token, consisting of the product it is used for, a token ID of the form
+/// `user@realm!tokenname` and the token's secret.
+///
+/// Unlike tickets, tokens do not expire and need no CSRF prevention token. This is serializable
+/// in order to easily store it. The secret is not included in the `Debug` output.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", try_from = "RawToken")]
+pub struct Token {
+    product: String,
+    tokenid: String,
+    secret: String,
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Token")
+            .field("product", &self.product)
+            .field("tokenid", &self.tokenid)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawToken {
+    product: String,
+    tokenid: String,
+    secret: String,
+}
+
+impl TryFrom<RawToken> for Token {
+    type Error = TokenError;
+
+    fn try_from(raw: RawToken) -> Result<Self, TokenError> {
+        Self::new(raw.product, raw.tokenid, raw.secret)
+    }
+}
+
+fn is_valid_user(user: &str) -> bool {
+    !user.is_empty()
+        && !user
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == ':' || c == '/')
+}
+
+/// Realms and token names start with a letter, followed by letters, digits, `.`, `-` or `_`.
+fn is_valid_name(name: &str, min_len: usize) -> bool {
+    let mut chars = name.chars();
+    name.len() >= min_len
+        && chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+/// Check whether `tokenid` is a valid token ID of the form `user@realm!tokenname`.
+pub fn verify_tokenid(tokenid: &str) -> Result<(), TokenError> {
+    let (userid, tokenname) = tokenid.rsplit_once('!').ok_or(TokenError)?;
+    let (user, realm) = userid.rsplit_once('@').ok_or(TokenError)?;
+
+    if is_valid_user(user) && is_valid_name(realm, 2) && is_valid_name(tokenname, 2) {
+        Ok(())
+    } else {
+        Err(TokenError)
+    }
+}
+
+impl Token {
+    /// Create a token for a product (`PVE` or `PBS`), validating the token ID and secret.
+    pub fn new(
+        product: impl Into<String>,
+        tokenid: impl Into<String>,
+        secret: impl Into<String>,
+    ) -> Result<Self, TokenError> {
+        let product = product.into();
+        let tokenid = tokenid.into();
+        let secret = secret.into();
+
+        if !matches!(product.as_str(), "PVE" | "PBS") {
+            return Err(TokenError);
+        }
+
+        verify_tokenid(&tokenid)?;
+
+        if secret.is_empty() || secret.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(TokenError);
+        }
+
+        Ok(Self {
+            product,
+            tokenid,
+            secret,
+        })
+    }
+
+    /// Create a Proxmox VE API token.
+    pub fn pve(tokenid: impl Into<String>, secret: impl Into<String>) -> Result<Self, TokenError> {
+        Self::new("PVE", tokenid, secret)
+    }
+
+    /// Create a Proxmox Backup Server API token.
+    pub fn pbs(tokenid: impl Into<String>, secret: impl Into<String>) -> Result<Self, TokenError> {
+        Self::new("PBS", tokenid, secret)
+    }
+
+    /// The product this token is used for.
+    pub fn product(&self) -> &str {
+        &self.product
+    }
+
+    /// The full token ID in the form `user@realm!tokenname`.
+    pub fn tokenid(&self) -> &str {
+        &self.tokenid
+    }
+
+    /// The userid of the token's owner.
+    pub fn userid(&self) -> &str {
+        match self.tokenid.rsplit_once('!') {
+            Some((userid, _)) => userid,
+            None => &self.tokenid,
+        }
+    }
+
+    /// The token's name.
+    pub fn tokenname(&self) -> &str {
+        match self.tokenid.rsplit_once('!') {
+            Some((_, tokenname)) => tokenname,
+            None => "",
+        }
+    }
+
+    /// The token's secret.
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// Get the value of the `Authorization` header, e.g.
+    /// `PVEAPIToken=root@pam!monitoring=<secret>` or `PBSAPIToken=root@pam!monitoring:<secret>`.
+    pub fn authorization(&self) -> String {
+        let separator = if self.product == "PVE" { '=' } else { ':' };
+        format!(
+            "{}APIToken={}{separator}{}",
+            self.product, self.tokenid, self.secret
+        )
+    }
+
+    #[cfg(feature = "http")]
+    /// Add the authorization header to a request.
+    pub fn set_auth_headers(&self, request: http::request::Builder) -> http::request::Builder {
+        request.header(http::header::AUTHORIZATION, self.authorization())
+    }
+}
+
+/// Credentials for authenticating API requests, either via ticket or via API token.
+///
+/// This is serializable / deserializable in order to be able to easily store it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Credentials {
+    /// Authentication via ticket and CSRF prevention token.
+    Ticket(Authentication),
+
+    /// Authentication via API token.
+    Token(Token),
+}
+
+impl Credentials {
+    /// The userid requests are authenticated as. For tokens, this is the token's owner.
+    pub fn userid(&self) -> &str {
+        match self {
+            Self::Ticket(auth) => &auth.userid,
+            Self::Token(token) => token.userid(),
+        }
+    }
+
+    /// The headers (name and value) to add to API requests.
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Self::Ticket(auth) => vec![
+                ("Cookie", auth.cookie()),
+                (crate::CSRF_HEADER_NAME, auth.csrfprevention_token.clone()),
+            ],
+            Self::Token(token) => vec![(AUTHORIZATION_HEADER_NAME, token.authorization())],
+        }
+    }
+
+    #[cfg(feature = "http")]
+    /// Add authentication headers to a request.
+    pub fn set_auth_headers(&self, request: http::request::Builder) -> http::request::Builder {
+        match self {
+            Self::Ticket(auth) => auth.set_auth_headers(request),
+            Self::Token(token) => token.set_auth_headers(request),
+        }
+    }
+}
+
+impl From<Authentication> for Credentials {
+    fn from(auth: Authentication) -> Self {
+        Self::Ticket(auth)
+    }
+}
+
+impl From<Token> for Credentials {
+    fn from(token: Token) -> Self {
+        Self::Token(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0b5b8e1c-3c1a-4b7e-9d8a-1f2e3d4c5b6a";
+
+    #[test]
+    fn test_verify_tokenid() {
+        assert!(verify_tokenid("root@pam!monitoring").is_ok());
+        assert!(verify_tokenid("user.name@my-realm!token_1").is_ok());
+        assert!(verify_tokenid("user@domain.com@pve!t2").is_ok());
+
+        assert!(verify_tokenid("root@pam").is_err());
+        assert!(verify_tokenid("root!monitoring").is_err());
+        assert!(verify_tokenid("@pam!monitoring").is_err());
+        assert!(verify_tokenid("root user@pam!monitoring").is_err());
+        assert!(verify_tokenid("root@p!monitoring").is_err());
+        assert!(verify_tokenid("root@1pam!monitoring").is_err());
+        assert!(verify_tokenid("root@pam!m").is_err());
+        assert!(verify_tokenid("root@pam!-monitoring").is_err());
+        assert!(verify_tokenid("root@pam!moni:toring").is_err());
+    }
+
+    #[test]
+    fn test_authorization() {
+        let token = Token::pve("root@pam!monitoring", SECRET).unwrap();
+        assert_eq!(
+            token.authorization(),
+            format!("PVEAPIToken=root@pam!monitoring={SECRET}")
+        );
+        assert_eq!(token.userid(), "root@pam");
+        assert_eq!(token.tokenname(), "monitoring");
+
+        let token = Token::pbs("backup@pbs!sync", SECRET).unwrap();
+        assert_eq!(
+            token.authorization(),
+            format!("PBSAPIToken=backup@pbs!sync:{SECRET}")
+        );
+
+        assert!(Token::new("PMG", "root@pam!monitoring", SECRET).is_err());
+        assert!(Token::pve("root@pam!monitoring", "").is_err());
+        assert!(Token::pve("root@pam!monitoring", "with space").is_err());
+    }
+
+    #[test]
+    fn test_debug_redacts_secret() {
+        let token = Token::pve("root@pam!monitoring", SECRET).unwrap();
+
+        let debug = format!("{token:?}");
+        assert!(debug.contains("root@pam!monitoring"));
+        assert!(!debug.contains(SECRET));
+
+        let debug = format!("{:?}", Credentials::from(token));
+        assert!(!debug.contains(SECRET));
+    }
+}
@@ -0,0 +1,319 @@
+//! WebAuthn (FIDO2) challenge and response types.
+//!
+//! These mirror the JSON representation used by the Proxmox APIs (and the `webauthn-rs` crate)
+//! without depending on it, so that clients without a browser can complete a WebAuthn login with
+//! any FIDO2/CTAP implementation:
+//!
+//! 1. Get the [`WebauthnChallenge`] via
+//!    [`SecondFactorChallenge::webauthn_challenge`](crate::SecondFactorChallenge::webauthn_challenge).
+//! 2. Build the [`CollectedClientData`], usually via
+//!    [`SecondFactorChallenge::webauthn_client_data`](crate::SecondFactorChallenge::webauthn_client_data),
+//!    and let the authenticator sign the SHA-256 hash of its
+//!    [`to_json`](CollectedClientData::to_json) output, using the relying party ID and one of the
+//!    allowed credentials of the [`PublicKeyCredentialRequestOptions`].
+//! 3. Pass the authenticator's result as [`AuthenticatorAssertion`] to
+//!    [`SecondFactorChallenge::respond_webauthn_assertion`](crate::SecondFactorChallenge::respond_webauthn_assertion).
+
+use serde::{Deserialize, Serialize};
+
+/// A WebAuthn authentication challenge, as included in a TFA challenge.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebauthnChallenge {
+    /// The options to pass to the authenticator.
+    #[serde(rename = "publicKey")]
+    pub public_key: PublicKeyCredentialRequestOptions,
+}
+
+/// The `publicKey` options of a WebAuthn authentication challenge.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyCredentialRequestOptions {
+    /// The random challenge which has to be signed by the authenticator.
+    #[serde(with = "bytes_as_base64url_nopad")]
+    pub challenge: Vec<u8>,
+
+    /// Timeout in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+
+    /// The relying party ID, usually the host name of the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rp_id: Option<String>,
+
+    /// The credentials registered for the user, one of which has to be used.
+    #[serde(default)]
+    pub allow_credentials: Vec<AllowCredential>,
+
+    /// Whether user verification (e.g. a PIN) is required, preferred or discouraged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_verification: Option<String>,
+
+    /// Extensions requested by the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<serde_json::Value>,
+}
+
+/// A credential which may be used to answer a challenge.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AllowCredential {
+    /// The credential type, always `public-key`.
+    #[serde(rename = "type")]
+    pub ty: String,
+
+    /// The credential ID.
+    #[serde(with = "bytes_as_base64url_nopad")]
+    pub id: Vec<u8>,
+
+    /// Transports the authenticator supports, e.g. `usb` or `nfc`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transports: Option<Vec<String>>,
+}
+
+/// The client data an authenticator signs, see the `CollectedClientData` dictionary of the
+/// WebAuthn specification.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectedClientData {
+    /// Always `webauthn.get` for authentication.
+    #[serde(rename = "type")]
+    pub ty: String,
+
+    /// The challenge from the [`PublicKeyCredentialRequestOptions`].
+    #[serde(with = "bytes_as_base64url_nopad")]
+    pub challenge: Vec<u8>,
+
+    /// The origin of the server, e.g. `https://pve.example.com:8006`.
+    pub origin: String,
+
+    /// Whether the request was made from a cross-origin context.
+    pub cross_origin: bool,
+}
+
+impl CollectedClientData {
+    /// Create the client data for answering `challenge` for a server at `origin`.
+    pub fn new(challenge: &WebauthnChallenge, origin: impl Into<String>) -> Self {
+        Self {
+            ty: "webauthn.get".to_string(),
+            challenge: challenge.public_key.challenge.clone(),
+            origin: origin.into(),
+            cross_origin: false,
+        }
+    }
+
+    /// The serialized client data. Its SHA-256 hash is the `clientDataHash` the authenticator
+    /// has to sign, the serialized data itself has to be sent back in the
+    /// [`AuthenticatorAssertion`].
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap() // this can never fail
+    }
+}
+
+/// The result of a WebAuthn authentication, as returned by the authenticator.
+#[derive(Clone, Debug)]
+pub struct AuthenticatorAssertion {
+    /// The ID of the credential which was used.
+    pub credential_id: Vec<u8>,
+
+    /// The authenticator data.
+    pub authenticator_data: Vec<u8>,
+
+    /// The serialized client data which was signed, see [`CollectedClientData::to_json`].
+    pub client_data_json: Vec<u8>,
+
+    /// The signature over the authenticator data and the client data hash.
+    pub signature: Vec<u8>,
+
+    /// The user handle, if the authenticator returned one.
+    pub user_handle: Option<Vec<u8>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicKeyCredential<'a> {
+    id: String,
+    #[serde(with = "bytes_as_base64url_nopad")]
+    raw_id: &'a [u8],
+    #[serde(rename = "type")]
+    ty: &'static str,
+    response: AuthenticatorAssertionResponse<'a>,
+    extensions: serde_json::Value,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthenticatorAssertionResponse<'a> {
+    #[serde(with = "bytes_as_base64url_nopad")]
+    authenticator_data: &'a [u8],
+    #[serde(rename = "clientDataJSON", with = "bytes_as_base64url_nopad")]
+    client_data_json: &'a [u8],
+    #[serde(with = "bytes_as_base64url_nopad")]
+    signature: &'a [u8],
+    user_handle: Option<String>,
+}
+
+impl AuthenticatorAssertion {
+    /// The `PublicKeyCredential` JSON object expected by the ticket API.
+    pub fn to_json(&self) -> String {
+        let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+
+        let credential = PublicKeyCredential {
+            id: encode(&self.credential_id),
+            raw_id: &self.credential_id,
+            ty: "public-key",
+            response: AuthenticatorAssertionResponse {
+                authenticator_data: &self.authenticator_data,
+                client_data_json: &self.client_data_json,
+                signature: &self.signature,
+                user_handle: self.user_handle.as_deref().map(encode),
+            },
+            extensions: serde_json::json!({}),
+        };
+
+        serde_json::to_string(&credential).unwrap() // this can never fail
+    }
+}
+
+/// Get the origin (scheme, host and port) of an API URL.
+pub(crate) fn origin(api_url: &str) -> &str {
+    let authority_start = api_url.find("://").map(|pos| pos + 3).unwrap_or(0);
+    match api_url[authority_start..].find('/') {
+        Some(end) => &api_url[..authority_start + end],
+        None => api_url,
+    }
+}
+
+mod bytes_as_base64url_nopad {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer, T: AsRef<[u8]>>(
+        data: T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode_config(
+            data.as_ref(),
+            base64::URL_SAFE_NO_PAD,
+        ))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        use serde::de::Error;
+        String::deserialize(deserializer).and_then(|string| {
+            // be lenient about padding, some implementations include it
+            base64::decode_config(string.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+                .map_err(|err| Error::custom(err.to_string()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // as sent by PVE and PBS, `webauthn-rs` 0.3 omits the padding
+    const CHALLENGE: &str = r#"{
+        "publicKey": {
+            "challenge": "q83vEjRWeJA",
+            "timeout": 60000,
+            "rpId": "pve.example.com",
+            "allowCredentials": [
+                { "type": "public-key", "id": "AQIDBA" }
+            ],
+            "userVerification": "preferred",
+            "extensions": { "appid": "https://pve.example.com:8006" }
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_challenge() {
+        let challenge: WebauthnChallenge = serde_json::from_str(CHALLENGE).unwrap();
+        let options = &challenge.public_key;
+        assert_eq!(
+            options.challenge,
+            [0xab, 0xcd, 0xef, 0x12, 0x34, 0x56, 0x78, 0x90]
+        );
+        assert_eq!(options.timeout, Some(60000));
+        assert_eq!(options.rp_id.as_deref(), Some("pve.example.com"));
+        assert_eq!(options.allow_credentials.len(), 1);
+        assert_eq!(options.allow_credentials[0].ty, "public-key");
+        assert_eq!(options.allow_credentials[0].id, [1, 2, 3, 4]);
+        assert_eq!(options.user_verification.as_deref(), Some("preferred"));
+
+        let padded: WebauthnChallenge = serde_json::from_str(
+            &CHALLENGE
+                .replace("q83vEjRWeJA", "q83vEjRWeJA=")
+                .replace("AQIDBA", "AQIDBA=="),
+        )
+        .unwrap();
+        assert_eq!(padded.public_key.challenge, options.challenge);
+        assert_eq!(padded.public_key.allow_credentials[0].id, [1, 2, 3, 4]);
+
+        let minimal: WebauthnChallenge =
+            serde_json::from_str(r#"{"publicKey":{"challenge":"AQID"}}"#).unwrap();
+        assert_eq!(minimal.public_key.challenge, [1, 2, 3]);
+        assert!(minimal.public_key.allow_credentials.is_empty());
+
+        assert!(serde_json::from_str::<WebauthnChallenge>(
+            r#"{"publicKey":{"challenge":"not base64!"}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_client_data() {
+        let challenge: WebauthnChallenge = serde_json::from_str(CHALLENGE).unwrap();
+        let client_data = CollectedClientData::new(&challenge, "https://pve.example.com:8006");
+
+        assert_eq!(
+            client_data.to_json(),
+            r#"{"type":"webauthn.get","challenge":"q83vEjRWeJA","origin":"https://pve.example.com:8006","crossOrigin":false}"#
+        );
+    }
+
+    #[test]
+    fn test_assertion() {
+        let assertion = AuthenticatorAssertion {
+            credential_id: vec![1, 2, 3, 4],
+            authenticator_data: vec![5, 6, 7],
+            client_data_json: b"{}".to_vec(),
+            signature: vec![0xff, 0xfe],
+            user_handle: None,
+        };
+        assert_eq!(
+            assertion.to_json(),
+            r#"{"id":"AQIDBA","rawId":"AQIDBA","type":"public-key","response":{"authenticatorData":"BQYH","clientDataJSON":"e30","signature":"__4","userHandle":null},"extensions":{}}"#
+        );
+
+        let assertion = AuthenticatorAssertion {
+            user_handle: Some(b"user".to_vec()),
+            ..assertion
+        };
+        assert!(assertion.to_json().contains(r#""userHandle":"dXNlcg""#));
+    }
+
+    #[test]
+    fn test_origin() {
+        assert_eq!(
+            origin("https://pve.example.com:8006"),
+            "https://pve.example.com:8006"
+        );
+        assert_eq!(
+            origin("https://pve.example.com:8006/"),
+            "https://pve.example.com:8006"
+        );
+        assert_eq!(
+            origin("https://pbs.example.com:8007/api2/json"),
+            "https://pbs.example.com:8007"
+        );
+        assert_eq!(origin("pve.example.com:8006/path"), "pve.example.com:8006");
+    }
+
+    #[cfg(not(feature = "webauthn"))]
+    #[test]
+    fn test_lenient_tfa_challenge() {
+        let challenge: crate::tfa::TfaChallenge =
+            serde_json::from_str(r#"{"totp":true,"webauthn":{"unexpected":1}}"#).unwrap();
+        assert!(challenge.totp);
+        assert!(challenge.webauthn.is_some());
+        assert!(serde_json::from_value::<WebauthnChallenge>(challenge.webauthn.unwrap()).is_err());
+    }
+}
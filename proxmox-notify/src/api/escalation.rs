@@ -0,0 +1,31 @@
+use proxmox_http_error::HttpError;
+
+use crate::api::{http_bail, http_err};
+use crate::escalation::{self, EscalationState, PendingEscalation};
+
+/// Get a list of all events which will be escalated unless acknowledged.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns a `HttpError` if the escalation state could not be read
+/// (`500 Internal server error`).
+pub fn get_pending_escalations() -> Result<Vec<PendingEscalation>, HttpError> {
+    escalation::pending(&EscalationState::new("escalations"))
+        .map_err(|err| http_err!(INTERNAL_SERVER_ERROR, "{err}"))
+}
+
+/// Acknowledge an event, which stops its escalation chain.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns a `HttpError` if there is no pending escalation with the given `id`
+/// (`404 Not found`) or if the escalation state could not be updated
+/// (`500 Internal server error`).
+pub fn acknowledge_escalation(id: &str) -> Result<(), HttpError> {
+    let acknowledged = escalation::acknowledge(&EscalationState::new("escalations"), id)
+        .map_err(|err| http_err!(INTERNAL_SERVER_ERROR, "{err}"))?;
+
+    if !acknowledged {
+        http_bail!(NOT_FOUND, "no pending escalation with id '{id}'");
+    }
+
+    Ok(())
+}
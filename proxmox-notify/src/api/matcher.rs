@@ -39,6 +39,7 @@ pub fn get_matcher(config: &Config, name: &str) -> Result<MatcherConfig, HttpErr
 pub fn add_matcher(config: &mut Config, matcher_config: MatcherConfig) -> Result<(), HttpError> {
     super::ensure_unique(config, &matcher_config.name)?;
     super::ensure_endpoints_exist(config, &matcher_config.target)?;
+    ensure_escalation_targets_exist(config, &matcher_config)?;
 
     config
         .config
@@ -83,6 +84,8 @@ pub fn update_matcher(
                 DeleteableMatcherProperty::InvertMatch => matcher.invert_match = None,
                 DeleteableMatcherProperty::Comment => matcher.comment = None,
                 DeleteableMatcherProperty::Disable => matcher.disable = None,
+                DeleteableMatcherProperty::DigestWindow => matcher.digest_window = None,
+                DeleteableMatcherProperty::Escalate => matcher.escalate.clear(),
             }
         }
     }
@@ -115,6 +118,15 @@ pub fn update_matcher(
         matcher.disable = Some(disable);
     }
 
+    if let Some(digest_window) = matcher_updater.digest_window {
+        matcher.digest_window = Some(digest_window);
+    }
+
+    if let Some(escalate) = matcher_updater.escalate {
+        matcher.escalate = escalate;
+        ensure_escalation_targets_exist(config, &matcher)?;
+    }
+
     if let Some(target) = matcher_updater.target {
         super::ensure_endpoints_exist(config, target.as_slice())?;
         matcher.target = target;
@@ -133,6 +145,18 @@ pub fn update_matcher(
     Ok(())
 }
 
+fn ensure_escalation_targets_exist(
+    config: &Config,
+    matcher: &MatcherConfig,
+) -> Result<(), HttpError> {
+    let targets: Vec<&str> = matcher
+        .escalate
+        .iter()
+        .map(|step| step.target.as_str())
+        .collect();
+    super::ensure_endpoints_exist(config, &targets)
+}
+
 /// Delete existing matcher
 ///
 /// The caller is responsible for any needed permission checks.
@@ -8,6 +8,7 @@ use proxmox_schema::api;
 use crate::{Config, Origin};
 
 pub mod common;
+pub mod escalation;
 #[cfg(feature = "gotify")]
 pub mod gotify;
 pub mod matcher;
@@ -15,6 +16,9 @@ pub mod matcher;
 pub mod sendmail;
 #[cfg(feature = "smtp")]
 pub mod smtp;
+pub mod template;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 
 // We have our own, local versions of http_err and http_bail, because
 // we don't want to wrap the error in anyhow::Error. If we were to do that,
@@ -54,6 +58,9 @@ pub enum EndpointType {
     /// Gotify endpoint
     #[cfg(feature = "gotify")]
     Gotify,
+    /// Webhook endpoint
+    #[cfg(feature = "webhook")]
+    Webhook,
 }
 
 #[api]
@@ -113,6 +120,17 @@ pub fn get_targets(config: &Config) -> Result<Vec<Target>, HttpError> {
         })
     }
 
+    #[cfg(feature = "webhook")]
+    for endpoint in webhook::get_endpoints(config)? {
+        targets.push(Target {
+            name: endpoint.name,
+            origin: endpoint.origin.unwrap_or(Origin::UserCreated),
+            endpoint_type: EndpointType::Webhook,
+            disable: endpoint.disable,
+            comment: endpoint.comment,
+        })
+    }
+
     Ok(targets)
 }
 
@@ -145,6 +163,10 @@ fn ensure_endpoint_exists(#[allow(unused)] config: &Config, name: &str) -> Resul
     {
         exists = exists || smtp::get_endpoint(config, name).is_ok();
     }
+    #[cfg(feature = "webhook")]
+    {
+        exists = exists || webhook::get_endpoint(config, name).is_ok();
+    }
 
     if !exists {
         http_bail!(NOT_FOUND, "endpoint '{name}' does not exist")
@@ -179,7 +201,13 @@ fn get_referrers(config: &Config, entity: &str) -> Result<HashSet<String>, HttpE
     let mut referrers = HashSet::new();
 
     for matcher in matcher::get_matchers(config)? {
-        if matcher.target.iter().any(|target| target == entity) {
+        let escalation_targets = matcher.escalate.iter().map(|step| &step.target);
+        if matcher
+            .target
+            .iter()
+            .chain(escalation_targets)
+            .any(|target| target == entity)
+        {
             referrers.insert(matcher.name.clone());
         }
     }
@@ -228,6 +256,9 @@ fn get_referenced_entities(config: &Config, entity: &str) -> HashSet<String> {
                 for target in matcher.target {
                     new.insert(target.clone());
                 }
+                for step in matcher.escalate {
+                    new.insert(step.target);
+                }
             }
         }
 
@@ -86,6 +86,7 @@ pub fn update_endpoint(
                 DeleteableSendmailProperty::Mailto => endpoint.mailto.clear(),
                 DeleteableSendmailProperty::MailtoUser => endpoint.mailto_user.clear(),
                 DeleteableSendmailProperty::Disable => endpoint.disable = None,
+                DeleteableSendmailProperty::HtmlStyle => endpoint.html_style = None,
             }
         }
     }
@@ -106,6 +107,10 @@ pub fn update_endpoint(
         endpoint.author = Some(author);
     }
 
+    if let Some(html_style) = updater.html_style {
+        endpoint.html_style = Some(html_style);
+    }
+
     if let Some(comment) = updater.comment {
         endpoint.comment = Some(comment);
     }
@@ -2,7 +2,7 @@ use proxmox_http_error::HttpError;
 
 use crate::api::{http_bail, http_err};
 use crate::endpoints::smtp::{
-    DeleteableSmtpProperty, SmtpConfig, SmtpConfigUpdater, SmtpPrivateConfig,
+    DeleteableSmtpProperty, SmtpAuthMethod, SmtpConfig, SmtpConfigUpdater, SmtpPrivateConfig,
     SmtpPrivateConfigUpdater, SMTP_TYPENAME,
 };
 use crate::Config;
@@ -30,6 +30,22 @@ pub fn get_endpoint(config: &Config, name: &str) -> Result<SmtpConfig, HttpError
         .map_err(|_| http_err!(NOT_FOUND, "endpoint '{name}' not found"))
 }
 
+fn verify_auth_method(endpoint: &SmtpConfig) -> Result<(), HttpError> {
+    if endpoint.auth_method.unwrap_or_default() == SmtpAuthMethod::Oauth2 {
+        if endpoint.username.is_none() {
+            http_bail!(BAD_REQUEST, "OAuth2 authentication requires a username");
+        }
+        if endpoint.oauth2_token_url.is_none() || endpoint.oauth2_client_id.is_none() {
+            http_bail!(
+                BAD_REQUEST,
+                "OAuth2 authentication requires 'oauth2-token-url' and 'oauth2-client-id'"
+            );
+        }
+    }
+
+    Ok(())
+}
+
 /// Add a new smtp endpoint.
 ///
 /// The caller is responsible for any needed permission checks.
@@ -38,6 +54,7 @@ pub fn get_endpoint(config: &Config, name: &str) -> Result<SmtpConfig, HttpError
 ///   - an entity with the same name already exists (`400 Bad request`)
 ///   - the configuration could not be saved (`500 Internal server error`)
 ///   - mailto *and* mailto_user are both set to `None`
+///   - OAuth2 authentication is used without username, token URL or client ID
 pub fn add_endpoint(
     config: &mut Config,
     endpoint_config: SmtpConfig,
@@ -57,6 +74,8 @@ pub fn add_endpoint(
         );
     }
 
+    verify_auth_method(&endpoint_config)?;
+
     super::set_private_config_entry(
         config,
         private_endpoint_config,
@@ -83,6 +102,7 @@ pub fn add_endpoint(
 /// Returns a `HttpError` if:
 ///   - the configuration could not be saved (`500 Internal server error`)
 ///   - mailto *and* mailto_user are both set to `None`
+///   - OAuth2 authentication is used without username, token URL or client ID
 pub fn update_endpoint(
     config: &mut Config,
     name: &str,
@@ -94,24 +114,34 @@ pub fn update_endpoint(
     super::verify_digest(config, digest)?;
 
     let mut endpoint = get_endpoint(config, name)?;
+    let mut private_endpoint: SmtpPrivateConfig = config
+        .private_config
+        .lookup(SMTP_TYPENAME, name)
+        .unwrap_or_else(|_| SmtpPrivateConfig {
+            name: name.into(),
+            ..Default::default()
+        });
 
     if let Some(delete) = delete {
         for deleteable_property in delete {
             match deleteable_property {
+                DeleteableSmtpProperty::AuthMethod => endpoint.auth_method = None,
                 DeleteableSmtpProperty::Author => endpoint.author = None,
                 DeleteableSmtpProperty::Comment => endpoint.comment = None,
                 DeleteableSmtpProperty::Disable => endpoint.disable = None,
+                DeleteableSmtpProperty::HtmlStyle => endpoint.html_style = None,
                 DeleteableSmtpProperty::Mailto => endpoint.mailto.clear(),
                 DeleteableSmtpProperty::MailtoUser => endpoint.mailto_user.clear(),
-                DeleteableSmtpProperty::Password => super::set_private_config_entry(
-                    config,
-                    SmtpPrivateConfig {
-                        name: name.to_string(),
-                        password: None,
-                    },
-                    SMTP_TYPENAME,
-                    name,
-                )?,
+                DeleteableSmtpProperty::Oauth2ClientId => endpoint.oauth2_client_id = None,
+                DeleteableSmtpProperty::Oauth2ClientSecret => {
+                    private_endpoint.oauth2_client_secret = None
+                }
+                DeleteableSmtpProperty::Oauth2RefreshToken => {
+                    private_endpoint.oauth2_refresh_token = None
+                }
+                DeleteableSmtpProperty::Oauth2Scope => endpoint.oauth2_scope = None,
+                DeleteableSmtpProperty::Oauth2TokenUrl => endpoint.oauth2_token_url = None,
+                DeleteableSmtpProperty::Password => private_endpoint.password = None,
                 DeleteableSmtpProperty::Port => endpoint.port = None,
                 DeleteableSmtpProperty::Username => endpoint.username = None,
             }
@@ -139,22 +169,36 @@ pub fn update_endpoint(
     if let Some(mode) = updater.mode {
         endpoint.mode = Some(mode);
     }
+    if let Some(auth_method) = updater.auth_method {
+        endpoint.auth_method = Some(auth_method);
+    }
+    if let Some(oauth2_token_url) = updater.oauth2_token_url {
+        endpoint.oauth2_token_url = Some(oauth2_token_url);
+    }
+    if let Some(oauth2_client_id) = updater.oauth2_client_id {
+        endpoint.oauth2_client_id = Some(oauth2_client_id);
+    }
+    if let Some(oauth2_scope) = updater.oauth2_scope {
+        endpoint.oauth2_scope = Some(oauth2_scope);
+    }
     if let Some(password) = private_endpoint_config_updater.password {
-        super::set_private_config_entry(
-            config,
-            SmtpPrivateConfig {
-                name: name.into(),
-                password: Some(password),
-            },
-            SMTP_TYPENAME,
-            name,
-        )?;
+        private_endpoint.password = Some(password);
+    }
+    if let Some(client_secret) = private_endpoint_config_updater.oauth2_client_secret {
+        private_endpoint.oauth2_client_secret = Some(client_secret);
+    }
+    if let Some(refresh_token) = private_endpoint_config_updater.oauth2_refresh_token {
+        private_endpoint.oauth2_refresh_token = Some(refresh_token);
     }
 
     if let Some(author) = updater.author {
         endpoint.author = Some(author);
     }
 
+    if let Some(html_style) = updater.html_style {
+        endpoint.html_style = Some(html_style);
+    }
+
     if let Some(comment) = updater.comment {
         endpoint.comment = Some(comment);
     }
@@ -170,6 +214,10 @@ pub fn update_endpoint(
         );
     }
 
+    verify_auth_method(&endpoint)?;
+
+    super::set_private_config_entry(config, private_endpoint, SMTP_TYPENAME, name)?;
+
     config
         .config
         .set_data(name, SMTP_TYPENAME, &endpoint)
@@ -225,6 +273,7 @@ pub mod tests {
             SmtpPrivateConfig {
                 name: name.into(),
                 password: Some("password".into()),
+                ..Default::default()
             },
         )?;
 
@@ -348,6 +397,85 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_update_oauth2() -> Result<(), HttpError> {
+        let mut config = empty_config();
+        add_smtp_endpoint_for_test(&mut config, "smtp-endpoint")?;
+
+        // token URL and client ID are required
+        assert!(update_endpoint(
+            &mut config,
+            "smtp-endpoint",
+            SmtpConfigUpdater {
+                auth_method: Some(SmtpAuthMethod::Oauth2),
+                ..Default::default()
+            },
+            Default::default(),
+            None,
+            None,
+        )
+        .is_err());
+
+        update_endpoint(
+            &mut config,
+            "smtp-endpoint",
+            SmtpConfigUpdater {
+                auth_method: Some(SmtpAuthMethod::Oauth2),
+                oauth2_token_url: Some("https://login.example.com/token".into()),
+                oauth2_client_id: Some("client".into()),
+                ..Default::default()
+            },
+            SmtpPrivateConfigUpdater {
+                oauth2_client_secret: Some("secret".into()),
+                ..Default::default()
+            },
+            None,
+            None,
+        )?;
+
+        let private_config: SmtpPrivateConfig = config
+            .private_config
+            .lookup(SMTP_TYPENAME, "smtp-endpoint")
+            .unwrap();
+        // other private properties must be kept
+        assert_eq!(private_config.password.as_deref(), Some("password"));
+        assert_eq!(
+            private_config.oauth2_client_secret.as_deref(),
+            Some("secret")
+        );
+
+        // the username is still required
+        assert!(update_endpoint(
+            &mut config,
+            "smtp-endpoint",
+            Default::default(),
+            Default::default(),
+            Some(&[DeleteableSmtpProperty::Username]),
+            None,
+        )
+        .is_err());
+
+        update_endpoint(
+            &mut config,
+            "smtp-endpoint",
+            Default::default(),
+            Default::default(),
+            Some(&[
+                DeleteableSmtpProperty::AuthMethod,
+                DeleteableSmtpProperty::Oauth2ClientId,
+                DeleteableSmtpProperty::Oauth2ClientSecret,
+                DeleteableSmtpProperty::Oauth2TokenUrl,
+            ]),
+            None,
+        )?;
+
+        let endpoint = get_endpoint(&config, "smtp-endpoint")?;
+        assert_eq!(endpoint.auth_method, None);
+        assert_eq!(endpoint.oauth2_token_url, None);
+
+        Ok(())
+    }
+
     #[test]
     fn test_delete() -> Result<(), HttpError> {
         let mut config = empty_config();
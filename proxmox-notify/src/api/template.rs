@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use const_format::concatcp;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use proxmox_http_error::HttpError;
+use proxmox_schema::api_types::SAFE_ID_REGEX_STR;
+use proxmox_schema::{api, const_regex, ApiStringFormat, Schema, StringSchema};
+
+use crate::api::{http_bail, http_err};
+use crate::context::context;
+use crate::renderer::{self, TemplateType, BUILTIN_TEMPLATES};
+use crate::{Bus, Config};
+
+const_regex! {
+    pub TEMPLATE_FILENAME_REGEX = concatcp!(
+        r"^", SAFE_ID_REGEX_STR, r"-(?:body\.html|body\.txt|subject\.txt)\.hbs$"
+    );
+}
+
+pub const TEMPLATE_FILENAME_FORMAT: ApiStringFormat =
+    ApiStringFormat::Pattern(&TEMPLATE_FILENAME_REGEX);
+
+pub const TEMPLATE_FILENAME_SCHEMA: Schema = StringSchema::new(
+    "Template file name, e.g. 'vzdump-body.txt.hbs' or 'vzdump-subject.txt.hbs'.",
+)
+.format(&TEMPLATE_FILENAME_FORMAT)
+.max_length(128)
+.schema();
+
+#[api(
+    properties: {
+        name: {
+            schema: TEMPLATE_FILENAME_SCHEMA,
+        },
+    },
+)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// A notification template.
+pub struct TemplateEntry {
+    /// Name of the template file.
+    pub name: String,
+    /// Whether the template is overridden by a user-provided version.
+    pub overridden: bool,
+}
+
+fn template_type(name: &str) -> Result<TemplateType, HttpError> {
+    if !TEMPLATE_FILENAME_REGEX.is_match(name) {
+        http_bail!(BAD_REQUEST, "invalid template file name '{name}'");
+    }
+
+    TemplateType::from_filename(name)
+        .ok_or_else(|| http_err!(BAD_REQUEST, "invalid template file name '{name}'"))
+}
+
+fn override_dir() -> Result<std::path::PathBuf, HttpError> {
+    context()
+        .template_override_dir(None)
+        .ok_or_else(|| http_err!(BAD_REQUEST, "template overrides are not supported"))
+}
+
+fn list_template_files(dir: &Path) -> Result<Vec<String>, HttpError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => http_bail!(
+            INTERNAL_SERVER_ERROR,
+            "could not list templates in '{}': {err}",
+            dir.display()
+        ),
+    };
+
+    Ok(entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| TEMPLATE_FILENAME_REGEX.is_match(name))
+        .collect())
+}
+
+/// Get a list of all available templates.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns a `HttpError` if a template directory could not be read
+/// (`500 Internal server error`).
+pub fn get_templates() -> Result<Vec<TemplateEntry>, HttpError> {
+    let mut templates = BTreeMap::new();
+
+    for (name, _) in BUILTIN_TEMPLATES {
+        templates.insert(name.to_string(), false);
+    }
+
+    if let Some(dir) = context().template_dir(None) {
+        for name in list_template_files(&dir)? {
+            templates.insert(name, false);
+        }
+    }
+
+    if let Some(dir) = context().template_override_dir(None) {
+        for name in list_template_files(&dir)? {
+            templates.insert(name, true);
+        }
+    }
+
+    Ok(templates
+        .into_iter()
+        .map(|(name, overridden)| TemplateEntry { name, overridden })
+        .collect())
+}
+
+/// Get the content of the template `name`, as it is used when rendering notifications.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns a `HttpError` if:
+///   - the template file name is invalid (`400 Bad request`)
+///   - the template does not exist (`404 Not found`)
+///   - the template could not be read (`500 Internal server error`)
+pub fn get_template(name: &str) -> Result<String, HttpError> {
+    template_type(name)?;
+
+    renderer::lookup_template(name)
+        .map_err(|err| http_err!(INTERNAL_SERVER_ERROR, "{err}"))?
+        .ok_or_else(|| http_err!(NOT_FOUND, "template '{name}' not found"))
+}
+
+/// Override the template `name` with a user-provided `template`.
+///
+/// The template is checked for syntax errors and unknown helpers before it is saved.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns a `HttpError` if:
+///   - the template file name is invalid (`400 Bad request`)
+///   - the template is not valid (`400 Bad request`)
+///   - the product does not support template overrides (`400 Bad request`)
+///   - the template could not be saved (`500 Internal server error`)
+pub fn set_template_override(name: &str, template: &str) -> Result<(), HttpError> {
+    let ty = template_type(name)?;
+
+    renderer::validate_template(template, ty)
+        .map_err(|err| http_err!(BAD_REQUEST, "template '{name}' is not valid: {err}"))?;
+
+    let dir = override_dir()?;
+    std::fs::create_dir_all(&dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| {
+            proxmox_sys::fs::replace_file(
+                dir.join(name),
+                template.as_bytes(),
+                context().template_override_options(),
+                true,
+            )
+        })
+        .map_err(|err| {
+            http_err!(
+                INTERNAL_SERVER_ERROR,
+                "could not save template '{name}': {err}"
+            )
+        })
+}
+
+/// Remove the user-provided override of template `name`.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns a `HttpError` if:
+///   - the template file name is invalid (`400 Bad request`)
+///   - the template is not overridden (`404 Not found`)
+///   - the override could not be removed (`500 Internal server error`)
+pub fn reset_template(name: &str) -> Result<(), HttpError> {
+    template_type(name)?;
+
+    match std::fs::remove_file(override_dir()?.join(name)) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            http_bail!(NOT_FOUND, "template '{name}' is not overridden")
+        }
+        Err(err) => http_bail!(
+            INTERNAL_SERVER_ERROR,
+            "could not reset template '{name}': {err}"
+        ),
+    }
+}
+
+/// Send a synthetic notification using the template `template` to the target `endpoint`.
+///
+/// `data` is passed to the template, it defaults to an object only containing the
+/// name of the target, like for regular test notifications.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns a `HttpError` if:
+///   - the endpoint does not exist (`404 Not found`)
+///   - the notification could not be rendered or sent (`500 Internal server error`)
+pub fn test_template(
+    config: &Config,
+    endpoint: &str,
+    template: &str,
+    data: Option<Value>,
+) -> Result<(), HttpError> {
+    let bus = Bus::from_config(config).map_err(|err| {
+        http_err!(
+            INTERNAL_SERVER_ERROR,
+            "Could not instantiate notification bus: {err}"
+        )
+    })?;
+
+    let data = data.unwrap_or_else(|| json!({ "target": endpoint }));
+
+    bus.test_template(endpoint, template, data)
+        .map_err(|err| match err {
+            crate::Error::TargetDoesNotExist(endpoint) => {
+                http_err!(NOT_FOUND, "endpoint '{endpoint}' does not exist")
+            }
+            _ => http_err!(
+                INTERNAL_SERVER_ERROR,
+                "Could not send test notification: {err}"
+            ),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_override() -> Result<(), HttpError> {
+        struct RemoveDir(std::path::PathBuf);
+
+        impl Drop for RemoveDir {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_dir_all(&self.0);
+            }
+        }
+
+        let _dir = RemoveDir(override_dir()?);
+        let name = "template-api-body.txt.hbs";
+
+        assert!(set_template_override("../escape-body.txt.hbs", "").is_err());
+        assert!(set_template_override("template-api.hbs", "").is_err());
+        assert!(set_template_override(name, "{{#if foo}}unclosed").is_err());
+        assert!(set_template_override(name, "{{ unknown-helper foo }}").is_err());
+        assert!(set_template_override(name, "{{#each (unknown foo)}}{{/each}}").is_err());
+        assert!(set_template_override(name, "{{> partial}}").is_err());
+        assert!(reset_template(name).is_err());
+
+        set_template_override(
+            name,
+            "{{#if table}}{{ table table }}{{/if}} {{ human-bytes size }} {{ hostname }}",
+        )?;
+        assert_eq!(
+            get_template(name)?,
+            "{{#if table}}{{ table table }}{{/if}} {{ human-bytes size }} {{ hostname }}"
+        );
+        assert!(get_templates()?.contains(&TemplateEntry {
+            name: name.into(),
+            overridden: true,
+        }));
+        assert!(get_templates()?.contains(&TemplateEntry {
+            name: "digest-body.txt.hbs".into(),
+            overridden: false,
+        }));
+
+        reset_template(name)?;
+        assert!(!get_templates()?.iter().any(|entry| entry.name == name));
+        assert!(reset_template(name).is_err());
+
+        Ok(())
+    }
+}
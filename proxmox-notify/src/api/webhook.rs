@@ -0,0 +1,318 @@
+use proxmox_http_error::HttpError;
+
+use crate::api::{http_bail, http_err};
+use crate::endpoints::webhook::{
+    DeleteableWebhookProperty, WebhookConfig, WebhookConfigUpdater, WebhookPrivateConfig,
+    WebhookPrivateConfigUpdater, WEBHOOK_TYPENAME,
+};
+use crate::Config;
+
+/// Get a list of all webhook endpoints.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns a list of all webhook endpoints or a `HttpError` if the config is
+/// erroneous (`500 Internal server error`).
+pub fn get_endpoints(config: &Config) -> Result<Vec<WebhookConfig>, HttpError> {
+    config
+        .config
+        .convert_to_typed_array(WEBHOOK_TYPENAME)
+        .map_err(|e| http_err!(NOT_FOUND, "Could not fetch endpoints: {e}"))
+}
+
+/// Get webhook endpoint with given `name`.
+///
+/// The caller is responsible for any needed permission checks.
+/// Returns the endpoint or a `HttpError` if the endpoint was not found (`404 Not found`).
+pub fn get_endpoint(config: &Config, name: &str) -> Result<WebhookConfig, HttpError> {
+    config
+        .config
+        .lookup(WEBHOOK_TYPENAME, name)
+        .map_err(|_| http_err!(NOT_FOUND, "endpoint '{name}' not found"))
+}
+
+fn verify_body_template(body: Option<&str>) -> Result<(), HttpError> {
+    if let Some(body) = body {
+        let valid = base64::decode(body)
+            .ok()
+            .and_then(|body| String::from_utf8(body).ok())
+            .is_some();
+        if !valid {
+            http_bail!(
+                BAD_REQUEST,
+                "body template is not valid base64-encoded UTF-8"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a new webhook endpoint.
+///
+/// The caller is responsible for any needed permission checks.
+/// The caller also responsible for locking the configuration files.
+/// Returns a `HttpError` if:
+///   - an entity with the same name already exists (`400 Bad request`)
+///   - the body template is not valid base64 (`400 Bad request`)
+///   - the configuration could not be saved (`500 Internal server error`)
+///
+/// Panics if the names of the private config and the public config do not match.
+pub fn add_endpoint(
+    config: &mut Config,
+    endpoint_config: WebhookConfig,
+    private_endpoint_config: WebhookPrivateConfig,
+) -> Result<(), HttpError> {
+    if endpoint_config.name != private_endpoint_config.name {
+        // Programming error by the user of the crate, thus we panic
+        panic!("name for endpoint config and private config must be identical");
+    }
+
+    super::ensure_unique(config, &endpoint_config.name)?;
+    verify_body_template(endpoint_config.body.as_deref())?;
+
+    super::set_private_config_entry(
+        config,
+        private_endpoint_config,
+        WEBHOOK_TYPENAME,
+        &endpoint_config.name,
+    )?;
+
+    config
+        .config
+        .set_data(&endpoint_config.name, WEBHOOK_TYPENAME, &endpoint_config)
+        .map_err(|e| {
+            http_err!(
+                INTERNAL_SERVER_ERROR,
+                "could not save endpoint '{}': {e}",
+                endpoint_config.name
+            )
+        })
+}
+
+/// Update existing webhook endpoint
+///
+/// The caller is responsible for any needed permission checks.
+/// The caller also responsible for locking the configuration files.
+/// Returns a `HttpError` if:
+///   - the body template is not valid base64 (`400 Bad request`)
+///   - the configuration could not be saved (`500 Internal server error`)
+pub fn update_endpoint(
+    config: &mut Config,
+    name: &str,
+    updater: WebhookConfigUpdater,
+    private_endpoint_config_updater: WebhookPrivateConfigUpdater,
+    delete: Option<&[DeleteableWebhookProperty]>,
+    digest: Option<&[u8]>,
+) -> Result<(), HttpError> {
+    super::verify_digest(config, digest)?;
+
+    let mut endpoint = get_endpoint(config, name)?;
+    let mut private_endpoint: WebhookPrivateConfig = config
+        .private_config
+        .lookup(WEBHOOK_TYPENAME, name)
+        .unwrap_or_else(|_| WebhookPrivateConfig {
+            name: name.into(),
+            ..Default::default()
+        });
+
+    if let Some(delete) = delete {
+        for deleteable_property in delete {
+            match deleteable_property {
+                DeleteableWebhookProperty::Body => endpoint.body = None,
+                DeleteableWebhookProperty::Comment => endpoint.comment = None,
+                DeleteableWebhookProperty::Disable => endpoint.disable = None,
+                DeleteableWebhookProperty::Header => private_endpoint.header.clear(),
+                DeleteableWebhookProperty::Retries => endpoint.retries = None,
+                DeleteableWebhookProperty::Secret => private_endpoint.secret = None,
+            }
+        }
+    }
+
+    if let Some(url) = updater.url {
+        endpoint.url = url;
+    }
+    if let Some(body) = updater.body {
+        verify_body_template(Some(&body))?;
+        endpoint.body = Some(body);
+    }
+    if let Some(retries) = updater.retries {
+        endpoint.retries = Some(retries);
+    }
+    if let Some(header) = private_endpoint_config_updater.header {
+        private_endpoint.header = header;
+    }
+    if let Some(secret) = private_endpoint_config_updater.secret {
+        private_endpoint.secret = Some(secret);
+    }
+
+    if let Some(comment) = updater.comment {
+        endpoint.comment = Some(comment);
+    }
+
+    if let Some(disable) = updater.disable {
+        endpoint.disable = Some(disable);
+    }
+
+    super::set_private_config_entry(config, private_endpoint, WEBHOOK_TYPENAME, name)?;
+
+    config
+        .config
+        .set_data(name, WEBHOOK_TYPENAME, &endpoint)
+        .map_err(|e| {
+            http_err!(
+                INTERNAL_SERVER_ERROR,
+                "could not save endpoint '{}': {e}",
+                endpoint.name
+            )
+        })
+}
+
+/// Delete existing webhook endpoint
+///
+/// The caller is responsible for any needed permission checks.
+/// The caller also responsible for locking the configuration files.
+/// Returns a `HttpError` if:
+///   - the entity does not exist (`404 Not found`)
+///   - the endpoint is still referenced by another entity (`400 Bad request`)
+pub fn delete_endpoint(config: &mut Config, name: &str) -> Result<(), HttpError> {
+    // Check if the endpoint exists
+    let _ = get_endpoint(config, name)?;
+    super::ensure_safe_to_delete(config, name)?;
+
+    super::remove_private_config_entry(config, name)?;
+    config.config.sections.remove(name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_helpers::empty_config;
+
+    fn add_default_webhook_endpoint(config: &mut Config) -> Result<(), HttpError> {
+        add_endpoint(
+            config,
+            WebhookConfig {
+                name: "webhook-endpoint".into(),
+                url: "https://example.com/hook".into(),
+                body: Some(base64::encode(r#"{"text": "{{ title }}"}"#)),
+                ..Default::default()
+            },
+            WebhookPrivateConfig {
+                name: "webhook-endpoint".into(),
+                secret: Some("secret".into()),
+                header: vec!["X-Custom: value".into()],
+            },
+        )?;
+
+        assert!(get_endpoint(config, "webhook-endpoint").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_webhook_create() -> Result<(), HttpError> {
+        let mut config = empty_config();
+
+        add_default_webhook_endpoint(&mut config)?;
+        assert!(add_default_webhook_endpoint(&mut config).is_err());
+        assert_eq!(get_endpoints(&config)?.len(), 1);
+
+        assert!(add_endpoint(
+            &mut config,
+            WebhookConfig {
+                name: "invalid".into(),
+                url: "https://example.com/hook".into(),
+                body: Some("not base64!".into()),
+                ..Default::default()
+            },
+            WebhookPrivateConfig {
+                name: "invalid".into(),
+                ..Default::default()
+            },
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_webhook_update() -> Result<(), HttpError> {
+        let mut config = empty_config();
+        add_default_webhook_endpoint(&mut config)?;
+
+        let digest = config.digest;
+
+        update_endpoint(
+            &mut config,
+            "webhook-endpoint",
+            WebhookConfigUpdater {
+                url: Some("https://example.org/other".into()),
+                retries: Some(5),
+                ..Default::default()
+            },
+            WebhookPrivateConfigUpdater {
+                secret: Some("changed".into()),
+                ..Default::default()
+            },
+            Some(&[DeleteableWebhookProperty::Header]),
+            Some(&digest),
+        )?;
+
+        let endpoint = get_endpoint(&config, "webhook-endpoint")?;
+        assert_eq!(endpoint.url, "https://example.org/other");
+        assert_eq!(endpoint.retries, Some(5));
+
+        let private_endpoint: WebhookPrivateConfig = config
+            .private_config
+            .lookup(WEBHOOK_TYPENAME, "webhook-endpoint")
+            .unwrap();
+        assert_eq!(private_endpoint.secret.as_deref(), Some("changed"));
+        assert!(private_endpoint.header.is_empty());
+
+        update_endpoint(
+            &mut config,
+            "webhook-endpoint",
+            Default::default(),
+            WebhookPrivateConfigUpdater {
+                header: Some(vec!["Authorization: Bearer token".into()]),
+                ..Default::default()
+            },
+            None,
+            None,
+        )?;
+
+        update_endpoint(
+            &mut config,
+            "webhook-endpoint",
+            Default::default(),
+            Default::default(),
+            Some(&[DeleteableWebhookProperty::Secret]),
+            None,
+        )?;
+
+        let private_endpoint: WebhookPrivateConfig = config
+            .private_config
+            .lookup(WEBHOOK_TYPENAME, "webhook-endpoint")
+            .unwrap();
+        assert_eq!(private_endpoint.secret, None);
+        assert_eq!(private_endpoint.header, ["Authorization: Bearer token"]);
+        let (public, private) = config.write().unwrap();
+        assert!(!public.contains("Authorization"));
+        assert!(private.contains("Authorization: Bearer token"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_webhook_endpoint_delete() -> Result<(), HttpError> {
+        let mut config = empty_config();
+        add_default_webhook_endpoint(&mut config)?;
+
+        delete_endpoint(&mut config, "webhook-endpoint")?;
+        assert!(delete_endpoint(&mut config, "webhook-endpoint").is_err());
+        assert_eq!(get_endpoints(&config)?.len(), 0);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 use std::path::Path;
 
+use crate::Error;
+
 pub(crate) fn attempt_file_read<P: AsRef<Path>>(path: P) -> Option<String> {
     match proxmox_sys::fs::file_read_optional_string(path) {
         Ok(contents) => contents,
@@ -25,3 +27,40 @@ pub(crate) fn normalize_for_return(s: Option<&str>) -> Option<String> {
         s => Some(s.to_string()),
     }
 }
+
+/// Replace the OAuth2 refresh token of the endpoint `endpoint` in the raw private config.
+pub(crate) fn set_oauth2_refresh_token(
+    raw_private_config: &str,
+    endpoint: &str,
+    refresh_token: &str,
+) -> Result<String, Error> {
+    let (mut config, _digest) = crate::config::private_config(raw_private_config)?;
+
+    let (_, entry) = config
+        .sections
+        .get_mut(endpoint)
+        .ok_or_else(|| Error::Generic(format!("no private config for endpoint '{endpoint}'")))?;
+    entry["oauth2-refresh-token"] = refresh_token.into();
+
+    crate::config::write_private(&config)
+}
+
+#[cfg(all(test, feature = "smtp"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_oauth2_refresh_token() -> Result<(), Error> {
+        let raw = "smtp: mail\n\toauth2-client-secret secret\n\toauth2-refresh-token old\n";
+
+        let updated = set_oauth2_refresh_token(raw, "mail", "new")?;
+        assert_eq!(
+            updated,
+            "smtp: mail\n\toauth2-client-secret secret\n\toauth2-refresh-token new\n"
+        );
+
+        assert!(set_oauth2_refresh_token(raw, "other", "new").is_err());
+
+        Ok(())
+    }
+}
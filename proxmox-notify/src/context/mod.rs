@@ -1,6 +1,9 @@
 use std::fmt::Debug;
+use std::path::PathBuf;
 use std::sync::Mutex;
 
+use proxmox_sys::fs::CreateOptions;
+
 use crate::Error;
 
 #[cfg(any(feature = "pve-context", feature = "pbs-context"))]
@@ -30,6 +33,39 @@ pub trait Context: Send + Sync + Debug {
         filename: &str,
         namespace: Option<&str>,
     ) -> Result<Option<String>, Error>;
+    /// Directory containing the shipped templates of a certain (optional) namespace
+    fn template_dir(&self, _namespace: Option<&str>) -> Option<PathBuf> {
+        None
+    }
+    /// Directory containing user-provided templates of a certain (optional) namespace,
+    /// which take precedence over the shipped ones
+    fn template_override_dir(&self, _namespace: Option<&str>) -> Option<PathBuf> {
+        None
+    }
+    /// Ownership and permissions of user-provided templates
+    fn template_override_options(&self) -> CreateOptions {
+        CreateOptions::new()
+    }
+    /// Existing directory for state shared by all processes sending notifications, i.e. queued
+    /// digests and pending escalations. Without it, this state only lives as long as the
+    /// [`Bus`](crate::Bus).
+    fn state_dir(&self) -> Option<PathBuf> {
+        None
+    }
+    /// Ownership and permissions of the files in the state directory
+    fn state_file_options(&self) -> CreateOptions {
+        CreateOptions::new()
+    }
+    /// Store an OAuth2 refresh token rotated by the identity provider in the private config of
+    /// the endpoint `endpoint`. Without it, the rotated token is only kept in memory and later
+    /// processes use the outdated token from the config.
+    fn store_oauth2_refresh_token(
+        &self,
+        _endpoint: &str,
+        _refresh_token: &str,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 #[cfg(not(test))]
@@ -1,14 +1,21 @@
 use serde::Deserialize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nix::sys::stat::Mode;
+use nix::unistd::User;
 
 use proxmox_schema::{ObjectSchema, Schema, StringSchema};
 use proxmox_section_config::{SectionConfig, SectionConfigPlugin};
+use proxmox_sys::fs::CreateOptions;
 
 use crate::context::{common, Context};
 use crate::Error;
 
 const PBS_USER_CFG_FILENAME: &str = "/etc/proxmox-backup/user.cfg";
 const PBS_NODE_CFG_FILENAME: &str = "/etc/proxmox-backup/node.cfg";
+const PBS_NOTIFICATION_PRIV_CFG_FILENAME: &str = "/etc/proxmox-backup/notifications-priv.cfg";
+const PBS_NOTIFICATION_LOCK_FILENAME: &str = "/etc/proxmox-backup/.notifications.lck";
 
 // FIXME: Switch to the actual schema when possible in terms of dependency.
 // It's safe to assume that the config was written with the actual schema restrictions, so parsing
@@ -117,6 +124,73 @@ impl Context for PBSContext {
             .map_err(|err| Error::Generic(format!("could not load template: {err}")))?;
         Ok(template_string)
     }
+
+    fn template_dir(&self, namespace: Option<&str>) -> Option<PathBuf> {
+        Some(Path::new("/usr/share/proxmox-backup/templates").join(namespace.unwrap_or("default")))
+    }
+
+    fn template_override_dir(&self, namespace: Option<&str>) -> Option<PathBuf> {
+        Some(
+            Path::new("/etc/proxmox-backup/notification-templates")
+                .join(namespace.unwrap_or("default")),
+        )
+    }
+
+    fn template_override_options(&self) -> CreateOptions {
+        // templates are rendered by the proxy running as backup user
+        let options = CreateOptions::new()
+            .owner_root()
+            .perm(Mode::from_bits_truncate(0o640));
+        match User::from_name("backup") {
+            Ok(Some(user)) => options.group(user.gid),
+            _ => options,
+        }
+    }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        Some(PathBuf::from("/var/lib/proxmox-backup"))
+    }
+
+    fn state_file_options(&self) -> CreateOptions {
+        // notifications are sent by the proxy running as backup user, and by root
+        let options = CreateOptions::new().perm(Mode::from_bits_truncate(0o640));
+        match User::from_name("backup") {
+            Ok(Some(user)) => options.owner(user.uid).group(user.gid),
+            _ => options,
+        }
+    }
+
+    fn store_oauth2_refresh_token(&self, endpoint: &str, refresh_token: &str) -> Result<(), Error> {
+        let lock_options = CreateOptions::new().perm(Mode::from_bits_truncate(0o660));
+        let lock_options = match User::from_name("backup") {
+            Ok(Some(user)) => lock_options.owner(user.uid).group(user.gid),
+            _ => lock_options,
+        };
+        let _lock = proxmox_sys::fs::open_file_locked(
+            PBS_NOTIFICATION_LOCK_FILENAME,
+            Duration::from_secs(10),
+            true,
+            lock_options,
+        )
+        .map_err(|err| Error::Generic(format!("could not lock notification config: {err}")))?;
+
+        let raw_config =
+            proxmox_sys::fs::file_read_optional_string(PBS_NOTIFICATION_PRIV_CFG_FILENAME)
+                .map_err(|err| Error::Generic(format!("could not read private config: {err}")))?
+                .unwrap_or_default();
+        let raw_config = common::set_oauth2_refresh_token(&raw_config, endpoint, refresh_token)?;
+
+        let options = CreateOptions::new()
+            .owner_root()
+            .perm(Mode::from_bits_truncate(0o600));
+        proxmox_sys::fs::replace_file(
+            PBS_NOTIFICATION_PRIV_CFG_FILENAME,
+            raw_config.as_bytes(),
+            options,
+            true,
+        )
+        .map_err(|err| Error::Generic(format!("could not write private config: {err}")))
+    }
 }
 
 #[cfg(test)]
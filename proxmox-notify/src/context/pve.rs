@@ -1,6 +1,44 @@
 use crate::context::{common, Context};
 use crate::Error;
-use std::path::Path;
+use nix::sys::stat::Mode;
+use nix::sys::time::TimeVal;
+use proxmox_sys::fs::CreateOptions;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const PVE_NOTIFICATION_PRIV_CFG_FILENAME: &str = "/etc/pve/priv/notifications.cfg";
+// the lock `PVE::Cluster::cfs_lock_file` uses for notifications.cfg
+const PVE_NOTIFICATION_LOCK_DIR: &str = "/etc/pve/priv/lock/file-notifications_cfg";
+
+/// A cluster wide lock on pmxcfs, released on drop.
+struct ClusterLock(&'static str);
+
+impl ClusterLock {
+    fn acquire(path: &'static str, timeout: Duration) -> Result<Self, Error> {
+        let start = Instant::now();
+        loop {
+            match nix::unistd::mkdir(path, Mode::from_bits_truncate(0o700)) {
+                Ok(()) => return Ok(Self(path)),
+                Err(nix::errno::Errno::EEXIST) if start.elapsed() < timeout => {
+                    // asks pmxcfs to remove the lock if it expired
+                    let _ = nix::sys::stat::utimes(path, &TimeVal::new(0, 0), &TimeVal::new(0, 0));
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+                Err(err) => {
+                    return Err(Error::Generic(format!(
+                        "could not acquire lock '{path}': {err}"
+                    )))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ClusterLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir(self.0);
+    }
+}
 
 fn lookup_mail_address(content: &str, user: &str) -> Option<String> {
     common::normalize_for_return(content.lines().find_map(|line| {
@@ -66,6 +104,42 @@ impl Context for PVEContext {
             .map_err(|err| Error::Generic(format!("could not load template: {err}")))?;
         Ok(template_string)
     }
+
+    fn template_dir(&self, namespace: Option<&str>) -> Option<PathBuf> {
+        Some(Path::new("/usr/share/pve-manager/templates").join(namespace.unwrap_or("default")))
+    }
+
+    fn template_override_dir(&self, namespace: Option<&str>) -> Option<PathBuf> {
+        Some(Path::new("/etc/pve/notification-templates").join(namespace.unwrap_or("default")))
+    }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        Some(PathBuf::from("/var/lib/pve-manager"))
+    }
+
+    fn state_file_options(&self) -> CreateOptions {
+        CreateOptions::new()
+            .owner_root()
+            .perm(Mode::from_bits_truncate(0o600))
+    }
+
+    fn store_oauth2_refresh_token(&self, endpoint: &str, refresh_token: &str) -> Result<(), Error> {
+        let _lock = ClusterLock::acquire(PVE_NOTIFICATION_LOCK_DIR, Duration::from_secs(10))?;
+
+        let raw_config =
+            proxmox_sys::fs::file_read_optional_string(PVE_NOTIFICATION_PRIV_CFG_FILENAME)
+                .map_err(|err| Error::Generic(format!("could not read private config: {err}")))?
+                .unwrap_or_default();
+        let raw_config = common::set_oauth2_refresh_token(&raw_config, endpoint, refresh_token)?;
+
+        proxmox_sys::fs::replace_file(
+            PVE_NOTIFICATION_PRIV_CFG_FILENAME,
+            raw_config.as_bytes(),
+            CreateOptions::new(),
+            false,
+        )
+        .map_err(|err| Error::Generic(format!("could not write private config: {err}")))
+    }
 }
 
 pub static PVE_CONTEXT: PVEContext = PVEContext;
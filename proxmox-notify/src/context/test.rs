@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use crate::context::Context;
 use crate::Error;
 
@@ -32,4 +34,11 @@ impl Context for TestContext {
     ) -> Result<Option<String>, Error> {
         Ok(Some(String::new()))
     }
+
+    fn template_override_dir(&self, _namespace: Option<&str>) -> Option<PathBuf> {
+        Some(std::env::temp_dir().join(format!(
+            "proxmox-notify-test-templates-{}",
+            std::process::id()
+        )))
+    }
 }
@@ -0,0 +1,205 @@
+//! Aggregation of notifications into digests.
+//!
+//! Notifications matched by a matcher with a `digest-window` are not sent right away, but queued
+//! until the window, starting with the first queued notification, has passed. They are then sent
+//! to the matcher's targets as a single notification using the `digest` template, which lists
+//! the aggregated notifications in a table.
+//!
+//! The queue is stored in the [state directory](crate::context::Context::state_dir), so digests
+//! are sent by whichever process sends the next notification. Since the last digest would
+//! otherwise wait for the next notification, a long-running daemon should call
+//! [`Bus::flush_digests`](crate::Bus::flush_digests) periodically. Without a state directory,
+//! the queue only lives as long as the [`Bus`](crate::Bus), which sends all queued digests when
+//! it is dropped.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::matcher::MatcherConfig;
+use crate::renderer::{self, TemplateType};
+use crate::state::State;
+use crate::{Content, Error, Metadata, Notification, Severity};
+
+/// Name of the template used for digest notifications.
+pub const DIGEST_TEMPLATE_NAME: &str = "digest";
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PendingDigest {
+    start: i64,
+    window: i64,
+    targets: Vec<String>,
+    notifications: Vec<Notification>,
+}
+
+/// The pending digests by matcher name.
+pub(crate) type DigestQueue = State<HashMap<String, PendingDigest>>;
+
+/// Queue `notification` for all matchers with a digest window matching it.
+pub(crate) fn queue(
+    queue: &DigestQueue,
+    matchers: &[MatcherConfig],
+    notification: &Notification,
+    now: i64,
+) -> Result<(), Error> {
+    let mut matched = Vec::new();
+
+    for matcher in matchers {
+        if matcher.disable.unwrap_or_default() {
+            continue;
+        }
+
+        let window = match matcher.digest_window() {
+            Some(window) => window,
+            None => continue,
+        };
+
+        let targets = match matcher.matches(notification) {
+            Ok(Some(targets)) => targets,
+            Ok(None) => continue,
+            Err(err) => {
+                log::error!("matcher '{name}' failed: {err}", name = matcher.name);
+                continue;
+            }
+        };
+
+        matched.push((matcher.name.clone(), window, targets.to_vec()));
+    }
+
+    if matched.is_empty() {
+        return Ok(());
+    }
+
+    queue.update(|pending| {
+        for (name, window, targets) in matched {
+            pending
+                .entry(name)
+                .or_insert_with(|| PendingDigest {
+                    start: now,
+                    window,
+                    targets,
+                    notifications: Vec::new(),
+                })
+                .notifications
+                .push(notification.clone());
+        }
+    })
+}
+
+/// Remove all digests whose window has passed at `now` from the queue, or all digests if `all`
+/// is set.
+///
+/// Returns the targets and the notification to send for each of them.
+pub(crate) fn take_due(
+    queue: &DigestQueue,
+    now: i64,
+    all: bool,
+) -> Result<Vec<(Vec<String>, Notification)>, Error> {
+    let due = queue.update(|pending| {
+        let due: Vec<String> = pending
+            .iter()
+            .filter(|(_, digest)| all || now >= digest.start + digest.window)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        due.into_iter()
+            .filter_map(|name| Some((pending.remove(&name)?, name)))
+            .collect::<Vec<_>>()
+    })?;
+
+    // render outside of the update, which holds the lock for persisted queues
+    Ok(due
+        .into_iter()
+        .map(|(digest, name)| {
+            (
+                digest.targets.clone(),
+                digest_notification(&name, digest, now),
+            )
+        })
+        .collect())
+}
+
+fn notification_title(notification: &Notification) -> String {
+    match &notification.content {
+        Content::Template {
+            template_name,
+            data,
+        } => renderer::render_template(TemplateType::Subject, template_name, data).unwrap_or_else(
+            |err| {
+                log::error!("could not render title of notification: {err}");
+                template_name.clone()
+            },
+        ),
+        #[cfg(feature = "mail-forwarder")]
+        Content::ForwardedMail { title, .. } => title.clone(),
+    }
+}
+
+/// Build the notification for a digest, a single notification is sent unchanged.
+fn digest_notification(matcher: &str, mut digest: PendingDigest, now: i64) -> Notification {
+    if digest.notifications.len() == 1 {
+        return digest.notifications.remove(0);
+    }
+
+    let mut severity = Severity::Info;
+    for notification in &digest.notifications {
+        if notification.metadata.severity > severity {
+            severity = notification.metadata.severity;
+        }
+    }
+
+    // keep the metadata fields all aggregated notifications agree on, e.g. the hostname
+    let mut fields = digest.notifications[0].metadata.additional_fields.clone();
+    let mut differing = HashSet::new();
+    for notification in &digest.notifications[1..] {
+        for (key, value) in &fields {
+            if notification.metadata.additional_fields.get(key) != Some(value) {
+                differing.insert(key.clone());
+            }
+        }
+    }
+    fields.retain(|key, _| !differing.contains(key));
+
+    let rows: Vec<Value> = digest
+        .notifications
+        .iter()
+        .map(|notification| {
+            json!({
+                "timestamp": notification.metadata.timestamp,
+                "severity": notification.metadata.severity,
+                "title": notification_title(notification),
+            })
+        })
+        .collect();
+
+    let data = json!({
+        "matcher": matcher,
+        "count": digest.notifications.len(),
+        "window-start": digest.start,
+        "window-end": now,
+        "table": {
+            "schema": {
+                "columns": [
+                    { "label": "Time", "id": "timestamp", "renderer": "timestamp" },
+                    { "label": "Severity", "id": "severity" },
+                    { "label": "Title", "id": "title" },
+                ],
+            },
+            "data": rows,
+        },
+    });
+
+    Notification {
+        content: Content::Template {
+            template_name: DIGEST_TEMPLATE_NAME.to_string(),
+            data,
+        },
+        metadata: Metadata {
+            severity,
+            timestamp: now,
+            additional_fields: fields,
+        },
+        id: proxmox_uuid::Uuid::generate(),
+    }
+}
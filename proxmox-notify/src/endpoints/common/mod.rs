@@ -1,2 +1,4 @@
 #[cfg(any(feature = "sendmail", feature = "smtp"))]
 pub(crate) mod mail;
+#[cfg(feature = "smtp")]
+pub(crate) mod oauth2;
@@ -0,0 +1,311 @@
+//! OAuth2 access tokens for endpoints authenticating via XOAUTH2.
+//!
+//! Tokens are fetched from the token endpoint of the identity provider, either via the
+//! client credentials grant or, if a refresh token is available, via the refresh token grant.
+//! They are cached in memory until shortly before they expire.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+
+use proxmox_http::client::sync::Client;
+use proxmox_http::{HttpClient, HttpOptions, ProxyConfig};
+
+use crate::context::context;
+use crate::Error;
+
+/// Tokens are refreshed if they expire within this many seconds.
+const EXPIRY_MARGIN: i64 = 60;
+/// Assumed lifetime of tokens if the token endpoint does not report one.
+const DEFAULT_EXPIRES_IN: i64 = 3600;
+
+/// Parameters for requesting an access token.
+pub(crate) struct TokenRequest<'a> {
+    /// Name of the endpoint, used to store rotated refresh tokens.
+    pub endpoint: &'a str,
+    pub token_url: &'a str,
+    pub client_id: &'a str,
+    pub client_secret: Option<&'a str>,
+    pub scope: Option<&'a str>,
+    pub refresh_token: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+    refresh_token: Option<String>,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires_at: i64,
+    refresh_token: Option<String>,
+}
+
+static TOKEN_CACHE: Mutex<Option<HashMap<String, CachedToken>>> = Mutex::new(None);
+
+/// Build the `application/x-www-form-urlencoded` body of a token request.
+///
+/// Uses the refresh token grant if `refresh_token` is set, the client credentials
+/// grant otherwise.
+fn token_request_body(request: &TokenRequest, refresh_token: Option<&str>) -> String {
+    let mut body = form_urlencoded::Serializer::new(String::new());
+
+    match refresh_token {
+        Some(refresh_token) => body
+            .append_pair("grant_type", "refresh_token")
+            .append_pair("refresh_token", refresh_token),
+        None => body.append_pair("grant_type", "client_credentials"),
+    };
+
+    body.append_pair("client_id", request.client_id);
+    if let Some(client_secret) = request.client_secret {
+        body.append_pair("client_secret", client_secret);
+    }
+    if let Some(scope) = request.scope {
+        body.append_pair("scope", scope);
+    }
+
+    body.finish()
+}
+
+fn fetch_token(
+    request: &TokenRequest,
+    refresh_token: Option<&str>,
+) -> Result<TokenResponse, Error> {
+    let proxy_config = context()
+        .http_proxy_config()
+        .map(|url| ProxyConfig::parse_proxy_url(&url))
+        .transpose()
+        .map_err(|err| Error::Generic(format!("invalid proxy configuration: {err}")))?;
+
+    let options = HttpOptions {
+        proxy_config,
+        ..Default::default()
+    };
+
+    let response = Client::new(options)
+        .post(
+            request.token_url,
+            Some(token_request_body(request, refresh_token)),
+            Some("application/x-www-form-urlencoded"),
+            None,
+        )
+        .map_err(|err| Error::Generic(format!("could not fetch OAuth2 access token: {err}")))?;
+
+    serde_json::from_str(response.body())
+        .map_err(|err| Error::Generic(format!("invalid OAuth2 token response: {err}")))
+}
+
+fn cached_token(
+    key: &str,
+    request: &TokenRequest,
+    now: i64,
+    fetch: impl FnOnce(Option<&str>) -> Result<TokenResponse, Error>,
+    store: impl FnOnce(&str) -> Result<(), Error>,
+) -> Result<String, Error> {
+    let mut cache = TOKEN_CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(HashMap::new);
+
+    let refresh_token = match cache.get(key) {
+        Some(token) if now + EXPIRY_MARGIN < token.expires_at => {
+            return Ok(token.access_token.clone());
+        }
+        Some(token) => token.refresh_token.clone(),
+        None => None,
+    };
+    let refresh_token = refresh_token.or_else(|| request.refresh_token.map(String::from));
+
+    let response = fetch(refresh_token.as_deref())?;
+
+    // providers might rotate refresh tokens, keep using the most recent one
+    if let Some(new_token) = response.refresh_token.as_deref() {
+        if refresh_token.as_deref() != Some(new_token) {
+            if let Err(err) = store(new_token) {
+                log::error!(
+                    "could not store rotated OAuth2 refresh token of '{}': {err}",
+                    request.endpoint
+                );
+            }
+        }
+    }
+
+    let token = CachedToken {
+        access_token: response.access_token.clone(),
+        expires_at: now + response.expires_in.unwrap_or(DEFAULT_EXPIRES_IN),
+        refresh_token: response.refresh_token.or(refresh_token),
+    };
+    cache.insert(key.to_string(), token);
+
+    Ok(response.access_token)
+}
+
+/// Get a valid access token, fetching a new one if there is no cached token for `key`
+/// or if it is about to expire.
+///
+/// Refresh tokens rotated by the provider are written back to the private config, see
+/// [Context::store_oauth2_refresh_token](crate::context::Context::store_oauth2_refresh_token).
+pub(crate) fn access_token(key: &str, request: &TokenRequest) -> Result<String, Error> {
+    cached_token(
+        key,
+        request,
+        proxmox_time::epoch_i64(),
+        |refresh_token| fetch_token(request, refresh_token),
+        |refresh_token| context().store_oauth2_refresh_token(request.endpoint, refresh_token),
+    )
+}
+
+/// Drop the cached token for `key`, e.g. because it was rejected by the server.
+pub(crate) fn invalidate(key: &str) {
+    if let Some(cache) = TOKEN_CACHE.lock().unwrap().as_mut() {
+        cache.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> TokenRequest<'static> {
+        TokenRequest {
+            endpoint: "mail",
+            token_url: "https://login.example.com/token",
+            client_id: "client",
+            client_secret: Some("s3cret&more"),
+            scope: Some("https://outlook.office365.com/.default"),
+            refresh_token: None,
+        }
+    }
+
+    #[test]
+    fn test_token_request_body() {
+        assert_eq!(
+            token_request_body(&request(), None),
+            "grant_type=client_credentials&client_id=client&client_secret=s3cret%26more\
+            &scope=https%3A%2F%2Foutlook.office365.com%2F.default"
+        );
+        assert!(token_request_body(&request(), Some("refresh"))
+            .starts_with("grant_type=refresh_token&refresh_token=refresh&client_id=client"));
+    }
+
+    #[test]
+    fn test_cached_token() -> Result<(), Error> {
+        let key = "oauth2-test";
+        let request = request();
+        let fetch = |access_token: &str, refresh_token: Option<&str>| TokenResponse {
+            access_token: access_token.into(),
+            expires_in: Some(3600),
+            refresh_token: refresh_token.map(String::from),
+        };
+        let no_store = |_: &str| -> Result<(), Error> { panic!("refresh token was not rotated") };
+
+        let mut stored = None;
+        let token = cached_token(
+            key,
+            &request,
+            0,
+            |refresh_token| {
+                assert_eq!(refresh_token, None);
+                Ok(fetch("first", Some("refresh")))
+            },
+            |refresh_token| {
+                stored = Some(refresh_token.to_string());
+                Ok(())
+            },
+        )?;
+        assert_eq!(token, "first");
+        assert_eq!(stored.as_deref(), Some("refresh"));
+
+        let token = cached_token(
+            key,
+            &request,
+            1000,
+            |_| panic!("token is still valid"),
+            no_store,
+        )?;
+        assert_eq!(token, "first");
+
+        let token = cached_token(
+            key,
+            &request,
+            3550,
+            |refresh_token| {
+                assert_eq!(refresh_token, Some("refresh"));
+                Ok(fetch("second", Some("refresh")))
+            },
+            no_store,
+        )?;
+        assert_eq!(token, "second");
+
+        invalidate(key);
+        let token = cached_token(
+            key,
+            &request,
+            3550,
+            |refresh_token| {
+                assert_eq!(refresh_token, None);
+                Ok(fetch("third", None))
+            },
+            no_store,
+        )?;
+        assert_eq!(token, "third");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotated_refresh_token() -> Result<(), Error> {
+        let key = "oauth2-test-rotation";
+        let request = TokenRequest {
+            refresh_token: Some("configured"),
+            ..request()
+        };
+        let fetch = |refresh_token: Option<&str>| TokenResponse {
+            access_token: "access".into(),
+            expires_in: Some(3600),
+            refresh_token: refresh_token.map(String::from),
+        };
+
+        // failing to store the rotated token does not fail the request
+        let mut stored = Vec::new();
+        cached_token(
+            key,
+            &request,
+            0,
+            |refresh_token| {
+                assert_eq!(refresh_token, Some("configured"));
+                Ok(fetch(Some("rotated")))
+            },
+            |refresh_token| {
+                stored.push(refresh_token.to_string());
+                Err(Error::Generic("read-only".into()))
+            },
+        )?;
+        assert_eq!(stored, ["rotated"]);
+
+        // the rotated token is used from the cache
+        cached_token(
+            key,
+            &request,
+            3550,
+            |refresh_token| {
+                assert_eq!(refresh_token, Some("rotated"));
+                Ok(fetch(Some("rotated-again")))
+            },
+            |refresh_token| {
+                stored.push(refresh_token.to_string());
+                Ok(())
+            },
+        )?;
+        assert_eq!(stored, ["rotated", "rotated-again"]);
+
+        let response: TokenResponse =
+            serde_json::from_str(r#"{"access_token":"abc","token_type":"Bearer"}"#).unwrap();
+        assert_eq!(response.access_token, "abc");
+        assert_eq!(response.expires_in, None);
+
+        Ok(())
+    }
+}
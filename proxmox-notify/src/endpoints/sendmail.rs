@@ -5,7 +5,7 @@ use proxmox_schema::{api, Updater};
 
 use crate::context;
 use crate::endpoints::common::mail;
-use crate::renderer::TemplateType;
+use crate::renderer::{HtmlStyle, TemplateType};
 use crate::schema::{EMAIL_SCHEMA, ENTITY_NAME_SCHEMA, USER_SCHEMA};
 use crate::{renderer, Content, Endpoint, Error, Notification, Origin};
 
@@ -62,6 +62,9 @@ pub struct SendmailConfig {
     /// Author of the mail. Defaults to 'Proxmox Backup Server ($hostname)'
     #[serde(skip_serializing_if = "Option::is_none")]
     pub author: Option<String>,
+    /// Style of tables and objects in the HTML part of the mail.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html_style: Option<HtmlStyle>,
     /// Comment.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
@@ -88,6 +91,8 @@ pub enum DeleteableSendmailProperty {
     Comment,
     /// Delete `disable`
     Disable,
+    /// Delete `html-style`
+    HtmlStyle,
     /// Delete `from-address`
     FromAddress,
     /// Delete `mailto`
@@ -122,8 +127,9 @@ impl Endpoint for SendmailEndpoint {
             } => {
                 let subject =
                     renderer::render_template(TemplateType::Subject, template_name, data)?;
+                let html_style = self.config.html_style.unwrap_or_default();
                 let html_part =
-                    renderer::render_template(TemplateType::HtmlBody, template_name, data)?;
+                    renderer::render_template(html_style.template_type(), template_name, data)?;
                 let text_part =
                     renderer::render_template(TemplateType::PlaintextBody, template_name, data)?;
 
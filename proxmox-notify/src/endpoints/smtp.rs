@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use lettre::message::header::{HeaderName, HeaderValue};
 use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism, DEFAULT_MECHANISMS};
 use lettre::transport::smtp::client::{Tls, TlsParameters};
 use lettre::{message::header::ContentType, Message, SmtpTransport, Transport};
 use serde::{Deserialize, Serialize};
@@ -10,8 +11,8 @@ use proxmox_schema::api_types::COMMENT_SCHEMA;
 use proxmox_schema::{api, Updater};
 
 use crate::context::context;
-use crate::endpoints::common::mail;
-use crate::renderer::TemplateType;
+use crate::endpoints::common::{mail, oauth2};
+use crate::renderer::{HtmlStyle, TemplateType};
 use crate::schema::{EMAIL_SCHEMA, ENTITY_NAME_SCHEMA, USER_SCHEMA};
 use crate::{renderer, Content, Endpoint, Error, Notification, Origin};
 
@@ -37,6 +38,18 @@ pub enum SmtpMode {
     Tls,
 }
 
+#[api]
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Authentication method
+pub enum SmtpAuthMethod {
+    /// Authenticate with username and password, using PLAIN or LOGIN
+    #[default]
+    Password,
+    /// Authenticate with an OAuth2 access token, using XOAUTH2
+    Oauth2,
+}
+
 #[api(
     properties: {
         name: {
@@ -80,9 +93,24 @@ pub struct SmtpConfig {
     pub mode: Option<SmtpMode>,
     /// Username to use during authentication.
     /// If no username is set, no authentication will be performed.
-    /// The PLAIN and LOGIN authentication methods are supported
+    /// The PLAIN and LOGIN authentication methods are supported, as well as
+    /// XOAUTH2 if `auth-method` is set to 'oauth2'.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
+    /// Authentication method. Defaults to 'password'.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_method: Option<SmtpAuthMethod>,
+    /// Token endpoint of the OAuth2 identity provider, e.g.
+    /// 'https://login.microsoftonline.com/<tenant>/oauth2/v2.0/token'.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth2_token_url: Option<String>,
+    /// OAuth2 client ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth2_client_id: Option<String>,
+    /// Scope to request for the OAuth2 access token, e.g.
+    /// 'https://outlook.office365.com/.default'.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth2_scope: Option<String>,
     /// Mail address to send a mail to.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     #[updater(serde(skip_serializing_if = "Option::is_none"))]
@@ -100,6 +128,9 @@ pub struct SmtpConfig {
     /// Author of the mail. Defaults to 'Proxmox Backup Server ($hostname)'
     #[serde(skip_serializing_if = "Option::is_none")]
     pub author: Option<String>,
+    /// Style of tables and objects in the HTML part of the mail.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html_style: Option<HtmlStyle>,
     /// Comment.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
@@ -116,16 +147,30 @@ pub struct SmtpConfig {
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum DeleteableSmtpProperty {
+    /// Delete `auth-method`
+    AuthMethod,
     /// Delete `author`
     Author,
     /// Delete `comment`
     Comment,
     /// Delete `disable`
     Disable,
+    /// Delete `html-style`
+    HtmlStyle,
     /// Delete `mailto`
     Mailto,
     /// Delete `mailto-user`
     MailtoUser,
+    /// Delete `oauth2-client-id`
+    Oauth2ClientId,
+    /// Delete `oauth2-client-secret`
+    Oauth2ClientSecret,
+    /// Delete `oauth2-refresh-token`
+    Oauth2RefreshToken,
+    /// Delete `oauth2-scope`
+    Oauth2Scope,
+    /// Delete `oauth2-token-url`
+    Oauth2TokenUrl,
     /// Delete `password`
     Password,
     /// Delete `port`
@@ -135,7 +180,7 @@ pub enum DeleteableSmtpProperty {
 }
 
 #[api]
-#[derive(Serialize, Deserialize, Clone, Updater, Debug)]
+#[derive(Serialize, Deserialize, Clone, Updater, Debug, Default)]
 #[serde(rename_all = "kebab-case")]
 /// Private configuration for SMTP notification endpoints.
 /// This config will be saved to a separate configuration file with stricter
@@ -147,6 +192,13 @@ pub struct SmtpPrivateConfig {
     /// The password to use during authentication.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
+    /// OAuth2 client secret, used to request access tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth2_client_secret: Option<String>,
+    /// OAuth2 refresh token. If set, access tokens are requested with the refresh token
+    /// grant instead of the client credentials grant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth2_refresh_token: Option<String>,
 }
 
 /// A sendmail notification endpoint.
@@ -155,6 +207,65 @@ pub struct SmtpEndpoint {
     pub private_config: SmtpPrivateConfig,
 }
 
+impl SmtpEndpoint {
+    /// Key of this endpoint's access token in the OAuth2 token cache.
+    fn oauth2_cache_key(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.config.name,
+            self.config.oauth2_token_url.as_deref().unwrap_or_default(),
+            self.config.oauth2_client_id.as_deref().unwrap_or_default(),
+        )
+    }
+
+    fn credentials(&self) -> Result<Option<(Credentials, Vec<Mechanism>)>, Error> {
+        let username = match self.config.username.as_deref() {
+            Some(username) => username,
+            None => return Ok(None),
+        };
+
+        match self.config.auth_method.unwrap_or_default() {
+            SmtpAuthMethod::Password => {
+                let password = self.private_config.password.as_deref().ok_or_else(|| {
+                    Error::Generic("username is set but no password was provided".to_owned())
+                })?;
+
+                Ok(Some((
+                    (username, password).into(),
+                    DEFAULT_MECHANISMS.to_vec(),
+                )))
+            }
+            SmtpAuthMethod::Oauth2 => {
+                let (token_url, client_id) = self
+                    .config
+                    .oauth2_token_url
+                    .as_deref()
+                    .zip(self.config.oauth2_client_id.as_deref())
+                    .ok_or_else(|| {
+                        Error::Generic(
+                            "OAuth2 authentication requires a token URL and a client ID".to_owned(),
+                        )
+                    })?;
+
+                let request = oauth2::TokenRequest {
+                    endpoint: &self.config.name,
+                    token_url,
+                    client_id,
+                    client_secret: self.private_config.oauth2_client_secret.as_deref(),
+                    scope: self.config.oauth2_scope.as_deref(),
+                    refresh_token: self.private_config.oauth2_refresh_token.as_deref(),
+                };
+                let token = oauth2::access_token(&self.oauth2_cache_key(), &request)?;
+
+                Ok(Some((
+                    Credentials::new(username.to_string(), token),
+                    vec![Mechanism::Xoauth2],
+                )))
+            }
+        }
+    }
+}
+
 impl Endpoint for SmtpEndpoint {
     fn send(&self, notification: &Notification) -> Result<(), Error> {
         let tls_parameters = TlsParameters::new(self.config.server.clone())
@@ -180,17 +291,14 @@ impl Endpoint for SmtpEndpoint {
             .port(port)
             .timeout(Some(Duration::from_secs(SMTP_TIMEOUT.into())));
 
-        if let Some(username) = self.config.username.as_deref() {
-            if let Some(password) = self.private_config.password.as_deref() {
-                transport_builder = transport_builder.credentials((username, password).into());
-            } else {
-                return Err(Error::NotifyFailed(
-                    self.name().into(),
-                    Box::new(Error::Generic(
-                        "username is set but no password was provided".to_owned(),
-                    )),
-                ));
-            }
+        let credentials = self
+            .credentials()
+            .map_err(|err| Error::NotifyFailed(self.name().into(), Box::new(err)))?;
+
+        if let Some((credentials, mechanisms)) = credentials {
+            transport_builder = transport_builder
+                .credentials(credentials)
+                .authentication(mechanisms);
         }
 
         let transport = transport_builder.build();
@@ -226,8 +334,9 @@ impl Endpoint for SmtpEndpoint {
             } => {
                 let subject =
                     renderer::render_template(TemplateType::Subject, template_name, data)?;
+                let html_style = self.config.html_style.unwrap_or_default();
                 let html_part =
-                    renderer::render_template(TemplateType::HtmlBody, template_name, data)?;
+                    renderer::render_template(html_style.template_type(), template_name, data)?;
                 let text_part =
                     renderer::render_template(TemplateType::PlaintextBody, template_name, data)?;
 
@@ -353,9 +462,13 @@ impl Endpoint for SmtpEndpoint {
             "auto-generated;".into(),
         ));
 
-        transport
-            .send(&email)
-            .map_err(|err| Error::NotifyFailed(self.name().into(), err.into()))?;
+        transport.send(&email).map_err(|err| {
+            if self.config.auth_method == Some(SmtpAuthMethod::Oauth2) {
+                // the token might have been revoked, fetch a new one next time
+                oauth2::invalidate(&self.oauth2_cache_key());
+            }
+            Error::NotifyFailed(self.name().into(), err.into())
+        })?;
 
         Ok(())
     }
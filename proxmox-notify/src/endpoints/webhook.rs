@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use proxmox_http::client::sync::Client;
+use proxmox_http::{HttpClient, HttpOptions, ProxyConfig};
+use proxmox_schema::api_types::COMMENT_SCHEMA;
+use proxmox_schema::{api, ApiStringFormat, Schema, StringSchema, Updater};
+
+use crate::context::context;
+use crate::renderer::TemplateType;
+use crate::schema::ENTITY_NAME_SCHEMA;
+use crate::{renderer, Content, Endpoint, Error, Notification, Origin};
+
+pub(crate) const WEBHOOK_TYPENAME: &str = "webhook";
+
+/// Header containing the HMAC-SHA256 signature of the request body, if a secret is configured.
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Proxmox-Signature";
+
+const DEFAULT_RETRIES: u32 = 2;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Upper limit for the time spent waiting between retries, since sending blocks the caller.
+const MAX_TOTAL_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+pub const WEBHOOK_HEADER_FORMAT: ApiStringFormat = ApiStringFormat::VerifyFn(verify_header);
+
+fn verify_header(s: &str) -> Result<(), anyhow::Error> {
+    parse_header(s)?;
+    Ok(())
+}
+
+pub const WEBHOOK_HEADER_SCHEMA: Schema =
+    StringSchema::new("Additional HTTP header, in the form 'Name: value'.")
+        .format(&WEBHOOK_HEADER_FORMAT)
+        .min_length(3)
+        .max_length(1024)
+        .schema();
+
+/// Split a `Name: value` header entry.
+fn parse_header(header: &str) -> Result<(&str, &str), Error> {
+    let (name, value) = header.split_once(':').ok_or_else(|| {
+        Error::Generic(format!("invalid header '{header}', expected 'Name: value'"))
+    })?;
+
+    let is_token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if name.is_empty() || !name.chars().all(is_token) {
+        return Err(Error::Generic(format!("invalid header name '{name}'")));
+    }
+
+    let value = value.trim();
+    if value.chars().any(|c| c.is_control()) {
+        return Err(Error::Generic(format!("invalid value for header '{name}'")));
+    }
+
+    Ok((name, value))
+}
+
+#[api(
+    properties: {
+        name: {
+            schema: ENTITY_NAME_SCHEMA,
+        },
+        retries: {
+            optional: true,
+            minimum: 0,
+            maximum: 10,
+            default: 2,
+        },
+        comment: {
+            optional: true,
+            schema: COMMENT_SCHEMA,
+        },
+    }
+)]
+#[derive(Debug, Serialize, Deserialize, Updater, Default)]
+#[serde(rename_all = "kebab-case")]
+/// Config for webhook notification endpoints
+pub struct WebhookConfig {
+    /// Name of the endpoint.
+    #[updater(skip)]
+    pub name: String,
+    /// Target URL, the notification is sent there via a `POST` request.
+    pub url: String,
+    /// Base64-encoded handlebars template for the JSON request body.
+    /// Strings interpolated via `{{ ... }}` are escaped for use within JSON strings, the `json`
+    /// helper inserts any value as JSON. Available are `title`, `message`, `severity`,
+    /// `timestamp`, `fields` and the notification's template `data`.
+    /// If not set, an object containing the title, message, severity, timestamp and fields is
+    /// sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Number of retries if the target can not be reached or responds with a server error
+    /// (5xx), with an exponential backoff starting at one second. Retrying stops early once
+    /// more than a minute would be spent waiting in total.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Comment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// Disable this target.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable: Option<bool>,
+    /// Origin of this config entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[updater(skip)]
+    pub origin: Option<Origin>,
+}
+
+#[api(
+    properties: {
+        header: {
+            type: Array,
+            items: {
+                schema: WEBHOOK_HEADER_SCHEMA,
+            },
+            optional: true,
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone, Updater, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+/// Private configuration for webhook notification endpoints.
+/// This config will be saved to a separate configuration file with stricter
+/// permissions (root:root 0600)
+pub struct WebhookPrivateConfig {
+    /// Name of the endpoint
+    #[updater(skip)]
+    pub name: String,
+    /// Secret used to sign the request body with HMAC-SHA256. The signature is sent in the
+    /// `X-Proxmox-Signature` header as `sha256=<hex digest>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Additional HTTP headers, which usually contain credentials.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[updater(serde(skip_serializing_if = "Option::is_none"))]
+    pub header: Vec<String>,
+}
+
+/// A webhook notification endpoint.
+pub struct WebhookEndpoint {
+    pub config: WebhookConfig,
+    pub private_config: WebhookPrivateConfig,
+}
+
+#[api]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeleteableWebhookProperty {
+    /// Delete `body`
+    Body,
+    /// Delete `comment`
+    Comment,
+    /// Delete `disable`
+    Disable,
+    /// Delete `header`
+    Header,
+    /// Delete `retries`
+    Retries,
+    /// Delete `secret`
+    Secret,
+}
+
+impl WebhookEndpoint {
+    /// Render the request body and collect the headers to send.
+    fn build_request(
+        &self,
+        notification: &Notification,
+    ) -> Result<(Vec<u8>, HashMap<String, String>), Error> {
+        let (title, message, data) = match &notification.content {
+            Content::Template {
+                template_name,
+                data,
+            } => {
+                let rendered_title =
+                    renderer::render_template(TemplateType::Subject, template_name, data)?;
+                let rendered_message =
+                    renderer::render_template(TemplateType::PlaintextBody, template_name, data)?;
+
+                (rendered_title, rendered_message, data.clone())
+            }
+            #[cfg(feature = "mail-forwarder")]
+            Content::ForwardedMail { title, body, .. } => {
+                (title.clone(), body.clone(), serde_json::Value::Null)
+            }
+        };
+
+        let template_data = json!({
+            "title": title,
+            "message": message,
+            "severity": notification.metadata.severity,
+            "timestamp": notification.metadata.timestamp,
+            "fields": notification.metadata.additional_fields,
+            "data": data,
+        });
+
+        let body = match &self.config.body {
+            Some(template) => {
+                let template = base64::decode(template)
+                    .ok()
+                    .and_then(|template| String::from_utf8(template).ok())
+                    .ok_or_else(|| {
+                        Error::Generic("body template is not valid base64-encoded UTF-8".into())
+                    })?;
+                renderer::render_json_template(&template, &template_data)?
+            }
+            None => {
+                let mut body = template_data;
+                if let Some(body) = body.as_object_mut() {
+                    body.remove("data");
+                }
+                body.to_string()
+            }
+        };
+
+        let mut headers = HashMap::new();
+        for header in &self.private_config.header {
+            let (name, value) = parse_header(header)?;
+            headers.insert(name.to_string(), value.to_string());
+        }
+
+        if let Some(secret) = &self.private_config.secret {
+            let signature = sign_body(secret.as_bytes(), body.as_bytes())?;
+            headers.insert(WEBHOOK_SIGNATURE_HEADER.into(), signature);
+        }
+
+        Ok((body.into_bytes(), headers))
+    }
+}
+
+/// Compute the `sha256=<hex digest>` signature of `body`.
+fn sign_body(secret: &[u8], body: &[u8]) -> Result<String, Error> {
+    let sign = || -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let key = PKey::hmac(secret)?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(body)?;
+        signer.sign_to_vec()
+    };
+
+    let digest = sign().map_err(|err| Error::Generic(format!("could not sign body: {err}")))?;
+    let digest: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+
+    Ok(format!("sha256={digest}"))
+}
+
+/// Whether sending may succeed when retried, i.e. the target could not be reached or responded
+/// with a server error. Other errors, like an invalid request, would just fail again.
+fn is_retryable(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<ureq::Error>() {
+        Some(ureq::Error::Status(status, _)) => (500..600).contains(status),
+        Some(ureq::Error::Transport(transport)) => matches!(
+            transport.kind(),
+            ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed
+        ),
+        None => false,
+    }
+}
+
+impl Endpoint for WebhookEndpoint {
+    fn send(&self, notification: &Notification) -> Result<(), Error> {
+        let (body, extra_headers) = self
+            .build_request(notification)
+            .map_err(|err| Error::NotifyFailed(self.name().to_string(), err.into()))?;
+
+        let proxy_config = context()
+            .http_proxy_config()
+            .map(|url| ProxyConfig::parse_proxy_url(&url))
+            .transpose()
+            .map_err(|err| Error::NotifyFailed(self.name().to_string(), err.into()))?;
+
+        let options = HttpOptions {
+            proxy_config,
+            ..Default::default()
+        };
+
+        let client = Client::new(options);
+        let retries = self.config.retries.unwrap_or(DEFAULT_RETRIES);
+        let mut delay = INITIAL_RETRY_DELAY;
+        let mut total_delay = Duration::ZERO;
+        let mut retry = 0;
+
+        loop {
+            let result = client.post(
+                &self.config.url,
+                Some(body.as_slice()),
+                Some("application/json"),
+                Some(&extra_headers),
+            );
+
+            let err = match result {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+
+            total_delay += delay;
+            if retry >= retries || total_delay > MAX_TOTAL_RETRY_DELAY || !is_retryable(&err) {
+                return Err(Error::NotifyFailed(self.name().to_string(), err.into()));
+            }
+
+            log::warn!(
+                "could not notify via webhook '{}', retrying in {delay:?}: {err}",
+                self.name()
+            );
+            std::thread::sleep(delay);
+            delay *= 2;
+            retry += 1;
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Check if the endpoint is disabled
+    fn disabled(&self) -> bool {
+        self.config.disable.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::Severity;
+
+    fn endpoint(body: Option<&str>, secret: Option<&str>) -> WebhookEndpoint {
+        WebhookEndpoint {
+            config: WebhookConfig {
+                name: "webhook".into(),
+                url: "https://example.com/hook".into(),
+                body: body.map(base64::encode),
+                ..Default::default()
+            },
+            private_config: WebhookPrivateConfig {
+                name: "webhook".into(),
+                secret: secret.map(String::from),
+                header: vec!["X-Custom: some value".into()],
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header("Authorization: Bearer x:y").unwrap(),
+            ("Authorization", "Bearer x:y")
+        );
+        assert!(parse_header("no header").is_err());
+        assert!(parse_header("Bad Name: value").is_err());
+        assert!(parse_header("Name: a\nb").is_err());
+    }
+
+    #[test]
+    fn test_build_request() -> Result<(), Error> {
+        let notification = Notification::from_template(
+            Severity::Warning,
+            "test",
+            json!({ "vmid": 100 }),
+            HashMap::from([("hostname".to_string(), "pve \"1\"".to_string())]),
+        );
+
+        let template = r#"{"host": "{{ fields.hostname }}", "data": {{ json data }}}"#;
+        let (body, headers) =
+            endpoint(Some(template), Some("secret")).build_request(&notification)?;
+
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({ "host": "pve \"1\"", "data": { "vmid": 100 } })
+        );
+
+        assert_eq!(headers["X-Custom"], "some value");
+        let (raw_body, _) = endpoint(Some(template), None).build_request(&notification)?;
+        assert_eq!(
+            headers[WEBHOOK_SIGNATURE_HEADER],
+            sign_body(b"secret", &raw_body)?
+        );
+
+        let (body, headers) = endpoint(None, None).build_request(&notification)?;
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["severity"], "warning");
+        assert!(body.get("data").is_none());
+        assert!(!headers.contains_key(WEBHOOK_SIGNATURE_HEADER));
+
+        assert!(endpoint(Some("{ broken"), None)
+            .build_request(&notification)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let status = |code| {
+            let response = ureq::Response::new(code, "status", "").unwrap();
+            anyhow::Error::from(ureq::Error::Status(code, response))
+        };
+        assert!(is_retryable(&status(500)));
+        assert!(is_retryable(&status(503)));
+        assert!(!is_retryable(&status(400)));
+        assert!(!is_retryable(&status(404)));
+
+        // nothing listens on port 1
+        let err = ureq::post("http://127.0.0.1:1/").call().unwrap_err();
+        assert!(is_retryable(&err.into()));
+
+        let err = ureq::post("invalid url").call().unwrap_err();
+        assert!(!is_retryable(&err.into()));
+
+        assert!(!is_retryable(&anyhow::format_err!("other error")));
+    }
+
+    #[test]
+    fn test_sign_body() -> Result<(), Error> {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign_body(b"Jefe", b"what do ya want for nothing?")?,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        Ok(())
+    }
+}
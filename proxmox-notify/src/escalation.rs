@@ -0,0 +1,194 @@
+//! Escalation chains for notification matchers.
+//!
+//! A matcher with `escalate` steps notifies its targets right away, like any other matcher, but
+//! additionally remembers the event. If the event is not acknowledged via
+//! [`api::escalation::acknowledge_escalation`](crate::api::escalation::acknowledge_escalation)
+//! in time, the target of each escalation step is notified once its delay has passed.
+//!
+//! Events are identified by the matcher, the notification's template (or title for forwarded
+//! mails) and its metadata fields, so repeated notifications for the same event do not restart
+//! the chain. An event is only escalated while it is still firing: each step requires the event
+//! to have been notified again since the previous step (or the first notification). Otherwise the
+//! event is considered resolved and its chain ends.
+//!
+//! Pending escalations are stored in the [state directory](crate::context::Context::state_dir),
+//! so that the acknowledging API and all sending processes act on the same events. A long-running
+//! daemon should call [`Bus::check_escalations`](crate::Bus::check_escalations) periodically.
+//! Without a state directory, escalations only live as long as the [`Bus`](crate::Bus) and can
+//! not be acknowledged.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::api;
+
+use crate::matcher::{EscalationStep, MatcherConfig};
+use crate::state::State;
+use crate::{Content, Error, Notification};
+
+#[api]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// An event which will be escalated unless it is acknowledged.
+pub struct PendingEscalation {
+    /// Event ID, used to acknowledge the event.
+    pub id: String,
+    /// Name of the matcher defining the escalation chain.
+    pub matcher: String,
+    /// Time of the first notification for this event, as a UNIX epoch.
+    pub first_seen: i64,
+    /// Time of the next escalation, as a UNIX epoch.
+    pub next_escalation: i64,
+    /// Target notified by the next escalation.
+    pub next_target: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Escalation {
+    matcher: String,
+    first_seen: i64,
+    /// Time of the latest notification for this event.
+    last_seen: i64,
+    /// Time the previous step was due, or `first_seen` before the first step.
+    last_step: i64,
+    steps: Vec<EscalationStep>,
+    notification: Notification,
+}
+
+impl Escalation {
+    fn to_pending(&self, id: &str) -> Option<PendingEscalation> {
+        let step = self.steps.first()?;
+        Some(PendingEscalation {
+            id: id.to_string(),
+            matcher: self.matcher.clone(),
+            first_seen: self.first_seen,
+            next_escalation: self.first_seen + step.after,
+            next_target: step.target.clone(),
+        })
+    }
+}
+
+/// The pending escalations by event ID.
+pub(crate) type EscalationState = State<HashMap<String, Escalation>>;
+
+/// Compute the ID identifying the event `notification` belongs to for `matcher`.
+pub(crate) fn event_id(matcher: &str, notification: &Notification) -> String {
+    let mut data = vec![matcher.to_string()];
+
+    match &notification.content {
+        Content::Template { template_name, .. } => data.push(template_name.clone()),
+        #[cfg(feature = "mail-forwarder")]
+        Content::ForwardedMail { title, .. } => data.push(title.clone()),
+    }
+
+    let mut fields: Vec<_> = notification.metadata.additional_fields.iter().collect();
+    fields.sort();
+    for (key, value) in fields {
+        data.push(format!("{key}={value}"));
+    }
+
+    let digest = openssl::sha::sha256(data.join("\n").as_bytes());
+    digest[..16].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Start the escalation chain of all matchers with escalation steps matching `notification`,
+/// or mark the event as still firing if it is already pending.
+pub(crate) fn register(
+    state: &EscalationState,
+    matchers: &[MatcherConfig],
+    notification: &Notification,
+    now: i64,
+) -> Result<(), Error> {
+    let mut matched = Vec::new();
+
+    for matcher in matchers {
+        if matcher.escalate.is_empty() || matcher.disable.unwrap_or_default() {
+            continue;
+        }
+
+        match matcher.matches(notification) {
+            Ok(Some(_)) => (),
+            Ok(None) => continue,
+            Err(err) => {
+                log::error!("matcher '{name}' failed: {err}", name = matcher.name);
+                continue;
+            }
+        }
+
+        let mut steps = matcher.escalate.clone();
+        steps.sort_by_key(|step| step.after);
+
+        matched.push((matcher.name.clone(), steps));
+    }
+
+    if matched.is_empty() {
+        return Ok(());
+    }
+
+    state.update(|escalations| {
+        for (matcher, steps) in matched {
+            escalations
+                .entry(event_id(&matcher, notification))
+                .and_modify(|escalation| escalation.last_seen = now)
+                .or_insert_with(|| Escalation {
+                    matcher,
+                    first_seen: now,
+                    last_seen: now,
+                    last_step: now,
+                    steps,
+                    notification: notification.clone(),
+                });
+        }
+    })
+}
+
+/// Remove all escalation steps which are due at `now`, and all events which stopped firing.
+///
+/// Returns the target and the notification to send for each step of a still firing event.
+pub(crate) fn take_due(
+    state: &EscalationState,
+    now: i64,
+) -> Result<Vec<(String, Notification)>, Error> {
+    state.update(|escalations| {
+        let mut due = Vec::new();
+
+        for escalation in escalations.values_mut() {
+            while let Some(step) = escalation.steps.first() {
+                let step_time = escalation.first_seen + step.after;
+                if now < step_time {
+                    break;
+                }
+                if escalation.last_seen <= escalation.last_step {
+                    // not notified again since the previous step, the event was resolved
+                    escalation.steps.clear();
+                    break;
+                }
+                let step = escalation.steps.remove(0);
+                escalation.last_step = step_time;
+                due.push((step.target, escalation.notification.clone()));
+            }
+        }
+
+        escalations.retain(|_, escalation| !escalation.steps.is_empty());
+
+        due
+    })
+}
+
+/// Get all events which will still be escalated.
+pub(crate) fn pending(state: &EscalationState) -> Result<Vec<PendingEscalation>, Error> {
+    state.update(|escalations| {
+        let mut pending: Vec<_> = escalations
+            .iter()
+            .filter_map(|(id, escalation)| escalation.to_pending(id))
+            .collect();
+        pending.sort_by_key(|pending| pending.next_escalation);
+        pending
+    })
+}
+
+/// Stop escalating the event with ID `id`, returns whether such an event was pending.
+pub(crate) fn acknowledge(state: &EscalationState, id: &str) -> Result<bool, Error> {
+    state.update(|escalations| escalations.remove(id).is_some())
+}
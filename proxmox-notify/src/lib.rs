@@ -18,11 +18,14 @@ use matcher::{MatcherConfig, MATCHER_TYPENAME};
 pub mod api;
 pub mod config;
 pub mod context;
+pub mod digest;
 pub mod endpoints;
+pub mod escalation;
 pub mod filter;
 pub mod group;
 pub mod renderer;
 pub mod schema;
+mod state;
 
 #[derive(Debug)]
 pub enum Error {
@@ -377,6 +380,8 @@ impl Config {
 pub struct Bus {
     endpoints: HashMap<String, Box<dyn Endpoint>>,
     matchers: Vec<MatcherConfig>,
+    digests: digest::DigestQueue,
+    escalations: escalation::EscalationState,
 }
 
 #[allow(unused_macros)]
@@ -496,6 +501,22 @@ impl Bus {
                 .map(|e| (e.name().into(), e)),
             );
         }
+        #[cfg(feature = "webhook")]
+        {
+            use endpoints::webhook::WEBHOOK_TYPENAME;
+            use endpoints::webhook::{WebhookConfig, WebhookEndpoint, WebhookPrivateConfig};
+            endpoints.extend(
+                parse_endpoints_with_private_config!(
+                    config,
+                    WebhookConfig,
+                    WebhookPrivateConfig,
+                    WebhookEndpoint,
+                    WEBHOOK_TYPENAME
+                )?
+                .into_iter()
+                .map(|e| (e.name().into(), e)),
+            );
+        }
 
         let matchers = config
             .config
@@ -505,6 +526,8 @@ impl Bus {
         Ok(Bus {
             endpoints,
             matchers,
+            digests: digest::DigestQueue::new("digests"),
+            escalations: escalation::EscalationState::new("escalations"),
         })
     }
 
@@ -521,10 +544,78 @@ impl Bus {
     /// Send a notification. Notification matchers will determine which targets will receive
     /// the notification.
     ///
+    /// Notifications matched by a matcher with a digest window are queued and sent later as
+    /// part of a digest, see the [`digest`] module. Matchers with escalation steps additionally
+    /// start their escalation chain, see the [`escalation`] module.
+    ///
     /// Any errors will not be returned but only logged.
     pub fn send(&self, notification: &Notification) {
+        self.send_at(notification, proxmox_time::epoch_i64());
+    }
+
+    fn send_at(&self, notification: &Notification, now: i64) {
+        self.flush_digests_at(now);
+        self.check_escalations_at(now);
+
+        if let Err(err) = digest::queue(&self.digests, &self.matchers, notification, now) {
+            log::error!("could not queue notification for digests: {err}");
+        }
+        if let Err(err) = escalation::register(&self.escalations, &self.matchers, notification, now)
+        {
+            log::error!("could not register notification for escalation: {err}");
+        }
+
         let targets = matcher::check_matches(self.matchers.as_slice(), notification);
+        self.send_to_targets(targets, notification);
+    }
+
+    /// Send all queued digests whose aggregation window has passed.
+    ///
+    /// Should be called periodically by products using digests, since digests are otherwise
+    /// only sent when the next notification is sent.
+    pub fn flush_digests(&self) {
+        self.flush_digests_at(proxmox_time::epoch_i64());
+    }
+
+    fn flush_digests_at(&self, now: i64) {
+        self.send_digests(now, false);
+    }
+
+    fn send_digests(&self, now: i64, all: bool) {
+        match digest::take_due(&self.digests, now, all) {
+            Ok(digests) => {
+                for (targets, notification) in digests {
+                    self.send_to_targets(targets.iter().map(String::as_str), &notification);
+                }
+            }
+            Err(err) => log::error!("could not send digests: {err}"),
+        }
+    }
 
+    /// Notify the targets of all escalation steps which are due and were not acknowledged.
+    ///
+    /// Should be called periodically by products using escalation chains.
+    pub fn check_escalations(&self) {
+        self.check_escalations_at(proxmox_time::epoch_i64());
+    }
+
+    fn check_escalations_at(&self, now: i64) {
+        match escalation::take_due(&self.escalations, now) {
+            Ok(due) => {
+                for (target, notification) in due {
+                    log::info!("escalating notification to target '{target}'");
+                    self.send_to_targets([target.as_str()], &notification);
+                }
+            }
+            Err(err) => log::error!("could not check escalations: {err}"),
+        }
+    }
+
+    fn send_to_targets<'a>(
+        &self,
+        targets: impl IntoIterator<Item = &'a str>,
+        notification: &Notification,
+    ) {
         for target in targets {
             if let Some(endpoint) = self.endpoints.get(target) {
                 let name = endpoint.name();
@@ -555,6 +646,14 @@ impl Bus {
     /// In contrast to the `send` function, this function will return
     /// any errors to the caller.
     pub fn test_target(&self, target: &str) -> Result<(), Error> {
+        self.test_template(target, "test", json!({ "target": target }))
+    }
+
+    /// Send a synthetic notification using the template `template` with the
+    /// given `data` to a single target.
+    ///
+    /// Like `test_target`, this function will return any errors to the caller.
+    pub fn test_template(&self, target: &str, template: &str, data: Value) -> Result<(), Error> {
         let notification = Notification {
             metadata: Metadata {
                 severity: Severity::Info,
@@ -563,8 +662,8 @@ impl Bus {
                 timestamp: proxmox_time::epoch_i64(),
             },
             content: Content::Template {
-                template_name: "test".to_string(),
-                data: json!({ "target": target }),
+                template_name: template.to_string(),
+                data,
             },
             id: Uuid::generate(),
         };
@@ -579,6 +678,15 @@ impl Bus {
     }
 }
 
+impl Drop for Bus {
+    fn drop(&mut self) {
+        // digests queued in memory would be lost otherwise
+        if !self.digests.is_persistent() {
+            self.send_digests(proxmox_time::epoch_i64(), true);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{cell::RefCell, rc::Rc};
@@ -698,4 +806,215 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_digest() -> Result<(), Error> {
+        let mock = MockEndpoint::new("digest-endpoint");
+
+        let mut bus = Bus::default();
+        bus.add_endpoint(Box::new(mock.clone()));
+        bus.add_matcher(MatcherConfig {
+            name: "digest-matcher".into(),
+            target: vec!["digest-endpoint".into()],
+            digest_window: Some("15min".into()),
+            ..Default::default()
+        });
+
+        for severity in [Severity::Info, Severity::Error, Severity::Warning] {
+            bus.send(&Notification::from_template(
+                severity,
+                "test",
+                Default::default(),
+                HashMap::from([("hostname".into(), "pve".into())]),
+            ));
+        }
+        assert_eq!(mock.messages().len(), 0);
+
+        bus.flush_digests_at(proxmox_time::epoch_i64() + 60);
+        assert_eq!(mock.messages().len(), 0);
+
+        bus.flush_digests_at(proxmox_time::epoch_i64() + 15 * 60);
+        let messages = mock.messages();
+        assert_eq!(messages.len(), 1);
+
+        let digest = &messages[0];
+        assert_eq!(digest.metadata.severity, Severity::Error);
+        assert_eq!(digest.metadata.additional_fields["hostname"], "pve");
+        match &digest.content {
+            Content::Template {
+                template_name,
+                data,
+            } => {
+                assert_eq!(template_name, digest::DIGEST_TEMPLATE_NAME);
+                assert_eq!(data["count"], 3);
+                assert_eq!(data["table"]["data"].as_array().unwrap().len(), 3);
+            }
+            #[allow(unreachable_patterns)]
+            _ => panic!("digest is not a template notification"),
+        }
+
+        Ok(())
+    }
+
+    fn digest_bus(mock: &MockEndpoint) -> Bus {
+        let mut bus = Bus::default();
+        bus.add_endpoint(Box::new(mock.clone()));
+        bus.add_matcher(MatcherConfig {
+            name: "digest-matcher".into(),
+            target: vec![mock.name.into()],
+            digest_window: Some("15min".into()),
+            ..Default::default()
+        });
+        bus
+    }
+
+    fn notification(severity: Severity) -> Notification {
+        Notification::from_template(severity, "test", Default::default(), Default::default())
+    }
+
+    #[test]
+    fn test_digest_sent_on_drop() {
+        let mock = MockEndpoint::new("digest-endpoint");
+
+        let bus = digest_bus(&mock);
+        bus.send(&notification(Severity::Info));
+        assert_eq!(mock.messages().len(), 0);
+
+        // without a state directory, the queue is lost with the bus
+        drop(bus);
+        assert_eq!(mock.messages().len(), 1);
+    }
+
+    #[test]
+    fn test_digest_state_dir() {
+        let dir = std::env::temp_dir().join(format!(
+            "proxmox-notify-test-digests-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mock = MockEndpoint::new("digest-endpoint");
+        let persistent_bus = || {
+            let mut bus = digest_bus(&mock);
+            bus.digests = digest::DigestQueue::with_dir("digests", Some(dir.clone()));
+            bus
+        };
+
+        // the queue is shared with later buses, e.g. in other processes
+        let bus = persistent_bus();
+        bus.send(&notification(Severity::Info));
+        bus.send(&notification(Severity::Warning));
+        drop(bus);
+        assert_eq!(mock.messages().len(), 0);
+
+        let bus = persistent_bus();
+        bus.flush_digests_at(proxmox_time::epoch_i64() + 15 * 60);
+        let messages = mock.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].metadata.severity, Severity::Warning);
+
+        bus.flush_digests_at(proxmox_time::epoch_i64() + 30 * 60);
+        assert_eq!(mock.messages().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_escalation() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!(
+            "proxmox-notify-test-escalations-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let first = MockEndpoint::new("escalation-first");
+        let second = MockEndpoint::new("escalation-second");
+        let third = MockEndpoint::new("escalation-third");
+
+        let mut bus = Bus::default();
+        bus.escalations = escalation::EscalationState::with_dir("escalations", Some(dir.clone()));
+        bus.add_endpoint(Box::new(first.clone()));
+        bus.add_endpoint(Box::new(second.clone()));
+        bus.add_endpoint(Box::new(third.clone()));
+        bus.add_matcher(MatcherConfig {
+            name: "escalation-matcher".into(),
+            match_field: vec!["exact:type=escalation-test".parse()?],
+            target: vec!["escalation-first".into()],
+            escalate: vec![
+                "1h:escalation-third".parse()?,
+                "30min:escalation-second".parse()?,
+            ],
+            ..Default::default()
+        });
+
+        // the state as seen by another process, e.g. the API daemon
+        let api_state = escalation::EscalationState::with_dir("escalations", Some(dir.clone()));
+
+        let notification = |host: &str| {
+            Notification::from_template(
+                Severity::Error,
+                "test",
+                Default::default(),
+                HashMap::from([
+                    ("type".into(), "escalation-test".into()),
+                    ("hostname".into(), host.into()),
+                ]),
+            )
+        };
+
+        // repeated notifications for the same event only start a single chain
+        let now = proxmox_time::epoch_i64();
+        for host in ["a", "a", "b", "c"] {
+            bus.send_at(&notification(host), now);
+        }
+        assert_eq!(first.messages().len(), 4);
+
+        let pending = escalation::pending(&api_state)?;
+        assert_eq!(pending.len(), 3);
+        assert_eq!(pending[0].next_target, "escalation-second");
+        assert_eq!(pending[0].next_escalation, now + 30 * 60);
+
+        bus.check_escalations_at(now + 60);
+        assert_eq!(second.messages().len(), 0);
+
+        // "b" stopped firing and is not escalated
+        bus.send_at(&notification("a"), now + 10 * 60);
+        bus.send_at(&notification("c"), now + 10 * 60);
+        bus.check_escalations_at(now + 30 * 60);
+        assert_eq!(second.messages().len(), 2);
+        assert_eq!(third.messages().len(), 0);
+
+        let pending = escalation::pending(&api_state)?;
+        assert_eq!(pending.len(), 2);
+        let c_id = pending
+            .into_iter()
+            .find(|pending| {
+                pending.id == escalation::event_id("escalation-matcher", &notification("c"))
+            })
+            .unwrap()
+            .id;
+        assert!(escalation::acknowledge(&api_state, &c_id)?);
+        assert!(!escalation::acknowledge(&api_state, &c_id)?);
+
+        // only "a" is still firing and not acknowledged
+        bus.send_at(&notification("a"), now + 40 * 60);
+        bus.send_at(&notification("c"), now + 40 * 60);
+        bus.check_escalations_at(now + 60 * 60);
+        assert_eq!(third.messages().len(), 1);
+        assert_eq!(
+            third.messages()[0].metadata.additional_fields["hostname"],
+            "a"
+        );
+        assert_eq!(second.messages().len(), 2);
+
+        // an event firing again after it was acknowledged starts a new chain
+        let pending = escalation::pending(&api_state)?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, c_id);
+        assert_eq!(pending[0].first_seen, now + 40 * 60);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        Ok(())
+    }
 }
@@ -7,9 +7,9 @@ use const_format::concatcp;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use proxmox_schema::api_types::{COMMENT_SCHEMA, SAFE_ID_REGEX_STR};
+use proxmox_schema::api_types::{COMMENT_SCHEMA, SAFE_ID_REGEX, SAFE_ID_REGEX_STR};
 use proxmox_schema::{api, const_regex, ApiStringFormat, Schema, StringSchema, Updater};
-use proxmox_time::{parse_daily_duration, DailyDuration};
+use proxmox_time::{parse_daily_duration, DailyDuration, TimeSpan};
 
 use crate::schema::ENTITY_NAME_SCHEMA;
 use crate::{Error, Notification, Origin, Severity};
@@ -63,6 +63,21 @@ pub const MATCH_FIELD_ENTRY_SCHEMA: Schema = StringSchema::new("Match metadata f
     .max_length(1024)
     .schema();
 
+pub const DIGEST_WINDOW_FORMAT: ApiStringFormat = ApiStringFormat::VerifyFn(verify_digest_window);
+
+fn verify_digest_window(s: &str) -> Result<(), anyhow::Error> {
+    s.parse::<TimeSpan>()?;
+    Ok(())
+}
+
+pub const DIGEST_WINDOW_SCHEMA: Schema = StringSchema::new(
+    "Aggregate matching notifications over this time span (e.g. '15min') into a single digest.",
+)
+.format(&DIGEST_WINDOW_FORMAT)
+.min_length(1)
+.max_length(64)
+.schema();
+
 #[api(
     properties: {
         name: {
@@ -103,6 +118,19 @@ pub const MATCH_FIELD_ENTRY_SCHEMA: Schema = StringSchema::new("Match metadata f
             },
             optional: true,
         },
+        "digest-window": {
+            schema: DIGEST_WINDOW_SCHEMA,
+            optional: true,
+        },
+        "escalate": {
+            type: Array,
+            items: {
+                description: "Escalation step, e.g. '30min:target' to notify 'target' if the \
+                    event is still firing and was not acknowledged after 30 minutes.",
+                type: String
+            },
+            optional: true,
+        },
     })]
 #[derive(Debug, Serialize, Deserialize, Updater, Default)]
 #[serde(rename_all = "kebab-case")]
@@ -139,6 +167,16 @@ pub struct MatcherConfig {
     #[updater(serde(skip_serializing_if = "Option::is_none"))]
     pub target: Vec<String>,
 
+    /// Aggregate matching notifications into a digest sent after this time span.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest_window: Option<String>,
+
+    /// Escalation chain, additional targets to notify if a matched event keeps firing and is not
+    /// acknowledged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[updater(serde(skip_serializing_if = "Option::is_none"))]
+    pub escalate: Vec<EscalationStep>,
+
     /// Comment.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
@@ -318,6 +356,21 @@ impl MatcherConfig {
         })
     }
 
+    /// The digest window in seconds, if notifications are aggregated by this matcher.
+    pub fn digest_window(&self) -> Option<i64> {
+        let window = self.digest_window.as_deref()?;
+        match window.parse::<TimeSpan>() {
+            Ok(span) => Some(f64::from(span) as i64),
+            Err(err) => {
+                log::error!(
+                    "matcher '{name}' has invalid digest window '{window}': {err}",
+                    name = self.name
+                );
+                None
+            }
+        }
+    }
+
     /// Check if given `MatchDirectives` match a notification.
     fn check_matches(
         &self,
@@ -412,14 +465,67 @@ impl FromStr for CalendarMatcher {
     }
 }
 
+/// Escalation step, notifies a target if an event is still firing and was not acknowledged after
+/// some time.
+#[derive(Clone, Debug)]
+pub struct EscalationStep {
+    /// Delay in seconds, counted from the first notification for the event.
+    pub(crate) after: i64,
+    /// Target to notify.
+    pub(crate) target: String,
+    original: String,
+}
+
+proxmox_serde::forward_deserialize_to_from_str!(EscalationStep);
+proxmox_serde::forward_serialize_to_display!(EscalationStep);
+
+impl fmt::Display for EscalationStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.original)
+    }
+}
+
+impl FromStr for EscalationStep {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let (after, target) = s.split_once(':').ok_or_else(|| {
+            Error::Generic(format!(
+                "invalid escalation step '{s}', expected 'timespan:target'"
+            ))
+        })?;
+
+        let after: TimeSpan = after
+            .trim()
+            .parse()
+            .map_err(|err| Error::Generic(format!("invalid escalation delay: {err}")))?;
+
+        let target = target.trim();
+        if !SAFE_ID_REGEX.is_match(target) {
+            return Err(Error::Generic(format!(
+                "invalid escalation target '{target}'"
+            )));
+        }
+
+        Ok(Self {
+            after: f64::from(after) as i64,
+            target: target.to_string(),
+            original: s.to_string(),
+        })
+    }
+}
+
 #[api]
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum DeleteableMatcherProperty {
     /// Delete `comment`
     Comment,
+    /// Delete `digest-window`
+    DigestWindow,
     /// Delete `disable`
     Disable,
+    /// Delete `escalate`
+    Escalate,
     /// Delete `invert-match`
     InvertMatch,
     /// Delete `match-calendar`
@@ -434,6 +540,10 @@ pub enum DeleteableMatcherProperty {
     Target,
 }
 
+/// Collect the targets of all matchers matching `notification`.
+///
+/// Matchers with a digest window are skipped, notifications matched by them are aggregated via
+/// the digest queue instead.
 pub fn check_matches<'a>(
     matchers: &'a [MatcherConfig],
     notification: &Notification,
@@ -447,6 +557,10 @@ pub fn check_matches<'a>(
             continue;
         }
 
+        if matcher.digest_window().is_some() {
+            continue;
+        }
+
         match matcher.matches(notification) {
             Ok(t) => {
                 let t = t.unwrap_or_default();
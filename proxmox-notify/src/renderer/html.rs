@@ -2,7 +2,7 @@ use handlebars::{
     Context, Handlebars, Helper, HelperResult, Output, RenderContext,
     RenderError as HandlebarsRenderError,
 };
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 use super::{table::Table, value_to_string};
 use crate::renderer::BlockRenderFunctions;
@@ -84,3 +84,183 @@ pub(super) fn block_render_functions() -> BlockRenderFunctions {
         object: Box::new(render_object),
     }
 }
+
+// Styles for the styled renderer. Everything is inlined, since many email clients drop
+// `<style>` elements, only the dark mode overrides need one (see `DARK_MODE_STYLE`).
+const TABLE_STYLE: &str = "width:100%;max-width:800px;border-collapse:collapse;\
+    font-family:Arial,Helvetica,sans-serif;font-size:14px;";
+const TH_STYLE: &str = "text-align:left;padding:6px 10px;border-bottom:2px solid #cccccc;\
+    background-color:#f2f2f2;color:#333333;";
+const TD_STYLE: &str = "padding:6px 10px;border-bottom:1px solid #e5e5e5;color:#333333;\
+    vertical-align:top;word-break:break-word;";
+const KEY_STYLE: &str = "font-weight:bold;white-space:nowrap;width:1%;";
+const ROW_COLOR: &str = "background-color:#ffffff;";
+const ALT_ROW_COLOR: &str = "background-color:#fafafa;";
+
+/// Dark mode overrides for the classes used by the styled renderer, for clients supporting
+/// `prefers-color-scheme`.
+pub(super) const DARK_MODE_STYLE: &str = r#"<meta name="color-scheme" content="light dark">
+<meta name="supported-color-schemes" content="light dark">
+<style>
+@media (prefers-color-scheme: dark) {
+  .pmx-th { background-color: #2b2b2b !important; color: #e6e6e6 !important; border-color: #555555 !important; }
+  .pmx-td { color: #e6e6e6 !important; border-color: #444444 !important; }
+  .pmx-row { background-color: #1e1e1e !important; }
+  .pmx-row-alt { background-color: #262626 !important; }
+}
+</style>
+"#;
+
+fn write_row_start(out: &mut dyn Output, index: usize) -> Result<(), std::io::Error> {
+    if index & 1 == 0 {
+        out.write(&format!("  <tr class=\"pmx-row\" style=\"{ROW_COLOR}\">\n"))
+    } else {
+        out.write(&format!(
+            "  <tr class=\"pmx-row-alt\" style=\"{ALT_ROW_COLOR}\">\n"
+        ))
+    }
+}
+
+fn write_cell(out: &mut dyn Output, extra_style: &str, text: &str) -> Result<(), std::io::Error> {
+    out.write(&format!(
+        "    <td class=\"pmx-td\" style=\"{TD_STYLE}{extra_style}\">{}</td>\n",
+        handlebars::html_escape(text)
+    ))
+}
+
+fn render_styled_table(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| HandlebarsRenderError::new("parameter not found"))?;
+
+    let table: Table = serde_json::from_value(param.value().clone())?;
+
+    out.write(&format!(
+        "<table class=\"pmx-table\" style=\"{TABLE_STYLE}\">\n"
+    ))?;
+
+    // Write header
+    out.write("  <tr>\n")?;
+    for column in &table.schema.columns {
+        out.write(&format!(
+            "    <th class=\"pmx-th\" style=\"{TH_STYLE}\">{}</th>\n",
+            handlebars::html_escape(&column.label)
+        ))?;
+    }
+    out.write("  </tr>\n")?;
+
+    // Write individual rows
+    for (index, row) in table.data.iter().enumerate() {
+        write_row_start(out, index)?;
+
+        for column in &table.schema.columns {
+            let entry = row.get(&column.id).unwrap_or(&Value::Null);
+
+            let text = if let Some(renderer) = &column.renderer {
+                renderer.render(entry)
+            } else {
+                value_to_string(entry)
+            };
+
+            write_cell(out, "", &text)?;
+        }
+        out.write("  </tr>\n")?;
+    }
+
+    out.write("</table>\n")?;
+
+    Ok(())
+}
+
+/// Write `object` as a key/value table, nested objects are written as nested tables.
+fn write_key_value_table(
+    out: &mut dyn Output,
+    object: &Map<String, Value>,
+) -> Result<(), std::io::Error> {
+    out.write(&format!(
+        "<table class=\"pmx-table\" style=\"{TABLE_STYLE}\">\n"
+    ))?;
+
+    for (index, (key, value)) in object.iter().enumerate() {
+        write_row_start(out, index)?;
+        write_cell(out, KEY_STYLE, key)?;
+
+        match value {
+            Value::Object(nested) => {
+                out.write(&format!("    <td class=\"pmx-td\" style=\"{TD_STYLE}\">\n"))?;
+                write_key_value_table(out, nested)?;
+                out.write("    </td>\n")?;
+            }
+            Value::Array(values) => {
+                let values: Vec<String> = values.iter().map(value_to_string).collect();
+                write_cell(out, "", &values.join(", "))?;
+            }
+            value => write_cell(out, "", &value_to_string(value))?,
+        }
+
+        out.write("  </tr>\n")?;
+    }
+
+    out.write("</table>\n")
+}
+
+fn render_styled_object(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| HandlebarsRenderError::new("parameter not found"))?;
+
+    match param.value() {
+        Value::Object(object) => write_key_value_table(out, object)?,
+        value => {
+            out.write("\n<pre>")?;
+            out.write(&handlebars::html_escape(&serde_json::to_string_pretty(
+                value,
+            )?))?;
+            out.write("\n</pre>\n")?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) fn styled_block_render_functions() -> BlockRenderFunctions {
+    BlockRenderFunctions {
+        table: Box::new(render_styled_table),
+        object: Box::new(render_styled_object),
+    }
+}
+
+/// Add the dark mode style to a rendered HTML document or fragment.
+pub(super) fn add_dark_mode_style(rendered: String) -> String {
+    if let Some(index) = rendered.find("<head>") {
+        let index = index + "<head>".len();
+        format!(
+            "{}\n{DARK_MODE_STYLE}{}",
+            &rendered[..index],
+            &rendered[index..]
+        )
+    } else if let Some(index) = rendered
+        .find("<html")
+        .and_then(|start| Some(start + rendered[start..].find('>')? + 1))
+    {
+        format!(
+            "{}\n<head>\n{DARK_MODE_STYLE}</head>{}",
+            &rendered[..index],
+            &rendered[index..]
+        )
+    } else {
+        format!("{DARK_MODE_STYLE}{rendered}")
+    }
+}
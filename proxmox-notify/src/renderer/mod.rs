@@ -2,6 +2,7 @@
 
 use std::time::Duration;
 
+use handlebars::template::{Parameter, Template, TemplateElement};
 use handlebars::{
     Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
     RenderError as HandlebarsRenderError,
@@ -9,6 +10,8 @@ use handlebars::{
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use proxmox_schema::api;
+
 use proxmox_human_byte::HumanByte;
 use proxmox_time::TimeSpan;
 
@@ -189,11 +192,35 @@ impl ValueRenderFunction {
     }
 }
 
+#[api]
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Style of rendered HTML tables and objects.
+pub enum HtmlStyle {
+    /// Plain tables with borders
+    #[default]
+    Basic,
+    /// Responsive tables with inline styles and dark mode support
+    Styled,
+}
+
+impl HtmlStyle {
+    /// The template type to use for HTML bodies with this style.
+    pub fn template_type(self) -> TemplateType {
+        match self {
+            HtmlStyle::Basic => TemplateType::HtmlBody,
+            HtmlStyle::Styled => TemplateType::StyledHtmlBody,
+        }
+    }
+}
+
 /// Available template types
 #[derive(Copy, Clone)]
 pub enum TemplateType {
     /// HTML body template
     HtmlBody,
+    /// HTML body template, rendered with the styled table and object renderer
+    StyledHtmlBody,
     /// Plaintext body template
     PlaintextBody,
     /// Subject template
@@ -201,17 +228,26 @@ pub enum TemplateType {
 }
 
 impl TemplateType {
+    /// Determine the template type from a template's file name, e.g. `test-body.txt.hbs`.
+    pub(crate) fn from_filename(filename: &str) -> Option<Self> {
+        [Self::HtmlBody, Self::PlaintextBody, Self::Subject]
+            .into_iter()
+            .find(|ty| filename.ends_with(&format!("-{}", ty.file_suffix())))
+    }
+
     fn file_suffix(&self) -> &'static str {
         match self {
-            TemplateType::HtmlBody => "body.html.hbs",
+            TemplateType::HtmlBody | TemplateType::StyledHtmlBody => "body.html.hbs",
             TemplateType::PlaintextBody => "body.txt.hbs",
             TemplateType::Subject => "subject.txt.hbs",
         }
     }
 
     fn postprocess(&self, mut rendered: String) -> String {
-        if let Self::Subject = self {
-            rendered = rendered.replace('\n', " ");
+        match self {
+            Self::Subject => rendered = rendered.replace('\n', " "),
+            Self::StyledHtmlBody => rendered = html::add_dark_mode_style(rendered),
+            _ => (),
         }
 
         rendered
@@ -220,6 +256,7 @@ impl TemplateType {
     fn block_render_fns(&self) -> BlockRenderFunctions {
         match self {
             TemplateType::HtmlBody => html::block_render_functions(),
+            TemplateType::StyledHtmlBody => html::styled_block_render_functions(),
             TemplateType::Subject => plaintext::block_render_functions(),
             TemplateType::PlaintextBody => plaintext::block_render_functions(),
         }
@@ -229,7 +266,7 @@ impl TemplateType {
         match self {
             TemplateType::PlaintextBody => handlebars::no_escape,
             TemplateType::Subject => handlebars::no_escape,
-            TemplateType::HtmlBody => handlebars::html_escape,
+            TemplateType::HtmlBody | TemplateType::StyledHtmlBody => handlebars::html_escape,
         }
     }
 }
@@ -248,11 +285,7 @@ impl BlockRenderFunctions {
     }
 }
 
-fn render_template_impl(
-    template: &str,
-    data: &Value,
-    renderer: TemplateType,
-) -> Result<String, Error> {
+fn template_registry(renderer: TemplateType) -> Handlebars<'static> {
     let mut handlebars = Handlebars::new();
     handlebars.register_escape_fn(renderer.escape_fn());
 
@@ -266,13 +299,188 @@ fn render_template_impl(
         Box::new(handlebars_relative_percentage_helper),
     );
 
-    let rendered_template = handlebars
+    handlebars
+}
+
+fn render_template_impl(
+    template: &str,
+    data: &Value,
+    renderer: TemplateType,
+) -> Result<String, Error> {
+    let rendered_template = template_registry(renderer)
         .render_template(template, data)
         .map_err(|err| Error::RenderError(err.into()))?;
 
     Ok(rendered_template)
 }
 
+/// Escape a string for use within a JSON string literal.
+#[cfg(feature = "webhook")]
+fn json_escape(data: &str) -> String {
+    let escaped = serde_json::to_string(data).unwrap_or_default();
+    escaped
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(feature = "webhook")]
+fn handlebars_json_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _rc: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| HandlebarsRenderError::new("json: param0 not found"))?;
+    out.write(&param.value().to_string())?;
+    Ok(())
+}
+
+/// Render an inline template which has to result in a JSON document.
+///
+/// Interpolated values are escaped for use within JSON strings, the `json` helper can be used to
+/// insert a value as JSON.
+#[cfg(feature = "webhook")]
+pub(crate) fn render_json_template(template: &str, data: &Value) -> Result<String, Error> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(json_escape);
+
+    ValueRenderFunction::register_helpers(&mut handlebars);
+    handlebars.register_helper("json", Box::new(handlebars_json_helper));
+
+    let rendered = handlebars
+        .render_template(template, data)
+        .map_err(|err| Error::RenderError(err.into()))?;
+
+    serde_json::from_str::<Value>(&rendered)
+        .map_err(|err| Error::Generic(format!("rendered template is not valid JSON: {err}")))?;
+
+    Ok(rendered)
+}
+
+/// Built-in templates, used if the product does not ship its own version.
+pub(crate) const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "digest-subject.txt.hbs",
+        "{{ count }} notifications (digest)",
+    ),
+    (
+        "digest-body.txt.hbs",
+        concat!(
+            "{{ count }} notifications matched by '{{ matcher }}' between ",
+            "{{ timestamp window-start }} and {{ timestamp window-end }}:\n\n",
+            "{{ table table }}",
+        ),
+    ),
+];
+
+fn builtin_template(filename: &str) -> Option<&'static str> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .find(|(name, _)| *name == filename)
+        .map(|(_, template)| *template)
+}
+
+/// Read the user-provided override of a template, if there is one.
+pub(crate) fn lookup_template_override(filename: &str) -> Result<Option<String>, Error> {
+    let dir = match context::context().template_override_dir(None) {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+
+    match std::fs::read_to_string(dir.join(filename)) {
+        Ok(template) => Ok(Some(template)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::Generic(format!(
+            "could not load template override: {err}"
+        ))),
+    }
+}
+
+/// Look up a template, overrides take precedence over shipped and built-in templates.
+pub(crate) fn lookup_template(filename: &str) -> Result<Option<String>, Error> {
+    if let Some(template) = lookup_template_override(filename)? {
+        return Ok(Some(template));
+    }
+
+    Ok(context::context()
+        .lookup_template(filename, None)?
+        .or_else(|| builtin_template(filename).map(String::from)))
+}
+
+fn check_parameter_helpers(parameter: &Parameter, handlebars: &Handlebars) -> Result<(), Error> {
+    if let Parameter::Subexpression(subexpression) = parameter {
+        if let TemplateElement::Expression(helper) = subexpression.as_element() {
+            check_helper(helper.name.as_name(), handlebars)?;
+        }
+        check_element_helpers(subexpression.as_element(), handlebars)?;
+    }
+
+    Ok(())
+}
+
+fn check_helper(name: Option<&str>, handlebars: &Handlebars) -> Result<(), Error> {
+    match name {
+        Some(name) if handlebars.get_helper(name).is_none() => {
+            Err(Error::Generic(format!("unknown helper '{name}'")))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_element_helpers(element: &TemplateElement, handlebars: &Handlebars) -> Result<(), Error> {
+    match element {
+        TemplateElement::RawString(_) | TemplateElement::Comment(_) => (),
+        TemplateElement::HTMLExpression(parameter) => {
+            check_parameter_helpers(parameter, handlebars)?
+        }
+        TemplateElement::Expression(helper) | TemplateElement::HelperBlock(helper) => {
+            // expressions without parameters might also just reference a value
+            let is_call = !helper.params.is_empty() || !helper.hash.is_empty();
+            if helper.block || is_call {
+                check_helper(helper.name.as_name(), handlebars)?;
+            }
+
+            for parameter in helper.params.iter().chain(helper.hash.values()) {
+                check_parameter_helpers(parameter, handlebars)?;
+            }
+
+            for template in helper.template.iter().chain(helper.inverse.iter()) {
+                for element in &template.elements {
+                    check_element_helpers(element, handlebars)?;
+                }
+            }
+        }
+        TemplateElement::DecoratorExpression(_)
+        | TemplateElement::DecoratorBlock(_)
+        | TemplateElement::PartialExpression(_)
+        | TemplateElement::PartialBlock(_) => {
+            return Err(Error::Generic(
+                "partials and decorators are not supported".into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a template string is syntactically valid and only uses known helpers.
+pub fn validate_template(template: &str, ty: TemplateType) -> Result<(), Error> {
+    let compiled = Template::compile(template)
+        .map_err(|err| Error::Generic(format!("invalid template syntax: {err}")))?;
+
+    let handlebars = template_registry(ty);
+    for element in &compiled.elements {
+        check_element_helpers(element, &handlebars)?;
+    }
+
+    Ok(())
+}
+
 /// Render a template string.
 ///
 /// The output format can be chosen via the `renderer` parameter (see [TemplateType]
@@ -284,17 +492,14 @@ pub fn render_template(
 ) -> Result<String, Error> {
     let filename = format!("{template}-{suffix}", suffix = ty.file_suffix());
 
-    let template_string = context::context().lookup_template(&filename, None)?;
+    let template_string = lookup_template(&filename)?;
 
     let (template_string, fallback) = match (template_string, ty) {
-        (None, TemplateType::HtmlBody) => {
+        (None, TemplateType::HtmlBody | TemplateType::StyledHtmlBody) => {
             ty = TemplateType::PlaintextBody;
             let plaintext_filename = format!("{template}-{suffix}", suffix = ty.file_suffix());
             log::info!("html template '{filename}' not found, falling back to plain text template '{plaintext_filename}'");
-            (
-                context::context().lookup_template(&plaintext_filename, None)?,
-                true,
-            )
+            (lookup_template(&plaintext_filename)?, true)
         }
         (template_string, _) => (template_string, false),
     };
@@ -337,4 +542,62 @@ mod tests {
         assert!(value_to_timestamp(&json!(60)).is_some());
         assert!(value_to_timestamp(&json!("60")).is_some());
     }
+
+    #[test]
+    fn test_styled_html() -> Result<(), Error> {
+        let data = json!({
+            "table": {
+                "schema": {
+                    "columns": [{ "label": "Name", "id": "name" }],
+                },
+                "data": [{ "name": "<vm 100>" }, { "name": "vm 101" }],
+            },
+            "details": { "node": "pve", "disks": ["scsi0", "scsi1"], "nested": { "a": 1 } },
+        });
+
+        let rendered = render_template_impl(
+            "<html><head></head><body>{{table table}}{{object details}}</body></html>",
+            &data,
+            TemplateType::StyledHtmlBody,
+        )?;
+        let rendered = TemplateType::StyledHtmlBody.postprocess(rendered);
+
+        assert!(rendered.starts_with("<html><head>\n<meta name=\"color-scheme\""));
+        assert!(rendered.contains("prefers-color-scheme: dark"));
+        assert!(rendered.contains("&lt;vm 100&gt;"));
+        assert!(rendered.contains("class=\"pmx-row-alt\""));
+        assert!(rendered.contains(">scsi0, scsi1</td>"));
+        assert!(rendered.contains(">nested</td>"));
+
+        assert_eq!(
+            html::add_dark_mode_style("<p>x</p>".into()),
+            format!("{}<p>x</p>", html::DARK_MODE_STYLE)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builtin_digest_template() -> Result<(), Error> {
+        let data = json!({
+            "count": 2,
+            "matcher": "storm",
+            "window-start": 0,
+            "window-end": 900,
+            "table": {
+                "schema": {
+                    "columns": [{ "label": "Title", "id": "title" }],
+                },
+                "data": [{ "title": "first" }, { "title": "second" }],
+            },
+        });
+
+        let template = builtin_template("digest-body.txt.hbs").unwrap();
+        let rendered = render_template_impl(template, &data, TemplateType::PlaintextBody)?;
+        assert!(rendered.starts_with("2 notifications matched by 'storm' between"));
+        assert!(rendered.contains("first"));
+        assert!(rendered.contains("second"));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,82 @@
+//! State shared by all processes sending notifications, see [`Context::state_dir`].
+//!
+//! [`Context::state_dir`]: crate::context::Context::state_dir
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use proxmox_sys::fs::{file_read_optional_string, open_file_locked, replace_file};
+
+use crate::context::context;
+use crate::Error;
+
+/// State stored as `notification-<name>.json` in a state directory, or in memory without one.
+pub(crate) struct State<T> {
+    name: &'static str,
+    dir: Option<PathBuf>,
+    memory: Mutex<T>,
+}
+
+impl<T: Default> Default for State<T> {
+    fn default() -> Self {
+        Self::with_dir("", None)
+    }
+}
+
+impl<T: Default> State<T> {
+    /// The state `name` in the state directory of the product context.
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self::with_dir(name, context().state_dir())
+    }
+
+    pub(crate) fn with_dir(name: &'static str, dir: Option<PathBuf>) -> Self {
+        Self {
+            name,
+            dir,
+            memory: Mutex::new(T::default()),
+        }
+    }
+
+    /// Whether the state outlives this instance.
+    pub(crate) fn is_persistent(&self) -> bool {
+        self.dir.is_some()
+    }
+}
+
+impl<T: Default + Serialize + DeserializeOwned> State<T> {
+    /// Modify the state with `f`, persisted state is locked while doing so.
+    pub(crate) fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, Error> {
+        match &self.dir {
+            Some(dir) => self.update_file(dir, f).map_err(|err| {
+                Error::Generic(format!(
+                    "could not update notification state '{}': {err}",
+                    self.name
+                ))
+            }),
+            None => Ok(f(&mut self.memory.lock().unwrap())),
+        }
+    }
+
+    fn update_file<R>(&self, dir: &Path, f: impl FnOnce(&mut T) -> R) -> Result<R, anyhow::Error> {
+        let options = context().state_file_options();
+        let path = dir.join(format!("notification-{}.json", self.name));
+        let lock_path = dir.join(format!("notification-{}.lck", self.name));
+
+        let _lock = open_file_locked(lock_path, Duration::from_secs(10), true, options.clone())?;
+
+        let mut state = match file_read_optional_string(&path)? {
+            Some(data) => serde_json::from_str(&data)?,
+            None => T::default(),
+        };
+
+        let result = f(&mut state);
+
+        replace_file(&path, &serde_json::to_vec(&state)?, options, true)?;
+
+        Ok(result)
+    }
+}
@@ -73,8 +73,17 @@ pub fn replace_privileged_config<P: AsRef<Path>>(path: P, data: &[u8]) -> Result
     Ok(())
 }
 
-/// Atomically write data to file owned by `api-user.uid:api-user.gid` with permission `0660`.
+/// Atomically write data to file owned by `api-user.uid:api-user.gid` with permission `0640`.
+///
+/// This is the same as [replace_api_config].
 pub fn replace_config<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<(), Error> {
+    replace_api_config(path, data)
+}
+
+/// Atomically write data to file owned by `api-user.uid:api-user.gid` with permission `0640`.
+///
+/// The api user can read and write those files, its group can read them.
+pub fn replace_api_config<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<(), Error> {
     let options = default_create_options();
     proxmox_sys::fs::replace_file(path, data, options, true)?;
     Ok(())
@@ -101,6 +110,31 @@ pub fn create_secret_dir<P: AsRef<Path>>(dir: P) -> Result<(), Error> {
     }
 }
 
+/// Creates a directory owned by `api_user.uid:api_user.gid` with permission `0750`.
+///
+/// Simply returns Ok if the directory already exists.
+pub fn create_api_dir<P: AsRef<Path>>(dir: P) -> Result<(), Error> {
+    let options = default_create_options().perm(Mode::from_bits_truncate(0o750));
+    match proxmox_sys::fs::create_dir(dir, options) {
+        Ok(()) => Ok(()),
+        Err(err) if err.already_exists() => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Creates a directory owned by `priv_user.uid:api_user.gid` with permission `0750`.
+///
+/// Only the superuser can create files in it, but group `api-user.gid` can list and read them.
+/// Simply returns Ok if the directory already exists.
+pub fn create_privileged_dir<P: AsRef<Path>>(dir: P) -> Result<(), Error> {
+    let options = privileged_create_options().perm(Mode::from_bits_truncate(0o750));
+    match proxmox_sys::fs::create_dir(dir, options) {
+        Ok(()) => Ok(()),
+        Err(err) if err.already_exists() => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
 /// Atomically write data to file owned by `root:root` with permission `0644`.
 ///
 /// Everyone can read, but only the superuser can write those files. This is usually used
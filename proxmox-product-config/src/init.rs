@@ -1,32 +1,49 @@
+use std::sync::OnceLock;
+
+#[derive(PartialEq)]
 struct ProxmoxProductConfig {
     api_user: nix::unistd::User,
     priv_user: nix::unistd::User,
 }
 
-static mut PRODUCT_CONFIG: Option<ProxmoxProductConfig> = None;
+static PRODUCT_CONFIG: OnceLock<ProxmoxProductConfig> = OnceLock::new();
 
 /// Initialize the global product configuration.
+///
+/// # Panics
+///
+/// Panics if it was already initialized with different users.
 pub fn init(api_user: nix::unistd::User, priv_user: nix::unistd::User) {
-    unsafe {
-        PRODUCT_CONFIG = Some(ProxmoxProductConfig {
-            api_user,
-            priv_user,
-        });
+    let config = ProxmoxProductConfig {
+        api_user,
+        priv_user,
+    };
+
+    if let Err(config) = PRODUCT_CONFIG.set(config) {
+        if PRODUCT_CONFIG.get() != Some(&config) {
+            panic!("ProxmoxProductConfig is already initialized with different users!");
+        }
     }
 }
 
+/// Returns whether the global product configuration was initialized with [init].
+pub fn is_initialized() -> bool {
+    PRODUCT_CONFIG.get().is_some()
+}
+
+fn product_config() -> &'static ProxmoxProductConfig {
+    PRODUCT_CONFIG
+        .get()
+        .expect("ProxmoxProductConfig is not initialized!")
+}
+
 /// Returns the global api user set with [init].
 ///
 /// # Panics
 ///
 /// Panics if [init] wasn't called before.
 pub fn get_api_user() -> &'static nix::unistd::User {
-    unsafe {
-        &PRODUCT_CONFIG
-            .as_ref()
-            .expect("ProxmoxProductConfig is not initialized!")
-            .api_user
-    }
+    &product_config().api_user
 }
 
 // Returns the global priviledged user set with [init].
@@ -35,10 +52,5 @@ pub fn get_api_user() -> &'static nix::unistd::User {
 ///
 /// Panics if [init] wasn't called before.
 pub fn get_priv_user() -> &'static nix::unistd::User {
-    unsafe {
-        &PRODUCT_CONFIG
-            .as_ref()
-            .expect("ProxmoxProductConfig is not initialized!")
-            .priv_user
-    }
+    &product_config().priv_user
 }
@@ -3,3 +3,9 @@ pub use filesystem_helpers::*;
 
 mod init;
 pub use init::*;
+
+mod migration;
+pub use migration::*;
+
+mod registry;
+pub use registry::*;
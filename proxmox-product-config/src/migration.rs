@@ -0,0 +1,254 @@
+//! Versioned configuration files with ordered migrations.
+//!
+//! The format version is stored in a header line (`# version: <N>`) at the start of the file.
+//! Files without such a header are treated as version `0`. Migration `N` converts the file
+//! contents from version `N` to version `N + 1`, so the current version of a format equals the
+//! number of registered migrations.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_sys::fs::CreateOptions;
+
+/// Prefix of the version header line.
+const VERSION_HEADER_PREFIX: &str = "# version:";
+
+/// A migration function, converting the config contents to the next version.
+///
+/// The version header line is already stripped from the passed data.
+pub type ConfigMigrationFn = fn(&str) -> Result<String, Error>;
+
+/// Describes a versioned configuration file format.
+///
+/// ```
+/// # use anyhow::Error;
+/// use proxmox_product_config::VersionedConfig;
+///
+/// fn rename_option(data: &str) -> Result<String, Error> {
+///     Ok(data.replace("old-name:", "new-name:"))
+/// }
+///
+/// static EXAMPLE_CONFIG: VersionedConfig =
+///     VersionedConfig::new("example", "/etc/example.cfg", &[rename_option]);
+///
+/// let (data, migrated) = EXAMPLE_CONFIG.migrate("old-name: 1\n").unwrap();
+/// assert_eq!(data, "new-name: 1\n");
+/// assert!(migrated);
+/// ```
+pub struct VersionedConfig {
+    name: &'static str,
+    path: &'static str,
+    migrations: &'static [ConfigMigrationFn],
+}
+
+/// The registered config formats, see [register_versioned_config].
+static VERSIONED_CONFIGS: Mutex<Vec<&'static VersionedConfig>> = Mutex::new(Vec::new());
+
+impl VersionedConfig {
+    /// Create a new config format description. `migrations` must be ordered by version.
+    pub const fn new(
+        name: &'static str,
+        path: &'static str,
+        migrations: &'static [ConfigMigrationFn],
+    ) -> Self {
+        Self {
+            name,
+            path,
+            migrations,
+        }
+    }
+
+    /// The name of the config format.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The path of the config file.
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    /// The current version of the format.
+    pub fn current_version(&self) -> usize {
+        self.migrations.len()
+    }
+
+    /// Migrate the config contents to the current version.
+    ///
+    /// Returns the contents without the version header, and whether any migration was applied.
+    pub fn migrate(&self, data: &str) -> Result<(String, bool), Error> {
+        let (version, data) = parse_version_header(data)?;
+
+        if version > self.current_version() {
+            bail!(
+                "config '{}' has version {version}, but only versions up to {} are supported",
+                self.name,
+                self.current_version(),
+            );
+        }
+
+        let mut data = data.to_string();
+        for (from, migration) in self.migrations.iter().enumerate().skip(version) {
+            data = migration(&data).map_err(|err| {
+                format_err!(
+                    "migrating config '{}' from version {from} to {} failed - {err}",
+                    self.name,
+                    from + 1
+                )
+            })?;
+        }
+
+        Ok((data, version < self.current_version()))
+    }
+
+    /// Prefix the config contents with the header for the current version.
+    pub fn format(&self, data: &str) -> String {
+        format!("{VERSION_HEADER_PREFIX} {}\n{data}", self.current_version())
+    }
+
+    /// Read the config file and migrate it to the current version.
+    ///
+    /// If `rewrite` is set and the file was migrated, the updated contents are atomically
+    /// written back with the given [CreateOptions]. Returns `None` if the file does not exist.
+    pub fn read(&self, rewrite: Option<CreateOptions>) -> Result<Option<String>, Error> {
+        let raw = match proxmox_sys::fs::file_read_optional_string(self.path)? {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+
+        let (data, migrated) = self.migrate(&raw)?;
+
+        if migrated {
+            if let Some(options) = rewrite {
+                self.write_with_options(&data, options)?;
+                log::info!(
+                    "migrated config '{}' to version {}",
+                    self.path,
+                    self.current_version()
+                );
+            }
+        }
+
+        Ok(Some(data))
+    }
+
+    /// Atomically write the config contents, including the version header, to the config file.
+    pub fn write_with_options(&self, data: &str, options: CreateOptions) -> Result<(), Error> {
+        proxmox_sys::fs::replace_file(
+            Path::new(self.path),
+            self.format(data).as_bytes(),
+            options,
+            true,
+        )
+    }
+}
+
+/// Split off the version header line, returning the version and the remaining data.
+fn parse_version_header(data: &str) -> Result<(usize, &str), Error> {
+    let (first, rest) = match data.split_once('\n') {
+        Some((first, rest)) => (first, rest),
+        None => (data, ""),
+    };
+
+    match first.strip_prefix(VERSION_HEADER_PREFIX) {
+        Some(version) => {
+            let version = version.trim().parse().map_err(|err| {
+                format_err!("invalid config version '{}' - {err}", version.trim())
+            })?;
+            Ok((version, rest))
+        }
+        None => Ok((0, data)),
+    }
+}
+
+/// Register a versioned config format, so that it can be migrated via [migrate_all_configs].
+///
+/// Registering the same format twice has no effect.
+pub fn register_versioned_config(config: &'static VersionedConfig) -> Result<(), Error> {
+    let mut configs = VERSIONED_CONFIGS.lock().unwrap();
+
+    if let Some(existing) = configs.iter().find(|c| c.name == config.name) {
+        if std::ptr::eq(*existing, config) {
+            return Ok(());
+        }
+        bail!("config format '{}' already registered", config.name);
+    }
+
+    configs.push(config);
+
+    Ok(())
+}
+
+/// Returns all registered config formats.
+pub fn registered_versioned_configs() -> Vec<&'static VersionedConfig> {
+    VERSIONED_CONFIGS.lock().unwrap().clone()
+}
+
+/// Migrate and rewrite all registered config files, e.g. on package upgrade.
+///
+/// `options` returns the [CreateOptions] used to write the migrated file of a format. All files
+/// are processed, the errors are collected and returned together.
+pub fn migrate_all_configs(
+    options: impl Fn(&VersionedConfig) -> CreateOptions,
+) -> Result<(), Error> {
+    let mut errors = Vec::new();
+
+    for config in registered_versioned_configs() {
+        if let Err(err) = config.read(Some(options(config))) {
+            errors.push(format!("{}: {err}", config.path));
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("config migration failed:\n{}", errors.join("\n"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn add_comment(data: &str) -> Result<String, Error> {
+        Ok(format!("# migrated\n{data}"))
+    }
+
+    fn rename_key(data: &str) -> Result<String, Error> {
+        Ok(data.replace("foo:", "bar:"))
+    }
+
+    static TEST_CONFIG: VersionedConfig =
+        VersionedConfig::new("test", "/nonexistent/test.cfg", &[add_comment, rename_key]);
+
+    #[test]
+    fn test_migrate() {
+        assert_eq!(TEST_CONFIG.current_version(), 2);
+
+        let (data, migrated) = TEST_CONFIG.migrate("foo: 1\n").unwrap();
+        assert_eq!(data, "# migrated\nbar: 1\n");
+        assert!(migrated);
+
+        let (data, migrated) = TEST_CONFIG.migrate("# version: 1\nfoo: 1\n").unwrap();
+        assert_eq!(data, "bar: 1\n");
+        assert!(migrated);
+
+        let (data, migrated) = TEST_CONFIG
+            .migrate(&TEST_CONFIG.format("foo: 1\n"))
+            .unwrap();
+        assert_eq!(data, "foo: 1\n");
+        assert!(!migrated);
+
+        assert!(TEST_CONFIG.migrate("# version: 3\n").is_err());
+        assert!(TEST_CONFIG.migrate("# version: x\n").is_err());
+    }
+
+    #[test]
+    fn test_register() {
+        register_versioned_config(&TEST_CONFIG).unwrap();
+        register_versioned_config(&TEST_CONFIG).unwrap();
+        assert_eq!(registered_versioned_configs().len(), 1);
+    }
+}
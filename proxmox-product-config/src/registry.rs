@@ -0,0 +1,444 @@
+//! Registry of configuration files with cached, typed access.
+//!
+//! Products register each of their config files once, with a closure parsing its contents into
+//! a typed config and optionally one writing it back. [read_config] returns the parsed config
+//! together with the digest of the file contents and caches it until the file changes, which is
+//! detected via `inotify(7)` on the file's directory. [modify_config] edits the file while
+//! holding its lock file and verifies the digest a client read before, see [ConfigFileEditor].
+//!
+//! Note that `inotify(7)` does not work on every file system (e.g. for changes made on other
+//! nodes of a cluster file system). Configs in directories which cannot be watched are simply
+//! read and parsed on every access.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, format_err, Error};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+
+use proxmox_sys::fs::{config_digest, ConfigDigest, ConfigFileEditor, CreateOptions};
+
+use super::default_create_options;
+
+type AnyConfig = Box<dyn Any + Send + Sync>;
+type ParseFn = Box<dyn Fn(&str) -> Result<AnyConfig, Error> + Send + Sync>;
+type WriteFn = Box<dyn Fn(&(dyn Any + Send + Sync)) -> Result<String, Error> + Send + Sync>;
+
+struct CachedConfig {
+    data: Arc<dyn Any + Send + Sync>,
+    digest: ConfigDigest,
+}
+
+struct ConfigEntry {
+    name: String,
+    path: PathBuf,
+    parse: ParseFn,
+    write: Option<WriteFn>,
+    options: Option<CreateOptions>,
+    backups: usize,
+    watched: AtomicBool,
+    cache: Mutex<Option<CachedConfig>>,
+}
+
+impl ConfigEntry {
+    fn invalidate(&self) {
+        *self.cache.lock().unwrap() = None;
+    }
+}
+
+struct Watcher {
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+}
+
+#[derive(Default)]
+struct Registry {
+    entries: HashMap<String, Arc<ConfigEntry>>,
+    watcher: Option<Watcher>,
+}
+
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+const WATCH_FLAGS: AddWatchFlags = AddWatchFlags::IN_CLOSE_WRITE
+    .union(AddWatchFlags::IN_MOVED_TO)
+    .union(AddWatchFlags::IN_MOVED_FROM)
+    .union(AddWatchFlags::IN_DELETE)
+    .union(AddWatchFlags::IN_CREATE);
+
+impl Registry {
+    /// Watch the directory containing `path`, returns whether changes will be noticed.
+    fn watch(&mut self, path: &Path) -> bool {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        if self.watcher.is_none() {
+            match Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC) {
+                Ok(inotify) => {
+                    self.watcher = Some(Watcher {
+                        inotify,
+                        dirs: HashMap::new(),
+                    })
+                }
+                Err(err) => {
+                    log::warn!("unable to initialize inotify, not caching configs - {err}");
+                    return false;
+                }
+            }
+        }
+        let watcher = self.watcher.as_mut().unwrap();
+
+        if watcher.dirs.values().any(|watched| watched == dir) {
+            return true;
+        }
+
+        match watcher.inotify.add_watch(dir, WATCH_FLAGS) {
+            Ok(wd) => {
+                watcher.dirs.insert(wd, dir.to_path_buf());
+                true
+            }
+            Err(err) => {
+                log::info!("unable to watch {dir:?}, not caching configs in it - {err}");
+                false
+            }
+        }
+    }
+
+    /// Invalidate the cache of all configs whose files changed since the last call.
+    fn process_events(&mut self) {
+        let watcher = match self.watcher.as_mut() {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        loop {
+            let events = match watcher.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => return,
+                Err(err) => {
+                    log::warn!("reading inotify events failed - {err}");
+                    self.entries.values().for_each(|entry| entry.invalidate());
+                    return;
+                }
+            };
+
+            for event in events {
+                if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                    self.entries.values().for_each(|entry| entry.invalidate());
+                    continue;
+                }
+
+                let dir = match watcher.dirs.get(&event.wd) {
+                    Some(dir) => dir,
+                    None => continue,
+                };
+
+                if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                    // the directory is gone, we won't get any events for it anymore
+                    for entry in self.entries.values() {
+                        if entry.path.parent() == Some(dir) {
+                            entry.watched.store(false, Ordering::SeqCst);
+                            entry.invalidate();
+                        }
+                    }
+                    watcher.dirs.remove(&event.wd);
+                    continue;
+                }
+
+                if let Some(name) = event.name {
+                    let path = dir.join(name);
+                    for entry in self.entries.values() {
+                        if entry.path == path {
+                            entry.invalidate();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Describes a config file to register, see [register](ConfigRegistration::register).
+///
+/// ```no_run
+/// # use anyhow::Error;
+/// use proxmox_product_config::{read_config, ConfigRegistration};
+///
+/// ConfigRegistration::new("domains", "/etc/example/domains.cfg", |data| {
+///     Ok(data.lines().map(String::from).collect::<Vec<String>>())
+/// })
+/// .writer(|domains| Ok(domains.join("\n")))
+/// .register()?;
+///
+/// let (domains, digest) = read_config::<Vec<String>>("domains")?;
+/// # Ok::<(), Error>(())
+/// ```
+pub struct ConfigRegistration<T> {
+    name: String,
+    path: PathBuf,
+    parse: ParseFn,
+    write: Option<WriteFn>,
+    options: Option<CreateOptions>,
+    backups: usize,
+    _marker: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: Any + Send + Sync> ConfigRegistration<T> {
+    /// Describe the config `name`, stored at `path` and parsed with `parse`.
+    ///
+    /// Files which do not exist are parsed as empty string.
+    pub fn new<P, F>(name: &str, path: P, parse: F) -> Self
+    where
+        P: Into<PathBuf>,
+        F: Fn(&str) -> Result<T, Error> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            path: path.into(),
+            parse: Box::new(move |data| Ok(Box::new(parse(data)?) as AnyConfig)),
+            write: None,
+            options: None,
+            backups: 0,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Set the function used to write the config, required for [modify_config].
+    pub fn writer<F>(mut self, write: F) -> Self
+    where
+        F: Fn(&T) -> Result<String, Error> + Send + Sync + 'static,
+    {
+        self.write = Some(Box::new(move |data| match data.downcast_ref::<T>() {
+            Some(data) => write(data),
+            None => bail!("config has unexpected type"),
+        }));
+        self
+    }
+
+    /// Set the [CreateOptions] used if the file and its lock file do not exist yet.
+    ///
+    /// Defaults to [default_create_options].
+    pub fn create_options(mut self, options: CreateOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Keep the given number of backups of previous contents when modifying (default: 0).
+    pub fn backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Register the config. Fails if a config with the same name is already registered.
+    pub fn register(self) -> Result<(), Error> {
+        let mut registry = REGISTRY.lock().unwrap();
+        let registry = registry.get_or_insert_with(Registry::default);
+
+        if registry.entries.contains_key(&self.name) {
+            bail!("config '{}' already registered", self.name);
+        }
+
+        let watched = registry.watch(&self.path);
+
+        registry.entries.insert(
+            self.name.clone(),
+            Arc::new(ConfigEntry {
+                name: self.name,
+                path: self.path,
+                parse: self.parse,
+                write: self.write,
+                options: self.options,
+                backups: self.backups,
+                watched: AtomicBool::new(watched),
+                cache: Mutex::new(None),
+            }),
+        );
+
+        Ok(())
+    }
+}
+
+/// Look up a registered config, after invalidating the caches of changed files.
+fn lookup_entry(name: &str) -> Result<Arc<ConfigEntry>, Error> {
+    let mut registry = REGISTRY.lock().unwrap();
+    let registry = registry
+        .as_mut()
+        .ok_or_else(|| format_err!("config '{name}' is not registered"))?;
+
+    registry.process_events();
+
+    registry
+        .entries
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format_err!("config '{name}' is not registered"))
+}
+
+fn downcast<T: Any + Send + Sync>(
+    entry: &ConfigEntry,
+    data: Arc<dyn Any + Send + Sync>,
+) -> Result<Arc<T>, Error> {
+    data.downcast::<T>()
+        .map_err(|_| format_err!("config '{}' has a different type", entry.name))
+}
+
+/// Returns the names of all registered configs.
+pub fn registered_configs() -> Vec<String> {
+    let registry = REGISTRY.lock().unwrap();
+    let mut names: Vec<String> = registry
+        .iter()
+        .flat_map(|registry| registry.entries.keys().cloned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Read a registered config, together with the digest of the file contents.
+///
+/// The parsed config is cached until the file changes.
+pub fn read_config<T: Any + Send + Sync>(name: &str) -> Result<(Arc<T>, ConfigDigest), Error> {
+    let entry = lookup_entry(name)?;
+
+    let mut cache = entry.cache.lock().unwrap();
+    if let Some(cached) = cache.as_ref() {
+        return Ok((downcast(&entry, cached.data.clone())?, cached.digest));
+    }
+
+    let raw = proxmox_sys::fs::file_read_optional_string(&entry.path)?.unwrap_or_default();
+    let digest = config_digest(raw.as_bytes());
+    let data: Arc<dyn Any + Send + Sync> =
+        Arc::from((entry.parse)(&raw).map_err(|err| {
+            format_err!("unable to parse config '{}' - {err}", entry.path.display())
+        })?);
+
+    if entry.watched.load(Ordering::SeqCst) {
+        *cache = Some(CachedConfig {
+            data: data.clone(),
+            digest,
+        });
+    }
+
+    Ok((downcast(&entry, data)?, digest))
+}
+
+/// Modify a registered config while holding its lock file.
+///
+/// If `digest` is set, this fails if the file changed since it was read with this digest.
+/// `modify` gets the current config, which is written back if it succeeds. Returns the result
+/// of `modify` and the digest of the new file contents.
+pub fn modify_config<T, R, F>(
+    name: &str,
+    digest: Option<ConfigDigest>,
+    modify: F,
+) -> Result<(R, ConfigDigest), Error>
+where
+    T: Any + Send + Sync,
+    F: FnOnce(&mut T) -> Result<R, Error>,
+{
+    let entry = lookup_entry(name)?;
+    let write = entry
+        .write
+        .as_ref()
+        .ok_or_else(|| format_err!("config '{name}' has no writer"))?;
+
+    let options = entry.options.clone().unwrap_or_else(default_create_options);
+
+    let mut cache = entry.cache.lock().unwrap();
+    let mut result = None;
+
+    let new_digest = ConfigFileEditor::new(&entry.path, options)
+        .digest(digest)
+        .backups(entry.backups)
+        .edit(|old| {
+            let old = std::str::from_utf8(old.unwrap_or_default())?;
+            let mut data = (entry.parse)(old)?
+                .downcast::<T>()
+                .map_err(|_| format_err!("config '{name}' has a different type"))?;
+
+            result = Some(modify(&mut data)?);
+            let new = write(&*data)?;
+
+            *cache = None;
+            Ok(new.into_bytes())
+        })?;
+
+    // the new contents are parsed again on the next read, as the writer might normalize them
+    drop(cache);
+
+    // `result` is always set if editing succeeded
+    Ok((result.unwrap(), new_digest))
+}
+
+/// Drop the cached contents of a registered config, e.g. if it was changed in a way which is not
+/// noticed via `inotify(7)`.
+pub fn invalidate_config(name: &str) -> Result<(), Error> {
+    lookup_entry(name)?.invalidate();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_registry() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!(
+            "proxmox-product-config-registry-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("lines.cfg");
+
+        let result = (|| {
+            ConfigRegistration::new("test-lines", &path, |data| {
+                Ok(data.lines().map(String::from).collect::<Vec<String>>())
+            })
+            .writer(|lines| Ok(lines.join("\n")))
+            .create_options(CreateOptions::new())
+            .register()?;
+            assert!(ConfigRegistration::new("test-lines", &path, |_| Ok(()))
+                .register()
+                .is_err());
+            assert!(registered_configs().contains(&"test-lines".to_string()));
+
+            let (lines, digest) = read_config::<Vec<String>>("test-lines")?;
+            assert!(lines.is_empty());
+            assert_eq!(digest, config_digest(b""));
+            assert!(read_config::<String>("test-lines").is_err());
+            assert!(read_config::<String>("test-unknown").is_err());
+
+            let (len, digest) = modify_config("test-lines", Some(digest), |lines: &mut Vec<_>| {
+                lines.push("one".to_string());
+                Ok(lines.len())
+            })?;
+            assert_eq!(len, 1);
+            assert_eq!(std::fs::read_to_string(&path)?, "one");
+
+            let (lines, read_digest) = read_config::<Vec<String>>("test-lines")?;
+            assert_eq!(*lines, ["one"]);
+            assert_eq!(read_digest, digest);
+
+            // changes by others are noticed
+            std::fs::write(dir.join(".tmp"), "one\ntwo")?;
+            std::fs::rename(dir.join(".tmp"), &path)?;
+            let (lines, _) = read_config::<Vec<String>>("test-lines")?;
+            assert_eq!(*lines, ["one", "two"]);
+
+            // outdated digest
+            assert!(
+                modify_config("test-lines", Some(digest), |_: &mut Vec<String>| Ok(())).is_err()
+            );
+
+            Ok(())
+        })();
+
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    }
+}
@@ -204,7 +204,10 @@ async fn run() -> Result<(), Error> {
             let incoming = hyper::server::conn::AddrIncoming::from_listener(listener)?;
 
             Ok(async move {
-                hyper::Server::builder(incoming).serve(rest_server).await?;
+                rest_server
+                    .server_builder(incoming)
+                    .serve(rest_server)
+                    .await?;
 
                 Ok(())
             })
@@ -3,8 +3,9 @@ use std::future::Future;
 use std::io;
 use std::path::PathBuf;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use anyhow::{format_err, Error};
 use http::{HeaderMap, Method, Uri};
@@ -15,20 +16,35 @@ use tower_service::Service;
 use proxmox_router::{Router, RpcEnvironmentType, UserInformation};
 use proxmox_sys::fs::{create_path, CreateOptions};
 
+use crate::auth_cache::AuthCache;
+use crate::cors::CorsConfig;
+use crate::middleware::Middleware;
+use crate::request_rate_limit::RequestRateLimiter;
 use crate::rest::Handler;
-use crate::{CommandSocket, FileLogOptions, FileLogger, RestEnvironment};
+use crate::{
+    AccessLogFormat, CommandSocket, ConnectionInfo, FileLogOptions, FileLogger, RestEnvironment,
+};
+
+/// Default limit for request bodies containing API call parameters, see
+/// [ApiConfig::max_request_body_size].
+pub const DEFAULT_MAX_REQUEST_BODY_SIZE: usize = 64 * 1024;
 
 /// REST server configuration
 pub struct ApiConfig {
     basedir: PathBuf,
-    aliases: HashMap<String, PathBuf>,
     env_type: RpcEnvironmentType,
     request_log: Option<Arc<Mutex<FileLogger>>>,
+    access_log_format: AccessLogFormat,
     auth_log: Option<Arc<Mutex<FileLogger>>>,
     handlers: Vec<Handler>,
     auth_handler: Option<AuthHandler>,
-    index_handler: Option<IndexHandler>,
-    pub(crate) privileged_addr: Option<PrivilegedAddr>,
+    auth_cache: Option<Arc<AuthCache>>,
+    request_rate_limiter: RequestRateLimiter,
+    middlewares: Vec<Middleware>,
+    reloadable: Arc<RwLock<ReloadableConfig>>,
+    pub(crate) max_request_body_size: usize,
+    pub(crate) header_read_timeout: Option<Duration>,
+    pub(crate) keep_alive_timeout: Option<Duration>,
 
     #[cfg(feature = "templates")]
     templates: templates::Templates,
@@ -50,14 +66,19 @@ impl ApiConfig {
     pub fn new<B: Into<PathBuf>>(basedir: B, env_type: RpcEnvironmentType) -> Self {
         Self {
             basedir: basedir.into(),
-            aliases: HashMap::new(),
             env_type,
             request_log: None,
+            access_log_format: AccessLogFormat::default(),
             auth_log: None,
             handlers: Vec::new(),
             auth_handler: None,
-            index_handler: None,
-            privileged_addr: None,
+            auth_cache: None,
+            request_rate_limiter: RequestRateLimiter::default(),
+            middlewares: Vec::new(),
+            reloadable: Arc::new(RwLock::new(ReloadableConfig::default())),
+            max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+            header_read_timeout: None,
+            keep_alive_timeout: None,
 
             #[cfg(feature = "templates")]
             templates: Default::default(),
@@ -79,14 +100,42 @@ impl ApiConfig {
     }
 
     /// This is used for `protected` API calls to proxy to a more privileged service.
-    pub fn privileged_addr(mut self, addr: impl Into<PrivilegedAddr>) -> Self {
-        self.privileged_addr = Some(addr.into());
+    pub fn privileged_addr(self, addr: impl Into<PrivilegedAddr>) -> Self {
+        self.reloadable.write().unwrap().privileged_addr = Some(addr.into());
+        self
+    }
+
+    pub(crate) fn get_privileged_addr(&self) -> Option<PrivilegedAddr> {
+        self.reloadable.read().unwrap().privileged_addr.clone()
+    }
+
+    /// Register a request middleware.
+    ///
+    /// Middlewares wrap the handling of every request (API calls, static files and the index
+    /// page). Their `before` hooks run in registration order, their `after` hooks in reverse
+    /// order, see [Middleware].
+    pub fn register_middleware(mut self, middleware: Middleware) -> Self {
+        self.middlewares.push(middleware);
         self
     }
 
+    /// Enable CORS handling, so that browser based clients on other origins can access the API.
+    ///
+    /// Preflight (`OPTIONS`) requests are answered directly, and the CORS headers are added to
+    /// the responses of requests from allowed origins. This runs before all other registered
+    /// middlewares.
+    pub fn cors(mut self, cors: CorsConfig) -> Self {
+        self.middlewares.insert(0, cors.into_middleware());
+        self
+    }
+
+    pub(crate) fn middlewares(&self) -> &[Middleware] {
+        &self.middlewares
+    }
+
     /// Set the index handler.
-    pub fn index_handler(mut self, index_handler: IndexHandler) -> Self {
-        self.index_handler = Some(index_handler);
+    pub fn index_handler(self, index_handler: IndexHandler) -> Self {
+        self.reloadable.write().unwrap().index_handler = Some(index_handler);
         self
     }
 
@@ -103,21 +152,37 @@ impl ApiConfig {
         rest_env: RestEnvironment,
         parts: Parts,
     ) -> Response<Body> {
-        match self.index_handler.as_ref() {
-            Some(handler) => (handler.func)(rest_env, parts).await,
-            None => Response::builder().status(404).body("".into()).unwrap(),
-        }
+        let index_future = match self.reloadable.read().unwrap().index_handler.as_ref() {
+            Some(handler) => (handler.func)(rest_env, parts),
+            None => return Response::builder().status(404).body("".into()).unwrap(),
+        };
+        index_future.await
     }
 
     pub(crate) async fn check_auth(
         &self,
-        headers: &HeaderMap,
-        method: &Method,
+        parts: &Parts,
     ) -> Result<(String, Box<dyn UserInformation + Sync + Send>), AuthError> {
-        match self.auth_handler.as_ref() {
-            Some(handler) => (handler.func)(headers, method).await,
-            None => Err(AuthError::NoData),
+        let (headers, method) = (&parts.headers, &parts.method);
+
+        let handler = match self.auth_handler.as_ref() {
+            Some(handler) => handler,
+            None => return Err(AuthError::NoData),
+        };
+
+        let cache = match self.auth_cache.as_ref() {
+            Some(cache) if !handler.uses_connection() => cache,
+            _ => return handler.check(parts).await,
+        };
+
+        if let Some(cached) = cache.lookup(headers, method) {
+            return Ok(cached);
         }
+
+        let (auth_id, user_info) = handler.check(parts).await?;
+        let user_info = cache.insert(headers, method, auth_id.clone(), user_info);
+
+        Ok((auth_id, user_info))
     }
 
     pub(crate) fn find_alias(&self, mut components: &[&str]) -> PathBuf {
@@ -126,7 +191,7 @@ impl ApiConfig {
             return filename;
         }
 
-        if let Some(subdir) = self.aliases.get(components[0]) {
+        if let Some(subdir) = self.reloadable.read().unwrap().aliases.get(components[0]) {
             filename.push(subdir);
             components = &components[1..];
         }
@@ -148,27 +213,60 @@ impl ApiConfig {
     /// config.alias("extjs", "/usr/share/javascript/extjs");
     /// # }
     /// ```
-    pub fn alias<S, P>(mut self, alias: S, path: P) -> Self
+    pub fn alias<S, P>(self, alias: S, path: P) -> Self
     where
         S: Into<String>,
         P: Into<PathBuf>,
     {
-        self.aliases.insert(alias.into(), path.into());
+        self.reloadable
+            .write()
+            .unwrap()
+            .aliases
+            .insert(alias.into(), path.into());
         self
     }
 
     /// Register multiple path aliases. See `[ApiConfig::alias()]`.
-    pub fn aliases<I, S, P>(mut self, aliases: I) -> Self
+    pub fn aliases<I, S, P>(self, aliases: I) -> Self
     where
         I: IntoIterator<Item = (S, P)>,
         S: Into<String>,
         P: Into<PathBuf>,
     {
-        self.aliases
+        self.reloadable
+            .write()
+            .unwrap()
+            .aliases
             .extend(aliases.into_iter().map(|(s, p)| (s.into(), p.into())));
         self
     }
 
+    /// Enable reloading parts of the configuration at runtime.
+    ///
+    /// This registers a `reload-config` command on the [CommandSocket], which calls `func` and
+    /// replaces the path aliases, the privileged address and the index handler with the
+    /// returned [ReloadableConfig]. Templates registered with `register_template` are reloaded
+    /// automatically when they change on disk, so index handlers using them pick up changes
+    /// without this.
+    pub fn enable_config_reload<F>(
+        self,
+        commando_sock: &mut CommandSocket,
+        func: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn() -> Result<ReloadableConfig, Error> + Send + Sync + 'static,
+    {
+        let reloadable = Arc::clone(&self.reloadable);
+        commando_sock.register_command("reload-config".into(), move |_args| {
+            log::info!("reloading config");
+            let config = func()?;
+            *reloadable.write().unwrap() = config;
+            Ok(serde_json::Value::Null)
+        })?;
+
+        Ok(self)
+    }
+
     pub(crate) fn env_type(&self) -> RpcEnvironmentType {
         self.env_type
     }
@@ -220,6 +318,7 @@ impl ApiConfig {
         let logger_options = FileLogOptions {
             append: true,
             file_opts: file_opts.unwrap_or_default(),
+            access_log_format: self.access_log_format,
             ..Default::default()
         };
         let request_log = Arc::new(Mutex::new(FileLogger::new(&path, logger_options)?));
@@ -234,6 +333,15 @@ impl ApiConfig {
         Ok(self)
     }
 
+    /// Set the format of the access log, defaults to [AccessLogFormat::Native].
+    pub fn access_log_format(mut self, format: AccessLogFormat) -> Self {
+        self.access_log_format = format;
+        if let Some(request_log) = &self.request_log {
+            request_log.lock().unwrap().set_access_log_format(format);
+        }
+        self
+    }
+
     /// Enable the authentication log feature
     ///
     /// When enabled, all authentication requests are logged to the
@@ -274,6 +382,114 @@ impl ApiConfig {
         Ok(self)
     }
 
+    /// Enable caching of successful authentication results
+    ///
+    /// Requests with the same credentials (cookie, authorization and CSRF prevention token
+    /// headers) are not passed to the authentication handler again for the duration of `ttl`.
+    /// Note that this also delays the effect of changed permissions, revoked tokens and
+    /// logouts by up to `ttl`.
+    ///
+    /// Results of handlers created via [AuthHandler::from_fn_with_connection] are never cached,
+    /// as they may depend on the connection (e.g. the TLS client certificate) and not only on
+    /// the request headers.
+    ///
+    /// This function also registers a `api-auth-cache-invalidate` command on the
+    /// [CommandSocket], which removes all cached results, or only those of the auth id passed
+    /// as `auth-id` parameter (including the API tokens of a user).
+    pub fn enable_auth_cache(
+        mut self,
+        ttl: Duration,
+        commando_sock: &mut CommandSocket,
+    ) -> Result<Self, Error> {
+        let auth_cache = Arc::new(AuthCache::new(ttl));
+        self.auth_cache = Some(Arc::clone(&auth_cache));
+
+        commando_sock.register_command("api-auth-cache-invalidate".into(), move |args| {
+            let auth_id = match args.and_then(|args| args.get("auth-id")) {
+                Some(auth_id) => Some(
+                    auth_id
+                        .as_str()
+                        .ok_or_else(|| format_err!("parameter 'auth-id' must be a string"))?,
+                ),
+                None => None,
+            };
+            let count = auth_cache.invalidate(auth_id);
+            log::info!("invalidated {count} cached authentication results");
+            Ok(serde_json::Value::from(count))
+        })?;
+
+        Ok(self)
+    }
+
+    /// Limit the request rate for API paths starting with `path_prefix`
+    ///
+    /// Each user may issue `rate` requests per second to the matching paths on average, with
+    /// bursts of up to `burst` requests (token bucket). Unauthenticated requests are limited
+    /// per peer address. Requests exceeding the limit get a `429 Too Many Requests` response
+    /// with a `Retry-After` header.
+    ///
+    /// `path_prefix` is matched against the API path without the `/api2/<format>` prefix,
+    /// e.g. `/admin/datastore`. If multiple rules match, the one with the longest prefix
+    /// applies, so `/` can be used to set a default. Adding a rule for the same prefix again
+    /// replaces it.
+    pub fn request_rate_limit(mut self, path_prefix: &str, rate: f64, burst: u64) -> Self {
+        self.request_rate_limiter.add_rule(path_prefix, rate, burst);
+        self
+    }
+
+    /// Set the maximum size of request bodies containing the parameters of API calls.
+    ///
+    /// Larger requests are rejected with `413 Payload Too Large`. This does not apply to API
+    /// methods reading the request body themselves (e.g. uploads). Defaults to
+    /// [DEFAULT_MAX_REQUEST_BODY_SIZE].
+    pub fn max_request_body_size(mut self, size: usize) -> Self {
+        self.max_request_body_size = size;
+        self
+    }
+
+    /// Close connections which do not transmit the request headers within `timeout`, counted
+    /// from the first byte of the request.
+    ///
+    /// Applied by [RestServer::server_builder](crate::RestServer::server_builder).
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.header_read_timeout = Some(timeout);
+        self
+    }
+
+    /// Close kept-alive connections which are idle for longer than `timeout`. A zero timeout
+    /// disables keep-alive.
+    ///
+    /// Applied by [RestServer::server_builder](crate::RestServer::server_builder).
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    pub(crate) fn keep_alive_disabled(&self) -> bool {
+        self.keep_alive_timeout == Some(Duration::ZERO)
+    }
+
+    /// Account a request to the API path `path`, see [Self::request_rate_limit].
+    ///
+    /// Returns the time to wait before retrying if the limit is exceeded.
+    pub(crate) fn check_request_rate_limit(
+        &self,
+        auth_id: Option<&str>,
+        peer: &std::net::SocketAddr,
+        path: &str,
+    ) -> Result<(), Duration> {
+        if !self.request_rate_limiter.is_enabled() {
+            return Ok(());
+        }
+
+        match auth_id {
+            Some(auth_id) => self.request_rate_limiter.check(auth_id, path),
+            None => self
+                .request_rate_limiter
+                .check(&peer.ip().to_string(), path),
+        }
+    }
+
     pub(crate) fn get_access_log(&self) -> Option<&Arc<Mutex<FileLogger>>> {
         self.request_log.as_ref()
     }
@@ -396,6 +612,18 @@ mod templates {
     }
 }
 
+/// The part of the [ApiConfig] which can be replaced at runtime, see
+/// [ApiConfig::enable_config_reload].
+#[derive(Default)]
+pub struct ReloadableConfig {
+    /// Path aliases, see [ApiConfig::alias].
+    pub aliases: HashMap<String, PathBuf>,
+    /// See [ApiConfig::privileged_addr].
+    pub privileged_addr: Option<PrivilegedAddr>,
+    /// See [ApiConfig::index_handler].
+    pub index_handler: Option<IndexHandler>,
+}
+
 pub type IndexFuture = Pin<Box<dyn Future<Output = Response<Body>> + Send>>;
 pub type IndexFunc = Box<dyn Fn(RestEnvironment, Parts) -> IndexFuture + Send + Sync>;
 
@@ -433,13 +661,34 @@ pub type CheckAuthFuture<'a> = Pin<Box<dyn Future<Output = CheckAuthOutput> + Se
 pub type CheckAuthFunc =
     Box<dyn for<'a> Fn(&'a HeaderMap, &'a Method) -> CheckAuthFuture<'a> + Send + Sync>;
 
+pub type CheckAuthWithConnectionFunc = Box<
+    dyn for<'a> Fn(&'a HeaderMap, &'a Method, Option<&'a ConnectionInfo>) -> CheckAuthFuture<'a>
+        + Send
+        + Sync,
+>;
+
+enum AuthHandlerFunc {
+    Simple(CheckAuthFunc),
+    WithConnection(CheckAuthWithConnectionFunc),
+}
+
 pub struct AuthHandler {
-    func: CheckAuthFunc,
+    func: AuthHandlerFunc,
 }
 
 impl From<CheckAuthFunc> for AuthHandler {
     fn from(func: CheckAuthFunc) -> Self {
-        Self { func }
+        Self {
+            func: AuthHandlerFunc::Simple(func),
+        }
+    }
+}
+
+impl From<CheckAuthWithConnectionFunc> for AuthHandler {
+    fn from(func: CheckAuthWithConnectionFunc) -> Self {
+        Self {
+            func: AuthHandlerFunc::WithConnection(func),
+        }
     }
 }
 
@@ -450,6 +699,36 @@ impl AuthHandler {
     {
         Self::from(Box::new(func) as CheckAuthFunc)
     }
+
+    /// Create an authentication handler which also gets the [ConnectionInfo] of the request,
+    /// e.g. to only accept certain credentials on TLS connections.
+    ///
+    /// Results of such handlers are not cached, see [ApiConfig::enable_auth_cache].
+    pub fn from_fn_with_connection<Func>(func: Func) -> Self
+    where
+        Func: for<'a> Fn(&'a HeaderMap, &'a Method, Option<&'a ConnectionInfo>) -> CheckAuthFuture<'a>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self::from(Box::new(func) as CheckAuthWithConnectionFunc)
+    }
+
+    /// Whether the result may depend on the connection and not only on the request.
+    fn uses_connection(&self) -> bool {
+        matches!(self.func, AuthHandlerFunc::WithConnection(_))
+    }
+
+    fn check<'a>(&'a self, parts: &'a Parts) -> CheckAuthFuture<'a> {
+        match &self.func {
+            AuthHandlerFunc::Simple(func) => func(&parts.headers, &parts.method),
+            AuthHandlerFunc::WithConnection(func) => func(
+                &parts.headers,
+                &parts.method,
+                parts.extensions.get::<ConnectionInfo>(),
+            ),
+        }
+    }
 }
 
 /// Authentication Error
@@ -616,3 +895,98 @@ impl hyper::server::accept::Accept for UnixAcceptor {
             })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use anyhow::{bail, Error};
+    use hyper::body::HttpBody;
+    use nix::unistd::Gid;
+    use serde_json::json;
+
+    use proxmox_router::RpcEnvironmentType;
+
+    use super::{ApiConfig, IndexHandler, PrivilegedAddr, ReloadableConfig};
+    use crate::{CommandSocket, RestEnvironment};
+
+    fn index_body(config: &Arc<ApiConfig>) -> Result<String, Error> {
+        let rest_env = RestEnvironment::new(RpcEnvironmentType::PUBLIC, Arc::clone(config));
+        let (parts, _) = http::Request::new(()).into_parts();
+
+        futures::executor::block_on(async {
+            let mut response = config.get_index(rest_env, parts).await;
+            let mut body = Vec::new();
+            while let Some(chunk) = response.body_mut().data().await {
+                body.extend_from_slice(&chunk?);
+            }
+            Ok(String::from_utf8(body)?)
+        })
+    }
+
+    #[test]
+    fn test_config_reload() -> Result<(), Error> {
+        let mut socket = CommandSocket::new("/invalid", Gid::current());
+
+        let config = ApiConfig::new("/usr/share/test", RpcEnvironmentType::PUBLIC)
+            .alias("js", "/usr/share/javascript")
+            .index_handler(IndexHandler::new_static_body("old index"))
+            .enable_config_reload(&mut socket, || {
+                Ok(ReloadableConfig {
+                    aliases: [("js".to_string(), PathBuf::from("/opt/javascript"))].into(),
+                    privileged_addr: Some(PrivilegedAddr::Tcp(([127, 0, 0, 1], 82).into())),
+                    index_handler: Some(IndexHandler::new_static_body("new index")),
+                })
+            })?;
+        let config = Arc::new(config);
+
+        assert_eq!(
+            config.find_alias(&["js", "app.js"]),
+            PathBuf::from("/usr/share/javascript/app.js"),
+        );
+        assert!(config.get_privileged_addr().is_none());
+        assert_eq!(index_body(&config)?, "old index");
+
+        socket.handle_command(json!({ "command": "reload-config" }))?;
+
+        assert_eq!(
+            config.find_alias(&["js", "app.js"]),
+            PathBuf::from("/opt/javascript/app.js"),
+        );
+        assert!(matches!(
+            config.get_privileged_addr(),
+            Some(PrivilegedAddr::Tcp(addr)) if addr.port() == 82
+        ));
+        assert_eq!(index_body(&config)?, "new index");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_reload_failure() -> Result<(), Error> {
+        let mut socket = CommandSocket::new("/invalid", Gid::current());
+
+        let config = ApiConfig::new("/usr/share/test", RpcEnvironmentType::PUBLIC)
+            .alias("js", "/usr/share/javascript")
+            .enable_config_reload(&mut socket, || bail!("broken config"))?;
+        let config = Arc::new(config);
+
+        assert!(socket
+            .handle_command(json!({ "command": "reload-config" }))
+            .is_err());
+
+        // the previous configuration stays active
+        assert_eq!(
+            config.find_alias(&["js", "app.js"]),
+            PathBuf::from("/usr/share/javascript/app.js"),
+        );
+        assert_eq!(
+            index_body(&config)?,
+            "",
+            "no index handler should result in an empty 404 response"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+//! Cache for successful authentication results.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use http::{HeaderMap, Method};
+
+use proxmox_router::UserInformation;
+
+/// Upper limit for the number of cached credentials.
+const MAX_ENTRIES: usize = 4096;
+
+/// Headers which contain credentials checked by the authentication handler.
+const CREDENTIAL_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "csrfpreventiontoken",
+    crate::CLIENT_CERT_SUBJECT_HEADER,
+];
+
+type SharedUserInformation = Arc<dyn UserInformation + Send + Sync>;
+
+struct CachedAuth {
+    auth_id: String,
+    user_info: SharedUserInformation,
+    expire: Instant,
+}
+
+/// Wrapper to hand out the cached user information to multiple requests.
+struct CachedUserInformation(SharedUserInformation);
+
+impl UserInformation for CachedUserInformation {
+    fn is_superuser(&self, userid: &str) -> bool {
+        self.0.is_superuser(userid)
+    }
+
+    fn is_group_member(&self, userid: &str, group: &str) -> bool {
+        self.0.is_group_member(userid, group)
+    }
+
+    fn lookup_privs(&self, userid: &str, path: &[&str]) -> u64 {
+        self.0.lookup_privs(userid, path)
+    }
+}
+
+/// Caches successful authentication results keyed on a hash of the credential headers.
+///
+/// Failed authentication attempts are never cached.
+pub(crate) struct AuthCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<[u8; 32], CachedAuth>>,
+}
+
+impl AuthCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cache key, or `None` if the request does not contain any credentials.
+    fn key(headers: &HeaderMap, method: &Method) -> Option<[u8; 32]> {
+        let mut hasher = openssl::sha::Sha256::new();
+        let mut found = false;
+
+        for name in CREDENTIAL_HEADERS {
+            hasher.update(name.as_bytes());
+            for value in headers.get_all(*name) {
+                found = true;
+                hasher.update(b"\0");
+                hasher.update(value.as_bytes());
+            }
+            hasher.update(b"\n");
+        }
+
+        if !found {
+            return None;
+        }
+
+        // e.g. the CSRF token is only checked for non-GET requests
+        hasher.update(method.as_str().as_bytes());
+
+        Some(hasher.finish())
+    }
+
+    pub fn lookup(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+    ) -> Option<(String, Box<dyn UserInformation + Send + Sync>)> {
+        let key = Self::key(headers, method)?;
+
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(&key)?;
+
+        if entry.expire <= Instant::now() {
+            entries.remove(&key);
+            return None;
+        }
+
+        Some((
+            entry.auth_id.clone(),
+            Box::new(CachedUserInformation(Arc::clone(&entry.user_info))),
+        ))
+    }
+
+    /// Store a successful authentication result, returning the user information to use for
+    /// the current request.
+    pub fn insert(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+        auth_id: String,
+        user_info: Box<dyn UserInformation + Send + Sync>,
+    ) -> Box<dyn UserInformation + Send + Sync> {
+        let key = match Self::key(headers, method) {
+            Some(key) => key,
+            None => return user_info,
+        };
+
+        let user_info: SharedUserInformation = Arc::from(user_info);
+        let now = Instant::now();
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expire > now);
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+        }
+
+        entries.insert(
+            key,
+            CachedAuth {
+                auth_id,
+                user_info: Arc::clone(&user_info),
+                expire: now + self.ttl,
+            },
+        );
+
+        Box::new(CachedUserInformation(user_info))
+    }
+
+    /// Remove the cached results of an auth id, or all of them if `auth_id` is `None`.
+    ///
+    /// Returns the number of removed entries.
+    pub fn invalidate(&self, auth_id: Option<&str>) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let count = entries.len();
+
+        match auth_id {
+            Some(auth_id) => entries.retain(|_, entry| {
+                entry.auth_id != auth_id
+                    && entry.auth_id.split_once('!').map(|(user, _)| user) != Some(auth_id)
+            }),
+            None => entries.clear(),
+        }
+
+        count - entries.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct DummyUserInfo;
+
+    impl UserInformation for DummyUserInfo {
+        fn is_superuser(&self, userid: &str) -> bool {
+            userid == "root@pam"
+        }
+        fn is_group_member(&self, _userid: &str, _group: &str) -> bool {
+            false
+        }
+        fn lookup_privs(&self, _userid: &str, _path: &[&str]) -> u64 {
+            0
+        }
+    }
+
+    fn headers(cookie: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", cookie.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_auth_cache() {
+        let cache = AuthCache::new(Duration::from_secs(60));
+
+        let root = headers("AuthCookie=root");
+        let token = headers("AuthCookie=token");
+
+        assert!(cache.lookup(&root, &Method::GET).is_none());
+        assert!(cache.lookup(&HeaderMap::new(), &Method::GET).is_none());
+
+        cache.insert(
+            &root,
+            &Method::GET,
+            "root@pam".into(),
+            Box::new(DummyUserInfo),
+        );
+        cache.insert(
+            &token,
+            &Method::GET,
+            "root@pam!token".into(),
+            Box::new(DummyUserInfo),
+        );
+
+        let (auth_id, user_info) = cache.lookup(&root, &Method::GET).unwrap();
+        assert_eq!(auth_id, "root@pam");
+        assert!(user_info.is_superuser("root@pam"));
+        assert!(cache.lookup(&root, &Method::POST).is_none());
+
+        assert_eq!(cache.invalidate(Some("user@pam")), 0);
+        assert_eq!(cache.invalidate(Some("root@pam")), 2);
+        assert!(cache.lookup(&token, &Method::GET).is_none());
+
+        let cache = AuthCache::new(Duration::ZERO);
+        cache.insert(
+            &root,
+            &Method::GET,
+            "root@pam".into(),
+            Box::new(DummyUserInfo),
+        );
+        assert!(cache.lookup(&root, &Method::GET).is_none());
+    }
+}
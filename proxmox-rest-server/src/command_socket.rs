@@ -8,10 +8,13 @@ use std::sync::Arc;
 use futures::*;
 use nix::sys::socket;
 use nix::unistd::Gid;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{json, Value};
 use tokio::net::UnixListener;
 
+use proxmox_schema::ApiType;
+
 // Listens on a Unix Socket to handle simple command asynchronously
 fn create_control_socket<P, F>(
     path: P,
@@ -195,36 +198,36 @@ impl CommandSocket {
     /// Spawn the socket and consume self, meaning you cannot register commands anymore after
     /// calling this.
     pub fn spawn(self) -> Result<(), Error> {
+        let socket = self.socket.to_owned();
+        let gid = self.gid;
         let control_future =
-            create_control_socket(self.socket.to_owned(), self.gid, move |param| {
-                let param = param.as_object().ok_or_else(|| {
-                    format_err!("unable to parse parameters (expected json object)")
-                })?;
-
-                let command = match param.get("command") {
-                    Some(Value::String(command)) => command.as_str(),
-                    None => bail!("no command"),
-                    _ => bail!("unable to parse command"),
-                };
-
-                if !self.commands.contains_key(command) {
-                    bail!("got unknown command '{}'", command);
-                }
-
-                match self.commands.get(command) {
-                    None => bail!("got unknown command '{}'", command),
-                    Some(handler) => {
-                        let args = param.get("args"); //.unwrap_or(&Value::Null);
-                        (handler)(args)
-                    }
-                }
-            })?;
+            create_control_socket(socket, gid, move |param| self.handle_command(param))?;
 
         tokio::spawn(control_future);
 
         Ok(())
     }
 
+    pub(crate) fn handle_command(&self, param: Value) -> Result<Value, Error> {
+        let param = param
+            .as_object()
+            .ok_or_else(|| format_err!("unable to parse parameters (expected json object)"))?;
+
+        let command = match param.get("command") {
+            Some(Value::String(command)) => command.as_str(),
+            None => bail!("no command"),
+            _ => bail!("unable to parse command"),
+        };
+
+        match self.commands.get(command) {
+            None => bail!("got unknown command '{}'", command),
+            Some(handler) => {
+                let args = param.get("args"); //.unwrap_or(&Value::Null);
+                (handler)(args)
+            }
+        }
+    }
+
     /// Register a new command with a callback.
     pub fn register_command<F>(&mut self, command: String, handler: F) -> Result<(), Error>
     where
@@ -238,4 +241,115 @@ impl CommandSocket {
 
         Ok(())
     }
+
+    /// Register a new command with typed parameters and response.
+    ///
+    /// The arguments are verified against the API schema of `P` before being deserialized, a
+    /// missing `args` value is treated as empty object. Use [CommandSocketClient::call] to send
+    /// such commands.
+    pub fn register_typed_command<P, R, F>(
+        &mut self,
+        command: &str,
+        handler: F,
+    ) -> Result<(), Error>
+    where
+        P: ApiType + DeserializeOwned,
+        R: Serialize,
+        F: Fn(P) -> Result<R, Error> + Send + Sync + 'static,
+    {
+        let command_name = command.to_string();
+        self.register_command(command.to_string(), move |args| {
+            let args = args.cloned().unwrap_or_else(|| json!({}));
+            P::API_SCHEMA.verify_json(&args).map_err(|err| {
+                format_err!(
+                    "invalid parameters for command '{}' - {}",
+                    command_name,
+                    err
+                )
+            })?;
+            let response = handler(serde_json::from_value(args)?)?;
+            Ok(serde_json::to_value(response)?)
+        })
+    }
+}
+
+/// Client to send commands to a [CommandSocket], e.g. from CLI tools.
+pub struct CommandSocketClient {
+    path: PathBuf,
+}
+
+impl CommandSocketClient {
+    /// Create a client for the control socket at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Create a client for the control socket of the daemon with process ID `pid`.
+    pub fn for_pid(pid: i32) -> Self {
+        Self::new(crate::ctrl_sock_from_pid(pid))
+    }
+
+    /// Send `command` with arguments `args` and parse the response.
+    pub async fn call<P, R>(&self, command: &str, args: &P) -> Result<R, Error>
+    where
+        P: ?Sized + Serialize,
+        R: DeserializeOwned,
+    {
+        let response = send_command(
+            &self.path,
+            &json!({
+                "command": command,
+                "args": args,
+            }),
+        )
+        .await?;
+
+        serde_json::from_value(response)
+            .map_err(|err| format_err!("unable to parse response of '{}' - {}", command, err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use proxmox_schema::api;
+
+    use super::*;
+
+    #[api(
+        properties: {
+            repeat: { optional: true, minimum: 1 },
+        },
+    )]
+    #[derive(Deserialize)]
+    /// Test parameters.
+    struct EchoParams {
+        /// The text to echo.
+        text: String,
+        /// Repeat the text.
+        repeat: Option<u64>,
+    }
+
+    #[test]
+    fn test_typed_command() -> Result<(), Error> {
+        let mut socket = CommandSocket::new("/invalid", Gid::current());
+        socket.register_typed_command("echo", |params: EchoParams| {
+            Ok(params.text.repeat(params.repeat.unwrap_or(1) as usize))
+        })?;
+
+        let response = socket
+            .handle_command(json!({"command": "echo", "args": {"text": "a", "repeat": 3}}))?;
+        assert_eq!(response, "aaa");
+
+        assert!(socket
+            .handle_command(json!({"command": "echo", "args": {"text": "a", "repeat": 0}}))
+            .is_err());
+        assert!(socket.handle_command(json!({"command": "echo"})).is_err());
+        assert!(socket
+            .handle_command(json!({"command": "unknown"}))
+            .is_err());
+
+        Ok(())
+    }
 }
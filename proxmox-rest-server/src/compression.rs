@@ -1,39 +1,189 @@
 use anyhow::{bail, Error};
 use hyper::header;
 
+/// Responses smaller than this (in bytes) are not compressed, the savings would not be worth the
+/// overhead.
+pub const MIN_COMPRESSION_SIZE: u64 = 1024;
+
 /// Possible Compression Methods, order determines preference (later is preferred)
-#[derive(Eq, Ord, PartialEq, PartialOrd, Debug)]
+#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd, Debug)]
 pub enum CompressionMethod {
     Deflate,
     //    Gzip,
-    //    Brotli,
+    Brotli,
+    Zstd,
 }
 
 impl CompressionMethod {
+    const ALL: [CompressionMethod; 3] = [
+        CompressionMethod::Deflate,
+        CompressionMethod::Brotli,
+        CompressionMethod::Zstd,
+    ];
+
     pub fn content_encoding(&self) -> header::HeaderValue {
         header::HeaderValue::from_static(self.extension())
     }
 
     pub fn extension(&self) -> &'static str {
         match *self {
-            //            CompressionMethod::Brotli => "br",
+            CompressionMethod::Zstd => "zstd",
+            CompressionMethod::Brotli => "br",
             //            CompressionMethod::Gzip => "gzip",
             CompressionMethod::Deflate => "deflate",
         }
     }
+
+    /// Select the compression method to use from the value of an `Accept-Encoding` header.
+    ///
+    /// Takes the quality values (`;q=`) into account, the wildcard `*` matches all methods not
+    /// listed explicitly. Methods with equal quality are chosen by preference.
+    pub fn from_accept_encoding(accept_encoding: &str) -> Option<Self> {
+        let mut explicit: Vec<(CompressionMethod, f32)> = Vec::new();
+        let mut wildcard = None;
+
+        for entry in accept_encoding.split(',') {
+            let mut parts = entry.split(';').map(str::trim);
+            let coding = match parts.next() {
+                Some(coding) if !coding.is_empty() => coding,
+                _ => continue,
+            };
+
+            let mut quality = 1.0;
+            for param in parts {
+                if let Some(value) = param
+                    .strip_prefix("q=")
+                    .or_else(|| param.strip_prefix("Q="))
+                {
+                    quality = match value.parse::<f32>() {
+                        Ok(q) if (0.0..=1.0).contains(&q) => q,
+                        _ => 0.0, // ignore invalid entries
+                    };
+                }
+            }
+
+            if coding == "*" {
+                wildcard = Some(quality);
+            } else if let Ok(method) = coding.parse() {
+                explicit.push((method, quality));
+            }
+        }
+
+        let mut best: Option<(CompressionMethod, f32)> = None;
+        for method in Self::ALL {
+            let quality = match explicit.iter().find(|(m, _)| *m == method) {
+                Some((_, q)) => *q,
+                None => wildcard.unwrap_or(0.0),
+            };
+            // later methods are preferred
+            if quality > 0.0 && !matches!(best, Some((_, q)) if quality < q) {
+                best = Some((method, quality));
+            }
+        }
+
+        best.map(|(method, _)| method)
+    }
 }
 
 impl std::str::FromStr for CompressionMethod {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            //            "br" => Ok(CompressionMethod::Brotli),
+        // http accept-encoding allows to give weights with ';q='
+        let coding = s.split(';').next().unwrap_or_default().trim();
+        match coding.to_ascii_lowercase().as_str() {
+            "zstd" => Ok(CompressionMethod::Zstd),
+            "br" => Ok(CompressionMethod::Brotli),
             //            "gzip" => Ok(CompressionMethod::Gzip),
             "deflate" => Ok(CompressionMethod::Deflate),
-            // http accept-encoding allows to give weights with ';q='
-            other if other.starts_with("deflate;q=") => Ok(CompressionMethod::Deflate),
             _ => bail!("unknown compression format"),
         }
     }
 }
+
+/// Check whether a response should be compressed.
+///
+/// Small responses and content types which are already compressed (images, audio, video and
+/// archives) are excluded. `size` is `None` if not known in advance, e.g. for streams.
+pub fn should_compress(content_type: Option<&str>, size: Option<u64>) -> bool {
+    if matches!(size, Some(size) if size < MIN_COMPRESSION_SIZE) {
+        return false;
+    }
+
+    let content_type = match content_type {
+        Some(content_type) => content_type,
+        None => return true,
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    if mime == "image/svg+xml" {
+        return true;
+    }
+
+    !(mime.starts_with("image/")
+        || mime.starts_with("audio/")
+        || mime.starts_with("video/")
+        || matches!(
+            mime.as_str(),
+            "application/gzip"
+                | "application/x-gzip"
+                | "application/zip"
+                | "application/zstd"
+                | "application/x-xz"
+                | "application/x-bzip2"
+                | "application/x-compressed-tar"
+                | "application/epub+zip"
+                | "application/java-archive"
+                | "application/pdf"
+                | "application/font-woff"
+                | "application/font-woff2"
+                | "font/woff"
+                | "font/woff2"
+        ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accept_encoding() {
+        let select = CompressionMethod::from_accept_encoding;
+
+        assert_eq!(select(""), None);
+        assert_eq!(select("identity"), None);
+        assert_eq!(select("gzip, deflate"), Some(CompressionMethod::Deflate));
+        assert_eq!(
+            select("gzip, deflate, br, zstd"),
+            Some(CompressionMethod::Zstd)
+        );
+        assert_eq!(select("deflate, br"), Some(CompressionMethod::Brotli));
+        assert_eq!(
+            select("zstd;q=0.5, br;q=0.8, deflate;q=0.1"),
+            Some(CompressionMethod::Brotli)
+        );
+        assert_eq!(select("br;q=0, deflate"), Some(CompressionMethod::Deflate));
+        assert_eq!(select("*"), Some(CompressionMethod::Zstd));
+        assert_eq!(select("*;q=0.5, br"), Some(CompressionMethod::Brotli));
+        assert_eq!(select("zstd;q=0, *"), Some(CompressionMethod::Brotli));
+        assert_eq!(select("br;q=invalid"), None);
+    }
+
+    #[test]
+    fn test_should_compress() {
+        assert!(should_compress(None, None));
+        assert!(should_compress(
+            Some("application/json;charset=UTF-8"),
+            Some(4096)
+        ));
+        assert!(should_compress(Some("image/svg+xml"), None));
+        assert!(!should_compress(Some("application/json"), Some(100)));
+        assert!(!should_compress(Some("image/png"), Some(100_000)));
+        assert!(!should_compress(Some("application/zstd"), None));
+    }
+}
@@ -2,22 +2,33 @@
 //!
 //! Hyper building block.
 
+use std::future::Future;
+use std::net::SocketAddr;
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use std::time::Duration;
 
-use anyhow::{format_err, Context as _, Error};
-use futures::FutureExt;
+use anyhow::{bail, format_err, Context as _, Error};
+use futures::{ready, FutureExt};
 use hyper::server::accept;
 use openssl::ec::{EcGroup, EcKey};
 use openssl::nid::Nid;
 use openssl::pkey::{PKey, Private};
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
-use openssl::x509::X509;
+use openssl::ssl::{
+    AlpnError, SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod, SslOptions, SslVerifyMode,
+    SslVersion,
+};
+use openssl::x509::store::X509Lookup;
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::x509::{X509Name, X509};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
+use tokio::time::{Instant, Sleep};
 use tokio_openssl::SslStream;
 use tokio_stream::wrappers::ReceiverStream;
 
@@ -32,14 +43,102 @@ enum Tls {
     FilesPem(PathBuf, PathBuf),
 }
 
+/// Callback returning the DER encoded OCSP response to staple, see
+/// [TlsAcceptorBuilder::ocsp_response_fn].
+pub type OcspResponseFn = dyn Fn() -> Result<Option<Vec<u8>>, Error> + Send + Sync + 'static;
+
+enum OcspStapling {
+    File(PathBuf),
+    Callback(Arc<OcspResponseFn>),
+}
+
+/// TLS policy presets following the Mozilla server side TLS recommendations (version 5).
+///
+/// See <https://wiki.mozilla.org/Security/Server_Side_TLS>.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TlsPolicy {
+    /// TLS 1.3 only.
+    Modern,
+    /// TLS 1.2 and 1.3 with forward secret AEAD ciphers.
+    #[default]
+    Intermediate,
+    /// TLS 1.0 to 1.3 with a wide range of ciphers, only for very old clients.
+    Old,
+}
+
+// cipher list of the "old" configuration, TLS 1.0 and 1.1 require security level 0 with
+// OpenSSL 3
+const OLD_CIPHER_LIST: &str = "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:\
+    ECDHE-ECDSA-AES256-GCM-SHA384:ECDHE-RSA-AES256-GCM-SHA384:ECDHE-ECDSA-CHACHA20-POLY1305:\
+    ECDHE-RSA-CHACHA20-POLY1305:DHE-RSA-AES128-GCM-SHA256:DHE-RSA-AES256-GCM-SHA384:\
+    DHE-RSA-CHACHA20-POLY1305:ECDHE-ECDSA-AES128-SHA256:ECDHE-RSA-AES128-SHA256:\
+    ECDHE-ECDSA-AES128-SHA:ECDHE-RSA-AES128-SHA:ECDHE-ECDSA-AES256-SHA384:\
+    ECDHE-RSA-AES256-SHA384:ECDHE-ECDSA-AES256-SHA:ECDHE-RSA-AES256-SHA:DHE-RSA-AES128-SHA256:\
+    DHE-RSA-AES256-SHA256:AES128-GCM-SHA256:AES256-GCM-SHA384:AES128-SHA256:AES256-SHA256:\
+    AES128-SHA:AES256-SHA:DES-CBC3-SHA:@SECLEVEL=0";
+
+impl TlsPolicy {
+    fn acceptor_builder(self) -> Result<SslAcceptorBuilder, Error> {
+        let (min_version, cipher_list) = match self {
+            TlsPolicy::Modern => return Ok(SslAcceptor::mozilla_modern_v5(SslMethod::tls())?),
+            TlsPolicy::Intermediate => (SslVersion::TLS1_2, None),
+            TlsPolicy::Old => (SslVersion::TLS1, Some(OLD_CIPHER_LIST)),
+        };
+
+        // the intermediate preset disables old versions via options, use the minimum version
+        // instead so it can be overridden
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+        acceptor.clear_options(SslOptions::NO_TLSV1 | SslOptions::NO_TLSV1_1);
+        acceptor.set_min_proto_version(Some(min_version))?;
+
+        if let Some(cipher_list) = cipher_list {
+            acceptor.set_cipher_list(cipher_list)?;
+            acceptor.set_options(SslOptions::CIPHER_SERVER_PREFERENCE);
+        }
+
+        Ok(acceptor)
+    }
+}
+
+impl std::str::FromStr for TlsPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Ok(match s {
+            "modern" => TlsPolicy::Modern,
+            "intermediate" => TlsPolicy::Intermediate,
+            "old" => TlsPolicy::Old,
+            _ => bail!("unknown TLS policy '{}'", s),
+        })
+    }
+}
+
 /// A builder for an `SslAcceptor` which can be configured either with certificates (or path to PEM
 /// files), or otherwise builds a self-signed certificate on the fly (mostly useful during
 /// development).
+///
+/// Client certificates are verified if a CA is configured with
+/// [client_ca_file](Self::client_ca_file). The subject of a verified client certificate is
+/// passed to the authentication handler in the
+/// [CLIENT_CERT_SUBJECT_HEADER](crate::CLIENT_CERT_SUBJECT_HEADER) header.
+///
+/// The protocol versions and ciphers default to the [intermediate](TlsPolicy::Intermediate)
+/// policy and can be adapted with [policy](Self::policy), the protocol version setters and
+/// [cipher_suites](Self::cipher_suites) (TLS 1.3) or [cipher_list](Self::cipher_list) (up to
+/// TLS 1.2).
 #[derive(Default)]
 pub struct TlsAcceptorBuilder {
     tls: Option<Tls>,
+    policy: TlsPolicy,
+    min_version: Option<SslVersion>,
+    max_version: Option<SslVersion>,
     cipher_suites: Option<String>,
     cipher_list: Option<String>,
+    client_ca: Option<PathBuf>,
+    client_crl: Option<PathBuf>,
+    require_client_cert: bool,
+    alpn_protocols: Vec<Vec<u8>>,
+    ocsp: Option<OcspStapling>,
 }
 
 impl TlsAcceptorBuilder {
@@ -61,6 +160,24 @@ impl TlsAcceptorBuilder {
         self
     }
 
+    /// Use the protocol versions and ciphers of the TLS policy preset `policy`.
+    pub fn policy(mut self, policy: TlsPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set the minimum protocol version, overriding the one of the [policy](Self::policy).
+    pub fn min_protocol_version(mut self, version: SslVersion) -> Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    /// Set the maximum protocol version, by default the highest supported version is used.
+    pub fn max_protocol_version(mut self, version: SslVersion) -> Self {
+        self.max_version = Some(version);
+        self
+    }
+
     pub fn cipher_suites(mut self, suites: String) -> Self {
         self.cipher_suites = Some(suites);
         self
@@ -71,8 +188,95 @@ impl TlsAcceptorBuilder {
         self
     }
 
+    /// Verify client certificates against the CA certificates in the PEM file `path`.
+    pub fn client_ca_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.client_ca = Some(path.into());
+        self
+    }
+
+    /// Reject client certificates revoked by the CRL in the PEM file `path`.
+    ///
+    /// Only used together with [client_ca_file](Self::client_ca_file).
+    pub fn client_crl_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.client_crl = Some(path.into());
+        self
+    }
+
+    /// Reject connections without a valid client certificate. By default, client certificates
+    /// are optional and only verified if the client sends one.
+    ///
+    /// Only used together with [client_ca_file](Self::client_ca_file).
+    pub fn require_client_certificate(mut self, require: bool) -> Self {
+        self.require_client_cert = require;
+        self
+    }
+
+    /// Set the protocols offered via ALPN in order of preference, e.g. `["h2", "http/1.1"]`.
+    ///
+    /// Only offer `h2` if the server is configured to handle HTTP/2 connections.
+    pub fn alpn_protocols<I, S>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.alpn_protocols = protocols
+            .into_iter()
+            .map(|proto| proto.as_ref().as_bytes().to_vec())
+            .collect();
+        self
+    }
+
+    /// Staple the DER encoded OCSP response from the file at `path`.
+    ///
+    /// The file is read when the acceptor is built, so it has to be rebuilt to pick up a
+    /// renewed response.
+    pub fn ocsp_response_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ocsp = Some(OcspStapling::File(path.into()));
+        self
+    }
+
+    /// Staple the DER encoded OCSP response returned by `func`.
+    ///
+    /// The callback runs during every handshake requesting the certificate status, so it should
+    /// return a cached response instead of contacting the OCSP responder. Returning `None`
+    /// sends no response.
+    pub fn ocsp_response_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn() -> Result<Option<Vec<u8>>, Error> + Send + Sync + 'static,
+    {
+        self.ocsp = Some(OcspStapling::Callback(Arc::new(func)));
+        self
+    }
+
+    fn acceptor_builder(&self) -> Result<SslAcceptorBuilder, Error> {
+        let mut acceptor = self.policy.acceptor_builder()?;
+
+        if let Some(version) = self.min_version {
+            acceptor
+                .set_min_proto_version(Some(version))
+                .context("failed to set minimum TLS protocol version")?;
+        }
+        if let Some(version) = self.max_version {
+            acceptor
+                .set_max_proto_version(Some(version))
+                .context("failed to set maximum TLS protocol version")?;
+        }
+        if let Some(suites) = &self.cipher_suites {
+            acceptor
+                .set_ciphersuites(suites)
+                .context("failed to set TLS cipher suites")?;
+        }
+        if let Some(list) = &self.cipher_list {
+            acceptor
+                .set_cipher_list(list)
+                .context("failed to set TLS cipher list")?;
+        }
+
+        Ok(acceptor)
+    }
+
     pub fn build(self) -> Result<SslAcceptor, Error> {
-        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        let mut acceptor = self.acceptor_builder()?;
 
         match self.tls {
             Some(Tls::KeyCert(key, cert)) => {
@@ -127,6 +331,61 @@ impl TlsAcceptorBuilder {
                     .context("failed to set tls acceptor certificate")?;
             }
         }
+        if let Some(ca) = &self.client_ca {
+            acceptor
+                .set_ca_file(ca)
+                .context("failed to load client CA file")?;
+            acceptor.set_client_ca_list(
+                X509Name::load_client_ca_file(ca).context("failed to load client CA names")?,
+            );
+
+            if let Some(crl) = &self.client_crl {
+                let store = acceptor.cert_store_mut();
+                store
+                    .add_lookup(X509Lookup::file())?
+                    .load_crl_file(crl, SslFiletype::PEM)
+                    .context("failed to load client CRL file")?;
+                store.set_flags(X509VerifyFlags::CRL_CHECK)?;
+            }
+
+            let mut mode = SslVerifyMode::PEER;
+            if self.require_client_cert {
+                mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+            }
+            acceptor.set_verify(mode);
+            // required for session resumption with client certificates
+            acceptor.set_session_id_context(b"proxmox-rest-server")?;
+        }
+
+        if !self.alpn_protocols.is_empty() {
+            let protocols = self.alpn_protocols;
+            acceptor.set_alpn_select_callback(move |_ssl, client| {
+                select_alpn_protocol(&protocols, client).ok_or(AlpnError::NOACK)
+            });
+        }
+
+        if let Some(ocsp) = self.ocsp {
+            let response_fn: Arc<OcspResponseFn> = match ocsp {
+                OcspStapling::File(path) => {
+                    let response = std::fs::read(&path)
+                        .with_context(|| format!("failed to read OCSP response file {:?}", path))?;
+                    Arc::new(move || Ok(Some(response.clone())))
+                }
+                OcspStapling::Callback(func) => func,
+            };
+            acceptor.set_status_callback(move |ssl| match response_fn() {
+                Ok(Some(response)) => {
+                    ssl.set_ocsp_status(&response)?;
+                    Ok(true)
+                }
+                Ok(None) => Ok(false),
+                Err(err) => {
+                    log::error!("failed to get OCSP response for stapling - {err}");
+                    Ok(false)
+                }
+            })?;
+        }
+
         acceptor.set_options(openssl::ssl::SslOptions::NO_RENEGOTIATION);
         acceptor.check_private_key().unwrap();
 
@@ -134,10 +393,319 @@ impl TlsAcceptorBuilder {
     }
 }
 
+/// A listening address served by [RestServer::serve_listeners](crate::RestServer::serve_listeners).
+///
+/// ```
+/// # use proxmox_rest_server::connection::{ListenerConfig, TlsAcceptorBuilder};
+/// let listeners = vec![
+///     ListenerConfig::new(([0, 0, 0, 0], 8006).into())
+///         .tls(TlsAcceptorBuilder::new().certificate_paths_pem("/etc/key.pem", "/etc/cert.pem")),
+///     ListenerConfig::new(([127, 0, 0, 1], 85).into()).allow_path_prefix("/api2/json/"),
+/// ];
+/// ```
+pub struct ListenerConfig {
+    pub(crate) addr: SocketAddr,
+    pub(crate) tls: Option<TlsAcceptorBuilder>,
+    pub(crate) accept_builder: AcceptBuilder,
+    pub(crate) allowed_paths: Option<Vec<String>>,
+}
+
+impl ListenerConfig {
+    /// Listen on `addr` without TLS, serving all paths.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            tls: None,
+            accept_builder: AcceptBuilder::new(),
+            allowed_paths: None,
+        }
+    }
+
+    /// Use TLS on this listener.
+    pub fn tls(mut self, tls: TlsAcceptorBuilder) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Set the connection settings for TLS connections.
+    pub fn accept_builder(mut self, accept_builder: AcceptBuilder) -> Self {
+        self.accept_builder = accept_builder;
+        self
+    }
+
+    /// Only serve requests whose path starts with the components of `prefix` on this listener,
+    /// all other requests are answered with `404 Not Found`. Can be called multiple times.
+    pub fn allow_path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.allowed_paths
+            .get_or_insert_with(Vec::new)
+            .push(prefix.into());
+        self
+    }
+}
+
+/// Select the first of our `protocols` offered by the client (in ALPN wire format).
+fn select_alpn_protocol<'a>(protocols: &[Vec<u8>], mut client: &'a [u8]) -> Option<&'a [u8]> {
+    let mut offered = Vec::new();
+    while let Some((&len, rest)) = client.split_first() {
+        if rest.len() < len as usize {
+            return None; // malformed
+        }
+        let (proto, rest) = rest.split_at(len as usize);
+        offered.push(proto);
+        client = rest;
+    }
+
+    protocols
+        .iter()
+        .find_map(|proto| offered.iter().find(|offered| **offered == &proto[..]))
+        .copied()
+}
+
+/// A client connection, possibly accepted via a proxy using the PROXY protocol, see
+/// [AcceptBuilder::proxy_protocol].
+pub struct ProxiedStream<S> {
+    inner: S,
+    proxied_peer: Option<SocketAddr>,
+}
+
+impl<S> ProxiedStream<S> {
+    /// The original client address as reported by the proxy.
+    pub fn proxied_peer(&self) -> Option<SocketAddr> {
+        self.proxied_peer
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ProxiedStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ProxiedStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Counts the requests of a connection, shared between a [TimeoutStream] and the API service
+/// handling the connection.
+#[derive(Default)]
+pub struct RequestTracker {
+    started: AtomicUsize,
+    finished: AtomicUsize,
+}
+
+impl RequestTracker {
+    /// Mark the start of a request, which ends when the returned guard is dropped.
+    pub(crate) fn start(self: &Arc<Self>) -> RequestGuard {
+        self.started.fetch_add(1, Ordering::AcqRel);
+        RequestGuard(Arc::clone(self))
+    }
+
+    fn started(&self) -> usize {
+        self.started.load(Ordering::Acquire)
+    }
+
+    fn in_progress(&self) -> bool {
+        self.started() != self.finished.load(Ordering::Acquire)
+    }
+}
+
+pub(crate) struct RequestGuard(Arc<RequestTracker>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.0.finished.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// A client connection enforcing the [header read](crate::ApiConfig::header_read_timeout) and
+/// [keep-alive](crate::ApiConfig::keep_alive_timeout) timeouts, see
+/// [RestServer::server_builder](crate::RestServer::server_builder).
+///
+/// Reading fails with [TimedOut](std::io::ErrorKind::TimedOut) if the headers of a request are
+/// not received within the header read timeout after its first byte, or if no request arrives
+/// within the keep-alive timeout after the last data was sent. No timeout applies while a
+/// request is being processed.
+pub struct TimeoutStream<S> {
+    inner: S,
+    requests: Arc<RequestTracker>,
+    header_read_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    seen_requests: usize,
+    request_start: Option<Instant>,
+    idle_since: Instant,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> TimeoutStream<S> {
+    pub(crate) fn new(
+        inner: S,
+        header_read_timeout: Option<Duration>,
+        keep_alive_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            inner,
+            requests: Arc::default(),
+            header_read_timeout,
+            keep_alive_timeout,
+            seen_requests: 0,
+            request_start: None,
+            idle_since: Instant::now(),
+            timer: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// The request tracker the API service uses to mark requests as in progress.
+    pub fn request_tracker(&self) -> &Arc<RequestTracker> {
+        &self.requests
+    }
+
+    fn wrote(&mut self, result: &Poll<std::io::Result<usize>>) {
+        if matches!(result, Poll::Ready(Ok(written)) if *written > 0) {
+            self.idle_since = Instant::now();
+        }
+    }
+
+    fn poll_timeout(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let (deadline, what) = match self.request_start {
+            Some(start) => (
+                self.header_read_timeout.map(|timeout| start + timeout),
+                "reading request headers",
+            ),
+            None => (
+                self.keep_alive_timeout
+                    .map(|timeout| self.idle_since + timeout),
+                "waiting for request",
+            ),
+        };
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return Poll::Pending,
+        };
+
+        let timer = self
+            .timer
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+        if timer.deadline() != deadline {
+            timer.as_mut().reset(deadline);
+        }
+
+        ready!(timer.as_mut().poll(cx));
+        Poll::Ready(Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("timeout {what}"),
+        )))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TimeoutStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        let started = this.requests.started();
+        if started != this.seen_requests {
+            this.seen_requests = started;
+            this.request_start = None;
+        }
+        if this.requests.in_progress() {
+            this.idle_since = Instant::now();
+            return result;
+        }
+
+        match result {
+            Poll::Ready(Ok(())) if buf.filled().len() > filled => {
+                this.request_start.get_or_insert_with(Instant::now);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(result) => Poll::Ready(result),
+            Poll::Pending => this.poll_timeout(cx),
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TimeoutStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        this.wrote(&result);
+        result
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        this.wrote(&result);
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
 #[cfg(not(feature = "rate-limited-stream"))]
-type InsecureClientStream = TcpStream;
+type InsecureClientStream = ProxiedStream<TcpStream>;
 #[cfg(feature = "rate-limited-stream")]
-type InsecureClientStream = RateLimitedStream<TcpStream>;
+type InsecureClientStream = ProxiedStream<RateLimitedStream<TcpStream>>;
 
 type InsecureClientStreamResult = Pin<Box<InsecureClientStream>>;
 
@@ -153,6 +721,7 @@ pub struct AcceptBuilder {
     debug: bool,
     tcp_keepalive_time: u32,
     max_pending_accepts: usize,
+    proxy_protocol: bool,
 
     #[cfg(feature = "rate-limited-stream")]
     lookup_rate_limiter: Option<Arc<LookupRateLimiter>>,
@@ -164,6 +733,7 @@ impl Default for AcceptBuilder {
             debug: false,
             tcp_keepalive_time: 120,
             max_pending_accepts: 1024,
+            proxy_protocol: false,
 
             #[cfg(feature = "rate-limited-stream")]
             lookup_rate_limiter: None,
@@ -191,6 +761,17 @@ impl AcceptBuilder {
         self
     }
 
+    /// Expect a PROXY protocol (version 1 or 2) header on every connection, as sent by e.g.
+    /// haproxy with `send-proxy`. The client address from the header is used as peer address
+    /// for logging and rate limiting.
+    ///
+    /// Only enable this if all connections come from a trusted proxy, connections without
+    /// valid header are rejected.
+    pub fn proxy_protocol(mut self, enable: bool) -> Self {
+        self.proxy_protocol = enable;
+        self
+    }
+
     #[cfg(feature = "rate-limited-stream")]
     pub fn rate_limiter_lookup(mut self, lookup_rate_limiter: Arc<LookupRateLimiter>) -> Self {
         self.lookup_rate_limiter = Some(lookup_rate_limiter);
@@ -266,7 +847,7 @@ impl AcceptBuilder {
         let mut shutdown_future = crate::shutdown_future().fuse();
 
         loop {
-            let socket = futures::select! {
+            let (socket, peer) = futures::select! {
                 res = self.try_setup_socket(&listener).fuse() => match res {
                     Ok(socket) => socket,
                     Err(err) => {
@@ -285,29 +866,45 @@ impl AcceptBuilder {
                 continue;
             }
 
+            let socket = self.finish_socket_setup(socket, peer);
+            let debug = self.debug;
+
             match sender {
                 Sender::Secure(ref secure_sender) => {
-                    let accept_future = Self::do_accept_tls(
-                        socket,
-                        acceptor,
-                        accept_counter,
-                        self.debug,
-                        secure_sender.clone(),
-                    );
-
-                    tokio::spawn(accept_future);
+                    let secure_sender = secure_sender.clone();
+                    tokio::spawn(async move {
+                        let socket = match socket.await {
+                            Ok(socket) => socket,
+                            Err(err) => {
+                                log::error!("couldn't set up connection from {peer}: {err}");
+                                return;
+                            }
+                        };
+                        Self::do_accept_tls(socket, acceptor, accept_counter, debug, secure_sender)
+                            .await
+                    });
                 }
                 Sender::SecureAndInsecure(ref secure_sender, ref insecure_sender) => {
-                    let accept_future = Self::do_accept_tls_optional(
-                        socket,
-                        acceptor,
-                        accept_counter,
-                        self.debug,
-                        secure_sender.clone(),
-                        insecure_sender.clone(),
-                    );
-
-                    tokio::spawn(accept_future);
+                    let secure_sender = secure_sender.clone();
+                    let insecure_sender = insecure_sender.clone();
+                    tokio::spawn(async move {
+                        let socket = match socket.await {
+                            Ok(socket) => socket,
+                            Err(err) => {
+                                log::error!("couldn't set up connection from {peer}: {err}");
+                                return;
+                            }
+                        };
+                        Self::do_accept_tls_optional(
+                            socket,
+                            acceptor,
+                            accept_counter,
+                            debug,
+                            secure_sender,
+                            insecure_sender,
+                        )
+                        .await
+                    });
                 }
             };
         }
@@ -316,7 +913,7 @@ impl AcceptBuilder {
     async fn try_setup_socket(
         &self,
         listener: &TcpListener,
-    ) -> Result<InsecureClientStream, Error> {
+    ) -> Result<(TcpStream, SocketAddr), Error> {
         let (socket, peer) = match listener.accept().await {
             Ok(connection) => connection,
             Err(error) => {
@@ -331,16 +928,53 @@ impl AcceptBuilder {
         proxmox_sys::linux::socket::set_tcp_keepalive(socket.as_raw_fd(), self.tcp_keepalive_time)
             .context("error while setting SO_KEEPALIVE on socket")?;
 
+        Ok((socket, peer))
+    }
+
+    /// Read the PROXY protocol header (if enabled) and wrap the socket. This runs in the
+    /// spawned connection task, so slow clients do not block accepting new connections.
+    fn finish_socket_setup(
+        &self,
+        mut socket: TcpStream,
+        peer: SocketAddr,
+    ) -> impl std::future::Future<Output = Result<InsecureClientStream, Error>> + Send + 'static
+    {
+        let proxy_protocol = self.proxy_protocol;
+
         #[cfg(feature = "rate-limited-stream")]
-        let socket = match self.lookup_rate_limiter.clone() {
-            Some(lookup) => RateLimitedStream::with_limiter_update_cb(socket, move || lookup(peer)),
-            None => RateLimitedStream::with_limiter(socket, None, None),
-        };
+        let lookup_rate_limiter = self.lookup_rate_limiter.clone();
+
+        async move {
+            let proxied_peer = if proxy_protocol {
+                tokio::time::timeout(
+                    Duration::new(10, 0),
+                    crate::proxy_protocol::read_header(&mut socket),
+                )
+                .await
+                .map_err(|_| format_err!("timeout reading PROXY protocol header"))??
+            } else {
+                None
+            };
+
+            #[cfg(feature = "rate-limited-stream")]
+            let socket = {
+                let peer = proxied_peer.unwrap_or(peer);
+                match lookup_rate_limiter {
+                    Some(lookup) => {
+                        RateLimitedStream::with_limiter_update_cb(socket, move || lookup(peer))
+                    }
+                    None => RateLimitedStream::with_limiter(socket, None, None),
+                }
+            };
 
-        #[cfg(not(feature = "rate-limited-stream"))]
-        let _peer = peer;
+            #[cfg(not(feature = "rate-limited-stream"))]
+            let _peer = peer;
 
-        Ok(socket)
+            Ok(ProxiedStream {
+                inner: socket,
+                proxied_peer,
+            })
+        }
     }
 
     async fn do_accept_tls(
@@ -410,10 +1044,10 @@ impl AcceptBuilder {
     ) {
         let client_initiates_handshake = {
             #[cfg(feature = "rate-limited-stream")]
-            let socket = socket.inner();
+            let socket = socket.get_ref().inner();
 
             #[cfg(not(feature = "rate-limited-stream"))]
-            let socket = &socket;
+            let socket = socket.get_ref();
 
             match Self::wait_for_client_tls_handshake(socket).await {
                 Ok(initiates_handshake) => initiates_handshake,
@@ -505,3 +1139,105 @@ fn contains_tls_handshake_fragment(buf: &[u8]) -> bool {
 
     buf[0] == 0x16 && buf[1] == 0x3 && (((buf[3] as u16) << 8) + buf[4] as u16) <= CONTENT_SIZE
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_select_alpn_protocol() {
+        let ours = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        assert_eq!(
+            select_alpn_protocol(&ours, b"\x08http/1.1\x02h2"),
+            Some(&b"h2"[..])
+        );
+        assert_eq!(
+            select_alpn_protocol(&ours, b"\x08http/1.1"),
+            Some(&b"http/1.1"[..])
+        );
+        assert_eq!(select_alpn_protocol(&ours, b"\x06spdy/3"), None);
+        assert_eq!(select_alpn_protocol(&ours, b"\x08http"), None);
+    }
+
+    #[test]
+    fn test_tls_policy() -> Result<(), Error> {
+        let mut acceptor = TlsAcceptorBuilder::new().acceptor_builder()?;
+        assert_eq!(acceptor.min_proto_version(), Some(SslVersion::TLS1_2));
+
+        let mut acceptor = TlsAcceptorBuilder::new()
+            .policy("modern".parse()?)
+            .acceptor_builder()?;
+        assert_eq!(acceptor.min_proto_version(), Some(SslVersion::TLS1_3));
+
+        let mut acceptor = TlsAcceptorBuilder::new()
+            .policy(TlsPolicy::Old)
+            .min_protocol_version(SslVersion::TLS1_1)
+            .max_protocol_version(SslVersion::TLS1_2)
+            .acceptor_builder()?;
+        assert_eq!(acceptor.min_proto_version(), Some(SslVersion::TLS1_1));
+        assert_eq!(acceptor.max_proto_version(), Some(SslVersion::TLS1_2));
+
+        assert!("insecure".parse::<TlsPolicy>().is_err());
+        assert!(TlsAcceptorBuilder::new()
+            .cipher_list("INVALID".into())
+            .acceptor_builder()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout_stream() -> Result<(), Error> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        const SHORT: Duration = Duration::from_millis(50);
+        const LONG: Duration = Duration::from_secs(60);
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        rt.block_on(async {
+            let mut buf = [0u8; 16];
+
+            // idle connection
+            let (_client, server) = tokio::io::duplex(64);
+            let mut stream = TimeoutStream::new(server, Some(LONG), Some(SHORT));
+            let start = Instant::now();
+            let err = stream.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+            assert!(start.elapsed() >= SHORT);
+
+            // incomplete request headers
+            let (mut client, server) = tokio::io::duplex(64);
+            let mut stream = TimeoutStream::new(server, Some(SHORT), Some(LONG));
+            client.write_all(b"GET / HTTP/1.1\r\n").await?;
+            assert_eq!(stream.read(&mut buf).await?, 16);
+            let err = stream.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+            // no timeout while a request is in progress
+            let (_client, server) = tokio::io::duplex(64);
+            let mut stream = TimeoutStream::new(server, Some(SHORT), Some(SHORT));
+            let guard = stream.request_tracker().start();
+            assert!(tokio::time::timeout(4 * SHORT, stream.read(&mut buf))
+                .await
+                .is_err());
+            drop(guard);
+            let err = stream.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+            // a new request resets the header read timer
+            let (mut client, server) = tokio::io::duplex(64);
+            let mut stream = TimeoutStream::new(server, Some(SHORT), Some(LONG));
+            client.write_all(b"GET").await?;
+            assert_eq!(stream.read(&mut buf).await?, 3);
+            drop(stream.request_tracker().start());
+            assert!(tokio::time::timeout(4 * SHORT, stream.read(&mut buf))
+                .await
+                .is_err());
+
+            Ok(())
+        })
+    }
+}
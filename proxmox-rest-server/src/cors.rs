@@ -0,0 +1,325 @@
+//! Cross-Origin Resource Sharing (CORS), see [ApiConfig::cors](crate::ApiConfig::cors).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use http::header::{self, HeaderMap, HeaderValue};
+use http::{Method, StatusCode};
+use hyper::http::request::Parts;
+use hyper::{Body, Response};
+
+use crate::Middleware;
+
+/// CORS configuration.
+///
+/// By default, no origin is allowed. Allowed are the methods `GET`, `POST`, `PUT` and
+/// `DELETE`, and the request headers `Authorization`, `Content-Type` and
+/// `CSRFPreventionToken`.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use proxmox_rest_server::CorsConfig;
+/// let cors = CorsConfig::new()
+///     .allow_origin("https://dashboard.example.com")
+///     .allow_credentials(true)
+///     .max_age(Duration::from_secs(3600));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CorsConfig {
+    origins: Vec<String>,
+    any_origin: bool,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    expose_headers: Vec<String>,
+    allow_credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            origins: Vec::new(),
+            any_origin: false,
+            methods: vec![Method::GET, Method::POST, Method::PUT, Method::DELETE],
+            headers: vec![
+                "Authorization".to_string(),
+                "Content-Type".to_string(),
+                "CSRFPreventionToken".to_string(),
+            ],
+            expose_headers: Vec::new(),
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow requests from `origin`, e.g. `https://example.com:8443`.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origins
+            .push(origin.into().trim_end_matches('/').to_ascii_lowercase());
+        self
+    }
+
+    /// Allow requests from any origin.
+    ///
+    /// Credentials are only ever allowed for origins added via
+    /// [allow_origin](CorsConfig::allow_origin), other origins get `*` as allowed origin, without
+    /// credentials. Otherwise any website could make authenticated requests.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.any_origin = true;
+        self
+    }
+
+    /// Set the allowed request methods.
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Set the allowed request headers.
+    pub fn allow_headers<S: Into<String>>(mut self, headers: impl IntoIterator<Item = S>) -> Self {
+        self.headers = headers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the response headers accessible by the client (besides the CORS-safelisted ones).
+    pub fn expose_headers<S: Into<String>>(mut self, headers: impl IntoIterator<Item = S>) -> Self {
+        self.expose_headers = headers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Allow requests with credentials (cookies, authorization headers).
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.allow_credentials = allow;
+        self
+    }
+
+    /// Let clients cache the preflight response for `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn origin_listed(&self, origin: &str) -> bool {
+        self.origins.iter().any(|o| o.eq_ignore_ascii_case(origin))
+    }
+
+    /// Returns the value for `Access-Control-Allow-Origin` if the request origin is allowed, and
+    /// whether credentials are allowed for it.
+    fn allowed_origin(&self, headers: &HeaderMap) -> Option<(HeaderValue, bool)> {
+        let origin = headers.get(header::ORIGIN)?;
+        if self.origin_listed(origin.to_str().ok()?) {
+            Some((origin.clone(), self.allow_credentials))
+        } else if self.any_origin {
+            Some((HeaderValue::from_static("*"), false))
+        } else {
+            None
+        }
+    }
+
+    fn add_common_headers(
+        &self,
+        headers: &mut HeaderMap,
+        (origin, credentials): (HeaderValue, bool),
+    ) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        if credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    /// Answer preflight requests.
+    fn preflight(&self, parts: &Parts) -> Option<Response<Body>> {
+        if parts.method != Method::OPTIONS {
+            return None;
+        }
+        let requested_method = parts
+            .headers
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)?
+            .to_str()
+            .ok()
+            .and_then(|method| method.parse::<Method>().ok());
+
+        let origin = match (self.allowed_origin(&parts.headers), requested_method) {
+            (Some(origin), Some(method)) if self.methods.contains(&method) => origin,
+            _ => {
+                return Some(
+                    Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+            }
+        };
+
+        let mut response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+
+        let headers = response.headers_mut();
+        self.add_common_headers(headers, origin);
+
+        let methods: Vec<&str> = self.methods.iter().map(|m| m.as_str()).collect();
+        if let Ok(value) = HeaderValue::from_str(&methods.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&self.headers.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, value);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(
+                header::ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from(max_age.as_secs()),
+            );
+        }
+
+        Some(response)
+    }
+
+    /// Add the CORS headers to the response of an actual request.
+    fn add_response_headers(&self, parts: &Parts, response: &mut Response<Body>) {
+        let origin = match self.allowed_origin(&parts.headers) {
+            Some(origin) => origin,
+            None => return,
+        };
+
+        let headers = response.headers_mut();
+        self.add_common_headers(headers, origin);
+        if !self.expose_headers.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(&self.expose_headers.join(", ")) {
+                headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, value);
+            }
+        }
+    }
+
+    pub(crate) fn into_middleware(self) -> Middleware {
+        let before = Arc::new(self);
+        let after = Arc::clone(&before);
+
+        Middleware::new()
+            .before_fn(move |parts, _peer| {
+                let response = before.preflight(parts);
+                Box::pin(async move { Ok(response) })
+            })
+            .after_fn(move |parts, response| {
+                after.add_response_headers(parts, response);
+                Box::pin(async {})
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hyper::Request;
+
+    fn request(method: Method, origin: &str, request_method: Option<&str>) -> Parts {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri("/api2/json/version")
+            .header(header::ORIGIN, origin);
+        if let Some(request_method) = request_method {
+            builder = builder.header(header::ACCESS_CONTROL_REQUEST_METHOD, request_method);
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn test_cors() {
+        let cors = CorsConfig::new()
+            .allow_origin("https://Example.com/")
+            .max_age(Duration::from_secs(600));
+
+        let parts = request(Method::OPTIONS, "https://example.com", Some("POST"));
+        let response = cors.preflight(&parts).unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "GET, POST, PUT, DELETE"
+        );
+
+        let parts = request(Method::OPTIONS, "https://example.com", Some("PATCH"));
+        let response = cors.preflight(&parts).unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let parts = request(Method::OPTIONS, "https://evil.com", Some("GET"));
+        let response = cors.preflight(&parts).unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // not a preflight request
+        let parts = request(Method::GET, "https://example.com", None);
+        assert!(cors.preflight(&parts).is_none());
+
+        let mut response = Response::new(Body::empty());
+        cors.add_response_headers(&parts, &mut response);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+
+        let parts = request(Method::GET, "https://evil.com", None);
+        let mut response = Response::new(Body::empty());
+        cors.add_response_headers(&parts, &mut response);
+        assert!(response.headers().is_empty());
+
+        let cors = CorsConfig::new().allow_any_origin();
+        let parts = request(Method::GET, "https://evil.com", None);
+        let mut response = Response::new(Body::empty());
+        cors.add_response_headers(&parts, &mut response);
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[test]
+    fn test_cors_any_origin_credentials() {
+        let cors = CorsConfig::new()
+            .allow_origin("https://example.com")
+            .allow_any_origin()
+            .allow_credentials(true);
+
+        // credentials are never allowed for arbitrary origins
+        let parts = request(Method::GET, "https://evil.com", None);
+        let mut response = Response::new(Body::empty());
+        cors.add_response_headers(&parts, &mut response);
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(headers
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+
+        let parts = request(Method::OPTIONS, "https://evil.com", Some("GET"));
+        let response = cors.preflight(&parts).unwrap();
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(headers
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+
+        // but still for explicitly allowed ones
+        let parts = request(Method::GET, "https://example.com", None);
+        let mut response = Response::new(Body::empty());
+        cors.add_response_headers(&parts, &mut response);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    }
+}
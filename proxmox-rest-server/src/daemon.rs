@@ -12,6 +12,7 @@ use std::pin::Pin;
 
 use anyhow::{bail, format_err, Error};
 use futures::future::{self, Either};
+use nix::sys::socket::{self, AddressFamily, SockType, SockaddrLike, SockaddrStorage};
 use nix::unistd::{fork, ForkResult};
 
 use proxmox_io::{ReadExt, WriteExt};
@@ -300,6 +301,132 @@ impl Listenable for tokio::net::UnixListener {
     }
 }
 
+/// Listeners which can be inherited from systemd via socket activation, see
+/// [create_daemon_from_listen_fds].
+pub trait ActivatableListener: Reloadable {
+    /// The address families an inherited socket may have.
+    const ADDRESS_FAMILIES: &'static [AddressFamily];
+
+    /// Create the listener from an inherited, already listening socket.
+    fn from_listen_fd(fd: OwnedFd) -> Result<Self, Error>;
+}
+
+impl ActivatableListener for tokio::net::TcpListener {
+    const ADDRESS_FAMILIES: &'static [AddressFamily] = &[AddressFamily::Inet, AddressFamily::Inet6];
+
+    fn from_listen_fd(fd: OwnedFd) -> Result<Self, Error> {
+        let listener = std::net::TcpListener::from(fd);
+        listener.set_nonblocking(true)?;
+        Ok(Self::from_std(listener)?)
+    }
+}
+
+impl ActivatableListener for tokio::net::UnixListener {
+    const ADDRESS_FAMILIES: &'static [AddressFamily] = &[AddressFamily::Unix];
+
+    fn from_listen_fd(fd: OwnedFd) -> Result<Self, Error> {
+        let listener = std::os::unix::net::UnixListener::from(fd);
+        listener.set_nonblocking(true)?;
+        Ok(Self::from_std(listener)?)
+    }
+}
+
+/// The first file descriptor passed by systemd (see ``man sd_listen_fds``).
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Take the file descriptors passed via systemd socket activation.
+///
+/// The `LISTEN_*` environment variables are removed, so they are not passed on to child
+/// processes.
+fn take_listen_fds() -> Result<Vec<OwnedFd>, Error> {
+    let listen_pid = std::env::var("LISTEN_PID")
+        .map_err(|_| format_err!("LISTEN_PID not set - not started via socket activation?"))?;
+    let listen_fds = std::env::var("LISTEN_FDS")
+        .map_err(|_| format_err!("LISTEN_FDS not set - not started via socket activation?"))?;
+
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    let listen_pid: i32 = listen_pid
+        .parse()
+        .map_err(|err| format_err!("invalid LISTEN_PID - {}", err))?;
+    if listen_pid != nix::unistd::getpid().as_raw() {
+        bail!("LISTEN_PID does not match our pid - sockets were passed to another process");
+    }
+
+    let count: RawFd = listen_fds
+        .parse()
+        .map_err(|err| format_err!("invalid LISTEN_FDS - {}", err))?;
+
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count)
+        .map(|fd| {
+            fd_change_cloexec(fd, true)?;
+            Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+        })
+        .collect()
+}
+
+/// Check that an inherited file descriptor is a listening stream socket of the right family.
+fn check_listen_fd(fd: &OwnedFd, families: &[AddressFamily]) -> Result<(), Error> {
+    let fd = fd.as_raw_fd();
+
+    if socket::getsockopt(fd, socket::sockopt::SockType)
+        .map_err(|err| format_err!("inherited file descriptor {} is not a socket - {}", fd, err))?
+        != SockType::Stream
+    {
+        bail!("inherited socket {} is not a stream socket", fd);
+    }
+
+    if !socket::getsockopt(fd, socket::sockopt::AcceptConn)? {
+        bail!("inherited socket {} is not listening", fd);
+    }
+
+    let addr: SockaddrStorage = socket::getsockname(fd)?;
+    match addr.family() {
+        Some(family) if families.contains(&family) => Ok(()),
+        family => bail!(
+            "inherited socket {} has unexpected address family {:?}",
+            fd,
+            family
+        ),
+    }
+}
+
+/// Like [create_daemon], but uses the listening socket passed by systemd via socket activation
+/// (``LISTEN_FDS``) instead of binding a new one.
+///
+/// Exactly one socket must be passed. On reloads, the socket is passed on to the new process
+/// like in [create_daemon].
+pub async fn create_daemon_from_listen_fds<F, S, L>(
+    create_service: F,
+    pidfn: Option<&str>,
+) -> Result<(), Error>
+where
+    L: ActivatableListener,
+    F: FnOnce(L) -> Result<S, Error>,
+    S: Future<Output = Result<(), Error>>,
+{
+    let mut reloader = Reloader::new()?;
+
+    let listener: L = reloader
+        .restore("PROXMOX_BACKUP_LISTEN_FD", move || async move {
+            let mut fds = take_listen_fds()?;
+            if fds.len() != 1 {
+                bail!(
+                    "expected exactly one socket from systemd, got {}",
+                    fds.len()
+                );
+            }
+            let fd = fds.pop().unwrap();
+            check_listen_fd(&fd, L::ADDRESS_FAMILIES)?;
+            L::from_listen_fd(fd)
+        })
+        .await?;
+
+    run_daemon(reloader, create_service(listener)?, pidfn).await
+}
+
 /// This creates a future representing a daemon which reloads itself when receiving a SIGHUP.
 /// If this is started regularly, a listening socket is created. In this case, the file descriptor
 /// number will be remembered in `PROXMOX_BACKUP_LISTEN_FD`.
@@ -325,8 +452,13 @@ where
         })
         .await?;
 
-    let service = create_service(listener)?;
+    run_daemon(reloader, create_service(listener)?, pidfn).await
+}
 
+async fn run_daemon<S>(reloader: Reloader, service: S, pidfn: Option<&str>) -> Result<(), Error>
+where
+    S: Future<Output = Result<(), Error>>,
+{
     let service = async move {
         if let Err(err) = service.await {
             log::error!("server error: {}", err);
@@ -1,4 +1,6 @@
 use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
 
 use anyhow::Error;
 use nix::fcntl::OFlag;
@@ -23,6 +25,110 @@ pub struct FileLogOptions {
     pub prefix_time: bool,
     /// File owner/group and mode
     pub file_opts: CreateOptions,
+    /// Output format used by [FileLogger::log_access]
+    pub access_log_format: AccessLogFormat,
+}
+
+/// Output format of access logs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// `<client> - <user> [<time>] "<method> <path>" <status> <size> <user agent>`
+    #[default]
+    Native,
+    /// The Combined Log Format used by Apache and nginx
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
+/// A request logged with [FileLogger::log_access]
+pub struct AccessLogEntry<'a> {
+    pub client: IpAddr,
+    /// The authenticated user, if any
+    pub user: Option<&'a str>,
+    /// Time (epoch) of the request
+    pub time: i64,
+    pub method: &'a str,
+    pub path: &'a str,
+    /// Protocol version, e.g. `HTTP/1.1`
+    pub protocol: &'a str,
+    pub status: u16,
+    /// Size of the response body, if known in advance
+    pub size: Option<u64>,
+    /// Time it took to produce the response
+    pub duration: Duration,
+    pub referer: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+}
+
+/// Quote a string for the Combined Log Format.
+fn clf_quote(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("\"{}\"", value.escape_default()),
+        None => "\"-\"".to_string(),
+    }
+}
+
+impl AccessLogEntry<'_> {
+    /// Format the entry as log line (without newline).
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Native => {
+                // time format which apache/nginx use (by default), copied from pve-http-server
+                let datetime = proxmox_time::strftime_local("%d/%m/%Y:%H:%M:%S %z", self.time)
+                    .unwrap_or_else(|_| "-".to_string());
+                format!(
+                    "{} - {} [{}] \"{} {}\" {} {} {}",
+                    self.client,
+                    self.user.unwrap_or("-"),
+                    datetime,
+                    self.method,
+                    self.path,
+                    self.status,
+                    self.size.unwrap_or(0),
+                    self.user_agent.unwrap_or("-"),
+                )
+            }
+            AccessLogFormat::Combined => {
+                let datetime = proxmox_time::Locale::new(libc::LC_ALL, "C")
+                    .and_then(|locale| {
+                        let tm = proxmox_time::localtime(self.time)?;
+                        proxmox_time::strftime_l("%d/%b/%Y:%H:%M:%S %z", &tm, &locale)
+                    })
+                    .unwrap_or_else(|_| "-".to_string());
+                format!(
+                    "{} - {} [{}] \"{} {} {}\" {} {} {} {}",
+                    self.client,
+                    self.user.unwrap_or("-"),
+                    datetime,
+                    self.method,
+                    self.path.escape_default(),
+                    self.protocol,
+                    self.status,
+                    match self.size {
+                        Some(size) if size > 0 => size.to_string(),
+                        _ => "-".to_string(),
+                    },
+                    clf_quote(self.referer),
+                    clf_quote(self.user_agent),
+                )
+            }
+            AccessLogFormat::Json => serde_json::json!({
+                "time": proxmox_time::epoch_to_rfc3339(self.time).ok(),
+                "client": self.client.to_string(),
+                "user": self.user,
+                "method": self.method,
+                "path": self.path,
+                "protocol": self.protocol,
+                "status": self.status,
+                "size": self.size,
+                "duration": self.duration.as_secs_f64(),
+                "referer": self.referer,
+                "user-agent": self.user_agent,
+            })
+            .to_string(),
+        }
+    }
 }
 
 /// Log messages with optional automatically added timestamps into files
@@ -72,6 +178,16 @@ impl FileLogger {
         })
     }
 
+    /// Change the format used by [Self::log_access].
+    pub fn set_access_log_format(&mut self, format: AccessLogFormat) {
+        self.options.access_log_format = format;
+    }
+
+    /// Log a request in the configured [AccessLogFormat].
+    pub fn log_access(&mut self, entry: &AccessLogEntry) {
+        self.log(entry.format(self.options.access_log_format));
+    }
+
     pub fn reopen(&mut self) -> Result<&Self, Error> {
         let file = Self::open(&self.file_name, &self.options)?;
         self.file = file;
@@ -145,3 +261,40 @@ impl std::io::Write for FileLogger {
         self.file.flush()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_access_log_format() {
+        let entry = AccessLogEntry {
+            client: [192, 168, 0, 1].into(),
+            user: Some("root@pam"),
+            time: 0,
+            method: "GET",
+            path: "/api2/json/version",
+            protocol: "HTTP/1.1",
+            status: 200,
+            size: Some(1234),
+            duration: Duration::from_millis(250),
+            referer: None,
+            user_agent: Some("curl/\"8\""),
+        };
+
+        let line = entry.format(AccessLogFormat::Combined);
+        assert!(line.starts_with("192.168.0.1 - root@pam ["));
+        assert!(line
+            .ends_with("] \"GET /api2/json/version HTTP/1.1\" 200 1234 \"-\" \"curl/\\\"8\\\"\""));
+
+        let line = entry.format(AccessLogFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["client"], "192.168.0.1");
+        assert_eq!(value["user"], "root@pam");
+        assert_eq!(value["status"], 200);
+        assert_eq!(value["size"], 1234);
+        assert_eq!(value["duration"], 0.25);
+        assert_eq!(value["referer"], serde_json::Value::Null);
+        assert_eq!(value["user-agent"], "curl/\"8\"");
+    }
+}
@@ -24,6 +24,7 @@ pub struct H2Service<E> {
     rpcenv: E,
     worker: Arc<WorkerTask>,
     debug: bool,
+    max_request_body_size: usize,
 }
 
 impl<E: RpcEnvironment + Clone> H2Service<E> {
@@ -33,9 +34,17 @@ impl<E: RpcEnvironment + Clone> H2Service<E> {
             worker,
             router,
             debug,
+            max_request_body_size: crate::DEFAULT_MAX_REQUEST_BODY_SIZE,
         }
     }
 
+    /// Set the maximum size of request bodies containing API call parameters, see
+    /// [ApiConfig::max_request_body_size](crate::ApiConfig::max_request_body_size).
+    pub fn max_request_body_size(mut self, size: usize) -> Self {
+        self.max_request_body_size = size;
+        self
+    }
+
     pub fn debug<S: AsRef<str>>(&self, msg: S) {
         if self.debug {
             self.worker.log_message(msg);
@@ -70,6 +79,7 @@ impl<E: RpcEnvironment + Clone> H2Service<E> {
                 parts,
                 body,
                 uri_param,
+                self.max_request_body_size,
             )
             .boxed(),
         }
@@ -13,6 +13,7 @@
 //! * extra control socket to trigger management operations
 //!   - logfile rotation
 //!   - worker task management
+//! * scheduled jobs driven by calendar events
 //! * generic interface to authenticate user
 
 use std::fmt;
@@ -42,15 +43,30 @@ mod command_socket;
 pub use command_socket::*;
 
 mod file_logger;
-pub use file_logger::{FileLogOptions, FileLogger};
+pub use file_logger::{AccessLogEntry, AccessLogFormat, FileLogOptions, FileLogger};
+
+mod auth_cache;
+mod request_rate_limit;
+
+mod middleware;
+pub use middleware::{
+    Middleware, MiddlewareAfterFunc, MiddlewareBeforeFunc, MiddlewareBeforeOutput, MiddlewareFuture,
+};
+
+mod cors;
+pub use cors::CorsConfig;
 
 mod api_config;
-pub use api_config::{ApiConfig, AuthError, AuthHandler, IndexHandler, UnixAcceptor};
+pub use api_config::{
+    ApiConfig, AuthError, AuthHandler, IndexHandler, PrivilegedAddr, ReloadableConfig,
+    UnixAcceptor, DEFAULT_MAX_REQUEST_BODY_SIZE,
+};
 
 mod rest;
-pub use rest::{Redirector, RestServer};
+pub use rest::{ConnectionInfo, Redirector, RestServer, TlsInfo, CLIENT_CERT_SUBJECT_HEADER};
 
 pub mod connection;
+mod proxy_protocol;
 
 mod worker_task;
 pub use worker_task::*;
@@ -58,6 +74,9 @@ pub use worker_task::*;
 mod h2service;
 pub use h2service::*;
 
+mod scheduler;
+pub use scheduler::{JobHandler, JobState, ScheduledJob, ScheduledJobStatus, Scheduler};
+
 lazy_static::lazy_static! {
     static ref PID: i32 = unsafe { libc::getpid() };
     static ref PSTART: u64 = PidStat::read_from_pid(Pid::from_raw(*PID)).unwrap().starttime;
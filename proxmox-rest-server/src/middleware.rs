@@ -0,0 +1,202 @@
+//! Request middleware, see [ApiConfig::register_middleware](crate::ApiConfig::register_middleware).
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use anyhow::Error;
+use hyper::http::request::Parts;
+use hyper::{Body, Request, Response};
+
+pub type MiddlewareFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Output of a `before` hook: `Some(response)` stops processing the request and returns the
+/// response instead.
+pub type MiddlewareBeforeOutput = Result<Option<Response<Body>>, Error>;
+
+pub type MiddlewareBeforeFunc = Box<
+    dyn for<'a> Fn(&'a mut Parts, &'a SocketAddr) -> MiddlewareFuture<'a, MiddlewareBeforeOutput>
+        + Send
+        + Sync,
+>;
+
+pub type MiddlewareAfterFunc = Box<
+    dyn for<'a> Fn(&'a Parts, &'a mut Response<Body>) -> MiddlewareFuture<'a, ()> + Send + Sync,
+>;
+
+/// A request middleware with optional hooks running before and after routing.
+///
+/// `before` hooks run in registration order and get the request parts (which they may
+/// modify, e.g. to add headers) and the peer address. `after` hooks run in reverse order and
+/// get the request parts and the produced response, including error responses.
+///
+/// ```
+/// # use proxmox_rest_server::Middleware;
+/// let audit = Middleware::new().after_fn(|parts, response| {
+///     let value = format!("{} {}", parts.method, parts.uri.path());
+///     Box::pin(async move {
+///         response
+///             .headers_mut()
+///             .insert("x-audit", value.parse().unwrap());
+///     })
+/// });
+/// ```
+#[derive(Default)]
+pub struct Middleware {
+    before: Option<MiddlewareBeforeFunc>,
+    after: Option<MiddlewareAfterFunc>,
+}
+
+impl Middleware {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the hook running before routing.
+    pub fn before_fn<Func>(mut self, func: Func) -> Self
+    where
+        Func: for<'a> Fn(
+                &'a mut Parts,
+                &'a SocketAddr,
+            ) -> MiddlewareFuture<'a, MiddlewareBeforeOutput>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.before = Some(Box::new(func));
+        self
+    }
+
+    /// Set the hook running after the response was produced.
+    pub fn after_fn<Func>(mut self, func: Func) -> Self
+    where
+        Func: for<'a> Fn(&'a Parts, &'a mut Response<Body>) -> MiddlewareFuture<'a, ()>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.after = Some(Box::new(func));
+        self
+    }
+}
+
+/// Copy the request parts for the `after` hooks, the original ones are consumed by the
+/// request handler. Extensions are not copied.
+fn copy_parts(parts: &Parts) -> Parts {
+    let (mut copy, ()) = Request::builder()
+        .method(parts.method.clone())
+        .uri(parts.uri.clone())
+        .version(parts.version)
+        .body(())
+        .unwrap()
+        .into_parts();
+    copy.headers = parts.headers.clone();
+    copy
+}
+
+/// Run `handler` wrapped by the `middlewares`.
+pub(crate) async fn run_middlewares<F, Fut>(
+    middlewares: &[Middleware],
+    req: Request<Body>,
+    peer: &SocketAddr,
+    handler: F,
+) -> Response<Body>
+where
+    F: FnOnce(Request<Body>) -> Fut,
+    Fut: Future<Output = Response<Body>>,
+{
+    let (mut parts, body) = req.into_parts();
+
+    let mut response = None;
+    let mut entered = 0;
+    for middleware in middlewares {
+        entered += 1;
+        if let Some(before) = &middleware.before {
+            match before(&mut parts, peer).await {
+                Ok(None) => (),
+                Ok(Some(resp)) => {
+                    response = Some(resp);
+                    break;
+                }
+                Err(err) => {
+                    response = Some(crate::rest::error_to_http_response(err));
+                    break;
+                }
+            }
+        }
+    }
+
+    let request_parts = copy_parts(&parts);
+
+    let mut response = match response {
+        Some(response) => response,
+        None => handler(Request::from_parts(parts, body)).await,
+    };
+
+    for middleware in middlewares[..entered].iter().rev() {
+        if let Some(after) = &middleware.after {
+            after(&request_parts, &mut response).await;
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tag(name: &'static str, short_circuit: bool) -> Middleware {
+        Middleware::new()
+            .before_fn(move |parts, _peer| {
+                Box::pin(async move {
+                    parts.headers.append("x-trace", name.parse().unwrap());
+                    if short_circuit {
+                        return Ok(Some(Response::new(Body::empty())));
+                    }
+                    Ok(None)
+                })
+            })
+            .after_fn(move |_parts, response| {
+                Box::pin(async move {
+                    response
+                        .headers_mut()
+                        .append("x-trace", name.parse().unwrap());
+                })
+            })
+    }
+
+    fn trace(headers: &http::HeaderMap) -> Vec<&str> {
+        headers
+            .get_all("x-trace")
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_middleware_order() {
+        let peer: SocketAddr = "127.0.0.1:8007".parse().unwrap();
+
+        let middlewares = [tag("a", false), tag("b", false)];
+        let response = futures::executor::block_on(run_middlewares(
+            &middlewares,
+            Request::new(Body::empty()),
+            &peer,
+            |req| async move {
+                assert_eq!(trace(req.headers()), ["a", "b"]);
+                Response::new(Body::empty())
+            },
+        ));
+        assert_eq!(trace(response.headers()), ["b", "a"]);
+
+        let middlewares = [tag("a", true), tag("b", false)];
+        let response = futures::executor::block_on(run_middlewares(
+            &middlewares,
+            Request::new(Body::empty()),
+            &peer,
+            |_req| async move { panic!("handler called despite short circuit") },
+        ));
+        assert_eq!(trace(response.headers()), ["a"]);
+    }
+}
@@ -0,0 +1,166 @@
+//! PROXY protocol (version 1 and 2) support, see
+//! <https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt>.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{bail, format_err, Error};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LENGTH: usize = 107;
+const V1_MIN_LENGTH: usize = 15; // "PROXY UNKNOWN\r\n"
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LENGTH: usize = 16;
+
+/// Read the PROXY protocol header from `stream`, without consuming any data after it.
+///
+/// Returns the source address of the proxied connection, or `None` if the proxy did not
+/// provide one (e.g. for health checks).
+pub(crate) async fn read_header<S>(stream: &mut S) -> Result<Option<SocketAddr>, Error>
+where
+    S: AsyncRead + Unpin,
+{
+    // both headers are at least this long, so it is safe to read without looking ahead
+    let mut header = vec![0u8; V1_MIN_LENGTH];
+    stream.read_exact(&mut header).await?;
+
+    if header.starts_with(V2_SIGNATURE) {
+        header.resize(V2_HEADER_LENGTH, 0);
+        stream.read_exact(&mut header[V1_MIN_LENGTH..]).await?;
+        let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+        header.resize(V2_HEADER_LENGTH + len, 0);
+        stream.read_exact(&mut header[V2_HEADER_LENGTH..]).await?;
+    } else if header.starts_with(V1_PREFIX) {
+        // read byte by byte, so nothing after the header is consumed
+        while !header.ends_with(b"\r\n") {
+            if header.len() >= V1_MAX_LENGTH {
+                bail!("PROXY protocol v1 header too long");
+            }
+            header.push(stream.read_u8().await?);
+        }
+    } else {
+        bail!("missing PROXY protocol header");
+    }
+
+    parse_header(&header)
+}
+
+/// Parse a complete PROXY protocol header.
+fn parse_header(header: &[u8]) -> Result<Option<SocketAddr>, Error> {
+    if header.starts_with(V2_SIGNATURE) {
+        parse_v2(header)
+    } else if let Some(line) = header.strip_prefix(V1_PREFIX) {
+        let line = line
+            .strip_suffix(b"\r\n")
+            .ok_or_else(|| format_err!("PROXY protocol v1 header not terminated"))?;
+        parse_v1(std::str::from_utf8(line)?)
+    } else {
+        bail!("missing PROXY protocol header");
+    }
+}
+
+fn parse_v1(line: &str) -> Result<Option<SocketAddr>, Error> {
+    let mut parts = line.split(' ');
+
+    let protocol = parts.next().unwrap_or_default();
+    if protocol == "UNKNOWN" {
+        return Ok(None);
+    }
+
+    let (src, _dst, src_port, _dst_port) = match (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) {
+        (Some(src), Some(dst), Some(src_port), Some(dst_port), None) => {
+            (src, dst, src_port, dst_port)
+        }
+        _ => bail!("invalid PROXY protocol v1 header"),
+    };
+
+    let ip: IpAddr = match protocol {
+        "TCP4" => src.parse::<Ipv4Addr>()?.into(),
+        "TCP6" => src.parse::<Ipv6Addr>()?.into(),
+        _ => bail!("unsupported PROXY protocol v1 protocol '{}'", protocol),
+    };
+
+    Ok(Some(SocketAddr::new(ip, src_port.parse()?)))
+}
+
+fn parse_v2(header: &[u8]) -> Result<Option<SocketAddr>, Error> {
+    let version = header[12] >> 4;
+    let command = header[12] & 0x0f;
+    if version != 2 {
+        bail!("unsupported PROXY protocol version {}", version);
+    }
+
+    let addresses = &header[V2_HEADER_LENGTH..];
+    match command {
+        0 => return Ok(None), // LOCAL
+        1 => (),              // PROXY
+        _ => bail!("unsupported PROXY protocol v2 command {}", command),
+    }
+
+    let family = header[13] >> 4;
+    match family {
+        // AF_INET
+        1 if addresses.len() >= 12 => {
+            let ip: [u8; 4] = addresses[0..4].try_into()?;
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port)))
+        }
+        // AF_INET6
+        2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[0..16].try_into()?;
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        1 | 2 => bail!("PROXY protocol v2 address block too short"),
+        // AF_UNSPEC, AF_UNIX
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn read(mut data: &[u8]) -> Result<(Option<SocketAddr>, &[u8]), Error> {
+        let addr = read_header(&mut data).await?;
+        Ok((addr, data))
+    }
+
+    #[test]
+    fn test_proxy_protocol() -> Result<(), Error> {
+        let rt = tokio::runtime::Builder::new_current_thread().build()?;
+        rt.block_on(async {
+            let (addr, rest) =
+                read(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\nGET /").await?;
+            assert_eq!(addr, Some(([192, 168, 0, 1], 56324).into()));
+            assert_eq!(rest, b"GET /");
+
+            let (addr, _) = read(b"PROXY TCP6 ::1 ::1 4000 443\r\n").await?;
+            assert_eq!(addr, Some("[::1]:4000".parse()?));
+
+            let (addr, rest) = read(b"PROXY UNKNOWN\r\n\x16").await?;
+            assert_eq!(addr, None);
+            assert_eq!(rest, b"\x16");
+
+            let mut v2 = V2_SIGNATURE.to_vec();
+            v2.extend([0x21, 0x11, 0, 12]);
+            v2.extend([10, 0, 0, 1, 10, 0, 0, 2, 0x1f, 0x90, 0x01, 0xbb]);
+            v2.extend(b"\x16\x03");
+            let (addr, rest) = read(&v2).await?;
+            assert_eq!(addr, Some(([10, 0, 0, 1], 8080).into()));
+            assert_eq!(rest, b"\x16\x03");
+
+            assert!(read(b"GET / HTTP/1.1\r\n\r\n").await.is_err());
+            assert!(read(b"PROXY TCP4 192.168.0.1\r\n").await.is_err());
+
+            Ok(())
+        })
+    }
+}
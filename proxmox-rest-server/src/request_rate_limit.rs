@@ -0,0 +1,145 @@
+//! Request rate limiting per user and API path.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper limit for the number of tracked buckets.
+const MAX_BUCKETS: usize = 16 * 1024;
+
+struct RateLimitRule {
+    path_prefix: String,
+    rate: f64,
+    burst: f64,
+}
+
+impl RateLimitRule {
+    fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(&self.path_prefix) {
+            Some(rest) => self.path_prefix.is_empty() || rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, rule: &RateLimitRule, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_update)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rule.rate).min(rule.burst);
+        self.last_update = now;
+    }
+}
+
+/// Token bucket rate limiter for API requests, keyed by the requesting user (or peer address)
+/// and the most specific matching path prefix rule.
+#[derive(Default)]
+pub(crate) struct RequestRateLimiter {
+    rules: Vec<RateLimitRule>,
+    buckets: Mutex<HashMap<(String, usize), Bucket>>,
+}
+
+impl RequestRateLimiter {
+    /// Allow `rate` requests per second with bursts of up to `burst` requests for API paths
+    /// starting with `path_prefix`.
+    pub fn add_rule(&mut self, path_prefix: &str, rate: f64, burst: u64) {
+        let path_prefix = path_prefix.trim_end_matches('/').to_string();
+        let rule = RateLimitRule {
+            path_prefix,
+            rate,
+            burst: burst.max(1) as f64,
+        };
+
+        match self
+            .rules
+            .iter_mut()
+            .find(|r| r.path_prefix == rule.path_prefix)
+        {
+            Some(existing) => *existing = rule,
+            None => self.rules.push(rule),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    /// Account a request of `key` to `path`.
+    ///
+    /// Returns the time to wait before the next request would be allowed if the limit is
+    /// exceeded.
+    pub fn check(&self, key: &str, path: &str) -> Result<(), Duration> {
+        let (index, rule) = match self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.matches(path))
+            .max_by_key(|(_, rule)| rule.path_prefix.len())
+        {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_BUCKETS {
+            // drop buckets which are full again, they behave like new ones anyway
+            let rules = &self.rules;
+            buckets.retain(|(_, index), bucket| {
+                bucket.refill(&rules[*index], now);
+                bucket.tokens < rules[*index].burst
+            });
+        }
+
+        let bucket = buckets
+            .entry((key.to_string(), index))
+            .or_insert_with(|| Bucket {
+                tokens: rule.burst,
+                last_update: now,
+            });
+        bucket.refill(rule, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(
+                Duration::try_from_secs_f64((1.0 - bucket.tokens) / rule.rate)
+                    .unwrap_or(Duration::MAX),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_request_rate_limiter() {
+        let mut limiter = RequestRateLimiter::default();
+        assert!(!limiter.is_enabled());
+        assert!(limiter.check("root@pam", "/nodes").is_ok());
+
+        limiter.add_rule("/", 1000.0, 100);
+        limiter.add_rule("/admin/", 0.001, 2);
+        assert!(limiter.is_enabled());
+
+        assert!(limiter.check("root@pam", "/admin").is_ok());
+        assert!(limiter.check("root@pam", "/admin/datastore").is_ok());
+        let wait = limiter.check("root@pam", "/admin/datastore").unwrap_err();
+        assert!(wait > Duration::from_secs(900));
+
+        // other users, other prefixes and unrelated paths are not affected
+        assert!(limiter.check("user@pam", "/admin").is_ok());
+        assert!(limiter.check("root@pam", "/administration").is_ok());
+        assert!(limiter.check("root@pam", "/nodes").is_ok());
+    }
+}
@@ -10,9 +10,10 @@ use std::task::{Context, Poll};
 use anyhow::{bail, format_err, Error};
 use futures::future::FutureExt;
 use futures::stream::TryStreamExt;
-use hyper::body::HttpBody;
+use hyper::body::{Bytes, HttpBody};
 use hyper::header::{self, HeaderMap};
 use hyper::http::request::Parts;
+use hyper::server::accept::Accept;
 use hyper::{Body, Request, Response, StatusCode};
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -30,11 +31,15 @@ use proxmox_router::{http_bail, http_err};
 use proxmox_schema::{ObjectSchemaType, ParameterSchema};
 
 use proxmox_async::stream::AsyncReaderStream;
+use proxmox_compression::brotli::BrotliEncoder;
+use proxmox_compression::zstd::ZstdEncoder;
 use proxmox_compression::{DeflateEncoder, Level};
 
+use crate::connection::{ListenerConfig, RequestTracker, TimeoutStream};
+use crate::middleware::run_middlewares;
 use crate::{
-    formatter::*, normalize_path, ApiConfig, AuthError, CompressionMethod, FileLogger,
-    RestEnvironment,
+    formatter::*, normalize_path, normalize_path_with_components, should_compress, AccessLogEntry,
+    ApiConfig, AuthError, CompressionMethod, FileLogger, RestEnvironment,
 };
 
 extern "C" {
@@ -61,8 +66,10 @@ impl UserInformation for EmptyUserInformation {
 ///
 /// This struct implements the [Service] trait in order to use it with
 /// [hyper::server::Builder::serve].
+#[derive(Clone)]
 pub struct RestServer {
     api_config: Arc<ApiConfig>,
+    allowed_paths: Option<Arc<[String]>>,
 }
 
 const MAX_URI_QUERY_LENGTH: usize = 3072;
@@ -73,8 +80,116 @@ impl RestServer {
     pub fn new(api_config: ApiConfig) -> Self {
         Self {
             api_config: Arc::new(api_config),
+            allowed_paths: None,
         }
     }
+
+    /// Create a server sharing the configuration with this one, which only serves requests
+    /// whose path starts with the components of one of `prefixes`, so `/api2/json` matches
+    /// `/api2/json/version` but not `/api2/jsonx`.
+    pub fn restricted<I, S>(&self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            api_config: Arc::clone(&self.api_config),
+            allowed_paths: Some(prefixes.into_iter().map(Into::into).collect()),
+        }
+    }
+
+    /// Bind and serve all `listeners` until the server shuts down.
+    ///
+    /// Each listener can use its own TLS settings and path restrictions. All listeners stop
+    /// accepting connections on [shutdown](crate::request_shutdown). Note that, unlike
+    /// [create_daemon](crate::daemon::create_daemon), the listening sockets are not kept
+    /// across daemon reloads.
+    pub async fn serve_listeners(&self, listeners: Vec<ListenerConfig>) -> Result<(), Error> {
+        let mut servers = Vec::new();
+
+        for listener_config in listeners {
+            let ListenerConfig {
+                addr,
+                tls,
+                accept_builder,
+                allowed_paths,
+            } = listener_config;
+
+            let server = match allowed_paths {
+                Some(prefixes) => self.restricted(prefixes),
+                None => self.clone(),
+            };
+
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|err| format_err!("unable to listen on {} - {}", addr, err))?;
+
+            let server_future: Pin<Box<dyn Future<Output = Result<(), hyper::Error>> + Send>> =
+                match tls {
+                    Some(tls) => {
+                        let acceptor = Arc::new(std::sync::Mutex::new(tls.build()?));
+                        let incoming = accept_builder.accept_tls(listener, acceptor);
+                        let builder = server.server_builder(incoming);
+                        Box::pin(
+                            builder
+                                .serve(server)
+                                .with_graceful_shutdown(crate::shutdown_future()),
+                        )
+                    }
+                    None => {
+                        let incoming = hyper::server::conn::AddrIncoming::from_listener(listener)?;
+                        let builder = server.server_builder(incoming);
+                        Box::pin(
+                            builder
+                                .serve(server)
+                                .with_graceful_shutdown(crate::shutdown_future()),
+                        )
+                    }
+                };
+
+            servers.push(server_future);
+        }
+
+        futures::future::try_join_all(servers).await?;
+
+        Ok(())
+    }
+}
+
+impl RestServer {
+    /// Create a hyper server builder for `incoming` with the connection settings of the
+    /// [ApiConfig].
+    ///
+    /// The connections are wrapped in a [TimeoutStream] to enforce the
+    /// [header read](ApiConfig::header_read_timeout) and
+    /// [keep-alive](ApiConfig::keep_alive_timeout) timeouts.
+    pub fn server_builder<I>(
+        &self,
+        incoming: I,
+    ) -> hyper::server::Builder<impl Accept<Conn = TimeoutStream<I::Conn>, Error = I::Error>>
+    where
+        I: Accept,
+    {
+        let header_read_timeout = self.api_config.header_read_timeout;
+        let keep_alive_timeout = self.api_config.keep_alive_timeout;
+
+        let mut incoming = Box::pin(incoming);
+        let incoming = futures::stream::poll_fn(move |cx| {
+            incoming.as_mut().poll_accept(cx).map(|conn| {
+                conn.map(|conn| {
+                    conn.map(|conn| {
+                        TimeoutStream::new(conn, header_read_timeout, keep_alive_timeout)
+                    })
+                })
+            })
+        });
+
+        let mut builder = hyper::Server::builder(hyper::server::accept::from_stream(incoming));
+        if self.api_config.keep_alive_disabled() {
+            builder = builder.http1_keepalive(false);
+        }
+        builder
+    }
 }
 
 impl<T: PeerAddress> Service<&T> for RestServer {
@@ -89,10 +204,21 @@ impl<T: PeerAddress> Service<&T> for RestServer {
     fn call(&mut self, ctx: &T) -> Self::Future {
         std::future::ready(match ctx.peer_addr() {
             Err(err) => Err(format_err!("unable to get peer address - {}", err)),
-            Ok(peer) => Ok(ApiService {
-                peer,
-                api_config: Arc::clone(&self.api_config),
-            }),
+            Ok(peer) => {
+                let tls = ctx.tls_info();
+                let client_cert_subject = tls
+                    .as_ref()
+                    .and_then(|tls| tls.client_certificate_subject.as_deref())
+                    .and_then(|subject| header::HeaderValue::from_str(subject).ok());
+                Ok(ApiService {
+                    peer,
+                    api_config: Arc::clone(&self.api_config),
+                    allowed_paths: self.allowed_paths.clone(),
+                    connection_info: ConnectionInfo { peer, tls },
+                    client_cert_subject,
+                    requests: ctx.request_tracker(),
+                })
+            }
         })
     }
 }
@@ -170,8 +296,114 @@ impl Service<Request<Body>> for RedirectService {
     }
 }
 
+/// Request header containing the subject of the verified TLS client certificate as RFC 4514
+/// string, e.g. `CN=client.example.com,O=Example`.
+///
+/// Special characters in attribute values are escaped, so that values cannot be mistaken for
+/// other attributes. Non-ASCII characters are escaped as UTF-8 hex pairs, e.g. `CN=J\C3\BCrgen`.
+///
+/// It can be used by the authentication handler to map certificates to users or API tokens.
+/// The header is always removed from incoming requests, so it can only be set by the server.
+pub const CLIENT_CERT_SUBJECT_HEADER: &str = "proxmox-client-cert-subject";
+
+/// Information about the connection a request was received on.
+///
+/// Inserted into the extensions of every request handled by the [RestServer], so it is
+/// available to handlers getting the request [Parts] and to authentication handlers created
+/// with [AuthHandler::from_fn_with_connection](crate::AuthHandler::from_fn_with_connection).
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    /// The peer address of the connection (or the one reported by a proxy using the PROXY
+    /// protocol). Note that this does not take the `Forwarded` header into account.
+    pub peer: std::net::SocketAddr,
+    /// TLS session information, `None` for plain text connections.
+    pub tls: Option<TlsInfo>,
+}
+
+/// TLS session information of a connection, see [ConnectionInfo].
+#[derive(Clone, Debug)]
+pub struct TlsInfo {
+    /// The negotiated protocol version, e.g. `TLSv1.3`.
+    pub version: String,
+    /// The negotiated cipher.
+    pub cipher: Option<String>,
+    /// The server name requested by the client via SNI.
+    pub server_name: Option<String>,
+    /// The subject of the verified client certificate, see [CLIENT_CERT_SUBJECT_HEADER].
+    pub client_certificate_subject: Option<String>,
+}
+
 pub trait PeerAddress {
     fn peer_addr(&self) -> Result<std::net::SocketAddr, Error>;
+
+    /// TLS session information, if this is a TLS connection.
+    fn tls_info(&self) -> Option<TlsInfo> {
+        None
+    }
+
+    /// The request tracker of a [TimeoutStream].
+    fn request_tracker(&self) -> Option<Arc<RequestTracker>> {
+        None
+    }
+}
+
+/// Escape an attribute value as described in RFC 4514, section 2.4.
+///
+/// Additionally, all non-printable and non-ASCII characters are escaped as UTF-8 hex pairs, so
+/// that the result can always be used as header value.
+fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '"' | '+' | ',' | ';' | '<' | '>' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' ' if i == 0 || i == last => escaped.push_str("\\ "),
+            '#' if i == 0 => escaped.push_str("\\#"),
+            c if c.is_ascii_graphic() || c == ' ' => escaped.push(c),
+            c => {
+                let mut buf = [0u8; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    escaped.push_str(&format!("\\{byte:02X}"));
+                }
+            }
+        }
+    }
+
+    escaped
+}
+
+/// Format the subject of a certificate as RFC 4514 string, e.g. `CN=client,O=Example`.
+///
+/// Values which are not valid UTF-8 (e.g. `BMPString`s) or contain NUL bytes are represented as
+/// `#` followed by the hex encoded value, so that they cannot be mistaken for other values.
+fn certificate_subject(cert: &openssl::x509::X509Ref) -> String {
+    let mut entries: Vec<String> = cert
+        .subject_name()
+        .entries()
+        .map(|entry| {
+            let object = entry.object();
+            let name = match object.nid().short_name() {
+                Ok(name) => name.to_string(),
+                Err(_) => object.to_string(),
+            };
+            let data = entry.data().as_slice();
+            let value = match std::str::from_utf8(data) {
+                Ok(value) if !value.contains('\0') => escape_dn_value(value),
+                _ => data.iter().fold("#".to_string(), |mut hex, byte| {
+                    hex.push_str(&format!("{byte:02x}"));
+                    hex
+                }),
+            };
+            format!("{name}={value}")
+        })
+        .collect();
+    // RFC 4514 starts with the last RDN
+    entries.reverse();
+    entries.join(",")
 }
 
 // tokio_openssl's SslStream requires the stream to be pinned in order to accept it, and we need to
@@ -181,12 +413,65 @@ impl<T: PeerAddress> PeerAddress for Pin<Box<T>> {
     fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
         T::peer_addr(&**self)
     }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        T::tls_info(&**self)
+    }
+
+    fn request_tracker(&self) -> Option<Arc<RequestTracker>> {
+        T::request_tracker(&**self)
+    }
+}
+
+impl<T: PeerAddress> PeerAddress for TimeoutStream<T> {
+    fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
+        self.get_ref().peer_addr()
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        self.get_ref().tls_info()
+    }
+
+    fn request_tracker(&self) -> Option<Arc<RequestTracker>> {
+        Some(Arc::clone(TimeoutStream::request_tracker(self)))
+    }
 }
 
 impl<T: PeerAddress> PeerAddress for tokio_openssl::SslStream<T> {
     fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
         self.get_ref().peer_addr()
     }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        let ssl = self.ssl();
+
+        // only set if the certificate was verified, see TlsAcceptorBuilder::client_ca_file
+        let client_certificate_subject =
+            if ssl.verify_result() == openssl::x509::X509VerifyResult::OK {
+                ssl.peer_certificate()
+                    .map(|cert| certificate_subject(&cert))
+            } else {
+                None
+            };
+
+        Some(TlsInfo {
+            version: ssl.version_str().to_string(),
+            cipher: ssl.current_cipher().map(|cipher| cipher.name().to_string()),
+            server_name: ssl
+                .servername(openssl::ssl::NameType::HOST_NAME)
+                .map(str::to_string),
+            client_certificate_subject,
+        })
+    }
+}
+
+impl<T: PeerAddress> PeerAddress for crate::connection::ProxiedStream<T> {
+    fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
+        match self.proxied_peer() {
+            Some(peer) => Ok(peer),
+            None => self.get_ref().peer_addr(),
+        }
+    }
 }
 
 impl PeerAddress for tokio::net::TcpStream {
@@ -227,15 +512,73 @@ impl<T: PeerAddress> PeerAddress for proxmox_http::RateLimitedStream<T> {
 pub struct ApiService {
     pub peer: std::net::SocketAddr,
     pub api_config: Arc<ApiConfig>,
+    allowed_paths: Option<Arc<[String]>>,
+    connection_info: ConnectionInfo,
+    client_cert_subject: Option<header::HeaderValue>,
+    requests: Option<Arc<RequestTracker>>,
+}
+
+impl ApiService {
+    /// Create a service for a plain connection from `peer` without path restrictions.
+    pub fn new(peer: std::net::SocketAddr, api_config: Arc<ApiConfig>) -> Self {
+        Self {
+            peer,
+            api_config,
+            allowed_paths: None,
+            connection_info: ConnectionInfo { peer, tls: None },
+            client_cert_subject: None,
+            requests: None,
+        }
+    }
+
+    // Compare whole path components of the normalized path, so neither duplicate slashes nor
+    // partial component matches (`/api2/json` vs. `/api2/jsonx`) get past the restriction.
+    fn path_allowed(&self, path: &str) -> bool {
+        let prefixes = match &self.allowed_paths {
+            Some(prefixes) => prefixes,
+            None => return true,
+        };
+
+        let components = match normalize_path_with_components(path) {
+            Ok((_, components)) => components,
+            Err(_) => return false,
+        };
+
+        prefixes.iter().any(|prefix| {
+            let prefix: Vec<&str> = prefix.split('/').filter(|c| !c.is_empty()).collect();
+            components.starts_with(&prefix)
+        })
+    }
+}
+
+/// Request information needed for the access log.
+struct RequestLogInfo {
+    method: hyper::Method,
+    path: String,
+    version: http::Version,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    start: Instant,
+}
+
+impl RequestLogInfo {
+    fn new(req: &Request<Body>) -> Self {
+        Self {
+            method: req.method().clone(),
+            path: req.uri().path_and_query().unwrap().as_str().to_owned(),
+            version: req.version(),
+            user_agent: get_user_agent(req.headers()),
+            referer: get_referer(req.headers()),
+            start: Instant::now(),
+        }
+    }
 }
 
 fn log_response(
     logfile: Option<&Arc<Mutex<FileLogger>>>,
     peer: &std::net::SocketAddr,
-    method: hyper::Method,
-    path_query: &str,
+    info: RequestLogInfo,
     resp: &Response<Body>,
-    user_agent: Option<String>,
 ) {
     if resp.extensions().get::<NoLogExtension>().is_some() {
         return;
@@ -243,7 +586,7 @@ fn log_response(
 
     // we also log URL-to-long requests, so avoid message bigger than PIPE_BUF (4k on Linux)
     // to profit from atomicty guarantees for O_APPEND opened logfiles
-    let path = &path_query[..MAX_URI_QUERY_LENGTH.min(path_query.len())];
+    let path = &info.path[..MAX_URI_QUERY_LENGTH.min(info.path.len())];
 
     let status = resp.status();
     if !(status.is_success() || status.is_informational()) {
@@ -256,7 +599,7 @@ fn log_response(
 
         log::error!(
             "{} {}: {} {}: [client {}] {}",
-            method.as_str(),
+            info.method.as_str(),
             path,
             status.as_str(),
             reason,
@@ -265,26 +608,25 @@ fn log_response(
         );
     }
     if let Some(logfile) = logfile {
-        let auth_id = match resp.extensions().get::<AuthStringExtension>() {
-            Some(AuthStringExtension(auth_id)) => auth_id.clone(),
-            None => "-".to_string(),
-        };
-        let now = proxmox_time::epoch_i64();
-        // time format which apache/nginx use (by default), copied from pve-http-server
-        let datetime = proxmox_time::strftime_local("%d/%m/%Y:%H:%M:%S %z", now)
-            .unwrap_or_else(|_| "-".to_string());
-
-        logfile.lock().unwrap().log(format!(
-            "{} - {} [{}] \"{} {}\" {} {} {}",
-            peer.ip(),
-            auth_id,
-            datetime,
-            method.as_str(),
+        let auth_id = resp
+            .extensions()
+            .get::<AuthStringExtension>()
+            .map(|AuthStringExtension(auth_id)| auth_id.as_str());
+        let protocol = format!("{:?}", info.version);
+
+        logfile.lock().unwrap().log_access(&AccessLogEntry {
+            client: peer.ip(),
+            user: auth_id,
+            time: proxmox_time::epoch_i64(),
+            method: info.method.as_str(),
             path,
-            status.as_str(),
-            resp.body().size_hint().lower(),
-            user_agent.unwrap_or_else(|| "-".to_string()),
-        ));
+            protocol: &protocol,
+            status: status.as_u16(),
+            size: HttpBody::size_hint(resp.body()).exact(),
+            duration: info.start.elapsed(),
+            referer: info.referer.as_deref(),
+            user_agent: info.user_agent.as_deref(),
+        });
     }
 }
 
@@ -299,6 +641,12 @@ fn get_proxied_peer(headers: &HeaderMap) -> Option<std::net::SocketAddr> {
     rhost.parse().ok()
 }
 
+fn get_referer(headers: &HeaderMap) -> Option<String> {
+    let mut referer = headers.get(header::REFERER)?.to_str().ok()?.to_owned();
+    referer.truncate(MAX_URI_QUERY_LENGTH);
+    Some(referer)
+}
+
 fn get_user_agent(headers: &HeaderMap) -> Option<String> {
     let agent = headers.get(header::USER_AGENT)?.to_str();
     agent
@@ -320,38 +668,54 @@ impl Service<Request<Body>> for ApiService {
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
-        let path = req.uri().path_and_query().unwrap().as_str().to_owned();
-        let method = req.method().clone();
-        let user_agent = get_user_agent(req.headers());
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let info = RequestLogInfo::new(&req);
+
+        let headers = req.headers_mut();
+        headers.remove(CLIENT_CERT_SUBJECT_HEADER);
+        if let Some(subject) = &self.client_cert_subject {
+            headers.insert(CLIENT_CERT_SUBJECT_HEADER, subject.clone());
+        }
+        req.extensions_mut().insert(self.connection_info.clone());
 
         let config = Arc::clone(&self.api_config);
         let peer = match get_proxied_peer(req.headers()) {
             Some(proxied_peer) => proxied_peer,
             None => self.peer,
         };
+        let path_allowed = self.path_allowed(req.uri().path());
+        let request_guard = self.requests.as_ref().map(|requests| requests.start());
         async move {
-            let response = match Arc::clone(&config).handle_request(req, &peer).await {
+            let _request_guard = request_guard;
+            let result = if path_allowed {
+                Arc::clone(&config).handle_request(req, &peer).await
+            } else {
+                Err(http_err!(NOT_FOUND, "path not served on this listener"))
+            };
+            let response = match result {
                 Ok(response) => response,
-                Err(err) => {
-                    let (err, code) = match err.downcast_ref::<HttpError>() {
-                        Some(apierr) => (apierr.message.clone(), apierr.code),
-                        _ => (err.to_string(), StatusCode::BAD_REQUEST),
-                    };
-                    Response::builder()
-                        .status(code)
-                        .extension(ErrorMessageExtension(err.to_string()))
-                        .body(err.into())?
-                }
+                Err(err) => error_to_http_response(err),
             };
             let logger = config.get_access_log();
-            log_response(logger, &peer, method, &path, &response, user_agent);
+            log_response(logger, &peer, info, &response);
             Ok(response)
         }
         .boxed()
     }
 }
 
+/// Convert a request handling error into a plain text response.
+pub(crate) fn error_to_http_response(err: Error) -> Response<Body> {
+    let (err, code) = match err.downcast_ref::<HttpError>() {
+        Some(apierr) => (apierr.message.clone(), apierr.code),
+        _ => (err.to_string(), StatusCode::BAD_REQUEST),
+    };
+    let mut response = Response::new(err.clone().into());
+    *response.status_mut() = code;
+    response.extensions_mut().insert(ErrorMessageExtension(err));
+    response
+}
+
 fn parse_query_parameters<S: 'static + BuildHasher + Send>(
     param_schema: ParameterSchema,
     form: &str, // x-www-form-urlencoded body data
@@ -389,6 +753,7 @@ async fn get_request_parameters<S: 'static + BuildHasher + Send>(
     parts: Parts,
     req_body: Body,
     uri_param: HashMap<String, String, S>,
+    max_body_size: usize,
 ) -> Result<Value, Error> {
     let mut is_json = false;
 
@@ -404,16 +769,24 @@ async fn get_request_parameters<S: 'static + BuildHasher + Send>(
         }
     }
 
+    let content_length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if matches!(content_length, Some(len) if len > max_body_size as u64) {
+        http_bail!(PAYLOAD_TOO_LARGE, "Request body too large");
+    }
+
     let body = TryStreamExt::map_err(req_body, |err| {
         http_err!(BAD_REQUEST, "Problems reading request body: {}", err)
     })
     .try_fold(Vec::new(), |mut acc, chunk| async move {
-        // FIXME: max request body size?
-        if acc.len() + chunk.len() < 64 * 1024 {
+        if acc.len() + chunk.len() <= max_body_size {
             acc.extend_from_slice(&chunk);
             Ok(acc)
         } else {
-            Err(http_err!(BAD_REQUEST, "Request body too large"))
+            Err(http_err!(PAYLOAD_TOO_LARGE, "Request body too large"))
         }
     })
     .await?;
@@ -465,7 +838,7 @@ async fn proxy_protected_request(
 
     let reload_timezone = info.reload_timezone;
 
-    let mut resp = match config.privileged_addr.clone() {
+    let mut resp = match config.get_privileged_addr() {
         None => hyper::client::Client::new().request(request).await?,
         Some(addr) => {
             hyper::client::Client::builder()
@@ -485,6 +858,19 @@ async fn proxy_protected_request(
     Ok(resp)
 }
 
+/// Add the `Retry-After` header to a rate limited response.
+fn too_many_requests(
+    mut response: Response<Body>,
+    retry_after: std::time::Duration,
+) -> Response<Body> {
+    // round up, so that clients do not retry too early
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, header::HeaderValue::from(seconds));
+    response
+}
+
 fn delay_unauth_time() -> std::time::Instant {
     std::time::Instant::now() + std::time::Duration::from_millis(3000)
 }
@@ -500,6 +886,7 @@ pub(crate) async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHa
     parts: Parts,
     req_body: Body,
     uri_param: HashMap<String, String, S>,
+    max_body_size: usize,
 ) -> Result<Response<Body>, Error> {
     let compression = extract_compression_method(&parts.headers);
 
@@ -510,25 +897,29 @@ pub(crate) async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHa
         }
         ApiHandler::StreamingSync(handler) => {
             let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
+                get_request_parameters(info.parameters, parts, req_body, uri_param, max_body_size)
+                    .await?;
             (handler)(params, info, &mut rpcenv)
                 .and_then(|data| formatter.format_data_streaming(data, &rpcenv))
         }
         ApiHandler::StreamingAsync(handler) => {
             let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
+                get_request_parameters(info.parameters, parts, req_body, uri_param, max_body_size)
+                    .await?;
             (handler)(params, info, &mut rpcenv)
                 .await
                 .and_then(|data| formatter.format_data_streaming(data, &rpcenv))
         }
         ApiHandler::Sync(handler) => {
             let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
+                get_request_parameters(info.parameters, parts, req_body, uri_param, max_body_size)
+                    .await?;
             (handler)(params, info, &mut rpcenv).map(|data| formatter.format_data(data, &rpcenv))
         }
         ApiHandler::Async(handler) => {
             let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
+                get_request_parameters(info.parameters, parts, req_body, uri_param, max_body_size)
+                    .await?;
             (handler)(params, info, &mut rpcenv)
                 .await
                 .map(|data| formatter.format_data(data, &rpcenv))
@@ -538,7 +929,7 @@ pub(crate) async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHa
         }
     };
 
-    let mut resp = match result {
+    let resp = match result {
         Ok(resp) => resp,
         Err(err) => {
             if let Some(httperr) = err.downcast_ref::<HttpError>() {
@@ -550,23 +941,7 @@ pub(crate) async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHa
         }
     };
 
-    let resp = match compression {
-        Some(CompressionMethod::Deflate) => {
-            resp.headers_mut().insert(
-                header::CONTENT_ENCODING,
-                CompressionMethod::Deflate.content_encoding(),
-            );
-            resp.map(|body| {
-                Body::wrap_stream(DeflateEncoder::with_quality(
-                    TryStreamExt::map_err(body, |err| {
-                        proxmox_lang::io_format_err!("error during compression: {}", err)
-                    }),
-                    Level::Default,
-                ))
-            })
-        }
-        None => resp,
-    };
+    let resp = compress_response(resp, compression)?;
 
     if info.reload_timezone {
         unsafe {
@@ -583,6 +958,7 @@ async fn handle_unformatted_api_request<Env: RpcEnvironment, S: 'static + BuildH
     parts: Parts,
     req_body: Body,
     uri_param: HashMap<String, String, S>,
+    max_body_size: usize,
 ) -> Result<Response<Body>, Error> {
     let compression = extract_compression_method(&parts.headers);
 
@@ -609,12 +985,14 @@ async fn handle_unformatted_api_request<Env: RpcEnvironment, S: 'static + BuildH
         }
         ApiHandler::Sync(handler) => {
             let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
+                get_request_parameters(info.parameters, parts, req_body, uri_param, max_body_size)
+                    .await?;
             (handler)(params, info, &mut rpcenv).and_then(|v| to_json_response(v, &rpcenv))
         }
         ApiHandler::Async(handler) => {
             let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
+                get_request_parameters(info.parameters, parts, req_body, uri_param, max_body_size)
+                    .await?;
             (handler)(params, info, &mut rpcenv)
                 .await
                 .and_then(|v| to_json_response(v, &rpcenv))
@@ -632,7 +1010,7 @@ async fn handle_unformatted_api_request<Env: RpcEnvironment, S: 'static + BuildH
         }
     };
 
-    let mut resp = match result {
+    let resp = match result {
         Ok(resp) => resp,
         Err(err) => {
             if let Some(httperr) = err.downcast_ref::<HttpError>() {
@@ -644,23 +1022,7 @@ async fn handle_unformatted_api_request<Env: RpcEnvironment, S: 'static + BuildH
         }
     };
 
-    let resp = match compression {
-        Some(CompressionMethod::Deflate) => {
-            resp.headers_mut().insert(
-                header::CONTENT_ENCODING,
-                CompressionMethod::Deflate.content_encoding(),
-            );
-            resp.map(|body| {
-                Body::wrap_stream(DeflateEncoder::with_quality(
-                    TryStreamExt::map_err(body, |err| {
-                        proxmox_lang::io_format_err!("error during compression: {}", err)
-                    }),
-                    Level::Default,
-                ))
-            })
-        }
-        None => resp,
-    };
+    let resp = compress_response(resp, compression)?;
 
     if info.reload_timezone {
         unsafe {
@@ -700,6 +1062,61 @@ fn extension_to_content_type(filename: &Path) -> (&'static str, bool) {
     ("application/octet-stream", false)
 }
 
+/// Wrap `stream` with an encoder for the given compression method.
+fn compress_stream<S, O>(method: CompressionMethod, stream: S) -> Result<Body, Error>
+where
+    S: futures::Stream<Item = Result<O, io::Error>> + Send + Unpin + 'static,
+    O: Into<Bytes> + 'static,
+{
+    Ok(match method {
+        CompressionMethod::Deflate => {
+            Body::wrap_stream(DeflateEncoder::with_quality(stream, Level::Default))
+        }
+        CompressionMethod::Brotli => Body::wrap_stream(BrotliEncoder::new(stream)),
+        CompressionMethod::Zstd => Body::wrap_stream(ZstdEncoder::new(stream)?),
+    })
+}
+
+/// Compress the response body, unless it is already encoded or not worth compressing.
+fn compress_response(
+    mut resp: Response<Body>,
+    compression: Option<CompressionMethod>,
+) -> Result<Response<Body>, Error> {
+    let method = match compression {
+        Some(method) => method,
+        None => return Ok(resp),
+    };
+
+    let headers = resp.headers();
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        return Ok(resp);
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    if !should_compress(content_type, resp.body().size_hint().exact()) {
+        return Ok(resp);
+    }
+
+    let headers = resp.headers_mut();
+    headers.insert(header::CONTENT_ENCODING, method.content_encoding());
+    headers.append(
+        header::VARY,
+        header::HeaderValue::from_static("Accept-Encoding"),
+    );
+    headers.remove(header::CONTENT_LENGTH);
+
+    let (parts, body) = resp.into_parts();
+    let body = compress_stream(
+        method,
+        TryStreamExt::map_err(body, |err| {
+            proxmox_lang::io_format_err!("error during compression: {}", err)
+        }),
+    )?;
+
+    Ok(Response::from_parts(parts, body))
+}
+
 async fn simple_static_file_download(
     mut file: File,
     content_type: &'static str,
@@ -708,25 +1125,24 @@ async fn simple_static_file_download(
     use tokio::io::AsyncReadExt;
 
     let mut data: Vec<u8> = Vec::new();
+    file.read_to_end(&mut data)
+        .await
+        .map_err(|err| http_err!(BAD_REQUEST, "File read failed: {}", err))?;
 
     let mut response = match compression {
-        Some(CompressionMethod::Deflate) => {
-            let mut enc = DeflateEncoder::with_quality(data, Level::Default);
-            enc.compress_vec(&mut file, CHUNK_SIZE_LIMIT as usize)
-                .await?;
-            let mut response = Response::new(enc.into_inner().into());
-            response.headers_mut().insert(
-                header::CONTENT_ENCODING,
-                CompressionMethod::Deflate.content_encoding(),
+        Some(method) => {
+            let stream = futures::stream::iter([Ok::<_, io::Error>(data)]);
+            let data = hyper::body::to_bytes(compress_stream(method, stream)?).await?;
+            let mut response = Response::new(data.into());
+            let headers = response.headers_mut();
+            headers.insert(header::CONTENT_ENCODING, method.content_encoding());
+            headers.insert(
+                header::VARY,
+                header::HeaderValue::from_static("Accept-Encoding"),
             );
             response
         }
-        None => {
-            file.read_to_end(&mut data)
-                .await
-                .map_err(|err| http_err!(BAD_REQUEST, "File read failed: {}", err))?;
-            Response::new(data.into())
-        }
+        None => Response::new(data.into()),
     };
 
     response.headers_mut().insert(
@@ -747,15 +1163,11 @@ async fn chunked_static_file_download(
         .header(header::CONTENT_TYPE, content_type);
 
     let body = match compression {
-        Some(CompressionMethod::Deflate) => {
-            resp = resp.header(
-                header::CONTENT_ENCODING,
-                CompressionMethod::Deflate.content_encoding(),
-            );
-            Body::wrap_stream(DeflateEncoder::with_quality(
-                AsyncReaderStream::new(file),
-                Level::Default,
-            ))
+        Some(method) => {
+            resp = resp
+                .header(header::CONTENT_ENCODING, method.content_encoding())
+                .header(header::VARY, "Accept-Encoding");
+            compress_stream(method, AsyncReaderStream::new(file))?
         }
         None => Body::wrap_stream(AsyncReaderStream::new(file)),
     };
@@ -763,10 +1175,194 @@ async fn chunked_static_file_download(
     Ok(resp.body(body).unwrap())
 }
 
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Format `epoch` as HTTP date (IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`).
+fn format_http_date(epoch: i64) -> Result<String, Error> {
+    let locale = proxmox_time::Locale::new(libc::LC_ALL, "C")?;
+    proxmox_time::strftime_l(
+        "%a, %d %b %Y %T GMT",
+        &proxmox_time::gmtime(epoch)?,
+        &locale,
+    )
+}
+
+/// Parse a HTTP date in IMF-fixdate format. The obsolete formats are not supported.
+fn parse_http_date(date: &str) -> Result<i64, Error> {
+    let parts: Vec<&str> = date.split_ascii_whitespace().collect();
+    if parts.len() != 6 || parts[5] != "GMT" {
+        bail!("invalid http date '{}'", date);
+    }
+
+    let time: Vec<&str> = parts[4].split(':').collect();
+    if time.len() != 3 {
+        bail!("invalid http date '{}'", date);
+    }
+
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_mday = parts[1].parse()?;
+    tm.tm_mon = MONTHS
+        .iter()
+        .position(|m| *m == parts[2])
+        .ok_or_else(|| format_err!("invalid month in http date '{}'", date))?
+        as libc::c_int;
+    tm.tm_year = parts[3].parse::<libc::c_int>()? - 1900;
+    tm.tm_hour = time[0].parse()?;
+    tm.tm_min = time[1].parse()?;
+    tm.tm_sec = time[2].parse()?;
+
+    proxmox_time::timegm(&mut tm)
+}
+
+/// Strong entity tag of a static file, derived from its size and modification time. The
+/// compression method is part of the tag, since each encoding is a different representation.
+fn static_file_etag(
+    metadata: &std::fs::Metadata,
+    compression: Option<CompressionMethod>,
+) -> String {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut etag = format!(
+        "\"{:x}-{:x}.{:x}",
+        metadata.size(),
+        metadata.mtime(),
+        metadata.mtime_nsec()
+    );
+    if let Some(method) = compression {
+        etag.push('-');
+        etag.push_str(method.extension());
+    }
+    etag.push('"');
+    etag
+}
+
+/// Evaluate `If-None-Match` and `If-Modified-Since`, returns true if the representation cached by
+/// the client is still up to date.
+fn static_file_not_modified(headers: &HeaderMap, etag: &str, mtime: i64) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        // If-Modified-Since must be ignored if If-None-Match is present
+        return match if_none_match.to_str() {
+            // weak comparison, see RFC 9110, section 13.1.2
+            Ok(tags) => tags
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag),
+            Err(_) => false,
+        };
+    }
+
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_http_date(value).ok())
+        .is_some_and(|since| mtime <= since)
+}
+
+/// Result of evaluating a `Range` request header.
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    /// No (usable) range requested, send the whole file.
+    Full,
+    /// Send the bytes from `start` to `end` (inclusive).
+    Partial(u64, u64),
+    /// The range does not overlap with the file.
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header for a file of `size` bytes.
+///
+/// Only single byte ranges are supported, everything else is ignored (which is allowed by RFC
+/// 9110, section 14.2).
+fn parse_byte_range(range: &str, size: u64) -> ByteRange {
+    let spec = match range.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return ByteRange::Full,
+    };
+
+    let (start, end) = match spec.split_once('-') {
+        Some(parts) => parts,
+        None => return ByteRange::Full,
+    };
+
+    if start.is_empty() {
+        // suffix range, the last `end` bytes
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(size.saturating_sub(suffix), size - 1),
+            Err(_) => ByteRange::Full,
+        };
+    }
+
+    let start = match start.parse::<u64>() {
+        Ok(start) => start,
+        Err(_) => return ByteRange::Full,
+    };
+    let end = if end.is_empty() {
+        u64::MAX
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return ByteRange::Full,
+        }
+    };
+
+    if start >= size {
+        return ByteRange::Unsatisfiable;
+    }
+
+    ByteRange::Partial(start, end.min(size - 1))
+}
+
+/// Evaluate `If-Range`, returns true if the `Range` header should be honored.
+fn static_file_if_range(headers: &HeaderMap, etag: &str, mtime: i64) -> bool {
+    let if_range = match headers.get(header::IF_RANGE) {
+        Some(value) => value,
+        None => return true,
+    };
+
+    match if_range.to_str() {
+        // entity tags require a strong comparison, so weak tags never match
+        Ok(value) if value.starts_with('"') || value.starts_with("W/") => value == etag,
+        Ok(value) => parse_http_date(value).is_ok_and(|date| date == mtime),
+        Err(_) => false,
+    }
+}
+
+async fn ranged_static_file_download(
+    mut file: File,
+    content_type: &'static str,
+    start: u64,
+    end: u64,
+    size: u64,
+) -> Result<Response<Body>, Error> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    file.seek(io::SeekFrom::Start(start))
+        .await
+        .map_err(|err| http_err!(BAD_REQUEST, "File seek failed: {}", err))?;
+
+    let length = end - start + 1;
+    let body = Body::wrap_stream(AsyncReaderStream::new(file.take(length)));
+
+    Ok(Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, length)
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, size),
+        )
+        .body(body)?)
+}
+
 async fn handle_static_file_download(
     components: &[&str],
     filename: PathBuf,
     compression: Option<CompressionMethod>,
+    headers: &HeaderMap,
 ) -> Result<Response<Body>, Error> {
     let metadata = match tokio::fs::metadata(filename.clone()).await {
         Ok(metadata) => metadata,
@@ -781,35 +1377,86 @@ async fn handle_static_file_download(
         ),
     };
 
+    let size = metadata.len();
+    let last_modified = {
+        use std::os::unix::fs::MetadataExt;
+        metadata.mtime()
+    };
+
+    let range = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range)
+            if static_file_if_range(headers, &static_file_etag(&metadata, None), last_modified) =>
+        {
+            parse_byte_range(range, size)
+        }
+        _ => ByteRange::Full,
+    };
+
     let (content_type, nocomp) = extension_to_content_type(&filename);
-    let compression = if nocomp { None } else { compression };
+    // ranges refer to the unencoded file, so never compress partial responses
+    let compression =
+        if nocomp || range != ByteRange::Full || !should_compress(Some(content_type), Some(size)) {
+            None
+        } else {
+            compression
+        };
 
-    let file = File::open(filename).await.map_err(|err| {
-        http_err!(
-            BAD_REQUEST,
-            "File open failed for '{}': {}",
-            components.join("/"),
-            err.kind()
-        )
-    })?;
+    let etag = static_file_etag(&metadata, compression);
 
-    if metadata.len() < CHUNK_SIZE_LIMIT {
-        simple_static_file_download(file, content_type, compression).await
+    let mut response = if static_file_not_modified(headers, &etag, last_modified) {
+        let mut response = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())?;
+        if compression.is_some() {
+            response.headers_mut().insert(
+                header::VARY,
+                header::HeaderValue::from_static("Accept-Encoding"),
+            );
+        }
+        response
+    } else if range == ByteRange::Unsatisfiable {
+        Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+            .body(Body::empty())?
     } else {
-        chunked_static_file_download(file, content_type, compression).await
-    }
+        let file = File::open(filename).await.map_err(|err| {
+            http_err!(
+                BAD_REQUEST,
+                "File open failed for '{}': {}",
+                components.join("/"),
+                err.kind()
+            )
+        })?;
+
+        if let ByteRange::Partial(start, end) = range {
+            ranged_static_file_download(file, content_type, start, end, size).await?
+        } else if size < CHUNK_SIZE_LIMIT {
+            simple_static_file_download(file, content_type, compression).await?
+        } else {
+            chunked_static_file_download(file, content_type, compression).await?
+        }
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(
+        header::ACCEPT_RANGES,
+        header::HeaderValue::from_static("bytes"),
+    );
+    headers.insert(header::ETAG, etag.parse()?);
+    headers.insert(
+        header::LAST_MODIFIED,
+        format_http_date(last_modified)?.parse()?,
+    );
+
+    Ok(response)
 }
 
-// FIXME: support handling multiple compression methods
 fn extract_compression_method(headers: &http::HeaderMap) -> Option<CompressionMethod> {
-    if let Some(Ok(encodings)) = headers.get(header::ACCEPT_ENCODING).map(|v| v.to_str()) {
-        for encoding in encodings.split(&[',', ' '][..]) {
-            if let Ok(method) = encoding.parse() {
-                return Some(method);
-            }
-        }
-    }
-    None
+    headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .and_then(CompressionMethod::from_accept_encoding)
 }
 
 impl ApiConfig {
@@ -817,6 +1464,27 @@ impl ApiConfig {
         self: Arc<ApiConfig>,
         req: Request<Body>,
         peer: &std::net::SocketAddr,
+    ) -> Result<Response<Body>, Error> {
+        if self.middlewares().is_empty() {
+            return self.handle_request_impl(req, peer).await;
+        }
+
+        let config = Arc::clone(&self);
+        let response = run_middlewares(self.middlewares(), req, peer, |req| async move {
+            match config.handle_request_impl(req, peer).await {
+                Ok(response) => response,
+                Err(err) => error_to_http_response(err),
+            }
+        })
+        .await;
+
+        Ok(response)
+    }
+
+    async fn handle_request_impl(
+        self: Arc<ApiConfig>,
+        req: Request<Body>,
+        peer: &std::net::SocketAddr,
     ) -> Result<Response<Body>, Error> {
         let (parts, body) = req.into_parts();
         let method = parts.method.clone();
@@ -856,7 +1524,7 @@ impl ApiConfig {
         }
 
         if components.is_empty() {
-            match self.check_auth(&parts.headers, &method).await {
+            match self.check_auth(&parts).await {
                 Ok((auth_id, _user_info)) => {
                     rpcenv.set_auth_id(Some(auth_id));
                     return Ok(self.get_index(rpcenv, parts).await);
@@ -870,7 +1538,7 @@ impl ApiConfig {
         } else {
             let filename = self.find_alias(&components);
             let compression = extract_compression_method(&parts.headers);
-            handle_static_file_download(&components, filename, compression).await
+            handle_static_file_download(&components, filename, compression, &parts.headers).await
         }
     }
 }
@@ -981,7 +1649,7 @@ impl Formatted {
             Box::new(EmptyUserInformation {});
 
         if auth_required {
-            match config.check_auth(&parts.headers, &parts.method).await {
+            match config.check_auth(&parts).await {
                 Ok((authid, info)) => {
                     rpcenv.set_auth_id(Some(authid));
                     user_info = info;
@@ -1004,13 +1672,21 @@ impl Formatted {
             }
         }
 
+        let api_path = format!("/{}", relative_path_components[1..].join("/"));
+        let auth_id = rpcenv.get_auth_id();
+        if let Err(retry_after) =
+            config.check_request_rate_limit(auth_id.as_deref(), peer, &api_path)
+        {
+            let err = http_err!(TOO_MANY_REQUESTS, "too many requests");
+            return Ok(too_many_requests(formatter.format_error(err), retry_after));
+        }
+
         match api_method {
             None => {
                 let err = http_err!(NOT_FOUND, "Path '{}' not found.", full_path);
                 Ok(formatter.format_error(err))
             }
             Some(api_method) => {
-                let auth_id = rpcenv.get_auth_id();
                 let user_info = user_info;
 
                 if !check_api_permission(
@@ -1024,13 +1700,21 @@ impl Formatted {
                     return Ok(formatter.format_error(err));
                 }
 
-                let result = if api_method.protected
-                    && rpcenv.env_type == RpcEnvironmentType::PUBLIC
-                {
-                    proxy_protected_request(config, api_method, parts, body, peer).await
-                } else {
-                    handle_api_request(rpcenv, api_method, formatter, parts, body, uri_param).await
-                };
+                let result =
+                    if api_method.protected && rpcenv.env_type == RpcEnvironmentType::PUBLIC {
+                        proxy_protected_request(config, api_method, parts, body, peer).await
+                    } else {
+                        handle_api_request(
+                            rpcenv,
+                            api_method,
+                            formatter,
+                            parts,
+                            body,
+                            uri_param,
+                            config.max_request_body_size,
+                        )
+                        .await
+                    };
 
                 let mut response = match result {
                     Ok(resp) => resp,
@@ -1087,7 +1771,7 @@ impl Unformatted {
         let user_info: Box<dyn UserInformation + Send + Sync>;
 
         if auth_required {
-            match config.check_auth(&parts.headers, &parts.method).await {
+            match config.check_auth(&parts).await {
                 Ok((authid, info)) => {
                     rpcenv.set_auth_id(Some(authid));
                     user_info = info;
@@ -1112,10 +1796,21 @@ impl Unformatted {
             user_info = Box::new(EmptyUserInformation {});
         }
 
+        let api_path = format!("/{}", relative_path_components.join("/"));
+        let auth_id = rpcenv.get_auth_id();
+        if let Err(retry_after) =
+            config.check_request_rate_limit(auth_id.as_deref(), peer, &api_path)
+        {
+            let err = http_err!(TOO_MANY_REQUESTS, "too many requests");
+            return Ok(too_many_requests(
+                crate::formatter::error_to_response(err),
+                retry_after,
+            ));
+        }
+
         match api_method {
             None => http_bail!(NOT_FOUND, "Path '{}' not found.", full_path),
             Some(api_method) => {
-                let auth_id = rpcenv.get_auth_id();
                 let user_info = user_info;
 
                 if !check_api_permission(
@@ -1129,13 +1824,20 @@ impl Unformatted {
                     return Err(err);
                 }
 
-                let result = if api_method.protected
-                    && rpcenv.env_type == RpcEnvironmentType::PUBLIC
-                {
-                    proxy_protected_request(config, api_method, parts, body, peer).await
-                } else {
-                    handle_unformatted_api_request(rpcenv, api_method, parts, body, uri_param).await
-                };
+                let result =
+                    if api_method.protected && rpcenv.env_type == RpcEnvironmentType::PUBLIC {
+                        proxy_protected_request(config, api_method, parts, body, peer).await
+                    } else {
+                        handle_unformatted_api_request(
+                            rpcenv,
+                            api_method,
+                            parts,
+                            body,
+                            uri_param,
+                            config.max_request_body_size,
+                        )
+                        .await
+                    };
 
                 let mut response = match result {
                     Ok(resp) => resp,
@@ -1153,3 +1855,169 @@ impl Unformatted {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_certificate_subject() {
+        let mut name = openssl::x509::X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("C", "AT").unwrap();
+        name.append_entry_by_text("O", "Jürgen's Lab").unwrap();
+        name.append_entry_by_text("CN", " x,O=Proxmox+\\#").unwrap();
+        let name = name.build();
+
+        let mut cert = openssl::x509::X509Builder::new().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        let cert = cert.build();
+
+        let subject = certificate_subject(&cert);
+        assert_eq!(
+            subject,
+            "CN=\\ x\\,O=Proxmox\\+\\\\#,O=J\\C3\\BCrgen's Lab,C=AT"
+        );
+        assert!(header::HeaderValue::from_str(&subject).is_ok());
+
+        assert_eq!(escape_dn_value("#a b "), "\\#a b\\ ");
+    }
+
+    #[test]
+    fn test_http_date() {
+        let date = format_http_date(784111777).unwrap();
+        assert_eq!(date, "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date(&date).unwrap(), 784111777);
+
+        assert!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT").is_err());
+        assert!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT").is_err());
+    }
+
+    #[test]
+    fn test_static_file_not_modified() {
+        let etag = "\"400-5f5e100.0\"";
+        let mtime = 784111777;
+
+        let mut headers = HeaderMap::new();
+        assert!(!static_file_not_modified(&headers, etag, mtime));
+
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            "Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap(),
+        );
+        assert!(static_file_not_modified(&headers, etag, mtime));
+        assert!(!static_file_not_modified(&headers, etag, mtime + 1));
+
+        // If-None-Match takes precedence
+        headers.insert(header::IF_NONE_MATCH, "\"other\"".parse().unwrap());
+        assert!(!static_file_not_modified(&headers, etag, mtime));
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            "\"other\", W/\"400-5f5e100.0\"".parse().unwrap(),
+        );
+        assert!(static_file_not_modified(&headers, etag, mtime + 1));
+
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(static_file_not_modified(&headers, etag, mtime));
+    }
+
+    #[test]
+    fn test_byte_range() {
+        use ByteRange::*;
+
+        assert_eq!(parse_byte_range("bytes=0-99", 1000), Partial(0, 99));
+        assert_eq!(parse_byte_range("bytes=500-", 1000), Partial(500, 999));
+        assert_eq!(parse_byte_range("bytes=900-2000", 1000), Partial(900, 999));
+        assert_eq!(parse_byte_range("bytes=-100", 1000), Partial(900, 999));
+        assert_eq!(parse_byte_range("bytes=-2000", 1000), Partial(0, 999));
+
+        assert_eq!(parse_byte_range("bytes=1000-", 1000), Unsatisfiable);
+        assert_eq!(parse_byte_range("bytes=-0", 1000), Unsatisfiable);
+        assert_eq!(parse_byte_range("bytes=-10", 0), Unsatisfiable);
+
+        // ignored
+        assert_eq!(parse_byte_range("bytes=0-1, 5-6", 1000), Full);
+        assert_eq!(parse_byte_range("bytes=10-5", 1000), Full);
+        assert_eq!(parse_byte_range("items=0-5", 1000), Full);
+        assert_eq!(parse_byte_range("bytes=a-b", 1000), Full);
+    }
+
+    #[test]
+    fn test_restricted_paths() {
+        let server = RestServer::new(ApiConfig::new("/", RpcEnvironmentType::PUBLIC));
+        let peer: std::net::SocketAddr = ([127, 0, 0, 1], 1234).into();
+        let service = |server: &RestServer| {
+            let mut service = ApiService::new(peer, Arc::clone(&server.api_config));
+            service.allowed_paths = server.allowed_paths.clone();
+            service
+        };
+
+        let unrestricted = service(&server);
+        assert!(unrestricted.path_allowed("/api2/json/version"));
+        assert!(unrestricted.path_allowed("/"));
+
+        let restricted = service(&server.restricted(["/api2/json/"]));
+        assert!(restricted.path_allowed("/api2/json/version"));
+        assert!(!restricted.path_allowed("/api2/extjs/version"));
+        assert!(!restricted.path_allowed("/"));
+
+        assert!(restricted.path_allowed("//api2//json/version"));
+        assert!(restricted.path_allowed("/api2/json"));
+        assert!(!restricted.path_allowed("/api2/jsonx/version"));
+        assert!(!restricted.path_allowed("/api2/json/../extjs/version"));
+    }
+
+    #[test]
+    fn test_max_request_body_size() {
+        const SCHEMA: proxmox_schema::ObjectSchema = proxmox_schema::ObjectSchema::new(
+            "test",
+            &[(
+                "data",
+                true,
+                &proxmox_schema::StringSchema::new("data").schema(),
+            )],
+        );
+
+        let request = |body: &'static str, content_length: Option<usize>| {
+            let mut request = Request::post("/").header(header::CONTENT_TYPE, "application/json");
+            if let Some(len) = content_length {
+                request = request.header(header::CONTENT_LENGTH, len);
+            }
+            let (parts, _) = request.body(()).unwrap().into_parts();
+            let body = Body::wrap_stream(futures::stream::iter(
+                body.as_bytes()
+                    .chunks(4)
+                    .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()))
+                    .collect::<Vec<_>>(),
+            ));
+            let uri_param: HashMap<String, String> = HashMap::new();
+            futures::executor::block_on(get_request_parameters(
+                ParameterSchema::from(&SCHEMA),
+                parts,
+                body,
+                uri_param,
+                16,
+            ))
+        };
+        let status = |result: Result<Value, Error>| {
+            result
+                .unwrap_err()
+                .downcast::<HttpError>()
+                .expect("expected HTTP error")
+                .code
+        };
+
+        assert_eq!(
+            request(r#"{"data":"abc"}"#, None).unwrap(),
+            serde_json::json!({ "data": "abc" }),
+        );
+        assert_eq!(
+            status(request(r#"{"data":"abcdefgh"}"#, None)),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        );
+        assert_eq!(
+            status(request("{}", Some(17))),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        );
+    }
+}
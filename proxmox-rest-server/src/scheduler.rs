@@ -0,0 +1,454 @@
+//! Run worker tasks periodically, driven by calendar events.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+use proxmox_time::CalendarEvent;
+
+use crate::CommandSocket;
+
+/// Handler starting a job, returns the UPID of the spawned worker task.
+///
+/// Called with the job id.
+pub type JobHandler = Box<dyn Fn(&str) -> Result<String, Error> + Send + Sync>;
+
+/// Definition of a scheduled job.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScheduledJob {
+    /// Unique job id.
+    pub id: String,
+    /// The job type, selects the handler registered with [Scheduler::register_job_type].
+    pub job_type: String,
+    /// A calendar event, see [CalendarEvent].
+    pub schedule: String,
+    /// Run the job once if one or more runs were missed (e.g. because the server was down).
+    #[serde(default)]
+    pub run_missed: bool,
+}
+
+/// Persistent state of a scheduled job.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct JobState {
+    /// Time the job was last run (or scheduled, when it was created).
+    #[serde(default)]
+    pub last_run: i64,
+    /// UPID of the last started worker task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_upid: Option<String>,
+    /// Set if runs were missed since the last run.
+    #[serde(default)]
+    pub missed: bool,
+}
+
+/// Status of a scheduled job as returned by [Scheduler::jobs].
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScheduledJobStatus {
+    #[serde(flatten)]
+    pub job: ScheduledJob,
+    #[serde(flatten)]
+    pub state: JobState,
+    /// Next time the job will run.
+    pub next_run: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JobEntry {
+    #[serde(flatten)]
+    job: ScheduledJob,
+    #[serde(default)]
+    state: JobState,
+}
+
+/// Persistent job scheduler.
+///
+/// Job definitions and their state are stored as JSON in a single file. The scheduler checks
+/// for due jobs once per minute and starts them using the handler registered for their job
+/// type. If several runs were missed (e.g. because the server was down), the job is flagged and
+/// only run once, if [ScheduledJob::run_missed] is set.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use anyhow::Error;
+/// # use proxmox_rest_server::{Scheduler, ScheduledJob, WorkerTask};
+/// # fn example() -> Result<(), Error> {
+/// let scheduler = Scheduler::new("/var/lib/myproduct/jobs.json", Default::default())?
+///     .register_job_type("garbage-collection", |id| {
+///         WorkerTask::spawn("gc", Some(id.to_string()), "root@pam".into(), false, |worker| {
+///             async move { Ok(()) }
+///         })
+///     });
+///
+/// scheduler.add_job(ScheduledJob {
+///     id: "gc-store1".into(),
+///     job_type: "garbage-collection".into(),
+///     schedule: "daily".into(),
+///     run_missed: true,
+/// })?;
+///
+/// Arc::new(scheduler).spawn();
+/// # Ok(())
+/// # }
+/// ```
+pub struct Scheduler {
+    path: PathBuf,
+    file_opts: CreateOptions,
+    handlers: HashMap<String, JobHandler>,
+    jobs: Mutex<Vec<JobEntry>>,
+}
+
+impl Scheduler {
+    /// Create a new scheduler, loading the jobs stored in `path`.
+    pub fn new<P: Into<PathBuf>>(path: P, file_opts: CreateOptions) -> Result<Self, Error> {
+        let path = path.into();
+        let jobs = Self::load(&path)?;
+
+        Ok(Self {
+            path,
+            file_opts,
+            handlers: HashMap::new(),
+            jobs: Mutex::new(jobs),
+        })
+    }
+
+    fn load(path: &PathBuf) -> Result<Vec<JobEntry>, Error> {
+        let mut jobs: Vec<JobEntry> = match file_read_optional_string(path)? {
+            Some(data) => serde_json::from_str(&data)
+                .map_err(|err| format_err!("unable to parse job file {:?} - {}", path, err))?,
+            None => Vec::new(),
+        };
+
+        // jobs without state, e.g. added by hand, are treated like newly created ones
+        let now = proxmox_time::epoch_i64();
+        for entry in jobs.iter_mut().filter(|entry| entry.state.last_run == 0) {
+            entry.state.last_run = now;
+        }
+
+        Ok(jobs)
+    }
+
+    fn store(&self, jobs: &[JobEntry]) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(jobs)?;
+        replace_file(&self.path, &data, self.file_opts.clone(), true)
+    }
+
+    /// Register the handler for jobs of type `job_type`.
+    pub fn register_job_type<F>(mut self, job_type: &str, handler: F) -> Self
+    where
+        F: Fn(&str) -> Result<String, Error> + Send + Sync + 'static,
+    {
+        self.handlers
+            .insert(job_type.to_string(), Box::new(handler));
+        self
+    }
+
+    /// Add a job, or update the definition of an existing job with the same id.
+    pub fn add_job(&self, job: ScheduledJob) -> Result<(), Error> {
+        let _: CalendarEvent = job.schedule.parse()?;
+        if !self.handlers.contains_key(&job.job_type) {
+            bail!("unknown job type '{}'", job.job_type);
+        }
+
+        let mut jobs = self.jobs.lock().unwrap();
+        match jobs.iter_mut().find(|entry| entry.job.id == job.id) {
+            Some(entry) => entry.job = job,
+            None => jobs.push(JobEntry {
+                job,
+                state: JobState {
+                    last_run: proxmox_time::epoch_i64(),
+                    ..Default::default()
+                },
+            }),
+        }
+        self.store(&jobs)
+    }
+
+    /// Remove the job with id `id`.
+    pub fn remove_job(&self, id: &str) -> Result<(), Error> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let len = jobs.len();
+        jobs.retain(|entry| entry.job.id != id);
+        if jobs.len() == len {
+            bail!("no such job '{}'", id);
+        }
+        self.store(&jobs)
+    }
+
+    /// Reload the job definitions from disk, e.g. after they were edited externally.
+    pub fn reload(&self) -> Result<(), Error> {
+        let jobs = Self::load(&self.path)?;
+        *self.jobs.lock().unwrap() = jobs;
+        Ok(())
+    }
+
+    /// List all jobs including their state.
+    pub fn jobs(&self) -> Vec<ScheduledJobStatus> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| ScheduledJobStatus {
+                job: entry.job.clone(),
+                state: entry.state.clone(),
+                next_run: next_run(&entry.job, entry.state.last_run).ok().flatten(),
+            })
+            .collect()
+    }
+
+    /// Start all jobs which are due at `now`.
+    ///
+    /// The handlers are called without holding the job list lock, so they may use the
+    /// scheduler themselves. Returns the ids of the started jobs.
+    pub fn run_pending(&self, now: i64) -> Vec<String> {
+        let due = self.update_due_jobs(now);
+
+        let mut started = Vec::new();
+        for (id, job_type) in due {
+            let handler = match self.handlers.get(&job_type) {
+                Some(handler) => handler,
+                None => {
+                    log::error!("job '{}' has unknown type '{}'", id, job_type);
+                    continue;
+                }
+            };
+
+            match handler(&id) {
+                Ok(upid) => started.push((id, upid)),
+                Err(err) => log::error!("unable to start job '{}' - {}", id, err),
+            }
+        }
+
+        if started.is_empty() {
+            return Vec::new();
+        }
+
+        let mut jobs = self.jobs.lock().unwrap();
+        for (id, upid) in started.iter() {
+            // the job may have been removed in the meantime
+            if let Some(entry) = jobs.iter_mut().find(|entry| entry.job.id == *id) {
+                entry.state.last_upid = Some(upid.clone());
+            }
+        }
+        if let Err(err) = self.store(&jobs) {
+            log::error!("unable to store job state - {}", err);
+        }
+
+        started.into_iter().map(|(id, _)| id).collect()
+    }
+
+    // Updates the state of all jobs due at `now` and returns the ids and types of those which
+    // need to be started.
+    fn update_due_jobs(&self, now: i64) -> Vec<(String, String)> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let mut due = Vec::new();
+        let mut changed = false;
+
+        for entry in jobs.iter_mut() {
+            let id = &entry.job.id;
+            let event: CalendarEvent = match entry.job.schedule.parse() {
+                Ok(event) => event,
+                Err(err) => {
+                    log::error!("job '{}' has an invalid schedule - {}", id, err);
+                    continue;
+                }
+            };
+
+            let next = match event.compute_next_event(entry.state.last_run) {
+                Ok(Some(next)) if next <= now => next,
+                Ok(_) => continue,
+                Err(err) => {
+                    log::error!("unable to compute next run of job '{}' - {}", id, err);
+                    continue;
+                }
+            };
+
+            let missed = matches!(event.compute_next_event(next), Ok(Some(t)) if t <= now);
+
+            changed = true;
+            entry.state.last_run = now;
+            entry.state.missed = missed;
+
+            if missed {
+                log::warn!("job '{}' missed one or more runs", id);
+                if !entry.job.run_missed {
+                    continue;
+                }
+            }
+
+            due.push((id.clone(), entry.job.job_type.clone()));
+        }
+
+        if changed {
+            if let Err(err) = self.store(&jobs) {
+                log::error!("unable to store job state - {}", err);
+            }
+        }
+
+        due
+    }
+
+    /// Spawn the scheduler loop, which runs pending jobs at the start of every minute until
+    /// the server shuts down.
+    pub fn spawn(self: Arc<Self>) {
+        let run_loop = async move {
+            loop {
+                let now = proxmox_time::epoch_i64();
+                let wait = Duration::from_secs(60 - (now % 60) as u64);
+                tokio::time::sleep(wait).await;
+
+                self.run_pending(proxmox_time::epoch_i64());
+            }
+        };
+
+        crate::spawn_internal_task(async move {
+            futures::select! {
+                _ = Box::pin(run_loop).fuse() => {},
+                _ = Box::pin(crate::shutdown_future()).fuse() => {},
+            }
+        });
+    }
+
+    /// Register the `scheduler-reload` command on a [CommandSocket], which calls
+    /// [reload](Self::reload).
+    pub fn register_commands(
+        self: &Arc<Self>,
+        commando_sock: &mut CommandSocket,
+    ) -> Result<(), Error> {
+        let scheduler = Arc::clone(self);
+        commando_sock.register_command("scheduler-reload".into(), move |_args| {
+            scheduler.reload()?;
+            Ok(Value::Null)
+        })
+    }
+}
+
+fn next_run(job: &ScheduledJob, last_run: i64) -> Result<Option<i64>, Error> {
+    let event: CalendarEvent = job.schedule.parse()?;
+    event.compute_next_event(last_run)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Weak;
+
+    use once_cell::sync::OnceCell;
+
+    use super::*;
+
+    #[test]
+    fn test_run_pending() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("scheduler-test-{}.json", crate::pid()));
+        let count = Arc::new(AtomicUsize::new(0));
+        let count2 = Arc::clone(&count);
+
+        let scheduler =
+            Scheduler::new(&path, CreateOptions::new())?.register_job_type("test", move |id| {
+                count2.fetch_add(1, Ordering::SeqCst);
+                Ok(format!("upid-{}", id))
+            });
+        assert!(scheduler
+            .add_job(ScheduledJob {
+                id: "job1".into(),
+                job_type: "unknown".into(),
+                schedule: "daily".into(),
+                run_missed: false,
+            })
+            .is_err());
+        scheduler.add_job(ScheduledJob {
+            id: "job1".into(),
+            job_type: "test".into(),
+            schedule: "daily UTC".into(),
+            run_missed: false,
+        })?;
+
+        let created = scheduler.jobs()[0].state.last_run;
+
+        assert!(scheduler.run_pending(created).is_empty());
+        // exactly one run is due after one day
+        assert_eq!(scheduler.run_pending(created + 86400), ["job1"]);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // missed runs are skipped and flagged
+        assert!(scheduler.run_pending(created + 4 * 86400).is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // state is persisted
+        let reloaded = Scheduler::new(&path, CreateOptions::new())?;
+        let status = &reloaded.jobs()[0];
+        assert!(status.state.missed);
+        assert_eq!(status.state.last_run, created + 4 * 86400);
+        assert_eq!(status.state.last_upid.as_deref(), Some("upid-job1"));
+
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_handler_uses_scheduler() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("scheduler-test-lock-{}.json", crate::pid()));
+        let _ = std::fs::remove_file(&path);
+
+        let slot: Arc<OnceCell<Weak<Scheduler>>> = Arc::new(OnceCell::new());
+        let handler_slot = Arc::clone(&slot);
+
+        let scheduler =
+            Scheduler::new(&path, CreateOptions::new())?.register_job_type("test", move |id| {
+                // would dead-lock if the job list was still locked
+                let scheduler = handler_slot.get().and_then(Weak::upgrade).unwrap();
+                assert_eq!(scheduler.jobs().len(), 1);
+                Ok(format!("upid-{}", id))
+            });
+        let scheduler = Arc::new(scheduler);
+        let _ = slot.set(Arc::downgrade(&scheduler));
+
+        scheduler.add_job(ScheduledJob {
+            id: "job1".into(),
+            job_type: "test".into(),
+            schedule: "daily UTC".into(),
+            run_missed: false,
+        })?;
+        let created = scheduler.jobs()[0].state.last_run;
+
+        assert_eq!(scheduler.run_pending(created + 86400), ["job1"]);
+        assert_eq!(
+            scheduler.jobs()[0].state.last_upid.as_deref(),
+            Some("upid-job1")
+        );
+
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_without_state() -> Result<(), Error> {
+        let path =
+            std::env::temp_dir().join(format!("scheduler-test-compat-{}.json", crate::pid()));
+        std::fs::write(
+            &path,
+            r#"[{"id": "job1", "job-type": "test", "schedule": "daily"}]"#,
+        )?;
+
+        let before = proxmox_time::epoch_i64();
+        let scheduler = Scheduler::new(&path, CreateOptions::new())?;
+        let jobs = scheduler.jobs();
+        assert_eq!(jobs.len(), 1);
+        assert!(!jobs[0].job.run_missed);
+        assert!(jobs[0].state.last_run >= before);
+        assert!(!jobs[0].state.missed);
+        assert!(jobs[0].state.last_upid.is_none());
+
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+}
@@ -100,6 +100,12 @@ impl WorkerTaskSetup {
         path
     }
 
+    fn result_path(&self, upid: &UPID) -> std::path::PathBuf {
+        let mut path = self.log_directory(upid);
+        path.push(format!("{}.result", upid));
+        path
+    }
+
     fn create_and_get_log_path(&self, upid: &UPID) -> Result<std::path::PathBuf, Error> {
         let mut path = self.log_directory(upid);
         let dir_opts = self
@@ -381,6 +387,25 @@ pub fn upid_log_path(upid: &UPID) -> Result<std::path::PathBuf, Error> {
     Ok(setup.log_path(upid))
 }
 
+/// Maximum size of all serialized result artifacts of a single task.
+pub const MAX_TASK_RESULT_SIZE: usize = 64 * 1024;
+
+/// Read the result artifacts a task attached with [WorkerTaskContext::set_result].
+///
+/// Returns a JSON object mapping the result names to their values, or `None` if the task did
+/// not set any results.
+pub fn upid_read_result(upid: &UPID) -> Result<Option<Value>, Error> {
+    let setup = worker_task_setup()?;
+
+    let path = setup.result_path(upid);
+    match proxmox_sys::fs::file_read_optional_string(&path)? {
+        Some(data) => Ok(Some(serde_json::from_str(&data).map_err(|err| {
+            format_err!("unable to parse task result {:?} - {}", path, err)
+        })?)),
+        None => Ok(None),
+    }
+}
+
 /// Parse the time and exit status from the last log line in a worker task log file. Works only
 /// correctly on finished tasks.
 ///
@@ -825,6 +850,7 @@ struct WorkerTaskData {
     logger: FileLogger,
     progress: f64, // 0..1
     warn_count: u64,
+    results: serde_json::Map<String, Value>,
     pub abort_listeners: Vec<oneshot::Sender<()>>,
 }
 
@@ -860,6 +886,7 @@ impl WorkerTask {
                 logger,
                 progress: 0.0,
                 warn_count: 0,
+                results: serde_json::Map::new(),
                 abort_listeners: vec![],
             }),
         });
@@ -1021,6 +1048,92 @@ impl WorkerTask {
     pub fn upid(&self) -> &UPID {
         &self.upid
     }
+
+    /// Create a [Checkpoint] to cooperatively check for abort requests.
+    pub fn checkpoint(&self) -> Checkpoint<'_> {
+        Checkpoint {
+            worker: self,
+            cleanup: Vec::new(),
+        }
+    }
+}
+
+/// Cooperative cancellation point of a [WorkerTask].
+///
+/// Long running tasks call [check](Checkpoint::check) (or await
+/// [reached](Checkpoint::reached) in async code) at points where it is safe to stop. Once an
+/// abort was requested, these fail and the registered cleanup callbacks are run in reverse
+/// order of registration.
+///
+/// ```no_run
+/// # use anyhow::Error;
+/// # use proxmox_rest_server::WorkerTask;
+/// # async fn example(worker: &WorkerTask, items: Vec<String>) -> Result<(), Error> {
+/// let mut checkpoint = worker.checkpoint();
+/// checkpoint.on_abort(|| {
+///     let _ = std::fs::remove_file("/tmp/partial-download");
+/// });
+/// for item in items {
+///     checkpoint.reached().await?;
+///     // process item
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Checkpoint<'a> {
+    worker: &'a WorkerTask,
+    cleanup: Vec<Box<dyn FnOnce() + Send + 'a>>,
+}
+
+impl<'a> Checkpoint<'a> {
+    /// Register a callback which is run when the task gets aborted at this checkpoint.
+    pub fn on_abort<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        self.cleanup.push(Box::new(callback));
+        self
+    }
+
+    /// Fail if an abort was requested, running the cleanup callbacks.
+    pub fn check(&mut self) -> Result<(), Error> {
+        if let Err(err) = self.worker.check_abort() {
+            self.run_cleanup();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Yield to the runtime, then [check](Self::check) for abort requests.
+    pub async fn reached(&mut self) -> Result<(), Error> {
+        tokio::task::yield_now().await;
+        self.check()
+    }
+
+    /// Run `future` to completion, unless the task gets aborted first.
+    ///
+    /// On abort, `future` is dropped, the cleanup callbacks are run and an error is returned.
+    pub async fn run<F, T>(&mut self, future: F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        self.check()?;
+
+        let abort_future = self.worker.abort_future();
+        futures::select! {
+            result = future.fuse() => result,
+            _ = abort_future.fuse() => {
+                self.check()?;
+                bail!("abort listener dropped");
+            }
+        }
+    }
+
+    fn run_cleanup(&mut self) {
+        while let Some(callback) = self.cleanup.pop() {
+            callback();
+        }
+    }
 }
 
 impl WorkerTaskContext for WorkerTask {
@@ -1045,6 +1158,27 @@ impl WorkerTaskContext for WorkerTask {
             log::Level::Trace => self.log_message(format!("TRACE: {}", message)),
         }
     }
+
+    fn set_result(&self, name: &str, value: Value) -> Result<(), Error> {
+        let mut data = self.data.lock().unwrap();
+
+        let mut results = data.results.clone();
+        results.insert(name.to_string(), value);
+        let raw = serde_json::to_vec(&results)?;
+        if raw.len() > MAX_TASK_RESULT_SIZE {
+            bail!(
+                "unable to set task result '{}' - results exceed {} bytes",
+                name,
+                MAX_TASK_RESULT_SIZE
+            );
+        }
+
+        let path = self.setup.result_path(&self.upid);
+        replace_file(path, &raw, self.setup.file_opts.clone(), false)?;
+        data.results = results;
+
+        Ok(())
+    }
 }
 
 /// Wait for a locally spanned worker task
@@ -1094,3 +1228,80 @@ pub async fn handle_worker(upid_str: &str) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex, Once};
+
+    use anyhow::Error;
+
+    use super::WorkerTask;
+
+    fn test_worker() -> Result<Arc<WorkerTask>, Error> {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let basedir = std::env::temp_dir().join(format!(
+                "proxmox-rest-server-worker-test-{}",
+                std::process::id()
+            ));
+            super::init_worker_tasks(basedir, proxmox_sys::fs::CreateOptions::new())
+                .expect("failed to initialize worker tasks");
+        });
+        WorkerTask::new("checkpoint-test", None, "root@pam".to_string(), false)
+    }
+
+    #[test]
+    fn test_checkpoint_check() -> Result<(), Error> {
+        let worker = test_worker()?;
+        let order = Mutex::new(Vec::new());
+
+        let mut checkpoint = worker.checkpoint();
+        checkpoint
+            .on_abort(|| order.lock().unwrap().push(1))
+            .on_abort(|| order.lock().unwrap().push(2));
+
+        checkpoint.check()?;
+        assert!(order.lock().unwrap().is_empty());
+
+        worker.request_abort();
+        assert!(checkpoint.check().is_err());
+        // cleanup runs in reverse order of registration, and only once
+        assert!(checkpoint.check().is_err());
+        drop(checkpoint);
+        assert_eq!(*order.lock().unwrap(), [2, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_async() -> Result<(), Error> {
+        let worker = test_worker()?;
+        let cleaned_up = Mutex::new(false);
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(async {
+            let mut checkpoint = worker.checkpoint();
+            checkpoint.on_abort(|| *cleaned_up.lock().unwrap() = true);
+
+            checkpoint.reached().await?;
+            assert_eq!(checkpoint.run(async { Ok(42) }).await?, 42);
+            assert!(!*cleaned_up.lock().unwrap());
+
+            // a pending future is dropped once the abort is requested
+            let abort_worker = Arc::clone(&worker);
+            tokio::spawn(async move { abort_worker.request_abort() });
+            let result = checkpoint
+                .run(futures::future::pending::<Result<(), Error>>())
+                .await;
+            assert!(result.is_err());
+            assert!(*cleaned_up.lock().unwrap());
+
+            assert!(checkpoint.reached().await.is_err());
+            Ok::<_, Error>(())
+        })?;
+
+        Ok(())
+    }
+}
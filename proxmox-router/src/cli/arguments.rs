@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use serde::de::DeserializeOwned;
+
+use proxmox_schema::{ApiType, ParameterSchema, Schema};
+
+use super::getopts::do_parse_arguments;
+
+/// Argument structs for command line tools.
+///
+/// Properties are passed as `--name <value>` options, except for the positional arguments in
+/// `ARG_PARAM`. Properties missing on the command line are taken from the environment variables
+/// in `ENV_PARAM`, if set. The result is verified against the type's schema.
+///
+/// This is usually implemented via `#[derive(CliArguments)]` on an `#[api]` struct.
+pub trait CliArguments: ApiType + DeserializeOwned {
+    /// The object schema of the arguments.
+    const PARAMETER_SCHEMA: ParameterSchema = match &Self::API_SCHEMA {
+        Schema::Object(schema) => ParameterSchema::Object(schema),
+        Schema::AllOf(schema) => ParameterSchema::AllOf(schema),
+        Schema::OneOf(schema) => ParameterSchema::OneOf(schema),
+        _ => panic!("command line arguments require an object schema"),
+    };
+
+    /// The positional arguments, in order. Only the last one may be optional.
+    const ARG_PARAM: &'static [&'static str] = &[];
+
+    /// Pairs of property names and environment variables.
+    const ENV_PARAM: &'static [(&'static str, &'static str)] = &[];
+
+    /// Parse command line arguments (without the program name).
+    ///
+    /// Returns the parsed arguments together with the list of additional arguments.
+    fn parse_cli_arguments<T: AsRef<str>>(args: &[T]) -> Result<(Self, Vec<String>), Error> {
+        let (value, remaining) = do_parse_arguments(
+            args,
+            Self::ARG_PARAM,
+            &HashMap::new(),
+            Self::ENV_PARAM,
+            Self::PARAMETER_SCHEMA,
+        )?;
+
+        Ok((serde_json::from_value(value)?, remaining))
+    }
+}
@@ -137,6 +137,18 @@ pub fn parse_arguments<T: AsRef<str>>(
     arg_param: &[&str],
     fixed_param: &HashMap<&'static str, String>,
     schema: ParameterSchema,
+) -> Result<(Value, Vec<String>), ParameterError> {
+    do_parse_arguments(args, arg_param, fixed_param, &[], schema)
+}
+
+/// Like [parse_arguments], but `env_param` maps property names to environment variables which
+/// are used for properties not passed on the command line.
+pub(crate) fn do_parse_arguments<T: AsRef<str>>(
+    args: &[T],
+    arg_param: &[&str],
+    fixed_param: &HashMap<&'static str, String>,
+    env_param: &[(&str, &str)],
+    schema: ParameterSchema,
 ) -> Result<(Value, Vec<String>), ParameterError> {
     let mut errors = ParameterError::new();
 
@@ -185,6 +197,15 @@ pub fn parse_arguments<T: AsRef<str>>(
         return Err(errors);
     }
 
+    for (name, var) in env_param {
+        if fixed_param.contains_key(name) || data.iter().any(|(key, _)| key == name) {
+            continue;
+        }
+        if let Ok(value) = std::env::var(var) {
+            data.push((name.to_string(), value));
+        }
+    }
+
     for (name, value) in fixed_param.iter() {
         data.push((name.to_string(), value.to_string()));
     }
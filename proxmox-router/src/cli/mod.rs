@@ -36,6 +36,9 @@ pub use completion_helpers::*;
 mod getopts;
 pub use getopts::*;
 
+mod arguments;
+pub use arguments::*;
+
 mod command;
 pub use command::*;
 
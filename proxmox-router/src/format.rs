@@ -11,26 +11,49 @@ use proxmox_schema::ObjectSchemaType;
 use crate::ApiHandler;
 use crate::ApiMethod;
 
+/// Output format for [dump_api_reference].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiDocFormat {
+    /// Markdown, suitable for static site generators.
+    Markdown,
+    /// Roff (man page) format.
+    Roff,
+}
+
+/// Returns the displayed method name, upload/download handlers are named accordingly.
+fn method_display_name<'a>(method: &'a str, _api_method: &ApiMethod) -> &'a str {
+    #[cfg(feature = "server")]
+    if let ApiHandler::AsyncHttp(_) = _api_method.handler {
+        return match method {
+            "POST" => "UPLOAD",
+            "GET" => "DOWNLOAD",
+            _ => method,
+        };
+    }
+
+    method
+}
+
+/// Returns the method description, including a note if the method is deprecated.
+fn method_description(api_method: &ApiMethod) -> String {
+    let description = api_method.parameters.description();
+    match api_method.deprecation() {
+        Some(deprecation) => format!("{}\n\nThis method is {}.", description, deprecation),
+        None => description.to_string(),
+    }
+}
+
 fn dump_method_definition(method: &str, path: &str, def: Option<&ApiMethod>) -> Option<String> {
     let style = ParameterDisplayStyle::Config;
     match def {
         None => None,
         Some(api_method) => {
-            let description = wrap_text("", "", api_method.parameters.description(), 80);
+            let description = wrap_text("", "", &method_description(api_method), 80);
             let param_descr = dump_properties(&api_method.parameters, "", style, &[]);
 
             let return_descr = dump_api_return_schema(&api_method.returns, style);
 
-            #[cfg(feature = "server")]
-            let mut method = method;
-            #[cfg(not(feature = "server"))]
-            let method = method;
-
-            #[cfg(feature = "server")]
-            if let ApiHandler::AsyncHttp(_) = api_method.handler {
-                method = if method == "POST" { "UPLOAD" } else { method };
-                method = if method == "GET" { "DOWNLOAD" } else { method };
-            }
+            let method = method_display_name(method, api_method);
 
             let res = format!(
                 "**{} {}**\n\n{}{}\n\n{}",
@@ -92,3 +115,272 @@ pub fn dump_api(
 
     Ok(())
 }
+
+fn dump_method_definition_markdown(
+    method: &str,
+    path: &str,
+    def: Option<&ApiMethod>,
+) -> Option<String> {
+    let api_method = def?;
+
+    let method = method_display_name(method, api_method);
+    let description = method_description(api_method);
+    let param_descr = dump_properties_markdown(&api_method.parameters, &[]);
+    let return_descr = dump_api_return_schema_markdown(&api_method.returns);
+
+    Some(format!(
+        "### `{} /{}`\n\n{}\n\n{}\n{}",
+        method,
+        path.trim_start_matches('.'),
+        description,
+        param_descr,
+        return_descr
+    ))
+}
+
+fn dump_method_definition_roff(
+    method: &str,
+    path: &str,
+    def: Option<&ApiMethod>,
+) -> Option<String> {
+    let api_method = def?;
+
+    let method = method_display_name(method, api_method);
+    let description = roff_paragraphs(&method_description(api_method));
+    let param_descr = dump_properties_roff(&api_method.parameters, &[]);
+    let return_descr = dump_api_return_schema_roff(&api_method.returns);
+
+    Some(format!(
+        ".SS \"{} /{}\"\n{}\n{}{}",
+        method,
+        roff_escape(path.trim_start_matches('.')),
+        description,
+        param_descr,
+        return_descr
+    ))
+}
+
+/// Calls `func` for all methods of the ``Router`` and its sub-routers, in API path order.
+pub(crate) fn walk_api<F>(router: &crate::Router, path: &str, func: &mut F) -> Result<(), Error>
+where
+    F: FnMut(&str, &str, Option<&ApiMethod>) -> Result<(), Error>,
+{
+    use crate::SubRoute;
+
+    func("GET", path, router.get)?;
+    func("POST", path, router.post)?;
+    func("PUT", path, router.put)?;
+    func("DELETE", path, router.delete)?;
+
+    match &router.subroute {
+        None => (),
+        Some(SubRoute::MatchAll { router, param_name }) => {
+            let sub_path = if path == "." {
+                format!("<{}>", param_name)
+            } else {
+                format!("{}/<{}>", path, param_name)
+            };
+            walk_api(router, &sub_path, func)?;
+        }
+        Some(SubRoute::Map(dirmap)) => {
+            for (key, sub_router) in dirmap.iter() {
+                let sub_path = if path == "." {
+                    (*key).to_string()
+                } else {
+                    format!("{}/{}", path, key)
+                };
+                walk_api(sub_router, &sub_path, func)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Generate Markdown Documentation for a complete API defined by a ``Router``.
+///
+/// Every method gets its own section with a parameter table and the return schema.
+pub fn dump_api_markdown(
+    output: &mut dyn Write,
+    router: &crate::Router,
+    path: &str,
+) -> Result<(), Error> {
+    walk_api(router, path, &mut |method, path, def| {
+        if let Some(text) = dump_method_definition_markdown(method, path, def) {
+            writeln!(output, "{}", text)?;
+        }
+        Ok(())
+    })
+}
+
+/// Generate a roff man page for a complete API defined by a ``Router``.
+///
+/// `title` is used for the `.TH` header, every method gets its own sub-section.
+pub fn dump_api_roff(
+    output: &mut dyn Write,
+    router: &crate::Router,
+    path: &str,
+    title: &str,
+) -> Result<(), Error> {
+    writeln!(output, ".TH \"{}\" 7", roff_escape(title))?;
+    writeln!(output, ".SH API REFERENCE")?;
+
+    walk_api(router, path, &mut |method, path, def| {
+        if let Some(text) = dump_method_definition_roff(method, path, def) {
+            write!(output, "{}", text)?;
+        }
+        Ok(())
+    })
+}
+
+/// Generate API reference documentation in the given `format`.
+pub fn dump_api_reference(
+    output: &mut dyn Write,
+    router: &crate::Router,
+    format: ApiDocFormat,
+    title: &str,
+) -> Result<(), Error> {
+    match format {
+        ApiDocFormat::Markdown => {
+            writeln!(output, "# {}\n", title)?;
+            dump_api_markdown(output, router, ".")
+        }
+        ApiDocFormat::Roff => dump_api_roff(output, router, ".", title),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Error;
+    use serde_json::Value;
+
+    use proxmox_schema::{Deprecation, IntegerSchema, ObjectSchema, ReturnType, StringSchema};
+
+    use crate::{ApiHandler, ApiMethod, Router, RpcEnvironment, SubdirMap};
+
+    fn dummy_method(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    const NAME_SCHEMA: proxmox_schema::Schema =
+        StringSchema::new(".hidden names need\\escaping.").schema();
+    const VALUE_SCHEMA: proxmox_schema::Schema = IntegerSchema::new("The value.")
+        .minimum(-1)
+        .default(0)
+        .schema();
+
+    const API_METHOD_GET_ITEM: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "Get an item.",
+            &[
+                ("value", true, &VALUE_SCHEMA),
+                ("name", false, &NAME_SCHEMA),
+            ],
+        ),
+    )
+    .returns(ReturnType::new(true, &VALUE_SCHEMA));
+
+    const API_METHOD_DELETE_ITEM: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new("Delete an item.", &[("name", false, &NAME_SCHEMA)]),
+    )
+    .deprecated(&Deprecation::new().note("use PUT instead"));
+
+    const ITEM_ROUTER: Router = Router::new()
+        .get(&API_METHOD_GET_ITEM)
+        .delete(&API_METHOD_DELETE_ITEM);
+    const ITEMS_ROUTER: Router = Router::new().match_all("name", &ITEM_ROUTER);
+    const SUBDIRS: SubdirMap = &[("items", &ITEMS_ROUTER)];
+    const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+    #[test]
+    fn test_dump_api_markdown() -> Result<(), Error> {
+        let mut output = Vec::new();
+        super::dump_api_markdown(&mut output, &ROUTER, ".")?;
+        let output = String::from_utf8(output)?;
+
+        assert_eq!(
+            output,
+            "\
+### `GET /items/<name>`
+
+Get an item.
+
+| Name | Type | Required | Default | Description |
+|------|------|----------|---------|-------------|
+| `name` | `<string>` | yes |  | .hidden names need\\escaping. |
+| `value` | `<integer> (-1 - N)` | no | `0` | The value. |
+
+**Returns** (optionally): `<integer> (-1 - N)`
+
+The value.
+
+
+### `DELETE /items/<name>`
+
+Delete an item.
+
+This method is deprecated: use PUT instead.
+
+| Name | Type | Required | Default | Description |
+|------|------|----------|---------|-------------|
+| `name` | `<string>` | yes |  | .hidden names need\\escaping. |
+
+**Returns**: `<null>`
+
+
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_api_roff() -> Result<(), Error> {
+        let mut output = Vec::new();
+        super::dump_api_roff(&mut output, &ROUTER, ".", "test-api")?;
+        let output = String::from_utf8(output)?;
+
+        assert_eq!(
+            output,
+            "\
+.TH \"test\\-api\" 7
+.SH API REFERENCE
+.SS \"GET /items/<name>\"
+Get an item.
+.TP
+.B name
+<string>
+.br
+\\&.hidden names need\\eescaping.
+.TP
+.B value
+<integer> (\\-1 \\- N) (default=0) (optional)
+.br
+The value.
+.PP
+.B Returns (optionally):
+<integer> (\\-1 \\- N)
+.PP
+The value.
+.SS \"DELETE /items/<name>\"
+Delete an item.
+.PP
+This method is deprecated: use PUT instead.
+.TP
+.B name
+<string>
+.br
+\\&.hidden names need\\eescaping.
+.PP
+.B Returns:
+<null>
+"
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 //! API Router and Command Line Interface utilities.
 
 pub mod format;
+pub mod openapi;
 
 #[cfg(feature = "cli")]
 pub mod cli;
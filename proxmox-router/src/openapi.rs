@@ -0,0 +1,340 @@
+//! Generate OpenAPI 3.1 documents for an API defined by a ``Router``.
+
+use anyhow::Error;
+use serde_json::{json, Map, Value};
+
+use proxmox_schema::json_schema::to_json_schema;
+use proxmox_schema::ObjectSchemaType;
+
+#[cfg(feature = "server")]
+use crate::ApiHandler;
+use crate::{ApiMethod, Permission, Router};
+
+/// Generate an OpenAPI 3.1 document for a complete API defined by a ``Router``.
+///
+/// `base_url` is the (relative) URL the router is mounted on, e.g. `/api2/json`. The document
+/// describes the responses of the JSON output formatter of the REST server: results are wrapped
+/// in a `data` property, errors are returned as plain message with the HTTP status code.
+///
+/// Parameters are passed as path parameters for `MatchAll` routes, as query parameters for `GET`
+/// and `DELETE` and as JSON request body otherwise.
+pub fn openapi_document(
+    router: &Router,
+    title: &str,
+    version: &str,
+    base_url: &str,
+) -> Result<Value, Error> {
+    let mut paths = Map::new();
+
+    crate::format::walk_api(router, ".", &mut |method, path, def| {
+        let api_method = match def {
+            Some(api_method) => api_method,
+            None => return Ok(()),
+        };
+
+        let path = openapi_path(path);
+        let operation = method_operation(method, &path, api_method);
+
+        let entry = paths
+            .entry(path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        entry[method.to_lowercase()] = operation;
+
+        Ok(())
+    })?;
+
+    Ok(json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": title,
+            "version": version,
+        },
+        "servers": [{ "url": base_url }],
+        "paths": paths,
+    }))
+}
+
+/// Convert a path as passed by `walk_api` (`.`, `a/<b>`) into an OpenAPI path template.
+fn openapi_path(path: &str) -> String {
+    let path = path.trim_start_matches('.');
+
+    let components: Vec<String> = path
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .map(|component| match component.strip_prefix('<') {
+            Some(param) => format!("{{{}}}", param.trim_end_matches('>')),
+            None => component.to_string(),
+        })
+        .collect();
+
+    format!("/{}", components.join("/"))
+}
+
+fn path_parameters(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter_map(|component| component.strip_prefix('{')?.strip_suffix('}'))
+        .collect()
+}
+
+fn operation_id(method: &str, path: &str) -> String {
+    let mut id = method.to_lowercase();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        id.push('_');
+        id.extend(
+            component
+                .chars()
+                .filter(|c| !matches!(c, '{' | '}'))
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }),
+        );
+    }
+    id
+}
+
+fn is_http_handler(_api_method: &ApiMethod) -> bool {
+    #[cfg(feature = "server")]
+    if let ApiHandler::AsyncHttp(_) = _api_method.handler {
+        return true;
+    }
+
+    false
+}
+
+fn method_operation(method: &str, path: &str, api_method: &ApiMethod) -> Value {
+    let params = &api_method.parameters;
+    let description = params.description();
+    let summary = description.lines().next().unwrap_or_default();
+
+    let path_params = path_parameters(path);
+    let in_query = matches!(method, "GET" | "DELETE");
+
+    let mut parameters = Vec::new();
+    let mut body_properties = Map::new();
+    let mut body_required = Vec::new();
+
+    for (name, optional, schema) in params.properties() {
+        let schema_value = to_json_schema(schema);
+
+        if path_params.contains(name) || in_query {
+            let location = if path_params.contains(name) {
+                "path"
+            } else {
+                "query"
+            };
+            let mut parameter = json!({
+                "name": name,
+                "in": location,
+                "required": location == "path" || !*optional,
+                "schema": schema_value,
+            });
+            if schema.deprecation().is_some() {
+                parameter["deprecated"] = true.into();
+            }
+            parameters.push(parameter);
+        } else {
+            body_properties.insert(name.to_string(), schema_value);
+            if !*optional {
+                body_required.push(*name);
+            }
+        }
+    }
+
+    let mut operation = json!({
+        "operationId": operation_id(method, path),
+        "summary": summary,
+        "description": description,
+        "parameters": parameters,
+        "responses": responses(api_method),
+    });
+
+    if api_method.deprecation().is_some() {
+        operation["deprecated"] = true.into();
+    }
+
+    if let Some(tag) = path
+        .split('/')
+        .find(|c| !c.is_empty() && !c.starts_with('{'))
+    {
+        operation["tags"] = json!([tag]);
+    }
+
+    if !body_properties.is_empty() || (!in_query && params.additional_properties()) {
+        let required = !body_required.is_empty();
+
+        let mut body_schema = json!({
+            "type": "object",
+            "properties": body_properties,
+            "additionalProperties": params.additional_properties(),
+        });
+        if required {
+            body_schema["required"] = body_required.into();
+        }
+
+        operation["requestBody"] = json!({
+            "required": required,
+            "content": {
+                "application/json": { "schema": body_schema },
+            },
+        });
+    }
+
+    let access = &api_method.access;
+    let mut permissions = json!({ "check": format!("{:?}", access.permission) });
+    if let Some(description) = access.description {
+        permissions["description"] = description.into();
+    }
+    operation["x-permissions"] = permissions;
+
+    operation
+}
+
+fn responses(api_method: &ApiMethod) -> Value {
+    let success = if is_http_handler(api_method) {
+        json!({ "description": "Raw HTTP response of the handler." })
+    } else {
+        let returns = &api_method.returns;
+        let mut data = to_json_schema(returns.schema);
+        if returns.optional {
+            data = json!({ "anyOf": [data, { "type": "null" }] });
+        }
+
+        json!({
+            "description": "Success.",
+            "content": {
+                "application/json": {
+                    "schema": {
+                        "type": "object",
+                        "properties": { "data": data },
+                    },
+                },
+            },
+        })
+    };
+
+    let mut responses = json!({
+        "200": success,
+        "400": { "description": "Invalid parameters or failed request, the body contains the error message." },
+        "403": { "description": "Permission denied." },
+    });
+
+    if !matches!(api_method.access.permission, Permission::World) {
+        responses["401"] = json!({ "description": "Authentication required." });
+    }
+
+    responses
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Error;
+    use serde_json::{json, Value};
+
+    use proxmox_schema::{Deprecation, IntegerSchema, ObjectSchema, ReturnType, StringSchema};
+
+    use crate::{ApiHandler, ApiMethod, Permission, Router, RpcEnvironment, SubdirMap};
+
+    fn dummy_method(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    const NAME_SCHEMA: proxmox_schema::Schema = StringSchema::new("The name.").schema();
+    const VALUE_SCHEMA: proxmox_schema::Schema = IntegerSchema::new("The value.").schema();
+    const VERBOSE_SCHEMA: proxmox_schema::Schema = IntegerSchema::new("The value.")
+        .deprecated(&Deprecation::new())
+        .schema();
+
+    const API_METHOD_GET_ITEM: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "Get an item.\n\nReturns the item's value.",
+            &[
+                ("name", false, &NAME_SCHEMA),
+                ("verbose", true, &VERBOSE_SCHEMA),
+            ],
+        ),
+    )
+    .returns(ReturnType::new(true, &VALUE_SCHEMA))
+    .access(None, &Permission::World);
+
+    const API_METHOD_SET_ITEM: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "Set an item.",
+            &[
+                ("name", false, &NAME_SCHEMA),
+                ("value", false, &VALUE_SCHEMA),
+            ],
+        ),
+    )
+    .access(Some("Only for admins."), &Permission::Superuser)
+    .deprecated(&Deprecation::new().note("use POST instead"));
+
+    const ITEM_ROUTER: Router = Router::new()
+        .get(&API_METHOD_GET_ITEM)
+        .put(&API_METHOD_SET_ITEM);
+    const ITEMS_ROUTER: Router = Router::new().match_all("name", &ITEM_ROUTER);
+    const SUBDIRS: SubdirMap = &[("items", &ITEMS_ROUTER)];
+    const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+    #[test]
+    fn test_openapi_document() -> Result<(), Error> {
+        let doc = super::openapi_document(&ROUTER, "Test", "1.0", "/api2/json")?;
+
+        assert_eq!(doc["openapi"], "3.1.0");
+        assert_eq!(doc["servers"], json!([{ "url": "/api2/json" }]));
+
+        let paths = doc["paths"].as_object().unwrap();
+        assert_eq!(paths.keys().collect::<Vec<_>>(), ["/items/{name}"]);
+
+        let get = &paths["/items/{name}"]["get"];
+        assert_eq!(get["operationId"], "get_items_name");
+        assert_eq!(get["summary"], "Get an item.");
+        assert_eq!(get["tags"], json!(["items"]));
+        assert_eq!(
+            get["parameters"],
+            json!([
+                {
+                    "name": "name",
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string", "description": "The name." },
+                },
+                {
+                    "name": "verbose",
+                    "in": "query",
+                    "required": false,
+                    "schema": { "type": "integer", "description": "The value.", "deprecated": true },
+                    "deprecated": true,
+                },
+            ])
+        );
+        assert_eq!(
+            get["responses"]["200"]["content"]["application/json"]["schema"]["properties"]["data"],
+            json!({ "anyOf": [{ "type": "integer", "description": "The value." }, { "type": "null" }] })
+        );
+        assert!(get["responses"].get("401").is_none());
+        assert!(get.get("requestBody").is_none());
+        assert!(get.get("deprecated").is_none());
+
+        let put = &paths["/items/{name}"]["put"];
+        assert_eq!(
+            put["requestBody"]["content"]["application/json"]["schema"],
+            json!({
+                "type": "object",
+                "properties": {
+                    "value": { "type": "integer", "description": "The value." },
+                },
+                "additionalProperties": false,
+                "required": ["value"],
+            })
+        );
+        assert_eq!(put["x-permissions"]["description"], "Only for admins.");
+        assert!(put["responses"].get("401").is_some());
+        assert_eq!(put["deprecated"], true);
+
+        Ok(())
+    }
+}
@@ -13,7 +13,7 @@ use hyper::Body;
 use percent_encoding::percent_decode_str;
 use serde_json::Value;
 
-use proxmox_schema::{ObjectSchema, ParameterSchema, ReturnType, Schema};
+use proxmox_schema::{Deprecation, ObjectSchema, ParameterSchema, ReturnType, Schema};
 
 use super::Permission;
 use crate::RpcEnvironment;
@@ -503,6 +503,8 @@ pub struct ApiMethod {
     pub handler: &'static ApiHandler,
     /// Access Permissions
     pub access: ApiAccess,
+    /// Set if the method is deprecated, see [ApiMethod::deprecation].
+    deprecated: Option<&'static Deprecation>,
 }
 
 impl std::fmt::Debug for ApiMethod {
@@ -528,6 +530,7 @@ impl ApiMethod {
                 description: None,
                 permission: &Permission::Superuser,
             },
+            deprecated: None,
         }
     }
 
@@ -546,6 +549,7 @@ impl ApiMethod {
                 description: None,
                 permission: &Permission::Superuser,
             },
+            deprecated: None,
         }
     }
 
@@ -579,4 +583,15 @@ impl ApiMethod {
 
         self
     }
+
+    pub const fn deprecated(mut self, deprecation: &'static Deprecation) -> Self {
+        self.deprecated = Some(deprecation);
+
+        self
+    }
+
+    /// Gets the deprecation information, if the method is marked as deprecated.
+    pub fn deprecation(&self) -> Option<&'static Deprecation> {
+        self.deprecated
+    }
 }
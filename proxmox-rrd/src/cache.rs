@@ -1,4 +1,4 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::os::unix::io::AsRawFd;
@@ -9,12 +9,18 @@ use std::time::SystemTime;
 
 use anyhow::{bail, format_err, Error};
 use crossbeam_channel::{bounded, TryRecvError};
+use serde::{Deserialize, Serialize};
 
+use proxmox_schema::api;
 use proxmox_sys::fs::{create_path, CreateOptions};
 
-use crate::rrd::{AggregationFn, Archive, DataSourceType, Database};
+use crate::rrd::{AggregationFn, Archive, DataSourceType, Database, MAX_ARCHIVE_POINTS};
+use crate::sink::MetricsSink;
 use crate::Entry;
 
+/// Smallest supported base step (resolution of the finest RRA) in seconds.
+pub const MIN_BASE_STEP: u64 = 1;
+
 mod journal;
 use journal::*;
 
@@ -29,6 +35,35 @@ pub struct Cache {
     config: Arc<CacheConfig>,
     state: Arc<RwLock<JournalState>>,
     rrd_map: Arc<RwLock<RRDMap>>,
+    sinks: Vec<Box<dyn MetricsSink>>,
+}
+
+#[api()]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// RRD cache statistics, see [Cache::stats]
+pub struct CacheStats {
+    /// Number of RRD files loaded into memory.
+    pub files: usize,
+    /// Size of the current journal file in bytes.
+    pub journal_size: u64,
+    /// Number of updates in the current journal (not yet committed).
+    pub pending_updates: u64,
+    /// Number of rotated journal files waiting to be committed.
+    pub old_journals: usize,
+    /// Whether the journal got applied since startup.
+    pub journal_applied: bool,
+    /// A journal apply/commit is currently running.
+    pub commit_running: bool,
+    /// Time (epoch) of the last successful commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_commit: Option<f64>,
+    /// Duration of the last journal apply in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_apply_duration: Option<f64>,
+    /// Duration of the last successful commit in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_commit_duration: Option<f64>,
 }
 
 pub(crate) struct CacheConfig {
@@ -86,9 +121,16 @@ impl Cache {
             config: Arc::clone(&config),
             state: Arc::new(RwLock::new(state)),
             rrd_map: Arc::new(RwLock::new(rrd_map)),
+            sinks: Vec::new(),
         })
     }
 
+    /// Add a sink which receives all values passed to [Self::update_value] and
+    /// [Self::update_batch], see [crate::sink].
+    pub fn add_metrics_sink(&mut self, sink: Box<dyn MetricsSink>) {
+        self.sinks.push(sink);
+    }
+
     /// Create a new RRD as used by the proxmox backup server
     ///
     /// It contains the following RRAs:
@@ -122,62 +164,121 @@ impl Cache {
         Database::new(dst, rra_list)
     }
 
+    /// Create a new RRD with a configurable base step
+    ///
+    /// Same as [Self::create_proxmox_backup_default_rrd], but prepends
+    /// an average and maximum RRA with `base_step` seconds resolution,
+    /// sized to span `window` seconds. This is useful to store metrics
+    /// like latency or IOPS at high resolution for a short time window.
+    ///
+    /// `base_step` can be as small as one second and needs to divide 60
+    /// evenly. A `base_step` of 60 simply returns the default layout.
+    pub fn create_proxmox_backup_rrd_with_base_step(
+        dst: DataSourceType,
+        base_step: u64,
+        window: u64,
+    ) -> Result<Database, Error> {
+        if base_step < MIN_BASE_STEP || 60 % base_step != 0 {
+            bail!("invalid base step {} - must be a divisor of 60", base_step);
+        }
+
+        let mut rrd = Self::create_proxmox_backup_default_rrd(dst);
+        if base_step == 60 {
+            return Ok(rrd);
+        }
+
+        let points = window / base_step;
+        if points < 1 {
+            bail!("window too small ('{}' < '{}')", window, base_step);
+        }
+        if points > MAX_ARCHIVE_POINTS {
+            bail!(
+                "window too large for base step {} ('{}' > '{}' data points)",
+                base_step,
+                points,
+                MAX_ARCHIVE_POINTS
+            );
+        }
+
+        rrd.rra_list.splice(
+            0..0,
+            [
+                Archive::new(AggregationFn::Average, base_step, points as usize),
+                Archive::new(AggregationFn::Maximum, base_step, points as usize),
+            ],
+        );
+
+        Ok(rrd)
+    }
+
     /// Sync the journal data to disk (using `fdatasync` syscall)
     pub fn sync_journal(&self) -> Result<(), Error> {
         self.state.read().unwrap().sync_journal()
     }
 
     /// Apply and commit the journal. Should be used at server startup.
+    ///
+    /// Afterwards, the journal is committed every `apply_interval` seconds, or earlier if it
+    /// exceeds the size limit (see [Self::set_max_journal_size]).
     pub fn apply_journal(&self) -> Result<bool, Error> {
-        let config = Arc::clone(&self.config);
-        let state = Arc::clone(&self.state);
-        let rrd_map = Arc::clone(&self.rrd_map);
-
-        let mut state_guard = self.state.write().unwrap();
-        let journal_applied = state_guard.journal_applied;
+        schedule_journal_apply(&self.config, &self.state, &self.rrd_map, false)
+    }
 
-        if let Some(ref recv) = state_guard.apply_thread_result {
-            match recv.try_recv() {
-                Ok(Ok(())) => {
-                    // finished without errors, OK
-                    state_guard.apply_thread_result = None;
-                }
-                Ok(Err(err)) => {
-                    // finished with errors, log them
-                    log::error!("{}", err);
-                    state_guard.apply_thread_result = None;
-                }
-                Err(TryRecvError::Empty) => {
-                    // still running
-                    return Ok(journal_applied);
-                }
-                Err(TryRecvError::Disconnected) => {
-                    // crashed, start again
-                    log::error!("apply journal thread crashed - try again");
-                    state_guard.apply_thread_result = None;
-                }
-            }
-        }
+    /// Commit the journal now, without waiting for the apply interval.
+    ///
+    /// The commit runs in a background thread, this does nothing if a commit is already in
+    /// progress.
+    pub fn flush_journal(&self) -> Result<(), Error> {
+        schedule_journal_apply(&self.config, &self.state, &self.rrd_map, true)?;
+        Ok(())
+    }
 
-        let now = proxmox_time::epoch_f64();
-        let wants_commit = (now - state_guard.last_journal_flush) > self.config.apply_interval;
+    /// Set the journal size (in bytes) which triggers an early commit. `None` disables the
+    /// limit, the default is 64 MiB.
+    pub fn set_max_journal_size(&self, max_journal_size: Option<u64>) {
+        self.state.write().unwrap().max_journal_size = max_journal_size;
+    }
 
-        if journal_applied && !wants_commit {
-            return Ok(journal_applied);
-        }
+    /// Returns statistics about the cache and its journal.
+    pub fn stats(&self) -> Result<CacheStats, Error> {
+        cache_stats(&self.state, &self.rrd_map)
+    }
 
-        state_guard.last_journal_flush = proxmox_time::epoch_f64();
+    /// Register a `rrd-cache-stats` command on the [CommandSocket], which returns
+    /// [Self::stats], e.g. to be served by a metrics endpoint of another process.
+    ///
+    /// [CommandSocket]: proxmox_rest_server::CommandSocket
+    #[cfg(feature = "rest-server")]
+    pub fn register_stats_command(
+        &self,
+        commando_sock: &mut proxmox_rest_server::CommandSocket,
+    ) -> Result<(), Error> {
+        let state = Arc::clone(&self.state);
+        let rrd_map = Arc::clone(&self.rrd_map);
 
-        let (sender, receiver) = bounded(1);
-        state_guard.apply_thread_result = Some(receiver);
+        commando_sock.register_command("rrd-cache-stats".into(), move |_args| {
+            Ok(serde_json::to_value(cache_stats(&state, &rrd_map)?)?)
+        })
+    }
 
-        spawn(move || {
-            let result = apply_and_commit_journal_thread(config, state, rrd_map, journal_applied)
-                .map_err(|err| err.to_string());
-            sender.send(result).unwrap();
-        });
+    /// Register a `rrd-journal-flush` command on the [CommandSocket], which calls
+    /// [Self::flush_journal].
+    ///
+    /// [CommandSocket]: proxmox_rest_server::CommandSocket
+    #[cfg(feature = "rest-server")]
+    pub fn register_flush_command(
+        &self,
+        commando_sock: &mut proxmox_rest_server::CommandSocket,
+    ) -> Result<(), Error> {
+        let config = Arc::clone(&self.config);
+        let state = Arc::clone(&self.state);
+        let rrd_map = Arc::clone(&self.rrd_map);
 
-        Ok(journal_applied)
+        commando_sock.register_command("rrd-journal-flush".into(), move |_args| {
+            log::info!("flushing rrd journal");
+            schedule_journal_apply(&config, &state, &rrd_map, true)?;
+            Ok(serde_json::Value::Null)
+        })
     }
 
     /// Update data in RAM and write file back to disk (journal)
@@ -195,6 +296,10 @@ impl Cache {
             .unwrap()
             .append_journal_entry(time, value, dst, rel_path)?;
 
+        for sink in &self.sinks {
+            sink.send(rel_path, time, value, dst);
+        }
+
         if journal_applied {
             self.rrd_map
                 .write()
@@ -205,6 +310,55 @@ impl Cache {
         Ok(())
     }
 
+    /// Update multiple values with the same timestamp at once
+    ///
+    /// Same as calling [Self::update_value] for each `(rel_path, value, dst)` entry, but
+    /// the entries are grouped by their parent directory and written to the journal with a
+    /// single write. Each directory is created at most once. This considerably reduces the
+    /// overhead when updating many files at once.
+    pub fn update_batch(
+        &self,
+        time: f64,
+        entries: &[(&str, f64, DataSourceType)],
+    ) -> Result<(), Error> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let journal_applied = self.apply_journal()?;
+
+        let groups = group_by_directory(entries);
+
+        self.state
+            .write()
+            .unwrap()
+            .append_journal_entries(time, groups.iter().flatten().copied())?;
+
+        for sink in &self.sinks {
+            for (rel_path, value, dst) in groups.iter().flatten() {
+                sink.send(rel_path, time, *value, *dst);
+            }
+        }
+
+        if journal_applied {
+            let mut rrd_map = self.rrd_map.write().unwrap();
+            let mut errors = 0;
+            for group in groups {
+                if let Err(err) = rrd_map.update_group(time, &group) {
+                    errors += group.len();
+                    for (rel_path, _, _) in group {
+                        log::error!("unable to update rrd {}: {}", rel_path, err);
+                    }
+                }
+            }
+            if errors != 0 {
+                bail!("errors during batch update of {} rrd files", errors);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Extract data from cached RRD
     ///
     /// `start`: Start time. If not specified, we simply extract 10 data points.
@@ -226,6 +380,115 @@ impl Cache {
     }
 }
 
+fn cache_stats(
+    state: &Arc<RwLock<JournalState>>,
+    rrd_map: &Arc<RwLock<RRDMap>>,
+) -> Result<CacheStats, Error> {
+    let files = rrd_map.read().unwrap().file_count();
+    let state = state.read().unwrap();
+
+    Ok(CacheStats {
+        files,
+        journal_size: state.journal_size,
+        pending_updates: state.journal_entries,
+        old_journals: state.list_old_journals()?.len(),
+        journal_applied: state.journal_applied,
+        commit_running: state
+            .apply_thread_result
+            .as_ref()
+            .is_some_and(|result| result.is_empty()),
+        last_commit: state.last_commit,
+        last_apply_duration: state.last_apply_duration,
+        last_commit_duration: state.last_commit_duration,
+    })
+}
+
+/// Groups the entries by their parent directory, in the order the directories first occur.
+///
+/// Updates of the same file keep their order.
+fn group_by_directory<'a>(
+    entries: &[(&'a str, f64, DataSourceType)],
+) -> Vec<Vec<(&'a str, f64, DataSourceType)>> {
+    let mut groups: Vec<Vec<(&'a str, f64, DataSourceType)>> = Vec::new();
+    let mut index: HashMap<Option<&Path>, usize> = HashMap::new();
+
+    for entry in entries {
+        let dir = Path::new(entry.0).parent();
+        match index.get(&dir) {
+            Some(i) => groups[*i].push(*entry),
+            None => {
+                index.insert(dir, groups.len());
+                groups.push(vec![*entry]);
+            }
+        }
+    }
+
+    groups
+}
+
+/// Start applying/committing the journal in a background thread, if necessary.
+///
+/// Returns whether the journal was already applied (i.e. updates can go to the RRD map
+/// directly).
+fn schedule_journal_apply(
+    config: &Arc<CacheConfig>,
+    state: &Arc<RwLock<JournalState>>,
+    rrd_map: &Arc<RwLock<RRDMap>>,
+    force: bool,
+) -> Result<bool, Error> {
+    let mut state_guard = state.write().unwrap();
+    let journal_applied = state_guard.journal_applied;
+
+    if let Some(ref recv) = state_guard.apply_thread_result {
+        match recv.try_recv() {
+            Ok(Ok(())) => {
+                // finished without errors, OK
+                state_guard.apply_thread_result = None;
+            }
+            Ok(Err(err)) => {
+                // finished with errors, log them
+                log::error!("{}", err);
+                state_guard.apply_thread_result = None;
+            }
+            Err(TryRecvError::Empty) => {
+                // still running
+                return Ok(journal_applied);
+            }
+            Err(TryRecvError::Disconnected) => {
+                // crashed, start again
+                log::error!("apply journal thread crashed - try again");
+                state_guard.apply_thread_result = None;
+            }
+        }
+    }
+
+    let now = proxmox_time::epoch_f64();
+    let wants_commit = force
+        || (now - state_guard.last_journal_flush) > config.apply_interval
+        || state_guard.journal_too_large();
+
+    if journal_applied && !wants_commit {
+        return Ok(journal_applied);
+    }
+
+    state_guard.last_journal_flush = proxmox_time::epoch_f64();
+
+    let (sender, receiver) = bounded(1);
+    state_guard.apply_thread_result = Some(receiver);
+
+    let config = Arc::clone(config);
+    let state = Arc::clone(state);
+    let rrd_map = Arc::clone(rrd_map);
+
+    spawn(move || {
+        let result = apply_and_commit_journal_thread(config, state, rrd_map, journal_applied)
+            .map_err(|err| err.to_string());
+        sender.send(result).unwrap();
+    });
+
+    Ok(journal_applied)
+}
+
 fn apply_and_commit_journal_thread(
     config: Arc<CacheConfig>,
     state: Arc<RwLock<JournalState>>,
@@ -241,6 +504,7 @@ fn apply_and_commit_journal_thread(
         match apply_journal_impl(Arc::clone(&state), Arc::clone(&rrd_map)) {
             Ok(entries) => {
                 let elapsed = start_time.elapsed().unwrap().as_secs_f64();
+                state.write().unwrap().last_apply_duration = Some(elapsed);
                 log::info!(
                     "applied rrd journal ({} entries in {:.3} seconds)",
                     entries,
@@ -254,9 +518,13 @@ fn apply_and_commit_journal_thread(
     let start_time = SystemTime::now();
     log::debug!("commit rrd journal");
 
-    match commit_journal_impl(config, state, rrd_map) {
+    match commit_journal_impl(config, Arc::clone(&state), rrd_map) {
         Ok(rrd_file_count) => {
             let elapsed = start_time.elapsed().unwrap().as_secs_f64();
+            let mut state = state.write().unwrap();
+            state.last_commit = Some(proxmox_time::epoch_f64());
+            state.last_commit_duration = Some(elapsed);
+            drop(state);
             log::info!(
                 "rrd journal successfully committed ({} files in {:.3} seconds)",
                 rrd_file_count,
@@ -291,6 +559,16 @@ fn apply_journal_lines(
             break;
         }
 
+        if !line.ends_with('\n') {
+            // we crashed while writing this entry
+            log::warn!(
+                "incomplete entry at end of rrd journal '{}' line {} (skip)",
+                journal_name,
+                linenr,
+            );
+            break;
+        }
+
         let entry: JournalEntry = match line.parse() {
             Ok(entry) => entry,
             Err(err) => {
@@ -446,3 +724,42 @@ fn commit_journal_impl(
 
     Ok(rrd_file_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_by_directory() {
+        use DataSourceType::Gauge;
+
+        let entries = [
+            ("host/cpu", 1.0, Gauge),
+            ("vm/100/cpu", 2.0, Gauge),
+            ("host/mem", 3.0, Gauge),
+            ("top", 4.0, Gauge),
+            ("vm/100/cpu", 5.0, Gauge),
+            ("vm/101/cpu", 6.0, Gauge),
+        ];
+
+        let groups: Vec<Vec<(&str, f64)>> = group_by_directory(&entries)
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .map(|(path, value, _)| (path, value))
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(
+            groups,
+            [
+                vec![("host/cpu", 1.0), ("host/mem", 3.0)],
+                vec![("vm/100/cpu", 2.0), ("vm/100/cpu", 5.0)],
+                vec![("top", 4.0)],
+                vec![("vm/101/cpu", 6.0)],
+            ]
+        );
+    }
+}
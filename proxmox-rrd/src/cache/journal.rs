@@ -1,8 +1,8 @@
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{BufReader, Write};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
 use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -14,6 +14,9 @@ use proxmox_sys::fs::atomic_open_or_create_file;
 
 const RRD_JOURNAL_NAME: &str = "rrd.journal";
 
+/// Default size limit of the journal, see [JournalState::max_journal_size].
+pub const DEFAULT_MAX_JOURNAL_SIZE: u64 = 64 * 1024 * 1024;
+
 use crate::cache::CacheConfig;
 use crate::rrd::DataSourceType;
 
@@ -21,9 +24,21 @@ use crate::rrd::DataSourceType;
 pub struct JournalState {
     config: Arc<CacheConfig>,
     journal: File,
+    /// Size of the current journal file in bytes.
+    pub journal_size: u64,
+    /// Number of entries written to the current journal file by this process.
+    pub journal_entries: u64,
+    /// Commit the journal early if it grows larger than this.
+    pub max_journal_size: Option<u64>,
     pub last_journal_flush: f64,
     pub journal_applied: bool,
     pub apply_thread_result: Option<Receiver<Result<(), String>>>,
+    /// Time (epoch) of the last successful commit.
+    pub last_commit: Option<f64>,
+    /// Duration of the last journal apply in seconds.
+    pub last_apply_duration: Option<f64>,
+    /// Duration of the last successful commit in seconds.
+    pub last_commit_duration: Option<f64>,
 }
 
 pub struct JournalEntry {
@@ -57,6 +72,9 @@ impl FromStr for JournalEntry {
         let dst = match dst {
             0 => DataSourceType::Gauge,
             1 => DataSourceType::Derive,
+            2 => DataSourceType::Counter,
+            3 => DataSourceType::Absolute,
+            4 => DataSourceType::DeriveRate,
             _ => bail!("got strange value for data source type '{}'", dst),
         };
 
@@ -79,13 +97,24 @@ pub struct JournalFileInfo {
 
 impl JournalState {
     pub(crate) fn new(config: Arc<CacheConfig>) -> Result<Self, Error> {
+        let mut journal_path = config.basedir.clone();
+        journal_path.push(RRD_JOURNAL_NAME);
+        remove_incomplete_entry(&journal_path)?;
+
         let journal = JournalState::open_journal_writer(&config)?;
+        let journal_size = journal.metadata()?.len();
         Ok(Self {
             config,
             journal,
+            journal_size,
+            journal_entries: 0,
+            max_journal_size: Some(DEFAULT_MAX_JOURNAL_SIZE),
             last_journal_flush: 0.0,
             journal_applied: false,
             apply_thread_result: None,
+            last_commit: None,
+            last_apply_duration: None,
+            last_commit_duration: None,
         })
     }
 
@@ -103,6 +132,24 @@ impl JournalState {
     ) -> Result<(), Error> {
         let journal_entry = format!("{}:{}:{}:{}\n", time, value, dst as u8, rel_path);
         self.journal.write_all(journal_entry.as_bytes())?;
+        self.journal_size += journal_entry.len() as u64;
+        self.journal_entries += 1;
+        Ok(())
+    }
+
+    /// Append multiple entries with a single write.
+    pub fn append_journal_entries<'a>(
+        &mut self,
+        time: f64,
+        entries: impl IntoIterator<Item = (&'a str, f64, DataSourceType)>,
+    ) -> Result<(), Error> {
+        let mut data = String::new();
+        for (rel_path, value, dst) in entries {
+            self.journal_entries += 1;
+            data.push_str(&format!("{}:{}:{}:{}\n", time, value, dst as u8, rel_path));
+        }
+        self.journal.write_all(data.as_bytes())?;
+        self.journal_size += data.len() as u64;
         Ok(())
     }
 
@@ -142,11 +189,18 @@ impl JournalState {
         journal_path.push(RRD_JOURNAL_NAME);
 
         let mut new_name = journal_path.clone();
-        let now = proxmox_time::epoch_i64();
+        let mut now = proxmox_time::epoch_i64();
         new_name.set_extension(format!("journal-{:08x}", now));
+        // do not overwrite an uncommitted journal rotated within the same second
+        while new_name.exists() {
+            now += 1;
+            new_name.set_extension(format!("journal-{:08x}", now));
+        }
         std::fs::rename(journal_path, &new_name)?;
 
         self.journal = Self::open_journal_writer(&self.config)?;
+        self.journal_size = 0;
+        self.journal_entries = 0;
 
         // make sure the old journal data landed on the disk
         super::fsync_file_and_parent(&new_name)?;
@@ -154,6 +208,11 @@ impl JournalState {
         Ok(())
     }
 
+    /// Returns true if the journal exceeds the configured size limit.
+    pub fn journal_too_large(&self) -> bool {
+        matches!(self.max_journal_size, Some(max) if self.journal_size > max)
+    }
+
     pub fn remove_old_journals(&self) -> Result<(), Error> {
         let journal_list = self.list_old_journals()?;
 
@@ -198,3 +257,92 @@ impl JournalState {
         Ok(list)
     }
 }
+
+/// Truncate the journal after the last complete entry.
+///
+/// If we crashed while writing an entry, the journal ends with an incomplete line. New entries
+/// would be appended to that line, which would corrupt them as well.
+fn remove_incomplete_entry(path: &Path) -> Result<(), Error> {
+    let mut file = match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => bail!("unable to open rrd journal {path:?} - {err}"),
+    };
+
+    let size = file.metadata()?.len();
+    let mut end = size;
+    let mut buffer = [0u8; 4096];
+
+    while end > 0 {
+        let start = end.saturating_sub(buffer.len() as u64);
+        let chunk = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+
+        if let Some(pos) = chunk.iter().rposition(|b| *b == b'\n') {
+            end = start + pos as u64 + 1;
+            break;
+        }
+        end = start;
+    }
+
+    if end < size {
+        log::warn!(
+            "removing incomplete entry at the end of rrd journal ({} bytes)",
+            size - end
+        );
+        file.set_len(end)?;
+        file.sync_data()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_incomplete_entry_test() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("rrd-journal-test-{}", std::process::id()));
+
+        let complete = "1:2:0:a/b\n3:4:1:a/c\n";
+        std::fs::write(&path, format!("{complete}5:6"))?;
+        remove_incomplete_entry(&path)?;
+        assert_eq!(std::fs::read_to_string(&path)?, complete);
+
+        // nothing to do
+        remove_incomplete_entry(&path)?;
+        assert_eq!(std::fs::read_to_string(&path)?, complete);
+
+        std::fs::write(&path, "1:2:0")?;
+        remove_incomplete_entry(&path)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "");
+
+        std::fs::remove_file(&path)?;
+        remove_incomplete_entry(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn journal_entry_dst_test() -> Result<(), Error> {
+        for dst in [
+            DataSourceType::Gauge,
+            DataSourceType::Derive,
+            DataSourceType::Counter,
+            DataSourceType::Absolute,
+            DataSourceType::DeriveRate,
+        ] {
+            let entry: JournalEntry = format!("1:2:{}:a/b", dst as u8).parse()?;
+            assert_eq!(entry.dst, dst);
+        }
+        assert!("1:2:5:a/b".parse::<JournalEntry>().is_err());
+
+        Ok(())
+    }
+}
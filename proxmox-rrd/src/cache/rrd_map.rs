@@ -29,6 +29,11 @@ impl RRDMap {
         }
     }
 
+    /// Number of RRD files loaded into memory.
+    pub fn file_count(&self) -> usize {
+        self.map.len()
+    }
+
     pub fn update(
         &mut self,
         rel_path: &str,
@@ -37,6 +42,54 @@ impl RRDMap {
         dst: DataSourceType,
         new_only: bool,
     ) -> Result<(), Error> {
+        if !self.map.contains_key(rel_path) {
+            self.create_parent_dir(rel_path)?;
+        }
+        self.update_or_load(rel_path, time, value, dst, new_only);
+        Ok(())
+    }
+
+    /// Update multiple RRD files located in the same directory.
+    ///
+    /// The directory is created only once, if any of the files is not loaded yet.
+    pub fn update_group(
+        &mut self,
+        time: f64,
+        entries: &[(&str, f64, DataSourceType)],
+    ) -> Result<(), Error> {
+        if let Some((rel_path, _, _)) = entries
+            .iter()
+            .find(|(rel_path, _, _)| !self.map.contains_key(*rel_path))
+        {
+            self.create_parent_dir(rel_path)?;
+        }
+
+        for (rel_path, value, dst) in entries {
+            self.update_or_load(rel_path, time, *value, *dst, false);
+        }
+        Ok(())
+    }
+
+    fn create_parent_dir(&self, rel_path: &str) -> Result<(), Error> {
+        let mut path = self.config.basedir.clone();
+        path.push(rel_path);
+        create_path(
+            path.parent().unwrap(),
+            Some(self.config.dir_options.clone()),
+            Some(self.config.dir_options.clone()),
+        )?;
+        Ok(())
+    }
+
+    // Updates a loaded RRD file, or loads it first, its directory needs to exist already.
+    fn update_or_load(
+        &mut self,
+        rel_path: &str,
+        time: f64,
+        value: f64,
+        dst: DataSourceType,
+        new_only: bool,
+    ) {
         if let Some(rrd) = self.map.get_mut(rel_path) {
             if !new_only || time > rrd.last_update() {
                 rrd.update(time, value);
@@ -44,11 +97,6 @@ impl RRDMap {
         } else {
             let mut path = self.config.basedir.clone();
             path.push(rel_path);
-            create_path(
-                path.parent().unwrap(),
-                Some(self.config.dir_options.clone()),
-                Some(self.config.dir_options.clone()),
-            )?;
 
             let mut rrd = (self.load_rrd_cb)(&path, rel_path, dst);
 
@@ -57,7 +105,6 @@ impl RRDMap {
             }
             self.map.insert(rel_path.to_string(), rrd);
         }
-        Ok(())
     }
 
     pub fn file_list(&self) -> Vec<String> {
@@ -95,3 +142,48 @@ impl RRDMap {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use proxmox_sys::fs::CreateOptions;
+
+    use super::*;
+
+    static LOADED: AtomicUsize = AtomicUsize::new(0);
+
+    fn load_rrd(_path: &Path, _rel_path: &str, dst: DataSourceType) -> Database {
+        LOADED.fetch_add(1, Ordering::SeqCst);
+        Database::new(dst, Vec::new())
+    }
+
+    #[test]
+    fn test_update_group() -> Result<(), Error> {
+        let basedir = std::env::temp_dir().join(format!("rrd-map-test-{}", std::process::id()));
+        let config = Arc::new(CacheConfig {
+            apply_interval: 0.0,
+            basedir: basedir.clone(),
+            file_options: CreateOptions::new(),
+            dir_options: CreateOptions::new(),
+        });
+        let mut map = RRDMap::new(config, load_rrd);
+
+        let entries = [
+            ("host/cpu", 1.0, DataSourceType::Gauge),
+            ("host/mem", 2.0, DataSourceType::Gauge),
+        ];
+        map.update_group(60.0, &entries)?;
+        assert!(basedir.join("host").is_dir());
+        assert_eq!(LOADED.load(Ordering::SeqCst), 2);
+        assert_eq!(map.file_count(), 2);
+
+        // loaded files are only updated
+        map.update_group(120.0, &entries)?;
+        assert_eq!(LOADED.load(Ordering::SeqCst), 2);
+        assert_eq!(map.map["host/cpu"].last_update(), 120.0);
+
+        let _ = std::fs::remove_dir_all(&basedir);
+        Ok(())
+    }
+}
@@ -5,6 +5,7 @@
 //! * One file stores a single data source
 //! * Stores data for different time resolution
 //! * Simple cache implementation with journal support
+//! * Optional forwarding of cached values to external metrics servers
 
 #[cfg(feature = "rrd_v1")]
 mod rrd_v1;
@@ -15,3 +16,5 @@ pub use rrd::Entry;
 
 mod cache;
 pub use cache::*;
+
+pub mod sink;
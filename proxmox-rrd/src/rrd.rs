@@ -21,6 +21,9 @@ use serde::{Deserialize, Serialize};
 use proxmox_schema::api;
 use proxmox_sys::fs::{make_tmp_file, CreateOptions};
 
+mod mapped;
+pub use mapped::{MappedArchive, MappedDatabase};
+
 /// Proxmox RRD v2 file magic number
 // openssl::sha::sha256(b"Proxmox Round Robin Database file v2.0")[0..8];
 pub const PROXMOX_RRD_MAGIC_2_0: [u8; 8] = [224, 200, 228, 27, 239, 112, 122, 159];
@@ -32,11 +35,18 @@ pub const PROXMOX_RRD_MAGIC_2_0: [u8; 8] = [224, 200, 228, 27, 239, 112, 122, 15
 pub enum DataSourceType {
     /// Gauge values are stored unmodified.
     Gauge,
-    /// Stores the difference to the previous value.
+    /// Stores the difference to the previous value, normalized to a per second rate.
     Derive,
     /// Stores the difference to the previous value (like Derive), but
     /// detect counter overflow (and ignores that value)
     Counter,
+    /// The value is the difference since the last update (i.e. the counter gets reset after
+    /// each read), stored as per second rate.
+    Absolute,
+    /// Stores the difference to the previous value as per second rate (like Derive), but a
+    /// decreasing value is considered a counter restart, so the rate is computed from zero
+    /// instead of getting negative.
+    DeriveRate,
 }
 
 #[api()]
@@ -52,6 +62,43 @@ pub enum AggregationFn {
     Minimum,
     /// Use the last value
     Last,
+    /// Median (50th percentile)
+    Median,
+    /// 95th percentile
+    Percentile95,
+    /// 99th percentile
+    Percentile99,
+}
+
+/// Maximum number of data points for a single RRA.
+pub(crate) const MAX_ARCHIVE_POINTS: u64 = 1024 * 1024;
+
+/// Maximum number of samples kept per slot to compute percentiles.
+const MAX_PERCENTILE_SAMPLES: usize = 1024;
+
+impl AggregationFn {
+    /// Returns the percentile (`0.0..=1.0`) for percentile consolidation functions.
+    pub fn percentile(self) -> Option<f64> {
+        match self {
+            AggregationFn::Median => Some(0.5),
+            AggregationFn::Percentile95 => Some(0.95),
+            AggregationFn::Percentile99 => Some(0.99),
+            _ => None,
+        }
+    }
+}
+
+/// Compute a percentile of sorted `values` with linear interpolation between the closest ranks.
+fn compute_percentile(values: &[f64], percentile: f64) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+
+    let rank = percentile * (values.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    values[lower] + (values[upper] - values[lower]) * (rank - lower as f64)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -64,6 +111,12 @@ pub struct DataSource {
     /// Stores the last value, used to compute differential value for
     /// derive/counters
     pub last_value: f64,
+    /// Maximum plausible rate (per second) for differential data sources.
+    ///
+    /// If set, decreasing derive and counter values are checked for a 32 or 64 bit counter
+    /// wrap, and rates above this limit are considered a counter reset and dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rate: Option<f64>,
 }
 
 /// An RRD entry.
@@ -94,6 +147,106 @@ impl Entry {
     pub fn get(&self, idx: usize) -> Option<f64> {
         self.data.get(idx).copied().flatten()
     }
+
+    /// Iterate over the data points as `(time, value)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, Option<f64>)> + '_ {
+        let mut time = self.start;
+        self.data.iter().map(move |value| {
+            let item = (time, *value);
+            time += self.resolution;
+            item
+        })
+    }
+
+    /// Resample the data to a coarser resolution of `step` seconds.
+    ///
+    /// The data points within each step are consolidated with `cf`, missing values are
+    /// ignored. The start time is aligned to `step`. Percentiles cannot be computed from
+    /// already consolidated values, so this fails for percentile aggregation functions.
+    pub fn resample(&self, step: u64, cf: AggregationFn) -> Result<Entry, Error> {
+        if step == 0 || step % self.resolution != 0 {
+            bail!(
+                "resample step {step} is not a multiple of the resolution {}",
+                self.resolution
+            );
+        }
+        if cf.percentile().is_some() {
+            bail!("unable to resample {cf:?} values");
+        }
+
+        let start = self.start - self.start % step;
+
+        let mut data: Vec<Option<f64>> = Vec::new();
+        let mut counts: Vec<u64> = Vec::new();
+
+        for (time, value) in self.iter() {
+            let index = ((time - start) / step) as usize;
+            if index >= data.len() {
+                data.resize(index + 1, None);
+                counts.resize(index + 1, 0);
+            }
+
+            let value = match value {
+                Some(value) => value,
+                None => continue,
+            };
+
+            counts[index] += 1;
+            data[index] = Some(match (data[index], cf) {
+                (None, _) => value,
+                (Some(current), AggregationFn::Average) => current + value,
+                (Some(current), AggregationFn::Maximum) => current.max(value),
+                (Some(current), AggregationFn::Minimum) => current.min(value),
+                (Some(_), _) => value,
+            });
+        }
+
+        if cf == AggregationFn::Average {
+            for (value, count) in data.iter_mut().zip(counts) {
+                if let Some(value) = value {
+                    *value /= count as f64;
+                }
+            }
+        }
+
+        Ok(Entry::new(start, step, data))
+    }
+
+    /// Format the data as JSON list of `{ "time": <epoch>, "value": <value> }` objects.
+    ///
+    /// Missing values are represented as `null`.
+    pub fn to_json(&self) -> serde_json::Value {
+        self.iter()
+            .map(|(time, value)| serde_json::json!({ "time": time, "value": value }))
+            .collect()
+    }
+
+    /// Format the data as CSV with a `time,value` header line.
+    ///
+    /// Missing values are represented as empty fields.
+    pub fn to_csv(&self) -> String {
+        use std::fmt::Write;
+
+        let mut csv = String::from("time,value\n");
+        for (time, value) in self.iter() {
+            let _ = match value {
+                Some(value) => writeln!(csv, "{time},{value}"),
+                None => writeln!(csv, "{time},"),
+            };
+        }
+        csv
+    }
+}
+
+#[api()]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Export format for [Database::export_data]
+pub enum ExportFormat {
+    /// JSON list of objects with `time` and `value` properties
+    Json,
+    /// CSV with `time` and `value` columns
+    Csv,
 }
 
 impl From<Entry> for (u64, u64, Vec<Option<f64>>) {
@@ -115,10 +268,27 @@ impl DataSource {
             dst,
             last_update: 0.0,
             last_value: f64::NAN,
+            max_rate: None,
+        }
+    }
+
+    /// Compute the difference to the last value, assuming the counter wrapped around.
+    ///
+    /// Tries a 32 bit wrap first, then a 64 bit wrap (like rrdtool's COUNTER type).
+    fn wrapped_diff(&self, value: f64) -> f64 {
+        let diff = value - self.last_value;
+        if self.last_value < 4294967296.0 {
+            diff + 4294967296.0
+        } else {
+            diff + 18446744073709551616.0
         }
     }
 
-    fn compute_new_value(&mut self, time: f64, mut value: f64) -> Result<f64, Error> {
+    // Computes the value to store in the archives.
+    //
+    // `last_value` is only updated if the value is accepted, so the next value is compared with
+    // the last accepted one.
+    fn compute_new_value(&mut self, time: f64, value: f64) -> Result<f64, Error> {
         if time < 0.0 {
             bail!("got negative time");
         }
@@ -140,22 +310,67 @@ impl DataSource {
                 0.0
             } else if is_counter && value < 0.0 {
                 bail!("got negative value for counter");
+            } else if value < self.last_value && self.max_rate.is_some() {
+                self.wrapped_diff(value)
             } else if is_counter && value < self.last_value {
-                // Note: We do not try automatic overflow corrections, but
-                // we update last_value anyways, so that we can compute the diff
-                // next time.
-                self.last_value = value;
+                // Note: We do not try automatic overflow corrections.
                 bail!("counter overflow/reset detected");
             } else {
                 value - self.last_value
             };
+            let rate = diff / time_diff;
+
+            if let Some(max_rate) = self.max_rate {
+                if rate > max_rate {
+                    bail!("counter reset detected (rate {rate} > {max_rate})");
+                }
+            }
+            self.last_value = value;
+            Ok(rate)
+        } else if self.dst == DataSourceType::Absolute {
+            if value < 0.0 {
+                bail!("got negative value for absolute counter");
+            }
+            let time_diff = time - self.last_update;
+
+            // we do not know the period the first value was collected in
+            let diff = if self.last_value.is_nan() { 0.0 } else { value };
+            let rate = diff / time_diff;
+
+            if let Some(max_rate) = self.max_rate {
+                if rate > max_rate {
+                    bail!("absolute counter rate too high ({rate} > {max_rate})");
+                }
+            }
             self.last_value = value;
-            value = diff / time_diff;
+            Ok(rate)
+        } else if self.dst == DataSourceType::DeriveRate {
+            if value < 0.0 {
+                bail!("got negative value for derive rate");
+            }
+            let time_diff = time - self.last_update;
+
+            let diff = if self.last_value.is_nan() {
+                0.0
+            } else if value < self.last_value {
+                // counter restarted, so it counted up from zero since the last update
+                value
+            } else {
+                value - self.last_value
+            };
+            let rate = diff / time_diff;
+
+            if let Some(max_rate) = self.max_rate {
+                if rate > max_rate {
+                    bail!("derive rate too high ({rate} > {max_rate})");
+                }
+            }
+            self.last_value = value;
+            Ok(rate)
         } else {
             self.last_value = value;
+            Ok(value)
         }
-
-        Ok(value)
     }
 }
 
@@ -170,6 +385,9 @@ pub struct Archive {
     pub last_count: u64,
     /// The actual data entries.
     pub data: Vec<f64>,
+    /// Sorted values of the current slot, only used by percentile consolidation functions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slot_values: Vec<f64>,
 }
 
 impl Archive {
@@ -180,6 +398,7 @@ impl Archive {
             resolution,
             last_count: 0,
             data: vec![f64::NAN; points],
+            slot_values: Vec::new(),
         }
     }
 
@@ -270,6 +489,16 @@ impl Archive {
 
         let new_count = self.last_count.saturating_add(1);
 
+        if let Some(percentile) = self.cf.percentile() {
+            if self.last_count == 0 {
+                self.slot_values.clear();
+            }
+            self.add_slot_value(value);
+            self.data[index] = compute_percentile(&self.slot_values, percentile);
+            self.last_count = new_count;
+            return;
+        }
+
         if self.last_count == 0 {
             self.data[index] = value;
             self.last_count = 1;
@@ -294,52 +523,107 @@ impl Archive {
                     (last_value * (self.last_count as f64)) / (new_count as f64)
                         + value / (new_count as f64)
                 }
+                AggregationFn::Median
+                | AggregationFn::Percentile95
+                | AggregationFn::Percentile99 => unreachable!(),
             };
             self.data[index] = new_value;
             self.last_count = new_count;
         }
     }
 
+    // Insert a value into the sorted list of slot values.
+    //
+    // If the list gets too long, every second value is dropped. This keeps the distribution
+    // of the values, so the computed percentiles remain a good approximation.
+    fn add_slot_value(&mut self, value: f64) {
+        let pos = self.slot_values.partition_point(|v| *v < value);
+        self.slot_values.insert(pos, value);
+
+        if self.slot_values.len() > MAX_PERCENTILE_SAMPLES {
+            let mut index = 0;
+            self.slot_values.retain(|_| {
+                index += 1;
+                index % 2 == 1
+            });
+        }
+    }
+
     /// Extract data
     ///
     /// Extract data from `start` to `end`. The RRA itself does not
     /// store the `last_update` time, so you need to pass this a
     /// parameter (see [DataSource]).
     pub fn extract_data(&self, start: u64, end: u64, last_update: f64) -> Entry {
-        let last_update = last_update as u64;
-        let reso = self.resolution;
-        let num_entries = self.data.len() as u64;
+        let index = self.slot(start);
+        let values = self.data[index..]
+            .iter()
+            .chain(&self.data[..index])
+            .copied();
+        extract_entry(
+            self.resolution,
+            self.data.len(),
+            start,
+            end,
+            last_update,
+            values,
+        )
+    }
+}
 
-        let mut list = Vec::new();
+// Build the [Entry] from `start` to `end` for an archive with `num_entries` slots.
+//
+// `values` must yield the slot values starting at the slot of `start`, wrapping around.
+fn extract_entry(
+    reso: u64,
+    num_entries: usize,
+    start: u64,
+    end: u64,
+    last_update: f64,
+    values: impl Iterator<Item = f64>,
+) -> Entry {
+    let last_update = last_update as u64;
+
+    let mut list = Vec::new();
+
+    let rrd_end = reso * (last_update / reso + 1);
+    let rrd_start = rrd_end.saturating_sub(reso * num_entries as u64);
+
+    let mut t = start;
+    for value in values.take(num_entries) {
+        if t > end {
+            break;
+        };
+        if t < rrd_start || t >= rrd_end || value.is_nan() {
+            list.push(None);
+        } else {
+            list.push(Some(value));
+        }
+        t += reso;
+    }
 
-        let rrd_end = self.slot_end_time(last_update);
-        let rrd_start = rrd_end.saturating_sub(reso * num_entries);
+    Entry::new(start, reso, list)
+}
 
-        let mut t = start;
-        let mut index = self.slot(t);
-        for _ in 0..num_entries {
-            if t > end {
-                break;
-            };
-            if t < rrd_start || t >= rrd_end {
-                list.push(None);
-            } else {
-                let value = self.data[index];
-                if value.is_nan() {
-                    list.push(None);
-                } else {
-                    list.push(Some(value));
-                }
-            }
-            t += reso;
-            index += 1;
-            if index >= self.data.len() {
-                index = 0;
-            }
+// Find the archive using `cf` with the largest resolution not above `resolution`.
+//
+// Returns the index of the first such archive in `list`.
+fn find_archive(
+    list: impl Iterator<Item = (AggregationFn, u64)>,
+    cf: AggregationFn,
+    resolution: u64,
+) -> Option<usize> {
+    let mut found: Option<(usize, u64)> = None;
+    for (index, (item_cf, item_resolution)) in list.enumerate() {
+        if item_cf != cf || item_resolution > resolution {
+            continue;
+        }
+        match found {
+            Some((_, current)) if item_resolution <= current => (),
+            _ => found = Some((index, item_resolution)),
         }
-
-        Entry::new(start, reso, list)
     }
+    found.map(|(index, _)| index)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -385,6 +669,9 @@ impl Database {
 
     /// Load data from a file
     ///
+    /// The whole file is read and decoded. For read-only access to files queried frequently,
+    /// see [Database::open_readonly_mmap].
+    ///
     /// Setting `avoid_page_cache` uses
     /// `fadvise(..,POSIX_FADV_DONTNEED)` to avoid keeping the data in
     /// the linux page cache.
@@ -413,6 +700,18 @@ impl Database {
         }
     }
 
+    /// Open a file read-only by mapping it into memory.
+    ///
+    /// Only the data source is decoded, archive values are read straight from the mapping when
+    /// they are accessed, see [MappedDatabase]. Since [Database::save] atomically replaces
+    /// files, the mapping always refers to a consistent version of the file. Reopen it to see
+    /// newer data.
+    ///
+    /// Files in the old v1 format are not supported and need to be loaded with [Database::load].
+    pub fn open_readonly_mmap(path: &Path) -> Result<MappedDatabase, Error> {
+        MappedDatabase::open(path)
+    }
+
     /// Store data into a file (atomic replace file)
     ///
     /// Setting `avoid_page_cache` uses
@@ -466,6 +765,11 @@ impl Database {
         self.source.last_update
     }
 
+    /// Returns the base step, which is the finest resolution of all RRAs.
+    pub fn base_step(&self) -> Option<u64> {
+        self.rra_list.iter().map(|rra| rra.resolution).min()
+    }
+
     /// Update the value (in memory)
     ///
     /// Note: This does not call [Self::save].
@@ -501,32 +805,145 @@ impl Database {
         start: Option<u64>,
         end: Option<u64>,
     ) -> Result<Entry, Error> {
-        let mut rra: Option<&Archive> = None;
-        for item in self.rra_list.iter() {
-            if item.cf != cf {
-                continue;
+        let index = find_archive(
+            self.rra_list.iter().map(|rra| (rra.cf, rra.resolution)),
+            cf,
+            resolution,
+        );
+
+        match index {
+            Some(index) => {
+                let rra = &self.rra_list[index];
+                let end = end.unwrap_or_else(|| proxmox_time::epoch_f64() as u64);
+                let start = start.unwrap_or_else(|| end.saturating_sub(10 * rra.resolution));
+                Ok(rra.extract_data(start, end, self.source.last_update))
             }
-            if item.resolution > resolution {
-                continue;
+            None => bail!("unable to find RRA suitable ({:?}:{})", cf, resolution),
+        }
+    }
+
+    /// Export data from the archive
+    ///
+    /// Extracts the data like [Self::extract_data], optionally resamples it to `step`
+    /// seconds (see [Entry::resample]) and formats it as JSON or CSV.
+    pub fn export_data(
+        &self,
+        cf: AggregationFn,
+        resolution: u64,
+        start: Option<u64>,
+        end: Option<u64>,
+        step: Option<u64>,
+        format: ExportFormat,
+    ) -> Result<String, Error> {
+        let mut entry = self.extract_data(cf, resolution, start, end)?;
+
+        if let Some(step) = step {
+            if step != entry.resolution {
+                entry = entry.resample(step, cf)?;
             }
+        }
 
-            if let Some(current) = rra {
-                if item.resolution > current.resolution {
-                    rra = Some(item);
-                }
-            } else {
-                rra = Some(item);
+        Ok(match format {
+            ExportFormat::Json => entry.to_json().to_string(),
+            ExportFormat::Csv => entry.to_csv(),
+        })
+    }
+}
+
+/// Builder for a [Database] with a custom set of archives.
+///
+/// ```
+/// # use proxmox_rrd::rrd::{AggregationFn, DataSourceType, DatabaseBuilder};
+/// // keep one year of 5 minute data, and one day of minute data
+/// let rrd = DatabaseBuilder::new(DataSourceType::Gauge)
+///     .archive_timespan(AggregationFn::Average, 300, 365 * 86400)
+///     .archive(AggregationFn::Average, 60, 1440)
+///     .archive(AggregationFn::Maximum, 60, 1440)
+///     .build()
+///     .unwrap();
+/// assert_eq!(rrd.base_step(), Some(60));
+/// ```
+pub struct DatabaseBuilder {
+    dst: DataSourceType,
+    max_rate: Option<f64>,
+    archives: Vec<(AggregationFn, u64, u64)>,
+}
+
+impl DatabaseBuilder {
+    /// Creates a new builder for the given data source type.
+    pub fn new(dst: DataSourceType) -> Self {
+        Self {
+            dst,
+            max_rate: None,
+            archives: Vec::new(),
+        }
+    }
+
+    /// Set the maximum plausible rate for differential data sources, see
+    /// [DataSource::max_rate].
+    pub fn max_rate(mut self, max_rate: f64) -> Self {
+        self.max_rate = Some(max_rate);
+        self
+    }
+
+    /// Add an archive with `resolution` seconds per data point, storing `points` data points.
+    pub fn archive(mut self, cf: AggregationFn, resolution: u64, points: u64) -> Self {
+        self.archives.push((cf, resolution, points));
+        self
+    }
+
+    /// Add an archive with `resolution` seconds per data point, spanning `timespan` seconds.
+    pub fn archive_timespan(self, cf: AggregationFn, resolution: u64, timespan: u64) -> Self {
+        let points = timespan.checked_div(resolution).unwrap_or(0);
+        self.archive(cf, resolution, points)
+    }
+
+    /// Validate the archive list and create the [Database].
+    pub fn build(self) -> Result<Database, Error> {
+        if self.archives.is_empty() {
+            bail!("no archives defined");
+        }
+
+        if let Some(max_rate) = self.max_rate {
+            if self.dst == DataSourceType::Gauge {
+                bail!("max rate is not supported for gauge data sources");
+            }
+            if max_rate.is_nan() || max_rate <= 0.0 {
+                bail!("max rate must be positive");
             }
         }
 
-        match rra {
-            Some(rra) => {
-                let end = end.unwrap_or_else(|| proxmox_time::epoch_f64() as u64);
-                let start = start.unwrap_or_else(|| end.saturating_sub(10 * rra.resolution));
-                Ok(rra.extract_data(start, end, self.source.last_update))
+        let mut rra_list = Vec::with_capacity(self.archives.len());
+
+        for (index, (cf, resolution, points)) in self.archives.iter().enumerate() {
+            if *resolution == 0 {
+                bail!("archive {index} ({cf:?}): resolution must not be zero");
             }
-            None => bail!("unable to find RRA suitable ({:?}:{})", cf, resolution),
+            if *points == 0 {
+                bail!("archive {index} ({cf:?}, {resolution}s): no data points");
+            }
+            if *points > MAX_ARCHIVE_POINTS {
+                bail!(
+                    "archive {index} ({cf:?}, {resolution}s): too many data points \
+                    ({points} > {MAX_ARCHIVE_POINTS})"
+                );
+            }
+            if self.archives[..index]
+                .iter()
+                .any(|(other_cf, other_resolution, _)| {
+                    other_cf == cf && other_resolution == resolution
+                })
+            {
+                bail!("duplicate archive ({cf:?}, {resolution}s)");
+            }
+
+            rra_list.push(Archive::new(*cf, *resolution, *points as usize));
         }
+
+        let mut rrd = Database::new(self.dst, rra_list);
+        rrd.source.max_rate = self.max_rate;
+
+        Ok(rrd)
     }
 }
 
@@ -603,6 +1020,165 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn basic_rra_percentile_gauge_test() -> Result<(), Error> {
+        let rra_list = vec![
+            Archive::new(AggregationFn::Median, 60, 5),
+            Archive::new(AggregationFn::Percentile95, 60, 5),
+        ];
+        let mut rrd = Database::new(DataSourceType::Gauge, rra_list);
+
+        // 21 values per minute, in reverse order
+        for i in 0..42 {
+            let time = 120 + (i % 21) * 2 + (i / 21) * 60;
+            let value = -((i % 21) * 10 + (i / 21) * 1000);
+            rrd.update(time as f64, value as f64);
+        }
+
+        let Entry { data, .. } =
+            rrd.extract_data(AggregationFn::Median, 60, Some(120), Some(3 * 60))?;
+        assert_eq!(data, [Some(-100.0), Some(-1100.0)]);
+
+        let Entry { data, .. } =
+            rrd.extract_data(AggregationFn::Percentile95, 60, Some(120), Some(3 * 60))?;
+        assert_eq!(data, [Some(-10.0), Some(-1010.0)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn database_builder_test() -> Result<(), Error> {
+        let mut rrd = DatabaseBuilder::new(DataSourceType::Gauge)
+            .archive(AggregationFn::Minimum, 60, 5)
+            .archive_timespan(AggregationFn::Average, 300, 365 * 86400)
+            .build()?;
+
+        assert_eq!(rrd.rra_list.len(), 2);
+        assert_eq!(rrd.rra_list[1].data.len(), 365 * 288);
+        assert_eq!(rrd.base_step(), Some(60));
+
+        for i in 2..10 {
+            rrd.update((i as f64) * 30.0, i as f64);
+        }
+
+        let Entry { data, .. } =
+            rrd.extract_data(AggregationFn::Minimum, 60, Some(0), Some(5 * 60))?;
+        assert_eq!(data, [None, Some(2.0), Some(4.0), Some(6.0), Some(8.0)]);
+
+        let Entry {
+            resolution, data, ..
+        } = rrd.extract_data(AggregationFn::Average, 300, Some(0), Some(0))?;
+        assert_eq!(resolution, 300);
+        assert_eq!(data, [Some(5.5)]);
+
+        assert!(DatabaseBuilder::new(DataSourceType::Gauge).build().is_err());
+        assert!(DatabaseBuilder::new(DataSourceType::Gauge)
+            .archive(AggregationFn::Average, 0, 10)
+            .build()
+            .is_err());
+        assert!(DatabaseBuilder::new(DataSourceType::Gauge)
+            .archive_timespan(AggregationFn::Average, 3600, 60)
+            .build()
+            .is_err());
+        assert!(DatabaseBuilder::new(DataSourceType::Gauge)
+            .archive(AggregationFn::Average, 60, 10)
+            .archive(AggregationFn::Average, 60, 20)
+            .build()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_resample_test() -> Result<(), Error> {
+        let rra = Archive::new(AggregationFn::Average, 30, 100);
+        let mut rrd = Database::new(DataSourceType::Gauge, vec![rra]);
+
+        for i in 1..=20 {
+            rrd.update((i * 30) as f64, i as f64);
+        }
+
+        let entry = rrd.extract_data(AggregationFn::Average, 30, Some(60), Some(5 * 60))?;
+        let resampled = entry.resample(120, AggregationFn::Average)?;
+        assert_eq!(resampled.start, 0);
+        assert_eq!(resampled.resolution, 120);
+        assert_eq!(resampled.data, [Some(2.5), Some(5.5), Some(9.0)]);
+
+        let resampled = entry.resample(120, AggregationFn::Maximum)?;
+        assert_eq!(resampled.data, [Some(3.0), Some(7.0), Some(10.0)]);
+
+        assert!(entry.resample(45, AggregationFn::Average).is_err());
+        assert!(entry.resample(120, AggregationFn::Median).is_err());
+
+        let csv = rrd.export_data(
+            AggregationFn::Average,
+            30,
+            Some(570),
+            Some(630),
+            None,
+            ExportFormat::Csv,
+        )?;
+        assert_eq!(csv, "time,value\n570,19\n600,20\n630,\n");
+
+        let json = rrd.export_data(
+            AggregationFn::Average,
+            30,
+            Some(540),
+            Some(630),
+            Some(60),
+            ExportFormat::Json,
+        )?;
+        assert_eq!(
+            json,
+            r#"[{"time":540,"value":18.5},{"time":600,"value":20.0}]"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn percentile_test() {
+        let values: Vec<f64> = (0..=100).map(|v| v as f64).collect();
+        assert_eq!(compute_percentile(&values, 0.5), 50.0);
+        assert_eq!(compute_percentile(&values, 0.95), 95.0);
+        assert_eq!(compute_percentile(&[1.0, 2.0], 0.5), 1.5);
+        assert_eq!(compute_percentile(&[3.0], 0.99), 3.0);
+        assert!(compute_percentile(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn one_second_base_step_test() -> Result<(), Error> {
+        let rra_list = vec![
+            Archive::new(AggregationFn::Average, 1, 60),
+            Archive::new(AggregationFn::Average, 60, 5),
+        ];
+        let mut rrd = Database::new(DataSourceType::Gauge, rra_list);
+        assert_eq!(rrd.base_step(), Some(1));
+
+        for i in 0..20 {
+            rrd.update(600.0 + (i as f64) * 0.5, i as f64);
+        }
+
+        let Entry {
+            start,
+            resolution,
+            data,
+        } = rrd.extract_data(AggregationFn::Average, 1, Some(600), Some(604))?;
+        assert_eq!(start, 600);
+        assert_eq!(resolution, 1);
+        assert_eq!(
+            data,
+            [Some(0.5), Some(2.5), Some(4.5), Some(6.5), Some(8.5)]
+        );
+
+        // coarser resolution requests still use the minute RRA
+        let Entry { resolution, .. } =
+            rrd.extract_data(AggregationFn::Average, 300, Some(600), Some(660))?;
+        assert_eq!(resolution, 60);
+
+        Ok(())
+    }
+
     #[test]
     fn basic_rra_average_derive_test() -> Result<(), Error> {
         let rra = Archive::new(AggregationFn::Average, 60, 5);
@@ -624,6 +1200,93 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn basic_rra_average_derive_rate_test() -> Result<(), Error> {
+        let rra = Archive::new(AggregationFn::Average, 60, 5);
+        let mut rrd = Database::new(DataSourceType::DeriveRate, vec![rra]);
+
+        rrd.update(60.0, 1000.0);
+        rrd.update(120.0, 1600.0);
+        // restart, counted from zero
+        rrd.update(180.0, 300.0);
+        rrd.update(240.0, 900.0);
+
+        let Entry { data, .. } =
+            rrd.extract_data(AggregationFn::Average, 60, Some(60), Some(5 * 60))?;
+        assert_eq!(data, [Some(0.0), Some(10.0), Some(5.0), Some(10.0), None]);
+
+        // survives a save/load round trip
+        let mut raw = PROXMOX_RRD_MAGIC_2_0.to_vec();
+        serde_cbor::to_writer(&mut raw, &rrd)?;
+        let rrd = Database::from_raw(&raw)?;
+        assert_eq!(rrd.source.dst, DataSourceType::DeriveRate);
+        assert_eq!(rrd.source.last_value, 900.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn derive_max_rate_test() -> Result<(), Error> {
+        let mut rrd = DatabaseBuilder::new(DataSourceType::Derive)
+            .archive(AggregationFn::Maximum, 10, 10)
+            .max_rate(1000.0)
+            .build()?;
+
+        // 32 bit wrap: 100 per second
+        rrd.update(10.0, 4294967296.0 - 500.0);
+        rrd.update(20.0, 500.0);
+        // too fast, dropped without changing the last value
+        rrd.update(30.0, 11000.0);
+        assert_eq!(rrd.source.last_value, 500.0);
+        assert_eq!(rrd.last_update(), 20.0);
+        // so the rate of the next update is computed over 20 seconds
+        rrd.update(40.0, 2500.0);
+        // reset, the wrapped difference exceeds the max rate
+        rrd.update(50.0, 100.0);
+
+        let Entry { data, .. } = rrd.extract_data(AggregationFn::Maximum, 10, Some(0), Some(50))?;
+        assert_eq!(
+            data,
+            [None, Some(0.0), Some(100.0), None, Some(100.0), None]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn counter_reset_test() -> Result<(), Error> {
+        let rra = Archive::new(AggregationFn::Last, 10, 10);
+        let mut rrd = Database::new(DataSourceType::Counter, vec![rra]);
+
+        rrd.update(10.0, 100.0);
+        // reset, dropped without changing the data source
+        rrd.update(20.0, 50.0);
+        assert_eq!(rrd.source.last_value, 100.0);
+        assert_eq!(rrd.last_update(), 10.0);
+        rrd.update(30.0, 150.0);
+
+        let Entry { data, .. } = rrd.extract_data(AggregationFn::Last, 10, Some(10), Some(30))?;
+        assert_eq!(data, [Some(0.0), None, Some(2.5)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn basic_rra_average_absolute_test() -> Result<(), Error> {
+        let rra = Archive::new(AggregationFn::Average, 60, 5);
+        let mut rrd = Database::new(DataSourceType::Absolute, vec![rra]);
+
+        for i in 2..10 {
+            rrd.update((i as f64) * 30.0, 60.0);
+        }
+
+        let Entry { data, .. } =
+            rrd.extract_data(AggregationFn::Average, 60, Some(60), Some(5 * 60))?;
+        assert_eq!(data, [Some(1.0), Some(2.0), Some(2.0), Some(2.0), None]);
+
+        Ok(())
+    }
+
     #[test]
     fn basic_rra_average_gauge_test() -> Result<(), Error> {
         let rra = Archive::new(AggregationFn::Average, 60, 5);
@@ -0,0 +1,491 @@
+//! Read-only access to memory mapped RRD files.
+//!
+//! Archive values are stored as CBOR floats, which are encoded with 2, 4 or 8 bytes depending
+//! on the value. So they cannot be accessed as `&[f64]` in place. Instead, opening a file only
+//! records where each archive is located inside the mapping, and values are decoded from there
+//! when they are accessed.
+
+use std::ops::Range;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_sys::mmap::Mmap;
+
+use super::{extract_entry, find_archive, AggregationFn, DataSource, Entry, PROXMOX_RRD_MAGIC_2_0};
+
+/// A read-only, memory mapped RRD file, see [Database::open_readonly_mmap].
+///
+/// [Database::open_readonly_mmap]: super::Database::open_readonly_mmap
+pub struct MappedDatabase {
+    map: Mmap<u8>,
+    source: DataSource,
+    rra_list: Vec<ArchiveIndex>,
+}
+
+// Location of an archive inside the mapping.
+struct ArchiveIndex {
+    resolution: u64,
+    cf: AggregationFn,
+    last_count: u64,
+    // encoded data values, without the array header
+    data: Range<usize>,
+    points: usize,
+}
+
+/// A round robin archive of a [MappedDatabase].
+///
+/// This borrows the encoded values from the mapping, see [MappedArchive::raw_data].
+pub struct MappedArchive<'a> {
+    /// Number of seconds spanned by a single data entry.
+    pub resolution: u64,
+    /// Consolidation function.
+    pub cf: AggregationFn,
+    /// Count values computed inside this update interval.
+    pub last_count: u64,
+    raw: &'a [u8],
+    points: usize,
+}
+
+impl MappedDatabase {
+    pub(super) fn open(path: &Path) -> Result<Self, Error> {
+        let file = std::fs::File::open(path)
+            .map_err(|err| format_err!("unable to open rrd file {path:?} - {err}"))?;
+        let len = file.metadata()?.len() as usize;
+        if len < 8 {
+            bail!("not an rrd file - file is too small ({len})");
+        }
+
+        let map = unsafe {
+            Mmap::<u8>::map_fd(
+                file.as_raw_fd(),
+                0,
+                len,
+                nix::sys::mman::ProtFlags::PROT_READ,
+                nix::sys::mman::MapFlags::MAP_PRIVATE,
+            )
+        }
+        .map_err(|err| format_err!("unable to map rrd file {path:?} - {err}"))?;
+
+        match &map[0..8] {
+            #[cfg(feature = "rrd_v1")]
+            magic if magic == crate::rrd_v1::PROXMOX_RRD_MAGIC_1_0 => {
+                bail!("unable to map rrd file {path:?} - old v1 format is not supported")
+            }
+            magic if magic == PROXMOX_RRD_MAGIC_2_0 => (),
+            _ => bail!("not an rrd file - unknown magic number"),
+        }
+
+        let (source, rra_list) = index_database(&map, 8)
+            .map_err(|err| format_err!("unable to decode RRD file {path:?} - {err}"))?;
+
+        if source.last_update < 0.0 {
+            bail!("rrd file has negative last_update time");
+        }
+
+        Ok(Self {
+            map,
+            source,
+            rra_list,
+        })
+    }
+
+    /// The data source definition.
+    pub fn source(&self) -> &DataSource {
+        &self.source
+    }
+
+    /// Returns the last update time.
+    pub fn last_update(&self) -> f64 {
+        self.source.last_update
+    }
+
+    /// Returns the base step, which is the finest resolution of all RRAs.
+    pub fn base_step(&self) -> Option<u64> {
+        self.rra_list.iter().map(|rra| rra.resolution).min()
+    }
+
+    /// List of round robin archives.
+    pub fn rra_list(&self) -> impl Iterator<Item = MappedArchive<'_>> {
+        self.rra_list.iter().map(|rra| MappedArchive {
+            resolution: rra.resolution,
+            cf: rra.cf,
+            last_count: rra.last_count,
+            raw: &self.map[rra.data.clone()],
+            points: rra.points,
+        })
+    }
+
+    /// Extract data from the archive, see [Database::extract_data].
+    ///
+    /// Only the values of the selected archive are decoded.
+    ///
+    /// [Database::extract_data]: super::Database::extract_data
+    pub fn extract_data(
+        &self,
+        cf: AggregationFn,
+        resolution: u64,
+        start: Option<u64>,
+        end: Option<u64>,
+    ) -> Result<Entry, Error> {
+        let index = find_archive(
+            self.rra_list.iter().map(|rra| (rra.cf, rra.resolution)),
+            cf,
+            resolution,
+        );
+
+        match index.and_then(|index| self.rra_list().nth(index)) {
+            Some(rra) => {
+                let end = end.unwrap_or_else(|| proxmox_time::epoch_f64() as u64);
+                let start = start.unwrap_or_else(|| end.saturating_sub(10 * rra.resolution));
+                Ok(rra.extract_data(start, end, self.source.last_update))
+            }
+            None => bail!("unable to find RRA suitable ({:?}:{})", cf, resolution),
+        }
+    }
+}
+
+impl<'a> MappedArchive<'a> {
+    /// Number of data entries.
+    pub fn len(&self) -> usize {
+        self.points
+    }
+
+    /// Returns true if the archive has no data entries.
+    pub fn is_empty(&self) -> bool {
+        self.points == 0
+    }
+
+    /// The CBOR encoded data entries, borrowed from the mapping.
+    pub fn raw_data(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Decode the data entries from the mapping.
+    pub fn values(&self) -> impl Iterator<Item = f64> + 'a {
+        let mut raw = self.raw;
+        std::iter::from_fn(move || {
+            let (value, len) = decode_float(raw).ok()?;
+            raw = &raw[len..];
+            Some(value)
+        })
+    }
+
+    /// Data slot index
+    pub fn slot(&self, time: u64) -> usize {
+        ((time / self.resolution) as usize) % self.points
+    }
+
+    /// Extract data, see [Archive::extract_data].
+    ///
+    /// [Archive::extract_data]: super::Archive::extract_data
+    pub fn extract_data(&self, start: u64, end: u64, last_update: f64) -> Entry {
+        let index = self.slot(start);
+        let values = self.values().skip(index).chain(self.values());
+        extract_entry(
+            self.resolution,
+            self.points,
+            start,
+            end,
+            last_update,
+            values,
+        )
+    }
+}
+
+// The file format is a serde_cbor encoded [Database](super::Database), which only uses definite
+// lengths. Walk it to decode the data source and to find the archive values.
+fn index_database(raw: &[u8], mut pos: usize) -> Result<(DataSource, Vec<ArchiveIndex>), Error> {
+    let mut source = None;
+    let mut rra_list = None;
+
+    let (fields, len) = decode_header(raw, pos, 5)?;
+    pos += len;
+    for _ in 0..fields {
+        let key;
+        (key, pos) = decode_text(raw, pos)?;
+        match key {
+            "source" => {
+                let end = skip_item(raw, pos)?;
+                source = Some(serde_cbor::from_slice(&raw[pos..end])?);
+                pos = end;
+            }
+            "rra_list" => {
+                let (count, len) = decode_header(raw, pos, 4)?;
+                pos += len;
+                let mut list = Vec::new();
+                for _ in 0..count {
+                    let rra;
+                    (rra, pos) = index_archive(raw, pos)?;
+                    list.push(rra);
+                }
+                rra_list = Some(list);
+            }
+            _ => pos = skip_item(raw, pos)?,
+        }
+    }
+
+    if pos != raw.len() {
+        bail!("trailing data");
+    }
+
+    Ok((
+        source.ok_or_else(|| format_err!("missing field 'source'"))?,
+        rra_list.ok_or_else(|| format_err!("missing field 'rra_list'"))?,
+    ))
+}
+
+fn index_archive(raw: &[u8], mut pos: usize) -> Result<(ArchiveIndex, usize), Error> {
+    let mut resolution = None;
+    let mut cf = None;
+    let mut last_count = None;
+    let mut data = None;
+
+    let (fields, len) = decode_header(raw, pos, 5)?;
+    pos += len;
+    for _ in 0..fields {
+        let key;
+        (key, pos) = decode_text(raw, pos)?;
+        match key {
+            "resolution" => {
+                let (value, len) = decode_header(raw, pos, 0)?;
+                resolution = Some(value);
+                pos += len;
+            }
+            "last_count" => {
+                let (value, len) = decode_header(raw, pos, 0)?;
+                last_count = Some(value);
+                pos += len;
+            }
+            "cf" => {
+                let end = skip_item(raw, pos)?;
+                cf = Some(serde_cbor::from_slice(&raw[pos..end])?);
+                pos = end;
+            }
+            "data" => {
+                let (count, len) = decode_header(raw, pos, 4)?;
+                pos += len;
+                let start = pos;
+                for _ in 0..count {
+                    pos += decode_float(&raw[pos..])?.1;
+                }
+                data = Some((start..pos, count as usize));
+            }
+            _ => pos = skip_item(raw, pos)?,
+        }
+    }
+
+    let (data, points) = data.ok_or_else(|| format_err!("missing field 'data'"))?;
+    let resolution = resolution.ok_or_else(|| format_err!("missing field 'resolution'"))?;
+    if resolution == 0 || points == 0 {
+        bail!("archive without resolution or data");
+    }
+
+    let rra = ArchiveIndex {
+        resolution,
+        cf: cf.ok_or_else(|| format_err!("missing field 'cf'"))?,
+        last_count: last_count.ok_or_else(|| format_err!("missing field 'last_count'"))?,
+        data,
+        points,
+    };
+
+    Ok((rra, pos))
+}
+
+// Decode the header of an item with the expected major type.
+//
+// Returns the argument (value, length or number of items) and the header size.
+fn decode_header(raw: &[u8], pos: usize, major: u8) -> Result<(u64, usize), Error> {
+    let (item_major, value, len) = decode_any_header(raw, pos)?;
+    if item_major != major {
+        bail!("unexpected major type {item_major} at offset {pos}, expected {major}");
+    }
+    Ok((value, len))
+}
+
+fn decode_any_header(raw: &[u8], pos: usize) -> Result<(u8, u64, usize), Error> {
+    let initial = *raw
+        .get(pos)
+        .ok_or_else(|| format_err!("unexpected end of data"))?;
+    let major = initial >> 5;
+    let info = initial & 0x1f;
+
+    let size = match info {
+        0..=23 => return Ok((major, info as u64, 1)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => bail!("unsupported additional information {info} at offset {pos}"),
+    };
+
+    let bytes = raw
+        .get((pos + 1)..(pos + 1 + size))
+        .ok_or_else(|| format_err!("unexpected end of data"))?;
+    let value = bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+
+    Ok((major, value, 1 + size))
+}
+
+// Returns the text string and the position after it.
+fn decode_text(raw: &[u8], pos: usize) -> Result<(&str, usize), Error> {
+    let (len, header) = decode_header(raw, pos, 3)?;
+    let start = pos + header;
+    let end = start + len as usize;
+    let text = raw
+        .get(start..end)
+        .ok_or_else(|| format_err!("unexpected end of data"))?;
+    Ok((std::str::from_utf8(text)?, end))
+}
+
+// Returns the position after the item starting at `pos`.
+fn skip_item(raw: &[u8], pos: usize) -> Result<usize, Error> {
+    let (major, value, len) = decode_any_header(raw, pos)?;
+    let mut pos = pos + len;
+    match major {
+        0 | 1 | 7 => (),
+        2 | 3 => pos += value as usize,
+        4 => {
+            for _ in 0..value {
+                pos = skip_item(raw, pos)?;
+            }
+        }
+        5 => {
+            for _ in 0..(value * 2) {
+                pos = skip_item(raw, pos)?;
+            }
+        }
+        6 => pos = skip_item(raw, pos)?,
+        _ => unreachable!(),
+    }
+    if pos > raw.len() {
+        bail!("unexpected end of data");
+    }
+    Ok(pos)
+}
+
+// Decode a half, single or double precision float, returns the value and its encoded size.
+fn decode_float(raw: &[u8]) -> Result<(f64, usize), Error> {
+    let (major, bits, len) = decode_any_header(raw, 0)?;
+    let value = match (major, len) {
+        (7, 3) => f16_to_f64(bits as u16),
+        (7, 5) => f32::from_bits(bits as u32) as f64,
+        (7, 9) => f64::from_bits(bits),
+        _ => bail!("expected floating point value"),
+    };
+    Ok((value, len))
+}
+
+fn f16_to_f64(bits: u16) -> f64 {
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+
+    let value = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(exponent - 25),
+    };
+
+    if bits & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proxmox_sys::fs::CreateOptions;
+
+    use crate::rrd::{DataSourceType, Database, DatabaseBuilder};
+
+    #[test]
+    fn f16_test() {
+        assert_eq!(f16_to_f64(0x0000), 0.0);
+        assert_eq!(f16_to_f64(0x3c00), 1.0);
+        assert_eq!(f16_to_f64(0xc000), -2.0);
+        assert_eq!(f16_to_f64(0x3555), 0.333251953125);
+        assert_eq!(f16_to_f64(0x0001), 2f64.powi(-24));
+        assert_eq!(f16_to_f64(0x7bff), 65504.0);
+        assert_eq!(f16_to_f64(0x7c00), f64::INFINITY);
+        assert_eq!(f16_to_f64(0xfc00), f64::NEG_INFINITY);
+        assert!(f16_to_f64(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn mapped_database_test() -> Result<(), Error> {
+        let mut rrd = DatabaseBuilder::new(DataSourceType::Gauge)
+            .archive(AggregationFn::Average, 60, 20)
+            .archive(AggregationFn::Maximum, 60, 20)
+            .archive(AggregationFn::Median, 300, 10)
+            .build()?;
+
+        // mix values encoded as half, single and double precision floats
+        let values = [1.0, 0.5, 1.0e10, 0.1, -3.0, 65504.0, 1.0 / 3.0];
+        for i in 0..30u64 {
+            rrd.update((i * 60) as f64, values[i as usize % values.len()]);
+        }
+
+        let path = std::env::temp_dir().join(format!("proxmox-rrd-mmap-{}", std::process::id()));
+        rrd.save(&path, CreateOptions::new(), false)?;
+        let mapped = Database::open_readonly_mmap(&path);
+        let _ = std::fs::remove_file(&path);
+        let mapped = mapped?;
+
+        assert_eq!(mapped.last_update(), rrd.last_update());
+        assert_eq!(mapped.source().dst, DataSourceType::Gauge);
+        assert_eq!(mapped.base_step(), rrd.base_step());
+
+        assert_eq!(mapped.rra_list().count(), rrd.rra_list.len());
+        for (archive, expected) in mapped.rra_list().zip(rrd.rra_list.iter()) {
+            assert_eq!(archive.cf, expected.cf);
+            assert_eq!(archive.resolution, expected.resolution);
+            assert_eq!(archive.last_count, expected.last_count);
+            assert_eq!(archive.len(), expected.data.len());
+
+            let values: Vec<f64> = archive.values().collect();
+            assert_eq!(values.len(), expected.data.len());
+            for (value, expected) in values.iter().zip(expected.data.iter()) {
+                assert!(value == expected || (value.is_nan() && expected.is_nan()));
+            }
+        }
+
+        for (cf, resolution) in [
+            (AggregationFn::Average, 60),
+            (AggregationFn::Maximum, 120),
+            (AggregationFn::Median, 300),
+        ] {
+            for (start, end) in [(0, 30 * 60), (600, 1200), (1500, 5000)] {
+                let expected = rrd.extract_data(cf, resolution, Some(start), Some(end))?;
+                let data = mapped.extract_data(cf, resolution, Some(start), Some(end))?;
+                assert_eq!(data.start, expected.start);
+                assert_eq!(data.resolution, expected.resolution);
+                assert_eq!(data.data, expected.data);
+            }
+        }
+
+        assert!(mapped
+            .extract_data(AggregationFn::Minimum, 60, None, None)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mapped_database_invalid_test() {
+        let mut raw = PROXMOX_RRD_MAGIC_2_0.to_vec();
+        let rrd = Database::new(DataSourceType::Gauge, Vec::new());
+        serde_cbor::to_writer(&mut raw, &rrd).unwrap();
+        assert!(index_database(&raw, 8).is_ok());
+
+        // truncated data
+        assert!(index_database(&raw[..raw.len() - 1], 8).is_err());
+        // trailing data
+        raw.push(0);
+        assert!(index_database(&raw, 8).is_err());
+    }
+}
@@ -289,6 +289,7 @@ impl RRDv1 {
             dst,
             last_value: f64::NAN,
             last_update: self.hour_avg.last_update, // IMPORTANT!
+            max_rate: None,
         };
         Ok(Database { source, rra_list })
     }
@@ -0,0 +1,326 @@
+//! Forward RRD updates to external time series databases
+//!
+//! A [MetricsSink] registered with [Cache::add_metrics_sink](crate::Cache::add_metrics_sink)
+//! gets called for every value written to the cache. Sinks must not block, so the provided
+//! implementations queue the data points and send them from a background thread. If the
+//! queue is full (e.g. because the remote side is unreachable), new data points are dropped.
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::thread::spawn;
+use std::time::Duration;
+
+use anyhow::Error;
+use crossbeam_channel::{bounded, Sender, TrySendError};
+
+use crate::rrd::DataSourceType;
+
+/// Number of data points a sink queues before dropping new ones.
+const SINK_QUEUE_SIZE: usize = 64 * 1024;
+
+/// Maximum number of data points sent at once.
+const SINK_BATCH_SIZE: usize = 1024;
+
+/// A single data point as passed to the RRD cache.
+#[derive(Clone, Debug)]
+pub struct DataPoint {
+    /// Path of the RRD file, relative to the cache base directory.
+    pub rel_path: String,
+    /// Update time (epoch).
+    pub time: f64,
+    /// The raw value, i.e. counter values are not converted to rates.
+    pub value: f64,
+    /// Data source type of the RRD file.
+    pub dst: DataSourceType,
+}
+
+/// Receives all values written to the RRD cache.
+pub trait MetricsSink: Send + Sync {
+    /// Called for every updated value. This must not block.
+    fn send(&self, rel_path: &str, time: f64, value: f64, dst: DataSourceType);
+}
+
+/// Queue data points and pass them in batches to `send_batch` from a background thread.
+struct SinkQueue {
+    name: &'static str,
+    sender: Sender<DataPoint>,
+}
+
+impl SinkQueue {
+    fn new<F>(name: &'static str, mut send_batch: F) -> Self
+    where
+        F: FnMut(&[DataPoint]) -> Result<(), Error> + Send + 'static,
+    {
+        let (sender, receiver) = bounded::<DataPoint>(SINK_QUEUE_SIZE);
+
+        spawn(move || {
+            let mut batch = Vec::with_capacity(SINK_BATCH_SIZE);
+            // terminates if the sink (and thus the sender) is dropped
+            while let Ok(point) = receiver.recv() {
+                batch.push(point);
+                while batch.len() < SINK_BATCH_SIZE {
+                    match receiver.try_recv() {
+                        Ok(point) => batch.push(point),
+                        Err(_) => break,
+                    }
+                }
+                if let Err(err) = send_batch(&batch) {
+                    log::error!("{name}: dropping {} data points - {err}", batch.len());
+                }
+                batch.clear();
+            }
+        });
+
+        Self { name, sender }
+    }
+
+    fn push(&self, rel_path: &str, time: f64, value: f64, dst: DataSourceType) {
+        let point = DataPoint {
+            rel_path: rel_path.to_string(),
+            time,
+            value,
+            dst,
+        };
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(point) {
+            log::warn!("{}: queue full, dropping data point", self.name);
+        }
+    }
+}
+
+/// Build a metric name from `prefix` and the RRD path, replacing all characters not in
+/// `[a-zA-Z0-9_-]` (and `separator`) with `_`.
+fn metric_name(prefix: &str, rel_path: &str, separator: char) -> String {
+    let mut name = String::from(prefix);
+    for component in rel_path.split('/').filter(|c| !c.is_empty()) {
+        if !name.is_empty() {
+            name.push(separator);
+        }
+        name.extend(component.chars().map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || (c == '-' && separator == '.') {
+                c
+            } else {
+                '_'
+            }
+        }));
+    }
+    name
+}
+
+/// Sends data points to a Graphite server using the plaintext (line) protocol over TCP.
+///
+/// `host/cpu` with prefix `proxmox` is sent as `proxmox.host.cpu <value> <time>`.
+pub struct GraphiteSink {
+    queue: SinkQueue,
+}
+
+impl GraphiteSink {
+    /// Create a new sink sending to `server` (`host:port`, usually port 2003).
+    pub fn new(server: impl Into<String>, prefix: impl Into<String>) -> Self {
+        let server = server.into();
+        let prefix = prefix.into();
+        let mut stream: Option<TcpStream> = None;
+
+        let queue = SinkQueue::new("graphite sink", move |batch| {
+            let mut data = String::new();
+            for point in batch {
+                data.push_str(&format!(
+                    "{} {} {}\n",
+                    metric_name(&prefix, &point.rel_path, '.'),
+                    point.value,
+                    point.time as i64
+                ));
+            }
+
+            if stream.is_none() {
+                let conn = TcpStream::connect(&server)?;
+                conn.set_write_timeout(Some(Duration::from_secs(10)))?;
+                stream = Some(conn);
+            }
+
+            let result = stream.as_mut().unwrap().write_all(data.as_bytes());
+            if result.is_err() {
+                stream = None; // reconnect next time
+            }
+            Ok(result?)
+        });
+
+        Self { queue }
+    }
+}
+
+impl MetricsSink for GraphiteSink {
+    fn send(&self, rel_path: &str, time: f64, value: f64, dst: DataSourceType) {
+        self.queue.push(rel_path, time, value, dst);
+    }
+}
+
+#[cfg(feature = "prometheus")]
+mod prometheus {
+    use std::collections::HashMap;
+
+    use anyhow::{bail, Error};
+
+    use proxmox_http::client::sync::Client;
+    use proxmox_http::{HttpClient, HttpOptions};
+
+    use super::{metric_name, DataPoint, MetricsSink, SinkQueue};
+    use crate::rrd::DataSourceType;
+
+    fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    fn encode_bytes(buf: &mut Vec<u8>, field: u64, data: &[u8]) {
+        encode_varint(buf, (field << 3) | 2);
+        encode_varint(buf, data.len() as u64);
+        buf.extend_from_slice(data);
+    }
+
+    fn encode_label(buf: &mut Vec<u8>, name: &str, value: &str) {
+        let mut label = Vec::new();
+        encode_bytes(&mut label, 1, name.as_bytes());
+        encode_bytes(&mut label, 2, value.as_bytes());
+        encode_bytes(buf, 1, &label);
+    }
+
+    fn encode_sample(buf: &mut Vec<u8>, value: f64, timestamp_ms: i64) {
+        let mut sample = Vec::new();
+        encode_varint(&mut sample, (1 << 3) | 1);
+        sample.extend_from_slice(&value.to_le_bytes());
+        encode_varint(&mut sample, 2 << 3);
+        encode_varint(&mut sample, timestamp_ms as u64);
+        encode_bytes(buf, 2, &sample);
+    }
+
+    /// Encode a protobuf `WriteRequest` message of the remote write protocol.
+    pub(super) fn encode_write_request(prefix: &str, batch: &[DataPoint]) -> Vec<u8> {
+        // group samples by series, keeping the order of the updates
+        let mut series: Vec<(String, Vec<(f64, i64)>)> = Vec::new();
+        let mut index: HashMap<&str, usize> = HashMap::new();
+
+        for point in batch {
+            let pos = *index.entry(&point.rel_path).or_insert_with(|| {
+                series.push((metric_name(prefix, &point.rel_path, '_'), Vec::new()));
+                series.len() - 1
+            });
+            series[pos]
+                .1
+                .push((point.value, (point.time * 1000.0) as i64));
+        }
+
+        let mut request = Vec::new();
+        for (name, samples) in series {
+            let mut time_series = Vec::new();
+            encode_label(&mut time_series, "__name__", &name);
+            for (value, timestamp) in samples {
+                encode_sample(&mut time_series, value, timestamp);
+            }
+            encode_bytes(&mut request, 1, &time_series);
+        }
+
+        request
+    }
+
+    /// Sends data points to a Prometheus compatible remote write endpoint.
+    ///
+    /// `host/cpu` with prefix `proxmox` is sent as time series `proxmox_host_cpu`.
+    pub struct PrometheusRemoteWriteSink {
+        queue: SinkQueue,
+    }
+
+    impl PrometheusRemoteWriteSink {
+        /// Create a new sink posting to the remote write `url`.
+        ///
+        /// `headers` are added to every request, e.g. for authentication.
+        pub fn new(
+            url: impl Into<String>,
+            prefix: impl Into<String>,
+            options: HttpOptions,
+            headers: HashMap<String, String>,
+        ) -> Self {
+            let url = url.into();
+            let prefix = prefix.into();
+            let client = Client::new(options);
+
+            let mut headers = headers;
+            headers.insert("Content-Encoding".into(), "snappy".into());
+            headers.insert("X-Prometheus-Remote-Write-Version".into(), "0.1.0".into());
+
+            let queue = SinkQueue::new("prometheus sink", move |batch| -> Result<(), Error> {
+                let request = encode_write_request(&prefix, batch);
+                let body = snap::raw::Encoder::new().compress_vec(&request)?;
+
+                let response = client.post(
+                    &url,
+                    Some(body.as_slice()),
+                    Some("application/x-protobuf"),
+                    Some(&headers),
+                )?;
+
+                if !response.status().is_success() {
+                    bail!("remote write failed with status {}", response.status());
+                }
+
+                Ok(())
+            });
+
+            Self { queue }
+        }
+    }
+
+    impl MetricsSink for PrometheusRemoteWriteSink {
+        fn send(&self, rel_path: &str, time: f64, value: f64, dst: DataSourceType) {
+            self.queue.push(rel_path, time, value, dst);
+        }
+    }
+}
+
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusRemoteWriteSink;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metric_name_test() {
+        assert_eq!(metric_name("proxmox", "host/cpu", '.'), "proxmox.host.cpu");
+        assert_eq!(
+            metric_name("", "datastore/my store/read-ios", '.'),
+            "datastore.my_store.read-ios"
+        );
+        assert_eq!(
+            metric_name("proxmox", "datastore/store1/read-ios", '_'),
+            "proxmox_datastore_store1_read_ios"
+        );
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn write_request_encoding_test() {
+        let point = DataPoint {
+            rel_path: "a".into(),
+            time: 1.0,
+            value: 1.0,
+            dst: DataSourceType::Gauge,
+        };
+
+        let request = prometheus::encode_write_request("", &[point]);
+        assert_eq!(
+            request,
+            [
+                0x0a, 0x1d, // timeseries
+                0x0a, 0x0d, // label
+                0x0a, 0x08, b'_', b'_', b'n', b'a', b'm', b'e', b'_', b'_', // name
+                0x12, 0x01, b'a', // value
+                0x12, 0x0c, // sample
+                0x09, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f, // value 1.0
+                0x10, 0xe8, 0x07, // timestamp 1000
+            ]
+        );
+    }
+}
@@ -55,3 +55,26 @@ fn load_and_save_rrd_v2() -> Result<(), Error> {
 
     Ok(())
 }
+
+// make sure the memory mapped read-only access returns the same data
+#[test]
+fn load_mmap() -> Result<(), Error> {
+    use proxmox_rrd::rrd::AggregationFn;
+
+    let rrd = Database::load(Path::new(RRD_V2_FN), false)?;
+    let mapped = Database::open_readonly_mmap(Path::new(RRD_V2_FN))?;
+
+    for (cf, resolution) in [(AggregationFn::Average, 60), (AggregationFn::Maximum, 3600)] {
+        let expected = rrd.extract_data(cf, resolution, Some(0), None)?;
+        let data = mapped.extract_data(cf, resolution, Some(0), None)?;
+        assert_eq!(data.start, expected.start);
+        assert_eq!(data.data, expected.data);
+    }
+
+    if cfg!(feature = "rrd_v1") {
+        assert!(Database::open_readonly_mmap(Path::new("./tests/testdata/cpu.rrd_v1")).is_err());
+    }
+    assert!(Database::open_readonly_mmap(Path::new("./tests/testdata/nonexistent")).is_err());
+
+    Ok(())
+}
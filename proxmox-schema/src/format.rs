@@ -189,48 +189,13 @@ pub fn get_property_description(
 ) -> String {
     let type_text = get_schema_type_text(schema, style);
 
-    let (descr, default, extra) = match schema {
-        Schema::Null => ("null", None, None),
-        Schema::String(ref schema) => (
-            schema.description,
-            schema.default.map(|v| v.to_owned()),
-            None,
-        ),
-        Schema::Boolean(ref schema) => (
-            schema.description,
-            schema.default.map(|v| v.to_string()),
-            None,
-        ),
-        Schema::Integer(ref schema) => (
-            schema.description,
-            schema.default.map(|v| v.to_string()),
-            None,
-        ),
-        Schema::Number(ref schema) => (
-            schema.description,
-            schema.default.map(|v| v.to_string()),
-            None,
-        ),
-        Schema::Object(ref schema) => (schema.description, None, None),
-        Schema::AllOf(ref schema) => (schema.description, None, None),
-        Schema::OneOf(ref schema) => (schema.description, None, None),
-        Schema::Array(ref schema) => (
-            schema.description,
-            None,
-            Some(String::from("Can be specified more than once.")),
-        ),
-    };
+    let (descr, default) = get_schema_description_and_default(schema);
 
     let default_text = match default {
         Some(text) => format!("   (default={})", text),
         None => String::new(),
     };
 
-    let descr = match extra {
-        Some(extra) => format!("{} {}", descr, extra),
-        None => String::from(descr),
-    };
-
     if format == DocumentationFormat::ReST {
         let mut text = match style {
             ParameterDisplayStyle::Config => {
@@ -271,6 +236,52 @@ pub fn get_property_description(
     }
 }
 
+// Returns the description (including hints for arrays) and the default value of a schema.
+fn get_schema_description_and_default(schema: &Schema) -> (String, Option<String>) {
+    let (descr, default, extra) = match schema {
+        Schema::Null => ("null", None, None),
+        Schema::String(ref schema) => (
+            schema.description,
+            schema.default.map(|v| v.to_owned()),
+            None,
+        ),
+        Schema::Boolean(ref schema) => (
+            schema.description,
+            schema.default.map(|v| v.to_string()),
+            None,
+        ),
+        Schema::Integer(ref schema) => (
+            schema.description,
+            schema.default.map(|v| v.to_string()),
+            None,
+        ),
+        Schema::Number(ref schema) => (
+            schema.description,
+            schema.default.map(|v| v.to_string()),
+            None,
+        ),
+        Schema::Object(ref schema) => (schema.description, None, None),
+        Schema::AllOf(ref schema) => (schema.description, None, None),
+        Schema::OneOf(ref schema) => (schema.description, None, None),
+        Schema::Array(ref schema) => (
+            schema.description,
+            None,
+            Some(String::from("Can be specified more than once.")),
+        ),
+    };
+
+    let mut descr = match extra {
+        Some(extra) => format!("{} {}", descr, extra),
+        None => String::from(descr),
+    };
+
+    if let Some(deprecation) = schema.deprecation() {
+        descr = format!("{} ({})", descr, deprecation);
+    }
+
+    (descr, default)
+}
+
 /// Helper to format the type text
 ///
 /// The result is a short string including important constraints, for
@@ -472,3 +483,211 @@ pub fn dump_api_return_schema(returns: &ReturnType, style: ParameterDisplayStyle
 
     res
 }
+
+// Escape text for use inside a markdown table cell, joining paragraphs with line breaks.
+fn markdown_table_text(text: &str) -> String {
+    text.split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<String>>()
+        .join("<br><br>")
+        .replace('|', "\\|")
+}
+
+/// Generate a Markdown table for object properties
+pub fn dump_properties_markdown(param: &dyn ObjectSchemaType, skip: &[&str]) -> String {
+    use std::fmt::Write;
+
+    let mut res = String::new();
+
+    let mut properties: Vec<_> = param
+        .properties()
+        .filter(|(prop, _, _)| !skip.iter().any(|n| n == prop))
+        .collect();
+
+    if properties.is_empty() {
+        return res;
+    }
+
+    // list required properties first
+    properties.sort_by_key(|(_, optional, _)| *optional);
+
+    res.push_str("| Name | Type | Required | Default | Description |\n");
+    res.push_str("|------|------|----------|---------|-------------|\n");
+
+    for (prop, optional, schema) in properties {
+        let type_text = get_schema_type_text(schema, ParameterDisplayStyle::Config);
+        let (descr, default) = get_schema_description_and_default(schema);
+
+        let _ = writeln!(
+            res,
+            "| `{}` | `{}` | {} | {} | {} |",
+            prop,
+            type_text.replace('|', "\\|"),
+            if *optional { "no" } else { "yes" },
+            default
+                .map(|v| format!("`{}`", v.replace('|', "\\|")))
+                .unwrap_or_default(),
+            markdown_table_text(&descr),
+        );
+    }
+
+    res
+}
+
+/// Generate Markdown documentation for an API return type.
+pub fn dump_api_return_schema_markdown(returns: &ReturnType) -> String {
+    use std::fmt::Write;
+
+    let schema = &returns.schema;
+
+    let type_text = get_schema_type_text(schema, ParameterDisplayStyle::Config);
+
+    let mut res = String::new();
+    let _ = write!(
+        res,
+        "**Returns**{}: `{}`\n\n",
+        if returns.optional {
+            " (optionally)"
+        } else {
+            ""
+        },
+        type_text
+    );
+
+    if let Schema::Null = schema {
+        return res;
+    }
+
+    res.push_str(&wrap_text("", "", get_schema_description(schema), 80));
+
+    match schema {
+        Schema::Object(obj_schema) => res.push_str(&dump_properties_markdown(obj_schema, &[])),
+        Schema::AllOf(all_of_schema) => res.push_str(&dump_properties_markdown(all_of_schema, &[])),
+        Schema::OneOf(one_of_schema) => res.push_str(&dump_properties_markdown(one_of_schema, &[])),
+        _ => (),
+    }
+
+    res
+}
+
+/// Escape text for roff, so that it cannot be interpreted as a request or escape sequence.
+pub fn roff_escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+
+    text.lines()
+        .map(|line| {
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Format paragraphs for roff, separated by `.PP` requests.
+pub fn roff_paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(roff_escape)
+        .collect::<Vec<String>>()
+        .join("\n.PP\n")
+}
+
+/// Generate roff (man page) documentation for object properties
+pub fn dump_properties_roff(param: &dyn ObjectSchemaType, skip: &[&str]) -> String {
+    use std::fmt::Write;
+
+    let mut res = String::new();
+
+    let mut properties: Vec<_> = param
+        .properties()
+        .filter(|(prop, _, _)| !skip.iter().any(|n| n == prop))
+        .collect();
+
+    // list required properties first
+    properties.sort_by_key(|(_, optional, _)| *optional);
+
+    for (prop, optional, schema) in properties {
+        let type_text = get_schema_type_text(schema, ParameterDisplayStyle::Config);
+        let (descr, default) = get_schema_description_and_default(schema);
+
+        let _ = writeln!(res, ".TP");
+        let _ = write!(res, ".B {}\n{}", roff_escape(prop), roff_escape(&type_text));
+        if let Some(default) = default {
+            let _ = write!(res, " (default={})", roff_escape(&default));
+        }
+        if *optional {
+            res.push_str(" (optional)");
+        }
+        res.push('\n');
+        let _ = writeln!(res, ".br\n{}", roff_paragraphs(&descr));
+    }
+
+    res
+}
+
+/// Generate roff (man page) documentation for an API return type.
+pub fn dump_api_return_schema_roff(returns: &ReturnType) -> String {
+    use std::fmt::Write;
+
+    let schema = &returns.schema;
+
+    let type_text = get_schema_type_text(schema, ParameterDisplayStyle::Config);
+
+    let mut res = String::new();
+    let _ = writeln!(
+        res,
+        ".PP\n.B Returns{}:\n{}",
+        if returns.optional {
+            " (optionally)"
+        } else {
+            ""
+        },
+        roff_escape(&type_text)
+    );
+
+    if let Schema::Null = schema {
+        return res;
+    }
+
+    let _ = writeln!(
+        res,
+        ".PP\n{}",
+        roff_paragraphs(get_schema_description(schema))
+    );
+
+    match schema {
+        Schema::Object(obj_schema) => res.push_str(&dump_properties_roff(obj_schema, &[])),
+        Schema::AllOf(all_of_schema) => res.push_str(&dump_properties_roff(all_of_schema, &[])),
+        Schema::OneOf(one_of_schema) => res.push_str(&dump_properties_roff(one_of_schema, &[])),
+        _ => (),
+    }
+
+    res
+}
+
+// Returns the plain description of a schema.
+fn get_schema_description(schema: &Schema) -> &'static str {
+    match schema {
+        Schema::Null => "null",
+        Schema::Boolean(schema) => schema.description,
+        Schema::Integer(schema) => schema.description,
+        Schema::Number(schema) => schema.description,
+        Schema::String(schema) => schema.description,
+        Schema::Array(schema) => schema.description,
+        Schema::Object(schema) => schema.description,
+        Schema::AllOf(schema) => schema.description,
+        Schema::OneOf(schema) => schema.description,
+    }
+}
+
+#[test]
+fn test_roff_escape() {
+    assert_eq!(roff_escape("a-b"), "a\\-b");
+    assert_eq!(roff_escape(".TH\n'x"), "\\&.TH\n\\&'x");
+    assert_eq!(roff_escape("C:\\path"), "C:\\epath");
+}
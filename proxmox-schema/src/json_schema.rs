@@ -0,0 +1,230 @@
+//! Convert API schemas to JSON Schema.
+//!
+//! The output follows JSON Schema draft 2020-12, which is also the schema dialect used by
+//! OpenAPI 3.1 documents.
+
+use serde_json::{json, Map, Value};
+
+use crate::{ApiStringFormat, ObjectSchemaType, OneOfSchema, Schema};
+
+/// Convert a schema into a JSON Schema object.
+///
+/// Some details cannot be represented:
+///
+/// - strings verified via a function only get their description,
+/// - deprecations are only marked via `deprecated`, their version and note are dropped,
+/// - property strings are plain strings, the schema of their content is added as
+///   `x-property-string` extension.
+pub fn to_json_schema(schema: &Schema) -> Value {
+    let mut value = match schema {
+        Schema::Null => json!({ "type": "null" }),
+        Schema::Boolean(s) => {
+            let mut value = json!({ "type": "boolean", "description": s.description });
+            add_optional(&mut value, "default", s.default);
+            value
+        }
+        Schema::Integer(s) => {
+            let mut value = json!({ "type": "integer", "description": s.description });
+            add_optional(&mut value, "minimum", s.minimum);
+            add_optional(&mut value, "maximum", s.maximum);
+            add_optional(&mut value, "default", s.default);
+            value
+        }
+        Schema::Number(s) => {
+            let mut value = json!({ "type": "number", "description": s.description });
+            add_optional(&mut value, "minimum", s.minimum);
+            add_optional(&mut value, "maximum", s.maximum);
+            add_optional(&mut value, "default", s.default);
+            value
+        }
+        Schema::String(s) => {
+            let mut value = json!({ "type": "string", "description": s.description });
+            add_optional(&mut value, "minLength", s.min_length);
+            add_optional(&mut value, "maxLength", s.max_length);
+            add_optional(&mut value, "default", s.default);
+            match s.format {
+                Some(ApiStringFormat::Enum(entries)) => {
+                    let values: Vec<&str> = entries.iter().map(|entry| entry.value).collect();
+                    value["enum"] = values.into();
+                }
+                Some(ApiStringFormat::Pattern(pattern)) => {
+                    value["pattern"] = pattern.regex_string.into();
+                }
+                Some(ApiStringFormat::PropertyString(schema)) => {
+                    value["x-property-string"] = to_json_schema(schema);
+                }
+                Some(ApiStringFormat::VerifyFn(_)) | None => (),
+            }
+            value
+        }
+        Schema::Array(s) => {
+            let mut value = json!({
+                "type": "array",
+                "description": s.description,
+                "items": to_json_schema(s.items),
+            });
+            add_optional(&mut value, "minItems", s.min_length);
+            add_optional(&mut value, "maxItems", s.max_length);
+            value
+        }
+        Schema::Object(s) => object_to_json_schema(s),
+        Schema::AllOf(s) => object_to_json_schema(s),
+        Schema::OneOf(s) => one_of_to_json_schema(s),
+    };
+
+    if schema.deprecation().is_some() {
+        value["deprecated"] = true.into();
+    }
+
+    value
+}
+
+/// Convert an object schema into a JSON Schema object.
+///
+/// `AllOf` schemas are merged into a single object schema, so that `additionalProperties` of
+/// their parts does not reject the properties of the other parts.
+pub fn object_to_json_schema(schema: &dyn ObjectSchemaType) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+
+    for (name, optional, prop_schema) in schema.properties() {
+        properties.insert(name.to_string(), to_json_schema(prop_schema));
+        if !*optional {
+            required.push(*name);
+        }
+    }
+
+    let mut value = json!({
+        "type": "object",
+        "description": schema.description(),
+        "properties": properties,
+        "additionalProperties": schema.additional_properties(),
+    });
+
+    if !required.is_empty() {
+        value["required"] = required.into();
+    }
+
+    value
+}
+
+/// The variants are object schemas with the type property restricted to the variant name.
+fn one_of_to_json_schema(schema: &OneOfSchema) -> Value {
+    let type_property = schema.type_property();
+
+    let variants: Vec<Value> = schema
+        .list
+        .iter()
+        .map(|(name, variant)| {
+            let mut value = match variant.any_object() {
+                Some(object) => object_to_json_schema(object),
+                None => to_json_schema(variant),
+            };
+
+            let mut type_schema = to_json_schema(schema.type_schema());
+            type_schema["const"] = (*name).into();
+            value["properties"][type_property] = type_schema;
+
+            match value["required"].as_array_mut() {
+                Some(required) if required.iter().any(|r| r == type_property) => (),
+                Some(required) => required.push(type_property.into()),
+                None => value["required"] = json!([type_property]),
+            }
+
+            value
+        })
+        .collect();
+
+    json!({
+        "description": schema.description,
+        "oneOf": variants,
+        "discriminator": { "propertyName": type_property },
+    })
+}
+
+fn add_optional<T: Into<Value>>(value: &mut Value, key: &str, optional: Option<T>) {
+    if let Some(v) = optional {
+        value[key] = v.into();
+    }
+}
+
+#[test]
+fn test_to_json_schema() {
+    use crate::{ArraySchema, Deprecation, EnumEntry, IntegerSchema, ObjectSchema, StringSchema};
+
+    const NAME: Schema = StringSchema::new("Name.")
+        .min_length(3)
+        .format(&ApiStringFormat::Enum(&[
+            EnumEntry::new("foo", "Foo."),
+            EnumEntry::new("bar", "Bar."),
+        ]))
+        .schema();
+    const COUNT: Schema = IntegerSchema::new("Count.").minimum(0).default(1).schema();
+    const LIST: Schema = ArraySchema::new("List.", &COUNT)
+        .deprecated(&Deprecation::new().since("2.0"))
+        .schema();
+    const OBJECT: Schema = ObjectSchema::new(
+        "Object.",
+        &[
+            ("count", true, &COUNT),
+            ("list", true, &LIST),
+            ("name", false, &NAME),
+        ],
+    )
+    .schema();
+
+    assert_eq!(
+        to_json_schema(&OBJECT),
+        json!({
+            "type": "object",
+            "description": "Object.",
+            "additionalProperties": false,
+            "required": ["name"],
+            "properties": {
+                "count": {
+                    "type": "integer",
+                    "description": "Count.",
+                    "minimum": 0,
+                    "default": 1,
+                },
+                "list": {
+                    "type": "array",
+                    "description": "List.",
+                    "deprecated": true,
+                    "items": {
+                        "type": "integer",
+                        "description": "Count.",
+                        "minimum": 0,
+                        "default": 1,
+                    },
+                },
+                "name": {
+                    "type": "string",
+                    "description": "Name.",
+                    "minLength": 3,
+                    "enum": ["foo", "bar"],
+                },
+            },
+        })
+    );
+
+    const TYPE: Schema = StringSchema::new("Type.").schema();
+    const ONE_OF: Schema = OneOfSchema::new(
+        "One of.",
+        &("type", false, &TYPE),
+        &[
+            ("a", &ObjectSchema::new("A.", &[]).schema()),
+            ("b", &OBJECT),
+        ],
+    )
+    .schema();
+
+    let value = to_json_schema(&ONE_OF);
+    assert_eq!(value["discriminator"], json!({ "propertyName": "type" }));
+    assert_eq!(value["oneOf"][0]["required"], json!(["type"]));
+    assert_eq!(
+        value["oneOf"][0]["properties"]["type"],
+        json!({ "type": "string", "description": "Type.", "const": "a" })
+    );
+    assert_eq!(value["oneOf"][1]["required"], json!(["name", "type"]));
+}
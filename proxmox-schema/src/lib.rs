@@ -19,6 +19,7 @@ pub use const_regex::ConstRegexPattern;
 
 pub mod de;
 pub mod format;
+pub mod json_schema;
 pub mod ser;
 
 pub mod property_string;
@@ -30,10 +31,11 @@ pub mod upid;
 
 // const_regex uses lazy_static, but we otherwise don't need it, and don't want to force users to
 // have to write it out in their Cargo.toml as dependency, so we add a hidden re-export here which
-// is semver-exempt!
+// is semver-exempt! The same goes for `Regex` for patterns generated by the api macro.
 #[doc(hidden)]
 pub mod semver_exempt {
     pub use lazy_static::lazy_static;
+    pub use regex::Regex;
 }
 
 #[cfg(feature = "api-types")]
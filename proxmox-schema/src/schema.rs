@@ -180,6 +180,48 @@ impl<'a> FromIterator<(&'a str, Error)> for ParameterError {
     }
 }
 
+/// Marks a parameter, property or API method as deprecated.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "test-harness", derive(Eq, PartialEq))]
+pub struct Deprecation {
+    /// The version since which the item is deprecated.
+    pub since: Option<&'static str>,
+    /// A note for clients, e.g. what to use instead.
+    pub note: Option<&'static str>,
+}
+
+impl Deprecation {
+    pub const fn new() -> Self {
+        Self {
+            since: None,
+            note: None,
+        }
+    }
+
+    pub const fn since(mut self, since: &'static str) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub const fn note(mut self, note: &'static str) -> Self {
+        self.note = Some(note);
+        self
+    }
+}
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("deprecated")?;
+        if let Some(since) = self.since {
+            write!(f, " since {since}")?;
+        }
+        if let Some(note) = self.note {
+            write!(f, ": {note}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Data type to describe boolean values
 #[derive(Debug)]
 #[cfg_attr(feature = "test-harness", derive(Eq, PartialEq))]
@@ -187,6 +229,8 @@ pub struct BooleanSchema {
     pub description: &'static str,
     /// Optional default value.
     pub default: Option<bool>,
+    /// Set if the value is deprecated, see [Schema::deprecation].
+    deprecated: Option<&'static Deprecation>,
 }
 
 impl BooleanSchema {
@@ -194,6 +238,7 @@ impl BooleanSchema {
         BooleanSchema {
             description,
             default: None,
+            deprecated: None,
         }
     }
 
@@ -202,6 +247,12 @@ impl BooleanSchema {
         self
     }
 
+    /// Mark the value as deprecated.
+    pub const fn deprecated(mut self, deprecation: &'static Deprecation) -> Self {
+        self.deprecated = Some(deprecation);
+        self
+    }
+
     pub const fn schema(self) -> Schema {
         Schema::Boolean(self)
     }
@@ -226,6 +277,8 @@ pub struct IntegerSchema {
     pub maximum: Option<isize>,
     /// Optional default.
     pub default: Option<isize>,
+    /// Set if the value is deprecated, see [Schema::deprecation].
+    deprecated: Option<&'static Deprecation>,
 }
 
 impl IntegerSchema {
@@ -235,6 +288,7 @@ impl IntegerSchema {
             default: None,
             minimum: None,
             maximum: None,
+            deprecated: None,
         }
     }
 
@@ -253,6 +307,12 @@ impl IntegerSchema {
         self
     }
 
+    /// Mark the value as deprecated.
+    pub const fn deprecated(mut self, deprecation: &'static Deprecation) -> Self {
+        self.deprecated = Some(deprecation);
+        self
+    }
+
     pub const fn schema(self) -> Schema {
         Schema::Integer(self)
     }
@@ -301,6 +361,8 @@ pub struct NumberSchema {
     pub maximum: Option<f64>,
     /// Optional default.
     pub default: Option<f64>,
+    /// Set if the value is deprecated, see [Schema::deprecation].
+    deprecated: Option<&'static Deprecation>,
 }
 
 impl NumberSchema {
@@ -310,6 +372,7 @@ impl NumberSchema {
             default: None,
             minimum: None,
             maximum: None,
+            deprecated: None,
         }
     }
 
@@ -328,6 +391,12 @@ impl NumberSchema {
         self
     }
 
+    /// Mark the value as deprecated.
+    pub const fn deprecated(mut self, deprecation: &'static Deprecation) -> Self {
+        self.deprecated = Some(deprecation);
+        self
+    }
+
     pub const fn schema(self) -> Schema {
         Schema::Number(self)
     }
@@ -384,6 +453,7 @@ impl PartialEq for NumberSchema {
             && f64_eq(self.minimum, rhs.minimum)
             && f64_eq(self.maximum, rhs.maximum)
             && f64_eq(self.default, rhs.default)
+            && self.deprecated == rhs.deprecated
     }
 }
 
@@ -402,6 +472,8 @@ pub struct StringSchema {
     pub format: Option<&'static ApiStringFormat>,
     /// A text representation of the format/type (used to generate documentation).
     pub type_text: Option<&'static str>,
+    /// Set if the value is deprecated, see [Schema::deprecation].
+    deprecated: Option<&'static Deprecation>,
 }
 
 impl StringSchema {
@@ -413,6 +485,7 @@ impl StringSchema {
             max_length: None,
             format: None,
             type_text: None,
+            deprecated: None,
         }
     }
 
@@ -441,6 +514,12 @@ impl StringSchema {
         self
     }
 
+    /// Mark the value as deprecated.
+    pub const fn deprecated(mut self, deprecation: &'static Deprecation) -> Self {
+        self.deprecated = Some(deprecation);
+        self
+    }
+
     pub const fn schema(self) -> Schema {
         Schema::String(self)
     }
@@ -520,6 +599,8 @@ pub struct ArraySchema {
     pub min_length: Option<usize>,
     /// Optional maximal length.
     pub max_length: Option<usize>,
+    /// Set if the value is deprecated, see [Schema::deprecation].
+    deprecated: Option<&'static Deprecation>,
 }
 
 impl ArraySchema {
@@ -529,6 +610,7 @@ impl ArraySchema {
             items: item_schema,
             min_length: None,
             max_length: None,
+            deprecated: None,
         }
     }
 
@@ -542,6 +624,12 @@ impl ArraySchema {
         self
     }
 
+    /// Mark the value as deprecated.
+    pub const fn deprecated(mut self, deprecation: &'static Deprecation) -> Self {
+        self.deprecated = Some(deprecation);
+        self
+    }
+
     pub const fn schema(self) -> Schema {
         Schema::Array(self)
     }
@@ -1023,7 +1111,20 @@ impl ObjectSchemaType for OneOfSchema {
             .lookup_variant(variant)
             .ok_or_else(|| format_err!("invalid '{}': {}", self.type_property(), variant))?;
 
-        schema.verify_json(data)
+        // The variant schemas do not necessarily contain the type property (eg. when they are
+        // shared with other types), so only verify it against the variant if it knows it.
+        let variant_has_type = schema
+            .any_object()
+            .expect("non-object-schema in `OneOfSchema`")
+            .lookup(self.type_property())
+            .is_some();
+        if variant_has_type {
+            return schema.verify_json(data);
+        }
+
+        let mut data = map.clone();
+        data.remove(self.type_property());
+        schema.verify_json(&Value::Object(data))
     }
 }
 
@@ -1214,6 +1315,22 @@ impl Schema {
         }
     }
 
+    /// Gets the deprecation information, if the schema is marked as deprecated.
+    ///
+    /// Object schemas ([`ObjectSchema`], [`AllOfSchema`], [`OneOfSchema`]) describe whole types
+    /// or parameter sets and cannot be marked as deprecated themselves, so this is always `None`
+    /// for them. Deprecate their individual properties or the API method instead.
+    pub fn deprecation(&self) -> Option<&'static Deprecation> {
+        match self {
+            Schema::Boolean(s) => s.deprecated,
+            Schema::Integer(s) => s.deprecated,
+            Schema::Number(s) => s.deprecated,
+            Schema::String(s) => s.deprecated,
+            Schema::Array(s) => s.deprecated,
+            Schema::Null | Schema::Object(_) | Schema::AllOf(_) | Schema::OneOf(_) => None,
+        }
+    }
+
     /// Gets the underlying [`BooleanSchema`], panics on different schemas.
     pub const fn unwrap_boolean_schema(&self) -> &BooleanSchema {
         match self {
@@ -1823,6 +1940,29 @@ impl<T> Updater for Option<T> {
     }
 }
 
+/// Apply the updater fields of a list field with an `#[updater(list)]` attribute.
+///
+/// The list is replaced with `set` if it is `Some`, then the `add` entries not yet contained in
+/// the list are appended and all `remove` entries are removed.
+pub fn update_list<T: PartialEq>(
+    list: &mut Vec<T>,
+    set: Option<Vec<T>>,
+    add: Vec<T>,
+    remove: &[T],
+) {
+    if let Some(set) = set {
+        *list = set;
+    }
+
+    for entry in add {
+        if !list.contains(&entry) {
+            list.push(entry);
+        }
+    }
+
+    list.retain(|entry| !remove.contains(entry));
+}
+
 /// Return type schema. Return types may be any schema and additionally be optional.
 #[cfg_attr(feature = "test-harness", derive(Eq, PartialEq))]
 pub struct ReturnType {
@@ -0,0 +1,262 @@
+//! Safely modify configuration files.
+
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+use nix::fcntl::{FlockArg, OFlag};
+use nix::sys::stat;
+use nix::unistd::{self, Gid, Uid};
+
+use crate::fs::CreateOptions;
+use crate::fs::{atomic_open_or_create_file, file_get_optional_contents, make_tmp_file};
+
+/// SHA-256 digest of a configuration file's content.
+pub type ConfigDigest = [u8; 32];
+
+/// Compute the digest of configuration file content, as returned by [ConfigFileEditor::edit].
+pub fn config_digest(data: &[u8]) -> ConfigDigest {
+    openssl::sha::sha256(data)
+}
+
+/// Modify a configuration file while holding a lock, see [ConfigFileEditor].
+///
+/// Shortcut without digest verification and backups.
+pub fn edit_config_file<P, F>(
+    path: P,
+    options: CreateOptions,
+    edit: F,
+) -> Result<ConfigDigest, Error>
+where
+    P: AsRef<Path>,
+    F: FnOnce(Option<&[u8]>) -> Result<Vec<u8>, Error>,
+{
+    ConfigFileEditor::new(path, options).edit(edit)
+}
+
+/// Modify a configuration file.
+///
+/// Editing a file:
+///
+/// - takes an exclusive `flock(2)` on a separate lock file `.<name>.lck` next to the file, so
+///   concurrent editors (in other processes, too) are serialized,
+/// - verifies the digest of the current content if one was passed, to detect that the file was
+///   modified since a client read it,
+/// - rotates the current content into up to `backups` backup files `<name>.1`, `<name>.2`, ...
+///   (the lowest number is the most recent one),
+/// - atomically replaces the file, keeping the owner, group and permissions of the existing file.
+///   The [CreateOptions] are only used for files which do not exist yet and for the lock file.
+pub struct ConfigFileEditor {
+    path: PathBuf,
+    options: CreateOptions,
+    digest: Option<ConfigDigest>,
+    backups: usize,
+    fsync: bool,
+}
+
+impl ConfigFileEditor {
+    /// Create an editor for the file at `path`, without digest verification and backups.
+    ///
+    /// The `options` are used when creating the lock file and the file itself if it does not
+    /// exist yet.
+    pub fn new<P: AsRef<Path>>(path: P, options: CreateOptions) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            options,
+            digest: None,
+            backups: 0,
+            fsync: true,
+        }
+    }
+
+    /// Fail if the digest of the current content does not match.
+    ///
+    /// A file which does not exist yet has the digest of empty content.
+    pub fn digest(mut self, digest: Option<ConfigDigest>) -> Self {
+        self.digest = digest;
+        self
+    }
+
+    /// Keep the given number of backups of previous contents (default: 0).
+    pub fn backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Whether to `fsync(2)` the new content before moving it into place (default: true).
+    pub fn fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    fn lock_path(&self) -> Result<PathBuf, Error> {
+        let name = self
+            .path
+            .file_name()
+            .ok_or_else(|| format_err!("invalid config file path {:?}", self.path))?;
+
+        let mut lock_name = std::ffi::OsString::from(".");
+        lock_name.push(name);
+        lock_name.push(".lck");
+
+        Ok(self.path.with_file_name(lock_name))
+    }
+
+    fn backup_path(&self, number: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{number}"));
+        PathBuf::from(path)
+    }
+
+    /// Lock the file and replace its content with the result of `edit`.
+    ///
+    /// `edit` gets the current content, `None` if the file does not exist. The file is left
+    /// untouched if `edit` fails. Returns the digest of the new content.
+    pub fn edit<F>(self, edit: F) -> Result<ConfigDigest, Error>
+    where
+        F: FnOnce(Option<&[u8]>) -> Result<Vec<u8>, Error>,
+    {
+        let lock_path = self.lock_path()?;
+        let lock = atomic_open_or_create_file(
+            &lock_path,
+            OFlag::O_RDWR | OFlag::O_CLOEXEC | OFlag::O_APPEND,
+            &[],
+            self.options.clone(),
+            false,
+        )?;
+        nix::fcntl::flock(lock.as_raw_fd(), FlockArg::LockExclusive)
+            .map_err(|err| format_err!("unable to acquire lock {:?} - {}", lock_path, err))?;
+
+        let old = file_get_optional_contents(&self.path)?;
+
+        if let Some(expected) = &self.digest {
+            let current = config_digest(old.as_deref().unwrap_or_default());
+            if *expected != current {
+                bail!("detected modified configuration - file changed by other user? Try again.");
+            }
+        }
+
+        let new = edit(old.as_deref())?;
+
+        let options = match std::fs::metadata(&self.path) {
+            Ok(metadata) => CreateOptions::new()
+                .perm(stat::Mode::from_bits_truncate(metadata.mode()))
+                .owner(Uid::from_raw(metadata.uid()))
+                .group(Gid::from_raw(metadata.gid())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => self.options.clone(),
+            Err(err) => bail!("unable to stat {:?} - {}", self.path, err),
+        };
+
+        let (file, tmp_path) = make_tmp_file(&self.path, options)?;
+        if let Err(err) = self.write_tmp_file(file, &new) {
+            let _ = unistd::unlink(&tmp_path);
+            bail!("writing {:?} failed - {}", tmp_path, err);
+        }
+
+        if old.is_some() && self.backups > 0 {
+            if let Err(err) = self.rotate_backups() {
+                let _ = unistd::unlink(&tmp_path);
+                return Err(err);
+            }
+        }
+
+        if let Err(err) = std::fs::rename(&tmp_path, &self.path) {
+            let _ = unistd::unlink(&tmp_path);
+            bail!("Atomic rename failed for file {:?} - {}", self.path, err);
+        }
+
+        drop(lock);
+
+        Ok(config_digest(&new))
+    }
+
+    fn write_tmp_file(&self, mut file: File, data: &[u8]) -> Result<(), Error> {
+        file.write_all(data)?;
+        if self.fsync {
+            unistd::fsync(file.as_raw_fd())?;
+        }
+        Ok(())
+    }
+
+    /// Shift the existing backups by one and hard link the current file as the first backup.
+    fn rotate_backups(&self) -> Result<(), Error> {
+        for number in (1..self.backups).rev() {
+            let from = self.backup_path(number);
+            let to = self.backup_path(number + 1);
+            match std::fs::rename(&from, &to) {
+                Ok(()) => (),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                Err(err) => bail!("unable to rotate backup {:?} - {}", from, err),
+            }
+        }
+
+        let backup = self.backup_path(1);
+        match std::fs::remove_file(&backup) {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => bail!("unable to remove old backup {:?} - {}", backup, err),
+        }
+        std::fs::hard_link(&self.path, &backup)
+            .map_err(|err| format_err!("unable to create backup {:?} - {}", backup, err))?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_edit_config_file() -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("proxmox-sys-config-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("test.cfg");
+
+    let result = (|| {
+        let options = CreateOptions::new().perm(stat::Mode::from_bits_truncate(0o600));
+
+        let digest = edit_config_file(&path, options.clone(), |old| {
+            assert_eq!(old, None);
+            Ok(b"one\n".to_vec())
+        })?;
+        assert_eq!(digest, config_digest(b"one\n"));
+        let mode = std::fs::metadata(&path)?.permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+
+        // the permissions of the existing file are kept
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640))?;
+
+        let editor = || ConfigFileEditor::new(&path, options.clone()).backups(2);
+        let digest = editor().digest(Some(digest)).edit(|old| {
+            assert_eq!(old, Some(&b"one\n"[..]));
+            Ok(b"two\n".to_vec())
+        })?;
+        editor().edit(|_| Ok(b"three\n".to_vec()))?;
+        editor().edit(|_| Ok(b"four\n".to_vec()))?;
+
+        assert_eq!(std::fs::read(&path)?, b"four\n");
+        assert_eq!(std::fs::read(dir.join("test.cfg.1"))?, b"three\n");
+        assert_eq!(std::fs::read(dir.join("test.cfg.2"))?, b"two\n");
+        assert!(!dir.join("test.cfg.3").exists());
+        let mode = std::fs::metadata(&path)?.permissions().mode() & 0o777;
+        assert_eq!(mode, 0o640);
+
+        // outdated digest
+        assert!(editor()
+            .digest(Some(digest))
+            .edit(|_| Ok(b"five\n".to_vec()))
+            .is_err());
+        // failing edit
+        assert!(editor().edit(|_| bail!("failed")).is_err());
+        assert_eq!(std::fs::read(&path)?, b"four\n");
+        assert_eq!(std::fs::read(dir.join("test.cfg.1"))?, b"three\n");
+
+        Ok(())
+    })();
+
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
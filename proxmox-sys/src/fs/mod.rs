@@ -22,8 +22,16 @@ pub use read_dir::*;
 mod fsx_attr;
 pub use fsx_attr::*;
 
+#[cfg(feature = "config-file")]
+mod config_file;
+#[cfg(feature = "config-file")]
+pub use config_file::*;
+
 pub mod xattr;
 
+#[cfg(feature = "watch")]
+pub mod watch;
+
 /// Change ownership of an open file handle
 pub fn fchown(fd: RawFd, owner: Option<Uid>, group: Option<Gid>) -> Result<(), Error> {
     nix::unistd::fchown(fd, owner, group).map_err(|err| err.into())
@@ -174,3 +182,63 @@ pub fn fs_info<P: ?Sized + nix::NixPath>(path: &P) -> nix::Result<FileSystemInfo
         fs_id: stat.f_fsid,
     })
 }
+
+/// File system usage as reported by `df`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileSystemUsage {
+    /// Total size in bytes.
+    pub total: u64,
+    /// Used bytes.
+    pub used: u64,
+    /// Bytes available to an unprivileged user.
+    pub available: u64,
+    /// Total number of inodes.
+    pub total_inodes: u64,
+    /// Number of free inodes.
+    pub free_inodes: u64,
+}
+
+impl FileSystemUsage {
+    /// The used space in percent, calculated like `df` does.
+    ///
+    /// Space reserved for the root user is not counted as available.
+    pub fn used_percent(&self) -> f64 {
+        let usable = self.used + self.available;
+        if usable == 0 {
+            return 0.0;
+        }
+        (self.used as f64) * 100.0 / (usable as f64)
+    }
+}
+
+/// Get the usage of the file system containing `path` via `statvfs(3)`.
+pub fn fs_usage<P: ?Sized + nix::NixPath>(path: &P) -> Result<FileSystemUsage, Error> {
+    let stat = nix::sys::statvfs::statvfs(path).context("statvfs failed")?;
+
+    let block_size = match stat.fragment_size() {
+        0 => stat.block_size(),
+        fragment_size => fragment_size,
+    } as u64;
+
+    let blocks = stat.blocks() as u64;
+    let free = stat.blocks_free() as u64;
+
+    Ok(FileSystemUsage {
+        total: blocks * block_size,
+        used: blocks.saturating_sub(free) * block_size,
+        available: stat.blocks_available() as u64 * block_size,
+        total_inodes: stat.files() as u64,
+        free_inodes: stat.files_free() as u64,
+    })
+}
+
+#[test]
+fn test_fs_usage() {
+    let usage = fs_usage("/").expect("failed to get usage of /");
+    assert!(usage.total > 0);
+    assert!(usage.used <= usage.total);
+    assert!(usage.available <= usage.total - usage.used);
+    assert!((0.0..=100.0).contains(&usage.used_percent()));
+
+    assert!(fs_usage("/does/not/exist").is_err());
+}
@@ -0,0 +1,355 @@
+//! Asynchronously watch files and directories for changes, based on inotify.
+//!
+//! Files are watched via their parent directory, so replacing a file atomically (by renaming a
+//! temporary file over it) is noticed as well, and watching a file which does not exist yet
+//! works as long as its directory exists.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use futures::stream::Stream;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use tokio::io::unix::AsyncFd;
+
+/// The kind of change reported by a [Watcher].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum WatchEventKind {
+    /// The file or directory was created.
+    Create,
+    /// The content or the metadata of the file was modified.
+    Modify,
+    /// The file or directory was deleted.
+    Delete,
+    /// The file or directory was moved away.
+    MovedFrom,
+    /// The file or directory was moved here, e.g. when atomically replacing a file.
+    MovedTo,
+    /// Events were lost because the kernel's queue overflowed, all watched paths should be
+    /// considered changed. The path of such an event is empty.
+    Rescan,
+}
+
+/// A change of a watched path.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct WatchEvent {
+    /// The changed path, i.e. the watched file or a file within a watched directory.
+    pub path: PathBuf,
+    /// The kind of change.
+    pub kind: WatchEventKind,
+}
+
+/// Closes the inotify file descriptor on drop, which nix' `Inotify` does not do.
+struct InotifyFd(Inotify);
+
+impl AsRawFd for InotifyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl Drop for InotifyFd {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.0.as_raw_fd());
+    }
+}
+
+/// A watched directory.
+struct WatchedDir {
+    path: PathBuf,
+    /// The watched file names within the directory, `None` if the whole directory is watched.
+    files: Option<Vec<OsString>>,
+}
+
+/// Watches files and directories for changes.
+///
+/// Needs to be used from within a tokio runtime with I/O enabled.
+pub struct Watcher {
+    inotify: AsyncFd<InotifyFd>,
+    watches: HashMap<WatchDescriptor, WatchedDir>,
+    /// Directories whose watch was removed by the kernel, reported as error by the next call.
+    removed: Vec<PathBuf>,
+}
+
+const WATCH_FLAGS: AddWatchFlags = AddWatchFlags::from_bits_truncate(
+    AddWatchFlags::IN_CREATE.bits()
+        | AddWatchFlags::IN_MODIFY.bits()
+        | AddWatchFlags::IN_CLOSE_WRITE.bits()
+        | AddWatchFlags::IN_ATTRIB.bits()
+        | AddWatchFlags::IN_DELETE.bits()
+        | AddWatchFlags::IN_MOVED_FROM.bits()
+        | AddWatchFlags::IN_MOVED_TO.bits()
+        | AddWatchFlags::IN_DELETE_SELF.bits()
+        | AddWatchFlags::IN_MOVE_SELF.bits(),
+);
+
+impl Watcher {
+    /// Create a new watcher without any watched paths.
+    pub fn new() -> Result<Self, Error> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)
+            .map_err(|err| format_err!("unable to initialize inotify - {err}"))?;
+        let inotify = AsyncFd::new(InotifyFd(inotify))?;
+
+        Ok(Self {
+            inotify,
+            watches: HashMap::new(),
+            removed: Vec::new(),
+        })
+    }
+
+    /// Watch a directory for changes of its entries.
+    pub fn watch_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        self.add_watch(path.as_ref(), None)
+    }
+
+    /// Watch a single file, via its parent directory, which needs to exist.
+    pub fn watch_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let (dir, name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => (dir, name),
+            _ => bail!("cannot watch {path:?} - not a file path"),
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+
+        self.add_watch(dir, Some(name.to_os_string()))
+    }
+
+    fn add_watch(&mut self, dir: &Path, file: Option<OsString>) -> Result<(), Error> {
+        let wd = self
+            .inotify
+            .get_ref()
+            .0
+            .add_watch(dir, WATCH_FLAGS)
+            .map_err(|err| format_err!("unable to watch {dir:?} - {err}"))?;
+
+        // the kernel returns the same descriptor when watching a directory twice
+        let watched = self.watches.entry(wd).or_insert_with(|| WatchedDir {
+            path: dir.to_path_buf(),
+            files: Some(Vec::new()),
+        });
+
+        match (&mut watched.files, file) {
+            (Some(files), Some(file)) => {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+            (files, None) => *files = None,
+            (None, Some(_)) => (), // the whole directory is watched already
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the next batch of changes.
+    ///
+    /// The result may be empty if only events for unwatched files in watched directories
+    /// were received.
+    async fn read_events(&mut self) -> Result<Vec<WatchEvent>, Error> {
+        loop {
+            if !self.removed.is_empty() {
+                let removed = std::mem::take(&mut self.removed);
+                bail!("no longer watching {removed:?} - removed or unmounted");
+            }
+
+            let mut guard = self.inotify.readable().await?;
+
+            match guard.try_io(|inotify| inotify.get_ref().0.read_events().map_err(io::Error::from))
+            {
+                Ok(Ok(events)) => {
+                    return Ok(events
+                        .into_iter()
+                        .filter_map(|event| self.convert_event(event))
+                        .collect())
+                }
+                Ok(Err(err)) => bail!("unable to read inotify events - {err}"),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn convert_event(&mut self, event: InotifyEvent) -> Option<WatchEvent> {
+        let mask = event.mask;
+
+        if mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+            return Some(WatchEvent {
+                path: PathBuf::new(),
+                kind: WatchEventKind::Rescan,
+            });
+        }
+
+        if mask.contains(AddWatchFlags::IN_IGNORED) {
+            if let Some(watched) = self.watches.remove(&event.wd) {
+                self.removed.push(watched.path);
+            }
+            return None;
+        }
+
+        let watched = self.watches.get(&event.wd)?;
+
+        let path = match &event.name {
+            Some(name) => {
+                if let Some(files) = &watched.files {
+                    if !files.contains(name) {
+                        return None;
+                    }
+                }
+                watched.path.join(name)
+            }
+            None => watched.path.clone(),
+        };
+
+        let kind = if mask.contains(AddWatchFlags::IN_CREATE) {
+            WatchEventKind::Create
+        } else if mask.intersects(AddWatchFlags::IN_DELETE | AddWatchFlags::IN_DELETE_SELF) {
+            WatchEventKind::Delete
+        } else if mask.intersects(AddWatchFlags::IN_MOVED_FROM | AddWatchFlags::IN_MOVE_SELF) {
+            WatchEventKind::MovedFrom
+        } else if mask.contains(AddWatchFlags::IN_MOVED_TO) {
+            WatchEventKind::MovedTo
+        } else {
+            WatchEventKind::Modify
+        };
+
+        Some(WatchEvent { path, kind })
+    }
+
+    /// Wait for the next changes.
+    ///
+    /// Multiple events for the same path and kind, e.g. for writes in several chunks, are
+    /// merged, keeping the order of their first occurrence.
+    ///
+    /// Fails once after a watched directory was deleted or unmounted, after its pending events
+    /// were returned. The directory is not watched anymore then, but can be added again.
+    pub async fn next(&mut self) -> Result<Vec<WatchEvent>, Error> {
+        loop {
+            let events = self.read_events().await?;
+            if !events.is_empty() {
+                return Ok(dedup(events));
+            }
+        }
+    }
+
+    /// Wait for the next changes and then until no further changes happen for `delay`.
+    ///
+    /// Useful to only react once to a series of modifications, e.g. a certificate and its key
+    /// being replaced one after the other.
+    pub async fn next_debounced(&mut self, delay: Duration) -> Result<Vec<WatchEvent>, Error> {
+        let mut events = self.next().await?;
+
+        loop {
+            // return the pending events first, the removal is reported by the next call
+            if !self.removed.is_empty() {
+                return Ok(dedup(events));
+            }
+
+            match tokio::time::timeout(delay, self.read_events()).await {
+                Ok(more) => events.extend(more?),
+                Err(_elapsed) => return Ok(dedup(events)),
+            }
+        }
+    }
+
+    /// Turn the watcher into a stream of changes, debounced by `delay` if set.
+    ///
+    /// The stream ends after yielding an error, see [next](Self::next).
+    pub fn into_stream(
+        self,
+        delay: Option<Duration>,
+    ) -> impl Stream<Item = Result<Vec<WatchEvent>, Error>> {
+        futures::stream::unfold(Some(self), move |watcher| async move {
+            let mut watcher = watcher?;
+            let result = match delay {
+                Some(delay) => watcher.next_debounced(delay).await,
+                None => watcher.next().await,
+            };
+            // end the stream after an error
+            let next = result.is_ok().then_some(watcher);
+            Some((result, next))
+        })
+    }
+}
+
+fn dedup(events: Vec<WatchEvent>) -> Vec<WatchEvent> {
+    let mut result: Vec<WatchEvent> = Vec::with_capacity(events.len());
+    for event in events {
+        if !result.contains(&event) {
+            result.push(event);
+        }
+    }
+    result
+}
+
+#[test]
+fn test_watcher() -> Result<(), Error> {
+    use futures::StreamExt;
+
+    let dir = std::env::temp_dir().join(format!("proxmox-sys-watch-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sub"))?;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let result = rt.block_on(async {
+        let mut watcher = Watcher::new()?;
+        watcher.watch_file(dir.join("cert.pem"))?;
+        watcher.watch_dir(dir.join("sub"))?;
+
+        // unwatched file in the same directory
+        std::fs::write(dir.join("other"), "x")?;
+        // atomic replace of the watched file
+        std::fs::write(dir.join("cert.pem.tmp"), "cert")?;
+        std::fs::rename(dir.join("cert.pem.tmp"), dir.join("cert.pem"))?;
+
+        let events = watcher.next().await?;
+        assert_eq!(
+            events,
+            [WatchEvent {
+                path: dir.join("cert.pem"),
+                kind: WatchEventKind::MovedTo,
+            }]
+        );
+
+        std::fs::write(dir.join("sub/a"), "1")?;
+        std::fs::write(dir.join("sub/a"), "2")?;
+        std::fs::remove_file(dir.join("sub/a"))?;
+
+        let mut stream = Box::pin(watcher.into_stream(Some(Duration::from_millis(50))));
+        let events = stream.next().await.unwrap()?;
+        let kinds: Vec<WatchEventKind> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                WatchEventKind::Create,
+                WatchEventKind::Modify,
+                WatchEventKind::Delete,
+            ]
+        );
+        assert!(events.iter().all(|event| event.path == dir.join("sub/a")));
+
+        // removing a watched directory ends the stream with an error
+        std::fs::remove_dir(dir.join("sub"))?;
+        let events = stream.next().await.unwrap()?;
+        assert!(events.contains(&WatchEvent {
+            path: dir.join("sub"),
+            kind: WatchEventKind::Delete,
+        }));
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+
+        Ok::<(), Error>(())
+    });
+
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
@@ -0,0 +1,201 @@
+//! `/proc/diskstats` handling.
+
+use anyhow::{bail, format_err, Error};
+use serde::Serialize;
+
+/// The size of a sector as used by the kernel's block layer statistics, independent of the
+/// device's actual sector size.
+pub const SECTOR_SIZE: u64 = 512;
+
+/// The I/O statistics of a single block device, see the kernel's `iostats.rst`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DiskStat {
+    /// Major device number.
+    pub major: u32,
+    /// Minor device number.
+    pub minor: u32,
+    /// Device name, e.g. `sda` or `nvme0n1p1`.
+    pub device: String,
+    /// Number of completed reads.
+    pub read_ios: u64,
+    /// Number of reads merged with adjacent ones.
+    pub read_merges: u64,
+    /// Number of sectors read, see [SECTOR_SIZE].
+    pub read_sectors: u64,
+    /// Time spent reading, in milliseconds.
+    pub read_ticks: u64,
+    /// Number of completed writes.
+    pub write_ios: u64,
+    /// Number of writes merged with adjacent ones.
+    pub write_merges: u64,
+    /// Number of sectors written, see [SECTOR_SIZE].
+    pub write_sectors: u64,
+    /// Time spent writing, in milliseconds.
+    pub write_ticks: u64,
+    /// Number of I/Os currently in progress.
+    pub in_flight: u64,
+    /// Time the device had I/Os in progress, in milliseconds.
+    pub io_ticks: u64,
+    /// Weighted time spent doing I/Os, in milliseconds.
+    pub time_in_queue: u64,
+}
+
+impl DiskStat {
+    /// Parse a single line of `/proc/diskstats`.
+    ///
+    /// Only the first 11 statistics fields are used, the discard and flush fields of newer
+    /// kernels are ignored.
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let mut parts = line.split_ascii_whitespace();
+
+        let mut next = |what: &'static str| {
+            parts
+                .next()
+                .ok_or_else(|| format_err!("missing field '{}'", what))
+        };
+
+        fn num<T: std::str::FromStr>(value: &str, what: &'static str) -> Result<T, Error> {
+            value
+                .parse::<T>()
+                .map_err(|_| format_err!("unable to parse field '{}' - '{}'", what, value))
+        }
+
+        Ok(Self {
+            major: num(next("major")?, "major")?,
+            minor: num(next("minor")?, "minor")?,
+            device: next("device")?.to_string(),
+            read_ios: num(next("read_ios")?, "read_ios")?,
+            read_merges: num(next("read_merges")?, "read_merges")?,
+            read_sectors: num(next("read_sectors")?, "read_sectors")?,
+            read_ticks: num(next("read_ticks")?, "read_ticks")?,
+            write_ios: num(next("write_ios")?, "write_ios")?,
+            write_merges: num(next("write_merges")?, "write_merges")?,
+            write_sectors: num(next("write_sectors")?, "write_sectors")?,
+            write_ticks: num(next("write_ticks")?, "write_ticks")?,
+            in_flight: num(next("in_flight")?, "in_flight")?,
+            io_ticks: num(next("io_ticks")?, "io_ticks")?,
+            time_in_queue: num(next("time_in_queue")?, "time_in_queue")?,
+        })
+    }
+
+    /// Number of bytes read.
+    pub fn read_bytes(&self) -> u64 {
+        self.read_sectors.saturating_mul(SECTOR_SIZE)
+    }
+
+    /// Number of bytes written.
+    pub fn write_bytes(&self) -> u64 {
+        self.write_sectors.saturating_mul(SECTOR_SIZE)
+    }
+
+    /// Compute the counters since a `previous` sample of the same device.
+    ///
+    /// The result can be divided by the time between the samples to get rates. `in_flight` is
+    /// not a counter and thus kept as is. Counters that went backwards, e.g. because the device
+    /// was re-added in between, are returned as 0.
+    pub fn delta(&self, previous: &DiskStat) -> DiskStat {
+        DiskStat {
+            major: self.major,
+            minor: self.minor,
+            device: self.device.clone(),
+            read_ios: self.read_ios.saturating_sub(previous.read_ios),
+            read_merges: self.read_merges.saturating_sub(previous.read_merges),
+            read_sectors: self.read_sectors.saturating_sub(previous.read_sectors),
+            read_ticks: self.read_ticks.saturating_sub(previous.read_ticks),
+            write_ios: self.write_ios.saturating_sub(previous.write_ios),
+            write_merges: self.write_merges.saturating_sub(previous.write_merges),
+            write_sectors: self.write_sectors.saturating_sub(previous.write_sectors),
+            write_ticks: self.write_ticks.saturating_sub(previous.write_ticks),
+            in_flight: self.in_flight,
+            io_ticks: self.io_ticks.saturating_sub(previous.io_ticks),
+            time_in_queue: self.time_in_queue.saturating_sub(previous.time_in_queue),
+        }
+    }
+}
+
+/// Parse the contents of `/proc/diskstats`.
+pub fn parse_diskstats(content: &str) -> Result<Vec<DiskStat>, Error> {
+    let mut result = Vec::new();
+
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match DiskStat::parse(line) {
+            Ok(stat) => result.push(stat),
+            Err(err) => bail!("{} in line '{}'", err, line.trim()),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Read and parse `/proc/diskstats`.
+pub fn read_proc_diskstats() -> Result<Vec<DiskStat>, Error> {
+    let path = "/proc/diskstats";
+    let content = std::fs::read_to_string(path)?;
+    parse_diskstats(&content).map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+}
+
+/// Compute the counters of all devices in `current` since the `previous` sample.
+///
+/// Devices are matched by name. Devices which are new are returned with all counters set to 0,
+/// since nothing is known about their previous state, devices which vanished are omitted.
+pub fn diskstats_delta(current: &[DiskStat], previous: &[DiskStat]) -> Vec<DiskStat> {
+    let mut result = Vec::with_capacity(current.len());
+
+    for stat in current {
+        match previous.iter().find(|prev| prev.device == stat.device) {
+            Some(prev) => result.push(stat.delta(prev)),
+            None => result.push(stat.delta(stat)),
+        }
+    }
+
+    result
+}
+
+#[test]
+fn test_parse_diskstats() {
+    let first = parse_diskstats(
+        "   8       0 sda 52365 14470 3810274 21690 162829 186446 7429840 178339 0 169380 213702 0 0 0 0 4545 13672\n   \
+            8       1 sda1 52204 14470 3803058 21634 162827 186446 7429840 178337 0 169352 199971 0 0 0 0 0 0\n \
+          259       0 nvme0n1 1000 0 80000 300 2000 10 160000 900 2 1100 1250\n",
+    )
+    .expect("failed to parse /proc/diskstats sample");
+
+    assert_eq!(first.len(), 3);
+    assert_eq!(first[0].device, "sda");
+    assert_eq!((first[0].major, first[0].minor), (8, 0));
+    assert_eq!(first[0].read_ios, 52365);
+    assert_eq!(first[0].read_sectors, 3810274);
+    assert_eq!(first[0].read_bytes(), 3810274 * 512);
+    assert_eq!(first[0].write_ios, 162829);
+    assert_eq!(first[0].write_sectors, 7429840);
+    assert_eq!(first[0].io_ticks, 169380);
+    assert_eq!(first[2].device, "nvme0n1");
+    assert_eq!(first[2].in_flight, 2);
+    assert_eq!(first[2].time_in_queue, 1250);
+
+    let second = parse_diskstats(
+        "   8       0 sda 52400 14470 3810474 21700 162900 186500 7430840 178400 1 169900 214000 0 0 0 0 4545 13672\n \
+          259       1 nvme1n1 10 0 80 3 0 0 0 0 0 3 3\n",
+    )
+    .expect("failed to parse /proc/diskstats sample");
+
+    let delta = diskstats_delta(&second, &first);
+    assert_eq!(delta.len(), 2);
+    assert_eq!(delta[0].read_ios, 35);
+    assert_eq!(delta[0].read_bytes(), 200 * 512);
+    assert_eq!(delta[0].write_sectors, 1000);
+    assert_eq!(delta[0].in_flight, 1);
+    assert_eq!(delta[0].io_ticks, 520);
+    assert_eq!(delta[1].device, "nvme1n1");
+    assert_eq!((delta[1].major, delta[1].minor), (259, 1));
+    assert_eq!(delta[1].read_ios, 0);
+    assert_eq!(delta[1].read_sectors, 0);
+    assert_eq!(delta[1].io_ticks, 0);
+    assert_eq!(delta[1].time_in_queue, 0);
+
+    assert!(parse_diskstats("   8       0 sda 1 2 3\n").is_err());
+}
@@ -0,0 +1,237 @@
+//! `/proc/interrupts` and `/proc/softirqs` handling.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, format_err, Error};
+use serde::Serialize;
+
+/// Per-CPU counts of a single interrupt source.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InterruptSource {
+    /// The IRQ number or name, e.g. `24`, `NMI` or `NET_RX`.
+    pub name: String,
+    /// The counts per CPU, in the order of [InterruptStats::cpus].
+    ///
+    /// Some sources (like `ERR` and `MIS`) only provide a single, system wide value.
+    pub counts: Vec<u64>,
+    /// Controller, trigger type and device names, empty for softirqs.
+    pub description: String,
+}
+
+impl InterruptSource {
+    /// The sum of the counts over all CPUs.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/// The contents of `/proc/interrupts` or `/proc/softirqs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InterruptStats {
+    /// The CPU column names, e.g. `CPU0`. Offline CPUs are not listed.
+    pub cpus: Vec<String>,
+    /// The interrupt sources in file order.
+    pub sources: Vec<InterruptSource>,
+}
+
+impl InterruptStats {
+    /// Parse the contents of `/proc/interrupts` or `/proc/softirqs`.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut lines = content.lines();
+
+        let cpus: Vec<String> = lines
+            .next()
+            .ok_or_else(|| format_err!("missing header line"))?
+            .split_ascii_whitespace()
+            .map(String::from)
+            .collect();
+
+        if cpus.is_empty() {
+            bail!("no CPUs found in header line");
+        }
+
+        let mut sources = Vec::new();
+        for line in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (name, rest) = line
+                .split_once(':')
+                .ok_or_else(|| format_err!("missing ':' in line '{}'", line))?;
+
+            let mut parts = rest.split_ascii_whitespace().peekable();
+            let mut counts = Vec::with_capacity(cpus.len());
+            while counts.len() < cpus.len() {
+                match parts.peek().map(|part| part.parse::<u64>()) {
+                    Some(Ok(count)) => {
+                        counts.push(count);
+                        parts.next();
+                    }
+                    _ => break,
+                }
+            }
+
+            if counts.is_empty() {
+                bail!("no counts found for interrupt source '{}'", name.trim());
+            }
+
+            sources.push(InterruptSource {
+                name: name.trim().to_string(),
+                counts,
+                description: parts.collect::<Vec<&str>>().join(" "),
+            });
+        }
+
+        Ok(Self { cpus, sources })
+    }
+
+    /// Look up an interrupt source by its name.
+    pub fn source(&self, name: &str) -> Option<&InterruptSource> {
+        self.sources.iter().find(|source| source.name == name)
+    }
+
+    /// The sum of all per-CPU interrupt counts, in the order of [Self::cpus].
+    ///
+    /// System wide values (like `ERR`) are not included.
+    pub fn cpu_totals(&self) -> Vec<u64> {
+        let mut totals = vec![0u64; self.cpus.len()];
+        for source in self.sources.iter() {
+            if source.counts.len() != self.cpus.len() {
+                continue;
+            }
+            for (total, count) in totals.iter_mut().zip(source.counts.iter()) {
+                *total = total.saturating_add(*count);
+            }
+        }
+        totals
+    }
+
+    /// Compute the counts since a `previous` sample.
+    ///
+    /// Sources and CPUs are matched by name, so CPUs going on- or offline in between are
+    /// handled. Sources or CPUs which are new are returned with their absolute counts.
+    pub fn delta(&self, previous: &InterruptStats) -> InterruptStats {
+        let prev_cpu_index: HashMap<&str, usize> = previous
+            .cpus
+            .iter()
+            .enumerate()
+            .map(|(index, cpu)| (cpu.as_str(), index))
+            .collect();
+
+        let sources = self
+            .sources
+            .iter()
+            .map(|source| {
+                let prev = previous.source(&source.name);
+                let counts = source
+                    .counts
+                    .iter()
+                    .enumerate()
+                    .map(|(index, count)| {
+                        let prev_index = if source.counts.len() == self.cpus.len() {
+                            self.cpus
+                                .get(index)
+                                .and_then(|cpu| prev_cpu_index.get(cpu.as_str()).copied())
+                        } else {
+                            Some(index)
+                        };
+                        let prev_count = prev
+                            .zip(prev_index)
+                            .and_then(|(prev, prev_index)| prev.counts.get(prev_index).copied())
+                            .unwrap_or(0);
+                        count.saturating_sub(prev_count)
+                    })
+                    .collect();
+
+                InterruptSource {
+                    name: source.name.clone(),
+                    counts,
+                    description: source.description.clone(),
+                }
+            })
+            .collect();
+
+        InterruptStats {
+            cpus: self.cpus.clone(),
+            sources,
+        }
+    }
+}
+
+/// Read and parse `/proc/interrupts`.
+pub fn read_proc_interrupts() -> Result<InterruptStats, Error> {
+    let path = "/proc/interrupts";
+    let content = std::fs::read_to_string(path)?;
+    InterruptStats::parse(&content)
+        .map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+}
+
+/// Read and parse `/proc/softirqs`.
+pub fn read_proc_softirqs() -> Result<InterruptStats, Error> {
+    let path = "/proc/softirqs";
+    let content = std::fs::read_to_string(path)?;
+    InterruptStats::parse(&content)
+        .map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+}
+
+#[test]
+fn test_parse_interrupts() {
+    let stats = InterruptStats::parse(
+        "            CPU0       CPU1       CPU2       CPU3\n   \
+         0:         36          0          0          0   IO-APIC   2-edge      timer\n   \
+         8:          0          0          1          0   IO-APIC   8-edge      rtc0\n  \
+         24:     100000         10          0         20   PCI-MSIX-0000:01:00.0   0-edge      eno1-TxRx-0\n\
+         NMI:         12         13         14         15   Non-maskable interrupts\n\
+         ERR:          0\n\
+         MIS:          0\n",
+    )
+    .expect("failed to parse /proc/interrupts sample");
+
+    assert_eq!(stats.cpus, ["CPU0", "CPU1", "CPU2", "CPU3"]);
+    assert_eq!(stats.sources.len(), 6);
+
+    let nic = stats.source("24").unwrap();
+    assert_eq!(nic.counts, [100000, 10, 0, 20]);
+    assert_eq!(nic.total(), 100030);
+    assert_eq!(nic.description, "PCI-MSIX-0000:01:00.0 0-edge eno1-TxRx-0");
+
+    let nmi = stats.source("NMI").unwrap();
+    assert_eq!(nmi.description, "Non-maskable interrupts");
+
+    let err = stats.source("ERR").unwrap();
+    assert_eq!(err.counts, [0]);
+    assert_eq!(err.description, "");
+
+    assert_eq!(stats.cpu_totals(), [100048, 23, 15, 35]);
+}
+
+#[test]
+fn test_parse_softirqs_delta() {
+    let first = InterruptStats::parse(
+        "                    CPU0       CPU1\n          \
+         HI:          1          0\n       \
+         TIMER:       1000       2000\n      \
+         NET_RX:        500         10\n",
+    )
+    .expect("failed to parse /proc/softirqs sample");
+
+    assert_eq!(first.source("TIMER").unwrap().description, "");
+
+    // CPU1 went offline, CPU2 came online
+    let second = InterruptStats::parse(
+        "                    CPU0       CPU2\n          \
+         HI:          1          3\n       \
+         TIMER:       1500        100\n      \
+         NET_RX:        900          5\n    \
+         NET_TX:          7          0\n",
+    )
+    .expect("failed to parse /proc/softirqs sample");
+
+    let delta = second.delta(&first);
+    assert_eq!(delta.cpus, ["CPU0", "CPU2"]);
+    assert_eq!(delta.source("HI").unwrap().counts, [0, 3]);
+    assert_eq!(delta.source("TIMER").unwrap().counts, [500, 100]);
+    assert_eq!(delta.source("NET_RX").unwrap().counts, [400, 5]);
+    assert_eq!(delta.source("NET_TX").unwrap().counts, [7, 0]);
+}
@@ -17,7 +17,38 @@ use crate::fs::{file_read_firstline, read_firstline};
 
 pub mod mountinfo;
 #[doc(inline)]
-pub use mountinfo::MountInfo;
+pub use mountinfo::{read_mountinfo, MountInfo};
+
+pub mod mounts;
+#[doc(inline)]
+pub use mounts::{read_proc_mounts, MountEntry};
+
+pub mod interrupts;
+#[doc(inline)]
+pub use interrupts::{read_proc_interrupts, read_proc_softirqs, InterruptStats};
+
+pub mod diskstats;
+#[doc(inline)]
+pub use diskstats::{diskstats_delta, read_proc_diskstats, DiskStat};
+
+pub mod pressure;
+#[doc(inline)]
+pub use pressure::{read_proc_pressure, Pressure, PressureStats};
+
+pub mod netdev;
+#[doc(inline)]
+pub use netdev::{read_network_device, read_network_devices, NetworkDevice, NetworkDeviceType};
+
+pub mod sockets;
+#[doc(inline)]
+pub use sockets::{
+    listening_sockets, read_proc_net_tcp, read_proc_net_tcp6, read_proc_net_udp,
+    read_proc_net_udp6, ListeningSocket, ProcFsSocket,
+};
+
+pub mod process;
+#[doc(inline)]
+pub use process::{PidIo, PidMetrics, PidStatus};
 
 /// POSIX sysconf call
 pub fn sysconf(name: i32) -> i64 {
@@ -32,6 +63,7 @@ lazy_static! {
 }
 
 /// Selected contents of the `/proc/PID/stat` file.
+#[derive(Clone, Debug)]
 pub struct PidStat {
     pub pid: Pid,
     pub ppid: Pid,
@@ -185,7 +217,7 @@ pub fn read_proc_uptime_ticks() -> Result<(u64, u64), Error> {
     Ok((up as u64, idle as u64))
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize)]
 /// The CPU fields from `/proc/stat` with their native time value. Multiply
 /// with CLOCK_TICKS to get the real value.
 pub struct ProcFsStat {
@@ -221,6 +253,59 @@ pub struct ProcFsStat {
     pub cpu_count: u32,
     /// The percentage (0 - 1.0) of system wide iowait.
     pub iowait_percent: f64,
+    /// The times of the single CPUs, in the order listed in `/proc/stat`.
+    pub cpus: Vec<ProcFsCpuStat>,
+}
+
+impl ProcFsStat {
+    /// The system wide times as [ProcFsCpuStat] named `cpu`.
+    pub fn total_times(&self) -> ProcFsCpuStat {
+        ProcFsCpuStat {
+            name: "cpu".to_string(),
+            user: self.user,
+            nice: self.nice,
+            system: self.system,
+            idle: self.idle,
+            iowait: self.iowait,
+            irq: self.irq,
+            softirq: self.softirq,
+            steal: self.steal,
+            guest: self.guest,
+            guest_nice: self.guest_nice,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+/// The times of a single CPU from `/proc/stat` in jiffies, see [ProcFsStat] for the meaning of
+/// the fields.
+pub struct ProcFsCpuStat {
+    /// The name of the CPU, e.g. `cpu3`.
+    pub name: String,
+    pub user: u64,
+    pub nice: u64,
+    pub system: u64,
+    pub idle: u64,
+    pub iowait: u64,
+    pub irq: u64,
+    pub softirq: u64,
+    pub steal: u64,
+    pub guest: u64,
+    pub guest_nice: u64,
+}
+
+impl ProcFsCpuStat {
+    /// The sum of all times, guest times are already included in the user times.
+    pub fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+            + self.idle
+    }
 }
 
 lazy_static! {
@@ -270,7 +355,7 @@ pub fn read_proc_stat() -> Result<ProcFsStat, Error> {
             stat.iowait_percent = delta_iowait / delta_seconds;
         }
 
-        *prev_stat = ProcFsStat { ..stat };
+        *prev_stat = stat.clone();
         *prev_time = sample_time;
         *first_time = false;
     }
@@ -279,14 +364,20 @@ pub fn read_proc_stat() -> Result<ProcFsStat, Error> {
 }
 
 fn parse_proc_stat(statstr: &str) -> Result<ProcFsStat, Error> {
-    let mut cpu_count = 0u32;
+    let mut cpus = Vec::new();
     let mut data = None;
     for line in statstr.lines() {
         let mut parts = line.trim_start().split_ascii_whitespace();
         match parts.next() {
             None => continue,
             Some("cpu") => data = Some(parse_proc_stat_cpu_line(parts)?),
-            Some(key) if key.starts_with("cpu") => cpu_count += 1,
+            Some(key) if key.starts_with("cpu") => {
+                let times = parse_proc_stat_cpu_line(parts)?.total_times();
+                cpus.push(ProcFsCpuStat {
+                    name: key.to_string(),
+                    ..times
+                });
+            }
             _ => (),
         }
     }
@@ -294,7 +385,8 @@ fn parse_proc_stat(statstr: &str) -> Result<ProcFsStat, Error> {
     match data {
         None => bail!("failed to find 'cpu' line in /proc/stat"),
         Some(mut data) => {
-            data.cpu_count = cpu_count;
+            data.cpu_count = cpus.len() as u32;
+            data.cpus = cpus;
             Ok(data)
         }
     }
@@ -333,6 +425,7 @@ fn parse_proc_stat_cpu_line<'a>(
         cpu: 0.0,
         cpu_count: 0,
         iowait_percent: 0.0,
+        cpus: Vec::new(),
     };
     stat.total = stat.user
         + stat.nice
@@ -408,6 +501,162 @@ fn test_read_proc_stat() {
     assert_eq!(stat.iowait_percent, 0.0);
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+/// The utilization of a CPU between two samples, as fractions (0 - 1.0) of the elapsed time.
+pub struct CpuUtilization {
+    /// The name of the CPU, e.g. `cpu3`, or `cpu` for the whole system.
+    pub name: String,
+    /// Time spent in user mode, including niced processes.
+    pub user: f64,
+    /// Time spent in system mode, including interrupts.
+    pub system: f64,
+    /// Time spent idle, excluding iowait.
+    pub idle: f64,
+    /// Time waiting for I/O to complete.
+    pub iowait: f64,
+    /// Time stolen by the hypervisor.
+    pub steal: f64,
+    /// The overall utilization, calculated like [ProcFsStat::cpu] as `1 - idle`.
+    pub usage: f64,
+}
+
+impl CpuUtilization {
+    fn between(current: &ProcFsCpuStat, previous: Option<&ProcFsCpuStat>) -> Self {
+        let zero = ProcFsCpuStat::default();
+        let previous = previous.unwrap_or(&zero);
+
+        let delta = |cur: u64, prev: u64| cur.saturating_sub(prev) as f64;
+        let total = delta(current.total(), previous.total());
+
+        if total == 0.0 {
+            return Self {
+                name: current.name.clone(),
+                ..Default::default()
+            };
+        }
+
+        let idle = delta(current.idle, previous.idle) / total;
+
+        Self {
+            name: current.name.clone(),
+            user: (delta(current.user, previous.user) + delta(current.nice, previous.nice)) / total,
+            system: (delta(current.system, previous.system)
+                + delta(current.irq, previous.irq)
+                + delta(current.softirq, previous.softirq))
+                / total,
+            idle,
+            iowait: delta(current.iowait, previous.iowait) / total,
+            steal: delta(current.steal, previous.steal) / total,
+            usage: 1.0 - idle,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+/// The CPU utilization between two samples of `/proc/stat`, see [CpuSampler].
+pub struct CpuUsage {
+    /// The utilization of the whole system.
+    pub total: CpuUtilization,
+    /// The utilization of the single CPUs, in the order listed in `/proc/stat`.
+    pub cpus: Vec<CpuUtilization>,
+}
+
+/// Computes the CPU utilization between successive reads of `/proc/stat`.
+///
+/// Unlike [read_proc_stat], which shares its last sample process wide, every sampler keeps its
+/// own last sample, so independent users do not influence each other's intervals.
+#[derive(Default)]
+pub struct CpuSampler {
+    last: Option<ProcFsStat>,
+}
+
+impl CpuSampler {
+    /// Create a new sampler without a previous sample.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read `/proc/stat` and return the utilization since the last call.
+    ///
+    /// The first call returns the utilization since boot.
+    pub fn sample(&mut self) -> Result<CpuUsage, Error> {
+        let content = std::fs::read_to_string("/proc/stat")?;
+        let stat = parse_proc_stat(&content)?;
+        Ok(self.update(stat))
+    }
+
+    /// Store `stat` as the last sample and return the utilization since the previous one.
+    ///
+    /// CPUs are matched by name. CPUs which were not part of the previous sample, e.g. because
+    /// they just came online, are reported with their utilization since boot.
+    pub fn update(&mut self, stat: ProcFsStat) -> CpuUsage {
+        let last = self.last.as_ref();
+
+        let total = CpuUtilization::between(
+            &stat.total_times(),
+            last.map(|last| last.total_times()).as_ref(),
+        );
+
+        let cpus = stat
+            .cpus
+            .iter()
+            .map(|cpu| {
+                let previous = last.and_then(|last| last.cpus.iter().find(|p| p.name == cpu.name));
+                CpuUtilization::between(cpu, previous)
+            })
+            .collect();
+
+        self.last = Some(stat);
+
+        CpuUsage { total, cpus }
+    }
+}
+
+#[test]
+fn test_cpu_sampler() {
+    let mut sampler = CpuSampler::new();
+
+    let first = parse_proc_stat(
+        "cpu  300 100 100 1400 50 0 50 0 0 0\n\
+         cpu0 200 100 50 600 25 0 25 0 0 0\n\
+         cpu1 100 0 50 800 25 0 25 0 0 0\n",
+    )
+    .expect("successful parsed a sample /proc/stat entry");
+
+    assert_eq!(first.cpu_count, 2);
+    assert_eq!(first.cpus[1].name, "cpu1");
+    assert_eq!(first.cpus[1].idle, 800);
+    assert_eq!(first.cpus[1].total(), 1000);
+
+    let usage = sampler.update(first);
+    assert_eq!(usage.total.idle, 0.7);
+    assert_eq!(usage.cpus[0].user, 0.3);
+
+    let second = parse_proc_stat(
+        "cpu  450 100 150 1600 100 0 50 50 0 0\n\
+         cpu0 300 100 75 650 50 0 25 0 0 0\n\
+         cpu1 150 0 75 950 50 0 25 50 0 0\n",
+    )
+    .expect("successful parsed a sample /proc/stat entry");
+
+    let usage = sampler.update(second);
+
+    assert_eq!(usage.total.name, "cpu");
+    assert_eq!(usage.total.user, 0.3);
+    assert_eq!(usage.total.system, 0.1);
+    assert_eq!(usage.total.idle, 0.4);
+    assert_eq!(usage.total.iowait, 0.1);
+    assert_eq!(usage.total.steal, 0.1);
+    assert_eq!(usage.total.usage, 0.6);
+
+    assert_eq!(usage.cpus.len(), 2);
+    assert_eq!(usage.cpus[0].user, 0.5);
+    assert_eq!(usage.cpus[0].idle, 0.25);
+    assert_eq!(usage.cpus[0].iowait, 0.125);
+    assert_eq!(usage.cpus[1].idle, 0.5);
+    assert_eq!(usage.cpus[1].steal, 50.0 / 300.0);
+}
+
 #[derive(Debug, Serialize)]
 pub struct ProcFsMemInfo {
     pub memtotal: u64,
@@ -550,6 +799,9 @@ pub struct ProcFsNetDev {
     pub send: u64,
 }
 
+/// Read the received and sent bytes of all network devices.
+///
+/// See [read_network_devices] for more detailed statistics and link information.
 pub fn read_proc_net_dev() -> Result<Vec<ProcFsNetDev>, Error> {
     let path = "/proc/net/dev";
     let file = OpenOptions::new().read(true).open(path)?;
@@ -83,6 +83,26 @@ impl Tag {
     }
 }
 
+/// The propagation settings of a mount, see `mount_namespaces(7)`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Propagation {
+    /// Peer group ID if the mount is shared.
+    pub shared: Option<u32>,
+    /// Peer group ID of the master if the mount is a slave.
+    pub master: Option<u32>,
+    /// Nearest dominant peer group of a slave mount, if it is not the master itself.
+    pub propagate_from: Option<u32>,
+    /// Whether the mount is unbindable.
+    pub unbindable: bool,
+}
+
+impl Propagation {
+    /// Whether the mount is private, i.e. neither shared nor a slave.
+    pub fn is_private(&self) -> bool {
+        self.shared.is_none() && self.master.is_none()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Entry {
     /// unique identifier of the mount (may be reused after being unmounted)
@@ -159,6 +179,39 @@ impl Entry {
 
         Ok(this)
     }
+
+    /// The propagation settings from the optional tags.
+    ///
+    /// Unknown tags and tags with invalid values are ignored.
+    pub fn propagation(&self) -> Propagation {
+        let mut propagation = Propagation::default();
+
+        for tag in self.tags.iter() {
+            let value = tag
+                .value
+                .as_ref()
+                .and_then(|value| value.to_str())
+                .and_then(|value| value.parse::<u32>().ok());
+
+            match tag.tag.to_str() {
+                Some("shared") => propagation.shared = value,
+                Some("master") => propagation.master = value,
+                Some("propagate_from") => propagation.propagate_from = value,
+                Some("unbindable") => propagation.unbindable = true,
+                _ => (),
+            }
+        }
+
+        propagation
+    }
+
+    /// The file system type without subtype, e.g. `fuse` for `fuse.sshfs`.
+    pub fn fs_main_type(&self) -> &str {
+        match self.fs_type.split_once('.') {
+            Some((main, _)) => main,
+            None => &self.fs_type,
+        }
+    }
 }
 
 // TODO: Add some structure to this? Eg. sort by parent/child relation? Make a tree?
@@ -238,6 +291,11 @@ impl MountInfo {
     }
 }
 
+/// Read the mount point information of the current process, see [MountInfo::read].
+pub fn read_mountinfo() -> Result<MountInfo, Error> {
+    MountInfo::read()
+}
+
 impl IntoIterator for MountInfo {
     type Item = (MountId, Entry);
     type IntoIter = std::collections::btree_map::IntoIter<MountId, Entry>;
@@ -374,6 +432,26 @@ fn test_entry() {
         ]
     );
 
+    let propagation = entry.propagation();
+    assert_eq!(
+        propagation,
+        Propagation {
+            shared: Some(5),
+            master: Some(7),
+            propagate_from: Some(2),
+            unbindable: true,
+        }
+    );
+    assert!(!propagation.is_private());
+    assert!(Entry::parse(l3).unwrap().propagation().is_private());
+
+    let l5: &[u8] = b"60 28 0:50 / /mnt/remote rw,nosuid,nodev,relatime master:3 - fuse.sshfs \
+          user@host:/srv rw,user_id=0,group_id=0";
+    let entry = Entry::parse(l5).expect("failed to parse fifth mountinfo test entry");
+    assert_eq!(entry.fs_type, "fuse.sshfs");
+    assert_eq!(entry.fs_main_type(), "fuse");
+    assert_eq!(entry.propagation().master, Some(3));
+
     let mount_info = [l1, l2].join(&b"\n"[..]);
     MountInfo::parse(&mount_info).expect("failed to parse mount info file");
 }
@@ -0,0 +1,132 @@
+//! `/proc/mounts` handling.
+
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+
+use anyhow::{bail, format_err, Error};
+
+/// An entry of `/proc/mounts`, in `fstab(5)` format.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MountEntry {
+    /// The mounted device or other source, e.g. `/dev/sda1` or `tmpfs`.
+    pub source: OsString,
+    /// The mount point.
+    pub mount_point: PathBuf,
+    /// The file system type.
+    pub fs_type: String,
+    /// The mount options.
+    pub options: OsString,
+}
+
+impl MountEntry {
+    /// Parse a line of `/proc/mounts`.
+    pub fn parse(line: &[u8]) -> Result<Self, Error> {
+        let mut parts = line
+            .split(u8::is_ascii_whitespace)
+            .filter(|part| !part.is_empty());
+
+        let mut next = |what: &'static str| {
+            parts
+                .next()
+                .map(unescape)
+                .ok_or_else(|| format_err!("missing {} in mounts line", what))
+        };
+
+        Ok(Self {
+            source: OsString::from_vec(next("source")??),
+            mount_point: OsString::from_vec(next("mount point")??).into(),
+            fs_type: String::from_utf8(next("file system type")??)
+                .map_err(|err| format_err!("invalid file system type - {}", err))?,
+            options: OsString::from_vec(next("options")??),
+        })
+    }
+
+    /// Check whether a mount option is set, e.g. `ro`.
+    pub fn has_option(&self, option: &str) -> bool {
+        let option = option.as_bytes();
+        self.options.as_bytes().split(|b| *b == b',').any(|opt| {
+            opt == option
+                || opt.iter().position(|b| *b == b'=').map(|pos| &opt[..pos]) == Some(option)
+        })
+    }
+}
+
+/// Decode the octal escapes the kernel uses for whitespace and backslashes, e.g. `\040`.
+fn unescape(value: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut result = Vec::with_capacity(value.len());
+    let mut pos = 0;
+
+    while pos < value.len() {
+        if value[pos] == b'\\' {
+            let code = value
+                .get(pos + 1..pos + 4)
+                .and_then(|code| std::str::from_utf8(code).ok())
+                .and_then(|code| u8::from_str_radix(code, 8).ok());
+
+            match code {
+                Some(code) => {
+                    result.push(code);
+                    pos += 4;
+                    continue;
+                }
+                None => bail!("invalid escape sequence in {:?}", OsStr::from_bytes(value)),
+            }
+        }
+
+        result.push(value[pos]);
+        pos += 1;
+    }
+
+    Ok(result)
+}
+
+/// Parse the contents of `/proc/mounts`.
+pub fn parse_mounts(content: &[u8]) -> Result<Vec<MountEntry>, Error> {
+    content
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|line| {
+            MountEntry::parse(line)
+                .map_err(|err| format_err!("{} in line {:?}", err, OsStr::from_bytes(line)))
+        })
+        .collect()
+}
+
+/// Read and parse `/proc/mounts`.
+///
+/// See [read_mountinfo](super::mountinfo::read_mountinfo) for more details like the mount
+/// propagation settings.
+pub fn read_proc_mounts() -> Result<Vec<MountEntry>, Error> {
+    let path = "/proc/mounts";
+    let content = std::fs::read(path)?;
+    parse_mounts(&content).map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+}
+
+#[test]
+fn test_parse_mounts() {
+    let mounts = parse_mounts(
+        b"/dev/mapper/pve-root / ext4 rw,relatime,errors=remount-ro 0 0\n\
+         tmpfs /run tmpfs rw,nosuid,nodev,noexec,relatime,size=1638400k,mode=755 0 0\n\
+         //server/share /mnt/my\\040share cifs ro,vers=3.0 0 0\n\
+         /dev/sdb1 /mnt/caf\xe9 ext4 rw 0 0\n",
+    )
+    .expect("failed to parse /proc/mounts sample");
+
+    assert_eq!(mounts.len(), 4);
+    assert_eq!(mounts[0].source, "/dev/mapper/pve-root");
+    assert_eq!(mounts[0].mount_point, PathBuf::from("/"));
+    assert_eq!(mounts[0].fs_type, "ext4");
+    assert!(mounts[0].has_option("errors"));
+    assert!(!mounts[0].has_option("ro"));
+    assert!(mounts[1].has_option("size"));
+    assert_eq!(mounts[2].mount_point, PathBuf::from("/mnt/my share"));
+    assert!(mounts[2].has_option("ro"));
+    assert_eq!(
+        mounts[3].mount_point.as_os_str().as_bytes(),
+        b"/mnt/caf\xe9"
+    );
+
+    assert!(parse_mounts(b"tmpfs /run\n").is_err());
+    assert!(parse_mounts(b"tmpfs /run\\04 tmpfs rw 0 0\n").is_err());
+}
@@ -0,0 +1,330 @@
+//! Network device statistics from `/proc/net/dev`, combined with link information from
+//! `/sys/class/net/`.
+
+use std::path::Path;
+
+use anyhow::{bail, format_err, Error};
+use serde::Serialize;
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// The kind of a network device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkDeviceType {
+    /// A device backed by hardware, e.g. a PCI network card.
+    Physical,
+    /// A Linux bridge.
+    Bridge,
+    /// A bond.
+    Bond,
+    /// A VLAN device.
+    Vlan,
+    /// The loopback device.
+    Loopback,
+    /// Any other virtual device, e.g. a `tap` or `veth` device.
+    Virtual,
+}
+
+/// The duplex mode of a network link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Duplex {
+    /// Full duplex.
+    Full,
+    /// Half duplex.
+    Half,
+}
+
+/// The statistics and link information of a network device.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct NetworkDevice {
+    /// The interface name.
+    pub name: String,
+    /// The kind of device.
+    pub device_type: NetworkDeviceType,
+    /// Received bytes.
+    pub rx_bytes: u64,
+    /// Received packets.
+    pub rx_packets: u64,
+    /// Receive errors.
+    pub rx_errors: u64,
+    /// Dropped received packets.
+    pub rx_dropped: u64,
+    /// Sent bytes.
+    pub tx_bytes: u64,
+    /// Sent packets.
+    pub tx_packets: u64,
+    /// Send errors.
+    pub tx_errors: u64,
+    /// Dropped packets to send.
+    pub tx_dropped: u64,
+    /// Link speed in Mbit/s, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<u64>,
+    /// Duplex mode, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplex: Option<Duplex>,
+    /// Whether the link is up, `None` if the interface is administratively down.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub carrier: Option<bool>,
+    /// Hardware address.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Maximum transmission unit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mtu: Option<u32>,
+}
+
+/// The counters of a single line of `/proc/net/dev`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct NetDevCounters {
+    rx_bytes: u64,
+    rx_packets: u64,
+    rx_errors: u64,
+    rx_dropped: u64,
+    tx_bytes: u64,
+    tx_packets: u64,
+    tx_errors: u64,
+    tx_dropped: u64,
+}
+
+fn parse_net_dev(content: &str) -> Result<Vec<(String, NetDevCounters)>, Error> {
+    let mut result = Vec::new();
+
+    for line in content.lines().skip(2) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (name, values) = line
+            .split_once(':')
+            .ok_or_else(|| format_err!("missing ':' in line '{}'", line.trim()))?;
+
+        let values = values
+            .split_ascii_whitespace()
+            .map(|value| value.parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|err| format_err!("invalid value in line '{}' - {}", line.trim(), err))?;
+
+        if values.len() < 12 {
+            bail!("missing values in line '{}'", line.trim());
+        }
+
+        result.push((
+            name.trim().to_string(),
+            NetDevCounters {
+                rx_bytes: values[0],
+                rx_packets: values[1],
+                rx_errors: values[2],
+                rx_dropped: values[3],
+                tx_bytes: values[8],
+                tx_packets: values[9],
+                tx_errors: values[10],
+                tx_dropped: values[11],
+            },
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Read a sysfs attribute, `None` if it does not exist or cannot be read in the current state
+/// of the device (e.g. `speed` of a link that is down).
+fn read_attribute(dir: &Path, name: &str) -> Option<String> {
+    std::fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+fn device_type(dir: &Path, name: &str) -> NetworkDeviceType {
+    let uevent = read_attribute(dir, "uevent").unwrap_or_default();
+    let devtype = uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("DEVTYPE="))
+        .unwrap_or("");
+
+    match devtype {
+        "bridge" => return NetworkDeviceType::Bridge,
+        "bond" => return NetworkDeviceType::Bond,
+        "vlan" => return NetworkDeviceType::Vlan,
+        _ => (),
+    }
+
+    if dir.join("bridge").is_dir() {
+        NetworkDeviceType::Bridge
+    } else if dir.join("bonding").is_dir() {
+        NetworkDeviceType::Bond
+    } else if dir.join("device").exists() {
+        NetworkDeviceType::Physical
+    } else if name == "lo" || read_attribute(dir, "type").as_deref() == Some("772") {
+        // ARPHRD_LOOPBACK
+        NetworkDeviceType::Loopback
+    } else {
+        NetworkDeviceType::Virtual
+    }
+}
+
+fn network_device(sysfs: &Path, name: String, counters: NetDevCounters) -> NetworkDevice {
+    let dir = sysfs.join(&name);
+
+    let speed = read_attribute(&dir, "speed")
+        .and_then(|speed| speed.parse::<i64>().ok())
+        .filter(|speed| *speed > 0)
+        .map(|speed| speed as u64);
+
+    let duplex = match read_attribute(&dir, "duplex").as_deref() {
+        Some("full") => Some(Duplex::Full),
+        Some("half") => Some(Duplex::Half),
+        _ => None,
+    };
+
+    let carrier = match read_attribute(&dir, "carrier").as_deref() {
+        Some("1") => Some(true),
+        Some("0") => Some(false),
+        _ => None,
+    };
+
+    NetworkDevice {
+        device_type: device_type(&dir, &name),
+        name,
+        rx_bytes: counters.rx_bytes,
+        rx_packets: counters.rx_packets,
+        rx_errors: counters.rx_errors,
+        rx_dropped: counters.rx_dropped,
+        tx_bytes: counters.tx_bytes,
+        tx_packets: counters.tx_packets,
+        tx_errors: counters.tx_errors,
+        tx_dropped: counters.tx_dropped,
+        speed,
+        duplex,
+        carrier,
+        address: read_attribute(&dir, "address").filter(|address| !address.is_empty()),
+        mtu: read_attribute(&dir, "mtu").and_then(|mtu| mtu.parse().ok()),
+    }
+}
+
+fn read_network_devices_from(content: &str, sysfs: &Path) -> Result<Vec<NetworkDevice>, Error> {
+    Ok(parse_net_dev(content)?
+        .into_iter()
+        .map(|(name, counters)| network_device(sysfs, name, counters))
+        .collect())
+}
+
+/// Read the statistics of all network devices from `/proc/net/dev` together with their type
+/// and link information from `/sys/class/net/<dev>/`.
+pub fn read_network_devices() -> Result<Vec<NetworkDevice>, Error> {
+    let path = "/proc/net/dev";
+    let content = std::fs::read_to_string(path)?;
+    read_network_devices_from(&content, Path::new(SYS_CLASS_NET))
+        .map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+}
+
+/// Read the statistics and link information of a single network device.
+pub fn read_network_device(name: &str) -> Result<NetworkDevice, Error> {
+    read_network_devices()?
+        .into_iter()
+        .find(|device| device.name == name)
+        .ok_or_else(|| format_err!("no such network device '{}'", name))
+}
+
+#[test]
+fn test_read_network_devices() -> Result<(), Error> {
+    let sysfs = std::env::temp_dir().join(format!("proxmox-sys-netdev-{}", std::process::id()));
+
+    let create = |dev: &str, attributes: &[(&str, &str)], dirs: &[&str]| -> Result<(), Error> {
+        let dir = sysfs.join(dev);
+        std::fs::create_dir_all(&dir)?;
+        for (name, value) in attributes {
+            std::fs::write(dir.join(name), format!("{value}\n"))?;
+        }
+        for name in dirs {
+            std::fs::create_dir_all(dir.join(name))?;
+        }
+        Ok(())
+    };
+
+    create(
+        "lo",
+        &[("type", "772"), ("mtu", "65536"), ("carrier", "1")],
+        &[],
+    )?;
+    create(
+        "eno1",
+        &[
+            ("speed", "1000"),
+            ("duplex", "full"),
+            ("carrier", "1"),
+            ("address", "aa:bb:cc:dd:ee:ff"),
+            ("mtu", "1500"),
+        ],
+        &["device"],
+    )?;
+    create("eno2", &[("speed", "-1"), ("carrier", "0")], &["device"])?;
+    create(
+        "vmbr0",
+        &[("uevent", "DEVTYPE=bridge\nINTERFACE=vmbr0")],
+        &[],
+    )?;
+    create("bond0", &[], &["bonding"])?;
+    create("vmbr0.10", &[("uevent", "DEVTYPE=vlan")], &[])?;
+    create("tap100i0", &[], &[])?;
+
+    let content = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 1000      10    0    0    0     0          0         0     1000      10    0    0    0     0       0          0
+  eno1: 123456789 1000  1    2    0     0          0        15 987654321   2000    3    4    0     0       0          0
+  eno2:       0       0    0    0    0     0          0         0        0       0    0    0    0     0       0          0
+ vmbr0: 5 1 0 0 0 0 0 0 6 1 0 0 0 0 0 0
+ bond0: 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+vmbr0.10: 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+tap100i0: 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+";
+
+    let result = read_network_devices_from(content, &sysfs);
+    let _ = std::fs::remove_dir_all(&sysfs);
+    let devices = result?;
+
+    let types: Vec<(&str, NetworkDeviceType)> = devices
+        .iter()
+        .map(|device| (device.name.as_str(), device.device_type))
+        .collect();
+    assert_eq!(
+        types,
+        [
+            ("lo", NetworkDeviceType::Loopback),
+            ("eno1", NetworkDeviceType::Physical),
+            ("eno2", NetworkDeviceType::Physical),
+            ("vmbr0", NetworkDeviceType::Bridge),
+            ("bond0", NetworkDeviceType::Bond),
+            ("vmbr0.10", NetworkDeviceType::Vlan),
+            ("tap100i0", NetworkDeviceType::Virtual),
+        ]
+    );
+
+    let eno1 = &devices[1];
+    assert_eq!(eno1.rx_bytes, 123456789);
+    assert_eq!(eno1.rx_packets, 1000);
+    assert_eq!(eno1.rx_errors, 1);
+    assert_eq!(eno1.rx_dropped, 2);
+    assert_eq!(eno1.tx_bytes, 987654321);
+    assert_eq!(eno1.tx_packets, 2000);
+    assert_eq!(eno1.tx_errors, 3);
+    assert_eq!(eno1.tx_dropped, 4);
+    assert_eq!(eno1.speed, Some(1000));
+    assert_eq!(eno1.duplex, Some(Duplex::Full));
+    assert_eq!(eno1.carrier, Some(true));
+    assert_eq!(eno1.address.as_deref(), Some("aa:bb:cc:dd:ee:ff"));
+    assert_eq!(eno1.mtu, Some(1500));
+
+    let eno2 = &devices[2];
+    assert_eq!(eno2.speed, None);
+    assert_eq!(eno2.duplex, None);
+    assert_eq!(eno2.carrier, Some(false));
+
+    assert_eq!(devices[6].carrier, None);
+    assert_eq!(devices[6].address, None);
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! Pressure stall information (PSI) from `/proc/pressure/`.
+//!
+//! The same format is used for the `*.pressure` files of cgroups, which can be parsed with
+//! [Pressure::parse].
+
+use anyhow::{bail, format_err, Error};
+use serde::Serialize;
+
+/// The averages and total stall time of one line of a pressure file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PressureRecord {
+    /// Share of time in percent tasks were stalled, over the last 10 seconds.
+    pub avg10: f64,
+    /// Share of time in percent tasks were stalled, over the last 60 seconds.
+    pub avg60: f64,
+    /// Share of time in percent tasks were stalled, over the last 300 seconds.
+    pub avg300: f64,
+    /// Total stall time in microseconds.
+    pub total: u64,
+}
+
+impl PressureRecord {
+    fn parse(fields: &str) -> Result<Self, Error> {
+        let (mut avg10, mut avg60, mut avg300, mut total) = (None, None, None, None);
+
+        for field in fields.split_ascii_whitespace() {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format_err!("invalid field '{}'", field))?;
+
+            let invalid = || format_err!("unable to parse value of '{}' - '{}'", key, value);
+
+            match key {
+                "avg10" => avg10 = Some(value.parse().map_err(|_| invalid())?),
+                "avg60" => avg60 = Some(value.parse().map_err(|_| invalid())?),
+                "avg300" => avg300 = Some(value.parse().map_err(|_| invalid())?),
+                "total" => total = Some(value.parse().map_err(|_| invalid())?),
+                _ => (), // ignore unknown fields
+            }
+        }
+
+        match (avg10, avg60, avg300, total) {
+            (Some(avg10), Some(avg60), Some(avg300), Some(total)) => Ok(Self {
+                avg10,
+                avg60,
+                avg300,
+                total,
+            }),
+            _ => bail!("missing field in '{}'", fields),
+        }
+    }
+}
+
+/// The contents of a pressure file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Pressure {
+    /// Stalls where at least some tasks were waiting for the resource.
+    pub some: PressureRecord,
+    /// Stalls where all non-idle tasks were waiting for the resource at the same time.
+    ///
+    /// Not available for CPU pressure on kernels before 5.13, where it is always `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full: Option<PressureRecord>,
+}
+
+impl Pressure {
+    /// Parse the contents of a pressure file.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut some = None;
+        let mut full = None;
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (kind, fields) = line
+                .split_once(' ')
+                .ok_or_else(|| format_err!("invalid line '{}'", line))?;
+
+            match kind {
+                "some" => some = Some(PressureRecord::parse(fields)?),
+                "full" => full = Some(PressureRecord::parse(fields)?),
+                _ => bail!("unknown pressure kind '{}'", kind),
+            }
+        }
+
+        Ok(Self {
+            some: some.ok_or_else(|| format_err!("missing 'some' line"))?,
+            full,
+        })
+    }
+
+    fn read(path: &str) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+    }
+}
+
+/// The pressure stall information of the whole system.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PressureStats {
+    /// CPU pressure.
+    pub cpu: Pressure,
+    /// Memory pressure.
+    pub memory: Pressure,
+    /// I/O pressure.
+    pub io: Pressure,
+}
+
+/// Read and parse `/proc/pressure/cpu`.
+pub fn read_cpu_pressure() -> Result<Pressure, Error> {
+    Pressure::read("/proc/pressure/cpu")
+}
+
+/// Read and parse `/proc/pressure/memory`.
+pub fn read_memory_pressure() -> Result<Pressure, Error> {
+    Pressure::read("/proc/pressure/memory")
+}
+
+/// Read and parse `/proc/pressure/io`.
+pub fn read_io_pressure() -> Result<Pressure, Error> {
+    Pressure::read("/proc/pressure/io")
+}
+
+/// Read and parse the CPU, memory and I/O pressure files.
+///
+/// Fails if the kernel does not provide PSI, i.e. was built without `CONFIG_PSI` or booted with
+/// `psi=0`.
+pub fn read_proc_pressure() -> Result<PressureStats, Error> {
+    Ok(PressureStats {
+        cpu: read_cpu_pressure()?,
+        memory: read_memory_pressure()?,
+        io: read_io_pressure()?,
+    })
+}
+
+#[test]
+fn test_parse_pressure() {
+    let io = Pressure::parse(
+        "some avg10=1.53 avg60=0.87 avg300=0.25 total=123456789\n\
+         full avg10=0.10 avg60=0.00 avg300=0.01 total=4242\n",
+    )
+    .expect("failed to parse pressure sample");
+
+    assert_eq!(
+        io.some,
+        PressureRecord {
+            avg10: 1.53,
+            avg60: 0.87,
+            avg300: 0.25,
+            total: 123456789,
+        }
+    );
+    assert_eq!(io.full.as_ref().unwrap().avg10, 0.10);
+    assert_eq!(io.full.as_ref().unwrap().total, 4242);
+
+    // CPU pressure of older kernels
+    let cpu = Pressure::parse("some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n")
+        .expect("failed to parse pressure sample");
+    assert_eq!(cpu.some, PressureRecord::default());
+    assert_eq!(cpu.full, None);
+
+    assert!(Pressure::parse("full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").is_err());
+    assert!(Pressure::parse("some avg10=0.00 avg60=0.00 total=0\n").is_err());
+    assert!(Pressure::parse("some avg10=x avg60=0.00 avg300=0.00 total=0\n").is_err());
+}
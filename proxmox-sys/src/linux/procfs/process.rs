@@ -0,0 +1,227 @@
+//! Per-process I/O and scheduling statistics from `/proc/PID/io` and `/proc/PID/status`.
+
+use anyhow::{bail, format_err, Error};
+use nix::unistd::Pid;
+use serde::Serialize;
+
+use super::PidStat;
+
+/// Selected contents of the `/proc/PID/io` file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PidIo {
+    /// Bytes read via `read(2)` and similar, including those served from the page cache.
+    pub rchar: u64,
+    /// Bytes written via `write(2)` and similar.
+    pub wchar: u64,
+    /// Number of read syscalls.
+    pub syscr: u64,
+    /// Number of write syscalls.
+    pub syscw: u64,
+    /// Bytes actually fetched from the storage layer.
+    pub read_bytes: u64,
+    /// Bytes caused to be sent to the storage layer.
+    pub write_bytes: u64,
+    /// Written bytes that were never sent to storage, e.g. due to truncation.
+    pub cancelled_write_bytes: u64,
+}
+
+impl PidIo {
+    /// Retrieve the `io` file contents of a process.
+    ///
+    /// Only permitted for the process' own user (and root).
+    pub fn read_from_pid(pid: Pid) -> Result<Self, Error> {
+        let path = format!("/proc/{}/io", pid);
+        let content = std::fs::read_to_string(&path)?;
+        Self::parse(&content).map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+    }
+
+    /// Parse the contents of a `/proc/PID/io` file.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut io = PidIo::default();
+
+        for (key, value) in key_values(content)? {
+            let field = match key {
+                "rchar" => &mut io.rchar,
+                "wchar" => &mut io.wchar,
+                "syscr" => &mut io.syscr,
+                "syscw" => &mut io.syscw,
+                "read_bytes" => &mut io.read_bytes,
+                "write_bytes" => &mut io.write_bytes,
+                "cancelled_write_bytes" => &mut io.cancelled_write_bytes,
+                _ => continue,
+            };
+            *field = parse_num(key, value)?;
+        }
+
+        Ok(io)
+    }
+}
+
+/// Selected contents of the `/proc/PID/status` file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PidStatus {
+    /// Number of threads.
+    pub threads: u64,
+    /// Number of voluntary context switches, e.g. when waiting for I/O.
+    pub voluntary_ctxt_switches: u64,
+    /// Number of involuntary context switches, i.e. preemptions.
+    pub nonvoluntary_ctxt_switches: u64,
+    /// Resident set size in bytes.
+    pub vm_rss: u64,
+    /// Peak resident set size in bytes.
+    pub vm_hwm: u64,
+}
+
+impl PidStatus {
+    /// Retrieve the `status` file contents of a process.
+    pub fn read_from_pid(pid: Pid) -> Result<Self, Error> {
+        let path = format!("/proc/{}/status", pid);
+        let content = std::fs::read_to_string(&path)?;
+        Self::parse(&content).map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+    }
+
+    /// Parse the contents of a `/proc/PID/status` file.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut status = PidStatus::default();
+        let mut found_threads = false;
+
+        for (key, value) in key_values(content)? {
+            match key {
+                "Threads" => {
+                    status.threads = parse_num(key, value)?;
+                    found_threads = true;
+                }
+                "voluntary_ctxt_switches" => {
+                    status.voluntary_ctxt_switches = parse_num(key, value)?
+                }
+                "nonvoluntary_ctxt_switches" => {
+                    status.nonvoluntary_ctxt_switches = parse_num(key, value)?
+                }
+                // memory values are not available for kernel threads
+                "VmRSS" => status.vm_rss = parse_kb(key, value)?,
+                "VmHWM" => status.vm_hwm = parse_kb(key, value)?,
+                _ => (),
+            }
+        }
+
+        if !found_threads {
+            bail!("missing 'Threads'");
+        }
+
+        Ok(status)
+    }
+}
+
+/// Split `key: value` lines.
+fn key_values(content: &str) -> Result<Vec<(&str, &str)>, Error> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.split_once(':')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| format_err!("missing ':' in line '{}'", line))
+        })
+        .collect()
+}
+
+fn parse_num(key: &str, value: &str) -> Result<u64, Error> {
+    value
+        .parse()
+        .map_err(|err| format_err!("invalid value for '{}' - {}", key, err))
+}
+
+fn parse_kb(key: &str, value: &str) -> Result<u64, Error> {
+    match value.strip_suffix(" kB") {
+        Some(value) => Ok(parse_num(key, value.trim())? * 1024),
+        None => bail!("invalid value for '{}' - missing unit", key),
+    }
+}
+
+/// Combined statistics of a process, e.g. for a worker task monitoring itself.
+#[derive(Clone, Debug)]
+pub struct PidMetrics {
+    /// The contents of `/proc/PID/stat`.
+    pub stat: PidStat,
+    /// The contents of `/proc/PID/io`, `None` if reading it is not permitted or the kernel was
+    /// built without I/O accounting.
+    pub io: Option<PidIo>,
+    /// The contents of `/proc/PID/status`.
+    pub status: PidStatus,
+}
+
+impl PidMetrics {
+    /// Read the statistics of a process.
+    pub fn read_from_pid(pid: Pid) -> Result<Self, Error> {
+        let io = match PidIo::read_from_pid(pid) {
+            Ok(io) => Some(io),
+            Err(err) => match err.downcast_ref::<std::io::Error>() {
+                Some(io_err)
+                    if matches!(
+                        io_err.kind(),
+                        std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::NotFound
+                    ) =>
+                {
+                    None
+                }
+                _ => return Err(err),
+            },
+        };
+
+        Ok(Self {
+            stat: PidStat::read_from_pid(pid)?,
+            io,
+            status: PidStatus::read_from_pid(pid)?,
+        })
+    }
+
+    /// Read the statistics of the current process.
+    pub fn read_self() -> Result<Self, Error> {
+        Self::read_from_pid(nix::unistd::getpid())
+    }
+}
+
+#[test]
+fn test_parse_pid_io_and_status() {
+    let io = PidIo::parse(
+        "rchar: 3980\n\
+         wchar: 1234\n\
+         syscr: 9\n\
+         syscw: 2\n\
+         read_bytes: 4096\n\
+         write_bytes: 8192\n\
+         cancelled_write_bytes: 0\n",
+    )
+    .expect("failed to parse /proc/PID/io sample");
+
+    assert_eq!(io.rchar, 3980);
+    assert_eq!(io.wchar, 1234);
+    assert_eq!(io.syscr, 9);
+    assert_eq!(io.syscw, 2);
+    assert_eq!(io.read_bytes, 4096);
+    assert_eq!(io.write_bytes, 8192);
+
+    let status = PidStatus::parse(
+        "Name:\tproxmox-backup-proxy\n\
+         State:\tS (sleeping)\n\
+         VmHWM:\t  204800 kB\n\
+         VmRSS:\t  102400 kB\n\
+         Threads:\t24\n\
+         voluntary_ctxt_switches:\t1522\n\
+         nonvoluntary_ctxt_switches:\t37\n",
+    )
+    .expect("failed to parse /proc/PID/status sample");
+
+    assert_eq!(status.threads, 24);
+    assert_eq!(status.voluntary_ctxt_switches, 1522);
+    assert_eq!(status.nonvoluntary_ctxt_switches, 37);
+    assert_eq!(status.vm_rss, 102400 * 1024);
+    assert_eq!(status.vm_hwm, 204800 * 1024);
+
+    assert!(PidStatus::parse("Name:\tkworker\n").is_err());
+
+    let metrics = PidMetrics::read_self().expect("failed to read own process metrics");
+    assert_eq!(metrics.stat.pid, nix::unistd::getpid());
+    // `io` depends on the kernel configuration and permissions, so it is not checked here
+    assert!(metrics.status.threads >= 1);
+}
@@ -0,0 +1,345 @@
+//! TCP and UDP socket tables from `/proc/net/{tcp,tcp6,udp,udp6}` and the processes owning
+//! the sockets.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+use anyhow::{bail, format_err, Error};
+use serde::Serialize;
+
+use super::hexstr_to_ipv4addr;
+
+/// The protocol of a socket table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SocketProtocol {
+    /// TCP, from `/proc/net/tcp` and `/proc/net/tcp6`.
+    Tcp,
+    /// UDP, from `/proc/net/udp` and `/proc/net/udp6`.
+    Udp,
+}
+
+/// The state of a socket, as used by the kernel for both TCP and UDP sockets, see
+/// `include/net/tcp_states.h`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SocketState {
+    Established,
+    SynSent,
+    SynRecv,
+    FinWait1,
+    FinWait2,
+    TimeWait,
+    Close,
+    CloseWait,
+    LastAck,
+    Listen,
+    Closing,
+    NewSynRecv,
+}
+
+impl TryFrom<u8> for SocketState {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Error> {
+        Ok(match value {
+            0x01 => SocketState::Established,
+            0x02 => SocketState::SynSent,
+            0x03 => SocketState::SynRecv,
+            0x04 => SocketState::FinWait1,
+            0x05 => SocketState::FinWait2,
+            0x06 => SocketState::TimeWait,
+            0x07 => SocketState::Close,
+            0x08 => SocketState::CloseWait,
+            0x09 => SocketState::LastAck,
+            0x0A => SocketState::Listen,
+            0x0B => SocketState::Closing,
+            0x0C => SocketState::NewSynRecv,
+            _ => bail!("unknown socket state {:#04x}", value),
+        })
+    }
+}
+
+/// An entry of a socket table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProcFsSocket {
+    /// The protocol of the socket.
+    pub protocol: SocketProtocol,
+    /// The local address and port.
+    pub local: SocketAddr,
+    /// The remote address and port, unspecified for listening sockets.
+    pub remote: SocketAddr,
+    /// The socket state.
+    pub state: SocketState,
+    /// The user ID of the socket's owner.
+    pub uid: u32,
+    /// The inode of the socket, 0 for sockets without an owning process (e.g. in `TIME_WAIT`).
+    pub inode: u64,
+}
+
+impl ProcFsSocket {
+    /// Whether the socket accepts connections (TCP) or datagrams from anyone (UDP).
+    pub fn is_listening(&self) -> bool {
+        match self.protocol {
+            SocketProtocol::Tcp => self.state == SocketState::Listen,
+            SocketProtocol::Udp => {
+                self.state == SocketState::Close && self.remote.ip().is_unspecified()
+            }
+        }
+    }
+}
+
+/// Parse an address of the form `0100007F:0016`, the IP address being in the kernel's byte
+/// order.
+fn parse_socket_addr(value: &str) -> Result<SocketAddr, Error> {
+    let (addr, port) = value
+        .split_once(':')
+        .ok_or_else(|| format_err!("invalid socket address '{}'", value))?;
+
+    let port = u16::from_str_radix(port, 16)
+        .map_err(|err| format_err!("invalid port in '{}' - {}", value, err))?;
+
+    let addr = match addr.len() {
+        8 => IpAddr::V4(hexstr_to_ipv4addr(addr)?),
+        32 => {
+            let mut octets = [0u8; 16];
+            for (chunk, group) in octets.chunks_mut(4).zip(addr.as_bytes().chunks(8)) {
+                let group = std::str::from_utf8(group)?;
+                let word = u32::from_str_radix(group, 16)
+                    .map_err(|err| format_err!("invalid address in '{}' - {}", value, err))?;
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => bail!("invalid address length in '{}'", value),
+    };
+
+    Ok(SocketAddr::new(addr, port))
+}
+
+/// Parse the contents of `/proc/net/{tcp,tcp6,udp,udp6}`.
+pub fn parse_socket_table(
+    content: &str,
+    protocol: SocketProtocol,
+) -> Result<Vec<ProcFsSocket>, Error> {
+    let mut result = Vec::new();
+
+    for line in content.lines().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+        if parts.len() < 10 {
+            bail!("missing fields in line '{}'", line.trim());
+        }
+
+        let state = u8::from_str_radix(parts[3], 16)
+            .map_err(|err| format_err!("invalid state '{}' - {}", parts[3], err))?;
+
+        result.push(ProcFsSocket {
+            protocol,
+            local: parse_socket_addr(parts[1])?,
+            remote: parse_socket_addr(parts[2])?,
+            state: SocketState::try_from(state)?,
+            uid: parts[7]
+                .parse()
+                .map_err(|err| format_err!("invalid uid '{}' - {}", parts[7], err))?,
+            inode: parts[9]
+                .parse()
+                .map_err(|err| format_err!("invalid inode '{}' - {}", parts[9], err))?,
+        });
+    }
+
+    Ok(result)
+}
+
+fn read_socket_table(path: &str, protocol: SocketProtocol) -> Result<Vec<ProcFsSocket>, Error> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        // e.g. IPv6 disabled
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => bail!("unable to read '{path}' - {err}"),
+    };
+    parse_socket_table(&content, protocol)
+        .map_err(|err| format_err!("Error while parsing '{path}' - {err}"))
+}
+
+/// Read the IPv4 TCP sockets from `/proc/net/tcp`.
+pub fn read_proc_net_tcp() -> Result<Vec<ProcFsSocket>, Error> {
+    read_socket_table("/proc/net/tcp", SocketProtocol::Tcp)
+}
+
+/// Read the IPv6 TCP sockets from `/proc/net/tcp6`.
+pub fn read_proc_net_tcp6() -> Result<Vec<ProcFsSocket>, Error> {
+    read_socket_table("/proc/net/tcp6", SocketProtocol::Tcp)
+}
+
+/// Read the IPv4 UDP sockets from `/proc/net/udp`.
+pub fn read_proc_net_udp() -> Result<Vec<ProcFsSocket>, Error> {
+    read_socket_table("/proc/net/udp", SocketProtocol::Udp)
+}
+
+/// Read the IPv6 UDP sockets from `/proc/net/udp6`.
+pub fn read_proc_net_udp6() -> Result<Vec<ProcFsSocket>, Error> {
+    read_socket_table("/proc/net/udp6", SocketProtocol::Udp)
+}
+
+/// A process holding a socket.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SocketProcess {
+    /// The process ID.
+    pub pid: i32,
+    /// The command name from `/proc/<pid>/comm`.
+    pub name: String,
+}
+
+/// Map socket inodes to the processes holding them, by scanning `/proc/<pid>/fd/`.
+///
+/// Processes whose file descriptors cannot be read, e.g. those of other users when not running
+/// as root, are skipped.
+pub fn socket_inode_owners() -> Result<HashMap<u64, Vec<SocketProcess>>, Error> {
+    let mut result: HashMap<u64, Vec<SocketProcess>> = HashMap::new();
+
+    for entry in std::fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid: i32 = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        let fds = match std::fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue, // gone or not permitted
+        };
+
+        let mut name = None;
+
+        for fd in fds.filter_map(Result::ok) {
+            let target = match std::fs::read_link(fd.path()) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+
+            let inode = match target
+                .to_str()
+                .and_then(|target| target.strip_prefix("socket:["))
+                .and_then(|target| target.strip_suffix(']'))
+                .and_then(|inode| inode.parse::<u64>().ok())
+            {
+                Some(inode) => inode,
+                None => continue,
+            };
+
+            let name = name.get_or_insert_with(|| {
+                std::fs::read_to_string(entry.path().join("comm"))
+                    .map(|comm| comm.trim_end().to_string())
+                    .unwrap_or_default()
+            });
+
+            let owners = result.entry(inode).or_default();
+            if !owners.iter().any(|owner| owner.pid == pid) {
+                owners.push(SocketProcess {
+                    pid,
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// A listening socket and the processes holding it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ListeningSocket {
+    /// The socket.
+    pub socket: ProcFsSocket,
+    /// The processes holding the socket, empty if they cannot be determined.
+    pub processes: Vec<SocketProcess>,
+}
+
+/// List all listening TCP and UDP sockets with their owning processes.
+pub fn listening_sockets() -> Result<Vec<ListeningSocket>, Error> {
+    let mut sockets = read_proc_net_tcp()?;
+    sockets.append(&mut read_proc_net_tcp6()?);
+    sockets.append(&mut read_proc_net_udp()?);
+    sockets.append(&mut read_proc_net_udp6()?);
+
+    let mut owners = socket_inode_owners()?;
+
+    Ok(sockets
+        .into_iter()
+        .filter(ProcFsSocket::is_listening)
+        .map(|socket| ListeningSocket {
+            processes: owners.remove(&socket.inode).unwrap_or_default(),
+            socket,
+        })
+        .collect())
+}
+
+/// Find the listening sockets bound to `port`, e.g. to check for conflicts before starting a
+/// service.
+pub fn listening_sockets_on_port(
+    protocol: SocketProtocol,
+    port: u16,
+) -> Result<Vec<ListeningSocket>, Error> {
+    Ok(listening_sockets()?
+        .into_iter()
+        .filter(|entry| entry.socket.protocol == protocol && entry.socket.local.port() == port)
+        .collect())
+}
+
+#[test]
+fn test_parse_socket_table() {
+    let tcp = parse_socket_table(
+        "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   \
+         0: 0100007F:0019 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 25187 1 0000000000000000 100 0 0 10 0\n   \
+         1: 0A00A8C0:0016 6400A8C0:D5B2 01 00000000:00000000 02:00001302 00000000     0        0 38473 4 0000000000000000 20 4 0 19 -1\n",
+        SocketProtocol::Tcp,
+    )
+    .expect("failed to parse /proc/net/tcp sample");
+
+    assert_eq!(tcp.len(), 2);
+    assert_eq!(tcp[0].local, "127.0.0.1:25".parse().unwrap());
+    assert_eq!(tcp[0].remote, "0.0.0.0:0".parse().unwrap());
+    assert_eq!(tcp[0].state, SocketState::Listen);
+    assert_eq!(tcp[0].inode, 25187);
+    assert!(tcp[0].is_listening());
+    assert_eq!(tcp[1].local, "192.168.0.10:22".parse().unwrap());
+    assert_eq!(tcp[1].remote, "192.168.0.100:54706".parse().unwrap());
+    assert_eq!(tcp[1].state, SocketState::Established);
+    assert!(!tcp[1].is_listening());
+
+    let udp6 = parse_socket_table(
+        "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops\n  \
+         12: 00000000000000000000000001000000:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000   108        0 19822 2 0000000000000000 0\n",
+        SocketProtocol::Udp,
+    )
+    .expect("failed to parse /proc/net/udp6 sample");
+
+    assert_eq!(udp6[0].local, "[::1]:53".parse().unwrap());
+    assert_eq!(udp6[0].uid, 108);
+    assert!(udp6[0].is_listening());
+
+    assert!(parse_socket_table("header\n 0: 0100007F:0019\n", SocketProtocol::Tcp).is_err());
+}
+
+#[test]
+fn test_listening_socket_owner() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    let sockets = listening_sockets_on_port(SocketProtocol::Tcp, port)?;
+    assert_eq!(sockets.len(), 1);
+    assert!(sockets[0]
+        .processes
+        .iter()
+        .any(|process| process.pid == std::process::id() as i32));
+
+    Ok(())
+}
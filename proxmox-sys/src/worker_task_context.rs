@@ -29,6 +29,15 @@ pub trait WorkerTaskContext: Send + Sync {
 
     /// Create a log message for this task.
     fn log(&self, level: log::Level, message: &std::fmt::Arguments);
+
+    /// Attach a small result artifact (e.g. a JSON blob or a file reference) named `name` to
+    /// this task, replacing any previous result with the same name.
+    ///
+    /// This gives callers a structured output channel besides the task log. The default
+    /// implementation does not support task results.
+    fn set_result(&self, name: &str, _value: serde_json::Value) -> Result<(), Error> {
+        bail!("unable to set task result '{}' - not supported", name);
+    }
 }
 
 /// Convenience implementation:
@@ -52,6 +61,10 @@ impl<T: WorkerTaskContext + ?Sized> WorkerTaskContext for std::sync::Arc<T> {
     fn log(&self, level: log::Level, message: &std::fmt::Arguments) {
         <T as WorkerTaskContext>::log(self, level, message)
     }
+
+    fn set_result(&self, name: &str, value: serde_json::Value) -> Result<(), Error> {
+        <T as WorkerTaskContext>::set_result(self, name, value)
+    }
 }
 
 /// Log an error to a [WorkerTaskContext]
@@ -1,7 +1,11 @@
+use anyhow::{bail, Error};
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::api;
-use proxmox_schema::api_types::TIME_ZONE_SCHEMA;
+use proxmox_schema::api_types::{DNS_NAME_OR_IP_FORMAT, TIME_ZONE_SCHEMA};
+use proxmox_schema::{ApiStringFormat, Schema, StringSchema};
+
+use proxmox_config_digest::ConfigDigest;
 
 #[api(
     properties: {
@@ -27,3 +31,122 @@ pub struct ServerTimeInfo {
     pub time: i64,
     pub localtime: i64,
 }
+
+pub const NTP_SOURCE_ADDRESS_SCHEMA: Schema =
+    StringSchema::new("Host name or IP address of the NTP server or pool.")
+        .format(&DNS_NAME_OR_IP_FORMAT)
+        .schema();
+
+/// Options have to be whitespace separated words like `iburst` or `maxpoll 10`, so that they
+/// can never add other directives to the configuration.
+fn verify_ntp_source_options(options: &str) -> Result<(), Error> {
+    let valid_word = |word: &str| {
+        word.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+    };
+
+    if options
+        .split(' ')
+        .all(|word| !word.is_empty() && valid_word(word))
+    {
+        Ok(())
+    } else {
+        bail!("options have to be space separated words of 'a-z', '0-9', '.', '_' and '-'");
+    }
+}
+
+pub const NTP_SOURCE_OPTIONS_FORMAT: ApiStringFormat =
+    ApiStringFormat::VerifyFn(verify_ntp_source_options);
+
+pub const NTP_SOURCE_OPTIONS_SCHEMA: Schema =
+    StringSchema::new("Additional options for the source, e.g. 'iburst maxpoll 10' (chrony only).")
+        .format(&NTP_SOURCE_OPTIONS_FORMAT)
+        .max_length(256)
+        .schema();
+
+#[api()]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// The service used for time synchronization.
+pub enum TimeSyncService {
+    /// chrony, configured in '/etc/chrony/chrony.conf'
+    Chrony,
+    /// systemd-timesyncd, configured in '/etc/systemd/timesyncd.conf'
+    Timesyncd,
+}
+
+#[api()]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Type of an NTP source.
+pub enum NtpSourceType {
+    #[default]
+    /// A single NTP server.
+    Server,
+    /// A pool of NTP servers, resolving to multiple addresses (chrony only).
+    Pool,
+}
+
+#[api(
+    properties: {
+        type: {
+            type: NtpSourceType,
+            optional: true,
+        },
+        address: {
+            schema: NTP_SOURCE_ADDRESS_SCHEMA,
+        },
+        options: {
+            schema: NTP_SOURCE_OPTIONS_SCHEMA,
+            optional: true,
+        },
+    }
+)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// An NTP server or pool.
+pub struct NtpSource {
+    #[serde(rename = "type", default)]
+    pub ty: NtpSourceType,
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
+}
+
+#[api(
+    properties: {
+        service: {
+            type: TimeSyncService,
+        },
+        sources: {
+            type: Array,
+            description: "The configured NTP servers and pools.",
+            items: {
+                type: NtpSource,
+            },
+        },
+    }
+)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// NTP configuration of the time synchronization service.
+pub struct TimeSyncConfig {
+    pub service: TimeSyncService,
+    pub sources: Vec<NtpSource>,
+}
+
+#[api(
+    properties: {
+        config: {
+            type: TimeSyncConfig,
+        },
+        digest: {
+            type: ConfigDigest,
+        },
+    }
+)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// NTP configuration with digest.
+pub struct TimeSyncConfigWithDigest {
+    #[serde(flatten)]
+    pub config: TimeSyncConfig,
+    pub digest: ConfigDigest,
+}
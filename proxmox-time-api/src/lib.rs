@@ -5,3 +5,8 @@ pub use api_types::*;
 mod time_impl;
 #[cfg(feature = "impl")]
 pub use time_impl::*;
+
+#[cfg(feature = "impl")]
+mod timesync;
+#[cfg(feature = "impl")]
+pub use timesync::*;
@@ -0,0 +1,393 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_config_digest::ConfigDigest;
+use proxmox_product_config::replace_system_config;
+use proxmox_sys::fs::file_read_optional_string;
+
+use super::{
+    NtpSource, NtpSourceType, TimeSyncConfig, TimeSyncConfigWithDigest, TimeSyncService,
+    NTP_SOURCE_ADDRESS_SCHEMA, NTP_SOURCE_OPTIONS_SCHEMA,
+};
+
+static CHRONY_CONF_FN: &str = "/etc/chrony/chrony.conf";
+static TIMESYNCD_CONF_FN: &str = "/etc/systemd/timesyncd.conf";
+
+impl TimeSyncService {
+    /// The path of the service's configuration file.
+    pub fn config_path(&self) -> &'static str {
+        match self {
+            Self::Chrony => CHRONY_CONF_FN,
+            Self::Timesyncd => TIMESYNCD_CONF_FN,
+        }
+    }
+
+    /// The name of the service's systemd unit.
+    pub fn unit_name(&self) -> &'static str {
+        match self {
+            Self::Chrony => "chrony.service",
+            Self::Timesyncd => "systemd-timesyncd.service",
+        }
+    }
+}
+
+/// Detect the time synchronization service in use.
+///
+/// Chrony is used if it is installed, systemd-timesyncd otherwise.
+pub fn detect_time_sync_service() -> TimeSyncService {
+    if Path::new(CHRONY_CONF_FN).exists() {
+        TimeSyncService::Chrony
+    } else {
+        TimeSyncService::Timesyncd
+    }
+}
+
+fn is_chrony_comment(line: &str) -> bool {
+    line.is_empty() || line.starts_with(['#', '!', ';', '%'])
+}
+
+/// Parse a `server` or `pool` line of a chrony configuration.
+fn parse_chrony_source(line: &str) -> Option<NtpSource> {
+    let line = line.trim();
+    if is_chrony_comment(line) {
+        return None;
+    }
+
+    let mut parts = line.split_whitespace();
+    let ty = match parts.next()? {
+        "server" => NtpSourceType::Server,
+        "pool" => NtpSourceType::Pool,
+        _ => return None,
+    };
+    let address = parts.next()?.to_string();
+    let options = parts.collect::<Vec<_>>().join(" ");
+
+    Some(NtpSource {
+        ty,
+        address,
+        options: (!options.is_empty()).then_some(options),
+    })
+}
+
+fn parse_chrony_config(data: &str) -> Vec<NtpSource> {
+    data.lines().filter_map(parse_chrony_source).collect()
+}
+
+/// Replace the `server` and `pool` lines of a chrony configuration, keeping everything else.
+///
+/// The new sources are placed where the first source was, or appended if there was none.
+fn update_chrony_config(data: &str, sources: &[NtpSource]) -> String {
+    let mut source_lines = sources.iter().map(|source| {
+        let ty = match source.ty {
+            NtpSourceType::Server => "server",
+            NtpSourceType::Pool => "pool",
+        };
+        match &source.options {
+            Some(options) => format!("{ty} {} {options}\n", source.address),
+            None => format!("{ty} {}\n", source.address),
+        }
+    });
+
+    let mut new = String::new();
+    let mut replaced = false;
+
+    for line in data.lines() {
+        if parse_chrony_source(line).is_some() {
+            if !replaced {
+                new.extend(&mut source_lines);
+                replaced = true;
+            }
+            continue;
+        }
+        new.push_str(line);
+        new.push('\n');
+    }
+
+    if !replaced {
+        new.extend(source_lines);
+    }
+
+    new
+}
+
+/// Get the value of an active `NTP=` line of a systemd-timesyncd configuration.
+fn parse_timesyncd_ntp(line: &str) -> Option<&str> {
+    let (key, value) = line.trim().split_once('=')?;
+    (key.trim() == "NTP").then(|| value.trim())
+}
+
+fn is_section_header(line: &str) -> bool {
+    line.trim().starts_with('[')
+}
+
+fn is_time_section(line: &str) -> bool {
+    line.trim() == "[Time]"
+}
+
+fn parse_timesyncd_config(data: &str) -> Vec<NtpSource> {
+    let mut sources = Vec::new();
+    let mut in_time_section = false;
+
+    for line in data.lines() {
+        if is_section_header(line) {
+            in_time_section = is_time_section(line);
+            continue;
+        }
+        if !in_time_section {
+            continue;
+        }
+        if let Some(value) = parse_timesyncd_ntp(line) {
+            // assigning an empty value resets the list, like in systemd
+            if value.is_empty() {
+                sources.clear();
+            }
+            sources.extend(value.split_whitespace().map(|address| NtpSource {
+                ty: NtpSourceType::Server,
+                address: address.to_string(),
+                options: None,
+            }));
+        }
+    }
+
+    sources
+}
+
+/// Replace the `NTP=` setting of a systemd-timesyncd configuration, keeping everything else.
+///
+/// Without sources, the setting is removed so that the fallback servers are used.
+fn update_timesyncd_config(data: &str, sources: &[NtpSource]) -> String {
+    let ntp_line = (!sources.is_empty()).then(|| {
+        let addresses: Vec<&str> = sources.iter().map(|s| s.address.as_str()).collect();
+        format!("NTP={}\n", addresses.join(" "))
+    });
+
+    let mut new = String::new();
+    let mut in_time_section = false;
+    let mut written = false;
+
+    for line in data.lines() {
+        if is_section_header(line) {
+            in_time_section = is_time_section(line);
+            new.push_str(line);
+            new.push('\n');
+            if in_time_section && !written {
+                new.extend(ntp_line.as_deref());
+                written = true;
+            }
+            continue;
+        }
+        if in_time_section && parse_timesyncd_ntp(line).is_some() {
+            continue;
+        }
+        new.push_str(line);
+        new.push('\n');
+    }
+
+    if !written {
+        if let Some(ntp_line) = ntp_line {
+            new.push_str("[Time]\n");
+            new.push_str(&ntp_line);
+        }
+    }
+
+    new
+}
+
+/// Read the NTP configuration of the time synchronization service in use.
+pub fn read_time_sync_config(
+    expected_digest: Option<&ConfigDigest>,
+) -> Result<TimeSyncConfigWithDigest, Error> {
+    let service = detect_time_sync_service();
+
+    let data = file_read_optional_string(service.config_path())?.unwrap_or_default();
+    let digest = ConfigDigest::from_slice(data.as_bytes());
+
+    digest.detect_modification(expected_digest)?;
+
+    let sources = match service {
+        TimeSyncService::Chrony => parse_chrony_config(&data),
+        TimeSyncService::Timesyncd => parse_timesyncd_config(&data),
+    };
+
+    Ok(TimeSyncConfigWithDigest {
+        config: TimeSyncConfig { service, sources },
+        digest,
+    })
+}
+
+/// Update the NTP sources of the time synchronization service in use and restart it.
+pub fn update_time_sync_config(
+    sources: Vec<NtpSource>,
+    digest: Option<ConfigDigest>,
+) -> Result<(), Error> {
+    static MUTEX: Mutex<()> = Mutex::new(());
+
+    let _guard = MUTEX.lock();
+
+    let service = detect_time_sync_service();
+
+    // everything ends up in a config file written as root, never rely on the API schema alone
+    for source in &sources {
+        NTP_SOURCE_ADDRESS_SCHEMA
+            .unwrap_string_schema()
+            .check_constraints(&source.address)
+            .map_err(|err| format_err!("invalid NTP source '{}' - {err}", source.address))?;
+        if let Some(options) = &source.options {
+            NTP_SOURCE_OPTIONS_SCHEMA
+                .unwrap_string_schema()
+                .check_constraints(options)
+                .map_err(|err| format_err!("invalid options '{options}' - {err}"))?;
+        }
+    }
+
+    if service == TimeSyncService::Timesyncd {
+        for source in &sources {
+            if source.ty == NtpSourceType::Pool {
+                bail!("systemd-timesyncd does not support NTP pools");
+            }
+            if source.options.is_some() {
+                bail!("systemd-timesyncd does not support options for NTP servers");
+            }
+        }
+    }
+
+    let path = service.config_path();
+    let data = file_read_optional_string(path)?.unwrap_or_default();
+
+    ConfigDigest::from_slice(data.as_bytes()).detect_modification(digest.as_ref())?;
+
+    let data = match service {
+        TimeSyncService::Chrony => update_chrony_config(&data, &sources),
+        TimeSyncService::Timesyncd => update_timesyncd_config(&data, &sources),
+    };
+
+    replace_system_config(path, data.as_bytes())?;
+
+    let mut command = std::process::Command::new("systemctl");
+    command.args(["try-reload-or-restart", service.unit_name()]);
+    proxmox_sys::command::run_command(command, None)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn source(ty: NtpSourceType, address: &str, options: Option<&str>) -> NtpSource {
+        NtpSource {
+            ty,
+            address: address.to_string(),
+            options: options.map(str::to_string),
+        }
+    }
+
+    const CHRONY_CONF: &str = "\
+# Use Debian vendor zone.
+confdir /etc/chrony/conf.d
+pool 2.debian.pool.ntp.org iburst
+server 10.0.0.1
+! server 10.0.0.2
+keyfile /etc/chrony/chrony.keys
+";
+
+    #[test]
+    fn test_chrony_config() {
+        assert_eq!(
+            parse_chrony_config(CHRONY_CONF),
+            [
+                source(NtpSourceType::Pool, "2.debian.pool.ntp.org", Some("iburst")),
+                source(NtpSourceType::Server, "10.0.0.1", None),
+            ]
+        );
+
+        let sources = [
+            source(
+                NtpSourceType::Server,
+                "ntp.example.com",
+                Some("iburst prefer"),
+            ),
+            source(NtpSourceType::Pool, "pool.example.com", None),
+        ];
+        let updated = update_chrony_config(CHRONY_CONF, &sources);
+        assert_eq!(
+            updated,
+            "\
+# Use Debian vendor zone.
+confdir /etc/chrony/conf.d
+server ntp.example.com iburst prefer
+pool pool.example.com
+! server 10.0.0.2
+keyfile /etc/chrony/chrony.keys
+"
+        );
+        assert_eq!(parse_chrony_config(&updated), sources);
+
+        assert_eq!(
+            update_chrony_config("keyfile /etc/chrony/chrony.keys", &sources[..1]),
+            "keyfile /etc/chrony/chrony.keys\nserver ntp.example.com iburst prefer\n"
+        );
+        assert_eq!(update_chrony_config(CHRONY_CONF, &[]).lines().count(), 4);
+    }
+
+    const TIMESYNCD_CONF: &str = "\
+[Time]
+#NTP=
+NTP=a.example.com b.example.com
+NTP=
+NTP=c.example.com
+FallbackNTP=d.example.com
+[Other]
+NTP=z.example.com
+";
+
+    #[test]
+    fn test_timesyncd_config() {
+        // an empty assignment resets the list, other sections are ignored
+        assert_eq!(
+            parse_timesyncd_config(TIMESYNCD_CONF),
+            [source(NtpSourceType::Server, "c.example.com", None)]
+        );
+
+        let sources = [
+            source(NtpSourceType::Server, "x.example.com", None),
+            source(NtpSourceType::Server, "10.0.0.1", None),
+        ];
+        let updated = update_timesyncd_config(TIMESYNCD_CONF, &sources);
+        assert_eq!(
+            updated,
+            "\
+[Time]
+NTP=x.example.com 10.0.0.1
+#NTP=
+FallbackNTP=d.example.com
+[Other]
+NTP=z.example.com
+"
+        );
+        assert_eq!(parse_timesyncd_config(&updated), sources);
+
+        assert_eq!(
+            update_timesyncd_config("", &sources[..1]),
+            "[Time]\nNTP=x.example.com\n"
+        );
+        assert_eq!(
+            update_timesyncd_config(TIMESYNCD_CONF, &[]),
+            "[Time]\n#NTP=\nFallbackNTP=d.example.com\n[Other]\nNTP=z.example.com\n"
+        );
+    }
+
+    #[test]
+    fn test_source_options_schema() {
+        let schema = NTP_SOURCE_OPTIONS_SCHEMA.unwrap_string_schema();
+        assert!(schema.check_constraints("iburst").is_ok());
+        assert!(schema
+            .check_constraints("iburst maxpoll 10 minstratum 2")
+            .is_ok());
+        assert!(schema.check_constraints("iburst\nallow all").is_err());
+        assert!(schema.check_constraints("iburst # comment").is_err());
+        assert!(schema.check_constraints("").is_err());
+    }
+}
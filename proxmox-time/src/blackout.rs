@@ -0,0 +1,357 @@
+use anyhow::{bail, format_err, Error};
+
+use crate::{parse_daily_duration, DailyDuration};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{CalendarEvent, TmEditor};
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+// Without a year, February 29th is allowed, it is only matched in leap years.
+fn days_in_month(year: Option<i32>, month: u32) -> u32 {
+    match month {
+        2 if year.map(is_leap_year).unwrap_or(true) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A calendar date, the year is optional for dates recurring every year.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BlackoutDate {
+    pub year: Option<i32>,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl std::str::FromStr for BlackoutDate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split('-').collect();
+
+        let parse = |value: &str, max: u32| -> Result<u32, Error> {
+            match value.parse() {
+                Ok(value) if value >= 1 && value <= max => Ok(value),
+                _ => bail!("invalid date '{}'", s),
+            }
+        };
+
+        let (year, month, day) = match parts[..] {
+            [year, month, day] if year.len() == 4 => {
+                let year = year
+                    .parse()
+                    .map_err(|_| format_err!("invalid date '{}'", s))?;
+                (Some(year), month, day)
+            }
+            [month, day] => (None, month, day),
+            _ => bail!("invalid date '{}'", s),
+        };
+
+        let month = parse(month, 12)?;
+
+        Ok(Self {
+            year,
+            month,
+            day: parse(day, days_in_month(year, month))?,
+        })
+    }
+}
+
+/// A single period of a [BlackoutWindow].
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlackoutPeriod {
+    /// The days from start to end (inclusive). Without years, the range recurs every year and may
+    /// wrap around the end of the year, e.g. `12-24..01-01`.
+    Dates(BlackoutDate, BlackoutDate),
+    /// A recurring time window, e.g. `sat,sun 0:00-6:00`.
+    Weekly(DailyDuration),
+}
+
+impl std::str::FromStr for BlackoutPeriod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        // daily durations need a weekday or minutes, to distinguish them from dates
+        if s.starts_with(|c: char| c.is_ascii_alphabetic()) || s.contains(':') {
+            return Ok(BlackoutPeriod::Weekly(parse_daily_duration(s)?));
+        }
+
+        let (start, end): (BlackoutDate, BlackoutDate) = match s.split_once("..") {
+            Some((start, end)) => (start.parse()?, end.parse()?),
+            None => {
+                let date: BlackoutDate = s.parse()?;
+                (date, date)
+            }
+        };
+
+        match (start.year, end.year) {
+            (Some(_), Some(_)) if start > end => bail!("end date before start date in '{}'", s),
+            (Some(_), Some(_)) | (None, None) => (),
+            _ => bail!("either both or none of the dates need a year in '{}'", s),
+        }
+
+        Ok(BlackoutPeriod::Dates(start, end))
+    }
+}
+
+/// A list of periods during which scheduled events must not trigger, e.g. holidays or
+/// maintenance windows.
+///
+/// Parsed from a `;` separated list of date ranges and weekly windows, for example
+/// `2024-12-24..2024-12-26; 01-01; sat 0:00-6:00`. Daily windows need minutes (`8:00-12:00`)
+/// to be distinguishable from dates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlackoutWindow {
+    pub periods: Vec<BlackoutPeriod>,
+}
+
+impl std::str::FromStr for BlackoutWindow {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let periods = s
+            .split(';')
+            .filter(|period| !period.trim().is_empty())
+            .map(|period| period.parse())
+            .collect::<Result<_, Error>>()
+            .map_err(|err| format_err!("unable to parse blackout window - {err}"))?;
+
+        Ok(Self { periods })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BlackoutPeriod {
+    /// Returns the end of the period if it contains the time.
+    fn end_if_contains(&self, epoch: i64, utc: bool) -> Result<Option<i64>, Error> {
+        let mut t = TmEditor::with_epoch(epoch, utc)?;
+
+        match self {
+            BlackoutPeriod::Weekly(duration) => {
+                if !duration.time_match_with_tm_editor(&t) {
+                    return Ok(None);
+                }
+                t.set_time(duration.end.hour as i32, duration.end.minute as i32, 0)?;
+            }
+            BlackoutPeriod::Dates(start, end) => {
+                let date = (t.month() as u32, t.day() as u32);
+                let (start_date, end_date) = ((start.month, start.day), (end.month, end.day));
+
+                let end_year = match (start.year, end.year) {
+                    (Some(_), Some(end_year)) => {
+                        let current = BlackoutDate {
+                            year: Some(t.year()),
+                            month: date.0,
+                            day: date.1,
+                        };
+                        if current < *start || current > *end {
+                            return Ok(None);
+                        }
+                        end_year
+                    }
+                    _ if start_date <= end_date => {
+                        if date < start_date || date > end_date {
+                            return Ok(None);
+                        }
+                        t.year()
+                    }
+                    _ => {
+                        // wraps around the end of the year
+                        if date >= start_date {
+                            t.year() + 1
+                        } else if date <= end_date {
+                            t.year()
+                        } else {
+                            return Ok(None);
+                        }
+                    }
+                };
+
+                // the start of the day after the end date
+                t.set_mday(1)?;
+                t.set_year(end_year)?;
+                t.set_mon(end.month as i32)?;
+                // a recurring February 29th ends on the 28th in other years
+                t.set_mday((end.day as i32).min(t.days_in_month()))?;
+                t.set_time(0, 0, 0)?;
+                t.add_days(1)?;
+            }
+        }
+
+        Ok(Some(t.into_epoch()?))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BlackoutWindow {
+    /// Test if the time is within one of the periods.
+    pub fn time_match(&self, epoch: i64, utc: bool) -> Result<bool, Error> {
+        Ok(self.end(epoch, utc)?.is_some())
+    }
+
+    /// Returns the end of the blackout containing the time, `None` if it is not within a
+    /// blackout.
+    fn end(&self, epoch: i64, utc: bool) -> Result<Option<i64>, Error> {
+        let mut result = None;
+        for period in &self.periods {
+            if let Some(end) = period.end_if_contains(epoch, utc)? {
+                result = Some(end.max(result.unwrap_or(end)));
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Computes the next event after `last` which is not within the blackout window.
+///
+/// The blackout window is evaluated in the same timezone as the event.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn next_event_outside(
+    event: &CalendarEvent,
+    blackouts: &BlackoutWindow,
+    last: i64,
+) -> Result<Option<i64>, Error> {
+    let mut last = last;
+
+    // cancel after 1000 loops, like compute_next_event
+    for _ in 0..1000 {
+        let next = match event.compute_next_event(last)? {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+
+        match blackouts.end(next, event.utc)? {
+            // skip to the end of the blackout, the next event may start there
+            Some(end) => last = end.max(next + 1) - 1,
+            None => return Ok(Some(next)),
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+
+    use anyhow::Error;
+
+    use super::*;
+
+    const DAY: i64 = 3600 * 24;
+    const HOUR: i64 = 3600;
+
+    const DEC_24_2020: i64 = 1608768000; // Thursday, 2020-12-24 00:00:00
+
+    #[test]
+    fn test_blackout_window_parser() -> Result<(), Error> {
+        let window: BlackoutWindow =
+            "2020-12-24..2020-12-26; 01-01 ;12-31..01-02; sat,sun 0:00-6:00; 22:00-23:00"
+                .parse()?;
+
+        assert_eq!(window.periods.len(), 5);
+        assert_eq!(
+            window.periods[1],
+            BlackoutPeriod::Dates(
+                BlackoutDate {
+                    year: None,
+                    month: 1,
+                    day: 1
+                },
+                BlackoutDate {
+                    year: None,
+                    month: 1,
+                    day: 1
+                },
+            )
+        );
+        assert!(matches!(window.periods[3], BlackoutPeriod::Weekly(_)));
+        assert!(matches!(window.periods[4], BlackoutPeriod::Weekly(_)));
+
+        assert_eq!("".parse::<BlackoutWindow>()?, BlackoutWindow::default());
+
+        assert!("2020-12-26..2020-12-24".parse::<BlackoutWindow>().is_err());
+        assert!("2020-12-24..12-26".parse::<BlackoutWindow>().is_err());
+        assert!("13-01".parse::<BlackoutWindow>().is_err());
+        assert!("12-32".parse::<BlackoutWindow>().is_err());
+        assert!("02-30".parse::<BlackoutWindow>().is_err());
+        assert!("04-31".parse::<BlackoutWindow>().is_err());
+        assert!("2021-02-29".parse::<BlackoutWindow>().is_err());
+        assert!("1900-02-29".parse::<BlackoutWindow>().is_err());
+        assert!("2000-02-29".parse::<BlackoutWindow>().is_ok());
+        assert!("2020-02-29".parse::<BlackoutWindow>().is_ok());
+        assert!("02-29".parse::<BlackoutWindow>().is_ok());
+        assert!("8-12".parse::<BlackoutWindow>().is_ok()); // august 12th
+        assert!("xyz 8-12".parse::<BlackoutWindow>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_event_outside() -> Result<(), Error> {
+        let daily: CalendarEvent = "daily UTC".parse()?;
+        let hourly: CalendarEvent = "hourly UTC".parse()?;
+
+        let window: BlackoutWindow = "2020-12-24..2020-12-26".parse()?;
+        assert!(window.time_match(DEC_24_2020, true)?);
+        assert!(window.time_match(DEC_24_2020 + 3 * DAY - 1, true)?);
+        assert!(!window.time_match(DEC_24_2020 + 3 * DAY, true)?);
+        assert_eq!(
+            next_event_outside(&daily, &window, DEC_24_2020 - 1)?,
+            Some(DEC_24_2020 + 3 * DAY)
+        );
+
+        // recurring every year, wrapping around the end of the year
+        let window: BlackoutWindow = "12-31..01-01".parse()?;
+        assert_eq!(
+            next_event_outside(&daily, &window, DEC_24_2020 + 6 * DAY)?,
+            Some(DEC_24_2020 + 9 * DAY)
+        );
+        assert_eq!(
+            next_event_outside(&daily, &window, DEC_24_2020 + 6 * DAY + 365 * DAY)?,
+            Some(DEC_24_2020 + 9 * DAY + 365 * DAY)
+        );
+
+        // weekly windows, 2020-12-26 is a saturday
+        let window: BlackoutWindow = "sat,sun 0:00-6:00; 22:00-23:00".parse()?;
+        assert_eq!(
+            next_event_outside(&hourly, &window, DEC_24_2020 + 2 * DAY - 1)?,
+            Some(DEC_24_2020 + 2 * DAY + 6 * HOUR)
+        );
+        assert_eq!(
+            next_event_outside(&hourly, &window, DEC_24_2020 + 21 * HOUR)?,
+            Some(DEC_24_2020 + 23 * HOUR)
+        );
+
+        // overlapping periods
+        let window: BlackoutWindow = "2020-12-24; 12-25; thu,fri 0:00-12:00".parse()?;
+        assert_eq!(
+            next_event_outside(&daily, &window, DEC_24_2020 - 1)?,
+            Some(DEC_24_2020 + 2 * DAY)
+        );
+
+        // a recurring February 29th only ends early in non-leap years
+        let window: BlackoutWindow = "02-20..02-29".parse()?;
+        let feb_20_2020 = DEC_24_2020 - 308 * DAY;
+        let feb_20_2021 = feb_20_2020 + 366 * DAY;
+        assert_eq!(
+            next_event_outside(&daily, &window, feb_20_2020 - 1)?,
+            Some(feb_20_2020 + 10 * DAY)
+        );
+        assert_eq!(
+            next_event_outside(&daily, &window, feb_20_2021 - 1)?,
+            Some(feb_20_2021 + 9 * DAY)
+        );
+
+        // never outside
+        let window: BlackoutWindow = "01-01..12-31".parse()?;
+        assert_eq!(next_event_outside(&daily, &window, DEC_24_2020)?, None);
+
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use std::convert::TryInto;
 
 use anyhow::Error;
 use nom::{
+    branch::alt,
     bytes::complete::tag,
     character::complete::space0,
     combinator::opt,
@@ -20,7 +21,7 @@ use crate::{parse_weekdays_range, WeekDays};
 #[derive(Default, Clone, Debug)]
 pub struct CalendarEvent {
     /// if true, the event is calculated in utc and the local timezone otherwise
-    utc: bool,
+    pub(crate) utc: bool,
     /// the days in a week this event should trigger
     pub(crate) days: WeekDays,
     /// the second(s) this event should trigger
@@ -31,6 +32,10 @@ pub struct CalendarEvent {
     pub(crate) hour: Vec<DateTimeValue>,
     /// the day(s) in a month this event should trigger
     pub(crate) day: Vec<DateTimeValue>,
+    /// if true, the days count backwards from the last day of the month (`~` syntax)
+    pub(crate) day_from_end: bool,
+    /// the ISO 8601 week(s) in a year this event should trigger
+    pub(crate) week: Vec<DateTimeValue>,
     /// the month(s) in a year this event should trigger
     pub(crate) month: Vec<DateTimeValue>,
     /// the years(s) this event should trigger
@@ -58,7 +63,18 @@ impl CalendarEvent {
                 count += 1;
             }
 
-            if !self.year.is_empty() {
+            if !self.year.is_empty() && !self.week.is_empty() {
+                // with a week specification, the year refers to the ISO week-based year
+                let year: u32 = t.iso_week().0.try_into()?;
+                if !DateTimeValue::list_contains(&self.year, year) {
+                    if let Some(n) = DateTimeValue::find_next(&self.year, year) {
+                        t.set_iso_week_year(n.try_into()?)?;
+                        continue;
+                    } else {
+                        return Ok(None);
+                    }
+                }
+            } else if !self.year.is_empty() {
                 let year: u32 = t.year().try_into()?;
                 if !DateTimeValue::list_contains(&self.year, year) {
                     if let Some(n) = DateTimeValue::find_next(&self.year, year) {
@@ -84,7 +100,22 @@ impl CalendarEvent {
                 }
             }
 
-            if !self.day.is_empty() {
+            if !self.day.is_empty() && self.day_from_end {
+                let day: u32 = t.day().try_into()?;
+                let days_in_month: u32 = t.days_in_month().try_into()?;
+                let from_end = |d: u32| days_in_month - d + 1;
+                if !DateTimeValue::list_contains_from_end(&self.day, from_end(day)) {
+                    if let Some(n) = ((day + 1)..=days_in_month)
+                        .find(|d| DateTimeValue::list_contains_from_end(&self.day, from_end(*d)))
+                    {
+                        t.add_days((n - day).try_into()?)?;
+                    } else {
+                        // if we could not find valid mday, retry next month
+                        t.add_months(1)?;
+                    }
+                    continue;
+                }
+            } else if !self.day.is_empty() {
                 let day: u32 = t.day().try_into()?;
                 if !DateTimeValue::list_contains(&self.day, day) {
                     if let Some(n) = DateTimeValue::find_next(&self.day, day) {
@@ -97,6 +128,26 @@ impl CalendarEvent {
                 }
             }
 
+            if !self.week.is_empty() {
+                let (week_year, week) = t.iso_week();
+                let week: u32 = week.try_into()?;
+                if !DateTimeValue::list_contains(&self.week, week) {
+                    let weeks_in_year: u32 = t.iso_weeks_in_year().try_into()?;
+                    match DateTimeValue::find_next(&self.week, week) {
+                        Some(n) if n <= weeks_in_year => {
+                            // monday of the next matching week
+                            let days: libc::c_int = ((n - week) * 7).try_into()?;
+                            t.add_days(days - t.day_num())?;
+                        }
+                        _ => {
+                            // if we could not find a valid week, retry next year
+                            t.set_iso_week_year(week_year + 1)?;
+                        }
+                    }
+                    continue;
+                }
+            }
+
             if !all_days {
                 // match day first
                 let day_num: u32 = t.day_num().try_into()?;
@@ -213,7 +264,7 @@ fn parse_calendar_event_incomplete(mut i: &str) -> IResult<&str, CalendarEvent>
         i = n.trim_end_matches(' ');
     }
 
-    if i.starts_with(|c: char| char::is_ascii_alphabetic(&c)) {
+    if i.starts_with(|c: char| char::is_ascii_alphabetic(&c)) && !is_week_spec(i) {
         match i {
             "minutely" => {
                 return Ok((
@@ -342,6 +393,8 @@ fn parse_calendar_event_incomplete(mut i: &str) -> IResult<&str, CalendarEvent>
         event.year = date.year;
         event.month = date.month;
         event.day = date.day;
+        event.day_from_end = date.day_from_end;
+        event.week = date.week;
         has_datespec = true;
         i = space0(n)?.0;
     }
@@ -375,6 +428,16 @@ struct DateSpec {
     year: Vec<DateTimeValue>,
     month: Vec<DateTimeValue>,
     day: Vec<DateTimeValue>,
+    day_from_end: bool,
+    week: Vec<DateTimeValue>,
+}
+
+// check if the input starts with a week specification like 'W15' or 'W*/2'
+fn is_week_spec(i: &str) -> bool {
+    match i.strip_prefix('W') {
+        Some(rest) => rest.starts_with(|c: char| c.is_ascii_digit() || c == '*'),
+        None => false,
+    }
 }
 
 fn parse_date_time_comp(max: usize) -> impl Fn(&str) -> IResult<&str, DateTimeValue> {
@@ -440,18 +503,61 @@ fn parse_time_spec(i: &str) -> IResult<&str, TimeSpec> {
     ))
 }
 
+// parse the separator between month and day, '~' means counting the days from the end of the
+// month (see man systemd.time)
+fn parse_day_separator(i: &str) -> IResult<&str, bool> {
+    let (i, separator) = alt((tag("-"), tag("~")))(i)?;
+    Ok((i, separator == "~"))
+}
+
 fn parse_date_spec(i: &str) -> IResult<&str, DateSpec> {
-    // TODO: implement ~ for days (man systemd.time)
-    if let Ok((i, (year, month, day))) = tuple((
+    if let Ok((i, (year, week))) = tuple((
+        parse_date_time_comp_list(0, 2200),
+        preceded(tag("-W"), parse_date_time_comp_list(1, 54)),
+    ))(i)
+    {
+        Ok((
+            i,
+            DateSpec {
+                year,
+                month: Vec::new(),
+                day: Vec::new(),
+                day_from_end: false,
+                week,
+            },
+        ))
+    } else if let Ok((i, week)) = preceded(tag("W"), parse_date_time_comp_list(1, 54))(i) {
+        Ok((
+            i,
+            DateSpec {
+                year: Vec::new(),
+                month: Vec::new(),
+                day: Vec::new(),
+                day_from_end: false,
+                week,
+            },
+        ))
+    } else if let Ok((i, (year, month, day_from_end, day))) = tuple((
         parse_date_time_comp_list(0, 2200), // the upper limit for systemd, stay compatible
         preceded(tag("-"), parse_date_time_comp_list(1, 13)),
-        preceded(tag("-"), parse_date_time_comp_list(1, 32)),
+        parse_day_separator,
+        parse_date_time_comp_list(1, 32),
     ))(i)
     {
-        Ok((i, DateSpec { year, month, day }))
-    } else if let Ok((i, (month, day))) = tuple((
+        Ok((
+            i,
+            DateSpec {
+                year,
+                month,
+                day,
+                day_from_end,
+                week: Vec::new(),
+            },
+        ))
+    } else if let Ok((i, (month, day_from_end, day))) = tuple((
         parse_date_time_comp_list(1, 13),
-        preceded(tag("-"), parse_date_time_comp_list(1, 32)),
+        parse_day_separator,
+        parse_date_time_comp_list(1, 32),
     ))(i)
     {
         Ok((
@@ -460,6 +566,8 @@ fn parse_date_spec(i: &str) -> IResult<&str, DateSpec> {
                 year: Vec::new(),
                 month,
                 day,
+                day_from_end,
+                week: Vec::new(),
             },
         ))
     } else {
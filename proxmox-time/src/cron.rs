@@ -0,0 +1,145 @@
+use anyhow::{bail, format_err, Error};
+
+use crate::date_time_value::DateTimeValue;
+use crate::{CalendarEvent, WeekDays};
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parse a standard 5-field cron expression (`minute hour day-of-month month day-of-week`)
+/// into a [CalendarEvent].
+///
+/// The `@yearly`, `@annually`, `@monthly`, `@weekly`, `@daily`, `@midnight` and `@hourly`
+/// macros are supported as well. Like cron, the resulting event uses the local timezone.
+///
+/// Cron triggers if *either* the day of month or the day of week matches when both are
+/// restricted, which cannot be expressed as calendar event, so such expressions are rejected.
+pub fn parse_cron_expression(expr: &str) -> Result<CalendarEvent, Error> {
+    let expr = expr.trim();
+
+    if let Some(name) = expr.strip_prefix('@') {
+        let expanded = match name {
+            "yearly" | "annually" => "0 0 1 1 *",
+            "monthly" => "0 0 1 * *",
+            "weekly" => "0 0 * * 0",
+            "daily" | "midnight" => "0 0 * * *",
+            "hourly" => "0 * * * *",
+            _ => bail!("unsupported cron macro '{}'", expr),
+        };
+        return parse_cron_expression(expanded);
+    }
+
+    let fields: Vec<&str> = expr.split_ascii_whitespace().collect();
+    let [minute, hour, day, month, weekday] = fields[..] else {
+        bail!(
+            "unable to parse cron expression '{}' - expected 5 fields, got {}",
+            expr,
+            fields.len()
+        );
+    };
+
+    if !day.starts_with('*') && !weekday.starts_with('*') {
+        bail!(
+            "unable to parse cron expression '{}' - restricting both day of month and day of \
+             week is not supported",
+            expr
+        );
+    }
+
+    let field = |name: &str, value: &str, min: u32, max: u32, names: &[&str]| {
+        parse_cron_field(value, min, max, names).map_err(|err| {
+            format_err!("unable to parse cron expression '{expr}' - invalid {name} - {err}")
+        })
+    };
+
+    let weekdays = field("day of week", weekday, 0, 7, &WEEKDAY_NAMES)?;
+    let mut days = WeekDays::empty();
+    for num in 0..=7 {
+        if DateTimeValue::list_contains(&weekdays, num) {
+            // cron uses 0 and 7 for sunday
+            days.insert(WeekDays::from_bits(1 << ((num + 6) % 7)).unwrap());
+        }
+    }
+
+    Ok(CalendarEvent {
+        days,
+        second: vec![DateTimeValue::Single(0)],
+        minute: field("minute", minute, 0, 59, &[])?,
+        hour: field("hour", hour, 0, 23, &[])?,
+        day: field("day of month", day, 1, 31, &[])?,
+        month: field("month", month, 1, 12, &MONTH_NAMES)?,
+        ..Default::default()
+    })
+}
+
+// Parse a single value, `names` are the (case-insensitive) aliases for the values starting at
+// `min`.
+fn parse_cron_value(value: &str, min: u32, max: u32, names: &[&str]) -> Result<u32, Error> {
+    let num = match names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+    {
+        Some(pos) => min + pos as u32,
+        None => value
+            .parse()
+            .map_err(|_| format_err!("invalid value '{}'", value))?,
+    };
+
+    if num < min || num > max {
+        bail!("value '{}' out of range {}-{}", value, min, max);
+    }
+
+    Ok(num)
+}
+
+// Parse a cron field, an empty list means any value.
+fn parse_cron_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+) -> Result<Vec<DateTimeValue>, Error> {
+    let mut list = Vec::new();
+
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format_err!("invalid step '{}'", step))?;
+                if step == 0 {
+                    bail!("invalid step '0'");
+                }
+                (range, Some(step))
+            }
+            None => (item, None),
+        };
+
+        let (start, end) = if range == "*" {
+            if step.is_none() {
+                return Ok(Vec::new());
+            }
+            (min, None)
+        } else if let Some((start, end)) = range.split_once('-') {
+            let start = parse_cron_value(start, min, max, names)?;
+            let end = parse_cron_value(end, min, max, names)?;
+            if start > end {
+                bail!("range start is bigger than end in '{}'", range);
+            }
+            (start, Some(end))
+        } else {
+            (parse_cron_value(range, min, max, names)?, None)
+        };
+
+        list.push(match (end, step) {
+            (None, None) => DateTimeValue::Single(start),
+            (Some(end), None) => DateTimeValue::Range(start, end),
+            (end, Some(step)) => DateTimeValue::Repeated(start, step, end),
+        });
+    }
+
+    Ok(list)
+}
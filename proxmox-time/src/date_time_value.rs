@@ -35,6 +35,29 @@ impl DateTimeValue {
         list.iter().any(|spec| spec.contains(value))
     }
 
+    // Test if the entry contains the value, counted from the end (e.g. days relative to the end
+    // of a month). Open ended repetitions count down towards 1, so '7/1' means the last 7 days.
+    pub fn contains_from_end(&self, value: u32) -> bool {
+        match self {
+            DateTimeValue::Repeated(start, repetition, None) => {
+                if value <= *start {
+                    if *repetition > 0 {
+                        (start - value) % repetition == 0
+                    } else {
+                        *start == value
+                    }
+                } else {
+                    false
+                }
+            }
+            _ => self.contains(value),
+        }
+    }
+
+    pub fn list_contains_from_end(list: &[DateTimeValue], value: u32) -> bool {
+        list.iter().any(|spec| spec.contains_from_end(value))
+    }
+
     // Find an return an entry greater than value
     pub fn find_next(list: &[DateTimeValue], value: u32) -> Option<u32> {
         let mut next: Option<u32> = None;
@@ -12,15 +12,24 @@ pub(crate) mod date_time_value;
 mod calendar_event;
 pub use calendar_event::*;
 
+mod cron;
+pub use cron::*;
+
 mod time_span;
 pub use time_span::*;
 
+mod relative_time;
+pub use relative_time::*;
+
 mod week_days;
 pub use week_days::*;
 
 mod daily_duration;
 pub use daily_duration::*;
 
+mod blackout;
+pub use blackout::*;
+
 #[cfg(not(target_arch = "wasm32"))]
 mod posix;
 #[cfg(not(target_arch = "wasm32"))]
@@ -284,6 +284,77 @@ pub fn epoch_to_rfc3339(epoch: i64) -> Result<String, Error> {
     Ok(s)
 }
 
+// Format nanoseconds as fraction of a second, using 3, 6 or 9 digits, whichever is the shortest
+// exact representation. Empty if there is no fraction.
+fn format_subsec(nanos: u32) -> String {
+    if nanos == 0 {
+        String::new()
+    } else if nanos % 1_000_000 == 0 {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos % 1_000 == 0 {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{nanos:09}")
+    }
+}
+
+fn insert_subsec(mut rfc3339: String, nanos: u32) -> Result<String, Error> {
+    if nanos >= 1_000_000_000 {
+        bail!("invalid nanoseconds '{nanos}'");
+    }
+    // the seconds always end at position 19, years are limited to 4 digits
+    rfc3339.insert_str(19, &format_subsec(nanos));
+    Ok(rfc3339)
+}
+
+/// Convert Unix epoch and nanoseconds into RFC3339 UTC string with fractional seconds
+///
+/// The fraction is omitted if `nanos` is zero, otherwise it has 3, 6 or 9 digits, depending
+/// on the needed precision.
+pub fn epoch_to_rfc3339_utc_subsec(epoch: i64, nanos: u32) -> Result<String, Error> {
+    insert_subsec(epoch_to_rfc3339_utc(epoch)?, nanos)
+}
+
+/// Convert Unix epoch and nanoseconds into RFC3339 local time with TZ and fractional seconds
+///
+/// See [epoch_to_rfc3339_utc_subsec] for the format of the fraction.
+pub fn epoch_to_rfc3339_subsec(epoch: i64, nanos: u32) -> Result<String, Error> {
+    insert_subsec(epoch_to_rfc3339(epoch)?, nanos)
+}
+
+/// Parse RFC3339 with optional fractional seconds into Unix epoch and nanoseconds
+///
+/// Digits beyond nanosecond precision are truncated.
+pub fn parse_rfc3339_subsec(input_str: &str) -> Result<(i64, u32), Error> {
+    parse_rfc3339_subsec_do(input_str)
+        .map_err(|err| format_err!("failed to parse rfc3339 timestamp ({input_str:?}) - {err}",))
+}
+
+fn parse_rfc3339_subsec_do(input_str: &str) -> Result<(i64, u32), Error> {
+    let input = input_str.as_bytes();
+
+    if input.len() <= 19 || input[19] != b'.' {
+        return Ok((parse_rfc3339_do(input_str)?, 0));
+    }
+
+    let digits = input[20..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    if digits == 0 {
+        bail!("missing digits in fractional seconds");
+    }
+
+    let mut nanos = 0;
+    for (i, digit) in input[20..].iter().take(digits.min(9)).enumerate() {
+        nanos += (digit - b'0') as u32 * 10u32.pow(8 - i as u32);
+    }
+
+    let without_fraction = format!("{}{}", &input_str[..19], &input_str[(20 + digits)..]);
+
+    Ok((parse_rfc3339_do(&without_fraction)?, nanos))
+}
+
 /// Parse RFC3339 into Unix epoch
 pub fn parse_rfc3339(input_str: &str) -> Result<i64, Error> {
     parse_rfc3339_do(input_str)
@@ -488,6 +559,37 @@ fn test_timezones() {
     assert_eq!(expected_utc, res);
 }
 
+#[test]
+fn test_rfc3339_subsec() {
+    let epoch = 1609263000;
+
+    let convert = |nanos| epoch_to_rfc3339_utc_subsec(epoch, nanos).expect("converting failed");
+    assert_eq!(convert(0), "2020-12-29T17:30:00Z");
+    assert_eq!(convert(500_000_000), "2020-12-29T17:30:00.500Z");
+    assert_eq!(convert(123_456_000), "2020-12-29T17:30:00.123456Z");
+    assert_eq!(convert(1), "2020-12-29T17:30:00.000000001Z");
+    epoch_to_rfc3339_utc_subsec(epoch, 1_000_000_000).expect_err("invalid nanos should fail");
+
+    for nanos in [0, 1, 500_000_000, 123_456_000, 999_999_999] {
+        let local = epoch_to_rfc3339_subsec(epoch, nanos).expect("converting failed");
+        let parsed = parse_rfc3339_subsec(&local).expect("parsing failed");
+        assert_eq!(parsed, (epoch, nanos));
+    }
+
+    let parse = |input| parse_rfc3339_subsec(input).expect("parsing failed");
+    assert_eq!(parse("2020-12-29T17:30:00Z"), (epoch, 0));
+    assert_eq!(parse("2020-12-29T17:30:00.5Z"), (epoch, 500_000_000));
+    assert_eq!(parse("2020-12-30T00:00:00.25+06:30"), (epoch, 250_000_000));
+    assert_eq!(
+        parse("2020-12-29T17:30:00.1234567891Z"),
+        (epoch, 123_456_789)
+    );
+
+    parse_rfc3339_subsec("2020-12-29T17:30:00.Z").expect_err("empty fraction should fail");
+    parse_rfc3339_subsec("2020-12-29T17:30:00.5").expect_err("missing timezone should fail");
+    parse_rfc3339("2020-12-29T17:30:00.5Z").expect_err("parse_rfc3339 should stay strict");
+}
+
 #[test]
 fn test_strftime_l() {
     let epoch = 1609263000;
@@ -0,0 +1,130 @@
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::{format_err, Error};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::parse_helpers::{parse_complete_line, parse_hm_time};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{parse_weekday, TimeSpan, TmEditor};
+
+/// Format the distance of `epoch` to `now` in a human-friendly way, e.g. "3 days ago" or
+/// "in 2 hours".
+///
+/// Only the largest unit is used and the value is truncated, months count 30 and years 365
+/// days.
+pub fn format_relative(epoch: i64, now: i64) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 86400, "year"),
+        (30 * 86400, "month"),
+        (86400, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+
+    let diff = epoch - now;
+    let distance = diff.unsigned_abs();
+
+    let (count, unit) = match UNITS
+        .iter()
+        .find(|(seconds, _)| distance >= *seconds as u64)
+    {
+        Some((seconds, unit)) => (distance / *seconds as u64, *unit),
+        None => return "now".to_string(),
+    };
+
+    let plural = if count == 1 { "" } else { "s" };
+
+    if diff < 0 {
+        format!("{count} {unit}{plural} ago")
+    } else {
+        format!("in {count} {unit}{plural}")
+    }
+}
+
+/// Parse a time relative to `now` (in the local timezone) and return its epoch.
+///
+/// Supported expressions are:
+///
+/// - `now`, `today` (at 00:00), `yesterday` and `tomorrow`
+/// - a time of day, e.g. `08:00` (today)
+/// - a weekday with an optional time of day, e.g. `monday 08:00`, referring to its most recent
+///   occurrence which is not in the future
+///
+/// optionally followed by an offset in [TimeSpan] syntax, e.g. `now-1h` or `today + 8h 30min`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn parse_relative_time(expr: &str, now: i64) -> Result<i64, Error> {
+    let expr = expr.trim();
+
+    let (base, offset) = match expr.find(['+', '-']) {
+        Some(pos) => {
+            let span: TimeSpan = expr[(pos + 1)..]
+                .parse()
+                .map_err(|err| format_err!("unable to parse relative time '{expr}' - {err}"))?;
+            let seconds = f64::from(span) as i64;
+            let offset = if expr[pos..].starts_with('-') {
+                -seconds
+            } else {
+                seconds
+            };
+            (expr[..pos].trim(), offset)
+        }
+        None => (expr, 0),
+    };
+
+    let base = parse_relative_base(base, now)
+        .map_err(|err| format_err!("unable to parse relative time '{expr}' - {err}"))?;
+
+    Ok(base + offset)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_relative_base(base: &str, now: i64) -> Result<i64, Error> {
+    let mut t = TmEditor::with_epoch(now, false)?;
+
+    let keyword = base.to_ascii_lowercase();
+    match keyword.as_str() {
+        "now" => return Ok(now),
+        "today" => {
+            t.set_time(0, 0, 0)?;
+            return t.into_epoch();
+        }
+        "yesterday" | "tomorrow" => {
+            t.set_time(0, 0, 0)?;
+            t.add_days(if keyword == "yesterday" { -1 } else { 1 })?;
+            return t.into_epoch();
+        }
+        _ => (),
+    }
+
+    let (weekday, time) = match base.split_once(' ') {
+        Some((weekday, time)) => (Some(weekday), Some(time.trim())),
+        None if base.starts_with(|c: char| c.is_ascii_digit()) => (None, Some(base)),
+        None => (Some(base), None),
+    };
+
+    if let Some(weekday) = weekday {
+        let day_num = parse_complete_line("weekday", weekday, parse_weekday)?
+            .bits()
+            .trailing_zeros() as libc::c_int;
+        t.add_days(-((t.day_num() - day_num + 7) % 7))?;
+    }
+
+    match time {
+        Some(time) => {
+            let time = parse_complete_line("time", time, parse_hm_time)?;
+            t.set_time(time.hour as libc::c_int, time.minute as libc::c_int, 0)?;
+        }
+        None => t.set_time(0, 0, 0)?,
+    }
+
+    let epoch = t.into_epoch()?;
+
+    if weekday.is_some() && epoch > now {
+        // the weekday is today, but the time lies in the future
+        let mut t = TmEditor::with_epoch(epoch, false)?;
+        t.set_mday(t.day() - 7)?;
+        return t.into_epoch();
+    }
+
+    Ok(epoch)
+}
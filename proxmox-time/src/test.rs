@@ -189,8 +189,36 @@ fn test_compute_next_event() -> Result<(), Error> {
     test_value("semiannually", 0, (31 + 28 + 31 + 30 + 31 + 30) * DAY)?;
     test_value("yearly", 0, (365) * DAY)?;
 
+    // test days relative to the end of the month
+
+    test_value("*-*~1", 0, 30 * DAY)?;
+    test_value("*-*~1", 30 * DAY, (31 + 27) * DAY)?;
+    test_value("*-02~01", 0, (31 + 27) * DAY)?;
+    test_value("1972-02~1", 0, 2 * 365 * DAY + (31 + 28) * DAY)?; // 1972-02-29
+    test_value("02~3..5", 0, (31 + 23) * DAY)?;
+    test_value("02~3..5", (31 + 23) * DAY, (31 + 24) * DAY)?;
+    test_value("mon *-05~07/1", 0, (31 + 28 + 31 + 30 + 24) * DAY)?; // last monday in may
+    test_value("2020-07~2/1", 0, JUL_31_2020 - DAY)?;
+    test_value("2020-07~2/1", JUL_31_2020 - DAY, JUL_31_2020)?;
+
+    // test ISO week functionality
+
+    test_value("W1", THURSDAY_00_00, THURSDAY_00_00 + DAY)?;
+    test_value("W2", THURSDAY_00_00, THURSDAY_00_00 + 4 * DAY)?;
+    test_value(
+        "mon W*/2 2:00",
+        THURSDAY_00_00,
+        THURSDAY_00_00 + 11 * DAY + 2 * HOUR,
+    )?;
+    test_value("2021-W1", 0, DEC_31_2020 + 4 * DAY)?;
+    test_value("2020-W53", 0, DEC_31_2020 - 3 * DAY)?;
+    test_value("W53", DEC_31_2020, DEC_31_2020 + DAY)?; // 2021-01-01 is still in 2020-W53
+    test_value("W1", DEC_31_2020, DEC_31_2020 + 4 * DAY)?;
+
     test_never("2021-02-29", 0)?;
     test_never("02-30", 0)?;
+    test_never("2021-W53", 0)?;
+    test_never("*-02~30", 0)?;
 
     Ok(())
 }
@@ -220,6 +248,135 @@ fn test_calendar_event_weekday() -> Result<(), Error> {
     test_event("mon,tue,fri")?;
     test_event("mon,tue..wednesday,fri..sat")?;
 
+    test_event("W15")?;
+    test_event("2025-W15")?;
+    test_event("mon W1..52/2 02:00")?;
+    test_event("2025,2026-W*/2")?;
+
+    test_event("*-*~1")?;
+    test_event("*-02~3..5")?;
+    test_event("mon *-05~07/1 12:00")?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cron_expression() -> Result<(), Error> {
+    // compare the next events with the equivalent calendar event
+    let test_value = |cron: &str, calendar_event: &str| -> Result<(), Error> {
+        let cron_event = parse_cron_expression(cron)?;
+        let calendar_event: CalendarEvent = calendar_event.parse()?;
+
+        let mut last = 1_600_000_000; // 2020-09-13
+        for _ in 0..20 {
+            let next = cron_event.compute_next_event(last)?;
+            let expect = calendar_event.compute_next_event(last)?;
+            if next != expect {
+                bail!("cron expression '{cron}' failed - next {next:?}, expect {expect:?}");
+            }
+            last = match next {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        Ok(())
+    };
+
+    test_value("* * * * *", "*:*")?;
+    test_value("*/15 * * * *", "*:0/15")?;
+    test_value("5,35 2-4 * * *", "2..4:5,35")?;
+    test_value("0 22 * * 1-5", "mon..fri 22:00")?;
+    test_value("30 4 1,15 * *", "*-1,15 4:30")?;
+    test_value("0 0 * jan,JUL sun", "sun *-1,7-* 0:00")?;
+    test_value("0 0 * * 7", "sun 0:00")?;
+    test_value("0 12 */2 feb-apr/2 *", "*-2..4/2-1/2 12:00")?;
+    test_value("10/20 */6 * * *", "0/6:10/20")?;
+
+    test_value("@hourly", "hourly")?;
+    test_value("@daily", "daily")?;
+    test_value("@midnight", "daily")?;
+    test_value("@weekly", "sun 0:00")?;
+    test_value("@monthly", "monthly")?;
+    test_value("@yearly", "yearly")?;
+    test_value("@annually", "yearly")?;
+
+    assert!(parse_cron_expression("* * * *").is_err());
+    assert!(parse_cron_expression("60 * * * *").is_err());
+    assert!(parse_cron_expression("* * 0 * *").is_err());
+    assert!(parse_cron_expression("* * * 13 *").is_err());
+    assert!(parse_cron_expression("* * * * 8").is_err());
+    assert!(parse_cron_expression("*/0 * * * *").is_err());
+    assert!(parse_cron_expression("5-1 * * * *").is_err());
+    assert!(parse_cron_expression("0 0 1 * mon").is_err());
+    assert!(parse_cron_expression("@reboot").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_format_relative() {
+    const NOW: i64 = 1_700_000_000;
+
+    assert_eq!(format_relative(NOW, NOW), "now");
+    assert_eq!(format_relative(NOW - 1, NOW), "1 second ago");
+    assert_eq!(format_relative(NOW - 59, NOW), "59 seconds ago");
+    assert_eq!(format_relative(NOW + 60, NOW), "in 1 minute");
+    assert_eq!(format_relative(NOW + 2 * 3600 + 59, NOW), "in 2 hours");
+    assert_eq!(format_relative(NOW - 3 * 86400 - 3600, NOW), "3 days ago");
+    assert_eq!(format_relative(NOW - 45 * 86400, NOW), "1 month ago");
+    assert_eq!(format_relative(NOW + 800 * 86400, NOW), "in 2 years");
+}
+
+#[test]
+fn test_parse_relative_time() -> Result<(), Error> {
+    const NOW: i64 = 1_700_000_000; // Tuesday, 2023-11-14 22:13:20 UTC
+
+    assert_eq!(parse_relative_time("now", NOW)?, NOW);
+    assert_eq!(parse_relative_time("NOW", NOW)?, NOW);
+    assert_eq!(parse_relative_time("now-1h", NOW)?, NOW - 3600);
+    assert_eq!(parse_relative_time("now - 1h 30min", NOW)?, NOW - 5400);
+    assert_eq!(parse_relative_time("now+2d", NOW)?, NOW + 2 * 86400);
+    assert_eq!(
+        parse_relative_time("now+1M", NOW)?,
+        NOW + 30 * 86400 + 38016
+    );
+
+    // the following depend on the local timezone
+    let local = |epoch| crate::localtime(epoch).unwrap();
+
+    let today = parse_relative_time("today", NOW)?;
+    assert!(today <= NOW && NOW - today < 86400);
+    assert_eq!(local(today).tm_hour, 0);
+    assert_eq!(local(today).tm_min, 0);
+
+    let mut t = TmEditor::with_epoch(today, false)?;
+    t.add_days(-1)?;
+    assert_eq!(parse_relative_time("yesterday", NOW)?, t.into_epoch()?);
+
+    let mut t = TmEditor::with_epoch(today, false)?;
+    t.add_days(1)?;
+    assert_eq!(parse_relative_time("tomorrow", NOW)?, t.into_epoch()?);
+
+    assert_eq!(parse_relative_time("today+8h", NOW)?, today + 8 * 3600);
+
+    let time = parse_relative_time("08:30", NOW)?;
+    assert_eq!(local(time).tm_mday, local(NOW).tm_mday);
+    assert_eq!((local(time).tm_hour, local(time).tm_min), (8, 30));
+
+    for weekday in ["mon", "Tuesday", "sun"] {
+        let time = parse_relative_time(&format!("{weekday} 23:59"), NOW)?;
+        assert!(time <= NOW && NOW - time < 7 * 86400);
+        assert_eq!((local(time).tm_hour, local(time).tm_min), (23, 59));
+    }
+    let monday = parse_relative_time("monday 08:00", NOW)?;
+    assert_eq!(local(monday).tm_wday, 1);
+    assert_eq!(parse_relative_time("monday", NOW)?, monday - 8 * 3600);
+
+    assert!(parse_relative_time("", NOW).is_err());
+    assert!(parse_relative_time("later", NOW).is_err());
+    assert!(parse_relative_time("now-1x", NOW).is_err());
+    assert!(parse_relative_time("monday 25:00", NOW).is_err());
+
     Ok(())
 }
 
@@ -262,3 +419,50 @@ fn test_time_span_parser() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_time_span_iso8601() -> Result<(), Error> {
+    let test_value = |iso: &str, time_span: &str, expect: &str| -> Result<(), Error> {
+        let ts = TimeSpan::from_iso8601(iso)?;
+        assert_eq!(
+            f64::from(ts.clone()),
+            f64::from(time_span.parse::<TimeSpan>()?)
+        );
+        assert_eq!(ts.to_iso8601(), expect, "{}", iso);
+        Ok(())
+    };
+
+    test_value("P1DT2H", "1d 2h", "P1DT2H")?;
+    test_value("P1Y2M3DT4H5M6S", "1y 2M 3d 4h 5min 6s", "P1Y2M3DT4H5M6S")?;
+    test_value("P2W", "2w", "P14D")?;
+    test_value("P1W2D", "1w 2d", "P9D")?;
+    test_value("PT1M", "1min", "PT1M")?;
+    test_value("P1M", "1M", "P1M")?;
+    test_value("PT0.5S", "500ms", "PT0.5S")?;
+    test_value("PT1,000001S", "1s 1us", "PT1.000001S")?;
+    test_value("PT0.0000000019S", "1ns", "PT0.000000001S")?;
+    test_value("PT0S", "0s", "PT0S")?;
+    test_value("P0D", "0s", "PT0S")?;
+
+    let ts: TimeSpan = "90s 1500ms".parse()?;
+    assert_eq!(ts.to_iso8601(), "PT91.5S");
+    let ts: TimeSpan = std::time::Duration::from_millis(3_600_250).into();
+    assert_eq!(ts.to_iso8601(), "PT1H0.25S");
+    let ts: TimeSpan = "1000ms".parse()?;
+    assert_eq!(ts.to_iso8601(), "PT1S");
+    let ts: TimeSpan = "1s 999ms 999us 1000ns".parse()?;
+    assert_eq!(ts.to_iso8601(), "PT2S");
+    let ts: TimeSpan = "2500ms 1500us".parse()?;
+    assert_eq!(ts.to_iso8601(), "PT2.5015S");
+    let ts: TimeSpan = "30000000000000ms".parse()?;
+    assert_eq!(ts.to_iso8601(), "PT30000000000S");
+
+    for invalid in [
+        "", "P", "PT", "1D", "P1", "P1H", "PT1D", "P1DT", "PT1S1M", "P1D1Y", "P1.5D", "PT1.S",
+        "PTS", "P1D2D", "Pä", "P1ä", "PT1ä", "P1D1ä",
+    ] {
+        assert!(TimeSpan::from_iso8601(invalid).is_err(), "{}", invalid);
+    }
+
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use anyhow::Error;
+use anyhow::{bail, format_err, Error};
 use lazy_static::lazy_static;
 use nom::{bytes::complete::take_while1, character::complete::space0, combinator::opt};
 
@@ -90,6 +90,146 @@ pub struct TimeSpan {
     pub years: u64,
 }
 
+impl TimeSpan {
+    /// Format as ISO 8601 duration, e.g. `P1DT2H30M`.
+    ///
+    /// Weeks are converted to days, because ISO 8601 does not allow to combine them with other
+    /// units. Sub-second values are formatted as fractional seconds.
+    pub fn to_iso8601(&self) -> String {
+        use std::fmt::Write as _;
+
+        // carry whole seconds of every sub-second unit separately, so that e.g. 1000ms or
+        // 999ms 1000us end up as seconds and large values cannot overflow
+        let subsec_nanos = (self.msec % 1_000) * 1_000_000
+            + (self.usec % 1_000_000) * 1_000
+            + self.nsec % 1_000_000_000;
+        let seconds = self.seconds
+            + self.msec / 1_000
+            + self.usec / 1_000_000
+            + self.nsec / 1_000_000_000
+            + subsec_nanos / 1_000_000_000;
+        let subsec_nanos = subsec_nanos % 1_000_000_000;
+        let days = self.weeks * 7 + self.days;
+
+        let mut result = String::from("P");
+
+        for (value, unit) in [(self.years, 'Y'), (self.months, 'M'), (days, 'D')] {
+            if value > 0 {
+                let _ = write!(result, "{value}{unit}");
+            }
+        }
+
+        if self.hours > 0 || self.minutes > 0 || seconds > 0 || subsec_nanos > 0 {
+            result.push('T');
+            for (value, unit) in [(self.hours, 'H'), (self.minutes, 'M')] {
+                if value > 0 {
+                    let _ = write!(result, "{value}{unit}");
+                }
+            }
+            if subsec_nanos > 0 {
+                let fraction = format!("{subsec_nanos:09}");
+                let _ = write!(result, "{seconds}.{}S", fraction.trim_end_matches('0'));
+            } else if seconds > 0 {
+                let _ = write!(result, "{seconds}S");
+            }
+        }
+
+        if result.len() == 1 {
+            result.push_str("T0S");
+        }
+
+        result
+    }
+
+    /// Parse an ISO 8601 duration like `P1Y2M3DT4H5M6.5S` or `P2W`.
+    ///
+    /// Fractions are only supported for seconds.
+    pub fn from_iso8601(duration: &str) -> Result<Self, Error> {
+        Self::parse_iso8601(duration)
+            .map_err(|err| format_err!("unable to parse ISO 8601 duration '{duration}' - {err}"))
+    }
+
+    fn parse_iso8601(duration: &str) -> Result<Self, Error> {
+        // designators in the order they have to appear
+        const DATE_UNITS: &[char] = &['Y', 'M', 'W', 'D'];
+        const TIME_UNITS: &[char] = &['H', 'M', 'S'];
+
+        let mut ts = TimeSpan::default();
+
+        let mut rest = duration
+            .strip_prefix('P')
+            .ok_or_else(|| format_err!("missing 'P' designator"))?;
+
+        let mut units = DATE_UNITS;
+        let mut in_time = false;
+        let mut empty = true;
+
+        while !rest.is_empty() {
+            if let Some(time) = rest.strip_prefix('T') {
+                if in_time || time.is_empty() {
+                    bail!("unexpected 'T' designator");
+                }
+                in_time = true;
+                units = TIME_UNITS;
+                rest = time;
+                continue;
+            }
+
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+                .ok_or_else(|| format_err!("missing designator after '{rest}'"))?;
+            let (number, unit) = rest.split_at(len);
+            let unit = unit.chars().next().unwrap();
+
+            let pos = units
+                .iter()
+                .position(|u| *u == unit)
+                .ok_or_else(|| format_err!("unexpected designator '{unit}'"))?;
+            units = &units[(pos + 1)..];
+            rest = &rest[(len + unit.len_utf8())..];
+
+            let (integer, fraction) = match number.split_once(['.', ',']) {
+                Some((integer, fraction)) if in_time && unit == 'S' => (integer, Some(fraction)),
+                Some(_) => bail!("fractions are only supported for seconds"),
+                None => (number, None),
+            };
+
+            let value: u64 = integer
+                .parse()
+                .map_err(|_| format_err!("invalid number '{number}'"))?;
+
+            match (in_time, unit) {
+                (false, 'Y') => ts.years = value,
+                (false, 'M') => ts.months = value,
+                (false, 'W') => ts.weeks = value,
+                (false, 'D') => ts.days = value,
+                (true, 'H') => ts.hours = value,
+                (true, 'M') => ts.minutes = value,
+                (true, 'S') => ts.seconds = value,
+                _ => unreachable!(), // checked above
+            }
+
+            if let Some(fraction) = fraction {
+                if fraction.is_empty() || !fraction.chars().all(|c| c.is_ascii_digit()) {
+                    bail!("invalid number '{number}'");
+                }
+                let nanos: u64 = format!("{:0<9.9}", fraction).parse()?;
+                ts.msec = nanos / 1_000_000;
+                ts.usec = nanos / 1_000 % 1_000;
+                ts.nsec = nanos % 1_000;
+            }
+
+            empty = false;
+        }
+
+        if empty {
+            bail!("missing duration value");
+        }
+
+        Ok(ts)
+    }
+}
+
 impl From<TimeSpan> for f64 {
     fn from(ts: TimeSpan) -> Self {
         (ts.seconds as f64)
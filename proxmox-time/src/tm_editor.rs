@@ -106,11 +106,61 @@ impl TmEditor {
         self.t.tm_sec
     }
 
+    /// Returns the number of days in the current month
+    pub fn days_in_month(&self) -> libc::c_int {
+        match self.month() {
+            2 => {
+                let year = self.year();
+                if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 {
+                    29
+                } else {
+                    28
+                }
+            }
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
     // Note: tm_wday (0-6, Sunday = 0) => convert to Sunday = 6
     pub fn day_num(&self) -> libc::c_int {
         (self.t.tm_wday + 6) % 7
     }
 
+    /// Returns the ISO 8601 week-based year and week number (1-53).
+    pub fn iso_week(&self) -> (libc::c_int, libc::c_int) {
+        let year = self.year();
+        let week = (self.t.tm_yday + 1 - (self.day_num() + 1) + 10) / 7;
+
+        if week < 1 {
+            (year - 1, iso_weeks_in_year(year - 1))
+        } else if week > iso_weeks_in_year(year) {
+            (year + 1, 1)
+        } else {
+            (year, week)
+        }
+    }
+
+    /// Returns the number of ISO 8601 weeks (52 or 53) in the current week-based year.
+    pub fn iso_weeks_in_year(&self) -> libc::c_int {
+        iso_weeks_in_year(self.iso_week().0)
+    }
+
+    /// Sets the date to the Monday of ISO week 1 of `year` and resets the time
+    pub fn set_iso_week_year(&mut self, year: libc::c_int) -> Result<(), Error> {
+        // January 4th is always in week 1
+        self.t.tm_year = year - 1900;
+        self.t.tm_mon = 0;
+        self.t.tm_mday = 4;
+        self.t.tm_hour = 0;
+        self.t.tm_min = 0;
+        self.t.tm_sec = 0;
+        self.normalize_time()?;
+
+        self.t.tm_mday -= self.day_num();
+        self.normalize_time()
+    }
+
     pub fn set_time(
         &mut self,
         hour: libc::c_int,
@@ -169,3 +219,14 @@ impl TmEditor {
         self.normalize_time()
     }
 }
+
+// A year has 53 ISO weeks if it starts on a Thursday, or if it is a leap year starting on a
+// Wednesday.
+fn iso_weeks_in_year(year: libc::c_int) -> libc::c_int {
+    let p = |y: libc::c_int| (y + y / 4 - y / 100 + y / 400) % 7;
+    if p(year) == 4 || p(year - 1) == 3 {
+        53
+    } else {
+        52
+    }
+}
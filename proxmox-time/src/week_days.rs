@@ -17,7 +17,7 @@ bitflags! {
     }
 }
 
-fn parse_weekday(i: &str) -> IResult<&str, WeekDays> {
+pub(crate) fn parse_weekday(i: &str) -> IResult<&str, WeekDays> {
     let (i, text) = alpha1(i)?;
 
     match text.to_ascii_lowercase().as_str() {